@@ -18,7 +18,7 @@ echo "Testing agent-first-psql [$MODE]..."
 
 if [ "$MODE" = "unit" ] || [ "$MODE" = "integration" ]; then
     # Unit tests: no DB needed
-    (cd "$ROOTPATH" && cargo test --bin afpsql)
+    (cd "$ROOTPATH" && cargo test --lib --bins)
 fi
 
 if [ "$MODE" = "integration" ]; then
@@ -0,0 +1,62 @@
+//! Acknowledgements for streams sent with `ack_window`.
+//!
+//! The consumer acks each `seq` it has processed; a stream waits before
+//! sending a batch more than `ack_window` events past the last ack, so a
+//! slow consumer holds the query back instead of letting output pile up.
+//! Acks are only tracked while their stream is being sent.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+pub struct Acks {
+    /// Highest acked seq of each stream being sent, by request id.
+    acked: Mutex<HashMap<String, u64>>,
+    notify: Notify,
+}
+
+impl Acks {
+    pub fn begin(&self, id: &str) {
+        if let Ok(mut acked) = self.acked.lock() {
+            acked.insert(id.to_string(), 0);
+        }
+    }
+
+    pub fn finish(&self, id: &str) {
+        if let Ok(mut acked) = self.acked.lock() {
+            acked.remove(id);
+        }
+    }
+
+    /// Record an ack; acks for streams not being sent are dropped.
+    pub fn ack(&self, id: &str, seq: u64) {
+        if let Ok(mut acked) = self.acked.lock() {
+            if let Some(last) = acked.get_mut(id) {
+                *last = (*last).max(seq);
+            }
+        }
+        self.notify.notify_waiters();
+    }
+
+    fn acked(&self, id: &str) -> Option<u64> {
+        self.acked.lock().ok()?.get(id).copied()
+    }
+
+    /// Wait until `id` is acked up to `seq`, or is no longer tracked.
+    pub async fn wait_for(&self, id: &str, seq: u64) {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            match self.acked(id) {
+                Some(last) if last < seq => notified.await,
+                _ => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_ack.rs"]
+mod tests;
@@ -0,0 +1,34 @@
+//! Queries behind `psql_activity`.
+//!
+//! Backends are listed from `pg_stat_activity`, leaving out the one asking.
+//! Each filter is a bind parameter that matches everything when NULL, so one
+//! statement serves every combination.
+
+/// Parameters: `$1` state, `$2` user, `$3` database, `$4` wait event type,
+/// `$5` minimum query age in ms, `$6` whether to include background
+/// workers and other non-client backends.
+pub const LIST_SQL: &str = "select pid, usename as user, datname as database, application_name, \
+     client_addr::text as client_addr, backend_type, state, wait_event_type, wait_event, \
+     (extract(epoch from now() - query_start) * 1000)::bigint as query_age_ms, \
+     (extract(epoch from now() - xact_start) * 1000)::bigint as xact_age_ms, \
+     (extract(epoch from now() - state_change) * 1000)::bigint as state_age_ms, \
+     pg_blocking_pids(pid) as blocked_by, query \
+     from pg_stat_activity \
+     where pid <> pg_backend_pid() \
+     and ($1::text is null or state = $1) \
+     and ($2::text is null or usename = $2) \
+     and ($3::text is null or datname = $3) \
+     and ($4::text is null or wait_event_type = $4) \
+     and ($5::bigint is null or now() - query_start >= make_interval(secs => $5 / 1000.0)) \
+     and ($6::bool or backend_type = 'client backend') \
+     order by query_start nulls last, pid";
+
+/// `pg_cancel_backend` or `pg_terminate_backend` of `$1`; the row's
+/// `signalled` is false when no such backend exists.
+pub fn signal_sql(terminate: bool) -> &'static str {
+    if terminate {
+        "select pg_terminate_backend($1::int) as signalled"
+    } else {
+        "select pg_cancel_backend($1::int) as signalled"
+    }
+}
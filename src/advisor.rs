@@ -0,0 +1,166 @@
+//! Index candidates for `psql_suggest_indexes`.
+//!
+//! Candidates come from the sequential scans with a filter in a query's
+//! `EXPLAIN (FORMAT JSON, VERBOSE)` plan: the scanned table's columns that
+//! the filter mentions, equality comparisons first. Where hypopg is
+//! installed each candidate is planned as a hypothetical index; otherwise
+//! its benefit is estimated from the filter's selectivity.
+
+use crate::sqlgen::quote_ident;
+use serde_json::Value;
+
+/// Columns a suggested index covers at most.
+pub const MAX_INDEX_COLUMNS: usize = 3;
+
+/// Without hypopg, filters keeping more than this fraction of their table are
+/// not worth an index.
+pub const MAX_SELECTIVITY: f64 = 0.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilteredScan {
+    pub schema: String,
+    pub relation: String,
+    pub filter: String,
+    /// The scan node's total cost and row estimate.
+    pub cost: f64,
+    pub rows: f64,
+}
+
+impl FilteredScan {
+    /// `"schema"."relation"`, usable as a `regclass` literal.
+    pub fn qualified(&self) -> String {
+        format!(
+            "{}.{}",
+            quote_ident(&self.schema),
+            quote_ident(&self.relation)
+        )
+    }
+}
+
+/// Every `Seq Scan` with a `Filter` in the plan rooted at `plan`, in tree
+/// order.
+pub fn filtered_scans(plan: &Value) -> Vec<FilteredScan> {
+    let mut scans = vec![];
+    push_scans(plan, &mut scans);
+    scans
+}
+
+fn push_scans(node: &Value, scans: &mut Vec<FilteredScan>) {
+    let field = |name: &str| node.get(name).and_then(Value::as_str);
+    if field("Node Type") == Some("Seq Scan") {
+        if let (Some(relation), Some(filter)) = (field("Relation Name"), field("Filter")) {
+            scans.push(FilteredScan {
+                schema: field("Schema").unwrap_or("public").to_string(),
+                relation: relation.to_string(),
+                filter: filter.to_string(),
+                cost: node
+                    .get("Total Cost")
+                    .and_then(Value::as_f64)
+                    .unwrap_or(0.0),
+                rows: node.get("Plan Rows").and_then(Value::as_f64).unwrap_or(0.0),
+            });
+        }
+    }
+    for child in node
+        .get("Plans")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        push_scans(child, scans);
+    }
+}
+
+/// Which of `columns` the `filter` expression mentions: those compared with
+/// `=` first, then the rest, each in order of appearance and at most
+/// [`MAX_INDEX_COLUMNS`]. String literals are skipped and identifiers
+/// followed by `.` are taken as table aliases.
+pub fn filter_columns(filter: &str, columns: &[String]) -> Vec<String> {
+    let chars: Vec<char> = filter.chars().collect();
+    let mut equality = vec![];
+    let mut other = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (name, end) = match chars[i] {
+            '\'' => {
+                i = quoted_end(&chars, i, '\'');
+                continue;
+            }
+            '"' => {
+                let end = quoted_end(&chars, i, '"');
+                let inner: String = chars[i + 1..end.saturating_sub(1).max(i + 1)]
+                    .iter()
+                    .collect();
+                (inner.replace("\"\"", "\""), end)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_alphanumeric() || *c == '_' || *c == '$'))
+                    .map_or(chars.len(), |n| i + n);
+                (chars[i..end].iter().collect(), end)
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        i = end;
+        let rest: String = chars[end..].iter().collect();
+        if rest.starts_with('.') || !columns.contains(&name) {
+            continue;
+        }
+        if equality.contains(&name) || other.contains(&name) {
+            continue;
+        }
+        let rest = rest.trim_start();
+        if rest.starts_with('=') && !rest.starts_with("=>") {
+            equality.push(name);
+        } else {
+            other.push(name);
+        }
+    }
+    equality.extend(other);
+    equality.truncate(MAX_INDEX_COLUMNS);
+    equality
+}
+
+/// Index just past the quote closing the one at `start`; doubled quotes are
+/// escapes.
+fn quoted_end(chars: &[char], start: usize, quote: char) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// `CREATE INDEX` on `scan`'s table over `columns`.
+pub fn create_index_sql(scan: &FilteredScan, columns: &[String]) -> String {
+    let columns: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+    format!(
+        "create index on {} ({})",
+        scan.qualified(),
+        columns.join(", ")
+    )
+}
+
+/// Heuristic cost saved by an index for `scan` of a table of `table_rows`:
+/// the part of the scan's cost spent on rows the filter drops. `None` when
+/// the filter keeps more than [`MAX_SELECTIVITY`] of the table.
+pub fn estimated_saving(scan: &FilteredScan, table_rows: f64) -> Option<f64> {
+    let selectivity = scan.rows / table_rows.max(1.0);
+    (selectivity <= MAX_SELECTIVITY)
+        .then(|| (scan.cost * (1.0 - selectivity) * 100.0).round() / 100.0)
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_advisor.rs"]
+mod tests;
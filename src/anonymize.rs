@@ -0,0 +1,231 @@
+//! Anonymized table samples for `psql_export_sample`.
+//!
+//! Each table is sampled with the configured redaction rules plus the
+//! request's own, which usually `pseudonymize` keys and personal columns so
+//! the sample keeps its joins but not its people. Request rules come after
+//! the configured ones, so a configured rule always wins for its column.
+//! The manifest records, per column, which transformation was applied and
+//! by which rule, so a reviewer can check the sample before it is shared.
+
+use crate::conn::resolve_session_name;
+use crate::db::ExecOutcome;
+use crate::handler::App;
+use crate::redact;
+use crate::results;
+use crate::sqlgen;
+use crate::types::{ColumnInfo, QueryOptions, RedactAction, RedactionRule};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Schema, name and columns of the table `$1` names.
+pub const TABLE_SQL: &str = "select n.nspname::text as schema, c.relname::text as table, \
+     array(select a.attname::text from pg_attribute a \
+       where a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped \
+       order by a.attnum) as columns \
+     from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+     where c.oid = $1::text::regclass";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableInfo {
+    pub schema: String,
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
+/// Where a column's transformation came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleSource {
+    Config,
+    Request,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnTransform {
+    pub column: String,
+    /// `none` when the column is copied as is.
+    pub transform: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<RuleSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableManifest {
+    /// `schema.table`.
+    pub table: String,
+    pub rows: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub columns: Vec<ColumnTransform>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Manifest {
+    pub mode: String,
+    pub rows_per_table: u64,
+    /// Whether hashes and pseudonyms were salted; unsalted pseudonyms of
+    /// guessable values such as email addresses can be reversed by trying
+    /// candidates.
+    pub salted: bool,
+    pub tables: Vec<TableManifest>,
+}
+
+/// One sampled table, its rows already transformed.
+#[derive(Debug, Clone)]
+pub struct TableSample {
+    pub manifest: TableManifest,
+    pub rows: Vec<Value>,
+}
+
+/// The transformation each column of `info` gets from `config` rules, then
+/// `request` rules.
+pub fn column_transforms(
+    info: &TableInfo,
+    config: &[RedactionRule],
+    request: &[RedactionRule],
+) -> Vec<ColumnTransform> {
+    let sources = [(info.schema.clone(), info.table.clone())];
+    info.columns
+        .iter()
+        .map(|column| {
+            let found = redact::rule_for(config, column, &sources)
+                .map(|rule| (rule, RuleSource::Config))
+                .or_else(|| {
+                    redact::rule_for(request, column, &sources)
+                        .map(|rule| (rule, RuleSource::Request))
+                });
+            ColumnTransform {
+                column: column.clone(),
+                transform: found.map_or("none".to_string(), |(rule, _)| {
+                    action_name(rule.action).to_string()
+                }),
+                rule: found.map(|(rule, _)| rule.column.clone()),
+                source: found.map(|(_, source)| source),
+            }
+        })
+        .collect()
+}
+
+fn action_name(action: RedactAction) -> &'static str {
+    match action {
+        RedactAction::Mask => "mask",
+        RedactAction::Hash => "hash",
+        RedactAction::Null => "null",
+        RedactAction::Pseudonymize => "pseudonymize",
+    }
+}
+
+/// Sample up to `limit` rows of `table`, read-only, with the configured
+/// redaction rules followed by `rules`; `salt` replaces the configured salt.
+pub async fn sample_table(
+    app: &Arc<App>,
+    session: Option<&str>,
+    table: &str,
+    limit: u64,
+    random: bool,
+    rules: &[RedactionRule],
+    salt: Option<&str>,
+) -> Result<TableSample, String> {
+    let name = sqlgen::qualified_name(table)?;
+    let cfg = app.config.read().await.clone();
+    let session_name = resolve_session_name(&cfg, session);
+    let Some(session_cfg) = cfg.sessions.get(&session_name) else {
+        return Err(format!("unknown session: {session_name}"));
+    };
+    let mut opts = cfg.resolve_options(&QueryOptions {
+        read_only: Some(true),
+        ..QueryOptions::default()
+    });
+    opts.default_limit = None;
+    let config_rules = std::mem::take(&mut opts.redact);
+
+    let info = match app
+        .executor
+        .execute(
+            &session_name,
+            session_cfg,
+            TABLE_SQL,
+            &[Value::String(name)],
+            &opts,
+        )
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        ExecOutcome::Rows(mut rows) if !rows.is_empty() => {
+            serde_json::from_value::<TableInfo>(rows.swap_remove(0)).map_err(|e| e.to_string())?
+        }
+        _ => return Err(format!("{table} is not a table")),
+    };
+    let columns = column_transforms(&info, &config_rules, rules);
+
+    opts.redact = config_rules;
+    opts.redact.extend(rules.iter().cloned());
+    if let Some(salt) = salt {
+        opts.redact_salt_secret = Some(salt.to_string());
+    }
+    let qualified = format!("{}.{}", info.schema, info.table);
+    let (sql, params) = sqlgen::build_sample(&qualified, limit, random)?;
+    let rows = match app
+        .executor
+        .execute(&session_name, session_cfg, &sql, &params, &opts)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        ExecOutcome::Rows(rows) => rows,
+        ExecOutcome::Command { .. } => vec![],
+    };
+    Ok(TableSample {
+        manifest: TableManifest {
+            table: qualified,
+            rows: rows.len(),
+            file: None,
+            columns,
+        },
+        rows,
+    })
+}
+
+/// Write each sample to `<schema.table>.jsonl` under `dir`, in the stored
+/// result format, and the manifest to `manifest.json` beside them.
+pub fn write_export(
+    dir: &Path,
+    manifest: &mut Manifest,
+    samples: &[TableSample],
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (entry, sample) in manifest.tables.iter_mut().zip(samples) {
+        // Quoted names may hold any character; keep the file inside `dir`.
+        let stem: String = entry
+            .table
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let file = format!("{stem}.jsonl");
+        let columns: Vec<ColumnInfo> = entry
+            .columns
+            .iter()
+            .map(|c| ColumnInfo {
+                name: c.column.clone(),
+                type_name: "json".to_string(),
+            })
+            .collect();
+        results::save_file(&dir.join(&file), &columns, &sample.rows)?;
+        entry.file = Some(file);
+    }
+    let body = serde_json::to_vec_pretty(manifest)?;
+    std::fs::write(dir.join("manifest.json"), body)
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_anonymize.rs"]
+mod tests;
@@ -0,0 +1,240 @@
+//! Statements parked until an `approve` input releases them.
+//!
+//! The executor decides what needs approval from server-observed effects
+//! (exclusive locks, rows written) and rolls the statement back; the handler
+//! keeps the request here under a token that expires after `approval_ttl_ms`.
+//! With `state_dir` set, each parked statement is also kept in
+//! `<state_dir>/approvals/<token>.json` until it is taken or expires, so a
+//! restart does not drop approvals still pending.
+
+use crate::types::QueryOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct PendingApproval {
+    pub session: String,
+    pub sql: String,
+    pub params: Vec<Value>,
+    pub options: QueryOptions,
+    pub reason: String,
+    pub expires_at: Instant,
+}
+
+/// A parked statement as listed by `operations_list`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PendingEntry {
+    pub token: String,
+    pub session: String,
+    pub sql: String,
+    pub reason: String,
+    pub expires_in_ms: u64,
+}
+
+/// A parked statement as saved under `state_dir`; the expiry is wall clock
+/// time, since an [`Instant`] means nothing to the next process.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedApproval {
+    session: String,
+    sql: String,
+    params: Vec<Value>,
+    options: QueryOptions,
+    reason: String,
+    expires_at_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Approvals {
+    pending: HashMap<String, PendingApproval>,
+    /// `<state_dir>/approvals`, when pending statements are saved.
+    dir: Option<PathBuf>,
+}
+
+impl Approvals {
+    /// Approvals saved under `state_dir`, if any, still pending.
+    pub fn load(state_dir: Option<&str>) -> Self {
+        let mut approvals = Self::default();
+        approvals.set_state_dir(state_dir);
+        approvals
+    }
+
+    /// Save pending statements under `state_dir` from now on, and take over
+    /// the ones saved there that have not expired. Unreadable or expired
+    /// files are removed.
+    pub fn set_state_dir(&mut self, state_dir: Option<&str>) {
+        let dir = state_dir.map(|d| Path::new(d).join("approvals"));
+        if dir == self.dir {
+            return;
+        }
+        self.dir = dir;
+        let Some(dir) = self.dir.clone() else {
+            return;
+        };
+        let now = now_ms();
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let token = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .filter(|t| t.starts_with("apr_") && crate::results::valid_handle(t))
+                .map(str::to_string);
+            let Some(token) = token.filter(|_| path.extension().is_some_and(|e| e == "json"))
+            else {
+                continue;
+            };
+            let saved = std::fs::read(&path)
+                .ok()
+                .and_then(|body| serde_json::from_slice::<SavedApproval>(&body).ok());
+            match saved {
+                Some(saved) if saved.expires_at_ms > now => {
+                    let expires_at =
+                        Instant::now() + Duration::from_millis(saved.expires_at_ms - now);
+                    self.pending.entry(token).or_insert(PendingApproval {
+                        session: saved.session,
+                        sql: saved.sql,
+                        params: saved.params,
+                        options: saved.options,
+                        reason: saved.reason,
+                        expires_at,
+                    });
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        let pending: Vec<_> = self
+            .pending
+            .iter()
+            .map(|(t, p)| (t.clone(), p.clone()))
+            .collect();
+        for (token, pending) in pending {
+            self.save(&token, &pending);
+        }
+    }
+
+    /// Park `pending` and return its token. Expired entries are pruned.
+    pub fn park(&mut self, pending: PendingApproval) -> String {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| p.expires_at <= now)
+            .map(|(t, _)| t.clone())
+            .collect();
+        for token in expired {
+            self.pending.remove(&token);
+            self.unsave(&token);
+        }
+        let token = new_token();
+        self.save(&token, &pending);
+        self.pending.insert(token.clone(), pending);
+        token
+    }
+
+    /// The statements still pending, soonest to expire first.
+    pub fn list(&self) -> Vec<PendingEntry> {
+        let now = Instant::now();
+        let mut pending: Vec<PendingEntry> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| p.expires_at > now)
+            .map(|(token, p)| PendingEntry {
+                token: token.clone(),
+                session: p.session.clone(),
+                sql: p.sql.clone(),
+                reason: p.reason.clone(),
+                expires_in_ms: p.expires_at.duration_since(now).as_millis() as u64,
+            })
+            .collect();
+        pending.sort_by_key(|p| p.expires_in_ms);
+        pending
+    }
+
+    /// Best effort: an approval that cannot be saved still works until the
+    /// process exits.
+    fn save(&self, token: &str, pending: &PendingApproval) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let remaining = pending.expires_at.saturating_duration_since(Instant::now());
+        let saved = SavedApproval {
+            session: pending.session.clone(),
+            sql: pending.sql.clone(),
+            params: pending.params.clone(),
+            options: pending.options.clone(),
+            reason: pending.reason.clone(),
+            expires_at_ms: now_ms() + remaining.as_millis() as u64,
+        };
+        let Ok(body) = serde_json::to_vec(&saved) else {
+            return;
+        };
+        let path = dir.join(format!("{token}.json"));
+        let tmp = dir.join(format!("{token}.json.tmp"));
+        let _ = std::fs::create_dir_all(dir)
+            .and_then(|()| std::fs::write(&tmp, body))
+            .and_then(|()| std::fs::rename(&tmp, &path));
+    }
+
+    fn unsave(&self, token: &str) {
+        if let Some(dir) = &self.dir {
+            let _ = std::fs::remove_file(dir.join(format!("{token}.json")));
+        }
+    }
+
+    /// The live statement for `token`; errors are user-facing.
+    pub fn get(&self, token: &str) -> Result<PendingApproval, String> {
+        match self.pending.get(token) {
+            Some(p) if p.expires_at > Instant::now() => Ok(p.clone()),
+            Some(_) => Err(format!("approval token expired: {token}")),
+            None => Err(format!("unknown approval token: {token}")),
+        }
+    }
+
+    /// Remove and return the statement for `token`, so it runs at most once.
+    pub fn take(&mut self, token: &str) -> Result<PendingApproval, String> {
+        let pending = self.get(token);
+        if self.pending.remove(token).is_some() {
+            self.unsave(token);
+        }
+        pending
+    }
+}
+
+pub fn expires_at(ttl_ms: u64) -> Instant {
+    Instant::now() + Duration::from_millis(ttl_ms)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn new_token() -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut hasher = Sha256::new();
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(SEQ.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .take(12)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("apr_{hex}")
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_approval.rs"]
+mod tests;
@@ -0,0 +1,32 @@
+//! Append-only JSONL audit file for approval and privilege events.
+//!
+//! Each record is written and flushed before the action it describes runs;
+//! a failed write refuses the action.
+
+use serde_json::{Map, Value};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Append `{"event", "at_ms", ...fields}` as one line of `path`.
+pub fn append(path: &str, event: &str, fields: Value) -> io::Result<()> {
+    let mut record = Map::new();
+    record.insert("event".to_string(), Value::String(event.to_string()));
+    let at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    record.insert("at_ms".to_string(), Value::from(at_ms));
+    if let Value::Object(fields) = fields {
+        record.extend(fields);
+    }
+    let mut line = serde_json::to_vec(&Value::Object(record))?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)?;
+    file.flush()
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_audit.rs"]
+mod tests;
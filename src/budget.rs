@@ -0,0 +1,158 @@
+//! Row and byte budgets from `agent_budgets` and `session_budgets`, kept as
+//! token buckets.
+//!
+//! Each agent or session with a budget has one bucket per limit
+//! (`rows_per_minute`, `bytes_per_hour`, ...). A bucket starts full and
+//! refills continuously at its limit per period; a sent result takes its rows
+//! or bytes out, possibly below zero. New queries are refused while any of
+//! their buckets is empty, so a burst is paid back before the next query.
+//! Agents or sessions falling under the `"*"` entry share its buckets.
+
+use crate::quota::tighter;
+use crate::types::CostBudget;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Rows,
+    Bytes,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    name: &'static str,
+    unit: Unit,
+    capacity: f64,
+    /// Tokens regained per millisecond.
+    refill: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refilled(&mut self, now: Instant) -> f64 {
+        let elapsed_ms = now.saturating_duration_since(self.updated).as_secs_f64() * 1000.0;
+        self.tokens = (self.tokens + elapsed_ms * self.refill).min(self.capacity);
+        self.updated = now;
+        self.tokens
+    }
+}
+
+fn buckets(budget: &CostBudget, now: Instant) -> Vec<Bucket> {
+    [
+        (
+            "rows_per_minute",
+            Unit::Rows,
+            budget.rows_per_minute,
+            MINUTE,
+        ),
+        ("rows_per_hour", Unit::Rows, budget.rows_per_hour, HOUR),
+        (
+            "bytes_per_minute",
+            Unit::Bytes,
+            budget.bytes_per_minute,
+            MINUTE,
+        ),
+        ("bytes_per_hour", Unit::Bytes, budget.bytes_per_hour, HOUR),
+    ]
+    .into_iter()
+    .filter_map(|(name, unit, limit, period)| {
+        let capacity = limit? as f64;
+        Some(Bucket {
+            name,
+            unit,
+            capacity,
+            refill: capacity / period.as_millis() as f64,
+            tokens: capacity,
+            updated: now,
+        })
+    })
+    .collect()
+}
+
+/// `patch` limited to what `current` allows.
+pub fn tighten_budget(current: &CostBudget, patch: CostBudget) -> CostBudget {
+    CostBudget {
+        rows_per_minute: tighter(current.rows_per_minute, patch.rows_per_minute),
+        rows_per_hour: tighter(current.rows_per_hour, patch.rows_per_hour),
+        bytes_per_minute: tighter(current.bytes_per_minute, patch.bytes_per_minute),
+        bytes_per_hour: tighter(current.bytes_per_hour, patch.bytes_per_hour),
+    }
+}
+
+/// Buckets by scope, `agent:<key>` or `session:<key>` where the key is the
+/// budget entry's name, `"*"` for everyone without their own.
+#[derive(Debug, Default)]
+pub struct Budgets {
+    scopes: HashMap<String, Vec<Bucket>>,
+}
+
+impl Budgets {
+    /// Let a query of `scope` start, or say which budget is spent and when
+    /// it has refilled enough to retry. Buckets follow `budget` as it is
+    /// reconfigured, keeping what they hold.
+    pub fn admit(&mut self, scope: &str, budget: &CostBudget, now: Instant) -> Result<(), String> {
+        let fresh = buckets(budget, now);
+        let tracked = self.scopes.entry(scope.to_string()).or_default();
+        let same_limits = tracked.len() == fresh.len()
+            && tracked
+                .iter()
+                .zip(&fresh)
+                .all(|(a, b)| a.name == b.name && a.capacity == b.capacity);
+        if !same_limits {
+            let old = std::mem::replace(tracked, fresh);
+            for bucket in tracked.iter_mut() {
+                if let Some(prev) = old.iter().find(|b| b.name == bucket.name) {
+                    bucket.tokens = prev.tokens.min(bucket.capacity);
+                }
+            }
+        }
+        for bucket in tracked.iter_mut() {
+            let tokens = bucket.refilled(now);
+            if tokens <= 0.0 {
+                let retry_ms = (1.0 - tokens) / bucket.refill;
+                return Err(format!(
+                    "{scope} spent its {} budget of {}; retry in {}s",
+                    bucket.name,
+                    bucket.capacity,
+                    (retry_ms / 1000.0).ceil() as u64
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Take a sent result out of `scope`'s buckets, if it has any.
+    pub fn charge(&mut self, scope: &str, rows: u64, bytes: u64, now: Instant) {
+        for bucket in self.scopes.get_mut(scope).into_iter().flatten() {
+            bucket.refilled(now);
+            bucket.tokens -= match bucket.unit {
+                Unit::Rows => rows,
+                Unit::Bytes => bytes,
+            } as f64;
+        }
+    }
+
+    /// What is left in each tracked bucket, by scope and budget name.
+    pub fn remaining(&mut self, now: Instant) -> BTreeMap<String, BTreeMap<String, u64>> {
+        self.scopes
+            .iter_mut()
+            .filter(|(_, buckets)| !buckets.is_empty())
+            .map(|(scope, buckets)| {
+                let left = buckets
+                    .iter_mut()
+                    .map(|b| (b.name.to_string(), b.refilled(now).max(0.0) as u64))
+                    .collect();
+                (scope.clone(), left)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_budget.rs"]
+mod tests;
@@ -0,0 +1,99 @@
+//! Opt-in result cache for queries that pass `cache_ttl_ms`.
+//!
+//! Entries are keyed by session, exact SQL text, params and the options
+//! that shape rows, so a hit returns exactly what running the statement
+//! again would have emitted a moment ago. The SQL is not normalized: that
+//! would mean parsing it, and whitespace inside literals is significant.
+
+use crate::types::ResolvedOptions;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct CachedRows {
+    session: String,
+    rows: Vec<Value>,
+    stored_at: Instant,
+    ttl: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct ResultCache {
+    entries: HashMap<String, CachedRows>,
+}
+
+/// Cache key for running `sql` with `params` on `session` under `opts`.
+pub fn cache_key(session: &str, sql: &str, params: &[Value], opts: &ResolvedOptions) -> String {
+    let material = json!([
+        session,
+        opts.role,
+        opts.context,
+        sql,
+        params,
+        opts.redact,
+        opts.default_limit,
+        opts.default_limit_action,
+    ]);
+    let digest = Sha256::digest(material.to_string().as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl ResultCache {
+    /// Rows stored under `key` no older than `max_age` nor past their own
+    /// TTL, with their age.
+    pub fn get(&mut self, key: &str, max_age: Duration) -> Option<(Vec<Value>, Duration)> {
+        let entry = self.entries.get(key)?;
+        let age = entry.stored_at.elapsed();
+        if age >= entry.ttl {
+            self.entries.remove(key);
+            return None;
+        }
+        (age < max_age).then(|| (entry.rows.clone(), age))
+    }
+
+    /// Store `rows` for `ttl`, dropping expired entries and then the oldest
+    /// ones to stay within `capacity`.
+    pub fn insert(
+        &mut self,
+        key: String,
+        session: &str,
+        rows: Vec<Value>,
+        ttl: Duration,
+        capacity: usize,
+    ) {
+        self.entries.retain(|_, e| e.stored_at.elapsed() < e.ttl);
+        while !self.entries.is_empty() && self.entries.len() >= capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.stored_at)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        if capacity > 0 {
+            let entry = CachedRows {
+                session: session.to_string(),
+                rows,
+                stored_at: Instant::now(),
+                ttl,
+            };
+            self.entries.insert(key, entry);
+        }
+    }
+
+    /// Drop the results of `session`, or all of them; returns how many.
+    pub fn invalidate(&mut self, session: Option<&str>) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, e| session.is_some_and(|only| only != e.session));
+        before - self.entries.len()
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_cache.rs"]
+mod tests;
@@ -0,0 +1,321 @@
+//! Fault injection for resilience tests, with `--chaos`.
+//!
+//! A `chaos` section wraps whichever executor runs statements so that some
+//! of them are held up, lose their connection or fail with a chosen
+//! SQLSTATE, letting an agent's retry and cancel handling be tested against
+//! afpsql without a real outage. Whether a statement is hit is drawn from a
+//! generator seeded with `seed`, by the statement's position in the run: the
+//! n-th statement fares the same every time, so a failure can be repeated.
+//! It is only ever set at startup and is not meant for production.
+
+use crate::db::{
+    BlockRun, CopyReadback, DbExecutor, ExecError, ExecOutcome, PoolReport, PoolState, ScriptRun,
+};
+use crate::types::{ColumnInfo, ResolvedOptions, SessionConfig, Timing};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Serialization failure, deadlock and cancelled statement: what a client
+/// is expected to retry or give up on.
+const DEFAULT_SQLSTATES: [&str; 3] = ["40001", "40P01", "57014"];
+
+/// Faults to inject and how often; probabilities are from `0` to `1`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Chaos {
+    #[serde(default)]
+    pub seed: u64,
+    /// Chance a statement waits `latency_ms` before it runs.
+    #[serde(default)]
+    pub latency_probability: f64,
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Chance a statement fails as if its connection had dropped.
+    #[serde(default)]
+    pub drop_probability: f64,
+    /// Chance a statement fails with one of `sqlstates`.
+    #[serde(default)]
+    pub error_probability: f64,
+    #[serde(default = "default_sqlstates")]
+    pub sqlstates: Vec<String>,
+}
+
+fn default_sqlstates() -> Vec<String> {
+    DEFAULT_SQLSTATES.iter().map(|s| s.to_string()).collect()
+}
+
+impl Chaos {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, p) in [
+            ("latency_probability", self.latency_probability),
+            ("drop_probability", self.drop_probability),
+            ("error_probability", self.error_probability),
+        ] {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(format!("chaos.{name} must be between 0 and 1"));
+            }
+        }
+        if self.error_probability > 0.0 && self.sqlstates.is_empty() {
+            return Err("chaos.sqlstates needs at least one SQLSTATE".to_string());
+        }
+        match self.sqlstates.iter().find(|s| !is_sqlstate(s)) {
+            Some(bad) => Err(format!("chaos.sqlstates: {bad} is not a SQLSTATE")),
+            None => Ok(()),
+        }
+    }
+
+    /// What happens to the statement at position `n`: whether it is delayed,
+    /// and the error it fails with, if any.
+    pub fn fault(&self, n: u64) -> (bool, Option<ExecError>) {
+        let slot = |k: u64| splitmix64(self.seed ^ splitmix64(n.wrapping_mul(4) + k));
+        let draw = |k: u64| unit(slot(k));
+        let delay = draw(0) < self.latency_probability;
+        let error = if draw(1) < self.drop_probability {
+            Some(ExecError::Connect("chaos: connection dropped".to_string()))
+        } else if draw(2) < self.error_probability {
+            let pick = slot(3);
+            let sqlstate = &self.sqlstates[(pick % self.sqlstates.len() as u64) as usize];
+            Some(ExecError::Sql {
+                sqlstate: sqlstate.clone(),
+                message: format!("chaos: injected {sqlstate}"),
+                detail: None,
+                hint: None,
+                position: None,
+            })
+        } else {
+            None
+        };
+        (delay, error)
+    }
+}
+
+fn is_sqlstate(s: &str) -> bool {
+    s.len() == 5
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase())
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// `x` as a fraction in `[0, 1)`.
+fn unit(x: u64) -> f64 {
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Runs statements on `inner` after injecting `chaos`'s faults. Statements,
+/// `DO` blocks and `COPY` count; the rest is passed through.
+pub struct ChaosExecutor {
+    inner: Arc<dyn DbExecutor>,
+    chaos: Chaos,
+    statements: AtomicU64,
+}
+
+impl ChaosExecutor {
+    pub fn new(inner: Arc<dyn DbExecutor>, chaos: Chaos) -> Self {
+        Self {
+            inner,
+            chaos,
+            statements: AtomicU64::new(0),
+        }
+    }
+
+    async fn inject(&self) -> Result<(), ExecError> {
+        let n = self.statements.fetch_add(1, Ordering::Relaxed);
+        let (delay, error) = self.chaos.fault(n);
+        if delay {
+            tokio::time::sleep(Duration::from_millis(self.chaos.latency_ms)).await;
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+#[async_trait]
+impl DbExecutor for ChaosExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        self.execute_timed(session_name, session_cfg, sql, params, opts)
+            .await
+            .0
+    }
+
+    async fn execute_timed(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> (Result<ExecOutcome, ExecError>, Timing) {
+        if let Err(e) = self.inject().await {
+            return (Err(e), Timing::default());
+        }
+        self.inner
+            .execute_timed(session_name, session_cfg, sql, params, opts)
+            .await
+    }
+
+    async fn execute_pages(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+        page_rows: usize,
+        pages: mpsc::Sender<Vec<Value>>,
+    ) -> Result<(), ExecError> {
+        self.inject().await?;
+        self.inner
+            .execute_pages(
+                session_name,
+                session_cfg,
+                sql,
+                params,
+                opts,
+                page_rows,
+                pages,
+            )
+            .await
+    }
+
+    async fn execute_block(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        body: &str,
+        vars: &[(String, String)],
+        opts: &ResolvedOptions,
+    ) -> BlockRun {
+        if let Err(e) = self.inject().await {
+            return BlockRun::failed(e);
+        }
+        self.inner
+            .execute_block(session_name, session_cfg, body, vars, opts)
+            .await
+    }
+
+    async fn export_snapshot(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<String, ExecError> {
+        self.inner.export_snapshot(session_name, session_cfg).await
+    }
+
+    async fn release_snapshot(&self, session_name: &str, snapshot: &str) -> bool {
+        self.inner.release_snapshot(session_name, snapshot).await
+    }
+
+    async fn open_workspace(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        workspace: &str,
+    ) -> Result<(), ExecError> {
+        self.inner
+            .open_workspace(session_name, session_cfg, workspace)
+            .await
+    }
+
+    async fn close_workspace(&self, workspace: &str) -> bool {
+        self.inner.close_workspace(workspace).await
+    }
+
+    async fn reconnect_workspace(&self, workspace: &str) -> Result<bool, ExecError> {
+        self.inner.reconnect_workspace(workspace).await
+    }
+
+    fn schema_generation(&self) -> u64 {
+        self.inner.schema_generation()
+    }
+
+    async fn execute_script(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        statements: &[String],
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> ScriptRun {
+        self.inner
+            .execute_script(session_name, session_cfg, statements, params, opts)
+            .await
+    }
+
+    async fn describe(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        opts: &ResolvedOptions,
+    ) -> Result<(Vec<String>, Vec<ColumnInfo>), ExecError> {
+        self.inner
+            .describe(session_name, session_cfg, sql, opts)
+            .await
+    }
+
+    async fn copy_in(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        self.inject().await?;
+        self.inner
+            .copy_in(session_name, session_cfg, sql, data, opts)
+            .await
+    }
+
+    async fn copy_in_verified(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        readback: &CopyReadback<'_>,
+        opts: &ResolvedOptions,
+    ) -> Result<(u64, Vec<Value>, bool), ExecError> {
+        self.inject().await?;
+        self.inner
+            .copy_in_verified(session_name, session_cfg, sql, data, readback, opts)
+            .await
+    }
+
+    async fn connect(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<(), ExecError> {
+        self.inner.connect(session_name, session_cfg).await
+    }
+
+    async fn maintain_pools(&self, idle_timeout: Option<Duration>) -> Vec<PoolReport> {
+        self.inner.maintain_pools(idle_timeout).await
+    }
+
+    async fn pool_states(&self) -> Vec<PoolState> {
+        self.inner.pool_states().await
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_chaos.rs"]
+mod tests;
@@ -0,0 +1,134 @@
+//! Data quality checks for `psql_run_checks`.
+//!
+//! Each check in the `checks` config selects the rows that break an
+//! assertion, such as orders without a customer. A check passes when it
+//! returns no rows; otherwise it fails with the number of offending rows
+//! and a sample of them. Checks run read-only and count at most
+//! [`MAX_COUNTED_ROWS`] rows, so a badly broken table stays cheap to check.
+
+use crate::conn::resolve_session_name;
+use crate::db::{ExecError, ExecOutcome};
+use crate::handler::App;
+use crate::types::{Check, CheckSeverity, DefaultLimitAction, QueryOptions};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Offending rows returned per failed check when the caller does not say.
+pub const DEFAULT_SAMPLE_ROWS: usize = 5;
+
+/// Offending rows counted per check; past this `more_rows` is set.
+pub const MAX_COUNTED_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check's SQL could not run.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub severity: CheckSeverity,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub failing_rows: usize,
+    /// More than `failing_rows` rows break the check.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub more_rows: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Run `check`, on `session` when given and otherwise on the check's own.
+pub async fn run_check(
+    app: &Arc<App>,
+    name: &str,
+    check: &Check,
+    session: Option<&str>,
+    sample_rows: usize,
+) -> CheckResult {
+    let start = Instant::now();
+    let cfg = app.config.read().await.clone();
+    let session_name = resolve_session_name(&cfg, session.or(check.session.as_deref()));
+    let mut opts = cfg.resolve_options(&QueryOptions {
+        read_only: Some(true),
+        statement_timeout_ms: check.statement_timeout_ms,
+        ..QueryOptions::default()
+    });
+    opts.default_limit = Some(MAX_COUNTED_ROWS);
+    opts.default_limit_action = DefaultLimitAction::Limit;
+    let result = match cfg.sessions.get(&session_name) {
+        Some(session_cfg) => {
+            app.executor
+                .execute(&session_name, session_cfg, &check.sql, &[], &opts)
+                .await
+        }
+        None => Err(ExecError::Connect(format!(
+            "unknown session: {session_name}"
+        ))),
+    };
+    let mut out = check_result(name, check, result, sample_rows);
+    out.duration_ms = start.elapsed().as_millis() as u64;
+    out
+}
+
+/// The outcome of running a check's SQL as a [`CheckResult`].
+pub fn check_result(
+    name: &str,
+    check: &Check,
+    result: Result<ExecOutcome, ExecError>,
+    sample_rows: usize,
+) -> CheckResult {
+    let mut out = CheckResult {
+        name: name.to_string(),
+        severity: check.severity,
+        status: CheckStatus::Pass,
+        description: check.description.clone(),
+        failing_rows: 0,
+        more_rows: false,
+        sample: vec![],
+        error: None,
+        duration_ms: 0,
+    };
+    match result {
+        Ok(ExecOutcome::Rows(rows)) if rows.is_empty() => {}
+        Ok(ExecOutcome::Rows(mut rows)) => {
+            out.status = CheckStatus::Fail;
+            out.more_rows = rows.len() > MAX_COUNTED_ROWS;
+            out.failing_rows = rows.len().min(MAX_COUNTED_ROWS);
+            rows.truncate(sample_rows);
+            out.sample = rows;
+        }
+        Ok(ExecOutcome::Command { .. }) => {
+            out.status = CheckStatus::Error;
+            out.error = Some("check sql must return rows".to_string());
+        }
+        Err(e) => {
+            out.status = CheckStatus::Error;
+            out.error = Some(e.to_string());
+        }
+    }
+    out
+}
+
+/// A run passes unless a check errored or an `error` severity check failed.
+pub fn passed(results: &[CheckResult]) -> bool {
+    results.iter().all(|r| match r.status {
+        CheckStatus::Pass => true,
+        CheckStatus::Fail => r.severity == CheckSeverity::Warning,
+        CheckStatus::Error => false,
+    })
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_checks.rs"]
+mod tests;
@@ -0,0 +1,125 @@
+//! Row counts and content checksums for operations with `verify`: exports
+//! to `export_uri`, `transfer` and `import`.
+//!
+//! A checksum is the wrapping sum of a 64-bit hash of each row, so row order
+//! does not matter and batches combine by adding. A row hashes its values in
+//! column order, each as text: strings as they are, numbers, booleans,
+//! arrays and objects as compact JSON, NULL as `\N`. Strings holding a JSON
+//! number, array or object are read as that value first, so `"7"` from a CSV
+//! file and `7` stored in an integer column agree, while `"007"` does not.
+
+use crate::types::{Checksum, Verification, VerifyMode};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowHasher {
+    rows: u64,
+    sum: u64,
+}
+
+impl RowHasher {
+    /// The hash of `rows`, reading `columns` of each; missing ones are NULL.
+    pub fn of(rows: &[Value], columns: &[String]) -> Self {
+        let mut hasher = Self::default();
+        for row in rows {
+            hasher.add(row, columns);
+        }
+        hasher
+    }
+
+    pub fn add(&mut self, row: &Value, columns: &[String]) {
+        let mut digest = Sha256::new();
+        for column in columns {
+            digest.update(canonical(row.get(column).unwrap_or(&Value::Null)).as_bytes());
+            digest.update(b"\x1f");
+        }
+        let hash = digest.finalize();
+        let mut first = [0u8; 8];
+        first.copy_from_slice(&hash[..8]);
+        self.rows += 1;
+        self.sum = self.sum.wrapping_add(u64::from_be_bytes(first));
+    }
+
+    pub fn merge(&mut self, other: RowHasher) {
+        self.rows += other.rows;
+        self.sum = self.sum.wrapping_add(other.sum);
+    }
+
+    pub fn checksum(&self) -> Checksum {
+        Checksum {
+            rows: self.rows,
+            checksum: format!("{:016x}", self.sum),
+        }
+    }
+}
+
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Null => "\\N".to_string(),
+        Value::String(s) => match serde_json::from_str::<Value>(s) {
+            Ok(parsed @ (Value::Number(_) | Value::Array(_) | Value::Object(_))) => {
+                parsed.to_string()
+            }
+            _ => s.clone(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// The rows sent and the rows found on the other end, batch by batch.
+#[derive(Debug)]
+pub struct Verifier {
+    pub mode: VerifyMode,
+    sent: RowHasher,
+    stored: RowHasher,
+    mismatch: Option<String>,
+}
+
+impl Verifier {
+    pub fn new(mode: VerifyMode) -> Self {
+        Self {
+            mode,
+            sent: RowHasher::default(),
+            stored: RowHasher::default(),
+            mismatch: None,
+        }
+    }
+
+    /// Whether a batch that reads back as `stored` may be kept.
+    pub fn accepts(&self, sent: &RowHasher, stored: &RowHasher) -> bool {
+        self.mode == VerifyMode::Flag || sent == stored
+    }
+
+    /// Count a batch that was kept; a rejected one is remembered as the
+    /// mismatch that ends the operation.
+    pub fn record(&mut self, sent: RowHasher, stored: RowHasher, kept: bool) {
+        if kept {
+            self.sent.merge(sent);
+            self.stored.merge(stored);
+        } else {
+            let (sent, stored) = (sent.checksum(), stored.checksum());
+            self.mismatch = Some(format!(
+                "checksum mismatch: sent {} rows ({}), read back {} rows ({})",
+                sent.rows, sent.checksum, stored.rows, stored.checksum
+            ));
+        }
+    }
+
+    /// The mismatch that rolled a batch back in fail mode, if any.
+    pub fn mismatch(&self) -> Option<&str> {
+        self.mismatch.as_deref()
+    }
+
+    pub fn report(&self) -> Verification {
+        Verification {
+            source: self.sent.checksum(),
+            target: self.stored.checksum(),
+            matched: self.sent == self.stored,
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_checksum.rs"]
+mod tests;
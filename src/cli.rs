@@ -1,11 +1,18 @@
-use crate::types::{QueryOptions, SessionConfig};
 use agent_first_data::{cli_parse_log_filters, cli_parse_output, OutputFormat};
+use agent_first_psql::chaos::Chaos;
+use agent_first_psql::deadline::Deadline;
+use agent_first_psql::script::split_statements;
+use agent_first_psql::template;
+use agent_first_psql::types::{
+    Compression, OnError, PublishFormat, QueryOptions, RedactAction, RedactionRule, SessionConfig,
+    VerifyMode,
+};
 use clap::{Parser, ValueEnum};
 use serde_json::{json, Value};
 use std::collections::BTreeMap;
 
 pub enum Mode {
-    Cli(CliRequest),
+    Cli(Box<CliRequest>),
     Pipe(PipeInit),
     #[cfg(feature = "mcp")]
     Mcp(PipeInit),
@@ -15,6 +22,32 @@ pub struct PipeInit {
     pub output: OutputFormat,
     pub session: SessionConfig,
     pub log: Vec<String>,
+    pub log_file: Option<String>,
+    pub redact: Vec<RedactionRule>,
+    pub redact_salt_secret: Option<String>,
+    pub results_dir: Option<String>,
+    pub state_dir: Option<String>,
+    pub require_approval: bool,
+    pub audit_log: Option<String>,
+    /// `--approver-session`: sessions allowed to `approve`; startup-only.
+    pub approver_sessions: Vec<String>,
+    /// `--approver-secret`: secret every `approve` must present; startup-only.
+    pub approver_secret: Option<String>,
+    /// `--allow-replication-role`: let queries set `replication_role`;
+    /// startup-only.
+    pub allow_replication_role: bool,
+    /// `--allow-backend-signals`: let `psql_activity` cancel and terminate
+    /// backends; startup-only.
+    pub allow_backend_signals: bool,
+    pub agent_name: Option<String>,
+    pub executor: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub mock: Option<String>,
+    /// `--chaos`: faults to inject into statements.
+    pub chaos: Option<Chaos>,
+    /// Connect every configured session before reading input.
+    pub eager_connect: bool,
     pub startup_argv: Vec<String>,
     pub startup_args: Value,
     pub startup_env: Value,
@@ -22,12 +55,31 @@ pub struct PipeInit {
 }
 
 pub struct CliRequest {
-    pub sql: String,
+    /// One or more statements; each runs as its own query.
+    pub statements: Vec<String>,
+    /// Statements run at once; `1` runs them in order.
+    pub parallel: usize,
+    /// Run the statements as a script that reports a `script_result`.
+    pub on_error: Option<OnError>,
     pub params: Vec<Value>,
     pub options: QueryOptions,
     pub session: SessionConfig,
     pub output: OutputFormat,
     pub log: Vec<String>,
+    pub redact: Vec<RedactionRule>,
+    pub redact_salt_secret: Option<String>,
+    pub results_dir: Option<String>,
+    pub require_approval: bool,
+    pub audit_log: Option<String>,
+    pub agent_name: Option<String>,
+    pub executor: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub mock: Option<String>,
+    /// `--chaos`: faults to inject into statements.
+    pub chaos: Option<Chaos>,
+    /// Give up on the query after this long and exit with code 3.
+    pub max_runtime_ms: Option<u64>,
     pub startup_argv: Vec<String>,
     pub startup_args: Value,
     pub startup_env: Value,
@@ -44,15 +96,49 @@ enum RuntimeMode {
     Psql,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum CompressArg {
+    Gzip,
+    Zstd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum VerifyArg {
+    Fail,
+    Flag,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum PublishFormatArg {
+    Row,
+    Batch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OnErrorArg {
+    Stop,
+    Continue,
+    #[value(name = "rollback_statement")]
+    RollbackStatement,
+}
+
 #[derive(Parser)]
 #[command(name = "afpsql", version, about = "Agent-First PostgreSQL client")]
 struct AfdCli {
     #[arg(long)]
-    sql: Option<String>,
+    sql: Vec<String>,
     #[arg(long = "sql-file")]
-    sql_file: Option<String>,
+    sql_file: Vec<String>,
+    #[arg(long)]
+    parallel: Option<usize>,
+    #[arg(long = "on-error", value_enum)]
+    on_error: Option<OnErrorArg>,
     #[arg(long = "param")]
     param: Vec<String>,
+    #[arg(long = "define")]
+    define: Vec<String>,
+    #[arg(long = "ident")]
+    ident: Vec<String>,
     #[arg(long = "stream-rows")]
     stream_rows: bool,
     #[arg(long = "batch-rows")]
@@ -63,12 +149,70 @@ struct AfdCli {
     statement_timeout_ms: Option<u64>,
     #[arg(long = "lock-timeout-ms")]
     lock_timeout_ms: Option<u64>,
+    #[arg(long = "timeout-profile")]
+    timeout_profile: Option<String>,
+    #[arg(long)]
+    deadline: Option<String>,
     #[arg(long = "inline-max-rows")]
     inline_max_rows: Option<usize>,
     #[arg(long = "inline-max-bytes")]
     inline_max_bytes: Option<usize>,
+    #[arg(long)]
+    summarize: Option<usize>,
     #[arg(long = "read-only")]
     read_only: bool,
+    #[arg(long = "defer-constraints")]
+    defer_constraints: bool,
+    #[arg(long = "default-limit")]
+    default_limit: Option<usize>,
+    #[arg(long = "compress", value_enum)]
+    compress: Option<CompressArg>,
+    #[arg(long = "store-result")]
+    store_result: bool,
+    #[arg(long = "export-uri")]
+    export_uri: Option<String>,
+    #[arg(long = "verify", value_enum)]
+    verify: Option<VerifyArg>,
+    #[arg(long = "publish-uri")]
+    publish_uri: Option<String>,
+    #[arg(long = "publish-key", value_delimiter = ',')]
+    publish_key: Vec<String>,
+    #[arg(long = "publish-format", value_enum)]
+    publish_format: Option<PublishFormatArg>,
+    #[arg(long = "server-timing")]
+    server_timing: bool,
+    #[arg(long = "results-dir")]
+    results_dir: Option<String>,
+    #[arg(long = "state-dir")]
+    state_dir: Option<String>,
+    #[arg(long = "require-approval")]
+    require_approval: bool,
+    #[arg(long = "audit-log")]
+    audit_log: Option<String>,
+    #[arg(long = "approver-session")]
+    approver_session: Vec<String>,
+    #[arg(long = "approver-secret")]
+    approver_secret: Option<String>,
+    #[arg(long = "allow-replication-role")]
+    allow_replication_role: bool,
+    #[arg(long = "allow-backend-signals")]
+    allow_backend_signals: bool,
+    #[arg(long = "agent-name")]
+    agent_name: Option<String>,
+    #[arg(long = "executor")]
+    executor: Option<String>,
+    #[arg(long = "record", conflicts_with_all = ["executor", "replay"])]
+    record: Option<String>,
+    #[arg(long = "replay", conflicts_with = "executor")]
+    replay: Option<String>,
+    #[arg(long = "mock", conflicts_with_all = ["executor", "record", "replay"])]
+    mock: Option<String>,
+    #[arg(long = "chaos")]
+    chaos: Option<String>,
+    #[arg(long = "eager-connect")]
+    eager_connect: bool,
+    #[arg(long = "max-runtime-ms")]
+    max_runtime_ms: Option<u64>,
 
     #[arg(long = "dsn-secret")]
     dsn_secret: Option<String>,
@@ -89,6 +233,12 @@ struct AfdCli {
     output: String,
     #[arg(long = "log", value_delimiter = ',')]
     log: Vec<String>,
+    #[arg(long = "log-file")]
+    log_file: Option<String>,
+    #[arg(long = "redact")]
+    redact: Vec<String>,
+    #[arg(long = "redact-salt-secret")]
+    redact_salt_secret: Option<String>,
     #[arg(long, value_enum, default_value_t = RuntimeMode::Cli)]
     mode: RuntimeMode,
 }
@@ -103,6 +253,7 @@ pub fn parse_args() -> Result<Mode, String> {
     let cli = AfdCli::try_parse_from(&raw).map_err(|e| e.to_string())?;
     let output = parse_output(&cli.output)?;
     let log = parse_log_categories(&cli.log);
+    let redact = parse_redact_rules(&cli.redact)?;
     let session = SessionConfig {
         dsn_secret: cli.dsn_secret,
         conninfo_secret: cli.conninfo_secret,
@@ -111,6 +262,12 @@ pub fn parse_args() -> Result<Mode, String> {
         user: cli.user,
         dbname: cli.dbname,
         password_secret: cli.password_secret,
+        pinned: None,
+        sqlite_path: None,
+        tcp_keepalive_idle_ms: None,
+        settings: None,
+        context: None,
+        eager: None,
     };
     let mode_name = match cli.mode {
         RuntimeMode::Cli => "cli",
@@ -123,15 +280,52 @@ pub fn parse_args() -> Result<Mode, String> {
         "mode": mode_name,
         "sql": &cli.sql,
         "sql_file": &cli.sql_file,
+        "parallel": cli.parallel,
+        "on_error": cli.on_error.map(|o| match o {
+            OnErrorArg::Stop => "stop",
+            OnErrorArg::Continue => "continue",
+            OnErrorArg::RollbackStatement => "rollback_statement",
+        }),
         "param": &cli.param,
+        "define": &cli.define,
+        "ident": &cli.ident,
         "stream_rows": cli.stream_rows,
         "batch_rows": cli.batch_rows,
         "batch_bytes": cli.batch_bytes,
         "statement_timeout_ms": cli.statement_timeout_ms,
         "lock_timeout_ms": cli.lock_timeout_ms,
+        "timeout_profile": &cli.timeout_profile,
+        "deadline": &cli.deadline,
         "inline_max_rows": cli.inline_max_rows,
         "inline_max_bytes": cli.inline_max_bytes,
+        "summarize": cli.summarize,
         "read_only": cli.read_only,
+        "defer_constraints": cli.defer_constraints,
+        "default_limit": cli.default_limit,
+        "compress": cli.compress.map(|c| format!("{c:?}").to_lowercase()),
+        "store_result": cli.store_result,
+        "export_uri": &cli.export_uri,
+        "verify": cli.verify.map(|v| format!("{v:?}").to_lowercase()),
+        "publish_uri": &cli.publish_uri,
+        "publish_key": &cli.publish_key,
+        "publish_format": cli.publish_format.map(|f| format!("{f:?}").to_lowercase()),
+        "server_timing": cli.server_timing,
+        "results_dir": &cli.results_dir,
+        "state_dir": &cli.state_dir,
+        "require_approval": cli.require_approval,
+        "audit_log": &cli.audit_log,
+        "approver_session": &cli.approver_session,
+        "approver_secret": &cli.approver_secret,
+        "allow_replication_role": cli.allow_replication_role,
+        "allow_backend_signals": cli.allow_backend_signals,
+        "agent_name": &cli.agent_name,
+        "executor": &cli.executor,
+        "record": &cli.record,
+        "replay": &cli.replay,
+        "mock": &cli.mock,
+        "chaos": &cli.chaos,
+        "eager_connect": cli.eager_connect,
+        "max_runtime_ms": cli.max_runtime_ms,
         "dsn_secret": &session.dsn_secret,
         "conninfo_secret": &session.conninfo_secret,
         "host": &session.host,
@@ -141,8 +335,12 @@ pub fn parse_args() -> Result<Mode, String> {
         "password_secret": &session.password_secret,
         "output": output_name(output),
         "log": &log,
+        "log_file": &cli.log_file,
+        "redact": &redact,
+        "redact_salt_secret": &cli.redact_salt_secret,
     });
     let startup_env = startup_env_snapshot();
+    let chaos = cli.chaos.as_deref().map(parse_chaos).transpose()?;
 
     match cli.mode {
         RuntimeMode::Pipe => {
@@ -150,6 +348,24 @@ pub fn parse_args() -> Result<Mode, String> {
                 output,
                 session,
                 log: log.clone(),
+                log_file: cli.log_file,
+                redact,
+                redact_salt_secret: cli.redact_salt_secret,
+                results_dir: cli.results_dir,
+                state_dir: cli.state_dir,
+                require_approval: cli.require_approval,
+                audit_log: cli.audit_log,
+                approver_sessions: cli.approver_session,
+                approver_secret: cli.approver_secret,
+                allow_replication_role: cli.allow_replication_role,
+                allow_backend_signals: cli.allow_backend_signals,
+                agent_name: cli.agent_name,
+                executor: cli.executor,
+                record: cli.record,
+                replay: cli.replay,
+                mock: cli.mock,
+                chaos,
+                eager_connect: cli.eager_connect,
                 startup_argv: raw,
                 startup_args,
                 startup_env,
@@ -162,6 +378,24 @@ pub fn parse_args() -> Result<Mode, String> {
                 output,
                 session,
                 log: log.clone(),
+                log_file: cli.log_file,
+                redact,
+                redact_salt_secret: cli.redact_salt_secret,
+                results_dir: cli.results_dir,
+                state_dir: cli.state_dir,
+                require_approval: cli.require_approval,
+                audit_log: cli.audit_log,
+                approver_sessions: cli.approver_session,
+                approver_secret: cli.approver_secret,
+                allow_replication_role: cli.allow_replication_role,
+                allow_backend_signals: cli.allow_backend_signals,
+                agent_name: cli.agent_name,
+                executor: cli.executor,
+                record: cli.record,
+                replay: cli.replay,
+                mock: cli.mock,
+                chaos,
+                eager_connect: cli.eager_connect,
                 startup_argv: raw,
                 startup_args,
                 startup_env,
@@ -171,7 +405,23 @@ pub fn parse_args() -> Result<Mode, String> {
         RuntimeMode::Cli | RuntimeMode::Psql => {}
     }
 
-    let sql = load_sql(cli.sql, cli.sql_file)?;
+    let statements = load_statements(cli.sql, cli.sql_file, read_stdin)?;
+    let parallel = match cli.parallel {
+        Some(0) => return Err("--parallel must be at least 1".to_string()),
+        Some(n) => n,
+        None => 1,
+    };
+    let on_error = cli.on_error.map(|o| match o {
+        OnErrorArg::Stop => OnError::Stop,
+        OnErrorArg::Continue => OnError::Continue,
+        OnErrorArg::RollbackStatement => OnError::RollbackStatement,
+    });
+    if parallel > 1 && on_error.is_some() {
+        return Err(
+            "--on-error runs statements in order; it cannot be combined with --parallel"
+                .to_string(),
+        );
+    }
     let params = parse_params(&cli.param)?;
 
     let options = QueryOptions {
@@ -180,23 +430,79 @@ pub fn parse_args() -> Result<Mode, String> {
         batch_bytes: cli.batch_bytes,
         statement_timeout_ms: cli.statement_timeout_ms,
         lock_timeout_ms: cli.lock_timeout_ms,
+        timeout_profile: cli.timeout_profile,
+        deadline: cli.deadline.as_deref().map(Deadline::from_arg),
         read_only: if cli.read_only { Some(true) } else { None },
+        defer_constraints: cli.defer_constraints,
+        replication_role: None,
         inline_max_rows: cli.inline_max_rows,
         inline_max_bytes: cli.inline_max_bytes,
+        summarize: cli.summarize,
+        default_limit: cli.default_limit,
+        compress: cli.compress.map(|c| match c {
+            CompressArg::Gzip => Compression::Gzip,
+            CompressArg::Zstd => Compression::Zstd,
+        }),
+        store_result: cli.store_result,
+        export_uri: cli.export_uri.clone(),
+        verify: cli.verify.map(|v| match v {
+            VerifyArg::Fail => VerifyMode::Fail,
+            VerifyArg::Flag => VerifyMode::Flag,
+        }),
+        publish_uri: cli.publish_uri.clone(),
+        publish_key: (!cli.publish_key.is_empty()).then(|| cli.publish_key.clone()),
+        publish_format: cli.publish_format.map(|f| match f {
+            PublishFormatArg::Row => PublishFormat::Row,
+            PublishFormatArg::Batch => PublishFormat::Batch,
+        }),
+        notify: None,
+        server_timing: cli.server_timing,
+        approved: false,
+        snapshot: None,
+        autocommit: false,
+        nested_tables: BTreeMap::new(),
+        workspace: None,
+        cache_ttl_ms: None,
+        materialize_to: None,
+        diff_output: false,
+        key_columns: None,
+        dedup: false,
+        resume_from: None,
+        ack_window: None,
+        vars: parse_defines(&cli.define)?,
+        idents: parse_idents(&cli.ident)?,
+        agent: None,
+        context: None,
+        prepare_transaction: None,
+        expect: None,
     };
 
-    Ok(Mode::Cli(CliRequest {
-        sql,
+    Ok(Mode::Cli(Box::new(CliRequest {
+        statements,
+        parallel,
+        on_error,
         params,
         options,
         session,
         output,
         log,
+        redact,
+        redact_salt_secret: cli.redact_salt_secret,
+        results_dir: cli.results_dir,
+        require_approval: cli.require_approval,
+        audit_log: cli.audit_log,
+        agent_name: cli.agent_name,
+        executor: cli.executor,
+        record: cli.record,
+        replay: cli.replay,
+        mock: cli.mock,
+        chaos,
+        max_runtime_ms: cli.max_runtime_ms,
         startup_argv: raw,
         startup_args,
         startup_env,
         startup_requested,
-    }))
+    })))
 }
 
 fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
@@ -284,7 +590,11 @@ fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
             }
             "-v" => {
                 i += 1;
-                params_kv.push(raw.get(i).ok_or("-v requires N=value")?.clone());
+                params_kv.push(
+                    raw.get(i)
+                        .ok_or("-v requires N=value or name=value")?
+                        .clone(),
+                );
                 i += 1;
             }
             "--output" => {
@@ -305,7 +615,7 @@ fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
             }
             other if other.starts_with("postgresql://") || other.starts_with("postgres://") => {
                 // treat positional DSN in psql mode
-                let session = SessionConfig {
+                let mut session = SessionConfig {
                     dsn_secret: Some(other.to_string()),
                     conninfo_secret,
                     host,
@@ -313,6 +623,12 @@ fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
                     user,
                     dbname,
                     password_secret: None,
+                    pinned: None,
+                    sqlite_path: None,
+                    tcp_keepalive_idle_ms: None,
+                    settings: None,
+                    context: None,
+                    eager: None,
                 };
                 let startup_args = psql_startup_args(
                     "psql",
@@ -323,20 +639,38 @@ fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
                     output,
                     &log_entries,
                 );
-                let sql = load_sql(sql, sql_file)?;
-                let params = parse_params(&params_kv)?;
-                return Ok(Mode::Cli(CliRequest {
-                    sql,
+                let statements = load_psql_statements(sql, sql_file, read_stdin)?;
+                pin_script_session(&mut session, &statements);
+                let (params, vars, on_error) = parse_psql_vars(&params_kv)?;
+                return Ok(Mode::Cli(Box::new(CliRequest {
+                    statements,
+                    parallel: 1,
+                    on_error,
                     params,
-                    options: QueryOptions::default(),
+                    options: QueryOptions {
+                        vars,
+                        ..QueryOptions::default()
+                    },
                     session,
                     output,
                     log: parse_log_categories(&log_entries),
+                    redact: vec![],
+                    redact_salt_secret: None,
+                    results_dir: None,
+                    require_approval: false,
+                    audit_log: None,
+                    agent_name: None,
+                    executor: None,
+                    record: None,
+                    replay: None,
+                    mock: None,
+                    chaos: None,
+                    max_runtime_ms: None,
                     startup_argv: raw.to_vec(),
                     startup_args,
                     startup_env: startup_env_snapshot(),
                     startup_requested,
-                }));
+                })));
             }
             unsupported => {
                 return Err(format!(
@@ -346,7 +680,7 @@ fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
         }
     }
 
-    let session = SessionConfig {
+    let mut session = SessionConfig {
         dsn_secret,
         conninfo_secret,
         host,
@@ -354,33 +688,57 @@ fn parse_psql_mode(raw: &[String]) -> Result<Mode, String> {
         user,
         dbname,
         password_secret: None,
+        pinned: None,
+        sqlite_path: None,
+        tcp_keepalive_idle_ms: None,
+        settings: None,
+        context: None,
+        eager: None,
     };
 
     let startup_sql = sql.clone();
     let startup_sql_file = sql_file.clone();
-    let sql = load_sql(sql, sql_file)?;
-    let params = parse_params(&params_kv)?;
+    let statements = load_psql_statements(sql, sql_file, read_stdin)?;
+    pin_script_session(&mut session, &statements);
+    let (params, vars, on_error) = parse_psql_vars(&params_kv)?;
     let startup_args = psql_startup_args(
         "psql",
-        startup_sql.or_else(|| Some(sql.clone())),
+        startup_sql.or_else(|| Some(statements.join(";\n"))),
         startup_sql_file,
         &params_kv,
         &session,
         output,
         &log_entries,
     );
-    Ok(Mode::Cli(CliRequest {
-        sql,
+    Ok(Mode::Cli(Box::new(CliRequest {
+        statements,
+        parallel: 1,
+        on_error,
         params,
-        options: QueryOptions::default(),
+        options: QueryOptions {
+            vars,
+            ..QueryOptions::default()
+        },
         session,
         output,
         log: parse_log_categories(&log_entries),
+        redact: vec![],
+        redact_salt_secret: None,
+        results_dir: None,
+        require_approval: false,
+        audit_log: None,
+        agent_name: None,
+        executor: None,
+        record: None,
+        replay: None,
+        mock: None,
+        chaos: None,
+        max_runtime_ms: None,
         startup_argv: raw.to_vec(),
         startup_args,
         startup_env: startup_env_snapshot(),
         startup_requested,
-    }))
+    })))
 }
 
 fn is_psql_mode_requested(raw: &[String]) -> bool {
@@ -401,21 +759,123 @@ fn is_psql_mode_requested(raw: &[String]) -> bool {
     false
 }
 
-fn load_sql(sql: Option<String>, sql_file: Option<String>) -> Result<String, String> {
+/// Every `--sql`, then every `--sql-file`, in the order given. `--sql -`
+/// reads a script from `stdin` and contributes each of its statements.
+fn load_statements(
+    sql: Vec<String>,
+    sql_file: Vec<String>,
+    stdin: impl FnOnce() -> Result<String, String>,
+) -> Result<Vec<String>, String> {
+    if sql.is_empty() && sql_file.is_empty() {
+        return Err("one of --sql or --sql-file is required".to_string());
+    }
+    let mut stdin = Some(stdin);
+    let mut statements = vec![];
+    for entry in sql {
+        if entry != "-" {
+            statements.push(entry);
+            continue;
+        }
+        let read = stdin.take().ok_or("--sql - can only be given once")?;
+        statements.extend(script_statements(&read()?, "stdin")?);
+    }
+    for path in sql_file {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("read --sql-file {path} failed: {e}"))?;
+        statements.push(text);
+    }
+    Ok(statements)
+}
+
+/// psql-mode statements: `-c` as given, or the script from `-f` (`-f -` is
+/// stdin) split into statements. Without either, the script comes from stdin
+/// as it does for `psql`.
+fn load_psql_statements(
+    sql: Option<String>,
+    sql_file: Option<String>,
+    stdin: impl FnOnce() -> Result<String, String>,
+) -> Result<Vec<String>, String> {
     match (sql, sql_file) {
-        (Some(s), None) => Ok(s),
+        (Some(s), None) => Ok(vec![s]),
+        (None, Some(path)) if path == "-" => script_statements(&stdin()?, "stdin"),
         (None, Some(path)) => {
-            std::fs::read_to_string(path).map_err(|e| format!("read --sql-file failed: {e}"))
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| format!("read --sql-file failed: {e}"))?;
+            script_statements(&text, &path)
         }
         (Some(_), Some(_)) => Err("--sql and --sql-file are mutually exclusive".to_string()),
-        (None, None) => Err("one of --sql or --sql-file is required".to_string()),
+        (None, None) => script_statements(&stdin()?, "stdin"),
+    }
+}
+
+/// A multi-statement script runs on one connection, so `SET` and temp tables
+/// carry from one statement to the next as in `psql`.
+fn pin_script_session(session: &mut SessionConfig, statements: &[String]) {
+    if statements.len() > 1 {
+        session.pinned = Some(true);
     }
 }
 
+fn script_statements(script: &str, source: &str) -> Result<Vec<String>, String> {
+    let statements = split_statements(script);
+    if statements.is_empty() {
+        return Err(format!("no SQL statements in {source}"));
+    }
+    Ok(statements)
+}
+
+fn read_stdin() -> Result<String, String> {
+    use std::io::{IsTerminal, Read};
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err("stdin is a terminal; pipe SQL in or pass it with --sql/-c".to_string());
+    }
+    let mut text = String::new();
+    stdin
+        .read_to_string(&mut text)
+        .map_err(|e| format!("read stdin failed: {e}"))?;
+    Ok(text)
+}
+
 fn parse_output(v: &str) -> Result<OutputFormat, String> {
     cli_parse_output(v)
 }
 
+/// `--redact PATTERN[=mask|hash|null|pseudonymize]`; the action defaults to `mask`.
+fn parse_redact_rules(entries: &[String]) -> Result<Vec<RedactionRule>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (column, action) = match entry.split_once('=') {
+                Some((column, "mask")) => (column, RedactAction::Mask),
+                Some((column, "hash")) => (column, RedactAction::Hash),
+                Some((column, "null")) => (column, RedactAction::Null),
+                Some((column, "pseudonymize")) => (column, RedactAction::Pseudonymize),
+                Some((_, other)) => {
+                    return Err(format!(
+                        "invalid --redact action '{other}', expected mask|hash|null|pseudonymize"
+                    ))
+                }
+                None => (entry.as_str(), RedactAction::Mask),
+            };
+            if column.is_empty() {
+                return Err("--redact requires a column pattern".to_string());
+            }
+            Ok(RedactionRule {
+                column: column.to_string(),
+                action,
+            })
+        })
+        .collect()
+}
+
+/// `--chaos JSON`, e.g. `{"seed": 7, "drop_probability": 0.1}`.
+fn parse_chaos(json: &str) -> Result<Chaos, String> {
+    let chaos: Chaos = serde_json::from_str(json).map_err(|e| format!("invalid --chaos: {e}"))?;
+    chaos.validate()?;
+    Ok(chaos)
+}
+
 fn parse_log_categories(entries: &[String]) -> Vec<String> {
     cli_parse_log_filters(entries)
 }
@@ -521,6 +981,87 @@ pub fn parse_params(entries: &[String]) -> Result<Vec<Value>, String> {
     Ok(out)
 }
 
+/// SQL variables by name, for `QueryOptions::vars`.
+type Vars = BTreeMap<String, String>;
+
+/// `--define name=value` entries; `None` when there are none.
+fn parse_defines(entries: &[String]) -> Result<Option<Vars>, String> {
+    if entries.is_empty() {
+        return Ok(None);
+    }
+    let mut vars = BTreeMap::new();
+    for entry in entries {
+        let (name, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid '{entry}', expected name=value"))?;
+        if !template::valid_name(name) {
+            return Err(format!("invalid variable name in '{entry}'"));
+        }
+        vars.insert(name.to_string(), value.to_string());
+    }
+    Ok(Some(vars))
+}
+
+/// `--ident name=value` entries, checked as identifiers; `None` when there
+/// are none.
+fn parse_idents(entries: &[String]) -> Result<Option<Vars>, String> {
+    let Some(idents) = parse_defines(entries)? else {
+        return Ok(None);
+    };
+    for (name, value) in &idents {
+        template::check_ident(value).map_err(|e| format!("--ident {name}: {e}"))?;
+    }
+    Ok(Some(idents))
+}
+
+/// Params, variables and error handling set with psql-mode `-v`.
+type PsqlVars = (Vec<Value>, Option<Vars>, Option<OnError>);
+
+/// psql-mode `-v`: numeric names bind `$N` params, `ON_ERROR_STOP` and
+/// `ON_ERROR_ROLLBACK` pick the script's error handling, and other names
+/// define variables as `psql -v name=value` does.
+fn parse_psql_vars(entries: &[String]) -> Result<PsqlVars, String> {
+    let mut on_error = None;
+    let mut rest = vec![];
+    for entry in entries {
+        let choice = match entry.split_once('=') {
+            Some(("ON_ERROR_STOP", value)) => psql_flag(value).then_some(OnError::Stop),
+            Some(("ON_ERROR_ROLLBACK", value)) => (psql_flag(value)
+                || value.eq_ignore_ascii_case("interactive"))
+            .then_some(OnError::RollbackStatement),
+            _ => {
+                rest.push(entry.clone());
+                continue;
+            }
+        };
+        match (on_error, choice) {
+            (Some(a), Some(b)) if a != b => {
+                return Err("ON_ERROR_STOP and ON_ERROR_ROLLBACK cannot both be set".to_string())
+            }
+            (_, Some(b)) => on_error = Some(b),
+            (_, None) => {}
+        }
+    }
+    let (defines, positional): (Vec<String>, Vec<String>) = rest.into_iter().partition(|entry| {
+        entry
+            .split_once('=')
+            .is_some_and(|(name, _)| name.parse::<usize>().is_err())
+    });
+    Ok((
+        parse_params(&positional)?,
+        parse_defines(&defines)?,
+        on_error,
+    ))
+}
+
+/// A psql boolean variable that is on.
+fn psql_flag(value: &str) -> bool {
+    matches!(
+        value.to_ascii_lowercase().as_str(),
+        "1" | "on" | "true" | "yes"
+    )
+}
+
 fn split_index_value(entry: &str) -> Result<(usize, &str), String> {
     let mut parts = entry.splitn(2, '=');
     let left = parts.next().unwrap_or_default();
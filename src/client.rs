@@ -0,0 +1,102 @@
+//! In-process client over [`App`], for embedding afpsql in a Rust program.
+
+use crate::db::DbExecutor;
+use crate::handler::{self, App};
+use crate::registry::ExecutorRegistry;
+use crate::types::{ConfigPatch, Output, QueryOptions, RuntimeConfig};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+const CLIENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Runs requests against an [`App`] and returns the events each produced,
+/// in order, as pipe mode would have written them.
+pub struct Client {
+    app: Arc<App>,
+    events: mpsc::Receiver<Output>,
+}
+
+impl Client {
+    /// A client whose sessions connect to PostgreSQL.
+    pub fn new(config: RuntimeConfig) -> Self {
+        let (tx, events) = mpsc::channel(CLIENT_CHANNEL_CAPACITY);
+        Self {
+            app: Arc::new(App::new(config, tx)),
+            events,
+        }
+    }
+
+    /// A client whose statements run on `executor` instead.
+    pub fn with_executor(config: RuntimeConfig, executor: Arc<dyn DbExecutor>) -> Self {
+        let (tx, events) = mpsc::channel(CLIENT_CHANNEL_CAPACITY);
+        Self {
+            app: Arc::new(App::with_executor(config, tx, executor)),
+            events,
+        }
+    }
+
+    /// A client on the backend `registry` has under `config.executor`.
+    pub fn from_registry(
+        config: RuntimeConfig,
+        registry: &ExecutorRegistry,
+    ) -> Result<Self, String> {
+        let executor = registry.build(&config)?;
+        Ok(Self::with_executor(config, executor))
+    }
+
+    /// The shared state, for the request handlers in [`handler`] that have
+    /// no method here.
+    pub fn app(&self) -> &Arc<App> {
+        &self.app
+    }
+
+    /// Apply a config patch, as a `config` input does, and return the
+    /// resulting config.
+    pub async fn configure(&self, patch: ConfigPatch) -> RuntimeConfig {
+        self.app.apply_config(patch).await
+    }
+
+    /// Run one statement; `session` defaults to the default session.
+    pub async fn query(
+        &mut self,
+        session: Option<&str>,
+        sql: &str,
+        params: Vec<Value>,
+        options: QueryOptions,
+    ) -> Vec<Output> {
+        let app = self.app.clone();
+        let request = handler::execute_query(
+            &app,
+            None,
+            session.map(str::to_string),
+            sql.to_string(),
+            params,
+            options,
+        );
+        self.collect(request).await
+    }
+
+    /// Drive `request` (any handler call on [`Client::app`]) to completion
+    /// and return the events it emitted. Events from background work, such
+    /// as expiry logs, appear in whichever call is running when they occur.
+    pub async fn collect(&mut self, request: impl Future<Output = ()>) -> Vec<Output> {
+        let mut out = vec![];
+        tokio::pin!(request);
+        loop {
+            tokio::select! {
+                () = &mut request => break,
+                Some(event) = self.events.recv() => out.push(event),
+            }
+        }
+        while let Ok(event) = self.events.try_recv() {
+            out.push(event);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_client.rs"]
+mod tests;
@@ -0,0 +1,44 @@
+//! Optional compression of streamed `result_rows` batches.
+//!
+//! A compressed batch carries the JSON array of its rows, compressed with the
+//! requested codec and base64-encoded, in place of `rows`.
+
+use crate::types::{CompressedRows, Compression};
+use serde_json::Value;
+
+/// Whether this build includes the `compression` feature.
+pub const AVAILABLE: bool = cfg!(feature = "compression");
+
+#[cfg(feature = "compression")]
+pub fn compress_rows(rows: &[Value], codec: Compression) -> Result<CompressedRows, String> {
+    use base64::Engine;
+    use std::io::Write;
+
+    let raw = serde_json::to_vec(rows).map_err(|e| format!("serialize rows failed: {e}"))?;
+    let packed = match codec {
+        Compression::Gzip => {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            enc.write_all(&raw)
+                .and_then(|_| enc.finish())
+                .map_err(|e| format!("gzip failed: {e}"))?
+        }
+        Compression::Zstd => {
+            zstd::encode_all(raw.as_slice(), 0).map_err(|e| format!("zstd failed: {e}"))?
+        }
+    };
+    Ok(CompressedRows {
+        codec,
+        encoding: "base64".to_string(),
+        raw_bytes: raw.len(),
+        data: base64::engine::general_purpose::STANDARD.encode(packed),
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn compress_rows(_rows: &[Value], _codec: Compression) -> Result<CompressedRows, String> {
+    Err("compression is not available in this build".to_string())
+}
+
+#[cfg(all(test, feature = "compression"))]
+#[path = "../tests/support/unit_compress.rs"]
+mod tests;
@@ -1,5 +1,8 @@
+use crate::budget;
+use crate::quota;
 use crate::types::*;
 use agent_first_data::cli_parse_log_filters;
+use std::collections::BTreeMap;
 
 #[cfg(feature = "mcp")]
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -24,6 +27,153 @@ impl RuntimeConfig {
         if let Some(v) = patch.log {
             self.log = cli_parse_log_filters(&v);
         }
+        if let Some(v) = patch.log_file {
+            self.log_file = (!v.is_empty()).then_some(v);
+        }
+        if let Some(v) = patch.log_rotate_bytes {
+            self.log_rotate_bytes = v;
+        }
+        if let Some(v) = patch.log_rotate_ms {
+            self.log_rotate_ms = v;
+        }
+        if let Some(v) = patch.log_keep {
+            self.log_keep = v;
+        }
+        if let Some(v) = patch.log_system {
+            self.log_system = v;
+        }
+        for rule in patch.redact.unwrap_or_default() {
+            if !self.redact.contains(&rule) {
+                self.redact.push(rule);
+            }
+        }
+        if let Some(v) = patch.redact_salt_secret {
+            self.redact_salt_secret = Some(v);
+        }
+        if let Some(v) = patch.default_limit {
+            self.default_limit = (v > 0).then_some(v);
+        }
+        if let Some(v) = patch.default_limit_action {
+            self.default_limit_action = v;
+        }
+        if let Some(v) = patch.memory_budget_bytes {
+            self.memory_budget_bytes = v;
+        }
+        if let Some(v) = patch.results_dir {
+            self.results_dir = (!v.is_empty()).then_some(v);
+        }
+        if let Some(v) = patch.state_dir {
+            self.state_dir = (!v.is_empty()).then_some(v);
+        }
+        if let Some(v) = patch.history_size {
+            self.history_size = v;
+        }
+        if let Some(v) = patch.transcript_max_statements {
+            self.transcript_max_statements = v;
+        }
+        if let Some(v) = patch.approval_row_threshold {
+            self.approval_row_threshold = if self.require_approval {
+                v.min(self.approval_row_threshold)
+            } else {
+                v
+            };
+        }
+        if patch.require_approval == Some(true) {
+            self.require_approval = true;
+        }
+        if let Some(v) = patch.approval_ttl_ms {
+            self.approval_ttl_ms = v;
+        }
+        if let Some(v) = patch.elevation_max_ms {
+            self.elevation_max_ms = v.min(self.elevation_max_ms);
+        }
+        if let Some(v) = patch.injection_warnings {
+            self.injection_warnings = v;
+        }
+        if let Some(v) = patch.slow_query_ms {
+            self.slow_query_ms = v;
+        }
+        if let Some(v) = patch.slow_query_params {
+            self.slow_query_params = v;
+        }
+        if let Some(v) = patch.lint {
+            self.lint = v;
+        }
+        if let Some(v) = patch.lint_block {
+            self.lint_block = v;
+        }
+        if let Some(v) = patch.annotate_queries {
+            self.annotate_queries = v;
+        }
+        if let Some(v) = patch.agent_name {
+            self.agent_name = Some(v).filter(|name| !name.is_empty());
+        }
+        quota::tighten_limits(
+            &mut self.agent_quotas,
+            patch.agent_quotas.unwrap_or_default(),
+            quota::tighten_quota,
+        );
+        quota::tighten_limits(
+            &mut self.agent_budgets,
+            patch.agent_budgets.unwrap_or_default(),
+            budget::tighten_budget,
+        );
+        quota::tighten_limits(
+            &mut self.session_budgets,
+            patch.session_budgets.unwrap_or_default(),
+            budget::tighten_budget,
+        );
+        self.timeout_profiles
+            .extend(patch.timeout_profiles.unwrap_or_default());
+        self.checks.extend(patch.checks.unwrap_or_default());
+        for (name, schedule) in patch.schedules.unwrap_or_default() {
+            match schedule {
+                Some(schedule) => self.schedules.insert(name, schedule),
+                None => self.schedules.remove(&name),
+            };
+        }
+        if let Some(v) = patch.subject_columns {
+            self.subject_columns = v;
+        }
+        if let Some(v) = patch.snapshot_ttl_ms {
+            self.snapshot_ttl_ms = v;
+        }
+        if let Some(v) = patch.workspace_idle_ms {
+            self.workspace_idle_ms = v;
+        }
+        if let Some(v) = patch.max_workspaces {
+            self.max_workspaces = v;
+        }
+        if let Some(v) = patch.cache_max_entries {
+            self.cache_max_entries = v;
+        }
+        if let Some(v) = patch.schema_cache_ttl_ms {
+            self.schema_cache_ttl_ms = v;
+        }
+        if let Some(v) = patch.diff_max_baselines {
+            self.diff_max_baselines = v;
+        }
+        if let Some(v) = patch.resume_buffer_bytes {
+            self.resume_buffer_bytes = v;
+        }
+        if let Some(v) = patch.writer_full_policy {
+            self.writer_full_policy = v;
+        }
+        if let Some(v) = patch.duplicate_id_policy {
+            self.duplicate_id_policy = v;
+        }
+        if let Some(v) = patch.max_message_bytes {
+            self.max_message_bytes = v;
+        }
+        if let Some(v) = patch.pool_check_interval_ms {
+            self.pool_check_interval_ms = v;
+        }
+        if let Some(v) = patch.pool_idle_timeout_ms {
+            self.pool_idle_timeout_ms = v;
+        }
+        if self.audit_log.is_none() {
+            self.audit_log = patch.audit_log.filter(|p| !p.is_empty());
+        }
         if let Some(sessions) = patch.sessions {
             for (name, s) in sessions {
                 let entry = self.sessions.entry(name).or_default();
@@ -48,6 +198,24 @@ impl RuntimeConfig {
                 if let Some(v) = s.password_secret {
                     entry.password_secret = Some(v);
                 }
+                if let Some(v) = s.pinned {
+                    entry.pinned = Some(v);
+                }
+                if let Some(v) = s.sqlite_path {
+                    entry.sqlite_path = Some(v);
+                }
+                if let Some(v) = s.tcp_keepalive_idle_ms {
+                    entry.tcp_keepalive_idle_ms = Some(v);
+                }
+                if let Some(v) = s.eager {
+                    entry.eager = Some(v);
+                }
+                if let Some(v) = s.settings {
+                    entry.settings.get_or_insert_with(BTreeMap::new).extend(v);
+                }
+                if let Some(v) = s.context {
+                    entry.context.get_or_insert_with(BTreeMap::new).extend(v);
+                }
             }
         }
         if !self.sessions.contains_key(&self.default_session) {
@@ -56,16 +224,74 @@ impl RuntimeConfig {
         }
     }
 
+    /// Unknown `timeout_profile` names fall back to the global timeouts;
+    /// request handlers reject them before resolving.
     pub fn resolve_options(&self, q: &QueryOptions) -> ResolvedOptions {
+        let profile = q
+            .timeout_profile
+            .as_ref()
+            .and_then(|name| self.timeout_profiles.get(name));
+        let statement_timeout_ms = match profile {
+            Some(p) => q
+                .statement_timeout_ms
+                .unwrap_or(p.statement_timeout_ms)
+                .min(p.max_statement_timeout_ms.unwrap_or(p.statement_timeout_ms)),
+            None => q.statement_timeout_ms.unwrap_or(self.statement_timeout_ms),
+        };
         ResolvedOptions {
             stream_rows: q.stream_rows,
             batch_rows: q.batch_rows.unwrap_or(1000).max(1),
             batch_bytes: q.batch_bytes.unwrap_or(262_144).max(1024),
-            statement_timeout_ms: q.statement_timeout_ms.unwrap_or(self.statement_timeout_ms),
-            lock_timeout_ms: q.lock_timeout_ms.unwrap_or(self.lock_timeout_ms),
+            statement_timeout_ms,
+            lock_timeout_ms: q
+                .lock_timeout_ms
+                .or(profile.and_then(|p| p.lock_timeout_ms))
+                .unwrap_or(self.lock_timeout_ms),
             read_only: q.read_only.unwrap_or(false),
+            defer_constraints: q.defer_constraints,
+            replication_role: q.replication_role,
             inline_max_rows: q.inline_max_rows.unwrap_or(self.inline_max_rows),
             inline_max_bytes: q.inline_max_bytes.unwrap_or(self.inline_max_bytes),
+            redact: self.redact.clone(),
+            redact_salt_secret: self.redact_salt_secret.clone(),
+            default_limit: q.default_limit.or(self.default_limit).filter(|n| *n > 0),
+            default_limit_action: self.default_limit_action,
+            compress: q.compress,
+            store_result: q.store_result,
+            export_uri: q.export_uri.clone(),
+            verify: q.verify,
+            publish_uri: q.publish_uri.clone(),
+            publish_key: q.publish_key.clone().unwrap_or_default(),
+            publish_format: q.publish_format.unwrap_or_default(),
+            server_timing: q.server_timing,
+            results_dir: self.results_dir.clone(),
+            approval_row_threshold: (self.require_approval && !q.approved)
+                .then_some(self.approval_row_threshold),
+            role: None,
+            snapshot: q.snapshot.clone(),
+            autocommit: q.autocommit,
+            nested_tables: q.nested_tables.clone(),
+            workspace: q.workspace.clone(),
+            cache_ttl_ms: q.cache_ttl_ms.filter(|ms| *ms > 0),
+            key_columns: q.key_columns.clone().unwrap_or_default(),
+            dedup_baseline: None,
+            ack_window: q.ack_window.filter(|n| *n > 0),
+            max_message_bytes: Some(self.max_message_bytes).filter(|n| *n > 0),
+            annotation: None,
+            summarize: q.summarize,
+            context: BTreeMap::new(),
+            prepare_transaction: None,
+        }
+    }
+}
+
+impl ResolvedOptions {
+    /// Rows the executor should fetch at most: one past `default_limit` so
+    /// truncation is detectable, or unbounded when not limiting.
+    pub fn fetch_cap(&self) -> Option<usize> {
+        match (self.default_limit, self.default_limit_action) {
+            (Some(n), DefaultLimitAction::Limit) => Some(n.saturating_add(1)),
+            _ => None,
         }
     }
 }
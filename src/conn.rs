@@ -7,6 +7,10 @@ pub fn resolve_session_name(cfg: &RuntimeConfig, requested: Option<&str>) -> Str
 }
 
 pub fn resolve_conn_string(cfg: &SessionConfig) -> Result<String, String> {
+    if cfg.sqlite_path.is_some() {
+        return Err("sqlite_path needs afpsql built with the `sqlite` feature".to_string());
+    }
+
     if let Some(dsn) = cfg
         .dsn_secret
         .clone()
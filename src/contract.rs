@@ -0,0 +1,81 @@
+//! Result contracts for `expect`.
+//!
+//! A contract names columns a query's result must have, optionally with
+//! their types, and bounds on its row count. Columns are checked against
+//! the prepared statement before it runs, so schema drift fails the query
+//! without side effects; row bounds can only be checked on the result.
+
+use crate::types::{ColumnInfo, ResultContract};
+
+/// PostgreSQL's own name (`pg_type.typname`) for a type written in SQL, so
+/// `integer`, `int` and `int4` all match. Arrays are `_` plus the element.
+pub fn type_name(written: &str) -> String {
+    let lower = written.trim().to_lowercase();
+    if let Some(element) = lower.strip_suffix("[]") {
+        return format!("_{}", type_name(element));
+    }
+    let canonical = match lower.as_str() {
+        "smallint" => "int2",
+        "integer" | "int" => "int4",
+        "bigint" => "int8",
+        "real" => "float4",
+        "double precision" => "float8",
+        "decimal" => "numeric",
+        "boolean" => "bool",
+        "character varying" => "varchar",
+        "character" | "char" => "bpchar",
+        "timestamp" | "timestamp without time zone" => "timestamp",
+        "timestamp with time zone" => "timestamptz",
+        "time" | "time without time zone" => "time",
+        "time with time zone" => "timetz",
+        other => other,
+    };
+    canonical.to_string()
+}
+
+/// Every way `actual` breaks the contract's columns.
+pub fn column_violations(contract: &ResultContract, actual: &[ColumnInfo]) -> Vec<String> {
+    let Some(expected) = &contract.columns else {
+        return vec![];
+    };
+    let mut violations = vec![];
+    for column in expected {
+        let Some(found) = actual.iter().find(|c| c.name == column.name) else {
+            violations.push(format!("missing column {}", column.name));
+            continue;
+        };
+        if let Some(want) = &column.type_name {
+            if type_name(want) != found.type_name {
+                violations.push(format!(
+                    "column {} is {}, expected {want}",
+                    column.name, found.type_name
+                ));
+            }
+        }
+    }
+    if contract.exact_columns {
+        let names: Vec<&str> = actual.iter().map(|c| c.name.as_str()).collect();
+        let wanted: Vec<&str> = expected.iter().map(|c| c.name.as_str()).collect();
+        if names != wanted {
+            violations.push(format!(
+                "columns are [{}], expected exactly [{}]",
+                names.join(", "),
+                wanted.join(", ")
+            ));
+        }
+    }
+    violations
+}
+
+/// How a result of `rows` rows breaks the contract's bounds, if it does.
+pub fn row_violation(contract: &ResultContract, rows: usize) -> Option<String> {
+    match (contract.min_rows, contract.max_rows) {
+        (Some(min), _) if rows < min => Some(format!("{rows} rows, expected at least {min}")),
+        (_, Some(max)) if rows > max => Some(format!("{rows} rows, expected at most {max}")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_contract.rs"]
+mod tests;
@@ -1,11 +1,24 @@
 use crate::conn::resolve_conn_string;
-use crate::types::{ResolvedOptions, SessionConfig};
+use crate::ext_types::{self, ExtParam, ExtTypeMap};
+use crate::profile;
+use crate::redact::{self, ColumnOrigins};
+use crate::types::{ColumnInfo, ResolvedOptions, SessionConfig, Timing};
 use async_trait::async_trait;
-use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use deadpool_postgres::{ClientWrapper, Manager, ManagerConfig, Object, Pool, RecyclingMethod};
+use futures_util::SinkExt;
+use serde::Serialize;
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use tokio::sync::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
 use tokio_postgres::types::{Json, ToSql, Type};
+use tokio_postgres::IsolationLevel;
+
+/// Pooled connections a statement is tried on before a closed one is
+/// reported as `connect_failed`.
+const CONNECTION_ATTEMPTS: u32 = 3;
 
 #[derive(Debug)]
 pub enum ExecOutcome {
@@ -13,6 +26,47 @@ pub enum ExecOutcome {
     Command { affected: usize },
 }
 
+/// What `execute_script` did: the outcome of each statement it ran, in
+/// order, and whether the script's transaction then committed. Statements
+/// after `outcomes` never ran.
+#[derive(Debug)]
+pub struct ScriptRun {
+    pub outcomes: Vec<Result<ExecOutcome, ExecError>>,
+    pub committed: Result<(), ExecError>,
+    /// The last statement run committed or rolled back the transaction
+    /// itself, so what ran before it took effect or not as it said.
+    pub ended_early: bool,
+}
+
+impl ScriptRun {
+    /// A script that could not start.
+    pub fn failed(err: ExecError) -> Self {
+        Self {
+            outcomes: vec![],
+            committed: Err(err),
+            ended_early: false,
+        }
+    }
+}
+
+/// What `execute_block` did: the notices the body raised, kept when it
+/// then failed, and whether it committed.
+#[derive(Debug)]
+pub struct BlockRun {
+    pub notices: Vec<Notice>,
+    pub result: Result<(), ExecError>,
+}
+
+impl BlockRun {
+    /// A block that could not start.
+    pub fn failed(err: ExecError) -> Self {
+        Self {
+            notices: vec![],
+            result: Err(err),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ExecError {
     Connect(String),
@@ -25,6 +79,81 @@ pub enum ExecError {
         position: Option<String>,
     },
     Internal(String),
+    /// The statement ran but was rolled back for needing approval; carries
+    /// the reason.
+    ApprovalRequired(String),
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecError::Connect(m) => write!(f, "connect_failed: {m}"),
+            ExecError::InvalidParams(m) => write!(f, "invalid_params: {m}"),
+            ExecError::Sql {
+                sqlstate, message, ..
+            } => write!(f, "sql_error {sqlstate}: {message}"),
+            ExecError::Internal(m) => write!(f, "invalid_request: {m}"),
+            ExecError::ApprovalRequired(m) => write!(f, "approval_required: {m}"),
+        }
+    }
+}
+
+/// Connections one pool maintenance pass closed in a session's pool.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolReport {
+    pub session: String,
+    /// Unused for longer than the idle timeout.
+    pub idle: usize,
+    /// Closed by the server or failing a probe.
+    pub broken: usize,
+}
+
+/// A session's connection pool, for `debug_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PoolState {
+    pub session: String,
+    pub max_size: usize,
+    /// Connections open, in use or idle.
+    pub size: usize,
+    pub idle: usize,
+    /// Requests waiting for a connection.
+    pub waiting: usize,
+}
+
+/// Server NOTICE/WARNING message captured while running a statement.
+#[derive(Debug, Clone)]
+pub struct Notice {
+    pub severity: String,
+    pub sqlstate: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+/// Send the rows of `outcome` to `pages`, `page_rows` at a time, until the
+/// receiver goes away; a statement that returns no rows is an error.
+pub async fn send_pages(
+    outcome: ExecOutcome,
+    page_rows: usize,
+    pages: &mpsc::Sender<Vec<Value>>,
+) -> Result<(), ExecError> {
+    let ExecOutcome::Rows(rows) = outcome else {
+        return Err(ExecError::Internal("sql must return rows".to_string()));
+    };
+    for page in rows.chunks(page_rows.max(1)) {
+        if pages.send(page.to_vec()).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Rows to read back after a `COPY`, before it commits.
+pub struct CopyReadback<'a> {
+    /// One `jsonb` column per row.
+    pub sql: &'a str,
+    /// Whether to commit, given the rows read back.
+    pub keep: &'a (dyn Fn(&[Value]) -> bool + Send + Sync),
 }
 
 #[async_trait]
@@ -37,28 +166,273 @@ pub trait DbExecutor: Send + Sync {
         params: &[Value],
         opts: &ResolvedOptions,
     ) -> Result<ExecOutcome, ExecError>;
+
+    /// As `execute`, also reporting where the time went; executors that do
+    /// not measure it report nothing.
+    async fn execute_timed(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> (Result<ExecOutcome, ExecError>, Timing) {
+        let result = self
+            .execute(session_name, session_cfg, sql, params, opts)
+            .await;
+        (result, Timing::default())
+    }
+
+    /// Run a row-returning `sql` and send its rows to `pages`, at most
+    /// `page_rows` at a time, as they are read: a full channel holds the
+    /// next read back and a closed one ends it. Executors that cannot read
+    /// in pages run `execute` and split its rows.
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_pages(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+        page_rows: usize,
+        pages: mpsc::Sender<Vec<Value>>,
+    ) -> Result<(), ExecError> {
+        let outcome = self
+            .execute(session_name, session_cfg, sql, params, opts)
+            .await?;
+        send_pages(outcome, page_rows, &pages).await
+    }
+
+    /// Run a plpgsql `DO` body and return the notices it raised, also when
+    /// it failed.
+    async fn execute_block(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _body: &str,
+        _vars: &[(String, String)],
+        _opts: &ResolvedOptions,
+    ) -> BlockRun {
+        BlockRun::failed(ExecError::Internal(
+            "execute_block is not supported by this executor".to_string(),
+        ))
+    }
+
+    /// Open a `REPEATABLE READ` transaction on a dedicated connection and
+    /// export its snapshot, which stays importable until `release_snapshot`.
+    async fn export_snapshot(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+    ) -> Result<String, ExecError> {
+        Err(ExecError::Internal(
+            "snapshots are not supported by this executor".to_string(),
+        ))
+    }
+
+    /// End the transaction holding `snapshot`; `false` when none was held.
+    async fn release_snapshot(&self, _session_name: &str, _snapshot: &str) -> bool {
+        false
+    }
+
+    /// Take a connection of `session_name` out of its pool and keep it for
+    /// requests whose options name `workspace`.
+    async fn open_workspace(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _workspace: &str,
+    ) -> Result<(), ExecError> {
+        Err(ExecError::Internal(
+            "workspaces are not supported by this executor".to_string(),
+        ))
+    }
+
+    /// Close the workspace's connection, dropping its temp tables; `false`
+    /// when it was not open.
+    async fn close_workspace(&self, _workspace: &str) -> bool {
+        false
+    }
+
+    /// Replace the workspace's connection with a fresh one if it was
+    /// closed; `true` when it was.
+    async fn reconnect_workspace(&self, _workspace: &str) -> Result<bool, ExecError> {
+        Ok(false)
+    }
+
+    /// Counter bumped whenever a statement or DO block may have changed the
+    /// schema; cached catalog lookups older than the current value are stale.
+    fn schema_generation(&self) -> u64 {
+        0
+    }
+
+    /// Run `statements` in one transaction, each under a savepoint so that a
+    /// failed one is rolled back alone, and commit the rest at the end.
+    async fn execute_script(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _statements: &[String],
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> ScriptRun {
+        ScriptRun::failed(ExecError::Internal(
+            "scripts in one transaction are not supported by this executor".to_string(),
+        ))
+    }
+
+    /// Run a `COPY ... FROM STDIN` statement fed with `data`; returns rows copied.
+    async fn copy_in(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _data: Vec<u8>,
+        _opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        Err(ExecError::Internal(
+            "copy_in is not supported by this executor".to_string(),
+        ))
+    }
+
+    /// `copy_in`, then run `readback.sql` in the same transaction and commit
+    /// only if `readback.keep` accepts its rows. Returns rows copied, the
+    /// rows read back and whether they were kept.
+    async fn copy_in_verified(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _data: Vec<u8>,
+        _readback: &CopyReadback<'_>,
+        _opts: &ResolvedOptions,
+    ) -> Result<(u64, Vec<Value>, bool), ExecError> {
+        Err(ExecError::Internal(
+            "verified copies are not supported by this executor".to_string(),
+        ))
+    }
+
+    /// Prepare `sql` without running it: the type of each parameter and the
+    /// columns it would return.
+    async fn describe(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _opts: &ResolvedOptions,
+    ) -> Result<(Vec<String>, Vec<ColumnInfo>), ExecError> {
+        Err(ExecError::Internal(
+            "describe is not supported by this executor".to_string(),
+        ))
+    }
+
+    /// Connect `session_name` now rather than on its first query, so a bad
+    /// config or unreachable server is reported up front.
+    async fn connect(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+    ) -> Result<(), ExecError> {
+        Ok(())
+    }
+
+    /// Close pooled connections idle for `idle_timeout` or longer, probe the
+    /// rest and close those that fail; one report per pool that lost any.
+    async fn maintain_pools(&self, _idle_timeout: Option<Duration>) -> Vec<PoolReport> {
+        vec![]
+    }
+
+    /// The state of each session's pool, by session name.
+    async fn pool_states(&self) -> Vec<PoolState> {
+        vec![]
+    }
 }
 
 pub struct PostgresExecutor {
-    pools: RwLock<HashMap<String, Pool>>,
+    pools: RwLock<HashMap<String, SessionPool>>,
+    /// Open exporting transactions by `(session, snapshot)`; dropping the
+    /// client closes its connection and releases the snapshot.
+    snapshots: tokio::sync::Mutex<HashMap<(String, String), tokio_postgres::Client>>,
+    /// Connections detached from their pool, by workspace id, with the pool
+    /// a replacement is taken from.
+    workspaces: tokio::sync::Mutex<HashMap<String, (Pool, PinnedClient)>>,
+    schema_generation: AtomicU64,
+}
+
+type PinnedClient = Arc<tokio::sync::Mutex<ClientWrapper>>;
+
+#[derive(Clone)]
+struct SessionPool {
+    pool: Pool,
+    ext_types: Arc<ExtTypeMap>,
+}
+
+impl Default for PostgresExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PostgresExecutor {
     pub fn new() -> Self {
         Self {
             pools: RwLock::new(HashMap::new()),
+            snapshots: tokio::sync::Mutex::new(HashMap::new()),
+            workspaces: tokio::sync::Mutex::new(HashMap::new()),
+            schema_generation: AtomicU64::new(0),
+        }
+    }
+
+    /// The pinned connection `opts` asks for, if any.
+    async fn pinned_client(
+        &self,
+        opts: &ResolvedOptions,
+    ) -> Result<Option<PinnedClient>, ExecError> {
+        let Some(workspace) = &opts.workspace else {
+            return Ok(None);
+        };
+        match self.workspaces.lock().await.get(workspace) {
+            Some((_, client)) => Ok(Some(client.clone())),
+            None => Err(ExecError::Internal(format!(
+                "workspace {workspace} is not open"
+            ))),
         }
     }
 
-    async fn get_pool(&self, session_name: &str, cfg: &SessionConfig) -> Result<Pool, ExecError> {
+    /// Run a `COPY ... FROM STDIN` on the pinned or a pooled connection.
+    async fn copy_in_inner(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        readback: Option<&CopyReadback<'_>>,
+        opts: &ResolvedOptions,
+    ) -> Result<(u64, Vec<Value>, bool), ExecError> {
+        if let Some(pinned) = self.pinned_client(opts).await? {
+            let mut client = pinned.lock().await;
+            return run_copy_in(&mut client, sql, data, readback, opts).await;
+        }
+        let pool = self.get_pool(session_name, session_cfg).await?;
+        let mut client = pool
+            .pool
+            .get()
+            .await
+            .map_err(|e| ExecError::Connect(format!("get connection failed: {e}")))?;
+        run_copy_in(&mut client, sql, data, readback, opts).await
+    }
+
+    async fn get_pool(
+        &self,
+        session_name: &str,
+        cfg: &SessionConfig,
+    ) -> Result<SessionPool, ExecError> {
         if let Some(pool) = self.pools.read().await.get(session_name) {
             return Ok(pool.clone());
         }
 
-        let conn_str = resolve_conn_string(cfg).map_err(ExecError::Connect)?;
-        let pg_cfg: tokio_postgres::Config = conn_str
-            .parse()
-            .map_err(|e| ExecError::Connect(format!("invalid postgres conn string: {e}")))?;
+        let pg_cfg = pg_config(cfg)?;
         let mgr = Manager::from_config(
             pg_cfg,
             tokio_postgres::NoTls,
@@ -70,6 +444,8 @@ impl PostgresExecutor {
             .max_size(5)
             .build()
             .map_err(|e| ExecError::Connect(format!("create pool failed: {e}")))?;
+        let ext_types = Arc::new(learn_ext_types(&pool).await?);
+        let pool = SessionPool { pool, ext_types };
 
         self.pools
             .write()
@@ -80,6 +456,135 @@ impl PostgresExecutor {
     }
 }
 
+/// Add how long `client` took to arrive since `waited` to `timing`; the
+/// part spent opening it, when it is new, counts as `connect_ms`.
+fn note_wait(timing: &mut Timing, waited: Instant, client: &Object) {
+    let total = waited.elapsed();
+    let connect = Object::metrics(client)
+        .created
+        .checked_duration_since(waited)
+        .unwrap_or_default();
+    let add = |ms: Option<u64>, d: Duration| Some(ms.unwrap_or(0) + d.as_millis() as u64);
+    timing.pool_wait_ms = add(timing.pool_wait_ms, total.saturating_sub(connect));
+    if !connect.is_zero() {
+        timing.connect_ms = add(timing.connect_ms, connect);
+    }
+}
+
+fn pg_config(cfg: &SessionConfig) -> Result<tokio_postgres::Config, ExecError> {
+    let conn_str = resolve_conn_string(cfg).map_err(ExecError::Connect)?;
+    let mut pg_cfg: tokio_postgres::Config = conn_str
+        .parse()
+        .map_err(|e| ExecError::Connect(format!("invalid postgres conn string: {e}")))?;
+    if let Some(settings) = cfg.settings.as_ref().filter(|s| !s.is_empty()) {
+        let mut options = pg_cfg.get_options().unwrap_or_default().to_string();
+        for (name, value) in settings {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            {
+                return Err(ExecError::Connect(format!(
+                    "invalid setting name: {name:?}"
+                )));
+            }
+            if !options.is_empty() {
+                options.push(' ');
+            }
+            options.push_str(&format!("-c {name}={}", escape_option(value)));
+        }
+        pg_cfg.options(&options);
+    }
+    if let Some(ms) = cfg.tcp_keepalive_idle_ms {
+        pg_cfg
+            .keepalives(true)
+            .keepalives_idle(Duration::from_millis(ms));
+    }
+    Ok(pg_cfg)
+}
+
+/// Escape a startup option value: the server splits `options` on spaces and
+/// unescapes backslashes.
+fn escape_option(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || c.is_whitespace() {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A new connection of `pool` that never returns to it, so temp tables and
+/// `SET` state do not leak into other queries.
+async fn detached_client(pool: &Pool) -> Result<ClientWrapper, ExecError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| ExecError::Connect(format!("get connection failed: {e}")))?;
+    Ok(Object::take(client))
+}
+
+/// Close idle and dead connections of `pool`, then probe the ones left idle.
+async fn maintain_pool(session: &str, pool: &Pool, idle_timeout: Option<Duration>) -> PoolReport {
+    let mut report = PoolReport {
+        session: session.to_string(),
+        ..PoolReport::default()
+    };
+    pool.retain(|client, metrics| {
+        if client.is_closed() {
+            report.broken += 1;
+            false
+        } else if idle_timeout.is_some_and(|idle| metrics.last_used() >= idle) {
+            report.idle += 1;
+            false
+        } else {
+            true
+        }
+    });
+    // Hold every idle connection at once so each is probed exactly once.
+    let mut probed = vec![];
+    for _ in 0..pool.status().available {
+        match pool.get().await {
+            Ok(client) => probed.push(client),
+            Err(_) => break,
+        }
+    }
+    for client in probed {
+        if client.simple_query("").await.is_err() {
+            report.broken += 1;
+            drop(Object::take(client));
+        }
+    }
+    report
+}
+
+/// Resolve OIDs of the known extension types installed in this database.
+/// A failed lookup (e.g. restricted catalog access) leaves the map empty.
+async fn learn_ext_types(pool: &Pool) -> Result<ExtTypeMap, ExecError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| ExecError::Connect(format!("get connection failed: {e}")))?;
+    let names: Vec<&str> = ext_types::EXTENSION_TYPES.iter().map(|(n, _)| *n).collect();
+    let rows = match client
+        .query(ext_types::EXTENSION_TYPES_SQL, &[&names])
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return Ok(ExtTypeMap::new()),
+    };
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let oid: u32 = row.try_get(0).ok()?;
+            let name: String = row.try_get(1).ok()?;
+            ext_types::ext_type_by_name(&name).map(|t| (oid, t))
+        })
+        .collect())
+}
+
 #[async_trait]
 impl DbExecutor for PostgresExecutor {
     async fn execute(
@@ -90,88 +595,951 @@ impl DbExecutor for PostgresExecutor {
         params: &[Value],
         opts: &ResolvedOptions,
     ) -> Result<ExecOutcome, ExecError> {
+        self.execute_timed(session_name, session_cfg, sql, params, opts)
+            .await
+            .0
+    }
+
+    async fn execute_timed(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> (Result<ExecOutcome, ExecError>, Timing) {
+        let _timer = profile::timer("db.execute");
+        let mut timing = Timing::default();
+        let waited = Instant::now();
+        let result = async {
+            let pool = self.get_pool(session_name, session_cfg).await?;
+            let schema = &self.schema_generation;
+            if opts.autocommit {
+                return run_autocommit(&pool.pool, sql, params, opts).await;
+            }
+            if let Some(pinned) = self.pinned_client(opts).await? {
+                let mut client = pinned.lock().await;
+                timing.pool_wait_ms = Some(waited.elapsed().as_millis() as u64);
+                timing.attempts = Some(1);
+                let ext_types = &pool.ext_types;
+                return run_statement(
+                    &mut client,
+                    ext_types,
+                    sql,
+                    params,
+                    opts,
+                    schema,
+                    &mut timing,
+                )
+                .await;
+            }
+            let mut waited = waited;
+            let mut attempt = 1;
+            loop {
+                let mut client = pool
+                    .pool
+                    .get()
+                    .await
+                    .map_err(|e| ExecError::Connect(format!("get connection failed: {e}")))?;
+                note_wait(&mut timing, waited, &client);
+                timing.attempts = Some(attempt);
+                let outcome = run_statement(
+                    &mut client,
+                    &pool.ext_types,
+                    sql,
+                    params,
+                    opts,
+                    schema,
+                    &mut timing,
+                )
+                .await;
+                match outcome {
+                    // Dropping the closed connection takes it out of the pool.
+                    Err(ExecError::Connect(_)) if attempt < CONNECTION_ATTEMPTS => {
+                        attempt += 1;
+                        waited = Instant::now();
+                    }
+                    outcome => return outcome,
+                }
+            }
+        }
+        .await;
+        (result, timing)
+    }
+
+    async fn execute_pages(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+        page_rows: usize,
+        pages: mpsc::Sender<Vec<Value>>,
+    ) -> Result<(), ExecError> {
+        let _timer = profile::timer("db.execute");
         let pool = self.get_pool(session_name, session_cfg).await?;
+        let ext_types = &pool.ext_types;
+        if let Some(pinned) = self.pinned_client(opts).await? {
+            let mut client = pinned.lock().await;
+            return run_pages(&mut client, ext_types, sql, params, opts, page_rows, &pages).await;
+        }
         let mut client = pool
+            .pool
             .get()
             .await
             .map_err(|e| ExecError::Connect(format!("get connection failed: {e}")))?;
+        run_pages(&mut client, ext_types, sql, params, opts, page_rows, &pages).await
+    }
+
+    async fn describe(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        opts: &ResolvedOptions,
+    ) -> Result<(Vec<String>, Vec<ColumnInfo>), ExecError> {
+        let pool = self.get_pool(session_name, session_cfg).await?;
+        let stmt = match self.pinned_client(opts).await? {
+            Some(pinned) => pinned.lock().await.prepare(sql).await,
+            None => {
+                let client = pool
+                    .pool
+                    .get()
+                    .await
+                    .map_err(|e| ExecError::Connect(format!("get connection failed: {e}")))?;
+                client.prepare(sql).await
+            }
+        }
+        .map_err(map_pg_error)?;
+        let params = stmt.params().iter().map(|t| t.name().to_string()).collect();
+        let columns = stmt
+            .columns()
+            .iter()
+            .map(|c| ColumnInfo {
+                name: c.name().to_string(),
+                type_name: c.type_().name().to_string(),
+            })
+            .collect();
+        Ok((params, columns))
+    }
+
+    async fn execute_script(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        statements: &[String],
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> ScriptRun {
+        let pool = match self.get_pool(session_name, session_cfg).await {
+            Ok(pool) => pool,
+            Err(e) => return ScriptRun::failed(e),
+        };
+        let schema = &self.schema_generation;
+        match self.pinned_client(opts).await {
+            Ok(Some(pinned)) => {
+                let mut client = pinned.lock().await;
+                run_script(
+                    &mut client,
+                    &pool.ext_types,
+                    statements,
+                    params,
+                    opts,
+                    schema,
+                )
+                .await
+            }
+            Ok(None) => match pool.pool.get().await {
+                Ok(mut client) => {
+                    let ext_types = &pool.ext_types;
+                    run_script(&mut client, ext_types, statements, params, opts, schema).await
+                }
+                Err(e) => {
+                    ScriptRun::failed(ExecError::Connect(format!("get connection failed: {e}")))
+                }
+            },
+            Err(e) => ScriptRun::failed(e),
+        }
+    }
+
+    async fn execute_block(
+        &self,
+        _session_name: &str,
+        session_cfg: &SessionConfig,
+        body: &str,
+        vars: &[(String, String)],
+        opts: &ResolvedOptions,
+    ) -> BlockRun {
+        let connected: Result<_, ExecError> = async {
+            if opts.workspace.is_some() {
+                return Err(ExecError::InvalidParams(
+                    "DO blocks run on their own connection and cannot use a workspace or pinned session"
+                        .to_string(),
+                ));
+            }
+            let sql = build_do_block(body)?;
+            for (name, _) in vars {
+                validate_block_var_name(name)?;
+            }
+            // Pooled connections discard async messages, so notices are only
+            // observable on a dedicated connection whose message loop we drive.
+            let (client, connection) = pg_config(session_cfg)?
+                .connect(tokio_postgres::NoTls)
+                .await
+                .map_err(|e| ExecError::Connect(format!("connect failed: {e}")))?;
+            Ok((sql, client, connection))
+        }
+        .await;
+        let (sql, mut client, mut connection) = match connected {
+            Ok(connected) => connected,
+            Err(e) => return BlockRun::failed(e),
+        };
+
+        let (notice_tx, mut notice_rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_task = tokio::spawn(async move {
+            while let Some(Ok(msg)) = std::future::poll_fn(|cx| connection.poll_message(cx)).await {
+                if let tokio_postgres::AsyncMessage::Notice(n) = msg {
+                    let _ = notice_tx.send(Notice {
+                        severity: n.severity().to_string(),
+                        sqlstate: n.code().code().to_string(),
+                        message: n.message().to_string(),
+                        detail: n.detail().map(std::string::ToString::to_string),
+                        hint: n.hint().map(std::string::ToString::to_string),
+                    });
+                }
+            }
+        });
+
+        let result: Result<(), ExecError> = async {
+            let mut tx = client.transaction().await.map_err(map_pg_error)?;
+            apply_query_settings(&mut tx, opts).await?;
+            for (name, value) in vars {
+                let guc = format!("afpsql.{name}");
+                tx.execute("select set_config($1, $2, true)", &[&guc, value])
+                    .await
+                    .map_err(map_pg_error)?;
+            }
+            if opts.approval_row_threshold.is_some() {
+                tx.batch_execute(APPROVAL_SAVEPOINT)
+                    .await
+                    .map_err(map_pg_error)?;
+            }
+            tx.batch_execute(&sql).await.map_err(map_pg_error)?;
+            if let Some(threshold) = opts.approval_row_threshold {
+                // The body's row counts are not reported; on this fresh
+                // connection the transaction's table statistics are its own.
+                let affected: i64 = tx
+                    .query_one(TX_ROWS_WRITTEN_SQL, &[])
+                    .await
+                    .map_err(map_pg_error)?
+                    .get(0);
+                check_needs_approval(&tx, affected as usize, threshold).await?;
+            }
+            tx.commit().await.map_err(map_pg_error)
+        }
+        .await;
+
+        drop(client);
+        let _ = conn_task.await;
+        // Notices raised before a failure explain it, so they are kept
+        // either way.
+        let mut notices = vec![];
+        while let Ok(n) = notice_rx.try_recv() {
+            notices.push(n);
+        }
+        if result.is_ok() {
+            // DO bodies are opaque; assume they may have changed the schema.
+            self.schema_generation.fetch_add(1, Ordering::Relaxed);
+        }
+        BlockRun { notices, result }
+    }
+
+    async fn export_snapshot(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<String, ExecError> {
+        let (client, connection) = pg_config(session_cfg)?
+            .connect(tokio_postgres::NoTls)
+            .await
+            .map_err(|e| ExecError::Connect(format!("connect failed: {e}")))?;
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+        client
+            .batch_execute("begin isolation level repeatable read read only")
+            .await
+            .map_err(map_pg_error)?;
+        let snapshot: String = client
+            .query_one("select pg_export_snapshot()", &[])
+            .await
+            .map_err(map_pg_error)?
+            .try_get(0)
+            .map_err(map_pg_error)?;
+        self.snapshots
+            .lock()
+            .await
+            .insert((session_name.to_string(), snapshot.clone()), client);
+        Ok(snapshot)
+    }
+
+    async fn release_snapshot(&self, session_name: &str, snapshot: &str) -> bool {
+        let key = (session_name.to_string(), snapshot.to_string());
+        let Some(client) = self.snapshots.lock().await.remove(&key) else {
+            return false;
+        };
+        let _ = client.batch_execute("commit").await;
+        true
+    }
+
+    async fn open_workspace(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        workspace: &str,
+    ) -> Result<(), ExecError> {
+        let pool = self.get_pool(session_name, session_cfg).await?;
+        let client = detached_client(&pool.pool).await?;
+        self.workspaces.lock().await.insert(
+            workspace.to_string(),
+            (pool.pool, Arc::new(tokio::sync::Mutex::new(client))),
+        );
+        Ok(())
+    }
+
+    async fn close_workspace(&self, workspace: &str) -> bool {
+        self.workspaces.lock().await.remove(workspace).is_some()
+    }
+
+    async fn reconnect_workspace(&self, workspace: &str) -> Result<bool, ExecError> {
+        let Some((pool, pinned)) = self.workspaces.lock().await.get(workspace).cloned() else {
+            return Ok(false);
+        };
+        let mut client = pinned.lock().await;
+        if !client.is_closed() {
+            return Ok(false);
+        }
+        *client = detached_client(&pool).await?;
+        Ok(true)
+    }
+
+    fn schema_generation(&self) -> u64 {
+        self.schema_generation.load(Ordering::Relaxed)
+    }
+
+    async fn copy_in(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        self.copy_in_inner(session_name, session_cfg, sql, data, None, opts)
+            .await
+            .map(|(copied, _, _)| copied)
+    }
+
+    async fn copy_in_verified(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        readback: &CopyReadback<'_>,
+        opts: &ResolvedOptions,
+    ) -> Result<(u64, Vec<Value>, bool), ExecError> {
+        let readback = Some(readback);
+        self.copy_in_inner(session_name, session_cfg, sql, data, readback, opts)
+            .await
+    }
+
+    async fn connect(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<(), ExecError> {
+        // Building the pool already holds one connection to learn types.
+        self.get_pool(session_name, session_cfg).await.map(|_| ())
+    }
+
+    async fn maintain_pools(&self, idle_timeout: Option<Duration>) -> Vec<PoolReport> {
+        let pools: Vec<(String, SessionPool)> = self
+            .pools
+            .read()
+            .await
+            .iter()
+            .map(|(name, pool)| (name.clone(), pool.clone()))
+            .collect();
+        let mut reports = vec![];
+        for (session, pool) in pools {
+            let report = maintain_pool(&session, &pool.pool, idle_timeout).await;
+            if report.idle > 0 || report.broken > 0 {
+                reports.push(report);
+            }
+        }
+        reports
+    }
+
+    async fn pool_states(&self) -> Vec<PoolState> {
+        let mut states: Vec<PoolState> = self
+            .pools
+            .read()
+            .await
+            .iter()
+            .map(|(session, pool)| {
+                let status = pool.pool.status();
+                PoolState {
+                    session: session.clone(),
+                    max_size: status.max_size,
+                    size: status.size,
+                    idle: status.available,
+                    waiting: status.waiting,
+                }
+            })
+            .collect();
+        states.sort_by(|a, b| a.session.cmp(&b.session));
+        states
+    }
+}
+
+async fn run_copy_in(
+    client: &mut ClientWrapper,
+    sql: &str,
+    data: Vec<u8>,
+    readback: Option<&CopyReadback<'_>>,
+    opts: &ResolvedOptions,
+) -> Result<(u64, Vec<Value>, bool), ExecError> {
+    let mut tx = client.transaction().await.map_err(map_pg_error)?;
+    apply_query_settings(&mut tx, opts).await?;
+    if opts.approval_row_threshold.is_some() {
+        tx.batch_execute(APPROVAL_SAVEPOINT)
+            .await
+            .map_err(map_pg_error)?;
+    }
+    let sink = tx
+        .copy_in::<_, bytes::Bytes>(sql)
+        .await
+        .map_err(map_pg_error)?;
+    futures_util::pin_mut!(sink);
+    sink.send(bytes::Bytes::from(data))
+        .await
+        .map_err(map_pg_error)?;
+    let copied = sink.finish().await.map_err(map_pg_error)?;
+    if let Some(threshold) = opts.approval_row_threshold {
+        check_needs_approval(&tx, copied as usize, threshold).await?;
+    }
+    let (mut rows, mut keep) = (vec![], true);
+    if let Some(readback) = readback {
+        for row in tx.query(readback.sql, &[]).await.map_err(map_pg_error)? {
+            rows.push(row.try_get::<_, Value>(0).map_err(map_pg_error)?);
+        }
+        keep = (readback.keep)(&rows);
+    }
+    if keep {
+        tx.commit().await.map_err(map_pg_error)?;
+    } else {
+        tx.rollback().await.map_err(map_pg_error)?;
+    }
+    Ok((copied, rows, keep))
+}
+
+/// Run `sql` outside a transaction block, as `VACUUM` and `REINDEX ...
+/// CONCURRENTLY` require, on a connection that is discarded afterwards so
+/// its session settings do not leak. Nothing can be rolled back, so there is
+/// no approval check here.
+async fn run_autocommit(
+    pool: &Pool,
+    sql: &str,
+    params: &[Value],
+    opts: &ResolvedOptions,
+) -> Result<ExecOutcome, ExecError> {
+    if !params.is_empty() || opts.workspace.is_some() || opts.snapshot.is_some() {
+        return Err(ExecError::InvalidParams(
+            "statements outside a transaction take no params, workspace or snapshot".to_string(),
+        ));
+    }
+    let client = detached_client(pool).await?;
+    let mut settings = vec![
+        (
+            "statement_timeout",
+            format!("{}ms", opts.statement_timeout_ms),
+        ),
+        ("lock_timeout", format!("{}ms", opts.lock_timeout_ms)),
+    ];
+    if let Some(role) = &opts.role {
+        settings.push(("role", role.clone()));
+    }
+    for (name, value) in &opts.context {
+        settings.push((name, value.clone()));
+    }
+    for (name, value) in &settings {
+        client
+            .execute("select set_config($1, $2, false)", &[name, value])
+            .await
+            .map_err(map_pg_error)?;
+    }
+    let annotation = opts.annotation.as_deref().unwrap_or_default();
+    client
+        .batch_execute(&format!("{annotation}{sql}"))
+        .await
+        .map_err(map_pg_error)?;
+    Ok(ExecOutcome::Command { affected: 0 })
+}
 
+/// Run `statements` in one transaction on `client`, each in a savepoint of
+/// it, and commit once all have run.
+async fn run_script(
+    client: &mut ClientWrapper,
+    ext_types: &ExtTypeMap,
+    statements: &[String],
+    params: &[Value],
+    opts: &ResolvedOptions,
+    schema_generation: &AtomicU64,
+) -> ScriptRun {
+    let mut outcomes = vec![];
+    let mut ended_early = false;
+    let run: Result<(), ExecError> = async {
         let mut tx = client.transaction().await.map_err(map_pg_error)?;
         apply_query_settings(&mut tx, opts).await?;
-        let stmt = tx.prepare(sql).await.map_err(map_pg_error)?;
+        for sql in statements {
+            // A savepoint dropped uncommitted rolls back to where it began.
+            let savepoint = tx
+                .savepoint("afpsql_statement")
+                .await
+                .map_err(map_pg_error)?;
+            let outcome =
+                run_in_transaction(savepoint, ext_types, sql, params, opts, schema_generation)
+                    .await;
+            // Releasing the savepoint finds no transaction (25P01) when the
+            // statement committed or rolled it back itself.
+            if matches!(&outcome, Err(ExecError::Sql { sqlstate, .. }) if sqlstate == "25P01") {
+                ended_early = true;
+                let message = "the statement ended the script's transaction; scripts with \
+                               rollback_statement cannot commit or roll back themselves";
+                outcomes.push(Err(ExecError::Internal(message.to_string())));
+                return Err(ExecError::Internal(message.to_string()));
+            }
+            outcomes.push(outcome);
+        }
+        tx.commit().await.map_err(map_pg_error)
+    }
+    .await;
+    ScriptRun {
+        outcomes,
+        committed: run,
+        ended_early,
+    }
+}
+
+/// Run one statement in its own transaction on `client`, bumping
+/// `schema_generation` when it was DDL. With `prepare_transaction` the
+/// transaction is prepared rather than committed. Records in `timing` how
+/// long the statement took and, with `server_timing`, what the server
+/// reports for it.
+async fn run_statement(
+    client: &mut ClientWrapper,
+    ext_types: &ExtTypeMap,
+    sql: &str,
+    params: &[Value],
+    opts: &ResolvedOptions,
+    schema_generation: &AtomicU64,
+    timing: &mut Timing,
+) -> Result<ExecOutcome, ExecError> {
+    let started = Instant::now();
+    let mut tx = match &opts.snapshot {
+        None => client.transaction().await,
+        Some(_) => {
+            client
+                .build_transaction()
+                .isolation_level(IsolationLevel::RepeatableRead)
+                .start()
+                .await
+        }
+    }
+    .map_err(|e| {
+        // Nothing ran yet, so the caller may start over on another connection.
+        if e.is_closed() {
+            ExecError::Connect(format!("connection closed: {e}"))
+        } else {
+            map_pg_error(e)
+        }
+    })?;
+    if let Some(snapshot) = &opts.snapshot {
+        // Must come before any other statement in the transaction, and
+        // takes no bind parameters.
+        let set = format!(
+            "set transaction snapshot '{}'",
+            snapshot.replace('\'', "''")
+        );
+        tx.batch_execute(&set).await.map_err(map_pg_error)?;
+    }
+    apply_query_settings(&mut tx, opts).await?;
+    let mut explained = Duration::ZERO;
+    if opts.server_timing {
+        let explain_started = Instant::now();
+        let server = explain_analyze(&mut tx, ext_types, sql, params).await?;
+        (timing.server_planning_ms, timing.server_execution_ms) = server;
+        explained = explain_started.elapsed();
+    }
+    let outcome = match &opts.prepare_transaction {
+        None => run_in_transaction(tx, ext_types, sql, params, opts, schema_generation).await,
+        Some(gid) => run_prepared(tx, gid, ext_types, sql, params, opts, schema_generation).await,
+    };
+    let spent = started.elapsed().saturating_sub(explained);
+    timing.execute_ms = Some(spent.as_millis() as u64);
+    outcome
+}
+
+/// Read the rows of `sql` through a portal in one transaction, `page_rows`
+/// at a time, sending each page before fetching the next. Rows come out as
+/// in `run_in_transaction`; a closed `pages` stops the read and commits.
+async fn run_pages(
+    client: &mut ClientWrapper,
+    ext_types: &ExtTypeMap,
+    sql: &str,
+    params: &[Value],
+    opts: &ResolvedOptions,
+    page_rows: usize,
+    pages: &mpsc::Sender<Vec<Value>>,
+) -> Result<(), ExecError> {
+    let mut tx = match &opts.snapshot {
+        None => client.transaction().await,
+        Some(_) => {
+            client
+                .build_transaction()
+                .isolation_level(IsolationLevel::RepeatableRead)
+                .start()
+                .await
+        }
+    }
+    .map_err(map_pg_error)?;
+    if let Some(snapshot) = &opts.snapshot {
+        let set = format!(
+            "set transaction snapshot '{}'",
+            snapshot.replace('\'', "''")
+        );
+        tx.batch_execute(&set).await.map_err(map_pg_error)?;
+    }
+    apply_query_settings(&mut tx, opts).await?;
+    let stmt = tx.prepare(sql).await.map_err(map_pg_error)?;
+    if stmt.columns().is_empty() {
+        return Err(ExecError::Internal("sql must return rows".to_string()));
+    }
+    validate_param_count(stmt.params().len(), params.len())?;
+    let mut wrapped = format!(
+        "with __afpsql_rows as ({sql}) select to_jsonb(__afpsql_rows) as row_json from __afpsql_rows"
+    );
+    if let Some(cap) = opts.fetch_cap() {
+        wrapped.push_str(&format!(" limit {cap}"));
+    }
+    // Some utility statements (e.g. SHOW) cannot be wrapped in a CTE.
+    tx.batch_execute("savepoint afpsql_wrap")
+        .await
+        .map_err(map_pg_error)?;
+    let portal_stmt = match tx.prepare(&wrapped).await {
+        Ok(wrapped) => {
+            tx.batch_execute("release savepoint afpsql_wrap")
+                .await
+                .map_err(map_pg_error)?;
+            wrapped
+        }
+        Err(_) => {
+            tx.batch_execute("rollback to savepoint afpsql_wrap; release savepoint afpsql_wrap")
+                .await
+                .map_err(map_pg_error)?;
+            stmt.clone()
+        }
+    };
+    let query_params = build_params(params, portal_stmt.params(), ext_types)?;
+    let bind_refs = build_param_refs(&query_params);
+    let origins = if redact::needs_origins(&opts.redact) {
+        column_origins(&tx, stmt.columns(), &opts.nested_tables).await?
+    } else {
+        ColumnOrigins::new()
+    };
+    let portal = tx
+        .bind(&portal_stmt, &bind_refs)
+        .await
+        .map_err(map_pg_error)?;
+    let page_rows = page_rows.clamp(1, i32::MAX as usize);
+    loop {
+        let rows = tx
+            .query_portal(&portal, page_rows as i32)
+            .await
+            .map_err(map_pg_error)?;
+        let last = rows.len() < page_rows;
+        let mut page: Vec<Value> = rows
+            .iter()
+            .map(|row| {
+                row.try_get::<_, Value>("row_json")
+                    .unwrap_or_else(|_| row_to_json_fallback(row))
+            })
+            .collect();
+        redact::redact_rows(
+            &mut page,
+            &origins,
+            &opts.redact,
+            opts.redact_salt_secret.as_deref(),
+        );
+        if last {
+            if !page.is_empty() {
+                let _ = pages.send(page).await;
+            }
+            break;
+        }
+        if pages.send(page).await.is_err() {
+            break;
+        }
+    }
+    drop(portal);
+    tx.commit().await.map_err(map_pg_error)
+}
+
+/// Planning and execution times the server reports for `sql` under
+/// `EXPLAIN ANALYZE`. It runs in a read-only savepoint that is rolled back,
+/// so a statement that writes fails there and reports nothing, and `tx`
+/// is left as it was.
+async fn explain_analyze(
+    tx: &mut deadpool_postgres::Transaction<'_>,
+    ext_types: &ExtTypeMap,
+    sql: &str,
+    params: &[Value],
+) -> Result<(Option<f64>, Option<f64>), ExecError> {
+    tx.batch_execute("savepoint afpsql_timing; set local transaction_read_only = on")
+        .await
+        .map_err(map_pg_error)?;
+    let explained: Result<Value, ExecError> = async {
+        let explain = format!("explain (analyze, summary, format json) {sql}");
+        let stmt = tx.prepare(&explain).await.map_err(map_pg_error)?;
         validate_param_count(stmt.params().len(), params.len())?;
-        let query_params = build_params(params, stmt.params())?;
-        let bind_refs = build_param_refs(&query_params);
-
-        if !stmt.columns().is_empty() {
-            // Primary row path: CTE + to_jsonb to preserve PostgreSQL's own type
-            // serialization. This supports SELECT and RETURNING-style statements.
-            let wrapped = format!(
-                "with __afpsql_rows as ({sql}) select to_jsonb(__afpsql_rows) as row_json from __afpsql_rows"
+        let query_params = build_params(params, stmt.params(), ext_types)?;
+        let row = tx
+            .query_one(&stmt, &build_param_refs(&query_params))
+            .await
+            .map_err(map_pg_error)?;
+        row.try_get::<_, Value>(0).map_err(map_pg_error)
+    }
+    .await;
+    tx.batch_execute("rollback to savepoint afpsql_timing; release savepoint afpsql_timing")
+        .await
+        .map_err(map_pg_error)?;
+    let Ok(explained) = explained else {
+        return Ok((None, None));
+    };
+    let summary = &explained[0];
+    Ok((
+        summary["Planning Time"].as_f64(),
+        summary["Execution Time"].as_f64(),
+    ))
+}
+
+/// Run one statement in a savepoint of `tx`, then prepare `tx` as `gid`.
+async fn run_prepared(
+    mut tx: deadpool_postgres::Transaction<'_>,
+    gid: &str,
+    ext_types: &ExtTypeMap,
+    sql: &str,
+    params: &[Value],
+    opts: &ResolvedOptions,
+    schema_generation: &AtomicU64,
+) -> Result<ExecOutcome, ExecError> {
+    // The statement commits into a savepoint, leaving the transaction open
+    // to be prepared.
+    let savepoint = tx
+        .savepoint("afpsql_statement")
+        .await
+        .map_err(map_pg_error)?;
+    let outcome =
+        run_in_transaction(savepoint, ext_types, sql, params, opts, schema_generation).await?;
+    tx.batch_execute(&format!(
+        "prepare transaction '{}'",
+        gid.replace('\'', "''")
+    ))
+    .await
+    .map_err(map_pg_error)?;
+    // The transaction is already over; this COMMIT only draws a warning.
+    tx.commit().await.map_err(map_pg_error)?;
+    Ok(outcome)
+}
+
+/// Run one statement in `tx`, a transaction of its own or a savepoint of a
+/// script's, and commit it.
+async fn run_in_transaction(
+    tx: deadpool_postgres::Transaction<'_>,
+    ext_types: &ExtTypeMap,
+    sql: &str,
+    params: &[Value],
+    opts: &ResolvedOptions,
+    schema_generation: &AtomicU64,
+) -> Result<ExecOutcome, ExecError> {
+    let annotation = opts.annotation.as_deref().unwrap_or_default();
+    let stmt = tx
+        .prepare(&format!("{annotation}{sql}"))
+        .await
+        .map_err(|e| unshift_position(map_pg_error(e), annotation))?;
+    validate_param_count(stmt.params().len(), params.len())?;
+    let query_params = build_params(params, stmt.params(), ext_types)?;
+    let bind_refs = build_param_refs(&query_params);
+    if opts.approval_row_threshold.is_some() {
+        tx.batch_execute(APPROVAL_SAVEPOINT)
+            .await
+            .map_err(map_pg_error)?;
+    }
+
+    if !stmt.columns().is_empty() {
+        // Primary row path: CTE + to_jsonb to preserve PostgreSQL's own type
+        // serialization. This supports SELECT and RETURNING-style statements.
+        let mut wrapped = format!(
+                "{annotation}with __afpsql_rows as ({sql}) select to_jsonb(__afpsql_rows) as row_json from __afpsql_rows"
             );
-            tx.execute("savepoint afpsql_wrap", &[])
+        if let Some(cap) = opts.fetch_cap() {
+            wrapped.push_str(&format!(" limit {cap}"));
+        }
+        tx.execute("savepoint afpsql_wrap", &[])
+            .await
+            .map_err(map_pg_error)?;
+
+        let wrapped_attempt: Result<Vec<tokio_postgres::Row>, ExecError> = async {
+            let wrapped_stmt = tx.prepare(&wrapped).await.map_err(map_pg_error)?;
+            validate_param_count(wrapped_stmt.params().len(), params.len())?;
+            let wrapped_params = build_params(params, wrapped_stmt.params(), ext_types)?;
+            let wrapped_refs = build_param_refs(&wrapped_params);
+            tx.query(&wrapped_stmt, &wrapped_refs)
                 .await
-                .map_err(map_pg_error)?;
+                .map_err(map_pg_error)
+        }
+        .await;
 
-            let wrapped_attempt: Result<Vec<tokio_postgres::Row>, ExecError> = async {
-                let wrapped_stmt = tx.prepare(&wrapped).await.map_err(map_pg_error)?;
-                validate_param_count(wrapped_stmt.params().len(), params.len())?;
-                let wrapped_params = build_params(params, wrapped_stmt.params())?;
-                let wrapped_refs = build_param_refs(&wrapped_params);
-                tx.query(&wrapped_stmt, &wrapped_refs)
+        let rows = match wrapped_attempt {
+            Ok(rows) => {
+                tx.execute("release savepoint afpsql_wrap", &[])
                     .await
-                    .map_err(map_pg_error)
+                    .map_err(map_pg_error)?;
+                rows
             }
-            .await;
+            Err(ExecError::InvalidParams(message)) => {
+                tx.execute("rollback to savepoint afpsql_wrap", &[])
+                    .await
+                    .map_err(map_pg_error)?;
+                tx.execute("release savepoint afpsql_wrap", &[])
+                    .await
+                    .map_err(map_pg_error)?;
+                return Err(ExecError::InvalidParams(message));
+            }
+            Err(_) => {
+                // Some utility statements (e.g. SHOW) cannot be wrapped in CTE.
+                // Roll back wrapper failure and fall back to direct row decode.
+                tx.execute("rollback to savepoint afpsql_wrap", &[])
+                    .await
+                    .map_err(map_pg_error)?;
+                tx.execute("release savepoint afpsql_wrap", &[])
+                    .await
+                    .map_err(map_pg_error)?;
+                tx.query(&stmt, &bind_refs).await.map_err(map_pg_error)?
+            }
+        };
 
-            let rows = match wrapped_attempt {
-                Ok(rows) => {
-                    tx.execute("release savepoint afpsql_wrap", &[])
-                        .await
-                        .map_err(map_pg_error)?;
-                    rows
-                }
-                Err(ExecError::InvalidParams(message)) => {
-                    tx.execute("rollback to savepoint afpsql_wrap", &[])
-                        .await
-                        .map_err(map_pg_error)?;
-                    tx.execute("release savepoint afpsql_wrap", &[])
-                        .await
-                        .map_err(map_pg_error)?;
-                    return Err(ExecError::InvalidParams(message));
-                }
-                Err(_) => {
-                    // Some utility statements (e.g. SHOW) cannot be wrapped in CTE.
-                    // Roll back wrapper failure and fall back to direct row decode.
-                    tx.execute("rollback to savepoint afpsql_wrap", &[])
-                        .await
-                        .map_err(map_pg_error)?;
-                    tx.execute("release savepoint afpsql_wrap", &[])
-                        .await
-                        .map_err(map_pg_error)?;
-                    tx.query(&stmt, &bind_refs).await.map_err(map_pg_error)?
+        if let Some(threshold) = opts.approval_row_threshold {
+            check_needs_approval(&tx, rows.len(), threshold).await?;
+        }
+        let origins = if redact::needs_origins(&opts.redact) {
+            column_origins(&tx, stmt.columns(), &opts.nested_tables).await?
+        } else {
+            ColumnOrigins::new()
+        };
+        tx.commit().await.map_err(map_pg_error)?;
+
+        let mut json_rows: Vec<Value> = rows
+            .into_iter()
+            .map(|row| {
+                if let Ok(value) = row.try_get::<_, Value>("row_json") {
+                    return value;
                 }
-            };
+                row_to_json_fallback(&row)
+            })
+            .collect();
+        redact::redact_rows(
+            &mut json_rows,
+            &origins,
+            &opts.redact,
+            opts.redact_salt_secret.as_deref(),
+        );
 
-            tx.commit().await.map_err(map_pg_error)?;
+        return Ok(ExecOutcome::Rows(json_rows));
+    }
 
-            let json_rows = rows
-                .into_iter()
-                .map(|row| {
-                    if let Ok(value) = row.try_get::<_, Value>("row_json") {
-                        return value;
-                    }
-                    row_to_json_fallback(&row)
-                })
-                .collect();
+    let affected = tx.execute(&stmt, &bind_refs).await.map_err(map_pg_error)? as usize;
+    if let Some(threshold) = opts.approval_row_threshold {
+        check_needs_approval(&tx, affected, threshold).await?;
+    }
+    // DDL reports no rows; only then is it worth asking about locks.
+    let ddl = affected == 0
+        && tx
+            .query_one(DDL_LOCKS_SQL, &[])
+            .await
+            .map_err(map_pg_error)?
+            .get::<_, bool>(0);
+    tx.commit().await.map_err(map_pg_error)?;
+    if ddl {
+        schema_generation.fetch_add(1, Ordering::Relaxed);
+    }
 
-            return Ok(ExecOutcome::Rows(json_rows));
-        }
+    Ok(ExecOutcome::Command { affected })
+}
 
-        let affected = tx.execute(&stmt, &bind_refs).await.map_err(map_pg_error)? as usize;
-        tx.commit().await.map_err(map_pg_error)?;
+const DO_BLOCK_TAG: &str = "$afpsql_block$";
 
-        Ok(ExecOutcome::Command { affected })
+fn build_do_block(body: &str) -> Result<String, ExecError> {
+    if body.contains(DO_BLOCK_TAG) {
+        return Err(ExecError::InvalidParams(format!(
+            "block body must not contain the {DO_BLOCK_TAG} delimiter"
+        )));
+    }
+    Ok(format!(
+        "do language plpgsql {DO_BLOCK_TAG}{body}{DO_BLOCK_TAG}"
+    ))
+}
+
+fn validate_block_var_name(name: &str) -> Result<(), ExecError> {
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false);
+    if valid_start && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Ok(());
+    }
+    Err(ExecError::InvalidParams(format!(
+        "invalid block variable name '{name}'"
+    )))
+}
+
+/// Error positions count from the start of the text sent; report them
+/// against the caller's SQL, without the annotation `prefix`.
+fn unshift_position(err: ExecError, prefix: &str) -> ExecError {
+    let ExecError::Sql {
+        sqlstate,
+        message,
+        detail,
+        hint,
+        position: Some(position),
+    } = err
+    else {
+        return err;
+    };
+    let shift = prefix.chars().count();
+    let position = match position.parse::<usize>() {
+        Ok(n) if n > shift => (n - shift).to_string(),
+        _ => position,
+    };
+    ExecError::Sql {
+        sqlstate,
+        message,
+        detail,
+        hint,
+        position: Some(position),
     }
 }
 
@@ -203,6 +1571,7 @@ enum QueryParam {
     Float(f64),
     Text(String),
     Json(Json<Value>),
+    Ext(ExtParam),
 }
 
 #[derive(Debug)]
@@ -224,10 +1593,19 @@ impl ToSql for AnyNull {
     tokio_postgres::types::to_sql_checked!();
 }
 
-fn build_params(values: &[Value], expected_types: &[Type]) -> Result<Vec<QueryParam>, ExecError> {
+fn build_params(
+    values: &[Value],
+    expected_types: &[Type],
+    ext_types: &ExtTypeMap,
+) -> Result<Vec<QueryParam>, ExecError> {
     let mut params = Vec::with_capacity(values.len());
     for (idx, v) in values.iter().enumerate() {
         let ty = expected_types.get(idx).unwrap_or(&Type::TEXT);
+        if let (false, Some(ext)) = (v.is_null(), ext_types.get(&ty.oid())) {
+            let p = ext.bind(v, idx + 1).map_err(ExecError::InvalidParams)?;
+            params.push(QueryParam::Ext(p));
+            continue;
+        }
         let p = match v {
             Value::Null => QueryParam::Null(AnyNull),
             Value::Array(_) | Value::Object(_) if *ty == Type::JSON || *ty == Type::JSONB => {
@@ -261,6 +1639,9 @@ fn build_param_refs(params: &[QueryParam]) -> Vec<&(dyn ToSql + Sync)> {
             QueryParam::Float(v) => v as &(dyn ToSql + Sync),
             QueryParam::Text(v) => v as &(dyn ToSql + Sync),
             QueryParam::Json(v) => v as &(dyn ToSql + Sync),
+            QueryParam::Ext(ExtParam::Vector(v)) => v as &(dyn ToSql + Sync),
+            QueryParam::Ext(ExtParam::Hstore(v)) => v as &(dyn ToSql + Sync),
+            QueryParam::Ext(ExtParam::Text(v)) => v as &(dyn ToSql + Sync),
         })
         .collect()
 }
@@ -349,6 +1730,54 @@ fn validate_param_count(expected: usize, actual: usize) -> Result<(), ExecError>
     )))
 }
 
+/// Source `(schema, table)` of each result column that reads a table column.
+async fn column_origins(
+    tx: &tokio_postgres::Transaction<'_>,
+    columns: &[tokio_postgres::Column],
+    nested: &BTreeMap<String, Vec<String>>,
+) -> Result<ColumnOrigins, ExecError> {
+    let mut oids: Vec<u32> = columns.iter().filter_map(|c| c.table_oid()).collect();
+    oids.sort_unstable();
+    oids.dedup();
+    let mut origins = ColumnOrigins::new();
+    for (column, tables) in nested {
+        let rows = tx
+            .query(redact::NAMED_TABLES_SQL, &[tables])
+            .await
+            .map_err(map_pg_error)?;
+        let sources = origins.entry(column.clone()).or_default();
+        for row in rows {
+            if let (Ok(schema), Ok(table)) = (row.try_get(0), row.try_get(1)) {
+                sources.push((schema, table));
+            }
+        }
+    }
+    if oids.is_empty() {
+        return Ok(origins);
+    }
+    let tables: HashMap<u32, (String, String)> = tx
+        .query(redact::TABLE_NAMES_SQL, &[&oids])
+        .await
+        .map_err(map_pg_error)?
+        .iter()
+        .filter_map(|row| {
+            Some((
+                row.try_get(0).ok()?,
+                (row.try_get(1).ok()?, row.try_get(2).ok()?),
+            ))
+        })
+        .collect();
+    for col in columns {
+        if let Some(table) = col.table_oid().and_then(|oid| tables.get(&oid)) {
+            origins
+                .entry(col.name().to_string())
+                .or_default()
+                .push(table.clone());
+        }
+    }
+    Ok(origins)
+}
+
 fn row_to_json_fallback(row: &tokio_postgres::Row) -> Value {
     let mut map = serde_json::Map::new();
     for (idx, col) in row.columns().iter().enumerate() {
@@ -419,6 +1848,129 @@ fn decode_row_value_fallback(row: &tokio_postgres::Row, idx: usize, ty: &Type) -
     }
 }
 
+/// What the current transaction did, as seen by the server: the relations
+/// and other objects it holds `AccessExclusiveLock` on (DDL, `TRUNCATE` and
+/// `DROP` take that lock on their target) and whether it has written
+/// anything (a transaction id is only assigned on first write). No statement
+/// text is inspected.
+const TX_EFFECTS_SQL: &str = "select \
+     coalesce(array_agg(distinct l.relation) filter (where l.locktype = 'relation'), '{}'), \
+     coalesce(array_agg(l.classid order by l.classid, l.objid) \
+         filter (where l.locktype = 'object'), '{}'), \
+     coalesce(array_agg(l.objid order by l.classid, l.objid) \
+         filter (where l.locktype = 'object'), '{}'), \
+     txid_current_if_assigned() is not null \
+     from pg_locks l where l.pid = pg_backend_pid() and l.granted \
+     and l.mode = 'AccessExclusiveLock' and l.locktype in ('relation', 'object')";
+
+/// Qualified names of the relations `$1` that exist.
+const RELATION_NAMES_SQL: &str = "select c.oid, format('%I.%I', n.nspname, c.relname) \
+     from pg_class c join pg_namespace n on n.oid = c.relnamespace where c.oid = any($1)";
+
+/// Descriptions of the objects (`$1` catalog, `$2` id) that exist.
+const OBJECT_NAMES_SQL: &str = "select o.objid, pg_describe_object(o.classid, o.objid, 0) \
+     from unnest($1::oid[], $2::oid[]) as o(classid, objid) \
+     where pg_describe_object(o.classid, o.objid, 0) is not null";
+
+/// Savepoint taken before a statement runs under `approval_row_threshold`,
+/// so the objects it dropped can be named again.
+const APPROVAL_SAVEPOINT: &str = "savepoint afpsql_approval";
+
+/// Rows inserted, updated and deleted in user tables by the current
+/// transaction, as far as the backend's pending statistics tell; only
+/// meaningful on a connection that has run nothing else.
+const TX_ROWS_WRITTEN_SQL: &str =
+    "select coalesce(sum(n_tup_ins + n_tup_upd + n_tup_del), 0)::int8 \
+     from pg_stat_xact_user_tables";
+
+/// Whether the transaction holds an `ACCESS EXCLUSIVE` lock, which every
+/// `CREATE`, `ALTER` and `DROP` of a relation takes.
+const DDL_LOCKS_SQL: &str = "select exists (select 1 from pg_locks l \
+     where l.pid = pg_backend_pid() and l.granted \
+     and l.mode = 'AccessExclusiveLock' and l.locktype in ('relation', 'object'))";
+
+/// Fail with `ApprovalRequired` (dropping, and so rolling back, the caller's
+/// transaction) when the statement took exclusive locks, or wrote and
+/// returned or affected more than `threshold` rows.
+async fn check_needs_approval(
+    tx: &tokio_postgres::Transaction<'_>,
+    affected: usize,
+    threshold: usize,
+) -> Result<(), ExecError> {
+    let row = tx
+        .query_one(TX_EFFECTS_SQL, &[])
+        .await
+        .map_err(map_pg_error)?;
+    let relations: Vec<u32> = row.get(0);
+    let objects: (Vec<u32>, Vec<u32>) = (row.get(1), row.get(2));
+    let wrote: bool = row.get(3);
+    if !relations.is_empty() || !objects.1.is_empty() {
+        let locked = locked_names(tx, &relations, &objects).await;
+        return Err(ExecError::ApprovalRequired(format!(
+            "statement takes exclusive locks on {locked}"
+        )));
+    }
+    if wrote && affected > threshold {
+        return Err(ExecError::ApprovalRequired(format!(
+            "statement affects {affected} rows, over approval_row_threshold {threshold}"
+        )));
+    }
+    Ok(())
+}
+
+/// Name what a statement locked, for an approver to read. A relation it
+/// created only has a name before rolling back, one it dropped only after
+/// rolling back to `APPROVAL_SAVEPOINT`; what is still unnamed is shown by
+/// its OID.
+async fn locked_names(
+    tx: &tokio_postgres::Transaction<'_>,
+    relations: &[u32],
+    (classes, objects): &(Vec<u32>, Vec<u32>),
+) -> String {
+    let mut named = HashMap::new();
+    name_locked(tx, relations, classes, objects, &mut named).await;
+    let all = relations.len() + objects.len();
+    if named.len() < all
+        && tx
+            .batch_execute(&format!("rollback to {APPROVAL_SAVEPOINT}"))
+            .await
+            .is_ok()
+    {
+        name_locked(tx, relations, classes, objects, &mut named).await;
+    }
+    let keys = relations.iter().map(|oid| (true, *oid));
+    let keys = keys.chain(objects.iter().map(|oid| (false, *oid)));
+    let mut names: Vec<String> = vec![];
+    for (relation, oid) in keys {
+        let name = match named.get(&(relation, oid)) {
+            Some(name) => name.clone(),
+            None if relation => oid.to_string(),
+            None => format!("object {oid}"),
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.join(", ")
+}
+
+/// Add the names found for `relations` (keyed `true`) and `objects` (keyed
+/// `false`) to `named`.
+async fn name_locked(
+    tx: &tokio_postgres::Transaction<'_>,
+    relations: &[u32],
+    classes: &[u32],
+    objects: &[u32],
+    named: &mut HashMap<(bool, u32), String>,
+) {
+    if let Ok(rows) = tx.query(RELATION_NAMES_SQL, &[&relations]).await {
+        named.extend(rows.iter().map(|r| ((true, r.get(0)), r.get(1))));
+    }
+    if let Ok(rows) = tx.query(OBJECT_NAMES_SQL, &[&classes, &objects]).await {
+        named.extend(rows.iter().map(|r| ((false, r.get(0)), r.get(1))));
+    }
+}
+
 async fn apply_query_settings(
     tx: &mut tokio_postgres::Transaction<'_>,
     opts: &ResolvedOptions,
@@ -439,6 +1991,33 @@ async fn apply_query_settings(
     .await
     .map_err(map_pg_error)?;
 
+    if let Some(role) = &opts.role {
+        tx.execute("select set_config('role', $1, true)", &[role])
+            .await
+            .map_err(map_pg_error)?;
+    }
+
+    for (name, value) in &opts.context {
+        tx.execute("select set_config($1, $2, true)", &[name, value])
+            .await
+            .map_err(map_pg_error)?;
+    }
+
+    if opts.defer_constraints {
+        tx.execute("set constraints all deferred", &[])
+            .await
+            .map_err(map_pg_error)?;
+    }
+
+    if let Some(role) = opts.replication_role {
+        tx.execute(
+            "select set_config('session_replication_role', $1, true)",
+            &[&role.name()],
+        )
+        .await
+        .map_err(map_pg_error)?;
+    }
+
     if opts.read_only {
         tx.execute("set local transaction read only", &[])
             .await
@@ -0,0 +1,63 @@
+//! Rows `import` or a batched `psql_insert` could not write, captured with
+//! their errors in a dead letter so that only they need fixing and retrying.
+//!
+//! A file dead letter holds one JSON object per line; a table one has the
+//! same columns plus `captured_at`. `row` is the row as it was to be
+//! written, after any `columns` mapping; a record that did not parse has
+//! its text in `raw` instead.
+
+use crate::sqlgen;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadRow {
+    /// Line of the source for `import`, 1-based index in `rows` for
+    /// `psql_insert`.
+    pub position: usize,
+    pub error: String,
+    pub row: Option<Value>,
+    pub raw: Option<String>,
+}
+
+impl DeadRow {
+    pub fn to_value(&self) -> Value {
+        json!({
+            "position": self.position,
+            "error": self.error,
+            "row": self.row,
+            "raw": self.raw,
+        })
+    }
+}
+
+/// The dead rows as NDJSON.
+pub fn encode(rows: &[DeadRow]) -> Vec<u8> {
+    let mut out = vec![];
+    for row in rows {
+        out.extend_from_slice(row.to_value().to_string().as_bytes());
+        out.push(b'\n');
+    }
+    out
+}
+
+/// Replace the file at `path` with `rows`, so it holds this run's failures
+/// only.
+pub async fn write_file(path: &str, rows: &[DeadRow]) -> Result<(), String> {
+    tokio::fs::write(path, encode(rows))
+        .await
+        .map_err(|e| format!("cannot write {path}: {e}"))
+}
+
+/// `create table if not exists` for a dead-letter table.
+pub fn create_table_sql(table: &str) -> Result<String, String> {
+    Ok(format!(
+        "create table if not exists {} (position integer not null, error text not null, \
+         row jsonb, raw text, captured_at timestamptz not null default now())",
+        sqlgen::qualified_name(table)?
+    ))
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_deadletter.rs"]
+mod tests;
@@ -0,0 +1,108 @@
+//! Absolute request deadlines from the `deadline` query option.
+//!
+//! A deadline is either Unix epoch milliseconds or an RFC 3339 timestamp. The
+//! handler turns it into the time left, which caps `statement_timeout_ms`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Deadline {
+    EpochMs(u64),
+    Rfc3339(String),
+}
+
+impl Deadline {
+    /// Parse a CLI value: all digits is epoch milliseconds, anything else
+    /// RFC 3339.
+    pub fn from_arg(v: &str) -> Self {
+        match v.parse::<u64>() {
+            Ok(ms) => Deadline::EpochMs(ms),
+            Err(_) => Deadline::Rfc3339(v.to_string()),
+        }
+    }
+
+    pub fn epoch_ms(&self) -> Result<u64, String> {
+        match self {
+            Deadline::EpochMs(ms) => Ok(*ms),
+            Deadline::Rfc3339(s) => parse_rfc3339_ms(s)
+                .ok_or_else(|| format!("invalid deadline {s}: expected RFC 3339 or epoch ms")),
+        }
+    }
+
+    /// Milliseconds left before the deadline at `now_ms`; `0` once past.
+    pub fn remaining_ms(&self, now_ms: u64) -> Result<u64, String> {
+        Ok(self.epoch_ms()?.saturating_sub(now_ms))
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SS[.fff][Z|±HH:MM]` as Unix epoch milliseconds.
+fn parse_rfc3339_ms(s: &str) -> Option<u64> {
+    let b = s.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    if !matches!(b[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    let num = |from, to| num_in(s, from, to);
+    let (year, month, day) = (num(0, 4)?, num(5, 7)?, num(8, 10)?);
+    let (hour, minute, second) = (num(11, 13)?, num(14, 16)?, num(17, 19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    let mut millis = 0i64;
+    if let Some(frac) = rest.strip_prefix('.') {
+        let digits = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        let padded = format!("{:0<3}", &frac[..digits.min(3)]);
+        millis = padded.parse().ok()?;
+        rest = &frac[digits..];
+    }
+    let offset_min = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            sign * (num_in(rest, 1, 3)? * 60 + num_in(rest, 4, 6)?)
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second - offset_min * 60;
+    u64::try_from(secs * 1_000 + millis).ok()
+}
+
+fn num_in(s: &str, from: usize, to: usize) -> Option<i64> {
+    let part = s.get(from..to)?;
+    if !part.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_deadline.rs"]
+mod tests;
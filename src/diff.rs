@@ -0,0 +1,101 @@
+//! Baselines for queries with `diff_output` or `dedup`.
+//!
+//! The rows a query last returned are kept under its [`cache_key`], so
+//! re-running it replies with the rows added and removed since. Rows are
+//! compared whole, as a multiset: a changed row is one removed plus one
+//! added. `dedup` instead matches rows by their `key_columns` key and keeps
+//! those whose values changed.
+//!
+//! [`cache_key`]: crate::cache::cache_key
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct Baselines {
+    /// Rows and the sequence number of their last use, by query key.
+    entries: HashMap<String, (Vec<Value>, u64)>,
+    next_seq: u64,
+}
+
+impl Baselines {
+    /// Keep `rows` as the baseline for `key` and return the previous one.
+    /// Least recently used baselines are dropped to stay within `capacity`.
+    pub fn swap(&mut self, key: String, rows: Vec<Value>, capacity: usize) -> Option<Vec<Value>> {
+        self.next_seq += 1;
+        let previous = self
+            .entries
+            .insert(key, (rows, self.next_seq))
+            .map(|(rows, _)| rows);
+        while self.entries.len() > capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, seq))| *seq)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+        previous
+    }
+}
+
+/// Rows of `new` missing from `old` (in `new` order) and rows of `old`
+/// missing from `new` (in `old` order), counting duplicates.
+pub fn row_diff(old: &[Value], new: &[Value]) -> (Vec<Value>, Vec<Value>) {
+    let mut remaining: HashMap<String, usize> = HashMap::new();
+    for row in old {
+        *remaining.entry(row.to_string()).or_default() += 1;
+    }
+    let mut added = Vec::new();
+    for row in new {
+        match remaining.get_mut(&row.to_string()) {
+            Some(n) if *n > 0 => *n -= 1,
+            _ => added.push(row.clone()),
+        }
+    }
+    let removed = old
+        .iter()
+        .filter(|row| {
+            remaining
+                .get_mut(&row.to_string())
+                .is_some_and(|n| match *n {
+                    0 => false,
+                    _ => {
+                        *n -= 1;
+                        true
+                    }
+                })
+        })
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+/// The `columns` values of `row`, in order; missing columns are null.
+pub fn row_key(row: &Value, columns: &[String]) -> Value {
+    Value::Array(
+        columns
+            .iter()
+            .map(|c| row.get(c).cloned().unwrap_or(Value::Null))
+            .collect(),
+    )
+}
+
+/// Rows of `new` whose key is not in `old` or whose values differ from the
+/// `old` row with that key.
+pub fn changed_rows(old: &[Value], new: Vec<Value>, columns: &[String]) -> Vec<Value> {
+    let previous: HashMap<String, String> = old
+        .iter()
+        .map(|row| (row_key(row, columns).to_string(), row.to_string()))
+        .collect();
+    new.into_iter()
+        .filter(|row| previous.get(&row_key(row, columns).to_string()) != Some(&row.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_diff.rs"]
+mod tests;
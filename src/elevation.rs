@@ -0,0 +1,58 @@
+//! Time-boxed elevated access granted by `grant_elevated`.
+//!
+//! While a grant is live, requests on its session run under `role` (via
+//! `SET LOCAL ROLE`) and/or over the connection of `via_session`. Grants are
+//! checked for expiry on every lookup, so a late revert timer never extends
+//! them.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct Elevation {
+    /// Distinguishes re-grants on the same session, so an old revert timer
+    /// does not end a newer grant.
+    pub grant: u64,
+    pub role: Option<String>,
+    pub via_session: Option<String>,
+    pub reason: String,
+    pub expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Elevations {
+    active: HashMap<String, Elevation>,
+    next_grant: u64,
+}
+
+impl Elevations {
+    /// Install `elevation` for `session`, replacing any earlier grant, and
+    /// return its grant number.
+    pub fn grant(&mut self, session: &str, mut elevation: Elevation) -> u64 {
+        self.next_grant += 1;
+        elevation.grant = self.next_grant;
+        self.active.insert(session.to_string(), elevation);
+        self.next_grant
+    }
+
+    /// The live grant for `session`, if any.
+    pub fn active(&self, session: &str) -> Option<Elevation> {
+        self.active
+            .get(session)
+            .filter(|e| e.expires_at > Instant::now())
+            .cloned()
+    }
+
+    /// Remove grant number `grant` from `session`; `None` when it was already
+    /// replaced or removed.
+    pub fn revert(&mut self, session: &str, grant: u64) -> Option<Elevation> {
+        if self.active.get(session).is_some_and(|e| e.grant == grant) {
+            return self.active.remove(session);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_elevation.rs"]
+mod tests;
@@ -0,0 +1,183 @@
+//! Table relationship graphs for `psql_er_graph`.
+//!
+//! Nodes are the ordinary and partitioned tables of one schema; edges are
+//! their foreign keys, pointing from the referencing table to the referenced
+//! one. Tables of other schemas appear qualified (`schema.table`) at the far
+//! end of an edge. A foreign key is one-to-one when its columns are also a
+//! unique key of the referencing table, and optional when any of them is
+//! nullable.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Tables of schema `$1`, with their primary key and estimated row count
+/// (`null` until the table is first analyzed).
+pub const NODES_SQL: &str = "select c.relname::text as table, \
+     coalesce((select array_agg(a.attname::text order by k.ord) \
+       from pg_index i \
+       cross join lateral unnest(i.indkey::int2[]) with ordinality as k(attnum, ord) \
+       join pg_attribute a on a.attrelid = i.indrelid and a.attnum = k.attnum \
+       where i.indrelid = c.oid and i.indisprimary), '{}') as primary_key, \
+     case when c.reltuples < 0 then null else c.reltuples::bigint end as estimated_rows \
+     from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+     where n.nspname = $1 and c.relkind in ('r', 'p') and not c.relispartition \
+     order by c.relname";
+
+/// Foreign keys from or to tables of schema `$1`.
+pub const EDGES_SQL: &str = "select con.conname::text as name, \
+     case when fn.nspname = $1 then f.relname::text else fn.nspname || '.' || f.relname end \
+       as from_table, \
+     (select array_agg(a.attname::text order by k.ord) \
+       from unnest(con.conkey) with ordinality as k(attnum, ord) \
+       join pg_attribute a on a.attrelid = con.conrelid and a.attnum = k.attnum) \
+       as from_columns, \
+     case when tn.nspname = $1 then t.relname::text else tn.nspname || '.' || t.relname end \
+       as to_table, \
+     (select array_agg(a.attname::text order by k.ord) \
+       from unnest(con.confkey) with ordinality as k(attnum, ord) \
+       join pg_attribute a on a.attrelid = con.confrelid and a.attnum = k.attnum) \
+       as to_columns, \
+     exists (select 1 from pg_index i \
+       where i.indrelid = con.conrelid and i.indisunique \
+       and i.indpred is null and i.indexprs is null \
+       and (select array_agg(x order by x) from unnest(i.indkey::int2[]) x) \
+         = (select array_agg(x order by x) from unnest(con.conkey) x)) as unique_from, \
+     exists (select 1 from pg_attribute a \
+       where a.attrelid = con.conrelid and a.attnum = any(con.conkey) \
+       and not a.attnotnull) as optional \
+     from pg_constraint con \
+     join pg_class f on f.oid = con.conrelid \
+     join pg_namespace fn on fn.oid = f.relnamespace \
+     join pg_class t on t.oid = con.confrelid \
+     join pg_namespace tn on tn.oid = t.relnamespace \
+     where con.contype = 'f' and con.conparentid = 0 and $1 in (fn.nspname, tn.nspname) \
+     order by from_table, con.conname";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub table: String,
+    pub primary_key: Vec<String>,
+    pub estimated_rows: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cardinality {
+    ManyToOne,
+    OneToOne,
+}
+
+/// A row of [`EDGES_SQL`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct EdgeRow {
+    pub name: String,
+    pub from_table: String,
+    pub from_columns: Vec<String>,
+    pub to_table: String,
+    pub to_columns: Vec<String>,
+    pub unique_from: bool,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Edge {
+    pub name: String,
+    pub from_table: String,
+    pub from_columns: Vec<String>,
+    pub to_table: String,
+    pub to_columns: Vec<String>,
+    /// Rows of `from_table` per row of `to_table`.
+    pub cardinality: Cardinality,
+    /// A referencing row may have no referenced row.
+    pub optional: bool,
+}
+
+impl From<EdgeRow> for Edge {
+    fn from(row: EdgeRow) -> Self {
+        Self {
+            name: row.name,
+            from_table: row.from_table,
+            from_columns: row.from_columns,
+            to_table: row.to_table,
+            to_columns: row.to_columns,
+            cardinality: if row.unique_from {
+                Cardinality::OneToOne
+            } else {
+                Cardinality::ManyToOne
+            },
+            optional: row.optional,
+        }
+    }
+}
+
+/// Graphviz `digraph`, one edge per foreign key labelled with its columns.
+pub fn to_dot(nodes: &[Node], edges: &[Edge]) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = String::from("digraph er {\n  rankdir=LR;\n  node [shape=box];\n");
+    for node in nodes {
+        let _ = writeln!(out, "  {};", quote(&node.table));
+    }
+    for edge in edges {
+        let label = format!(
+            "{} -> {}",
+            edge.from_columns.join(", "),
+            edge.to_columns.join(", ")
+        );
+        let _ = writeln!(
+            out,
+            "  {} -> {} [label={}];",
+            quote(&edge.from_table),
+            quote(&edge.to_table),
+            quote(&label)
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid `erDiagram`. Entity names keep letters, digits, `_` and `-`;
+/// other characters become `_`.
+pub fn to_mermaid(nodes: &[Node], edges: &[Edge]) -> String {
+    let entity = |s: &str| -> String {
+        s.chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    let mut out = String::from("erDiagram\n");
+    for node in nodes {
+        if node.primary_key.is_empty() {
+            let _ = writeln!(out, "  {}", entity(&node.table));
+            continue;
+        }
+        let _ = writeln!(out, "  {} {{", entity(&node.table));
+        for column in &node.primary_key {
+            let _ = writeln!(out, "    key {} PK", entity(column));
+        }
+        out.push_str("  }\n");
+    }
+    for edge in edges {
+        let parent = if edge.optional { "|o" } else { "||" };
+        let child = match edge.cardinality {
+            Cardinality::ManyToOne => "o{",
+            Cardinality::OneToOne => "o|",
+        };
+        let _ = writeln!(
+            out,
+            "  {} {parent}--{child} {} : \"{}\"",
+            entity(&edge.to_table),
+            entity(&edge.from_table),
+            edge.name.replace('"', "'")
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_er.rs"]
+mod tests;
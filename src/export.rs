@@ -0,0 +1,281 @@
+//! Exports of query results to object storage, for queries with
+//! `export_uri`, and reads of objects for `import`.
+//!
+//! `s3://bucket/key` and `gs://bucket/key` name the object. The key's
+//! extension picks the format, `.csv` or `.jsonl`/`.ndjson`, and a trailing
+//! `.gz` gzips it. Rows are encoded a chunk at a time into a multipart
+//! upload, so the encoded object is never held whole. Credentials come from
+//! the environment (`AWS_*`, `GOOGLE_*`), as for the providers' own tools.
+
+// Without the feature only the option checks are used.
+#![cfg_attr(not(feature = "object-store"), allow(dead_code))]
+
+use serde_json::Value;
+
+/// Whether this build includes the `object-store` feature.
+pub const AVAILABLE: bool = cfg!(feature = "object-store");
+
+/// Rows encoded per write to the upload.
+const CHUNK_ROWS: usize = 10_000;
+
+/// Upload parts sent concurrently before encoding waits.
+const PARTS_IN_FLIGHT: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    S3,
+    Gcs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Ndjson,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// The format named by a file name's extension (`.csv`, `.jsonl` or
+/// `.ndjson`, optionally followed by `.gz`), and whether it is gzipped.
+pub fn file_format(name: &str) -> Option<(Format, bool)> {
+    let (stem, gzip) = match name.strip_suffix(".gz") {
+        Some(stem) => (stem, true),
+        None => (name, false),
+    };
+    let format = match stem.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("csv") => Format::Csv,
+        Some("jsonl" | "ndjson") => Format::Ndjson,
+        _ => return None,
+    };
+    Some((format, gzip))
+}
+
+/// An `s3://` or `gs://` object holding CSV or NDJSON rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectUri {
+    pub provider: Provider,
+    pub bucket: String,
+    pub key: String,
+    pub format: Format,
+    pub gzip: bool,
+}
+
+impl ObjectUri {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let (provider, rest) = if let Some(rest) = uri.strip_prefix("s3://") {
+            (Provider::S3, rest)
+        } else if let Some(rest) = uri.strip_prefix("gs://") {
+            (Provider::Gcs, rest)
+        } else {
+            return Err(format!("{uri:?} must start with s3:// or gs://"));
+        };
+        let Some((bucket, key)) = rest
+            .split_once('/')
+            .filter(|(b, k)| !b.is_empty() && !k.is_empty())
+        else {
+            return Err(format!("{uri:?} needs a bucket and a key"));
+        };
+        let Some((format, gzip)) = file_format(key) else {
+            return Err(format!(
+                "{uri:?} must end in .csv, .jsonl or .ndjson, optionally followed by .gz"
+            ));
+        };
+        Ok(Self {
+            provider,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            format,
+            gzip,
+        })
+    }
+}
+
+/// `rows` in `format`, one line each; CSV starts with a header of `columns`
+/// when `header` is set.
+pub fn encode_rows(rows: &[Value], columns: &[String], format: Format, header: bool) -> Vec<u8> {
+    let mut out = String::new();
+    match format {
+        Format::Csv => {
+            if header {
+                let names: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+                out.push_str(&names.join(","));
+                out.push('\n');
+            }
+            for row in rows {
+                let fields: Vec<String> = columns
+                    .iter()
+                    .map(|c| match row.get(c) {
+                        None | Some(Value::Null) => String::new(),
+                        Some(Value::String(s)) => csv_field(s),
+                        Some(other) => csv_field(&other.to_string()),
+                    })
+                    .collect();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+        }
+        Format::Ndjson => {
+            for row in rows {
+                out.push_str(&row.to_string());
+                out.push('\n');
+            }
+        }
+    }
+    out.into_bytes()
+}
+
+/// `value` quoted when it holds a comma, quote or line break, with quotes
+/// doubled.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The store holding `uri`'s bucket, configured from the environment.
+#[cfg(feature = "object-store")]
+fn store(uri: &ObjectUri) -> Result<Box<dyn object_store::ObjectStore>, String> {
+    use object_store::aws::AmazonS3Builder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+
+    Ok(match uri.provider {
+        Provider::S3 => Box::new(
+            AmazonS3Builder::from_env()
+                .with_bucket_name(&uri.bucket)
+                .build()
+                .map_err(|e| e.to_string())?,
+        ),
+        Provider::Gcs => Box::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_bucket_name(&uri.bucket)
+                .build()
+                .map_err(|e| e.to_string())?,
+        ),
+    })
+}
+
+/// Upload `rows` to `target`; returns the bytes written.
+#[cfg(feature = "object-store")]
+pub async fn upload(target: &ObjectUri, columns: &[String], rows: &[Value]) -> Result<u64, String> {
+    write_object(store(target)?.as_ref(), target, columns, rows).await
+}
+
+#[cfg(not(feature = "object-store"))]
+pub async fn upload(_: &ObjectUri, _: &[String], _: &[Value]) -> Result<u64, String> {
+    Err("object storage requires a build with the object-store feature".to_string())
+}
+
+/// The bytes of the object at `uri`, as stored (still gzipped).
+#[cfg(feature = "object-store")]
+pub async fn download(uri: &ObjectUri) -> Result<Vec<u8>, String> {
+    let path = object_store::path::Path::from(uri.key.as_str());
+    let object = store(uri)?.get(&path).await.map_err(|e| e.to_string())?;
+    let bytes = object.bytes().await.map_err(|e| e.to_string())?;
+    Ok(bytes.to_vec())
+}
+
+#[cfg(not(feature = "object-store"))]
+pub async fn download(_: &ObjectUri) -> Result<Vec<u8>, String> {
+    Err("object storage requires a build with the object-store feature".to_string())
+}
+
+/// Remove the object at `uri`.
+#[cfg(feature = "object-store")]
+pub async fn delete(uri: &ObjectUri) -> Result<(), String> {
+    let path = object_store::path::Path::from(uri.key.as_str());
+    store(uri)?.delete(&path).await.map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "object-store"))]
+pub async fn delete(_: &ObjectUri) -> Result<(), String> {
+    Err("object storage requires a build with the object-store feature".to_string())
+}
+
+/// Write `rows` as `target.key` of `store`, aborting the upload on failure.
+#[cfg(feature = "object-store")]
+pub async fn write_object(
+    store: &dyn object_store::ObjectStore,
+    target: &ObjectUri,
+    columns: &[String],
+    rows: &[Value],
+) -> Result<u64, String> {
+    use flate2::write::GzEncoder;
+    use object_store::WriteMultipart;
+    use std::io::Write;
+
+    let path = object_store::path::Path::from(target.key.as_str());
+    let upload = store
+        .put_multipart(&path)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut writer = WriteMultipart::new(upload);
+    let mut gzip = target
+        .gzip
+        .then(|| GzEncoder::new(Vec::new(), flate2::Compression::default()));
+    let mut written = 0u64;
+    let mut chunks: Vec<&[Value]> = rows.chunks(CHUNK_ROWS).collect();
+    // An empty result still gets its header.
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let mut encoded = encode_rows(chunk, columns, target.format, i == 0);
+        if let Some(enc) = gzip.as_mut() {
+            if let Err(e) = enc.write_all(&encoded) {
+                return abort(writer, format!("gzip failed: {e}")).await;
+            }
+            encoded = std::mem::take(enc.get_mut());
+        }
+        if let Err(e) = put(&mut writer, &encoded, &mut written).await {
+            return abort(writer, e).await;
+        }
+    }
+    if let Some(enc) = gzip {
+        let tail = match enc.finish() {
+            Ok(tail) => tail,
+            Err(e) => return abort(writer, format!("gzip failed: {e}")).await,
+        };
+        if let Err(e) = put(&mut writer, &tail, &mut written).await {
+            return abort(writer, e).await;
+        }
+    }
+    writer.finish().await.map_err(|e| e.to_string())?;
+    Ok(written)
+}
+
+#[cfg(feature = "object-store")]
+async fn put(
+    writer: &mut object_store::WriteMultipart,
+    bytes: &[u8],
+    written: &mut u64,
+) -> Result<(), String> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    writer
+        .wait_for_capacity(PARTS_IN_FLIGHT)
+        .await
+        .map_err(|e| e.to_string())?;
+    writer.write(bytes);
+    *written += bytes.len() as u64;
+    Ok(())
+}
+
+#[cfg(feature = "object-store")]
+async fn abort(writer: object_store::WriteMultipart, error: String) -> Result<u64, String> {
+    let _ = writer.abort().await;
+    Err(error)
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_export.rs"]
+mod tests;
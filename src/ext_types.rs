@@ -0,0 +1,139 @@
+//! Parameter binding for extension types.
+//!
+//! Extension types have no fixed OID, so each session looks them up by name
+//! when its pool is created. `build_params` consults the resulting map before
+//! falling back to text. To support another type, add a variant, list it in
+//! [`EXTENSION_TYPES`] and teach [`ExtType::bind`] its wire format.
+
+use bytes::{BufMut, BytesMut};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio_postgres::types::{IsNull, ToSql, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtType {
+    /// pgvector `vector`: JSON number array or `"[1,2,3]"` text.
+    Vector,
+    /// `hstore`: JSON object of string (or scalar) values, `null` allowed.
+    Hstore,
+    /// `citext`: any JSON scalar, bound as text.
+    Citext,
+}
+
+/// Type names recognised when a session connects.
+pub const EXTENSION_TYPES: &[(&str, ExtType)] = &[
+    ("vector", ExtType::Vector),
+    ("hstore", ExtType::Hstore),
+    ("citext", ExtType::Citext),
+];
+
+/// `$1` is the list of type names; rows are `(oid, typname)`.
+pub const EXTENSION_TYPES_SQL: &str =
+    "select oid, typname::text from pg_type where typname::text = any($1::text[])";
+
+/// OID → binding for the extension types installed in one database.
+pub type ExtTypeMap = HashMap<u32, ExtType>;
+
+pub fn ext_type_by_name(name: &str) -> Option<ExtType> {
+    EXTENSION_TYPES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, t)| *t)
+}
+
+/// A parameter value already converted to an extension type's encoding.
+#[derive(Debug)]
+pub enum ExtParam {
+    Vector(PgVector),
+    Hstore(HashMap<String, Option<String>>),
+    Text(String),
+}
+
+impl ExtType {
+    /// Convert a JSON param for `$pos`; errors are user-facing messages.
+    pub fn bind(self, v: &Value, pos: usize) -> Result<ExtParam, String> {
+        match self {
+            ExtType::Vector => PgVector::from_json(v)
+                .map(ExtParam::Vector)
+                .map_err(|e| format!("param ${pos} {e}")),
+            ExtType::Hstore => {
+                let Value::Object(map) = v else {
+                    return Err(format!("param ${pos} must be an object for hstore"));
+                };
+                Ok(ExtParam::Hstore(
+                    map.iter()
+                        .map(|(k, v)| (k.clone(), scalar_text(v)))
+                        .collect(),
+                ))
+            }
+            ExtType::Citext => scalar_text(v)
+                .map(ExtParam::Text)
+                .ok_or_else(|| format!("param ${pos} must not be null")),
+        }
+    }
+}
+
+fn scalar_text(v: &Value) -> Option<String> {
+    match v {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// pgvector `vector` in binary wire format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgVector(pub Vec<f32>);
+
+impl PgVector {
+    pub fn from_json(v: &Value) -> Result<Self, String> {
+        let parsed: Value;
+        let items = match v {
+            Value::Array(items) => items,
+            Value::String(s) => {
+                parsed = serde_json::from_str(s)
+                    .map_err(|_| "cannot parse as vector (expected \"[1,2,3]\")".to_string())?;
+                parsed
+                    .as_array()
+                    .ok_or("cannot parse as vector (expected \"[1,2,3]\")")?
+            }
+            _ => return Err("must be an array of numbers for vector".to_string()),
+        };
+        items
+            .iter()
+            .map(|x| {
+                x.as_f64()
+                    .map(|f| f as f32)
+                    .filter(|f| f.is_finite())
+                    .ok_or_else(|| "must contain only finite numbers for vector".to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(PgVector)
+    }
+}
+
+impl ToSql for PgVector {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        let dim = i16::try_from(self.0.len()).map_err(|_| "vector has too many dimensions")?;
+        out.put_i16(dim);
+        out.put_i16(0);
+        for f in &self.0 {
+            out.put_f32(*f);
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "vector"
+    }
+
+    tokio_postgres::types::to_sql_checked!();
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_ext_types.rs"]
+mod tests;
@@ -0,0 +1,271 @@
+//! Golden result comparison for `psql_assert_snapshot`.
+//!
+//! A golden result is a file in the stored result format: a header line with
+//! the columns, then one row per line. It is either a path of its own or the
+//! handle of a result saved under `results_dir`. Rows are matched by
+//! `key_columns` when given, by position when `ordered`, and otherwise as a
+//! multiset, where a changed row reads as one removed plus one added. Each
+//! added, removed or changed row counts once against `max_row_changes`.
+
+use crate::diff::row_key;
+use crate::results;
+pub use crate::results::Page;
+use crate::types::ColumnInfo;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Rows listed per kind of change; the counts cover the rest.
+pub const MAX_LISTED_ROWS: usize = 20;
+
+/// How far the actual result may drift from the golden one.
+#[derive(Debug, Clone, Default)]
+pub struct Tolerances {
+    /// Columns left out of the comparison entirely.
+    pub ignore_columns: Vec<String>,
+    /// Largest absolute difference accepted per numeric column.
+    pub column_tolerances: HashMap<String, f64>,
+    pub key_columns: Vec<String>,
+    pub ordered: bool,
+    pub max_row_changes: usize,
+}
+
+/// A row present on both sides whose values differ.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RowChange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    /// `{"column": {"golden": ..., "actual": ...}}` for each differing cell.
+    pub cells: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotDiff {
+    pub passed: bool,
+    pub golden_rows: usize,
+    pub actual_rows: usize,
+    pub columns_added: Vec<String>,
+    pub columns_removed: Vec<String>,
+    pub rows_added: usize,
+    pub rows_removed: usize,
+    pub rows_changed: usize,
+    pub added: Vec<Value>,
+    pub removed: Vec<Value>,
+    pub changed: Vec<RowChange>,
+}
+
+/// The file behind stored result `handle`.
+pub fn handle_path(results_dir: &str, handle: &str) -> Result<PathBuf, String> {
+    if !results::valid_handle(handle) {
+        return Err(format!("invalid result handle: {handle}"));
+    }
+    Ok(results::result_path(Path::new(results_dir), handle))
+}
+
+pub fn load(path: &Path) -> std::io::Result<Page> {
+    results::read_file(path)
+}
+
+pub fn save(path: &Path, columns: &[ColumnInfo], rows: &[Value]) -> std::io::Result<usize> {
+    results::save_file(path, columns, rows)
+}
+
+/// Compare `actual` with `golden` under `tolerances`. Column names are only
+/// compared when both sides have rows, since an empty result names none.
+pub fn compare(golden: &Page, actual: &Page, tolerances: &Tolerances) -> SnapshotDiff {
+    let names = |page: &Page| -> Vec<String> {
+        page.columns
+            .iter()
+            .map(|c| c.name.clone())
+            .filter(|name| !tolerances.ignore_columns.contains(name))
+            .collect()
+    };
+    let (mut columns_added, mut columns_removed) = (vec![], vec![]);
+    if !golden.rows.is_empty() && !actual.rows.is_empty() {
+        let (golden_names, actual_names) = (names(golden), names(actual));
+        columns_added = actual_names
+            .iter()
+            .filter(|n| !golden_names.contains(n))
+            .cloned()
+            .collect();
+        columns_removed = golden_names
+            .iter()
+            .filter(|n| !actual_names.contains(n))
+            .cloned()
+            .collect();
+    }
+
+    let project = |rows: &[Value]| -> Vec<Value> {
+        rows.iter()
+            .map(|row| match row {
+                Value::Object(fields) => Value::Object(
+                    fields
+                        .iter()
+                        .filter(|(k, _)| !tolerances.ignore_columns.contains(k))
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                ),
+                other => other.clone(),
+            })
+            .collect()
+    };
+    let (old, new) = (project(&golden.rows), project(&actual.rows));
+    let (added, removed, changed) = if !tolerances.key_columns.is_empty() {
+        match_by_key(&old, &new, tolerances)
+    } else if tolerances.ordered {
+        match_by_position(&old, &new, tolerances)
+    } else {
+        match_as_multiset(&old, &new, tolerances)
+    };
+
+    let row_changes = added.len() + removed.len() + changed.len();
+    SnapshotDiff {
+        passed: columns_added.is_empty()
+            && columns_removed.is_empty()
+            && row_changes <= tolerances.max_row_changes,
+        golden_rows: golden.rows.len(),
+        actual_rows: actual.rows.len(),
+        columns_added,
+        columns_removed,
+        rows_added: added.len(),
+        rows_removed: removed.len(),
+        rows_changed: changed.len(),
+        added: added.into_iter().take(MAX_LISTED_ROWS).collect(),
+        removed: removed.into_iter().take(MAX_LISTED_ROWS).collect(),
+        changed: changed.into_iter().take(MAX_LISTED_ROWS).collect(),
+    }
+}
+
+type Matched = (Vec<Value>, Vec<Value>, Vec<RowChange>);
+
+fn match_by_key(old: &[Value], new: &[Value], tolerances: &Tolerances) -> Matched {
+    let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, row) in old.iter().enumerate().rev() {
+        let key = row_key(row, &tolerances.key_columns).to_string();
+        by_key.entry(key).or_default().push(i);
+    }
+    let mut matched = vec![false; old.len()];
+    let (mut added, mut changed) = (vec![], vec![]);
+    for row in new {
+        let key = row_key(row, &tolerances.key_columns);
+        match by_key.get_mut(&key.to_string()).and_then(Vec::pop) {
+            Some(i) => {
+                matched[i] = true;
+                let cells = cell_changes(&old[i], row, tolerances);
+                if !cells.is_empty() {
+                    changed.push(RowChange {
+                        key: Some(key),
+                        index: None,
+                        cells,
+                    });
+                }
+            }
+            None => added.push(row.clone()),
+        }
+    }
+    let removed = unmatched(old, &matched);
+    (added, removed, changed)
+}
+
+fn match_by_position(old: &[Value], new: &[Value], tolerances: &Tolerances) -> Matched {
+    let changed = old
+        .iter()
+        .zip(new)
+        .enumerate()
+        .filter_map(|(index, (golden, actual))| {
+            let cells = cell_changes(golden, actual, tolerances);
+            (!cells.is_empty()).then_some(RowChange {
+                key: None,
+                index: Some(index),
+                cells,
+            })
+        })
+        .collect();
+    let added = new.iter().skip(old.len()).cloned().collect();
+    let removed = old.iter().skip(new.len()).cloned().collect();
+    (added, removed, changed)
+}
+
+/// Exact matches first, then rows equal within the tolerances.
+fn match_as_multiset(old: &[Value], new: &[Value], tolerances: &Tolerances) -> Matched {
+    let mut exact: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, row) in old.iter().enumerate().rev() {
+        exact.entry(row.to_string()).or_default().push(i);
+    }
+    let mut matched = vec![false; old.len()];
+    let mut leftover = vec![];
+    for row in new {
+        match exact.get_mut(&row.to_string()).and_then(Vec::pop) {
+            Some(i) => matched[i] = true,
+            None => leftover.push(row),
+        }
+    }
+    let mut added = vec![];
+    for row in leftover {
+        let close = (0..old.len())
+            .find(|&i| !matched[i] && cell_changes(&old[i], row, tolerances).is_empty());
+        match close {
+            Some(i) => matched[i] = true,
+            None => added.push(row.clone()),
+        }
+    }
+    let removed = unmatched(old, &matched);
+    (added, removed, vec![])
+}
+
+fn unmatched(rows: &[Value], matched: &[bool]) -> Vec<Value> {
+    rows.iter()
+        .zip(matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(row, _)| row.clone())
+        .collect()
+}
+
+/// Cells of two rows that differ beyond their column's tolerance.
+fn cell_changes(golden: &Value, actual: &Value, tolerances: &Tolerances) -> Map<String, Value> {
+    let empty = Map::new();
+    let golden = golden.as_object().unwrap_or(&empty);
+    let actual = actual.as_object().unwrap_or(&empty);
+    let mut cells = Map::new();
+    for column in golden
+        .keys()
+        .chain(actual.keys().filter(|k| !golden.contains_key(*k)))
+    {
+        let (a, b) = (
+            golden.get(column).unwrap_or(&Value::Null),
+            actual.get(column).unwrap_or(&Value::Null),
+        );
+        let tolerance = tolerances.column_tolerances.get(column).copied();
+        if !within(a, b, tolerance) {
+            cells.insert(column.clone(), json!({"golden": a, "actual": b}));
+        }
+    }
+    cells
+}
+
+/// Equal, or both numeric and no further apart than `tolerance`. Numbers
+/// rendered as strings, as `numeric` values are, count as numeric.
+fn within(a: &Value, b: &Value, tolerance: Option<f64>) -> bool {
+    if a == b {
+        return true;
+    }
+    let number = |v: &Value| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    };
+    match (tolerance, number(a), number(b)) {
+        // Leave room for rounding, so 3.02 is within 0.02 of 3.0.
+        (Some(tolerance), Some(x), Some(y)) => {
+            (x - y).abs() - tolerance <= 1e-9 * x.abs().max(y.abs()).max(1.0)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_golden.rs"]
+mod tests;
@@ -1,31 +1,133 @@
+use crate::ack::Acks;
+use crate::activity;
+use crate::anonymize;
+use crate::approval::{self, Approvals, PendingApproval};
+use crate::audit;
+use crate::budget::Budgets;
+use crate::cache::{self, ResultCache};
+use crate::checksum::{RowHasher, Verifier};
+use crate::compress;
 use crate::conn::resolve_session_name;
-use crate::db::{DbExecutor, ExecError, ExecOutcome, PostgresExecutor};
+use crate::contract;
+use crate::db::{CopyReadback, DbExecutor, ExecError, ExecOutcome, PostgresExecutor};
+use crate::deadletter::{self, DeadRow};
+use crate::diff::{self, Baselines};
+use crate::elevation::{Elevation, Elevations};
+use crate::export::{self, ObjectUri};
+use crate::history::{self, History, HistoryEntry};
+use crate::import::{self, ImportSource, Record};
+use crate::injection;
+use crate::lint;
+use crate::memory::{MemoryReservation, MemoryUsage};
+use crate::notify::{self, Notify};
+use crate::operations::{self, Controls, Cursor, Operation};
+use crate::plan::{self, PlanBaselines};
+use crate::profile;
+use crate::publish::{self, PublishUri};
+use crate::quota::{self, Quotas};
+use crate::results;
+use crate::resume::{StreamEnd, StreamLog};
+use crate::schedule;
+use crate::schema_cache::SchemaCache;
+use crate::sqlgen;
+use crate::summary;
+use crate::template;
+use crate::transcript::{ParamStyle, Statement, Transcript};
 use crate::types::*;
-use serde_json::Value;
+use crate::workspace::Workspaces;
+use crate::writer::{self, OutputSender};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
+use tokio::sync::mpsc::error::SendError;
 use tokio::sync::{mpsc, Mutex, RwLock};
 
 pub struct App {
     pub config: RwLock<RuntimeConfig>,
     pub executor: Arc<dyn DbExecutor>,
-    pub writer: mpsc::Sender<Output>,
+    pub writer: OutputSender,
     pub in_flight: Mutex<std::collections::HashMap<String, tokio::task::JoinHandle<()>>>,
     pub requests_total: std::sync::atomic::AtomicU64,
     pub start_time: Instant,
+    pub memory: Arc<MemoryUsage>,
+    pub history: Mutex<History>,
+    pub transcript: Mutex<Transcript>,
+    pub approvals: Mutex<Approvals>,
+    pub elevations: Mutex<Elevations>,
+    /// Exported snapshots still held open, by `(session, snapshot)`.
+    pub snapshots: Mutex<HashSet<(String, String)>>,
+    pub workspaces: Mutex<Workspaces>,
+    pub cache: Mutex<ResultCache>,
+    pub schema_cache: Mutex<SchemaCache>,
+    pub diff_baselines: Mutex<Baselines>,
+    pub streams: Mutex<StreamLog>,
+    pub acks: Acks,
+    /// Usage counted against `agent_quotas`.
+    pub quotas: Mutex<Quotas>,
+    /// Token buckets for `agent_budgets` and `session_budgets`.
+    pub budgets: Mutex<Budgets>,
+    /// Plans recorded by `psql_plan_check`.
+    pub plan_baselines: Mutex<PlanBaselines>,
+    /// Pause switches of running managed operations.
+    pub operations: Arc<Controls>,
 }
 
 impl App {
     pub fn new(config: RuntimeConfig, writer: mpsc::Sender<Output>) -> Self {
+        Self::with_executor(config, writer, Arc::new(PostgresExecutor::new()))
+    }
+
+    pub fn with_executor(
+        config: RuntimeConfig,
+        writer: mpsc::Sender<Output>,
+        executor: Arc<dyn DbExecutor>,
+    ) -> Self {
+        #[cfg(feature = "sqlite")]
+        let executor: Arc<dyn DbExecutor> = Arc::new(crate::sqlite::SqliteSessions::new(executor));
+        let writer = OutputSender::from(writer);
+        writer.configure(&config);
+        let approvals = Approvals::load(config.state_dir.as_deref());
         Self {
             config: RwLock::new(config),
-            executor: Arc::new(PostgresExecutor::new()),
+            executor,
             writer,
             in_flight: Mutex::new(std::collections::HashMap::new()),
             requests_total: std::sync::atomic::AtomicU64::new(0),
             start_time: Instant::now(),
+            memory: Arc::new(MemoryUsage::default()),
+            history: Mutex::new(History::default()),
+            transcript: Mutex::new(Transcript::default()),
+            approvals: Mutex::new(approvals),
+            elevations: Mutex::new(Elevations::default()),
+            snapshots: Mutex::new(HashSet::new()),
+            workspaces: Mutex::new(Workspaces::default()),
+            cache: Mutex::new(ResultCache::default()),
+            schema_cache: Mutex::new(SchemaCache::default()),
+            diff_baselines: Mutex::new(Baselines::default()),
+            streams: Mutex::new(StreamLog::default()),
+            acks: Acks::default(),
+            quotas: Mutex::new(Quotas::default()),
+            budgets: Mutex::new(Budgets::default()),
+            plan_baselines: Mutex::new(PlanBaselines::default()),
+            operations: Arc::new(Controls::default()),
         }
     }
+
+    /// Apply a config patch and return the resulting config.
+    pub async fn apply_config(&self, patch: ConfigPatch) -> RuntimeConfig {
+        let cfg = {
+            let mut cfg = self.config.write().await;
+            cfg.apply_update(patch);
+            self.writer.configure(&cfg);
+            cfg.clone()
+        };
+        self.approvals
+            .lock()
+            .await
+            .set_state_dir(cfg.state_dir.as_deref());
+        cfg
+    }
 }
 
 pub async fn execute_query(
@@ -34,62 +136,203 @@ pub async fn execute_query(
     session: Option<String>,
     sql: String,
     params: Vec<Value>,
-    options: QueryOptions,
+    mut options: QueryOptions,
 ) {
+    let sql = match expand_templates(sql, &mut options) {
+        Ok(sql) => sql,
+        Err(message) => {
+            send_invalid_request(app, id.as_deref(), message, Instant::now()).await;
+            return;
+        }
+    };
+    if let Some(dest) = options.materialize_to.clone() {
+        materialize(app, id, session, sql, params, options, dest).await;
+        return;
+    }
+    if let Some(from) = options.resume_from {
+        resume_stream(app, id, from).await;
+        return;
+    }
     let start = Instant::now();
-    let cfg = app.config.read().await.clone();
-    let resolved_session = resolve_session_name(&cfg, session.as_deref());
-    let resolved_opts = cfg.resolve_options(&options);
-
-    let Some(session_cfg) = cfg.sessions.get(&resolved_session).cloned() else {
-        let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
-        let _ = app
-            .writer
-            .send(Output::Error {
-                id: id.clone(),
-                error_code: "connect_failed".to_string(),
-                error: format!("unknown session: {resolved_session}"),
-                retryable: true,
-                trace: trace.clone(),
-            })
-            .await;
-        emit_log(
-            app,
-            "query.error",
-            id.as_deref(),
-            Some(&resolved_session),
-            Some("connect_failed"),
-            None,
-            &trace,
-        )
-        .await;
+    let invalid = if options.diff_output && (options.stream_rows || options.store_result) {
+        Some("diff_output replies inline; stream_rows and store_result are not supported")
+    } else if options.export_uri.is_some()
+        && (options.stream_rows || options.store_result || options.diff_output)
+    {
+        Some("export_uri uploads the rows; stream_rows, store_result and diff_output are not supported")
+    } else if options.publish_uri.is_some()
+        && (options.stream_rows
+            || options.store_result
+            || options.diff_output
+            || options.export_uri.is_some())
+    {
+        Some("publish_uri publishes the rows; stream_rows, store_result, diff_output and export_uri are not supported")
+    } else if options.publish_uri.is_none()
+        && (options.publish_key.is_some() || options.publish_format.is_some())
+    {
+        Some("publish_key and publish_format apply to publish_uri")
+    } else if options.verify.is_some() && options.export_uri.is_none() {
+        Some("verify applies to export_uri, transfer and import")
+    } else if options.dedup && options.key_columns.as_ref().is_none_or(Vec::is_empty) {
+        Some("dedup needs key_columns")
+    } else if options.dedup && options.diff_output {
+        Some("dedup and diff_output cannot be combined")
+    } else if options.prepare_transaction.is_some() && options.cache_ttl_ms.is_some() {
+        Some("prepare_transaction always runs the statement; cache_ttl_ms is not supported")
+    } else if options.expect.as_ref().is_some_and(|e| {
+        e.min_rows
+            .zip(e.max_rows)
+            .is_some_and(|(min, max)| min > max)
+    }) {
+        Some("expect.min_rows is over expect.max_rows")
+    } else if options
+        .expect
+        .as_ref()
+        .is_some_and(|e| e.exact_columns && e.columns.is_none())
+    {
+        Some("expect.exact_columns needs expect.columns")
+    } else {
+        None
+    };
+    if let Some(message) = invalid {
+        send_invalid_request(app, id.as_deref(), message.to_string(), start).await;
+        return;
+    }
+    if let Some(Err(message)) = options.notify.as_ref().map(Notify::validate) {
+        send_invalid_request(app, id.as_deref(), message, start).await;
+        return;
+    }
+    let Some(target) =
+        resolve_target(app, id.as_deref(), session.as_deref(), &options, start).await
+    else {
         return;
     };
-
-    let result = app
-        .executor
-        .execute(
+    let Target {
+        session_name: resolved_session,
+        conn_session,
+        session_cfg,
+        opts: mut resolved_opts,
+        agent,
+    } = target;
+    resolved_opts.prepare_transaction = options.prepare_transaction.clone();
+    if options.dedup {
+        resolved_opts.dedup_baseline = Some(cache::cache_key(
             &resolved_session,
-            &session_cfg,
             &sql,
             &params,
             &resolved_opts,
-        )
-        .await;
+        ));
+    }
 
-    match result {
-        Ok(ExecOutcome::Rows(rows)) => {
-            let status = emit_rows_result(
-                app,
-                id.clone(),
-                Some(resolved_session.clone()),
-                rows,
-                start,
-                &resolved_opts,
-            )
+    if app.config.read().await.injection_warnings {
+        emit_injection_warnings(app, id.as_deref(), &resolved_session, &sql).await;
+    }
+    if !lint_passes(app, id.as_deref(), &resolved_session, &sql, start).await {
+        return;
+    }
+    if let Some(contract) = options.expect.as_ref().filter(|c| c.columns.is_some()) {
+        let described = app
+            .executor
+            .describe(&conn_session, &session_cfg, &sql, &resolved_opts)
             .await;
+        let violations = match described {
+            Ok((_, columns)) => contract::column_violations(contract, &columns),
+            Err(e) => {
+                emit_exec_error(app, id.as_deref(), &resolved_session, e, start).await;
+                return;
+            }
+        };
+        if !violations.is_empty() {
+            let error = violations.join("; ");
+            send_error(app, id.as_deref(), "contract_violation", error, start).await;
+            return;
+        }
+    }
+
+    let cache_max_entries = app.config.read().await.cache_max_entries;
+    let cache_key = resolved_opts
+        .cache_ttl_ms
+        .filter(|_| cache_max_entries > 0)
+        .filter(|_| resolved_opts.workspace.is_none() && resolved_opts.snapshot.is_none())
+        .map(|ttl| {
+            let key = cache::cache_key(&resolved_session, &sql, &params, &resolved_opts);
+            (key, std::time::Duration::from_millis(ttl))
+        });
+    let hit = match &cache_key {
+        Some((key, ttl)) => app.cache.lock().await.get(key, *ttl),
+        None => None,
+    };
+    let cache_age = hit.as_ref().map(|(_, age)| *age);
+    let (result, timing) = match hit {
+        Some((rows, _)) => (Ok(ExecOutcome::Rows(rows)), Timing::default()),
+        None => {
+            app.executor
+                .execute_timed(&conn_session, &session_cfg, &sql, &params, &resolved_opts)
+                .await
+        }
+    };
+    // Fetched rows count against memory_budget_bytes from here until the
+    // query returns, not only once its reply is queued, so a large result
+    // still being redacted, summarized or streamed holds back new queries.
+    let _fetched = match &result {
+        Ok(ExecOutcome::Rows(rows)) => app.memory.reserve(rows_bytes(rows)),
+        _ => MemoryReservation::default(),
+    };
+    if let (Some((key, ttl)), Ok(ExecOutcome::Rows(rows)), None) = (cache_key, &result, cache_age) {
+        app.cache
+            .lock()
+            .await
+            .insert(key, &resolved_session, rows.clone(), ttl, cache_max_entries);
+    }
+
+    // Only statements that took effect (or failed in the database) go to
+    // the transcript; cache hits ran nothing.
+    let executed = cache_age.is_none() && matches!(result, Ok(_) | Err(ExecError::Sql { .. }));
+    let violation = match (&options.expect, &result) {
+        (Some(contract), Ok(ExecOutcome::Rows(rows))) => {
+            contract::row_violation(contract, rows.len())
+        }
+        (Some(contract), Ok(ExecOutcome::Command { affected })) => {
+            contract::row_violation(contract, *affected)
+        }
+        _ => None,
+    };
+    let (outcome, error_code, row_count) = match result {
+        Ok(_) if violation.is_some() => {
+            let error = violation.unwrap_or_default();
+            send_error(app, id.as_deref(), "contract_violation", error, start).await;
+            ("error", Some("contract_violation".to_string()), None)
+        }
+        Ok(ExecOutcome::Rows(rows)) => {
+            let status = if options.diff_output {
+                let key = cache::cache_key(&resolved_session, &sql, &params, &resolved_opts);
+                emit_rows_diff(
+                    app,
+                    id.clone(),
+                    Some(resolved_session.clone()),
+                    key,
+                    rows,
+                    start,
+                    &resolved_opts,
+                    timing,
+                )
+                .await
+            } else {
+                emit_rows_result(
+                    app,
+                    id.clone(),
+                    Some(resolved_session.clone()),
+                    rows,
+                    start,
+                    &resolved_opts,
+                    cache_age,
+                    timing,
+                )
+                .await
+            };
             match status {
                 RowEmitStatus::Sent { trace } => {
+                    charge_usage(app, agent.as_deref(), &resolved_session, &trace).await;
                     emit_log(
                         app,
                         "query.result",
@@ -100,178 +343,4361 @@ pub async fn execute_query(
                         &trace,
                     )
                     .await;
+                    ("result", None, trace.row_count)
                 }
-                RowEmitStatus::TooLarge { trace } => {
+                RowEmitStatus::Failed { trace, error_code } => {
                     emit_log(
                         app,
                         "query.error",
                         id.as_deref(),
                         Some(&resolved_session),
-                        Some("result_too_large"),
+                        Some(error_code),
                         None,
                         &trace,
                     )
                     .await;
+                    ("error", Some(error_code.to_string()), trace.row_count)
                 }
             }
         }
         Ok(ExecOutcome::Command { affected }) => {
-            let command_tag = format!("EXECUTE {affected}");
-            let trace = Trace {
-                duration_ms: start.elapsed().as_millis() as u64,
-                row_count: Some(0),
-                payload_bytes: Some(0),
-            };
-            let _ = app
-                .writer
-                .send(Output::Result {
-                    id: id.clone(),
-                    session: Some(resolved_session.clone()),
-                    command_tag: command_tag.clone(),
-                    columns: vec![],
-                    rows: vec![],
-                    row_count: 0,
-                    trace: trace.clone(),
-                })
-                .await;
-            emit_log(
+            emit_command_result(
                 app,
-                "query.result",
                 id.as_deref(),
-                Some(&resolved_session),
-                None,
-                Some("EXECUTE"),
-                &trace,
+                &resolved_session,
+                format!("EXECUTE {affected}"),
+                "EXECUTE",
+                start,
+                timing,
             )
             .await;
+            ("result", None, Some(0))
         }
-        Err(ExecError::Connect(message)) => {
-            let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
-            let _ = app
-                .writer
-                .send(Output::Error {
-                    id: id.clone(),
-                    error_code: "connect_failed".to_string(),
-                    error: message,
-                    retryable: true,
-                    trace: trace.clone(),
-                })
-                .await;
-            emit_log(
-                app,
-                "query.error",
-                id.as_deref(),
-                Some(&resolved_session),
-                Some("connect_failed"),
-                None,
-                &trace,
-            )
-            .await;
+        Err(ExecError::ApprovalRequired(reason)) => {
+            let pending = PendingApproval {
+                session: resolved_session.clone(),
+                sql: sql.clone(),
+                params: params.clone(),
+                options: options.clone(),
+                reason,
+                expires_at: Instant::now(),
+            };
+            park_for_approval(app, id.as_deref(), pending, start).await;
+            ("approval_required", None, None)
         }
-        Err(ExecError::InvalidParams(message)) => {
-            let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
-            let _ = app
-                .writer
-                .send(Output::Error {
-                    id: id.clone(),
-                    error_code: "invalid_params".to_string(),
-                    error: message,
-                    retryable: false,
-                    trace: trace.clone(),
-                })
-                .await;
-            emit_log(
-                app,
-                "query.error",
-                id.as_deref(),
-                Some(&resolved_session),
-                Some("invalid_params"),
-                None,
-                &trace,
-            )
-            .await;
+        Err(err) => {
+            let (outcome, code) = match &err {
+                ExecError::Sql { sqlstate, .. } => ("sql_error", sqlstate.clone()),
+                other => ("error", exec_error_code(other).to_string()),
+            };
+            emit_exec_error(app, id.as_deref(), &resolved_session, err, start).await;
+            (outcome, Some(code), None)
         }
-        Err(ExecError::Sql {
-            sqlstate,
-            message,
-            detail,
-            hint,
-            position,
-        }) => {
-            let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
-            let _ = app
-                .writer
-                .send(Output::SqlError {
-                    id: id.clone(),
-                    session: Some(resolved_session.clone()),
-                    sqlstate: sqlstate.clone(),
-                    message,
-                    detail,
-                    hint,
-                    position,
-                    trace: trace.clone(),
-                })
-                .await;
-            emit_log(
-                app,
-                "query.sql_error",
-                id.as_deref(),
-                Some(&resolved_session),
-                Some(&sqlstate),
-                None,
-                &trace,
+    };
+    let duration_ms = start.elapsed().as_millis() as u64;
+    let slow_query_ms = app.config.read().await.slow_query_ms;
+    if executed && slow_query_ms > 0 && duration_ms >= slow_query_ms {
+        let statement = SlowStatement {
+            id: id.as_deref(),
+            session: &resolved_session,
+            conn_session: &conn_session,
+            session_cfg: &session_cfg,
+            sql: &sql,
+            params: &params,
+            opts: &resolved_opts,
+        };
+        let mut trace = Trace::only_duration(duration_ms);
+        trace.row_count = row_count;
+        trace.timing = timing;
+        log_slow_query(app, statement, outcome, error_code.as_deref(), trace).await;
+    }
+    let notification = options
+        .notify
+        .clone()
+        .filter(|n| outcome != "approval_required" && duration_ms >= n.after_ms)
+        .map(|n| {
+            let finished = notify::Finished {
+                id: id.as_deref(),
+                session: &resolved_session,
+                sql: &sql,
+                outcome,
+                error_code: error_code.as_deref(),
+                row_count,
+                duration_ms,
+            };
+            (
+                n,
+                notify::record(&finished),
+                id.clone(),
+                resolved_session.clone(),
             )
-            .await;
+        });
+
+    let (capacity, transcript_cap) = {
+        let cfg = app.config.read().await;
+        (cfg.history_size, cfg.transcript_max_statements)
+    };
+    if executed && transcript_cap > 0 {
+        let statement = Statement {
+            session: resolved_session.clone(),
+            sql: sql.clone(),
+            params: params.clone(),
+            sqlstate: (outcome == "sql_error").then(|| error_code.clone().unwrap_or_default()),
+        };
+        app.transcript
+            .lock()
+            .await
+            .record(statement, transcript_cap);
+    }
+    if capacity > 0 {
+        let entry = HistoryEntry {
+            seq: 0,
+            id,
+            session: resolved_session,
+            params_count: params.len(),
+            params_fingerprint: history::params_fingerprint(&params),
+            sql,
+            outcome: outcome.to_string(),
+            error_code,
+            row_count,
+            duration_ms: start.elapsed().as_millis() as u64,
+            started_at_ms: unix_millis().saturating_sub(start.elapsed().as_millis() as u64),
+            params,
+            options,
+        };
+        app.history.lock().await.record(entry, capacity);
+    }
+    if let Some((notify, record, id, session)) = notification {
+        send_notification(app, &notify, &record, id.as_deref(), &session).await;
+    }
+}
+
+/// A statement that ran past `slow_query_ms`, and where to explain it.
+struct SlowStatement<'a> {
+    id: Option<&'a str>,
+    session: &'a str,
+    conn_session: &'a str,
+    session_cfg: &'a SessionConfig,
+    sql: &'a str,
+    params: &'a [Value],
+    opts: &'a ResolvedOptions,
+}
+
+/// Log `query.slow` with what it takes to find the statement again: its
+/// fingerprint, the types of its params (or, with `slow_query_params:
+/// "values"`, the params themselves) and a hash of the plan it gets now.
+/// The plan comes from a plain `EXPLAIN`, which does not run it.
+async fn log_slow_query(
+    app: &Arc<App>,
+    statement: SlowStatement<'_>,
+    outcome: &str,
+    error_code: Option<&str>,
+    trace: Trace,
+) {
+    let (filters, show_values) = {
+        let cfg = app.config.read().await;
+        (
+            cfg.log.clone(),
+            cfg.slow_query_params == SlowQueryParams::Values,
+        )
+    };
+    if !log_enabled(&filters, "query.slow") {
+        return;
+    }
+    let explain_opts = ResolvedOptions {
+        read_only: true,
+        default_limit: None,
+        approval_row_threshold: None,
+        autocommit: false,
+        server_timing: false,
+        dedup_baseline: None,
+        prepare_transaction: None,
+        ..statement.opts.clone()
+    };
+    let explain = format!(
+        "explain (format json) {}",
+        statement.sql.trim().trim_end_matches(';')
+    );
+    let explained = app
+        .executor
+        .execute(
+            statement.conn_session,
+            statement.session_cfg,
+            &explain,
+            statement.params,
+            &explain_opts,
+        )
+        .await;
+    let plan_hash = match explained {
+        Ok(ExecOutcome::Rows(rows)) => rows
+            .first()
+            .and_then(plan::snapshot)
+            .map(|snapshot| plan::plan_hash(&snapshot)),
+        _ => None,
+    };
+    let slow_query = SlowQuery {
+        sql: statement.sql.to_string(),
+        fingerprint: plan::sql_fingerprint(statement.session, statement.sql),
+        param_types: history::param_types(statement.params),
+        params: show_values.then(|| statement.params.to_vec()),
+        plan_hash,
+    };
+    let _ = app
+        .writer
+        .send(Output::Log {
+            event: "query.slow".to_string(),
+            request_id: statement.id.map(std::string::ToString::to_string),
+            session: Some(statement.session.to_string()),
+            error_code: error_code.map(std::string::ToString::to_string),
+            command_tag: None,
+            warning: (outcome != "result").then(|| format!("ended in {outcome}")),
+            version: None,
+            argv: None,
+            config: None,
+            args: None,
+            env: None,
+            statement: Some(Box::new(slow_query)),
+            trace,
+        })
+        .await;
+}
+
+/// Deliver a `notify` record; the query already replied, so a failure is
+/// only logged as `notify.failed`.
+async fn send_notification(
+    app: &Arc<App>,
+    notify: &Notify,
+    record: &Value,
+    id: Option<&str>,
+    session: &str,
+) {
+    let start = Instant::now();
+    let Err(message) = notify::send(notify, record).await else {
+        return;
+    };
+    if !log_enabled(&app.config.read().await.log, "notify.failed") {
+        return;
+    }
+    let _ = app
+        .writer
+        .send(Output::Log {
+            event: "notify.failed".to_string(),
+            request_id: id.map(std::string::ToString::to_string),
+            session: Some(session.to_string()),
+            error_code: Some("notify_failed".to_string()),
+            command_tag: None,
+            warning: Some(message),
+            version: None,
+            argv: None,
+            config: None,
+            args: None,
+            env: None,
+            statement: None,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+/// Roll-back already happened in the executor; keep the request under a new
+/// token and tell the caller how to release it.
+async fn park_for_approval(
+    app: &Arc<App>,
+    id: Option<&str>,
+    mut pending: PendingApproval,
+    start: Instant,
+) {
+    let (ttl_ms, agent) = {
+        let cfg = app.config.read().await;
+        (cfg.approval_ttl_ms, request_agent(&cfg, &pending.options))
+    };
+    pending.expires_at = approval::expires_at(ttl_ms);
+    let session = pending.session.clone();
+    let reason = pending.reason.clone();
+    let audit_record = json!({
+        "request_id": id,
+        "session": session,
+        "agent": agent,
+        "sql": pending.sql,
+        "reason": reason,
+    });
+    let token = app.approvals.lock().await.park(pending);
+    if let Some(path) = app.config.read().await.audit_log.clone() {
+        let mut record = audit_record;
+        record["token"] = Value::String(token.clone());
+        // Parking changes nothing, so an unwritable audit file only blocks
+        // the later `approve`.
+        let _ = audit::append(&path, "approval.required", record);
+    }
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::ApprovalRequired {
+            id: id.map(std::string::ToString::to_string),
+            session: session.clone(),
+            token,
+            reason,
+            expires_in_ms: ttl_ms,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "approval.required",
+        id,
+        Some(&session),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Run a parked statement without the approval check. With
+/// `approver_secret` configured the `approve` must present it; with
+/// `approver_sessions` configured the secret is required and the approval
+/// must also come from one of those sessions, connected as a different
+/// database user than the requester. The decision is written to
+/// `audit_log` first; events carry the `approve` request's `id`.
+pub async fn approve(
+    app: &Arc<App>,
+    id: String,
+    token: String,
+    approver: Option<String>,
+    secret: Option<String>,
+) {
+    let start = Instant::now();
+    let looked_up = app.approvals.lock().await.get(&token);
+    let pending = match looked_up {
+        Ok(p) => p,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
         }
-        Err(ExecError::Internal(message)) => {
-            let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
-            let _ = app
-                .writer
-                .send(Output::Error {
-                    id: id.clone(),
-                    error_code: "invalid_request".to_string(),
-                    error: message,
-                    retryable: false,
-                    trace: trace.clone(),
-                })
-                .await;
-            emit_log(
+    };
+    let (approver_sessions, approver_secret, audit_log, agent) = {
+        let cfg = app.config.read().await;
+        let agent = request_agent(&cfg, &pending.options);
+        (
+            cfg.approver_sessions.clone(),
+            cfg.approver_secret.clone(),
+            cfg.audit_log.clone(),
+            agent,
+        )
+    };
+    let mut record = json!({
+        "request_id": id,
+        "token": token,
+        "session": pending.session,
+        "agent": agent,
+        "sql": pending.sql,
+        "reason": pending.reason,
+    });
+    let checked = match check_approver_secret(
+        approver_secret.as_deref(),
+        secret.as_deref(),
+        !approver_sessions.is_empty(),
+    ) {
+        Err(message) => Err(message),
+        Ok(()) if approver_sessions.is_empty() => Ok(Value::Null),
+        Ok(()) => {
+            check_dual_control(
                 app,
-                "query.error",
-                id.as_deref(),
-                Some(&resolved_session),
-                Some("invalid_request"),
-                None,
-                &trace,
+                &pending.session,
+                approver.as_deref(),
+                &approver_sessions,
             )
-            .await;
+            .await
+        }
+    };
+    match checked {
+        Ok(Value::Object(principals)) => {
+            if let Value::Object(map) = &mut record {
+                map.extend(principals);
+            }
+        }
+        Ok(_) => {}
+        Err(message) => {
+            if let Some(path) = &audit_log {
+                record["error"] = Value::String(message.clone());
+                record["approver_session"] = json!(approver);
+                let _ = audit::append(path, "approval.denied", record);
+            }
+            send_error(app, Some(&id), "approval_denied", message, start).await;
+            return;
         }
     }
+    if let Some(path) = &audit_log {
+        if let Err(e) = audit::append(path, "approval.granted", record) {
+            let message = format!("cannot write audit_log {path}: {e}");
+            send_error(app, Some(&id), "audit_failed", message, start).await;
+            return;
+        }
+    }
+    // A concurrent `approve` may have won the token while we checked.
+    let taken = app.approvals.lock().await.take(&token);
+    let pending = match taken {
+        Ok(p) => p,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    emit_log(
+        app,
+        "approval.granted",
+        Some(&id),
+        Some(&pending.session),
+        None,
+        None,
+        &Trace::only_duration(start.elapsed().as_millis() as u64),
+    )
+    .await;
+    let mut options = pending.options;
+    options.approved = true;
+    execute_query(
+        app,
+        Some(id),
+        Some(pending.session),
+        pending.sql,
+        pending.params,
+        options,
+    )
+    .await;
 }
 
-#[derive(Clone)]
-enum RowEmitStatus {
-    Sent { trace: Trace },
-    TooLarge { trace: Trace },
-}
-
-async fn emit_rows_result(
+/// Run a `psql_maintenance` statement outside a transaction block. The
+/// executor's approval check needs a transaction to roll back, so with
+/// `require_approval` on, a statement that `blocks` the table (the reason
+/// why) is parked up front instead. `read_only` requests are refused, and
+/// with `audit_log` set the statement is recorded as `maintenance` before it
+/// runs.
+pub async fn run_maintenance(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    sql: String,
+    blocks: Option<String>,
+    mut options: QueryOptions,
+) {
+    let start = Instant::now();
+    options.autocommit = true;
+    let cfg = app.config.read().await.clone();
+    if options.read_only.unwrap_or(false) {
+        let message = "maintenance cannot run read_only".to_string();
+        send_invalid_request(app, id.as_deref(), message, start).await;
+        return;
+    }
+    let session_name = resolve_session_name(&cfg, session.as_deref());
+    if let (true, Some(reason)) = (cfg.require_approval && !options.approved, blocks) {
+        let pending = PendingApproval {
+            session: session_name,
+            sql,
+            params: vec![],
+            options,
+            reason,
+            expires_at: Instant::now(),
+        };
+        park_for_approval(app, id.as_deref(), pending, start).await;
+        return;
+    }
+    if let Some(path) = &cfg.audit_log {
+        let record = json!({
+            "request_id": id,
+            "session": session_name,
+            "agent": request_agent(&cfg, &options),
+            "sql": sql,
+        });
+        if let Err(e) = audit::append(path, "maintenance", record) {
+            let message = format!("cannot write audit_log {path}: {e}");
+            send_error(app, id.as_deref(), "audit_failed", message, start).await;
+            return;
+        }
+    }
+    execute_query(app, id, session, sql, vec![], options).await;
+}
+
+/// Cancel the running query of backend `pid`, or with `terminate` close its
+/// connection, for `psql_activity`. Needs `--allow-backend-signals`; with
+/// `audit_log` set the signal is recorded before it is sent. `Ok(false)`
+/// when there is no such backend.
+pub async fn signal_backend(
+    app: &Arc<App>,
+    id: Option<&str>,
+    session: Option<&str>,
+    pid: i64,
+    terminate: bool,
+    options: &QueryOptions,
+) -> Result<bool, String> {
+    let cfg = app.config.read().await.clone();
+    let action = if terminate { "terminate" } else { "cancel" };
+    if !cfg.allow_backend_signals {
+        return Err(format!(
+            "{action}_backend needs --allow-backend-signals at startup"
+        ));
+    }
+    if let Some(path) = &cfg.audit_log {
+        let record = json!({
+            "request_id": id,
+            "session": resolve_session_name(&cfg, session),
+            "agent": request_agent(&cfg, options),
+            "pid": pid,
+        });
+        audit::append(path, &format!("backend.{action}"), record)
+            .map_err(|e| format!("cannot write audit_log {path}: {e}"))?;
+    }
+    let sql = activity::signal_sql(terminate);
+    let rows = fetch_rows(app, session, sql, &[json!(pid)], options)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows
+        .first()
+        .and_then(|row| row["signalled"].as_bool())
+        .unwrap_or(false))
+}
+
+/// The approver and both database principals for the audit record, or why
+/// the approval is refused.
+/// The approval credential: `--approver-secret`, which the agent is not
+/// given. Dual control without it would rest on session names alone.
+fn check_approver_secret(
+    expected: Option<&str>,
+    given: Option<&str>,
+    dual_control: bool,
+) -> Result<(), String> {
+    match (expected, given) {
+        (None, _) if dual_control => {
+            Err("approver_sessions requires --approver-secret at startup".to_string())
+        }
+        (None, _) => Ok(()),
+        (Some(_), None) => Err("approve requires approver_secret".to_string()),
+        (Some(expected), Some(given)) if secret_matches(expected, given) => Ok(()),
+        (Some(_), Some(_)) => Err("approver_secret does not match".to_string()),
+    }
+}
+
+/// Compare without stopping at the first differing byte.
+fn secret_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+async fn check_dual_control(
+    app: &Arc<App>,
+    requester: &str,
+    approver: Option<&str>,
+    allowed: &[String],
+) -> Result<Value, String> {
+    let Some(approver) = approver else {
+        return Err(format!(
+            "approve requires session, one of approver_sessions: {}",
+            allowed.join(", ")
+        ));
+    };
+    if !allowed.iter().any(|s| s == approver) {
+        return Err(format!("session {approver} is not in approver_sessions"));
+    }
+    if approver == requester {
+        return Err(format!(
+            "approver session must differ from the requesting session {requester}"
+        ));
+    }
+    let requester_principal = session_principal(app, requester).await?;
+    let approver_principal = session_principal(app, approver).await?;
+    if requester_principal == approver_principal {
+        return Err(format!(
+            "approver session {approver} connects as {approver_principal}, the same database user as the requester"
+        ));
+    }
+    Ok(json!({
+        "requester_principal": requester_principal,
+        "approver_session": approver,
+        "approver_principal": approver_principal,
+    }))
+}
+
+async fn session_principal(app: &Arc<App>, session: &str) -> Result<String, String> {
+    let rows = fetch_rows(
+        app,
+        Some(session),
+        "select current_user::text as principal",
+        &[],
+        &QueryOptions::default(),
+    )
+    .await
+    .map_err(|e| format!("cannot identify session {session}: {e}"))?;
+    rows.first()
+        .and_then(|r| r.get("principal"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("cannot identify session {session}"))
+}
+
+/// Break-glass access: run `session` under `role` and/or over `via_session`'s
+/// connection for `duration_ms`. Requires `audit_log`; the grant and its
+/// expiry are both recorded there, and the grant is refused if it cannot be.
+pub async fn grant_elevated(
+    app: &Arc<App>,
+    id: String,
+    session: Option<String>,
+    role: Option<String>,
+    via_session: Option<String>,
+    duration_ms: u64,
+    reason: String,
+) {
+    let start = Instant::now();
+    let cfg = app.config.read().await.clone();
+    let session = resolve_session_name(&cfg, session.as_deref());
+    let invalid = if reason.trim().is_empty() {
+        Some("grant_elevated requires a reason".to_string())
+    } else if role.is_none() && via_session.is_none() {
+        Some("grant_elevated requires role or via_session".to_string())
+    } else if duration_ms == 0 || duration_ms > cfg.elevation_max_ms {
+        Some(format!(
+            "duration_ms must be between 1 and elevation_max_ms {}",
+            cfg.elevation_max_ms
+        ))
+    } else if !cfg.sessions.contains_key(&session) {
+        Some(format!("unknown session: {session}"))
+    } else {
+        via_session
+            .as_ref()
+            .filter(|v| !cfg.sessions.contains_key(*v))
+            .map(|via| format!("unknown via_session: {via}"))
+    };
+    if let Some(message) = invalid {
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    }
+    let Some(audit_log) = cfg.audit_log else {
+        let message = "grant_elevated requires audit_log to be configured".to_string();
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+
+    let record = json!({
+        "request_id": id,
+        "session": session,
+        "agent": cfg.agent_name,
+        "role": role,
+        "via_session": via_session,
+        "duration_ms": duration_ms,
+        "reason": reason,
+    });
+    if let Err(e) = audit::append(&audit_log, "elevation.granted", record) {
+        let message = format!("cannot write audit_log {audit_log}: {e}");
+        send_error(app, Some(&id), "audit_failed", message, start).await;
+        return;
+    }
+    let grant = app.elevations.lock().await.grant(
+        &session,
+        Elevation {
+            grant: 0,
+            role: role.clone(),
+            via_session: via_session.clone(),
+            reason,
+            expires_at: approval::expires_at(duration_ms),
+        },
+    );
+
+    let app2 = app.clone();
+    let expiring = session.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(duration_ms)).await;
+        let reverted = app2.elevations.lock().await.revert(&expiring, grant);
+        if let Some(elevation) = reverted {
+            let record = json!({
+                "session": expiring,
+                "role": elevation.role,
+                "via_session": elevation.via_session,
+                "reason": elevation.reason,
+            });
+            let _ = audit::append(&audit_log, "elevation.expired", record);
+            emit_log(
+                &app2,
+                "elevation.expired",
+                None,
+                Some(&expiring),
+                None,
+                None,
+                &Trace::only_duration(duration_ms),
+            )
+            .await;
+        }
+    });
+
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::Elevated {
+            id: id.clone(),
+            session: session.clone(),
+            role,
+            via_session,
+            expires_in_ms: duration_ms,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "elevation.granted",
+        Some(&id),
+        Some(&session),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Emit the newest `limit` history entries (default: all kept).
+pub async fn history_list(app: &Arc<App>, id: String, limit: Option<usize>) {
+    let entries = app.history.lock().await.recent(limit.unwrap_or(usize::MAX));
+    let _ = app.writer.send(Output::History { id, entries }).await;
+}
+
+/// Run a recorded query again, on the same session and with the same params
+/// and options. Events carry the new request `id`.
+pub async fn history_replay(app: &Arc<App>, id: String, seq: u64) {
+    let entry = app.history.lock().await.get(seq).cloned();
+    let Some(entry) = entry else {
+        send_invalid_request(
+            app,
+            Some(&id),
+            format!("no history entry with seq {seq}"),
+            Instant::now(),
+        )
+        .await;
+        return;
+    };
+    // The original deadline has most likely passed, and the snapshot ended.
+    let mut options = entry.options;
+    options.deadline = None;
+    options.snapshot = None;
+    execute_query(
+        app,
+        Some(id),
+        Some(entry.session),
+        entry.sql,
+        entry.params,
+        options,
+    )
+    .await;
+}
+
+/// Emit the recorded statements as a `.sql` script.
+pub async fn transcript_export(
+    app: &Arc<App>,
+    id: String,
+    param_style: ParamStyle,
+    session: Option<String>,
+) {
+    let transcript = app.transcript.lock().await;
+    let (script, statement_count) = transcript.render(param_style, session.as_deref());
+    let omitted = transcript.omitted();
+    drop(transcript);
+    let _ = app
+        .writer
+        .send(Output::Transcript {
+            id,
+            format: "sql".to_string(),
+            statement_count,
+            omitted,
+            script,
+        })
+        .await;
+}
+
+/// Export a snapshot of `session` for `snapshot_query`. It is held by an open
+/// transaction until `snapshot_end` or `snapshot_ttl_ms`, whichever is first.
+pub async fn snapshot_begin(app: &Arc<App>, id: String, session: Option<String>) {
+    let start = Instant::now();
+    let cfg = app.config.read().await.clone();
+    let session = resolve_session_name(&cfg, session.as_deref());
+    let Some(session_cfg) = cfg.sessions.get(&session) else {
+        let message = format!("unknown session: {session}");
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+    let snapshot = match app.executor.export_snapshot(&session, session_cfg).await {
+        Ok(s) => s,
+        Err(e) => {
+            emit_exec_error(app, Some(&id), &session, e, start).await;
+            return;
+        }
+    };
+    app.snapshots
+        .lock()
+        .await
+        .insert((session.clone(), snapshot.clone()));
+
+    let ttl_ms = cfg.snapshot_ttl_ms;
+    let app2 = app.clone();
+    let key = (session.clone(), snapshot.clone());
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(ttl_ms)).await;
+        if app2.snapshots.lock().await.remove(&key) {
+            let (session, snapshot) = key;
+            app2.executor.release_snapshot(&session, &snapshot).await;
+            emit_log(
+                &app2,
+                "snapshot.expired",
+                None,
+                Some(&session),
+                None,
+                None,
+                &Trace::only_duration(ttl_ms),
+            )
+            .await;
+        }
+    });
+
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::SnapshotStarted {
+            id: id.clone(),
+            session: session.clone(),
+            snapshot,
+            expires_in_ms: ttl_ms,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "snapshot.started",
+        Some(&id),
+        Some(&session),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Run a query in a `REPEATABLE READ` transaction that imports `snapshot`.
+pub async fn snapshot_query(
+    app: &Arc<App>,
+    id: String,
+    session: Option<String>,
+    snapshot: String,
+    sql: String,
+    params: Vec<Value>,
+    mut options: QueryOptions,
+) {
+    let session_name = {
+        let cfg = app.config.read().await;
+        resolve_session_name(&cfg, session.as_deref())
+    };
+    let key = (session_name.clone(), snapshot);
+    if !app.snapshots.lock().await.contains(&key) {
+        let message = format!("no open snapshot {} on session {session_name}", key.1);
+        send_invalid_request(app, Some(&id), message, Instant::now()).await;
+        return;
+    }
+    options.snapshot = Some(key.1);
+    execute_query(app, Some(id), Some(session_name), sql, params, options).await;
+}
+
+/// Release a snapshot opened by `snapshot_begin`.
+pub async fn snapshot_end(app: &Arc<App>, id: String, session: Option<String>, snapshot: String) {
+    let start = Instant::now();
+    let session = {
+        let cfg = app.config.read().await;
+        resolve_session_name(&cfg, session.as_deref())
+    };
+    let key = (session.clone(), snapshot.clone());
+    if !app.snapshots.lock().await.remove(&key) {
+        let message = format!("no open snapshot {snapshot} on session {session}");
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    }
+    app.executor.release_snapshot(&session, &snapshot).await;
+
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::SnapshotEnded {
+            id: id.clone(),
+            session: session.clone(),
+            snapshot,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "snapshot.ended",
+        Some(&id),
+        Some(&session),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// List the transactions prepared on `session`'s server, oldest first.
+/// Those of other databases are included but can only be finished there.
+pub async fn prepared_list(
+    app: &Arc<App>,
+    id: String,
+    session: Option<String>,
+    older_than_ms: Option<u64>,
+) {
+    let start = Instant::now();
+    let session = {
+        let cfg = app.config.read().await;
+        resolve_session_name(&cfg, session.as_deref())
+    };
+    let sql = "select gid, owner::text, database::text, prepared::text, \
+               (extract(epoch from now() - prepared) * 1000)::bigint as age_ms \
+               from pg_prepared_xacts \
+               where now() - prepared >= make_interval(secs => $1::bigint / 1000.0) \
+               order by prepared";
+    let params = [json!(older_than_ms.unwrap_or(0))];
+    let rows = match fetch_rows(app, Some(&session), sql, &params, &QueryOptions::default()).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            emit_exec_error(app, Some(&id), &session, e, start).await;
+            return;
+        }
+    };
+    let transactions = rows
+        .into_iter()
+        .filter_map(|row| serde_json::from_value(row).ok())
+        .collect();
+    let _ = app
+        .writer
+        .send(Output::PreparedTransactions {
+            id,
+            session,
+            transactions,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+/// Prepare `sql` on the session (or the workspace in `options`) and reply
+/// with its parameter and result column types; nothing is executed.
+pub async fn describe_statement(
+    app: &Arc<App>,
+    id: String,
+    session: Option<String>,
+    sql: String,
+    mut options: QueryOptions,
+) {
+    let start = Instant::now();
+    let sql = match expand_templates(sql, &mut options) {
+        Ok(sql) => sql,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let Some(target) = resolve_target(app, Some(&id), session.as_deref(), &options, start).await
+    else {
+        return;
+    };
+    let described = app
+        .executor
+        .describe(
+            &target.conn_session,
+            &target.session_cfg,
+            &sql,
+            &target.opts,
+        )
+        .await;
+    let (params, columns) = match described {
+        Ok(v) => v,
+        Err(e) => {
+            emit_exec_error(app, Some(&id), &target.session_name, e, start).await;
+            return;
+        }
+    };
+    let _ = app
+        .writer
+        .send(Output::Described {
+            id,
+            session: target.session_name,
+            params,
+            columns,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+/// `COMMIT PREPARED` or `ROLLBACK PREPARED` a transaction left by
+/// `prepare_transaction`. Runs outside a transaction block, as PostgreSQL
+/// requires, on any connection to the database it was prepared in.
+pub async fn finish_prepared(
+    app: &Arc<App>,
+    id: String,
+    session: Option<String>,
+    gid: String,
+    commit: bool,
+) {
+    let start = Instant::now();
+    let cfg = app.config.read().await.clone();
+    let session = resolve_session_name(&cfg, session.as_deref());
+    let Some(session_cfg) = cfg.sessions.get(&session) else {
+        let message = format!("unknown session: {session}");
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+    let opts = cfg.resolve_options(&QueryOptions {
+        autocommit: true,
+        ..QueryOptions::default()
+    });
+    let verb = if commit { "commit" } else { "rollback" };
+    let sql = format!("{verb} prepared '{}'", gid.replace('\'', "''"));
+    if let Err(e) = app
+        .executor
+        .execute(&session, session_cfg, &sql, &[], &opts)
+        .await
+    {
+        emit_exec_error(app, Some(&id), &session, e, start).await;
+        return;
+    }
+
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::PreparedFinished {
+            id: id.clone(),
+            session: session.clone(),
+            gid,
+            committed: commit,
+            trace: trace.clone(),
+        })
+        .await;
+    let event = if commit {
+        "prepared.committed"
+    } else {
+        "prepared.rolled_back"
+    };
+    emit_log(app, event, Some(&id), Some(&session), None, None, &trace).await;
+}
+
+/// Run `sql` on every session in `sessions` concurrently and reply with one
+/// `fanout_result`. A failure on one session is reported in its own entry and
+/// does not affect the others; request-wide problems (bad options,
+/// backpressure) fail the whole request as with `query`.
+pub async fn fanout(
+    app: &Arc<App>,
+    id: String,
+    sessions: Vec<String>,
+    sql: String,
+    params: Vec<Value>,
+    mut options: QueryOptions,
+) {
+    let start = Instant::now();
+    let sql = match expand_templates(sql, &mut options) {
+        Ok(sql) => sql,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let invalid = if sessions.is_empty() {
+        Some("fanout requires at least one session")
+    } else if options.stream_rows || options.store_result {
+        Some("fanout results are inline; stream_rows and store_result are not supported")
+    } else {
+        None
+    };
+    if let Some(message) = invalid {
+        send_invalid_request(app, Some(&id), message.to_string(), start).await;
+        return;
+    }
+
+    let configured = app.config.read().await.sessions.clone();
+    let mut results = std::collections::BTreeMap::new();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut seen = HashSet::new();
+    for session in sessions {
+        if !seen.insert(session.clone()) {
+            continue;
+        }
+        if !configured.contains_key(&session) {
+            let entry = FanoutEntry::Error {
+                error_code: "connect_failed".to_string(),
+                sqlstate: None,
+                error: format!("unknown session: {session}"),
+                duration_ms: 0,
+            };
+            results.insert(session, entry);
+            continue;
+        }
+        let Some(mut target) =
+            resolve_target(app, Some(&id), Some(&session), &options, start).await
+        else {
+            return;
+        };
+        target.opts.prepare_transaction = options.prepare_transaction.clone();
+        let (app, sql, params) = (app.clone(), sql.clone(), params.clone());
+        tasks.spawn(async move {
+            let began = Instant::now();
+            let result = app
+                .executor
+                .execute(
+                    &target.conn_session,
+                    &target.session_cfg,
+                    &sql,
+                    &params,
+                    &target.opts,
+                )
+                .await;
+            (
+                target.session_name,
+                fanout_entry(result, &target.opts, began),
+            )
+        });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((session, entry)) = joined {
+            results.insert(session, entry);
+        }
+    }
+
+    let mut payload_bytes = 0usize;
+    let mut row_count = 0usize;
+    for entry in results.values() {
+        if let FanoutEntry::Ok { rows, .. } = entry {
+            row_count += rows.len();
+            for row in rows {
+                payload_bytes += serde_json::to_vec(row).map(|b| b.len()).unwrap_or(0);
+            }
+        }
+    }
+    let error_count = results
+        .values()
+        .filter(|e| matches!(e, FanoutEntry::Error { .. }))
+        .count();
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(row_count),
+        payload_bytes: Some(payload_bytes),
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let _ = app
+        .writer
+        .send(Output::FanoutResult {
+            id: id.clone(),
+            ok_count: results.len() - error_count,
+            error_count,
+            results,
+            trace: trace.clone(),
+            memory: app.memory.reserve(payload_bytes),
+        })
+        .await;
+    emit_log(app, "query.fanout", Some(&id), None, None, None, &trace).await;
+}
+
+fn fanout_entry(
+    result: Result<ExecOutcome, ExecError>,
+    opts: &ResolvedOptions,
+    began: Instant,
+) -> FanoutEntry {
+    let duration_ms = began.elapsed().as_millis() as u64;
+    let error = |error_code: &str, sqlstate: Option<String>, error: String| FanoutEntry::Error {
+        error_code: error_code.to_string(),
+        sqlstate,
+        error,
+        duration_ms,
+    };
+    match result {
+        Ok(ExecOutcome::Command { affected }) => FanoutEntry::Ok {
+            command_tag: format!("EXECUTE {affected}"),
+            columns: vec![],
+            rows: vec![],
+            row_count: 0,
+            limited: None,
+            warning: None,
+            duration_ms,
+        },
+        Ok(ExecOutcome::Rows(rows)) => {
+            let (rows, limited, warning) = apply_default_limit(rows, opts);
+            if exceeds_inline(&rows, opts) {
+                let message =
+                    "result exceeds inline limits; add a LIMIT or narrow the query".to_string();
+                return error("result_too_large", None, message);
+            }
+            FanoutEntry::Ok {
+                command_tag: format!("ROWS {}", rows.len()),
+                columns: infer_columns(&rows),
+                row_count: rows.len(),
+                rows,
+                limited,
+                warning,
+                duration_ms,
+            }
+        }
+        Err(err) => {
+            let code = exec_error_code(&err);
+            let (sqlstate, message) = match err {
+                ExecError::Sql {
+                    sqlstate, message, ..
+                } => (Some(sqlstate), message),
+                ExecError::Connect(message)
+                | ExecError::InvalidParams(message)
+                | ExecError::Internal(message)
+                | ExecError::ApprovalRequired(message) => (None, message),
+            };
+            error(code, sqlstate, message)
+        }
+    }
+}
+
+/// Pin a connection of `session` for queries that pass `workspace` in their
+/// options. It is closed by `workspace_close` or after `workspace_idle_ms`
+/// without a query.
+pub async fn workspace_open(
+    app: &Arc<App>,
+    id: String,
+    workspace: String,
+    session: Option<String>,
+) {
+    let start = Instant::now();
+    let cfg = app.config.read().await.clone();
+    let session = resolve_session_name(&cfg, session.as_deref());
+    let invalid = {
+        let workspaces = app.workspaces.lock().await;
+        if workspace.is_empty() {
+            Some("workspace must not be empty".to_string())
+        } else if workspace.starts_with(PINNED_WORKSPACE_PREFIX) {
+            Some(format!(
+                "workspace ids starting with {PINNED_WORKSPACE_PREFIX} are reserved for pinned sessions"
+            ))
+        } else if workspaces.contains(&workspace) {
+            Some(format!("workspace {workspace} is already open"))
+        } else if workspaces.len() >= cfg.max_workspaces {
+            Some(format!(
+                "max_workspaces {} already open",
+                cfg.max_workspaces
+            ))
+        } else {
+            None
+        }
+    };
+    if let Some(message) = invalid {
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    }
+    let Some(session_cfg) = cfg.sessions.get(&session) else {
+        let message = format!("unknown session: {session}");
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+    if let Err(e) = app
+        .executor
+        .open_workspace(&session, session_cfg, &workspace)
+        .await
+    {
+        emit_exec_error(app, Some(&id), &session, e, start).await;
+        return;
+    }
+    app.workspaces.lock().await.open(&workspace, &session);
+    spawn_workspace_expiry(app, &workspace, &session, cfg.workspace_idle_ms);
+
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::WorkspaceOpened {
+            id: id.clone(),
+            workspace,
+            session: session.clone(),
+            idle_timeout_ms: cfg.workspace_idle_ms,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "workspace.opened",
+        Some(&id),
+        Some(&session),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Close `workspace` once no query has used it for `idle_ms`.
+fn spawn_workspace_expiry(app: &Arc<App>, workspace: &str, session: &str, idle_ms: u64) {
+    let idle = std::time::Duration::from_millis(idle_ms);
+    // Weak, so a waiting expiry does not keep the app and its output channel
+    // alive after everything else is done with them.
+    let app = Arc::downgrade(app);
+    let workspace = workspace.to_string();
+    let session = session.to_string();
+    tokio::spawn(async move {
+        let mut wait = idle;
+        let app = loop {
+            tokio::time::sleep(wait).await;
+            let Some(app) = app.upgrade() else {
+                return;
+            };
+            let left = app.workspaces.lock().await.expire_idle(&workspace, idle);
+            match left {
+                None => return,
+                Some(left) if !left.is_zero() => wait = left,
+                Some(_) => break app,
+            }
+        };
+        app.executor.close_workspace(&workspace).await;
+        emit_log(
+            &app,
+            "workspace.expired",
+            None,
+            Some(&session),
+            None,
+            None,
+            &Trace::only_duration(idle_ms),
+        )
+        .await;
+    });
+}
+
+/// Sessions a config patch marks `eager`, to connect once it is applied.
+pub fn eager_sessions(patch: &ConfigPatch) -> Vec<String> {
+    let mut names: Vec<String> = patch
+        .sessions
+        .iter()
+        .flatten()
+        .filter(|(_, s)| s.eager == Some(true))
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Connect each of `sessions` at once, ahead of its first query, and report
+/// which could not. An unknown session is reported as failed.
+pub async fn connect_sessions(app: &Arc<App>, sessions: Vec<String>) -> Output {
+    let start = Instant::now();
+    let cfg = app.config.read().await.clone();
+    let mut tasks = vec![];
+    for name in sessions {
+        let app = app.clone();
+        let session_cfg = cfg.sessions.get(&name).cloned();
+        let task_name = name.clone();
+        let task = tokio::spawn(async move {
+            let Some(session_cfg) = session_cfg else {
+                return Err(format!("unknown session: {task_name}"));
+            };
+            app.executor
+                .connect(&task_name, &session_cfg)
+                .await
+                .map_err(|e| e.to_string())
+        });
+        tasks.push((name, task));
+    }
+    let mut connected = vec![];
+    let mut failed = std::collections::BTreeMap::new();
+    for (name, task) in tasks {
+        match task.await {
+            Ok(Ok(())) => connected.push(name),
+            Ok(Err(e)) => {
+                failed.insert(name, e);
+            }
+            Err(e) => {
+                failed.insert(name, format!("connect task failed: {e}"));
+            }
+        }
+    }
+    connected.sort();
+    Output::ConnectReport {
+        connected,
+        failed,
+        trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+    }
+}
+
+/// Every `pool_check_interval_ms`, close idle and broken pooled connections
+/// so the next query gets one that was just checked.
+pub fn spawn_pool_maintenance(app: &Arc<App>) {
+    let app = app.clone();
+    tokio::spawn(async move {
+        loop {
+            let (interval_ms, idle_ms) = {
+                let cfg = app.config.read().await;
+                (cfg.pool_check_interval_ms, cfg.pool_idle_timeout_ms)
+            };
+            if interval_ms == 0 {
+                // Disabled; look again in case the config changes.
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(interval_ms)).await;
+            let idle = (idle_ms > 0).then(|| std::time::Duration::from_millis(idle_ms));
+            let reports = app.executor.maintain_pools(idle).await;
+            if reports.is_empty() || !log_enabled(&app.config.read().await.log, "pool.pruned") {
+                continue;
+            }
+            for report in reports {
+                let _ = app
+                    .writer
+                    .send(Output::Log {
+                        event: "pool.pruned".to_string(),
+                        request_id: None,
+                        session: Some(report.session),
+                        error_code: None,
+                        command_tag: None,
+                        warning: Some(format!(
+                            "closed {} idle and {} broken connections",
+                            report.idle, report.broken
+                        )),
+                        version: None,
+                        argv: None,
+                        config: None,
+                        args: None,
+                        env: None,
+                        statement: None,
+                        trace: Trace::only_duration(0),
+                    })
+                    .await;
+            }
+        }
+    });
+}
+
+/// Start the runs of `schedules` due each minute, at the start of the
+/// minute. A schedule whose previous run is still going skips the minute.
+pub fn spawn_scheduler(app: &Arc<App>) {
+    let app = app.clone();
+    tokio::spawn(async move {
+        let mut running: std::collections::HashMap<String, tokio::task::JoinHandle<()>> =
+            std::collections::HashMap::new();
+        loop {
+            let now_ms = unix_millis();
+            let minute_ms = (now_ms / 60_000 + 1) * 60_000;
+            tokio::time::sleep(std::time::Duration::from_millis(minute_ms - now_ms)).await;
+            running.retain(|_, task| !task.is_finished());
+            let schedules = app.config.read().await.schedules.clone();
+            for (name, schedule) in schedules {
+                if !schedule.cron.matches(minute_ms) {
+                    continue;
+                }
+                if running.contains_key(&name) {
+                    let trace = Trace::only_duration(0);
+                    emit_log(
+                        &app,
+                        "schedule.skipped",
+                        Some(&name),
+                        None,
+                        None,
+                        None,
+                        &trace,
+                    )
+                    .await;
+                    continue;
+                }
+                let app = app.clone();
+                let key = name.clone();
+                let task = tokio::spawn(async move {
+                    run_schedule(&app, name, schedule, minute_ms).await;
+                });
+                running.insert(key, task);
+            }
+        }
+    });
+}
+
+/// Run schedule `name` once. Its result goes to its sink, or to the output
+/// stream as `schedule_result`; failures always go to the output stream,
+/// under the schedule's name.
+async fn run_schedule(app: &Arc<App>, name: String, schedule: Schedule, scheduled_at_ms: u64) {
+    let start = Instant::now();
+    app.requests_total
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let mut options = QueryOptions {
+        statement_timeout_ms: schedule.statement_timeout_ms,
+        vars: schedule.vars,
+        idents: schedule.idents,
+        ..QueryOptions::default()
+    };
+    let sql = match expand_templates(schedule.sql, &mut options) {
+        Ok(sql) => sql,
+        Err(message) => return send_invalid_request(app, Some(&name), message, start).await,
+    };
+    let Some(target) = resolve_target(
+        app,
+        Some(&name),
+        schedule.session.as_deref(),
+        &options,
+        start,
+    )
+    .await
+    else {
+        return;
+    };
+    let outcome = app
+        .executor
+        .execute(
+            &target.conn_session,
+            &target.session_cfg,
+            &sql,
+            &schedule.params,
+            &target.opts,
+        )
+        .await;
+    let (rows, row_count) = match outcome {
+        Ok(ExecOutcome::Rows(rows)) => {
+            let count = rows.len();
+            (rows, count)
+        }
+        Ok(ExecOutcome::Command { affected }) => (vec![], affected),
+        Err(e) => return emit_exec_error(app, Some(&name), &target.session_name, e, start).await,
+    };
+    let payload_bytes = rows_bytes(&rows);
+    if rows.len() > target.opts.inline_max_rows || payload_bytes > target.opts.inline_max_bytes {
+        let message = "schedule result exceeds inline limits; narrow the query".to_string();
+        return send_error(app, Some(&name), "result_too_large", message, start).await;
+    }
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(row_count),
+        payload_bytes: Some(payload_bytes),
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let result = Output::ScheduleResult {
+        id: name.clone(),
+        session: target.session_name.clone(),
+        scheduled_at_ms,
+        rows,
+        row_count,
+        trace: trace.clone(),
+        memory: app.memory.reserve(payload_bytes),
+    };
+    match &schedule.sink {
+        None => {
+            let _ = app.writer.send(result).await;
+        }
+        Some(sink) => {
+            // A sink gets what the output stream would: `_secret` values masked.
+            let mut record = serde_json::to_value(&result).unwrap_or(Value::Null);
+            agent_first_data::internal_redact_secrets(&mut record);
+            if let Err(message) = schedule::deliver(sink, &record).await {
+                return send_error(app, Some(&name), "schedule_failed", message, start).await;
+            }
+        }
+    }
+    emit_log(
+        app,
+        "schedule.run",
+        Some(&name),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Workspace holding the dedicated connection of a `pinned` session.
+fn pinned_workspace(session: &str) -> String {
+    format!("{PINNED_WORKSPACE_PREFIX}{session}")
+}
+
+const PINNED_WORKSPACE_PREFIX: &str = "session:";
+
+/// Open the pinned connection of `session` unless it already is, and mark
+/// it used.
+async fn ensure_pinned(
+    app: &Arc<App>,
+    session: &str,
+    session_cfg: &SessionConfig,
+    idle_ms: u64,
+) -> Result<String, ExecError> {
+    let workspace = pinned_workspace(session);
+    let mut workspaces = app.workspaces.lock().await;
+    if workspaces.touch(&workspace).is_none() {
+        app.executor
+            .open_workspace(session, session_cfg, &workspace)
+            .await?;
+        workspaces.open(&workspace, session);
+        spawn_workspace_expiry(app, &workspace, session, idle_ms);
+    }
+    Ok(workspace)
+}
+
+/// Close a workspace and its connection, dropping its temp tables.
+pub async fn workspace_close(app: &Arc<App>, id: String, workspace: String) {
+    let start = Instant::now();
+    let Some(closed) = app.workspaces.lock().await.close(&workspace) else {
+        let message = format!("workspace {workspace} is not open");
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+    app.executor.close_workspace(&workspace).await;
+
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::WorkspaceClosed {
+            id: id.clone(),
+            workspace,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "workspace.closed",
+        Some(&id),
+        Some(&closed.session),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// The id a new pipe request runs under. A request whose id is still in
+/// flight must not replace that task's handle, which would leave it running
+/// where `cancel` cannot reach it: `duplicate_id_policy` either fails the
+/// request with `duplicate_id` (`None`) or picks the first free `id#N`.
+pub async fn claim_id(app: &Arc<App>, id: String) -> Option<String> {
+    let policy = app.config.read().await.duplicate_id_policy;
+    claim_id_as(app, id, policy).await
+}
+
+/// [`claim_id`] under an explicit policy, for requests whose id names
+/// something else (a checkpoint) and so cannot take a suffix.
+pub async fn claim_id_as(app: &Arc<App>, id: String, policy: DuplicateIdPolicy) -> Option<String> {
+    let in_flight = app.in_flight.lock().await;
+    let busy = |key: &str| in_flight.get(key).is_some_and(|h| !h.is_finished());
+    if !busy(&id) {
+        return Some(id);
+    }
+    match policy {
+        DuplicateIdPolicy::Suffix => (2..).map(|n| format!("{id}#{n}")).find(|key| !busy(key)),
+        DuplicateIdPolicy::Reject => {
+            drop(in_flight);
+            let message = format!("request {id} is still in flight; use a new id");
+            send_error(app, Some(&id), "duplicate_id", message, Instant::now()).await;
+            None
+        }
+    }
+}
+
+pub async fn invalidate_cache(app: &Arc<App>, id: String, session: Option<String>) {
+    let start = Instant::now();
+    let result_entries = app.cache.lock().await.invalidate(session.as_deref());
+    let schema_entries = app.schema_cache.lock().await.invalidate(session.as_deref());
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let _ = app
+        .writer
+        .send(Output::CacheInvalidated {
+            id: id.clone(),
+            session: session.clone(),
+            result_entries,
+            schema_entries,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "cache.invalidated",
+        Some(&id),
+        session.as_deref(),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Run an anonymous plpgsql `DO` block. `vars` are exposed to the body as
+/// transaction-local GUCs readable via `current_setting('afpsql.<name>')`.
+pub async fn execute_block(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    body: String,
+    vars: Vec<(String, String)>,
+    options: QueryOptions,
+) {
+    let start = Instant::now();
+    let Some(target) =
+        resolve_target(app, id.as_deref(), session.as_deref(), &options, start).await
+    else {
+        return;
+    };
+
+    let run = app
+        .executor
+        .execute_block(
+            &target.conn_session,
+            &target.session_cfg,
+            &body,
+            &vars,
+            &target.opts,
+        )
+        .await;
+
+    // Notices come first whether the block committed or not: those raised
+    // before a failure say how far it got.
+    for notice in run.notices {
+        let _ = app
+            .writer
+            .send(Output::Notice {
+                id: id.clone(),
+                session: Some(target.session_name.clone()),
+                severity: notice.severity,
+                sqlstate: notice.sqlstate,
+                message: notice.message,
+                detail: notice.detail,
+                hint: notice.hint,
+            })
+            .await;
+    }
+    match run.result {
+        Ok(()) => {
+            emit_command_result(
+                app,
+                id.as_deref(),
+                &target.session_name,
+                "DO".to_string(),
+                "DO",
+                start,
+                Timing::default(),
+            )
+            .await;
+        }
+        Err(err) => emit_exec_error(app, id.as_deref(), &target.session_name, err, start).await,
+    }
+}
+
+/// Feed `data` to a `COPY ... FROM STDIN` statement and report `COPY n`.
+pub async fn execute_copy_in(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    sql: String,
+    data: Vec<u8>,
+    options: QueryOptions,
+) {
+    let start = Instant::now();
+    let Some(target) =
+        resolve_target(app, id.as_deref(), session.as_deref(), &options, start).await
+    else {
+        return;
+    };
+
+    let result = app
+        .executor
+        .copy_in(
+            &target.conn_session,
+            &target.session_cfg,
+            &sql,
+            data,
+            &target.opts,
+        )
+        .await;
+
+    match result {
+        Ok(copied) => {
+            emit_command_result(
+                app,
+                id.as_deref(),
+                &target.session_name,
+                format!("COPY {copied}"),
+                "COPY",
+                start,
+                Timing::default(),
+            )
+            .await;
+        }
+        Err(err) => emit_exec_error(app, id.as_deref(), &target.session_name, err, start).await,
+    }
+}
+
+/// Run the statements of a CLI script in order under `on_error`, each
+/// reported as usual with its index as `id`, then a `script_result` with the
+/// outcome of each. A `rollback_statement` script is one transaction, so its
+/// events come once that has ended.
+pub async fn run_script(
+    app: &Arc<App>,
+    statements: Vec<String>,
+    params: Vec<Value>,
+    options: QueryOptions,
+    on_error: OnError,
+) {
+    let start = Instant::now();
+    let mut entries: Vec<ScriptStatement> = (0..statements.len())
+        .map(|index| ScriptStatement {
+            index,
+            status: StatementStatus::Skipped,
+            error_code: None,
+            sqlstate: None,
+            error: None,
+        })
+        .collect();
+    let Some(target) = resolve_target(app, None, Some("default"), &options, start).await else {
+        return send_script_result(app, on_error, entries, None, start).await;
+    };
+    let sqls: Vec<Result<String, String>> = statements
+        .into_iter()
+        .map(|sql| expand_templates(sql, &mut options.clone()))
+        .collect();
+
+    if on_error != OnError::RollbackStatement {
+        for (index, sql) in sqls.into_iter().enumerate() {
+            let started = Instant::now();
+            let result = match sql {
+                Ok(sql) => {
+                    app.executor
+                        .execute(
+                            &target.conn_session,
+                            &target.session_cfg,
+                            &sql,
+                            &params,
+                            &target.opts,
+                        )
+                        .await
+                }
+                Err(message) => Err(ExecError::Internal(message)),
+            };
+            entries[index] = emit_script_outcome(app, index, &target, result, started).await;
+            if on_error == OnError::Stop && entries[index].status == StatementStatus::Error {
+                break;
+            }
+        }
+        return send_script_result(app, on_error, entries, None, start).await;
+    }
+
+    // Nothing of a script in one transaction runs if one of its statements
+    // cannot.
+    if let Some(index) = sqls.iter().position(Result::is_err) {
+        let Some(Err(message)) = sqls.into_iter().nth(index) else {
+            return;
+        };
+        let err = Err(ExecError::Internal(message));
+        entries[index] = emit_script_outcome(app, index, &target, err, start).await;
+        return send_script_result(app, on_error, entries, Some(false), start).await;
+    }
+    let sqls: Vec<String> = sqls.into_iter().flatten().collect();
+    let run = app
+        .executor
+        .execute_script(
+            &target.conn_session,
+            &target.session_cfg,
+            &sqls,
+            &params,
+            &target.opts,
+        )
+        .await;
+    let committed = run.committed.is_ok();
+    for (index, outcome) in run.outcomes.into_iter().enumerate() {
+        entries[index] = match outcome {
+            // Work that never committed is not reported as a result.
+            Ok(_) if !committed && !run.ended_early => ScriptStatement {
+                status: StatementStatus::RolledBack,
+                ..entries[index].clone()
+            },
+            outcome => emit_script_outcome(app, index, &target, outcome, start).await,
+        };
+    }
+    // A statement that ended the transaction has already been reported.
+    if let (Err(err), false) = (run.committed, run.ended_early) {
+        emit_exec_error(app, None, &target.session_name, err, start).await;
+    }
+    // Whether work before a statement that ended the transaction itself
+    // committed is up to that statement.
+    let committed = (!run.ended_early).then_some(committed);
+    send_script_result(app, on_error, entries, committed, start).await;
+}
+
+/// Report one statement of a script under its index and say how it went.
+async fn emit_script_outcome(
+    app: &Arc<App>,
+    index: usize,
+    target: &Target,
+    result: Result<ExecOutcome, ExecError>,
+    start: Instant,
+) -> ScriptStatement {
+    let id = index.to_string();
+    let session = &target.session_name;
+    let mut entry = ScriptStatement {
+        index,
+        status: StatementStatus::Ok,
+        error_code: None,
+        sqlstate: None,
+        error: None,
+    };
+    match result {
+        Ok(ExecOutcome::Rows(rows)) => {
+            let status = emit_rows_result(
+                app,
+                Some(id.clone()),
+                Some(session.clone()),
+                rows,
+                start,
+                &target.opts,
+                None,
+                Timing::default(),
+            )
+            .await;
+            match status {
+                RowEmitStatus::Sent { trace } => {
+                    charge_usage(app, target.agent.as_deref(), session, &trace).await;
+                    emit_log(
+                        app,
+                        "query.result",
+                        Some(&id),
+                        Some(session),
+                        None,
+                        Some("SELECT"),
+                        &trace,
+                    )
+                    .await;
+                }
+                RowEmitStatus::Failed { trace, error_code } => {
+                    emit_log(
+                        app,
+                        "query.error",
+                        Some(&id),
+                        Some(session),
+                        Some(error_code),
+                        None,
+                        &trace,
+                    )
+                    .await;
+                    entry.status = StatementStatus::Error;
+                    entry.error_code = Some(error_code.to_string());
+                }
+            }
+        }
+        Ok(ExecOutcome::Command { affected }) => {
+            let tag = format!("EXECUTE {affected}");
+            emit_command_result(
+                app,
+                Some(&id),
+                session,
+                tag,
+                "EXECUTE",
+                start,
+                Timing::default(),
+            )
+            .await;
+        }
+        Err(err) => {
+            entry.status = StatementStatus::Error;
+            entry.error_code = Some(exec_error_code(&err).to_string());
+            let (ExecError::Connect(message)
+            | ExecError::InvalidParams(message)
+            | ExecError::Internal(message)
+            | ExecError::ApprovalRequired(message)
+            | ExecError::Sql { message, .. }) = &err;
+            entry.error = Some(message.clone());
+            if let ExecError::Sql { sqlstate, .. } = &err {
+                entry.sqlstate = Some(sqlstate.clone());
+            }
+            emit_exec_error(app, Some(&id), session, err, start).await;
+        }
+    }
+    entry
+}
+
+async fn send_script_result(
+    app: &Arc<App>,
+    on_error: OnError,
+    statements: Vec<ScriptStatement>,
+    committed: Option<bool>,
+    start: Instant,
+) {
+    let count = |status| statements.iter().filter(|s| s.status == status).count();
+    let ok_count = count(StatementStatus::Ok);
+    let error_count = count(StatementStatus::Error);
+    let skipped_count = count(StatementStatus::Skipped);
+    let _ = app
+        .writer
+        .send(Output::ScriptResult {
+            on_error,
+            ok_count,
+            error_count,
+            skipped_count,
+            committed,
+            statements,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+/// Rows per write when a `transfer` does not set `batch_rows`.
+const TRANSFER_BATCH_ROWS: usize = 1000;
+
+/// Read the rows of a query on the source session and write them into a
+/// table on the target session, in batches. The source query always runs
+/// read-only. Each batch commits on its own, so when one fails the earlier
+/// batches stay written and the error says how many rows that was. With
+/// `require_approval`, a transfer over the threshold as a whole is refused
+/// before the first batch.
+pub async fn transfer(app: &Arc<App>, req: TransferRequest) {
+    let start = Instant::now();
+    let id = req.id;
+    let invalid = if req.options.stream_rows || req.options.store_result {
+        Some("transfer returns no rows; stream_rows and store_result are not supported".to_string())
+    } else if req.options.verify.is_some() && req.method != TransferMethod::Copy {
+        Some("verify needs method copy".to_string())
+    } else if req.options.prepare_transaction.is_some() {
+        Some("transfer commits batch by batch; prepare_transaction is not supported".to_string())
+    } else {
+        sqlgen::qualified_name(&req.table).err()
+    };
+    if let Some(message) = invalid {
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    }
+    let Some(source) = resolve_target(
+        app,
+        Some(&id),
+        req.source_session.as_deref(),
+        &req.options,
+        start,
+    )
+    .await
+    else {
+        return;
+    };
+    let Some(target) = resolve_target(
+        app,
+        Some(&id),
+        req.target_session.as_deref(),
+        &req.options,
+        start,
+    )
+    .await
+    else {
+        return;
+    };
+
+    let mut source_opts = source.opts.clone();
+    source_opts.read_only = true;
+    // Constraint timing and replication role are for the writes.
+    source_opts.defer_constraints = false;
+    source_opts.replication_role = None;
+    if req.options.default_limit.is_none() {
+        // A configured default_limit would silently leave rows behind.
+        source_opts.default_limit = None;
+    }
+    // Read the source a batch at a time and write each batch as it arrives,
+    // so the source result is never held whole.
+    let mut remaining = match source_opts.default_limit_action {
+        DefaultLimitAction::Limit => source_opts.default_limit,
+        DefaultLimitAction::Warn => None,
+    };
+    if target.opts.approval_row_threshold.is_some() {
+        // Checked as a whole, by the rows the source returns, since each
+        // batch alone could stay under the threshold.
+        let total = match app
+            .executor
+            .execute(
+                &source.conn_session,
+                &source.session_cfg,
+                &sqlgen::build_count_of(&req.sql),
+                &req.params,
+                &source_opts,
+            )
+            .await
+        {
+            Ok(ExecOutcome::Rows(rows)) => rows
+                .first()
+                .and_then(|row| row.get("rows"))
+                .and_then(Value::as_u64)
+                .map(|total| remaining.map_or(total, |cap| total.min(cap as u64))),
+            // A source that cannot be counted counts as over the threshold.
+            _ => None,
+        };
+        if let Some(err) = batches_need_approval(&target.opts, "transfer", total) {
+            emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+            return;
+        }
+    }
+    let batch_rows = req.batch_rows.unwrap_or(TRANSFER_BATCH_ROWS).max(1);
+    let (pages_tx, pages) = mpsc::channel(1);
+    let read = app.executor.execute_pages(
+        &source.conn_session,
+        &source.session_cfg,
+        &req.sql,
+        &req.params,
+        &source_opts,
+        batch_rows,
+        pages_tx,
+    );
+    let mut rows_read = 0usize;
+    let mut rows_written = 0u64;
+    let mut batches = 0usize;
+    let mut verifier = req.options.verify.map(Verifier::new);
+    let write = async {
+        // Owned here, so the reader stops once writing does.
+        let mut pages = pages;
+        while let Some(mut page) = pages.recv().await {
+            if let Some(left) = remaining.as_mut() {
+                page.truncate(*left);
+                *left -= page.len();
+            }
+            if page.is_empty() {
+                break;
+            }
+            // Held against memory_budget_bytes until the batch is written.
+            let _held = app.memory.reserve(rows_bytes(&page));
+            rows_read += page.len();
+            let page = sqlgen::rename_columns(page, &req.columns);
+            let mut write_rows = batch_rows;
+            if req.method == TransferMethod::Insert {
+                // One bind parameter per value.
+                let width = page
+                    .first()
+                    .and_then(Value::as_object)
+                    .map_or(1, |m| m.len().max(1));
+                write_rows = write_rows.min(sqlgen::MAX_BIND_PARAMS / width);
+            }
+            for batch in page.chunks(write_rows) {
+                let written = match verifier.as_mut() {
+                    Some(verifier) => {
+                        write_verified_batch(app, &target, &req.table, batch, verifier).await
+                    }
+                    None => write_transfer_batch(app, &target, &req.table, req.method, batch).await,
+                };
+                if let Some(mismatch) = verifier.as_ref().and_then(Verifier::mismatch) {
+                    let message = format!(
+                        "{mismatch}; the batch was rolled back (after {rows_written} rows written)"
+                    );
+                    send_checksum_mismatch(app, &id, message, start).await;
+                    return false;
+                }
+                match written {
+                    Ok(written) => {
+                        rows_written += written;
+                        batches += 1;
+                        send_progress(app, &id, &target, "transfer", rows_written, None, start)
+                            .await;
+                    }
+                    Err(err) => {
+                        let err = after_rows_written(err, rows_written);
+                        emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+                        return false;
+                    }
+                }
+            }
+            if remaining == Some(0) {
+                break;
+            }
+        }
+        true
+    };
+    let (read, written) = tokio::join!(read, write);
+    if !written {
+        return;
+    }
+    if let Err(err) = read {
+        let err = after_rows_written(err, rows_written);
+        emit_exec_error(app, Some(&id), &source.session_name, err, start).await;
+        return;
+    }
+
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(rows_read),
+        payload_bytes: None,
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let _ = app
+        .writer
+        .send(Output::TransferResult {
+            id: id.clone(),
+            source_session: source.session_name,
+            target_session: target.session_name.clone(),
+            table: req.table,
+            rows_read,
+            rows_written,
+            batches,
+            verification: verifier.as_ref().map(Verifier::report),
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.transfer",
+        Some(&id),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// `err` with how far a batched write got appended to its message.
+fn after_rows_written(mut err: ExecError, rows_written: u64) -> ExecError {
+    let (ExecError::Connect(message)
+    | ExecError::InvalidParams(message)
+    | ExecError::Internal(message)
+    | ExecError::ApprovalRequired(message)
+    | ExecError::Sql { message, .. }) = &mut err;
+    message.push_str(&format!(" (after {rows_written} rows written)"));
+    err
+}
+
+/// Rejected rows listed in an `import_result`; `rows_rejected` counts them
+/// all.
+const IMPORT_REJECTED_LISTED: usize = 100;
+
+/// Load the rows of a CSV or NDJSON file into a table with `COPY`, in
+/// batches. A batch the server refuses is retried a row at a time, so only
+/// the offending rows are rejected, and the import stops once more than
+/// `max_errors` rows have been. As in `transfer`, each batch commits on its
+/// own, and with `require_approval` an import over the threshold as a whole
+/// is refused up front. With `dead_letter`, every rejected record is written
+/// there at the end. An import can be paused and resumed between batches; resumed, it
+/// skips the records it already read.
+pub async fn import(app: &Arc<App>, req: ImportRequest) {
+    run_import(app, req, Cursor::default()).await;
+}
+
+async fn run_import(app: &Arc<App>, req: ImportRequest, cursor: Cursor) {
+    let start = Instant::now();
+    let operation = Operation::Import(req.clone());
+    let id = req.id;
+    let options = &req.options;
+    let checked = if options.stream_rows || options.store_result || options.export_uri.is_some() {
+        Err(
+            "import returns no rows; stream_rows, store_result and export_uri are not supported"
+                .to_string(),
+        )
+    } else if options.prepare_transaction.is_some() {
+        Err("import commits batch by batch; prepare_transaction is not supported".to_string())
+    } else {
+        sqlgen::qualified_name(&req.table)
+            .and_then(|_| ImportSource::parse(&req.source).map_err(|e| format!("source {e}")))
+    };
+    let source = match checked {
+        Ok(source) => source,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let Some(target) = resolve_target(app, Some(&id), req.session.as_deref(), options, start).await
+    else {
+        return;
+    };
+    let text = match source.read().await {
+        Ok(text) => text,
+        Err(e) => {
+            let error = format!("cannot read {}: {e}", req.source);
+            return send_import_failed(app, id, error, start).await;
+        }
+    };
+    let mut records = match import::records(&text, source.format) {
+        Ok(records) => records.peekable(),
+        Err(e) => {
+            let error = format!("cannot read {}: {e}", req.source);
+            return send_import_failed(app, id, error, start).await;
+        }
+    };
+
+    if target.opts.approval_row_threshold.is_some() {
+        // The records were parsed once already, so counting them cannot fail.
+        let total = import::records(&text, source.format).ok().map(|records| {
+            records
+                .skip(cursor.records_read)
+                .filter(|record| matches!(record, Record::Row { .. }))
+                .count() as u64
+        });
+        if let Some(err) = batches_need_approval(&target.opts, "import", total) {
+            emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+            return;
+        }
+    }
+    let batch_rows = req.batch_rows.unwrap_or(TRANSFER_BATCH_ROWS).max(1);
+    let mut managed = match Managed::start(app, &id, operation, &cursor).await {
+        Ok(managed) => managed,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let (mut rows_read, mut rows_imported, mut batches) =
+        (cursor.records_read, cursor.done, cursor.batches);
+    let mut dead: Vec<DeadRow> = cursor.rejected;
+    records.by_ref().take(rows_read).for_each(drop);
+    let mut verifier = options.verify.map(Verifier::new);
+    while records.peek().is_some() && dead.len() <= req.max_errors {
+        let (mut lines, mut rows) = (vec![], vec![]);
+        for record in records.by_ref() {
+            rows_read += 1;
+            match record {
+                Record::Row { line, row } => {
+                    lines.push(line);
+                    rows.push(row);
+                    if rows.len() == batch_rows {
+                        break;
+                    }
+                }
+                Record::Rejected { line, error, raw } => dead.push(DeadRow {
+                    position: line,
+                    error,
+                    row: None,
+                    raw: Some(raw),
+                }),
+            }
+        }
+        if rows.is_empty() {
+            continue;
+        }
+        let rows = sqlgen::rename_columns(rows, &req.columns);
+        let copy = BatchWrite::Copy(verifier.as_mut());
+        let written =
+            write_rejecting_rows(app, &target, &req.table, copy, (lines, &rows), &mut dead).await;
+        if let Some(mismatch) = verifier.as_ref().and_then(Verifier::mismatch) {
+            let message = format!(
+                "{mismatch}; the batch was rolled back (after {rows_imported} rows imported)"
+            );
+            send_checksum_mismatch(app, &id, message, start).await;
+            return;
+        }
+        match written {
+            Ok(written) => {
+                rows_imported += written;
+                batches += 1;
+                // The file is read as it goes, so its size in rows is not
+                // known up front.
+                send_progress(app, &id, &target, "import", rows_read as u64, None, start).await;
+            }
+            Err((err, written)) => {
+                let after = format!("{} rows imported", rows_imported + written);
+                return batches_aborted(app, &id, &target, err, &after, start).await;
+            }
+        }
+        if records.peek().is_none() || dead.len() > req.max_errors {
+            break;
+        }
+        let cursor = Cursor {
+            done: rows_imported,
+            batches,
+            after: None,
+            records_read: rows_read,
+            rejected: dead.clone(),
+        };
+        let read = rows_read as u64;
+        if let Err(e) = after_batch(app, &id, &target, "import", &mut managed, &cursor, read).await
+        {
+            let message = format!("{e} (after {rows_imported} rows imported)");
+            return send_error(app, Some(&id), "checkpoint_failed", message, start).await;
+        }
+    }
+    managed.finish();
+
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(rows_read),
+        payload_bytes: None,
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let rows_rejected = dead.len();
+    dead.sort_by_key(|r| r.position);
+    let dead_letter = match &req.dead_letter {
+        Some(to) => Some(write_dead_letter(app, &target, to, &dead).await),
+        None => None,
+    };
+    let rejected = dead
+        .into_iter()
+        .take(IMPORT_REJECTED_LISTED)
+        .map(|r| RejectedRow {
+            line: r.position,
+            error: r.error,
+        })
+        .collect();
+    let _ = app
+        .writer
+        .send(Output::ImportResult {
+            id: id.clone(),
+            session: target.session_name.clone(),
+            table: req.table,
+            source: req.source,
+            rows_read,
+            rows_imported,
+            rows_rejected,
+            rejected,
+            batches,
+            stopped: records.peek().is_some(),
+            verification: verifier.as_ref().map(Verifier::report),
+            dead_letter,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.import",
+        Some(&id),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Rows moved per `archive` batch when the request does not say.
+const ARCHIVE_BATCH_ROWS: usize = 1000;
+
+/// Move the rows matching a filter from a table into an archive table, a
+/// batch at a time. Each batch deletes and inserts in one statement, so a
+/// row is never in both tables or in neither; batches commit on their own,
+/// keeping locks short, and a `progress` event follows each one. With
+/// `require_approval`, an archive matching more rows than the approval
+/// threshold is refused before the first batch. An archive can be paused
+/// and resumed between batches (see [`operations`]).
+pub async fn archive(app: &Arc<App>, req: ArchiveRequest) {
+    run_archive(app, req, Cursor::default()).await;
+}
+
+async fn run_archive(app: &Arc<App>, req: ArchiveRequest, cursor: Cursor) {
+    let start = Instant::now();
+    let operation = Operation::Archive(req.clone());
+    let id = req.id;
+    let options = &req.options;
+    let batch_rows = req.batch_rows.unwrap_or(ARCHIVE_BATCH_ROWS).max(1);
+    let checked = if options.stream_rows || options.store_result || options.export_uri.is_some() {
+        Err(
+            "archive returns no rows; stream_rows, store_result and export_uri are not supported"
+                .to_string(),
+        )
+    } else if options.prepare_transaction.is_some() {
+        Err("archive commits batch by batch; prepare_transaction is not supported".to_string())
+    } else {
+        sqlgen::qualified_name(&req.archive_table)
+            .and_then(|_| sqlgen::build_count(&req.table, &req.filter))
+    };
+    let (count_sql, count_params) = match checked {
+        Ok(built) => built,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let Some(target) = resolve_target(app, Some(&id), req.session.as_deref(), options, start).await
+    else {
+        return;
+    };
+
+    let mut read_opts = target.opts.clone();
+    read_opts.read_only = true;
+    read_opts.default_limit = None;
+    let info = match app
+        .executor
+        .execute(
+            &target.conn_session,
+            &target.session_cfg,
+            anonymize::TABLE_SQL,
+            &[Value::String(req.table.clone())],
+            &read_opts,
+        )
+        .await
+    {
+        Ok(ExecOutcome::Rows(mut rows)) if !rows.is_empty() => {
+            serde_json::from_value::<anonymize::TableInfo>(rows.swap_remove(0)).ok()
+        }
+        Ok(_) => None,
+        Err(err) => {
+            emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+            return;
+        }
+    };
+    let Some(info) = info else {
+        let message = format!("{} is not a table", req.table);
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+    let total = match app
+        .executor
+        .execute(
+            &target.conn_session,
+            &target.session_cfg,
+            &count_sql,
+            &count_params,
+            &read_opts,
+        )
+        .await
+    {
+        Ok(ExecOutcome::Rows(rows)) => rows
+            .first()
+            .and_then(|row| row.get("rows"))
+            .and_then(Value::as_u64),
+        Ok(ExecOutcome::Command { .. }) => None,
+        Err(err) => {
+            emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+            return;
+        }
+    };
+    if let Some(err) = batches_need_approval(&target.opts, "archive", total) {
+        emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+        return;
+    }
+    // Rows moved before a resume count towards the total.
+    let total = total.map(|total| total + cursor.done);
+    let (sql, params) = match sqlgen::build_archive_batch(
+        &req.table,
+        &req.archive_table,
+        &info.columns,
+        &req.filter,
+        batch_rows,
+    ) {
+        Ok(built) => built,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let mut managed = match Managed::start(app, &id, operation, &cursor).await {
+        Ok(managed) => managed,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+
+    let mut cursor = cursor;
+    loop {
+        let rows_moved = cursor.done;
+        let moved = match app
+            .executor
+            .execute(
+                &target.conn_session,
+                &target.session_cfg,
+                &sql,
+                &params,
+                &target.opts,
+            )
+            .await
+        {
+            Ok(ExecOutcome::Command { affected }) => affected as u64,
+            Ok(ExecOutcome::Rows(rows)) => rows.len() as u64,
+            Err(err) => {
+                let after = format!("{rows_moved} rows moved");
+                return batches_aborted(app, &id, &target, err, &after, start).await;
+            }
+        };
+        if moved == 0 {
+            break;
+        }
+        cursor.done += moved;
+        cursor.batches += 1;
+        send_progress(app, &id, &target, "archive", cursor.done, total, start).await;
+        if moved < batch_rows as u64 {
+            break;
+        }
+        let done = cursor.done;
+        if let Err(e) = after_batch(app, &id, &target, "archive", &mut managed, &cursor, done).await
+        {
+            let message = format!("{e} (after {done} rows moved)");
+            return send_error(app, Some(&id), "checkpoint_failed", message, start).await;
+        }
+    }
+    managed.finish();
+
+    let (rows_moved, batches) = (cursor.done, cursor.batches);
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(rows_moved as usize),
+        payload_bytes: None,
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let _ = app
+        .writer
+        .send(Output::ArchiveResult {
+            id: id.clone(),
+            session: target.session_name.clone(),
+            table: req.table,
+            archive_table: req.archive_table,
+            rows_moved,
+            batches,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.archive",
+        Some(&id),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// A managed operation's pause switch and, with `state_dir`, where its
+/// checkpoint goes.
+struct Managed {
+    control: operations::Control,
+    checkpoint: Option<(std::path::PathBuf, Value)>,
+}
+
+impl Managed {
+    /// Register operation `id` and save its first checkpoint.
+    async fn start(
+        app: &Arc<App>,
+        id: &str,
+        operation: Operation,
+        cursor: &Cursor,
+    ) -> Result<Self, String> {
+        let Some(control) = app.operations.register(id, &operation) else {
+            return Err(format!("operation {id} is already running"));
+        };
+        let state_dir = app.config.read().await.state_dir.clone();
+        let checkpoint = match state_dir {
+            Some(dir) => {
+                let path = operations::checkpoint_path(&dir, id)?;
+                let operation = serde_json::to_value(operation).map_err(|e| e.to_string())?;
+                Some((path, operation))
+            }
+            None => None,
+        };
+        let managed = Self {
+            control,
+            checkpoint,
+        };
+        managed.save(cursor)?;
+        Ok(managed)
+    }
+
+    fn save(&self, cursor: &Cursor) -> Result<(), String> {
+        match &self.checkpoint {
+            Some((path, operation)) => operations::save(path, operation, cursor)
+                .map_err(|e| format!("cannot save checkpoint {}: {e}", path.display())),
+            None => Ok(()),
+        }
+    }
+
+    /// The operation is done; a checkpoint would only be resumed by mistake.
+    fn finish(self) {
+        if let Some((path, _)) = &self.checkpoint {
+            operations::remove(path);
+        }
+    }
+}
+
+/// Between two batches of a managed operation: save how far it got, then,
+/// if it was asked to pause, say so and wait to be resumed.
+async fn after_batch(
+    app: &Arc<App>,
+    id: &str,
+    target: &Target,
+    phase: &str,
+    managed: &mut Managed,
+    cursor: &Cursor,
+    done: u64,
+) -> Result<(), String> {
+    managed.save(cursor)?;
+    managed.control.progress(done, cursor.batches);
+    if managed.control.paused() {
+        let _ = app
+            .writer
+            .send(Output::Paused {
+                id: id.to_string(),
+                session: target.session_name.clone(),
+                phase: phase.to_string(),
+                done,
+            })
+            .await;
+        managed.control.resumed().await;
+    }
+    Ok(())
+}
+
+/// Ask running operation `id` to pause after its current batch.
+pub async fn pause(app: &Arc<App>, id: String) {
+    if !app.operations.set_paused(&id, true) {
+        let message = format!("no running operation {id}");
+        send_invalid_request(app, Some(&id), message, Instant::now()).await;
+    }
+}
+
+/// Wake paused operation `id`, if it runs in this process; `false` if it
+/// does not.
+pub async fn resume_running(app: &Arc<App>, id: &str) -> bool {
+    if !app.operations.set_paused(id, false) {
+        return false;
+    }
+    let _ = app
+        .writer
+        .send(Output::Resumed {
+            id: id.to_string(),
+            restored: false,
+        })
+        .await;
+    true
+}
+
+/// Operations running here, then checkpoints under `state_dir` that
+/// nothing here runs, and the statements pending approval; what a restart
+/// left behind shows up as `saved`.
+pub async fn operations_list(app: &Arc<App>, id: String) {
+    let mut entries = app.operations.list();
+    if let Some(dir) = app.config.read().await.state_dir.clone() {
+        let saved: Vec<_> = operations::saved(&dir)
+            .into_iter()
+            .filter(|(op, _)| !entries.iter().any(|e| &e.id == op))
+            .map(|(op, checkpoint)| checkpoint.entry(&op))
+            .collect();
+        entries.extend(saved);
+    }
+    let approvals = app.approvals.lock().await.list();
+    let _ = app
+        .writer
+        .send(Output::Operations {
+            id,
+            operations: entries,
+            approvals,
+        })
+        .await;
+}
+
+/// The process's own state, to tell a slow or stuck afpsql from a slow
+/// database.
+pub async fn debug_stats(app: &Arc<App>, id: String) {
+    let in_flight = app
+        .in_flight
+        .lock()
+        .await
+        .values()
+        .filter(|h| !h.is_finished())
+        .count();
+    let _ = app
+        .writer
+        .send(Output::DebugStats {
+            id,
+            runtime: profile::runtime(in_flight),
+            channels: app.writer.channel_stats(),
+            pools: app.executor.pool_states().await,
+            memory: profile::memory(app.memory.used()),
+            timings: profile::timings(),
+        })
+        .await;
+}
+
+/// Continue operation `id` from the checkpoint under `state_dir`.
+pub async fn resume_saved(app: &Arc<App>, id: String) {
+    let start = Instant::now();
+    let state_dir = app.config.read().await.state_dir.clone();
+    let Some(dir) = state_dir else {
+        let message = format!("no running operation {id}, and no state_dir to resume it from");
+        return send_invalid_request(app, Some(&id), message, start).await;
+    };
+    let loaded = operations::checkpoint_path(&dir, &id).and_then(|path| {
+        operations::load(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("no saved operation {id}"),
+            _ => format!("cannot read checkpoint {}: {e}", path.display()),
+        })
+    });
+    let checkpoint = match loaded {
+        Ok(checkpoint) => checkpoint,
+        Err(message) => return send_invalid_request(app, Some(&id), message, start).await,
+    };
+    let _ = app
+        .writer
+        .send(Output::Resumed {
+            id: id.clone(),
+            restored: true,
+        })
+        .await;
+    let cursor = checkpoint.cursor;
+    match checkpoint.operation {
+        Operation::Archive(mut req) => {
+            req.id = id;
+            run_archive(app, req, cursor).await;
+        }
+        Operation::Chunked(mut req) => {
+            req.id = id;
+            run_chunked_write(app, req, cursor).await;
+        }
+        Operation::Import(mut req) => {
+            req.id = id;
+            run_import(app, req, cursor).await;
+        }
+    }
+}
+
+/// Rows written per batch of a chunked write when `chunked` does not say.
+const CHUNK_ROWS: usize = 1000;
+
+/// Run a `psql_update` or `psql_delete` in batches, each committing on its
+/// own so locks stay short and WAL is written steadily, with a `progress`
+/// event after each and `sleep_ms` between them. A delete takes whatever
+/// still matches, since deleted rows stop matching; an update walks the
+/// primary key instead, since updated rows may not. Rows that start to
+/// match behind an update's position are left alone. Like an archive, a
+/// chunked write can be paused and resumed between batches.
+pub async fn chunked_write(app: &Arc<App>, req: ChunkedWriteRequest) {
+    run_chunked_write(app, req, Cursor::default()).await;
+}
+
+async fn run_chunked_write(app: &Arc<App>, req: ChunkedWriteRequest, cursor: Cursor) {
+    let start = Instant::now();
+    let operation = Operation::Chunked(req.clone());
+    let id = req.id;
+    let options = &req.options;
+    let phase = if req.set.is_some() {
+        "update"
+    } else {
+        "delete"
+    };
+    let batch_rows = req.chunking.batch_rows.unwrap_or(CHUNK_ROWS).max(1);
+    let checked = if options.stream_rows || options.store_result || options.export_uri.is_some() {
+        Err(
+            "chunked writes return no rows; stream_rows, store_result and export_uri are not supported"
+                .to_string(),
+        )
+    } else if options.prepare_transaction.is_some() {
+        Err(
+            "chunked writes commit batch by batch; prepare_transaction is not supported"
+                .to_string(),
+        )
+    } else {
+        sqlgen::build_count(&req.table, &req.filter)
+            .and_then(|(sql, params)| Ok((sql, params, sqlgen::qualified_name(&req.table)?)))
+    };
+    let (count_sql, count_params, relation) = match checked {
+        Ok(built) => built,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let Some(target) = resolve_target(app, Some(&id), req.session.as_deref(), options, start).await
+    else {
+        return;
+    };
+
+    let mut read_opts = target.opts.clone();
+    read_opts.read_only = true;
+    read_opts.default_limit = None;
+    let total = match app
+        .executor
+        .execute(
+            &target.conn_session,
+            &target.session_cfg,
+            &count_sql,
+            &count_params,
+            &read_opts,
+        )
+        .await
+    {
+        Ok(ExecOutcome::Rows(rows)) => rows
+            .first()
+            .and_then(|row| row.get("rows"))
+            .and_then(Value::as_u64),
+        Ok(ExecOutcome::Command { .. }) => None,
+        Err(err) => {
+            emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+            return;
+        }
+    };
+    if let Some(err) = batches_need_approval(&target.opts, phase, total) {
+        emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+        return;
+    }
+    let key: Vec<(String, String)> = if req.set.is_some() {
+        match app
+            .executor
+            .execute(
+                &target.conn_session,
+                &target.session_cfg,
+                sqlgen::PRIMARY_KEY_SQL,
+                &[Value::String(relation)],
+                &read_opts,
+            )
+            .await
+        {
+            Ok(ExecOutcome::Rows(rows)) => rows
+                .iter()
+                .filter_map(|row| {
+                    let text = |k: &str| row.get(k).and_then(Value::as_str).map(str::to_string);
+                    Some((text("column")?, text("type")?))
+                })
+                .collect(),
+            Ok(ExecOutcome::Command { .. }) => vec![],
+            Err(err) => {
+                emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+                return;
+            }
+        }
+    } else {
+        vec![]
+    };
+    // Rows deleted before a resume no longer match; rows updated may.
+    let total = match req.set {
+        Some(_) => total,
+        None => total.map(|total| total + cursor.done),
+    };
+    let mut managed = match Managed::start(app, &id, operation, &cursor).await {
+        Ok(managed) => managed,
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+
+    let mut cursor = cursor;
+    loop {
+        let rows_affected = cursor.done;
+        let built = match &req.set {
+            Some(set) => sqlgen::build_update_chunk(
+                &req.table,
+                set,
+                &req.filter,
+                &key,
+                cursor.after.as_deref(),
+                batch_rows,
+            ),
+            None => sqlgen::build_delete_chunk(&req.table, &req.filter, batch_rows),
+        };
+        let (sql, params) = match built {
+            Ok(built) => built,
+            Err(message) => {
+                send_invalid_request(app, Some(&id), message, start).await;
+                return;
+            }
+        };
+        let result = app
+            .executor
+            .execute(
+                &target.conn_session,
+                &target.session_cfg,
+                &sql,
+                &params,
+                &target.opts,
+            )
+            .await;
+        // Rows the batch took, rows it wrote, and for an update where the
+        // next batch starts.
+        let (picked, written, last) = match result {
+            Ok(ExecOutcome::Command { affected }) => (affected as u64, affected as u64, None),
+            Ok(ExecOutcome::Rows(rows)) => {
+                let row = rows.first().cloned().unwrap_or(Value::Null);
+                let count = |k: &str| row.get(k).and_then(Value::as_u64).unwrap_or(0);
+                let last = row.get("last").and_then(Value::as_array).cloned();
+                (count("picked"), count("updated"), last)
+            }
+            Err(err) => {
+                let after = format!("{rows_affected} rows written");
+                return batches_aborted(app, &id, &target, err, &after, start).await;
+            }
+        };
+        if picked == 0 {
+            break;
+        }
+        cursor.done += written;
+        cursor.batches += 1;
+        cursor.after = last;
+        send_progress(app, &id, &target, phase, cursor.done, total, start).await;
+        if picked < batch_rows as u64 {
+            break;
+        }
+        let done = cursor.done;
+        if let Err(e) = after_batch(app, &id, &target, phase, &mut managed, &cursor, done).await {
+            let message = format!("{e} (after {done} rows written)");
+            return send_error(app, Some(&id), "checkpoint_failed", message, start).await;
+        }
+        if req.chunking.sleep_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(req.chunking.sleep_ms)).await;
+        }
+    }
+    managed.finish();
+
+    let (rows_affected, batches) = (cursor.done, cursor.batches);
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(rows_affected as usize),
+        payload_bytes: None,
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let _ = app
+        .writer
+        .send(Output::ChunkedResult {
+            id: id.clone(),
+            session: target.session_name.clone(),
+            table: req.table,
+            rows_affected,
+            batches,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.chunked",
+        Some(&id),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// A `progress` event for a batched operation on `target`. Rows that start
+/// to match after `total` was counted are done too, so `total` never reads
+/// below `done`.
+async fn send_progress(
+    app: &Arc<App>,
+    id: &str,
+    target: &Target,
+    phase: &str,
+    done: u64,
+    total: Option<u64>,
+    start: Instant,
+) {
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+    let total = total.map(|total| total.max(done));
+    let _ = app
+        .writer
+        .send(Output::Progress {
+            id: id.to_string(),
+            session: target.session_name.clone(),
+            phase: phase.to_string(),
+            done,
+            total,
+            elapsed_ms,
+            eta_ms: eta_ms(elapsed_ms, done, total),
+        })
+        .await;
+}
+
+/// Time left if the rest goes at the rate so far; unknown until something
+/// is done or without a total.
+fn eta_ms(elapsed_ms: u64, done: u64, total: Option<u64>) -> Option<u64> {
+    let total = total?;
+    (done > 0).then(|| {
+        let left = u128::from(total.saturating_sub(done));
+        (u128::from(elapsed_ms) * left / u128::from(done)) as u64
+    })
+}
+
+/// Batches each under the approval threshold could still add up to a write
+/// over it, so a batched write is checked against the threshold as a whole,
+/// by the rows matching when it starts.
+fn batches_need_approval(
+    opts: &ResolvedOptions,
+    what: &str,
+    total: Option<u64>,
+) -> Option<ExecError> {
+    let threshold = opts.approval_row_threshold?;
+    let matched = match total {
+        Some(total) if total <= threshold as u64 => return None,
+        Some(total) => format!("{total} rows"),
+        None => "an unknown number of rows".to_string(),
+    };
+    Some(ExecError::ApprovalRequired(format!(
+        "{what} matches {matched}, over approval_row_threshold {threshold}"
+    )))
+}
+
+/// Insert `rows` in batches for a `psql_insert` with `dead_letter`. As in
+/// `import`, a batch the server refuses is retried a row at a time and the
+/// rows it still refuses are written to the dead letter instead of failing
+/// the insert; each batch commits on its own, so with `require_approval`
+/// the rows are checked against the threshold as a whole first.
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_rows(
+    app: &Arc<App>,
+    id: String,
+    session: Option<String>,
+    table: String,
+    rows: Vec<Value>,
+    on_conflict: sqlgen::OnConflict,
+    batch_rows: Option<usize>,
+    dead_letter: DeadLetter,
+    options: QueryOptions,
+) {
+    let start = Instant::now();
+    let checked =
+        if options.stream_rows || options.store_result || options.export_uri.is_some() {
+            Err("insert returns no rows; stream_rows, store_result and export_uri are not supported"
+            .to_string())
+        } else if options.prepare_transaction.is_some() {
+            Err("insert commits batch by batch; prepare_transaction is not supported".to_string())
+        } else {
+            sqlgen::qualified_name(&table).and_then(|_| sqlgen::row_columns(&rows))
+        };
+    let width = match checked {
+        Ok(columns) => columns.len().max(1),
+        Err(message) => {
+            send_invalid_request(app, Some(&id), message, start).await;
+            return;
+        }
+    };
+    let Some(target) = resolve_target(app, Some(&id), session.as_deref(), &options, start).await
+    else {
+        return;
+    };
+    if let Some(err) = batches_need_approval(&target.opts, "insert", Some(rows.len() as u64)) {
+        emit_exec_error(app, Some(&id), &target.session_name, err, start).await;
+        return;
+    }
+
+    let batch_rows = batch_rows
+        .unwrap_or(TRANSFER_BATCH_ROWS)
+        .clamp(1, (sqlgen::MAX_BIND_PARAMS / width).max(1));
+    let (mut rows_inserted, mut batches) = (0u64, 0usize);
+    let mut dead: Vec<DeadRow> = vec![];
+    for (n, batch) in rows.chunks(batch_rows).enumerate() {
+        let positions = (n * batch_rows + 1..).take(batch.len()).collect();
+        let insert = BatchWrite::Insert(&on_conflict);
+        match write_rejecting_rows(app, &target, &table, insert, (positions, batch), &mut dead)
+            .await
+        {
+            Ok(written) => {
+                rows_inserted += written;
+                batches += 1;
+                let (done, total) = (n * batch_rows + batch.len(), rows.len());
+                send_progress(
+                    app,
+                    &id,
+                    &target,
+                    "insert",
+                    done as u64,
+                    Some(total as u64),
+                    start,
+                )
+                .await;
+            }
+            Err((err, written)) => {
+                let after = format!("{} rows inserted", rows_inserted + written);
+                return batches_aborted(app, &id, &target, err, &after, start).await;
+            }
+        }
+    }
+
+    let dead_letter = write_dead_letter(app, &target, &dead_letter, &dead).await;
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(rows.len()),
+        payload_bytes: None,
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let _ = app
+        .writer
+        .send(Output::InsertResult {
+            id: id.clone(),
+            session: target.session_name.clone(),
+            table,
+            rows_inserted,
+            rows_rejected: dead.len(),
+            batches,
+            dead_letter,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.insert",
+        Some(&id),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// How `write_rejecting_rows` writes rows.
+enum BatchWrite<'a> {
+    /// `COPY`, verified when there is a `Verifier`.
+    Copy(Option<&'a mut Verifier>),
+    Insert(&'a sqlgen::OnConflict),
+}
+
+/// Write one batch, retrying a refused one a row at a time and adding the
+/// rows the server rejects to `dead` at their `positions`. Other errors come
+/// back with the rows written before them.
+async fn write_rejecting_rows(
+    app: &Arc<App>,
+    target: &Target,
+    table: &str,
+    mut how: BatchWrite<'_>,
+    (positions, rows): (Vec<usize>, &[Value]),
+    dead: &mut Vec<DeadRow>,
+) -> Result<u64, (ExecError, u64)> {
+    let (session, cfg, opts) = (&target.conn_session, &target.session_cfg, &target.opts);
+    let mut write = async |rows: &[Value]| match &mut how {
+        BatchWrite::Copy(Some(verifier)) => {
+            write_verified_batch(app, target, table, rows, verifier).await
+        }
+        BatchWrite::Copy(None) => {
+            write_transfer_batch(app, target, table, TransferMethod::Copy, rows).await
+        }
+        BatchWrite::Insert(on_conflict) => {
+            let (sql, params) = sqlgen::build_insert(table, rows, on_conflict, &[])
+                .map_err(ExecError::InvalidParams)?;
+            match app
+                .executor
+                .execute(session, cfg, &sql, &params, opts)
+                .await?
+            {
+                ExecOutcome::Command { affected } => Ok(affected as u64),
+                ExecOutcome::Rows(rows) => Ok(rows.len() as u64),
+            }
+        }
+    };
+    match write(rows).await {
+        Err(ExecError::Sql { .. } | ExecError::InvalidParams(_)) => {}
+        Err(err) => return Err((err, 0)),
+        Ok(written) => return Ok(written),
+    }
+    let mut written = 0;
+    for (position, row) in positions.into_iter().zip(rows) {
+        match write(std::slice::from_ref(row)).await {
+            Ok(n) => written += n,
+            Err(ExecError::Sql { message, .. } | ExecError::InvalidParams(message)) => {
+                dead.push(DeadRow {
+                    position,
+                    error: message,
+                    row: Some(row.clone()),
+                    raw: None,
+                });
+            }
+            Err(err) => return Err((err, written)),
+        }
+    }
+    Ok(written)
+}
+
+/// Write the rejected rows to `to`: a file is replaced, a table on the
+/// target session is created if missing and appended to. A failure is
+/// reported in the summary; the rows written to the table stay.
+async fn write_dead_letter(
+    app: &Arc<App>,
+    target: &Target,
+    to: &DeadLetter,
+    rows: &[DeadRow],
+) -> DeadLetterSummary {
+    let written = match to {
+        DeadLetter::Path(path) => deadletter::write_file(path, rows).await,
+        DeadLetter::Table(_) if rows.is_empty() => Ok(()),
+        DeadLetter::Table(table) => {
+            let (session, cfg, opts) = (&target.conn_session, &target.session_cfg, &target.opts);
+            let values: Vec<Value> = rows.iter().map(DeadRow::to_value).collect();
+            let prepared = deadletter::create_table_sql(table)
+                .and_then(|create| Ok((create, sqlgen::build_copy_in(table, &values)?)));
+            match prepared {
+                Err(e) => Err(e),
+                Ok((create, (sql, data))) => {
+                    let created = app.executor.execute(session, cfg, &create, &[], opts).await;
+                    let copied = match created {
+                        Ok(_) => app.executor.copy_in(session, cfg, &sql, data, opts).await,
+                        Err(e) => Err(e),
+                    };
+                    copied.map(|_| ()).map_err(|e| e.to_string())
+                }
+            }
+        }
+    };
+    DeadLetterSummary {
+        to: to.clone(),
+        rows: if written.is_ok() { rows.len() } else { 0 },
+        error: written.err(),
+    }
+}
+
+/// Write `rows` with `COPY` as `write_transfer_batch` does, reading them back
+/// before the commit and adding both checksums to `verifier`. In fail mode a
+/// batch that reads back differently is rolled back, writes nothing and
+/// leaves its mismatch in `verifier`.
+async fn write_verified_batch(
+    app: &Arc<App>,
+    target: &Target,
+    table: &str,
+    rows: &[Value],
+    verifier: &mut Verifier,
+) -> Result<u64, ExecError> {
+    let prepared = sqlgen::row_columns(rows).and_then(|columns| {
+        let (sql, data) = sqlgen::build_copy_in(table, rows)?;
+        Ok((
+            columns,
+            sql,
+            data,
+            sqlgen::build_copy_readback(table, rows)?,
+        ))
+    });
+    let (columns, sql, data, readback_sql) = prepared.map_err(ExecError::InvalidParams)?;
+    let sent = RowHasher::of(rows, &columns);
+    let (copied, stored, kept) = {
+        let verifier = &*verifier;
+        let keep = |stored: &[Value]| verifier.accepts(&sent, &RowHasher::of(stored, &columns));
+        let readback = CopyReadback {
+            sql: &readback_sql,
+            keep: &keep,
+        };
+        let (session, cfg, opts) = (&target.conn_session, &target.session_cfg, &target.opts);
+        app.executor
+            .copy_in_verified(session, cfg, &sql, data, &readback, opts)
+            .await?
+    };
+    verifier.record(sent, RowHasher::of(&stored, &columns), kept);
+    Ok(if kept { copied } else { 0 })
+}
+
+async fn send_checksum_mismatch(app: &Arc<App>, id: &str, error: String, start: Instant) {
+    let _ = app
+        .writer
+        .send(Output::Error {
+            id: Some(id.to_string()),
+            error_code: "checksum_mismatch".to_string(),
+            error,
+            retryable: false,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+async fn send_import_failed(app: &Arc<App>, id: String, error: String, start: Instant) {
+    let _ = app
+        .writer
+        .send(Output::Error {
+            id: Some(id),
+            error_code: "import_failed".to_string(),
+            error,
+            retryable: false,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+/// Report an error that ends an `import` or batched insert, saying how far
+/// it got, as in `(after 2000 rows imported)`.
+async fn batches_aborted(
+    app: &Arc<App>,
+    id: &str,
+    target: &Target,
+    mut err: ExecError,
+    after: &str,
+    start: Instant,
+) {
+    let (ExecError::Connect(message)
+    | ExecError::InvalidParams(message)
+    | ExecError::Internal(message)
+    | ExecError::ApprovalRequired(message)
+    | ExecError::Sql { message, .. }) = &mut err;
+    message.push_str(&format!(" (after {after})"));
+    emit_exec_error(app, Some(id), &target.session_name, err, start).await;
+}
+
+async fn write_transfer_batch(
+    app: &Arc<App>,
+    target: &Target,
+    table: &str,
+    method: TransferMethod,
+    rows: &[Value],
+) -> Result<u64, ExecError> {
+    let (session, cfg, opts) = (&target.conn_session, &target.session_cfg, &target.opts);
+    match method {
+        TransferMethod::Copy => {
+            let (sql, data) =
+                sqlgen::build_copy_in(table, rows).map_err(ExecError::InvalidParams)?;
+            app.executor.copy_in(session, cfg, &sql, data, opts).await
+        }
+        TransferMethod::Insert => {
+            let (sql, params) = sqlgen::build_insert(table, rows, &sqlgen::OnConflict::Error, &[])
+                .map_err(ExecError::InvalidParams)?;
+            match app
+                .executor
+                .execute(session, cfg, &sql, &params, opts)
+                .await?
+            {
+                ExecOutcome::Command { affected } => Ok(affected as u64),
+                ExecOutcome::Rows(rows) => Ok(rows.len() as u64),
+            }
+        }
+    }
+}
+
+/// SQLite's default cap on bind parameters per statement.
+const SQLITE_MAX_VARIABLES: usize = 32_766;
+
+/// Run a query and write its rows into a table on a SQLite session,
+/// replacing the table, instead of returning them. Like `transfer`, a
+/// configured `default_limit` only applies when the query sets one.
+async fn materialize(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    sql: String,
+    params: Vec<Value>,
+    options: QueryOptions,
+    dest: MaterializeTarget,
+) {
+    let start = Instant::now();
+    let invalid = if options.stream_rows || options.store_result {
+        Some(
+            "materialize_to returns no rows; stream_rows and store_result are not supported"
+                .to_string(),
+        )
+    } else {
+        sqlgen::qualified_name(&dest.table).err()
+    };
+    if let Some(message) = invalid {
+        send_invalid_request(app, id.as_deref(), message, start).await;
+        return;
+    }
+    let Some(source) =
+        resolve_target(app, id.as_deref(), session.as_deref(), &options, start).await
+    else {
+        return;
+    };
+    let target_options = QueryOptions::default();
+    let Some(target) = resolve_target(
+        app,
+        id.as_deref(),
+        Some(&dest.session),
+        &target_options,
+        start,
+    )
+    .await
+    else {
+        return;
+    };
+    if target.session_cfg.sqlite_path.is_none() {
+        let message = format!(
+            "materialize_to session {} is not a sqlite session (set sqlite_path)",
+            dest.session
+        );
+        send_invalid_request(app, id.as_deref(), message, start).await;
+        return;
+    }
+
+    let mut source_opts = source.opts.clone();
+    if options.default_limit.is_none() {
+        source_opts.default_limit = None;
+    }
+    let result = app
+        .executor
+        .execute(
+            &source.conn_session,
+            &source.session_cfg,
+            &sql,
+            &params,
+            &source_opts,
+        )
+        .await;
+    let rows = match result {
+        Ok(ExecOutcome::Rows(rows)) => apply_default_limit(rows, &source_opts).0,
+        Ok(ExecOutcome::Command { .. }) => {
+            let message = "materialize_to needs a statement that returns rows".to_string();
+            send_invalid_request(app, id.as_deref(), message, start).await;
+            return;
+        }
+        Err(err) => {
+            emit_exec_error(app, id.as_deref(), &source.session_name, err, start).await;
+            return;
+        }
+    };
+    let (columns, create) = match sqlgen::row_columns(&rows)
+        .and_then(|c| Ok((c, sqlgen::build_create_table(&dest.table, &rows)?)))
+    {
+        Ok(built) => built,
+        Err(e) => {
+            let message = format!("cannot materialize: {e}");
+            send_invalid_request(app, id.as_deref(), message, start).await;
+            return;
+        }
+    };
+
+    let row_count = rows.len();
+    let written = async {
+        let (session, cfg, opts) = (&target.conn_session, &target.session_cfg, &target.opts);
+        let table = sqlgen::qualified_name(&dest.table).map_err(ExecError::InvalidParams)?;
+        let drop = format!("drop table if exists {table}");
+        app.executor.execute(session, cfg, &drop, &[], opts).await?;
+        app.executor
+            .execute(session, cfg, &create, &[], opts)
+            .await?;
+        let batch_rows = (SQLITE_MAX_VARIABLES / columns.len().max(1)).max(1);
+        for batch in rows.chunks(batch_rows) {
+            write_transfer_batch(app, &target, &dest.table, TransferMethod::Insert, batch).await?;
+        }
+        Ok::<_, ExecError>(())
+    }
+    .await;
+    if let Err(err) = written {
+        emit_exec_error(app, id.as_deref(), &target.session_name, err, start).await;
+        return;
+    }
+
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(row_count),
+        payload_bytes: None,
+        cache_age_ms: None,
+        timing: Timing::default(),
+    };
+    let _ = app
+        .writer
+        .send(Output::Materialized {
+            id: id.clone(),
+            session: source.session_name,
+            target_session: target.session_name.clone(),
+            table: dest.table,
+            columns,
+            row_count,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.materialized",
+        id.as_deref(),
+        Some(&target.session_name),
+        None,
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// Run a statement for a tool's own use (catalog lookups, key inference) and
+/// return its rows without emitting any output events.
+pub async fn fetch_rows(
+    app: &Arc<App>,
+    session: Option<&str>,
+    sql: &str,
+    params: &[Value],
+    options: &QueryOptions,
+) -> Result<Vec<Value>, ExecError> {
+    let cfg = app.config.read().await.clone();
+    let session_name = resolve_session_name(&cfg, session);
+    let mut opts = cfg.resolve_options(options);
+    // Catalog lookups for helper tools must see every row.
+    opts.default_limit = None;
+    let Some(session_cfg) = cfg.sessions.get(&session_name).cloned() else {
+        return Err(ExecError::Connect(format!(
+            "unknown session: {session_name}"
+        )));
+    };
+    match app
+        .executor
+        .execute(&session_name, &session_cfg, sql, params, &opts)
+        .await?
+    {
+        ExecOutcome::Rows(rows) => Ok(rows),
+        ExecOutcome::Command { .. } => Ok(vec![]),
+    }
+}
+
+/// Open a workspace of `session` for a tool's own statements that must share
+/// one backend (such as hypopg's hypothetical indexes). It is not listed with
+/// the client's workspaces; pass its id as `workspace` to [`fetch_rows`] and
+/// end it with [`close_scratch_workspace`].
+pub async fn open_scratch_workspace(
+    app: &Arc<App>,
+    session: Option<&str>,
+) -> Result<String, ExecError> {
+    static SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let cfg = app.config.read().await.clone();
+    let session_name = resolve_session_name(&cfg, session);
+    let Some(session_cfg) = cfg.sessions.get(&session_name) else {
+        return Err(ExecError::Connect(format!(
+            "unknown session: {session_name}"
+        )));
+    };
+    let seq = SEQ.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let workspace = format!("{SCRATCH_WORKSPACE_PREFIX}{seq}");
+    app.executor
+        .open_workspace(&session_name, session_cfg, &workspace)
+        .await?;
+    Ok(workspace)
+}
+
+pub async fn close_scratch_workspace(app: &Arc<App>, workspace: &str) {
+    app.executor.close_workspace(workspace).await;
+}
+
+const SCRATCH_WORKSPACE_PREFIX: &str = "scratch:";
+
+/// [`fetch_rows`] for catalog lookups, served from the schema cache while
+/// `schema_cache_ttl_ms` allows and no DDL has run since.
+pub async fn fetch_catalog(
+    app: &Arc<App>,
+    session: Option<&str>,
+    sql: &str,
+    params: &[Value],
+    options: &QueryOptions,
+) -> Result<Vec<Value>, ExecError> {
+    let (session_name, ttl_ms) = {
+        let cfg = app.config.read().await;
+        (resolve_session_name(&cfg, session), cfg.schema_cache_ttl_ms)
+    };
+    if ttl_ms == 0 {
+        return fetch_rows(app, Some(&session_name), sql, params, options).await;
+    }
+    let ttl = std::time::Duration::from_millis(ttl_ms);
+    let generation = app.executor.schema_generation();
+    let cached = app
+        .schema_cache
+        .lock()
+        .await
+        .get(&session_name, sql, params, ttl, generation);
+    if let Some(rows) = cached {
+        return Ok(rows);
+    }
+    let rows = fetch_rows(app, Some(&session_name), sql, params, options).await?;
+    app.schema_cache
+        .lock()
+        .await
+        .insert(&session_name, sql, params, rows.clone(), ttl, generation);
+    Ok(rows)
+}
+
+/// `/* afpsql id=q1 session=default agent=name */ `, with characters that
+/// could end the comment early replaced, so request ids cannot inject SQL.
+pub fn query_annotation(id: Option<&str>, session: &str, agent: Option<&str>) -> String {
+    let clean = |value: &str| -> String {
+        value
+            .chars()
+            .take(64)
+            .map(|c| {
+                if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '@') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    let mut text = String::from("/* afpsql");
+    if let Some(id) = id {
+        text.push_str(&format!(" id={}", clean(id)));
+    }
+    text.push_str(&format!(" session={}", clean(session)));
+    if let Some(agent) = agent {
+        text.push_str(&format!(" agent={}", clean(agent)));
+    }
+    text.push_str(" */ ");
+    text
+}
+
+struct Target {
+    session_name: String,
+    /// Session whose connection runs the statement; differs from
+    /// `session_name` only under a `grant_elevated` with `via_session`.
+    conn_session: String,
+    session_cfg: SessionConfig,
+    opts: ResolvedOptions,
+    agent: Option<String>,
+}
+
+/// Count a sent result against the quota and budgets of its agent and
+/// session.
+async fn charge_usage(app: &Arc<App>, agent: Option<&str>, session: &str, trace: &Trace) {
+    let agent = agent.unwrap_or(quota::ANONYMOUS);
+    let rows = trace.row_count.unwrap_or(0) as u64;
+    let bytes = trace.payload_bytes.unwrap_or(0) as u64;
+    let now = Instant::now();
+    let (quota_key, scopes) = {
+        let cfg = app.config.read().await;
+        (
+            quota::quota_for(&cfg.agent_quotas, agent).map(|(key, _)| key.to_string()),
+            [
+                quota::quota_for(&cfg.agent_budgets, agent).map(|(key, _)| format!("agent:{key}")),
+                quota::quota_for(&cfg.session_budgets, session)
+                    .map(|(key, _)| format!("session:{key}")),
+            ],
+        )
+    };
+    if let Some(key) = quota_key {
+        app.quotas.lock().await.charge(&key, rows, bytes, now);
+    }
+    let mut budgets = app.budgets.lock().await;
+    for scope in scopes.into_iter().flatten() {
+        budgets.charge(&scope, rows, bytes, now);
+    }
+}
+
+/// The row security context a query runs with: the session's `context`
+/// and the query's own, which may add keys but not change the session's.
+/// Names must be custom settings (`prefix.name`).
+fn query_context(
+    session: Option<&BTreeMap<String, String>>,
+    query: Option<&BTreeMap<String, String>>,
+) -> Result<BTreeMap<String, String>, String> {
+    let mut context = session.cloned().unwrap_or_default();
+    for (name, value) in query.into_iter().flatten() {
+        match context.get(name) {
+            Some(fixed) if fixed != value => {
+                return Err(format!("context {name} is fixed by the session"));
+            }
+            Some(_) => {}
+            None => {
+                context.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    if let Some(name) = context.keys().find(|name| !is_custom_setting(name)) {
+        return Err(format!(
+            "context {name:?} must be a custom setting such as app.tenant_id"
+        ));
+    }
+    Ok(context)
+}
+
+/// Whether `name` is a dotted custom setting name, each part an identifier.
+fn is_custom_setting(name: &str) -> bool {
+    name.contains('.')
+        && name.split('.').all(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        })
+}
+
+/// The agent a request is attributed to: its own `agent`, else `agent_name`.
+fn request_agent(cfg: &RuntimeConfig, options: &QueryOptions) -> Option<String> {
+    options
+        .agent
+        .clone()
+        .filter(|agent| !agent.is_empty())
+        .or_else(|| cfg.agent_name.clone())
+}
+
+/// Resolve session and options for one request. Emits `backpressure` when
+/// result bytes held exceed the memory budget, `quota_exceeded` or
+/// `budget_exceeded` when the agent or session is over its limits, or
+/// `connect_failed` when the session is not configured, and returns `None`
+/// in those cases.
+async fn resolve_target(
+    app: &Arc<App>,
+    id: Option<&str>,
+    session: Option<&str>,
+    options: &QueryOptions,
+    start: Instant,
+) -> Option<Target> {
+    let cfg = app.config.read().await.clone();
+    let session_name = match &options.workspace {
+        None => resolve_session_name(&cfg, session),
+        Some(workspace) => {
+            let Some(ws_session) = app.workspaces.lock().await.touch(workspace) else {
+                let message = format!("workspace {workspace} is not open");
+                send_invalid_request(app, id, message, start).await;
+                return None;
+            };
+            if session.is_some_and(|s| s != ws_session) {
+                let message = format!("workspace {workspace} belongs to session {ws_session}");
+                send_invalid_request(app, id, message, start).await;
+                return None;
+            }
+            ws_session
+        }
+    };
+    let replication_role = options
+        .replication_role
+        .filter(|role| *role != ReplicationRole::Origin);
+    if let (Some(role), false) = (replication_role, cfg.allow_replication_role) {
+        let message = format!(
+            "replication_role {} needs --allow-replication-role at startup",
+            role.name()
+        );
+        send_invalid_request(app, id, message, start).await;
+        return None;
+    }
+    let mut opts = cfg.resolve_options(options);
+    let agent = request_agent(&cfg, options);
+    if cfg.annotate_queries {
+        opts.annotation = Some(query_annotation(id, &session_name, agent.as_deref()));
+    }
+
+    let held = app.memory.used();
+    if cfg.memory_budget_bytes > 0 && held >= cfg.memory_budget_bytes {
+        let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+        let _ = app
+            .writer
+            .send(Output::Error {
+                id: id.map(std::string::ToString::to_string),
+                error_code: "backpressure".to_string(),
+                error: format!(
+                    "{held} result bytes held, over memory_budget_bytes {}; retry later",
+                    cfg.memory_budget_bytes
+                ),
+                retryable: true,
+                trace: trace.clone(),
+            })
+            .await;
+        emit_log(
+            app,
+            "query.error",
+            id,
+            Some(&session_name),
+            Some("backpressure"),
+            None,
+            &trace,
+        )
+        .await;
+        return None;
+    }
+
+    let quota_agent = agent.as_deref().unwrap_or(quota::ANONYMOUS);
+    let now = Instant::now();
+    let mut refused = None;
+    if let Some((key, limit)) = quota::quota_for(&cfg.agent_quotas, quota_agent) {
+        if let Err(message) = app.quotas.lock().await.admit(key, limit, now) {
+            refused = Some(("quota_exceeded", message));
+        }
+    }
+    if refused.is_none() {
+        let scopes = [
+            quota::quota_for(&cfg.agent_budgets, quota_agent)
+                .map(|(key, budget)| (format!("agent:{key}"), budget)),
+            quota::quota_for(&cfg.session_budgets, &session_name)
+                .map(|(key, budget)| (format!("session:{key}"), budget)),
+        ];
+        let mut budgets = app.budgets.lock().await;
+        for (scope, budget) in scopes.into_iter().flatten() {
+            if let Err(message) = budgets.admit(&scope, budget, now) {
+                refused = Some(("budget_exceeded", message));
+                break;
+            }
+        }
+    }
+    if let Some((error_code, message)) = refused {
+        let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+        let _ = app
+            .writer
+            .send(Output::Error {
+                id: id.map(std::string::ToString::to_string),
+                error_code: error_code.to_string(),
+                error: message,
+                retryable: true,
+                trace: trace.clone(),
+            })
+            .await;
+        emit_log(
+            app,
+            "query.error",
+            id,
+            Some(&session_name),
+            Some(error_code),
+            None,
+            &trace,
+        )
+        .await;
+        return None;
+    }
+
+    if let Some(name) = options
+        .timeout_profile
+        .as_ref()
+        .filter(|n| !cfg.timeout_profiles.contains_key(*n))
+    {
+        let mut known: Vec<&str> = cfg.timeout_profiles.keys().map(String::as_str).collect();
+        known.sort_unstable();
+        send_invalid_request(
+            app,
+            id,
+            format!(
+                "unknown timeout_profile {name}; configured: {}",
+                known.join(", ")
+            ),
+            start,
+        )
+        .await;
+        return None;
+    }
+
+    if let Some(deadline) = &options.deadline {
+        match deadline.remaining_ms(unix_millis()) {
+            Err(message) => {
+                send_invalid_request(app, id, message, start).await;
+                return None;
+            }
+            Ok(0) => {
+                let message = "deadline already passed".to_string();
+                send_error(app, id, "deadline_exceeded", message, start).await;
+                let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+                emit_log(
+                    app,
+                    "query.error",
+                    id,
+                    Some(&session_name),
+                    Some("deadline_exceeded"),
+                    None,
+                    &trace,
+                )
+                .await;
+                return None;
+            }
+            // `0` means no statement timeout at all.
+            Ok(left) if opts.statement_timeout_ms == 0 => opts.statement_timeout_ms = left,
+            Ok(left) => opts.statement_timeout_ms = opts.statement_timeout_ms.min(left),
+        }
+    }
+
+    if opts.compress.is_some() && !compress::AVAILABLE {
+        send_invalid_request(
+            app,
+            id,
+            "compress requires a build with the compression feature".to_string(),
+            start,
+        )
+        .await;
+        return None;
+    }
+
+    if opts.store_result && opts.results_dir.is_none() {
+        send_invalid_request(
+            app,
+            id,
+            "store_result requires results_dir to be configured".to_string(),
+            start,
+        )
+        .await;
+        return None;
+    }
+
+    if let Some(uri) = opts.export_uri.as_deref() {
+        let checked = if export::AVAILABLE {
+            ObjectUri::parse(uri)
+                .map(|_| ())
+                .map_err(|e| format!("export_uri {e}"))
+        } else {
+            Err("export_uri requires a build with the object-store feature".to_string())
+        };
+        if let Err(message) = checked {
+            send_invalid_request(app, id, message, start).await;
+            return None;
+        }
+    }
+
+    if let Some(uri) = opts.publish_uri.as_deref() {
+        let checked = PublishUri::parse(uri)
+            .map_err(|e| format!("publish_uri {e}"))
+            .and_then(|target| match target.broker.available() {
+                true => Ok(()),
+                false => Err(format!(
+                    "publish_uri {uri} requires a build with the {} feature",
+                    target.broker.feature()
+                )),
+            });
+        if let Err(message) = checked {
+            send_invalid_request(app, id, message, start).await;
+            return None;
+        }
+    }
+
+    let Some(mut session_cfg) = cfg.sessions.get(&session_name).cloned() else {
+        let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+        let _ = app
+            .writer
+            .send(Output::Error {
+                id: id.map(std::string::ToString::to_string),
+                error_code: "connect_failed".to_string(),
+                error: format!("unknown session: {session_name}"),
+                retryable: true,
+                trace: trace.clone(),
+            })
+            .await;
+        emit_log(
+            app,
+            "query.error",
+            id,
+            Some(&session_name),
+            Some("connect_failed"),
+            None,
+            &trace,
+        )
+        .await;
+        return None;
+    };
+    match query_context(session_cfg.context.as_ref(), options.context.as_ref()) {
+        Ok(context) => opts.context = context,
+        Err(message) => {
+            send_invalid_request(app, id, message, start).await;
+            return None;
+        }
+    }
+
+    let mut conn_session = session_name.clone();
+    if let Some(elevation) = app.elevations.lock().await.active(&session_name) {
+        opts.role = elevation.role;
+        if let Some(via) = elevation.via_session {
+            if let Some(via_cfg) = cfg.sessions.get(&via) {
+                session_cfg = via_cfg.clone();
+                conn_session = via;
+            }
+        }
+    }
+
+    if opts.workspace.is_none() && session_cfg.pinned == Some(true) {
+        match ensure_pinned(app, &conn_session, &session_cfg, cfg.workspace_idle_ms).await {
+            Ok(workspace) => opts.workspace = Some(workspace),
+            Err(e) => {
+                emit_exec_error(app, id, &session_name, e, start).await;
+                return None;
+            }
+        }
+    }
+
+    if let Some(workspace) = &opts.workspace {
+        match app.executor.reconnect_workspace(workspace).await {
+            Ok(false) => {}
+            Ok(true) => {
+                let _ = app
+                    .writer
+                    .send(Output::SessionReconnected {
+                        id: id.map(std::string::ToString::to_string),
+                        session: session_name.clone(),
+                        workspace: workspace.clone(),
+                        trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+                    })
+                    .await;
+            }
+            Err(e) => {
+                emit_exec_error(app, id, &session_name, e, start).await;
+                return None;
+            }
+        }
+    }
+
+    Some(Target {
+        session_name,
+        conn_session,
+        session_cfg,
+        opts,
+        agent,
+    })
+}
+
+async fn emit_command_result(
+    app: &Arc<App>,
+    id: Option<&str>,
+    session: &str,
+    command_tag: String,
+    log_tag: &str,
+    start: Instant,
+    timing: Timing,
+) {
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(0),
+        payload_bytes: Some(0),
+        cache_age_ms: None,
+        timing,
+    };
+    let _ = app
+        .writer
+        .send(Output::Result {
+            id: id.map(std::string::ToString::to_string),
+            session: Some(session.to_string()),
+            command_tag,
+            columns: vec![],
+            rows: vec![],
+            row_count: 0,
+            limited: None,
+            warning: None,
+            cached: None,
+            trace: trace.clone(),
+            memory: MemoryReservation::default(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.result",
+        id,
+        Some(session),
+        None,
+        Some(log_tag),
+        &trace,
+    )
+    .await;
+}
+
+async fn emit_exec_error(
+    app: &Arc<App>,
+    id: Option<&str>,
+    session: &str,
+    err: ExecError,
+    start: Instant,
+) {
+    let trace = Trace::only_duration(start.elapsed().as_millis() as u64);
+    let error_code = exec_error_code(&err);
+    let (message, retryable) = match err {
+        ExecError::Connect(message) => (message, true),
+        ExecError::InvalidParams(message)
+        | ExecError::Internal(message)
+        | ExecError::ApprovalRequired(message) => (message, false),
+        ExecError::Sql {
+            sqlstate,
+            message,
+            detail,
+            hint,
+            position,
+        } => {
+            let _ = app
+                .writer
+                .send(Output::SqlError {
+                    id: id.map(std::string::ToString::to_string),
+                    session: Some(session.to_string()),
+                    sqlstate: sqlstate.clone(),
+                    message,
+                    detail,
+                    hint,
+                    position,
+                    trace: trace.clone(),
+                })
+                .await;
+            emit_log(
+                app,
+                "query.sql_error",
+                id,
+                Some(session),
+                Some(&sqlstate),
+                None,
+                &trace,
+            )
+            .await;
+            return;
+        }
+    };
+
+    let _ = app
+        .writer
+        .send(Output::Error {
+            id: id.map(std::string::ToString::to_string),
+            error_code: error_code.to_string(),
+            error: message,
+            retryable,
+            trace: trace.clone(),
+        })
+        .await;
+    emit_log(
+        app,
+        "query.error",
+        id,
+        Some(session),
+        Some(error_code),
+        None,
+        &trace,
+    )
+    .await;
+}
+
+/// `sql` with `options.idents` and `options.vars` substituted. Both are
+/// taken so that a statement handed on, e.g. to `approve`, is not expanded
+/// twice.
+fn expand_templates(sql: String, options: &mut QueryOptions) -> Result<String, String> {
+    let sql = match options.idents.take() {
+        Some(idents) => template::expand_idents(&sql, &idents)?,
+        None => sql,
+    };
+    match options.vars.take() {
+        Some(vars) => template::expand(&sql, &vars),
+        None => Ok(sql),
+    }
+}
+
+async fn send_invalid_request(app: &Arc<App>, id: Option<&str>, error: String, start: Instant) {
+    send_error(app, id, "invalid_request", error, start).await;
+}
+
+/// A non-retryable `error` event.
+async fn send_error(
+    app: &Arc<App>,
+    id: Option<&str>,
+    error_code: &str,
+    error: String,
+    start: Instant,
+) {
+    let _ = app
+        .writer
+        .send(Output::Error {
+            id: id.map(std::string::ToString::to_string),
+            error_code: error_code.to_string(),
+            error,
+            retryable: false,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        })
+        .await;
+}
+
+fn exec_error_code(err: &ExecError) -> &'static str {
+    match err {
+        ExecError::Connect(_) => "connect_failed",
+        ExecError::InvalidParams(_) => "invalid_params",
+        ExecError::Internal(_) => "invalid_request",
+        ExecError::Sql { .. } => "sql_error",
+        ExecError::ApprovalRequired(_) => "approval_required",
+    }
+}
+
+#[derive(Clone)]
+enum RowEmitStatus {
+    Sent {
+        trace: Trace,
+    },
+    Failed {
+        trace: Trace,
+        error_code: &'static str,
+    },
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn emit_rows_result(
     app: &Arc<App>,
     id: Option<String>,
     session: Option<String>,
     rows: Vec<Value>,
     start: Instant,
     opts: &ResolvedOptions,
+    cache_age: Option<std::time::Duration>,
+    timing: Timing,
 ) -> RowEmitStatus {
+    let cache_age_ms = cache_age.map(|age| age.as_millis() as u64);
+    let (mut rows, limited, warning) = apply_default_limit(rows, opts);
+    if let Some(key) = opts.dedup_baseline.clone() {
+        let capacity = app.config.read().await.diff_max_baselines;
+        let previous = app
+            .diff_baselines
+            .lock()
+            .await
+            .swap(key, rows.clone(), capacity);
+        if let Some(previous) = previous {
+            rows = diff::changed_rows(&previous, rows, &opts.key_columns);
+        }
+    }
+    if let Some(uri) = opts.export_uri.as_deref() {
+        return export_rows(
+            app,
+            id,
+            session,
+            rows,
+            uri,
+            opts,
+            start,
+            cache_age_ms,
+            timing,
+            limited,
+            warning,
+        )
+        .await;
+    }
+    if let Some(uri) = opts.publish_uri.as_deref() {
+        return publish_rows(
+            app,
+            id,
+            session,
+            rows,
+            uri,
+            opts,
+            start,
+            cache_age_ms,
+            timing,
+            limited,
+            warning,
+        )
+        .await;
+    }
+    if let Some(dir) = opts.results_dir.as_deref() {
+        if opts.store_result || (!opts.stream_rows && exceeds_inline(&rows, opts)) {
+            let columns = infer_columns(&rows);
+            let row_count = rows.len();
+            let handle = results::new_handle();
+            let saved = {
+                let dir = std::path::PathBuf::from(dir);
+                let handle = handle.clone();
+                let columns = columns.clone();
+                tokio::task::spawn_blocking(move || results::save(&dir, &handle, &columns, &rows))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|r| r.map_err(|e| e.to_string()))
+            };
+            let mut trace = Trace {
+                duration_ms: start.elapsed().as_millis() as u64,
+                row_count: Some(row_count),
+                payload_bytes: None,
+                cache_age_ms,
+                timing,
+            };
+            return match saved {
+                Ok(bytes) => {
+                    trace.payload_bytes = Some(bytes);
+                    let _ = app
+                        .writer
+                        .send(Output::ResultStored {
+                            id,
+                            session,
+                            handle,
+                            columns,
+                            row_count,
+                            limited,
+                            warning,
+                            trace: trace.clone(),
+                        })
+                        .await;
+                    RowEmitStatus::Sent { trace }
+                }
+                Err(e) => {
+                    let _ = app
+                        .writer
+                        .send(Output::Error {
+                            id,
+                            error_code: "result_store_failed".to_string(),
+                            error: format!("cannot save result to {dir}: {e}"),
+                            retryable: false,
+                            trace: trace.clone(),
+                        })
+                        .await;
+                    RowEmitStatus::Failed {
+                        trace,
+                        error_code: "result_store_failed",
+                    }
+                }
+            };
+        }
+    }
     if opts.stream_rows {
         let req_id = id.clone().unwrap_or_else(|| "cli".to_string());
         let columns = infer_columns(&rows);
+        // Only streams with a request id can be asked for again.
+        let resume_budget = match id {
+            Some(_) => app.config.read().await.resume_buffer_bytes,
+            None => 0,
+        };
+        if resume_budget > 0 {
+            app.streams
+                .lock()
+                .await
+                .begin(&req_id, session.clone(), columns.clone(), opts);
+        }
+        let ack_window = opts.ack_window.filter(|_| id.is_some());
+        if ack_window.is_some() {
+            app.acks.begin(&req_id);
+        }
         let _ = app
             .writer
             .send(Output::ResultStart {
                 id: req_id.clone(),
                 session: session.clone(),
                 columns,
+                seq: 0,
             })
             .await;
 
@@ -279,7 +4705,9 @@ async fn emit_rows_result(
         let mut batch_bytes = 0usize;
         let mut total_bytes = 0usize;
         let mut row_count = 0usize;
+        let mut seq = 0u64;
 
+        let mut batches = vec![];
         for row in rows {
             let sz = serde_json::to_vec(&row).map(|b| b.len()).unwrap_or(0);
             batch_bytes += sz;
@@ -288,44 +4716,76 @@ async fn emit_rows_result(
             batch.push(row);
 
             if batch.len() >= opts.batch_rows || batch_bytes >= opts.batch_bytes {
-                let n = batch.len();
+                batches.push((std::mem::take(&mut batch), batch_bytes));
+                batch_bytes = 0;
+            }
+        }
+        if !batch.is_empty() {
+            batches.push((batch, batch_bytes));
+        }
+
+        for (rows, bytes) in batches {
+            seq += 1;
+            if let Some(window) = ack_window {
+                app.acks.wait_for(&req_id, seq.saturating_sub(window)).await;
+            }
+            if resume_budget > 0 {
+                app.streams
+                    .lock()
+                    .await
+                    .append(&req_id, rows.clone(), bytes, resume_budget);
+            }
+            let out = rows_batch(app, &req_id, seq, rows, bytes, opts);
+            if send_rows(app, out, opts).await.is_err() {
+                if ack_window.is_some() {
+                    app.acks.finish(&req_id);
+                }
+                let trace = Trace {
+                    duration_ms: start.elapsed().as_millis() as u64,
+                    row_count: Some(row_count),
+                    payload_bytes: Some(total_bytes),
+                    cache_age_ms,
+                    timing,
+                };
                 let _ = app
                     .writer
-                    .send(Output::ResultRows {
-                        id: req_id.clone(),
-                        rows: std::mem::take(&mut batch),
-                        rows_batch_count: n,
+                    .send(Output::Error {
+                        id,
+                        error_code: "writer_full".to_string(),
+                        error: format!("output channel full; stream stopped after seq {}", seq - 1),
+                        retryable: true,
+                        trace: trace.clone(),
                     })
                     .await;
-                batch_bytes = 0;
+                return RowEmitStatus::Failed {
+                    trace,
+                    error_code: "writer_full",
+                };
             }
         }
 
-        for tail in std::iter::once(batch).filter(|r| !r.is_empty()) {
-            let n = tail.len();
-            let _ = app
-                .writer
-                .send(Output::ResultRows {
-                    id: req_id.clone(),
-                    rows: tail,
-                    rows_batch_count: n,
-                })
-                .await;
-        }
-
         let trace = Trace {
             duration_ms: start.elapsed().as_millis() as u64,
             row_count: Some(row_count),
             payload_bytes: Some(total_bytes),
+            cache_age_ms,
+            timing,
         };
+        let end = StreamEnd {
+            command_tag: format!("ROWS {row_count}"),
+            limited,
+            warning,
+            trace: trace.clone(),
+        };
+        if resume_budget > 0 {
+            app.streams.lock().await.finish(&req_id, end.clone());
+        }
+        if ack_window.is_some() {
+            app.acks.finish(&req_id);
+        }
         let _ = app
             .writer
-            .send(Output::ResultEnd {
-                id: req_id,
-                session,
-                command_tag: format!("ROWS {row_count}"),
-                trace: trace.clone(),
-            })
+            .send(stream_end(req_id, seq + 1, session, end))
             .await;
 
         return RowEmitStatus::Sent { trace };
@@ -342,18 +4802,50 @@ async fn emit_rows_result(
             duration_ms: start.elapsed().as_millis() as u64,
             row_count: Some(rows.len()),
             payload_bytes: Some(payload_bytes),
+            cache_age_ms,
+            timing,
         };
+        if let Some(n) = opts.summarize {
+            // Both ends together stay within the inline row limit.
+            let (head, tail) = summary::head_tail(&rows, n.min(opts.inline_max_rows / 2));
+            let summary_bytes: usize = head
+                .iter()
+                .chain(&tail)
+                .map(|r| serde_json::to_vec(r).map(|b| b.len()).unwrap_or(0))
+                .sum();
+            let _ = app
+                .writer
+                .send(Output::ResultSummary {
+                    id,
+                    session,
+                    column_stats: summary::column_stats(&rows, &columns),
+                    columns,
+                    row_count: rows.len(),
+                    head,
+                    tail,
+                    limited,
+                    warning,
+                    trace: trace.clone(),
+                    memory: app.memory.reserve(summary_bytes),
+                })
+                .await;
+            return RowEmitStatus::Sent { trace };
+        }
         let _ = app
             .writer
             .send(Output::Error {
                 id,
                 error_code: "result_too_large".to_string(),
-                error: "result exceeds inline limits; retry with stream_rows=true".to_string(),
+                error: "result exceeds inline limits; retry with stream_rows=true or summarize"
+                    .to_string(),
                 retryable: false,
                 trace: trace.clone(),
             })
             .await;
-        return RowEmitStatus::TooLarge { trace };
+        return RowEmitStatus::Failed {
+            trace,
+            error_code: "result_too_large",
+        };
     }
 
     let row_count = rows.len();
@@ -361,6 +4853,8 @@ async fn emit_rows_result(
         duration_ms: start.elapsed().as_millis() as u64,
         row_count: Some(row_count),
         payload_bytes: Some(payload_bytes),
+        cache_age_ms,
+        timing,
     };
     let _ = app
         .writer
@@ -371,13 +4865,558 @@ async fn emit_rows_result(
             columns,
             rows,
             row_count,
+            limited,
+            warning,
+            cached: cache_age.map(|_| true),
             trace: trace.clone(),
+            memory: app.memory.reserve(payload_bytes),
         })
         .await;
 
     RowEmitStatus::Sent { trace }
 }
 
+/// Reply to a `diff_output` query: the full result the first time (or once
+/// its baseline was evicted), then only the rows added and removed since the
+/// previous run under the same key.
+#[allow(clippy::too_many_arguments)]
+async fn emit_rows_diff(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    key: String,
+    rows: Vec<Value>,
+    start: Instant,
+    opts: &ResolvedOptions,
+    timing: Timing,
+) -> RowEmitStatus {
+    let capacity = app.config.read().await.diff_max_baselines;
+    let (current, limited, warning) = apply_default_limit(rows.clone(), opts);
+    let previous = match capacity {
+        0 => None,
+        _ => app
+            .diff_baselines
+            .lock()
+            .await
+            .swap(key, current.clone(), capacity),
+    };
+    let Some(previous) = previous else {
+        return emit_rows_result(app, id, session, rows, start, opts, None, timing).await;
+    };
+    let (added, removed) = diff::row_diff(&previous, &current);
+    let changed = added.len() + removed.len();
+    let payload_bytes: usize = added
+        .iter()
+        .chain(&removed)
+        .map(|r| serde_json::to_vec(r).map(|b| b.len()).unwrap_or(0))
+        .sum();
+    let trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(current.len()),
+        payload_bytes: Some(payload_bytes),
+        cache_age_ms: None,
+        timing,
+    };
+    if changed > opts.inline_max_rows || payload_bytes > opts.inline_max_bytes {
+        let _ = app
+            .writer
+            .send(Output::Error {
+                id,
+                error_code: "result_too_large".to_string(),
+                error: "result diff exceeds inline limits; retry without diff_output".to_string(),
+                retryable: false,
+                trace: trace.clone(),
+            })
+            .await;
+        return RowEmitStatus::Failed {
+            trace,
+            error_code: "result_too_large",
+        };
+    }
+    let _ = app
+        .writer
+        .send(Output::ResultDiff {
+            id,
+            session,
+            added,
+            removed,
+            row_count: current.len(),
+            limited,
+            warning,
+            trace: trace.clone(),
+            memory: app.memory.reserve(payload_bytes),
+        })
+        .await;
+    RowEmitStatus::Sent { trace }
+}
+
+/// JSON bytes of `rows`, as charged against `memory_budget_bytes`.
+fn rows_bytes(rows: &[Value]) -> usize {
+    rows.iter()
+        .map(|r| serde_json::to_vec(r).map(|b| b.len()).unwrap_or(0))
+        .sum()
+}
+
+fn exceeds_inline(rows: &[Value], opts: &ResolvedOptions) -> bool {
+    if rows.len() > opts.inline_max_rows {
+        return true;
+    }
+    let mut payload_bytes = 0usize;
+    for row in rows {
+        payload_bytes += serde_json::to_vec(row).map(|b| b.len()).unwrap_or(0);
+        if payload_bytes > opts.inline_max_bytes {
+            return true;
+        }
+    }
+    false
+}
+
+/// Page through a result saved under `results_dir`; `limit` defaults to
+/// `inline_max_rows`.
+pub async fn result_get(
+    app: &Arc<App>,
+    id: String,
+    handle: String,
+    offset: usize,
+    limit: Option<usize>,
+) {
+    let start = Instant::now();
+    let (dir, default_limit) = {
+        let cfg = app.config.read().await;
+        (cfg.results_dir.clone(), cfg.inline_max_rows)
+    };
+    let Some(dir) = stored_result_dir(app, &id, &handle, dir, start).await else {
+        return;
+    };
+    let limit = limit.unwrap_or(default_limit).max(1);
+    let page = {
+        let handle = handle.clone();
+        tokio::task::spawn_blocking(move || results::read_page(&dir, &handle, offset, limit)).await
+    };
+    match page {
+        Ok(Ok(page)) => {
+            let payload_bytes: usize = page
+                .rows
+                .iter()
+                .map(|r| serde_json::to_vec(r).map(|b| b.len()).unwrap_or(0))
+                .sum();
+            let row_count = page.rows.len();
+            let _ = app
+                .writer
+                .send(Output::ResultPage {
+                    id,
+                    handle,
+                    columns: page.columns,
+                    rows: page.rows,
+                    offset,
+                    row_count,
+                    total_rows: page.total_rows,
+                    trace: Trace {
+                        duration_ms: start.elapsed().as_millis() as u64,
+                        row_count: Some(row_count),
+                        payload_bytes: Some(payload_bytes),
+                        cache_age_ms: None,
+                        timing: Timing::default(),
+                    },
+                    memory: app.memory.reserve(payload_bytes),
+                })
+                .await;
+        }
+        Ok(Err(e)) => {
+            send_invalid_request(app, Some(&id), stored_result_error(&handle, &e), start).await;
+        }
+        Err(e) => send_invalid_request(app, Some(&id), e.to_string(), start).await,
+    }
+}
+
+pub async fn result_delete(app: &Arc<App>, id: String, handle: String) {
+    let start = Instant::now();
+    let dir = app.config.read().await.results_dir.clone();
+    let Some(dir) = stored_result_dir(app, &id, &handle, dir, start).await else {
+        return;
+    };
+    match results::delete(&dir, &handle) {
+        Ok(()) => {
+            let _ = app
+                .writer
+                .send(Output::ResultDeleted {
+                    id,
+                    handle,
+                    trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+                })
+                .await;
+        }
+        Err(e) => {
+            send_invalid_request(app, Some(&id), stored_result_error(&handle, &e), start).await;
+        }
+    }
+}
+
+/// The store directory for a lookup, or `None` after emitting why the
+/// request cannot be served.
+async fn stored_result_dir(
+    app: &Arc<App>,
+    id: &str,
+    handle: &str,
+    dir: Option<String>,
+    start: Instant,
+) -> Option<std::path::PathBuf> {
+    let Some(dir) = dir else {
+        send_invalid_request(
+            app,
+            Some(id),
+            "results_dir is not configured".to_string(),
+            start,
+        )
+        .await;
+        return None;
+    };
+    if !results::valid_handle(handle) {
+        send_invalid_request(
+            app,
+            Some(id),
+            format!("invalid result handle: {handle}"),
+            start,
+        )
+        .await;
+        return None;
+    }
+    Some(std::path::PathBuf::from(dir))
+}
+
+fn stored_result_error(handle: &str, e: &std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        format!("unknown result handle: {handle}")
+    } else {
+        format!("cannot read result {handle}: {e}")
+    }
+}
+
+/// One `result_rows` event, compressed when requested. A batch that fails to
+/// compress is sent as plain rows.
+fn rows_batch(
+    app: &Arc<App>,
+    id: &str,
+    seq: u64,
+    rows: Vec<Value>,
+    bytes: usize,
+    opts: &ResolvedOptions,
+) -> Output {
+    let n = rows.len();
+    let keys = match opts.key_columns.is_empty() {
+        true => vec![],
+        false => rows
+            .iter()
+            .map(|row| diff::row_key(row, &opts.key_columns))
+            .collect(),
+    };
+    let compressed = opts
+        .compress
+        .and_then(|c| compress::compress_rows(&rows, c).ok());
+    Output::ResultRows {
+        id: id.to_string(),
+        seq,
+        rows: if compressed.is_some() { vec![] } else { rows },
+        rows_compressed: compressed,
+        keys,
+        rows_batch_count: n,
+        memory: app.memory.reserve(bytes),
+    }
+}
+
+fn stream_end(id: String, seq: u64, session: Option<String>, end: StreamEnd) -> Output {
+    Output::ResultEnd {
+        id,
+        seq,
+        session,
+        command_tag: end.command_tag,
+        limited: end.limited,
+        warning: end.warning,
+        trace: end.trace,
+    }
+}
+
+/// Resend the part of a retained stream from `from` on. A stream still being
+/// sent is resent as far as it got; the rest follows as usual.
+async fn resume_stream(app: &Arc<App>, id: Option<String>, from: u64) {
+    let start = Instant::now();
+    let Some(id) = id else {
+        let message = "resume_from needs the id of the streamed query".to_string();
+        send_invalid_request(app, None, message, start).await;
+        return;
+    };
+    let outputs = {
+        let streams = app.streams.lock().await;
+        streams.get(&id).map(|stream| {
+            let mut outputs = vec![];
+            if from == 0 {
+                outputs.push(Output::ResultStart {
+                    id: id.clone(),
+                    session: stream.session.clone(),
+                    columns: stream.columns.clone(),
+                    seq: 0,
+                });
+            }
+            let mut seq = 0u64;
+            for (rows, bytes) in &stream.batches {
+                seq += 1;
+                if seq >= from {
+                    let out = rows_batch(app, &id, seq, rows.clone(), *bytes, &stream.opts);
+                    outputs.push(out);
+                }
+            }
+            if let Some(end) = stream.end.clone().filter(|_| seq + 1 >= from) {
+                outputs.push(stream_end(id.clone(), seq + 1, stream.session.clone(), end));
+            }
+            (outputs, stream.opts.clone())
+        })
+    };
+    let Some((outputs, opts)) = outputs else {
+        let message = format!("no retained stream for id {id}; run the query again");
+        send_invalid_request(app, Some(&id), message, start).await;
+        return;
+    };
+    for out in outputs {
+        let _ = send_rows(app, out, &opts).await;
+    }
+}
+
+/// Send an event; a `result_rows` event goes in parts when its line is
+/// longer than `max_message_bytes`.
+async fn send_rows(
+    app: &Arc<App>,
+    out: Output,
+    opts: &ResolvedOptions,
+) -> Result<(), SendError<Output>> {
+    let (Some(max), Output::ResultRows { id, seq, .. }) = (opts.max_message_bytes, &out) else {
+        return app.writer.send(out).await;
+    };
+    let part = |part, last, chunk: String| Output::ResultRowsPart {
+        id: id.clone(),
+        seq: *seq,
+        part,
+        last,
+        memory: app.memory.reserve(chunk.len()),
+        chunk,
+    };
+    let envelope = serde_json::to_string(&part(usize::MAX, false, String::new()))
+        .map(|line| line.len())
+        .unwrap_or(0);
+    let Some(chunks) = writer::split_line(&out, max, max.saturating_sub(envelope)) else {
+        return app.writer.send(out).await;
+    };
+    let count = chunks.len();
+    for (n, chunk) in chunks.into_iter().enumerate() {
+        app.writer.send(part(n, n + 1 == count, chunk)).await?;
+    }
+    Ok(())
+}
+
+/// Enforce `default_limit`: truncate and flag `limited`, or keep every row
+/// and attach a warning.
+fn apply_default_limit(
+    mut rows: Vec<Value>,
+    opts: &ResolvedOptions,
+) -> (Vec<Value>, Option<bool>, Option<String>) {
+    let Some(limit) = opts.default_limit.filter(|n| rows.len() > *n) else {
+        return (rows, None, None);
+    };
+    match opts.default_limit_action {
+        DefaultLimitAction::Limit => {
+            rows.truncate(limit);
+            (rows, Some(true), None)
+        }
+        DefaultLimitAction::Warn => {
+            let warning = format!(
+                "{} rows exceed default_limit {limit}; add LIMIT or set default_limit=0",
+                rows.len()
+            );
+            (rows, None, Some(warning))
+        }
+    }
+}
+
+/// Upload `rows` to `uri` and reply with `result_exported`.
+#[allow(clippy::too_many_arguments)]
+async fn export_rows(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    rows: Vec<Value>,
+    uri: &str,
+    opts: &ResolvedOptions,
+    start: Instant,
+    cache_age_ms: Option<u64>,
+    timing: Timing,
+    limited: Option<bool>,
+    warning: Option<String>,
+) -> RowEmitStatus {
+    let columns = infer_columns(&rows);
+    let names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+    let row_count = rows.len();
+    let exported = upload_export(uri, &names, &rows, opts.verify).await;
+    let mut trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(row_count),
+        payload_bytes: None,
+        cache_age_ms,
+        timing,
+    };
+    match exported {
+        Ok((target, bytes, verification)) => {
+            trace.payload_bytes = Some(bytes as usize);
+            let _ = app
+                .writer
+                .send(Output::ResultExported {
+                    id,
+                    session,
+                    uri: uri.to_string(),
+                    format: target.format.name().to_string(),
+                    gzip: target.gzip,
+                    columns,
+                    row_count,
+                    limited,
+                    warning,
+                    verification,
+                    trace: trace.clone(),
+                })
+                .await;
+            RowEmitStatus::Sent { trace }
+        }
+        Err((error_code, error)) => {
+            let _ = app
+                .writer
+                .send(Output::Error {
+                    id,
+                    error_code: error_code.to_string(),
+                    error,
+                    retryable: true,
+                    trace: trace.clone(),
+                })
+                .await;
+            RowEmitStatus::Failed { trace, error_code }
+        }
+    }
+}
+
+/// Publish `rows` to `uri` and reply with `result_published`.
+#[allow(clippy::too_many_arguments)]
+async fn publish_rows(
+    app: &Arc<App>,
+    id: Option<String>,
+    session: Option<String>,
+    rows: Vec<Value>,
+    uri: &str,
+    opts: &ResolvedOptions,
+    start: Instant,
+    cache_age_ms: Option<u64>,
+    timing: Timing,
+    limited: Option<bool>,
+    warning: Option<String>,
+) -> RowEmitStatus {
+    let row_count = rows.len();
+    // Only a failed delivery is worth retrying; a bad key column is not.
+    let published = match PublishUri::parse(uri).and_then(|target| {
+        let messages = publish::messages(
+            &target,
+            &rows,
+            &opts.publish_key,
+            opts.publish_format,
+            opts.batch_rows,
+        )?;
+        Ok((target, messages))
+    }) {
+        Ok((target, messages)) => publish::publish(&target, &messages)
+            .await
+            .map(|bytes| (messages.len(), bytes))
+            .map_err(|e| (e, true)),
+        Err(e) => Err((e, false)),
+    };
+    let mut trace = Trace {
+        duration_ms: start.elapsed().as_millis() as u64,
+        row_count: Some(row_count),
+        payload_bytes: None,
+        cache_age_ms,
+        timing,
+    };
+    match published {
+        Ok((messages, bytes)) => {
+            trace.payload_bytes = Some(bytes as usize);
+            let _ = app
+                .writer
+                .send(Output::ResultPublished {
+                    id,
+                    session,
+                    uri: uri.to_string(),
+                    messages,
+                    row_count,
+                    limited,
+                    warning,
+                    trace: trace.clone(),
+                })
+                .await;
+            RowEmitStatus::Sent { trace }
+        }
+        Err((e, retryable)) => {
+            let error_code = "publish_failed";
+            let _ = app
+                .writer
+                .send(Output::Error {
+                    id,
+                    error_code: error_code.to_string(),
+                    error: format!("cannot publish result to {uri}: {e}"),
+                    retryable,
+                    trace: trace.clone(),
+                })
+                .await;
+            RowEmitStatus::Failed { trace, error_code }
+        }
+    }
+}
+
+/// Upload `rows` to `uri`. With `verify` the object is read back and its
+/// rows checksummed against `rows`; on a mismatch in fail mode it is
+/// deleted.
+async fn upload_export(
+    uri: &str,
+    columns: &[String],
+    rows: &[Value],
+    verify: Option<VerifyMode>,
+) -> Result<(ObjectUri, u64, Option<Verification>), (&'static str, String)> {
+    let failed = |e: String| {
+        (
+            "export_failed",
+            format!("cannot export result to {uri}: {e}"),
+        )
+    };
+    let target = ObjectUri::parse(uri).map_err(failed)?;
+    let bytes = export::upload(&target, columns, rows)
+        .await
+        .map_err(failed)?;
+    let Some(mode) = verify else {
+        return Ok((target, bytes, None));
+    };
+    // An empty CSV export has no header to read back.
+    let stored = match rows.is_empty() {
+        true => vec![],
+        false => import::read_object_rows(&target).await.map_err(failed)?,
+    };
+    let mut verifier = Verifier::new(mode);
+    let (sent, stored) = (
+        RowHasher::of(rows, columns),
+        RowHasher::of(&stored, columns),
+    );
+    verifier.record(sent, stored, verifier.accepts(&sent, &stored));
+    if let Some(mismatch) = verifier.mismatch() {
+        let deleted = match export::delete(&target).await {
+            Ok(()) => "the object was deleted".to_string(),
+            Err(e) => format!("deleting the object failed: {e}"),
+        };
+        return Err(("checksum_mismatch", format!("{uri}: {mismatch}; {deleted}")));
+    }
+    Ok((target, bytes, Some(verifier.report())))
+}
+
 fn infer_columns(rows: &[Value]) -> Vec<ColumnInfo> {
     let Some(Value::Object(first)) = rows.first() else {
         return vec![];
@@ -416,17 +5455,91 @@ async fn emit_log(
             session: session.map(std::string::ToString::to_string),
             error_code: error_code.map(std::string::ToString::to_string),
             command_tag: command_tag.map(std::string::ToString::to_string),
+            warning: None,
             version: None,
             argv: None,
             config: None,
             args: None,
             env: None,
+            statement: None,
             trace: trace.clone(),
         })
         .await;
 }
 
-fn log_enabled(filters: &[String], event: &str) -> bool {
+/// With `lint` on, send the findings for `sql` as a `lint` event; `false`
+/// when one of them has a `lint_block` severity and the query must not run.
+async fn lint_passes(
+    app: &Arc<App>,
+    id: Option<&str>,
+    session: &str,
+    sql: &str,
+    start: Instant,
+) -> bool {
+    let block = {
+        let cfg = app.config.read().await;
+        if !cfg.lint {
+            return true;
+        }
+        cfg.lint_block.clone()
+    };
+    let findings = lint::lint(sql);
+    if findings.is_empty() {
+        return true;
+    }
+    let blocking: Vec<&str> = findings
+        .iter()
+        .filter(|f| block.contains(&f.severity))
+        .map(|f| f.rule.as_str())
+        .collect();
+    let error = (!blocking.is_empty()).then(|| format!("blocked by lint: {}", blocking.join(", ")));
+    let _ = app
+        .writer
+        .send(Output::Lint {
+            id: id.map(std::string::ToString::to_string),
+            session: session.to_string(),
+            blocked: error.is_some(),
+            findings,
+        })
+        .await;
+    match error {
+        Some(error) => {
+            send_error(app, id, "lint_blocked", error, start).await;
+            false
+        }
+        None => true,
+    }
+}
+
+/// One `query.warning` log event per injection heuristic that fires on `sql`.
+async fn emit_injection_warnings(app: &Arc<App>, id: Option<&str>, session: &str, sql: &str) {
+    let findings = injection::analyze(sql);
+    if findings.is_empty() || !log_enabled(&app.config.read().await.log, "query.warning") {
+        return;
+    }
+    for finding in findings {
+        let _ = app
+            .writer
+            .send(Output::Log {
+                event: "query.warning".to_string(),
+                request_id: id.map(std::string::ToString::to_string),
+                session: Some(session.to_string()),
+                error_code: Some(finding.code.to_string()),
+                command_tag: None,
+                warning: Some(finding.message),
+                version: None,
+                argv: None,
+                config: None,
+                args: None,
+                env: None,
+                statement: None,
+                trace: Trace::only_duration(0),
+            })
+            .await;
+    }
+}
+
+pub(crate) fn log_enabled(filters: &[String], event: &str) -> bool {
     if filters.is_empty() {
         return false;
     }
@@ -0,0 +1,93 @@
+//! Bounded in-memory record of executed queries for `history_list` and
+//! `history_replay`.
+//!
+//! Entries keep the original params and options so a replay runs the exact
+//! same request, but only a fingerprint of the params is ever emitted.
+
+use crate::types::QueryOptions;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub seq: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub session: String,
+    pub sql: String,
+    pub params_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params_fingerprint: Option<String>,
+    /// `result`, `sql_error` or `error`.
+    pub outcome: String,
+    /// SQLSTATE for `sql_error`, `error_code` for `error`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row_count: Option<usize>,
+    pub duration_ms: u64,
+    pub started_at_ms: u64,
+    #[serde(skip)]
+    pub params: Vec<Value>,
+    #[serde(skip)]
+    pub options: QueryOptions,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    next_seq: u64,
+}
+
+impl History {
+    /// Assign the next sequence number and append, keeping at most
+    /// `capacity` entries (`0` keeps none).
+    pub fn record(&mut self, mut entry: HistoryEntry, capacity: usize) {
+        self.next_seq += 1;
+        entry.seq = self.next_seq;
+        self.entries.push_back(entry);
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The newest `limit` entries, oldest first.
+    pub fn recent(&self, limit: usize) -> Vec<HistoryEntry> {
+        let skip = self.entries.len().saturating_sub(limit);
+        self.entries.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn get(&self, seq: u64) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|e| e.seq == seq)
+    }
+}
+
+/// Short stable digest of the bound values, `None` when there are none.
+pub fn params_fingerprint(params: &[Value]) -> Option<String> {
+    if params.is_empty() {
+        return None;
+    }
+    let digest = Sha256::digest(serde_json::to_vec(params).unwrap_or_default());
+    Some(digest.iter().take(8).map(|b| format!("{b:02x}")).collect())
+}
+
+/// The JSON type of each bound value, for logs that must not show them.
+pub fn param_types(params: &[Value]) -> Vec<&'static str> {
+    params
+        .iter()
+        .map(|v| match v {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_history.rs"]
+mod tests;
@@ -0,0 +1,282 @@
+//! Rows read from CSV or NDJSON files for `import`.
+//!
+//! The source is a local path or an `s3://` / `gs://` URI; its extension
+//! picks the format as for `export_uri`, and a trailing `.gz` is gunzipped.
+//! A CSV file starts with a header naming its columns; an unquoted empty
+//! field is NULL and a quoted one the empty string. Records are parsed one at
+//! a time, so a malformed one is rejected on its own and the rest still load.
+
+use crate::export::{self, Format, ObjectUri};
+use serde_json::Value;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(PathBuf),
+    Object(ObjectUri),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSource {
+    pub location: Location,
+    pub format: Format,
+    pub gzip: bool,
+}
+
+impl ImportSource {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        if source.contains("://") {
+            let uri = ObjectUri::parse(source)?;
+            return Ok(Self {
+                format: uri.format,
+                gzip: uri.gzip,
+                location: Location::Object(uri),
+            });
+        }
+        let Some((format, gzip)) = export::file_format(source) else {
+            return Err(format!(
+                "{source:?} must end in .csv, .jsonl or .ndjson, optionally followed by .gz"
+            ));
+        };
+        Ok(Self {
+            location: Location::Local(PathBuf::from(source)),
+            format,
+            gzip,
+        })
+    }
+
+    /// The source's text, gunzipped.
+    pub async fn read(&self) -> Result<String, String> {
+        let raw = match &self.location {
+            Location::Local(path) => tokio::fs::read(path).await.map_err(|e| e.to_string())?,
+            Location::Object(uri) => export::download(uri).await?,
+        };
+        let raw = if self.gzip { gunzip(&raw)? } else { raw };
+        String::from_utf8(raw).map_err(|_| "not UTF-8 text".to_string())
+    }
+}
+
+/// Every row of the object at `uri`; a record that does not parse is an
+/// error.
+pub async fn read_object_rows(uri: &ObjectUri) -> Result<Vec<Value>, String> {
+    let source = ImportSource {
+        location: Location::Object(uri.clone()),
+        format: uri.format,
+        gzip: uri.gzip,
+    };
+    let text = source.read().await?;
+    records(&text, uri.format)?
+        .map(|record| match record {
+            Record::Row { row, .. } => Ok(row),
+            Record::Rejected { line, error, .. } => Err(format!("line {line}: {error}")),
+        })
+        .collect()
+}
+
+#[cfg(any(feature = "compression", feature = "object-store"))]
+fn gunzip(raw: &[u8]) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+
+    let mut out = vec![];
+    flate2::read::GzDecoder::new(raw)
+        .read_to_end(&mut out)
+        .map_err(|e| format!("gunzip failed: {e}"))?;
+    Ok(out)
+}
+
+#[cfg(not(any(feature = "compression", feature = "object-store")))]
+fn gunzip(_: &[u8]) -> Result<Vec<u8>, String> {
+    Err("gzipped sources require a build with the object-store feature".to_string())
+}
+
+/// One record of a source, with the line it starts on (1-based). A rejected
+/// one keeps its text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Row {
+        line: usize,
+        row: Value,
+    },
+    Rejected {
+        line: usize,
+        error: String,
+        raw: String,
+    },
+}
+
+/// The fields of a CSV record, `None` for unquoted empty ones, or why it is
+/// malformed.
+type CsvFields = Result<Vec<Option<String>>, String>;
+
+/// The records of a source's text, in order; blank lines are skipped.
+pub struct Records<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+    format: Format,
+    header: Vec<String>,
+}
+
+/// Records of `text`. A CSV header is read first and must name each column
+/// once.
+pub fn records(text: &str, format: Format) -> Result<Records<'_>, String> {
+    let mut records = Records {
+        text,
+        pos: 0,
+        line: 1,
+        format,
+        header: vec![],
+    };
+    if format == Format::Csv {
+        let Some((line, fields, _)) = records.next_csv() else {
+            return Err("CSV source has no header".to_string());
+        };
+        for name in fields.map_err(|e| format!("line {line}: {e}"))? {
+            let name = name.unwrap_or_default();
+            if name.is_empty() || records.header.contains(&name) {
+                return Err(format!("line {line}: empty or repeated column {name:?}"));
+            }
+            records.header.push(name);
+        }
+    }
+    Ok(records)
+}
+
+impl Records<'_> {
+    /// Skip blank lines; `false` at the end of the text.
+    fn skip_blank(&mut self) -> bool {
+        loop {
+            let rest = &self.text[self.pos..];
+            let blank = if rest.starts_with('\n') {
+                1
+            } else if rest.starts_with("\r\n") {
+                2
+            } else {
+                return !rest.is_empty();
+            };
+            self.pos += blank;
+            self.line += 1;
+        }
+    }
+
+    /// The next CSV record, the line it starts on and its text.
+    fn next_csv(&mut self) -> Option<(usize, CsvFields, &str)> {
+        if !self.skip_blank() {
+            return None;
+        }
+        let first_line = self.line;
+        let rest = &self.text[self.pos..];
+        let mut chars = rest.char_indices().peekable();
+        let mut end = rest.len();
+        let mut fields = vec![];
+        let mut field = String::new();
+        let (mut quoted, mut in_quotes) = (false, false);
+        let mut error = None;
+        while let Some((i, c)) = chars.next() {
+            if in_quotes {
+                match c {
+                    '"' if chars.next_if(|&(_, c)| c == '"').is_some() => field.push('"'),
+                    '"' => in_quotes = false,
+                    c => {
+                        if c == '\n' {
+                            self.line += 1;
+                        }
+                        field.push(c);
+                    }
+                }
+                continue;
+            }
+            match c {
+                '"' if field.is_empty() && !quoted => (quoted, in_quotes) = (true, true),
+                ',' => {
+                    fields.push((!field.is_empty() || quoted).then(|| std::mem::take(&mut field)));
+                    quoted = false;
+                }
+                '\r' if chars.peek().is_some_and(|&(_, c)| c == '\n') => {}
+                '\n' => {
+                    end = i + 1;
+                    break;
+                }
+                c => {
+                    if quoted || c == '"' {
+                        error.get_or_insert(
+                            "quote inside an unquoted field or text after a closing quote",
+                        );
+                    }
+                    field.push(c);
+                }
+            }
+        }
+        if in_quotes {
+            error = Some("unterminated quoted field");
+        }
+        fields.push((!field.is_empty() || quoted).then_some(field));
+        self.pos += end;
+        self.line += 1;
+        let fields = error.map_or(Ok(fields), |e| Err(e.to_string()));
+        Some((
+            first_line,
+            fields,
+            rest[..end].trim_end_matches(['\r', '\n']),
+        ))
+    }
+
+    fn next_ndjson(&mut self) -> Option<Record> {
+        if !self.skip_blank() {
+            return None;
+        }
+        let line = self.line;
+        let rest = &self.text[self.pos..];
+        let end = rest.find('\n').map_or(rest.len(), |n| n + 1);
+        self.pos += end;
+        self.line += 1;
+        let raw = rest[..end].trim();
+        let error = match serde_json::from_str::<Value>(raw) {
+            Ok(row @ Value::Object(_)) => return Some(Record::Row { line, row }),
+            Ok(_) => "not a JSON object".to_string(),
+            Err(e) => format!("invalid JSON: {e}"),
+        };
+        Some(Record::Rejected {
+            line,
+            error,
+            raw: raw.to_string(),
+        })
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        if self.format == Format::Ndjson {
+            return self.next_ndjson();
+        }
+        let (line, fields, raw) = self.next_csv()?;
+        let raw = raw.to_string();
+        Some(match fields {
+            Err(error) => Record::Rejected { line, error, raw },
+            Ok(fields) if fields.len() != self.header.len() => Record::Rejected {
+                line,
+                error: format!(
+                    "expected {} fields, found {}",
+                    self.header.len(),
+                    fields.len()
+                ),
+                raw,
+            },
+            Ok(fields) => {
+                let values = fields
+                    .into_iter()
+                    .map(|f| f.map_or(Value::Null, Value::String));
+                Record::Row {
+                    line,
+                    row: Value::Object(self.header.iter().cloned().zip(values).collect()),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_import.rs"]
+mod tests;
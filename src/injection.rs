@@ -0,0 +1,235 @@
+//! Heuristics for SQL that looks assembled by string concatenation.
+//!
+//! Enabled by `injection_warnings`; each finding becomes a `query.warning`
+//! log event. The checks only look at the statement text and never block it,
+//! so false positives cost a log line, not a failed query.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Tok {
+    /// Contents of a `'...'` or `E'...'` literal, unescaped.
+    Str(String),
+    /// Keyword or identifier, upper-cased.
+    Word(String),
+    Num(String),
+    Sym(char),
+    Comment,
+}
+
+/// Findings for `sql`, in statement order.
+pub fn analyze(sql: &str) -> Vec<Finding> {
+    let toks = tokenize(sql);
+    let mut findings = vec![];
+    for (i, (tok, _)) in toks.iter().enumerate() {
+        let next = |k: usize| toks.get(i + k).map(|(t, _)| t);
+        match tok {
+            Tok::Str(s) => {
+                if let Some(hint) = placeholder_like(s) {
+                    findings.push(Finding {
+                        code: "quoted_placeholder",
+                        message: format!(
+                            "literal '{}' contains {hint}; bind the value as a param instead",
+                            excerpt(s)
+                        ),
+                    });
+                }
+                if matches!(toks.get(i + 1), Some((Tok::Comment, false))) {
+                    findings.push(Finding {
+                        code: "comment_after_literal",
+                        message: format!(
+                            "comment directly after literal '{}' can cut off the rest of the statement",
+                            excerpt(s)
+                        ),
+                    });
+                }
+                if is_word(next(1), "UNION") {
+                    findings.push(Finding {
+                        code: "union_after_literal",
+                        message: format!("UNION directly after literal '{}'", excerpt(s)),
+                    });
+                }
+            }
+            Tok::Word(w) if w == "UNION" => {
+                let mut k = 1;
+                if is_word(next(k), "ALL") {
+                    k += 1;
+                }
+                if is_word(next(k), "SELECT")
+                    && is_word(next(k + 1), "NULL")
+                    && next(k + 2) == Some(&Tok::Sym(','))
+                    && is_word(next(k + 3), "NULL")
+                {
+                    findings.push(Finding {
+                        code: "union_null_padding",
+                        message: "UNION SELECT padded with NULL columns".to_string(),
+                    });
+                }
+            }
+            Tok::Word(w) if w == "OR" => {
+                let same_operands = match (next(1), next(2), next(3)) {
+                    (Some(a @ (Tok::Str(_) | Tok::Num(_))), Some(Tok::Sym('=')), Some(b)) => a == b,
+                    _ => false,
+                };
+                if same_operands {
+                    findings.push(Finding {
+                        code: "tautology",
+                        message: "OR condition compares a constant with itself".to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    findings
+}
+
+fn is_word(tok: Option<&Tok>, word: &str) -> bool {
+    matches!(tok, Some(Tok::Word(w)) if w == word)
+}
+
+/// What in `s` looks like an unexpanded or quoted placeholder.
+fn placeholder_like(s: &str) -> Option<&'static str> {
+    let bare = s.trim();
+    if bare.len() > 1 && bare.starts_with('$') && bare[1..].bytes().all(|b| b.is_ascii_digit()) {
+        return Some("a quoted $n placeholder");
+    }
+    if bare == "?" || bare == "%s" {
+        return Some("a quoted placeholder");
+    }
+    if s.contains("${") {
+        return Some("a ${...} template");
+    }
+    if s.contains("{{") {
+        return Some("a {{...}} template");
+    }
+    None
+}
+
+fn excerpt(s: &str) -> String {
+    const MAX: usize = 40;
+    match s.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+/// Tokens paired with whether whitespace preceded them. Dollar-quoted bodies
+/// and quoted identifiers are skipped: they hold code and names, not values.
+pub(crate) fn tokenize(sql: &str) -> Vec<(Tok, bool)> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut toks = vec![];
+    let mut i = 0;
+    let mut spaced = false;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            spaced = true;
+            i += 1;
+            continue;
+        }
+        if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            toks.push((Tok::Comment, spaced));
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+            toks.push((Tok::Comment, spaced));
+        } else if c == '\'' || (matches!(c, 'e' | 'E') && next == Some('\'')) {
+            let escapes = c != '\'';
+            i += if escapes { 2 } else { 1 };
+            let mut text = String::new();
+            while i < chars.len() {
+                match chars[i] {
+                    '\\' if escapes => {
+                        if let Some(&n) = chars.get(i + 1) {
+                            text.push(n);
+                        }
+                        i += 2;
+                    }
+                    '\'' if chars.get(i + 1) == Some(&'\'') => {
+                        text.push('\'');
+                        i += 2;
+                    }
+                    '\'' => {
+                        i += 1;
+                        break;
+                    }
+                    other => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            toks.push((Tok::Str(text), spaced));
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            i += 1;
+            toks.push((Tok::Word(String::new()), spaced));
+        } else if c == '$' && next.is_some_and(|n| n.is_ascii_digit()) {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            toks.push((Tok::Sym('$'), spaced));
+        } else if c == '$' {
+            let tag_end = chars[i + 1..]
+                .iter()
+                .position(|&t| t == '$')
+                .map(|p| i + 1 + p)
+                .filter(|&end| {
+                    chars[i + 1..end]
+                        .iter()
+                        .all(|t| t.is_alphanumeric() || *t == '_')
+                });
+            let Some(tag_end) = tag_end else {
+                toks.push((Tok::Sym('$'), spaced));
+                i += 1;
+                spaced = false;
+                continue;
+            };
+            let tag: Vec<char> = chars[i..=tag_end].to_vec();
+            i = tag_end + 1;
+            while i < chars.len() && !chars[i..].starts_with(&tag) {
+                i += 1;
+            }
+            i += tag.len();
+            toks.push((Tok::Word(String::new()), spaced));
+        } else if c.is_alphabetic() || c == '_' {
+            let begin = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '$')) {
+                i += 1;
+            }
+            let word: String = chars[begin..i].iter().collect();
+            toks.push((Tok::Word(word.to_uppercase()), spaced));
+        } else if c.is_ascii_digit() {
+            let begin = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            toks.push((Tok::Num(chars[begin..i].iter().collect()), spaced));
+        } else {
+            toks.push((Tok::Sym(c), spaced));
+            i += 1;
+        }
+        spaced = false;
+    }
+    toks
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_injection.rs"]
+mod tests;
@@ -0,0 +1,96 @@
+//! Library half of `afpsql`: the same runtime the binary drives from stdin,
+//! for Rust programs that want to embed it in-process.
+//!
+//! [`Client`] is the entry point. Requests produce the [`Output`] events that
+//! pipe mode would print, so code written against the JSONL protocol maps
+//! over directly:
+//!
+//! ```no_run
+//! use agent_first_psql::{Client, Output, QueryOptions, RuntimeConfig};
+//!
+//! # async fn run() {
+//! let mut client = Client::new(RuntimeConfig::default());
+//! let events = client
+//!     .query(None, "select 1 as n", vec![], QueryOptions::default())
+//!     .await;
+//! if let Some(Output::Result { rows, .. }) = events.first() {
+//!     println!("{rows:?}");
+//! }
+//! # }
+//! ```
+//!
+//! The default session connects per `DATABASE_URL` / `PG*` as in the binary;
+//! add sessions with [`Client::configure`].
+#![deny(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::disallowed_methods,
+    clippy::disallowed_macros
+)]
+
+mod ack;
+pub mod activity;
+pub mod advisor;
+pub mod anonymize;
+mod approval;
+mod audit;
+mod budget;
+mod cache;
+pub mod chaos;
+pub mod checks;
+mod checksum;
+pub mod client;
+mod compress;
+pub mod config;
+mod conn;
+mod contract;
+pub mod db;
+mod deadletter;
+pub mod deadline;
+mod diff;
+mod elevation;
+pub mod er;
+mod export;
+mod ext_types;
+pub mod golden;
+pub mod handler;
+pub mod history;
+mod import;
+mod injection;
+mod lint;
+mod logfile;
+pub mod memory;
+mod mock;
+pub mod notify;
+pub mod operations;
+pub mod plan;
+pub mod profile;
+mod publish;
+mod quota;
+mod redact;
+pub mod registry;
+mod replay;
+pub mod replication;
+mod results;
+mod resume;
+pub mod schedule;
+mod schema_cache;
+pub mod schema_summary;
+pub mod script;
+pub mod sqlgen;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod subject;
+mod summary;
+mod syslog;
+pub mod template;
+pub mod transcript;
+pub mod types;
+mod workspace;
+pub mod writer;
+
+pub use client::Client;
+pub use db::{DbExecutor, ExecError, ExecOutcome, PostgresExecutor};
+pub use registry::ExecutorRegistry;
+pub use types::{ConfigPatch, Input, Output, QueryOptions, RuntimeConfig, SessionConfig};
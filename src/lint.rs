@@ -0,0 +1,287 @@
+//! Lint rules checked before a query runs.
+//!
+//! Enabled by `lint`; the findings of a query are sent as one `lint` event
+//! ahead of its result, and a finding whose severity is in `lint_block`
+//! stops the query instead. Like the injection heuristics the rules read the
+//! statement's tokens only, without table or column types, so they describe
+//! what the text asks for rather than what the planner will do.
+
+use crate::injection::{tokenize, Tok};
+use crate::profile;
+use crate::types::{LintFinding, LintSeverity};
+
+/// Words that may come right before a predicate in a `WHERE`, `ON` or
+/// `HAVING` condition.
+const PREDICATE_START: &[&str] = &["WHERE", "AND", "OR", "NOT", "ON", "HAVING"];
+
+/// Words that end a `FROM` list.
+const FROM_END: &[&str] = &[
+    "WHERE",
+    "GROUP",
+    "HAVING",
+    "WINDOW",
+    "ORDER",
+    "LIMIT",
+    "OFFSET",
+    "FETCH",
+    "FOR",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+    "RETURNING",
+    "ON",
+    "USING",
+];
+
+/// Words that look like a function call before `(` but are not one.
+const NOT_FUNCTIONS: &[&str] = &[
+    "EXISTS", "NOT", "IN", "ANY", "ALL", "SOME", "AND", "OR", "ARRAY", "ROW", "VALUES",
+];
+
+/// Findings for `sql`, in statement order.
+pub fn lint(sql: &str) -> Vec<LintFinding> {
+    let _timer = profile::timer("lint");
+    let toks: Vec<Tok> = tokenize(sql)
+        .into_iter()
+        .map(|(tok, _)| tok)
+        .filter(|tok| *tok != Tok::Comment)
+        .collect();
+    let mut findings = vec![];
+    let mut cte_closed = vec![false; toks.len()];
+    let mut open: Vec<(usize, bool)> = vec![];
+    for (i, tok) in toks.iter().enumerate() {
+        let prev = |k: usize| i.checked_sub(k).and_then(|j| toks.get(j));
+        match tok {
+            Tok::Sym('(') => open.push((i, opens_cte(prev(1)))),
+            Tok::Sym(')') => cte_closed[i] = open.pop().is_some_and(|(_, cte)| cte),
+            Tok::Sym('*') if is_select_star(&toks, i) => findings.push(finding(
+                "select_star",
+                LintSeverity::Warning,
+                "SELECT * returns every column; name the ones needed",
+            )),
+            Tok::Word(w) if w == "UPDATE" || w == "DELETE" => {
+                let starts_statement = match prev(1) {
+                    None | Some(Tok::Sym(';')) => true,
+                    Some(Tok::Sym('(')) => opens_cte(prev(2)),
+                    Some(Tok::Sym(')')) => cte_closed[i - 1],
+                    _ => false,
+                };
+                if starts_statement && !clause_has(&toks, i + 1, &["WHERE"]) {
+                    findings.push(finding(
+                        "missing_where",
+                        LintSeverity::Error,
+                        &format!("{w} without WHERE changes every row of the table"),
+                    ));
+                }
+            }
+            Tok::Word(w) if w == "CROSS" && is_word(toks.get(i + 1), "JOIN") => {
+                findings.push(finding(
+                    "cross_join",
+                    LintSeverity::Warning,
+                    "CROSS JOIN pairs every row with every row",
+                ));
+            }
+            Tok::Word(w) if w == "FROM" && has_comma_join(&toks, i + 1) => {
+                findings.push(finding(
+                    "cross_join",
+                    LintSeverity::Warning,
+                    "tables listed with commas and no WHERE are cross joined",
+                ));
+            }
+            Tok::Word(w) if PREDICATE_START.contains(&w.as_str()) => {
+                predicate_findings(&toks, i + 1, &mut findings);
+            }
+            _ => {}
+        }
+    }
+    findings
+}
+
+fn finding(rule: &str, severity: LintSeverity, message: &str) -> LintFinding {
+    LintFinding {
+        rule: rule.to_string(),
+        severity,
+        message: message.to_string(),
+    }
+}
+
+fn is_word(tok: Option<&Tok>, word: &str) -> bool {
+    matches!(tok, Some(Tok::Word(w)) if w == word)
+}
+
+/// Whether a `(` after `tok` holds the body of a `WITH` query.
+fn opens_cte(tok: Option<&Tok>) -> bool {
+    is_word(tok, "AS") || is_word(tok, "MATERIALIZED")
+}
+
+/// `*` as a whole select list item: `SELECT *`, `SELECT DISTINCT *` or a
+/// `t.*` followed by `,` or `FROM`. `EXISTS (SELECT * ...)` reads no
+/// columns and is left alone.
+fn is_select_star(toks: &[Tok], i: usize) -> bool {
+    let prev = |k: usize| i.checked_sub(k).and_then(|j| toks.get(j));
+    if is_word(prev(1), "SELECT") {
+        return !(matches!(prev(2), Some(Tok::Sym('('))) && is_word(prev(3), "EXISTS"));
+    }
+    if is_word(prev(1), "DISTINCT") && is_word(prev(2), "SELECT") {
+        return true;
+    }
+    matches!(prev(1), Some(Tok::Sym('.')))
+        && matches!(prev(2), Some(Tok::Word(_)))
+        && (matches!(toks.get(i + 1), Some(Tok::Sym(','))) || is_word(toks.get(i + 1), "FROM"))
+}
+
+/// Whether one of `words` appears in the clause starting at `from`, at its
+/// own nesting level, before the statement or enclosing parenthesis ends.
+fn clause_has(toks: &[Tok], from: usize, words: &[&str]) -> bool {
+    let mut depth = 0usize;
+    for tok in &toks[from.min(toks.len())..] {
+        match tok {
+            Tok::Sym('(') => depth += 1,
+            Tok::Sym(')') if depth == 0 => return false,
+            Tok::Sym(')') => depth -= 1,
+            Tok::Sym(';') if depth == 0 => return false,
+            Tok::Word(w) if depth == 0 && words.contains(&w.as_str()) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// A `FROM a, b` list naming a second table without a `WHERE` to join them.
+/// A function after the comma, such as `unnest(a.tags)`, is not a table.
+fn has_comma_join(toks: &[Tok], from: usize) -> bool {
+    let mut depth = 0usize;
+    for (i, tok) in toks.iter().enumerate().skip(from) {
+        match tok {
+            Tok::Sym('(') => depth += 1,
+            Tok::Sym(')') if depth == 0 => return false,
+            Tok::Sym(')') => depth -= 1,
+            Tok::Sym(';') if depth == 0 => return false,
+            Tok::Word(w) if depth == 0 && FROM_END.contains(&w.as_str()) => return false,
+            Tok::Word(w) if depth == 0 && w == "JOIN" => return false,
+            Tok::Sym(',') if depth == 0 => {
+                let after = column_ref_end(toks, i + 1);
+                let is_table = after
+                    .is_some_and(|end| !matches!(toks.get(end), Some(Tok::Sym('('))))
+                    && !is_word(toks.get(i + 1), "LATERAL");
+                return is_table && !clause_has(toks, i + 1, &["WHERE"]);
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// End of a column or table reference (`name`, `t.name`, `s.t.name`)
+/// starting at `i`.
+fn column_ref_end(toks: &[Tok], i: usize) -> Option<usize> {
+    let mut end = i;
+    loop {
+        if !matches!(toks.get(end), Some(Tok::Word(_))) {
+            return None;
+        }
+        end += 1;
+        if matches!(toks.get(end), Some(Tok::Sym('.'))) {
+            end += 1;
+        } else {
+            return Some(end);
+        }
+    }
+}
+
+/// Whether a comparison starts at `i`: an operator, or `LIKE`, `ILIKE`,
+/// `IN` or `BETWEEN`.
+fn is_comparison(toks: &[Tok], i: usize) -> bool {
+    match toks.get(i) {
+        Some(Tok::Sym('=' | '<' | '>')) => true,
+        Some(Tok::Sym('!')) => matches!(toks.get(i + 1), Some(Tok::Sym('='))),
+        Some(Tok::Word(w)) => matches!(w.as_str(), "LIKE" | "ILIKE" | "IN" | "BETWEEN"),
+        _ => false,
+    }
+}
+
+/// Non-sargable predicates and implicit casts in the predicate at `i`.
+fn predicate_findings(toks: &[Tok], i: usize, findings: &mut Vec<LintFinding>) {
+    // f(column) op ...
+    if let Some(Tok::Word(f)) = toks.get(i) {
+        if !f.is_empty()
+            && !NOT_FUNCTIONS.contains(&f.as_str())
+            && matches!(toks.get(i + 1), Some(Tok::Sym('(')))
+        {
+            if let Some(end) = column_ref_end(toks, i + 2) {
+                if matches!(toks.get(end), Some(Tok::Sym(')'))) && is_comparison(toks, end + 1) {
+                    findings.push(finding(
+                        "non_sargable",
+                        LintSeverity::Warning,
+                        &format!(
+                            "{f}() around a column keeps a plain index on it from being used; \
+                             compare the column itself or index the expression"
+                        ),
+                    ));
+                    return;
+                }
+            }
+        }
+    }
+    let Some(end) = column_ref_end(toks, i) else {
+        // 1.5 = column
+        if matches!(toks.get(i), Some(Tok::Num(n)) if n.contains('.'))
+            && matches!(toks.get(i + 1), Some(Tok::Sym('=' | '<' | '>')))
+        {
+            let operand = (i + 2..i + 4).find(|&k| matches!(toks.get(k), Some(Tok::Word(_))));
+            if operand.is_some_and(|k| column_ref_end(toks, k).is_some()) {
+                findings.push(decimal_finding());
+            }
+        }
+        return;
+    };
+    // column::type op ...
+    if matches!(toks.get(end), Some(Tok::Sym(':')))
+        && matches!(toks.get(end + 1), Some(Tok::Sym(':')))
+        && matches!(toks.get(end + 2), Some(Tok::Word(_)))
+        && is_comparison(toks, end + 3)
+    {
+        findings.push(finding(
+            "non_sargable",
+            LintSeverity::Warning,
+            "a cast on a column keeps a plain index on it from being used; cast the other side",
+        ));
+        return;
+    }
+    if !is_comparison(toks, end) {
+        return;
+    }
+    let mut k = end + 1;
+    while matches!(toks.get(k), Some(Tok::Sym('=' | '<' | '>'))) {
+        k += 1;
+    }
+    match toks.get(k) {
+        // column LIKE '%...'
+        Some(Tok::Str(s))
+            if matches!(toks.get(end), Some(Tok::Word(w)) if w == "LIKE" || w == "ILIKE")
+                && (s.starts_with('%') || s.starts_with('_')) =>
+        {
+            findings.push(finding(
+                "non_sargable",
+                LintSeverity::Warning,
+                "a pattern with a leading wildcard cannot use a b-tree index",
+            ));
+        }
+        // column = 1.5
+        Some(Tok::Num(n)) if n.contains('.') => findings.push(decimal_finding()),
+        _ => {}
+    }
+}
+
+fn decimal_finding() -> LintFinding {
+    finding(
+        "implicit_cast",
+        LintSeverity::Warning,
+        "a decimal literal compared with an integer column casts the column to numeric, \
+         so its index is not used; compare with an integer instead",
+    )
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_lint.rs"]
+mod tests;
@@ -0,0 +1,120 @@
+//! Rolling log files for `log_file`.
+//!
+//! With `log_file` set, `log` events are appended there as JSON lines instead
+//! of going to the output stream, so a long-lived server keeps its log
+//! whether or not a client stores what it reads. A task of its own does the
+//! writing, so a slow disk never holds up a reply. Once the file would grow
+//! past `log_rotate_bytes`, or has been written for `log_rotate_ms`, it is
+//! renamed to `<log_file>.1`, older files move up to `.2`, `.3`, ... and
+//! those past `log_keep` are deleted.
+
+use crate::types::RuntimeConfig;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Where and when to roll, from the config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub path: String,
+    /// `0` never rolls by size.
+    pub rotate_bytes: u64,
+    /// `0` never rolls by age.
+    pub rotate_ms: u64,
+    /// Rolled files kept; `0` deletes the file instead of rolling it.
+    pub keep: usize,
+}
+
+impl Policy {
+    pub fn from_config(cfg: &RuntimeConfig) -> Option<Self> {
+        cfg.log_file.clone().map(|path| Self {
+            path,
+            rotate_bytes: cfg.log_rotate_bytes,
+            rotate_ms: cfg.log_rotate_ms,
+            keep: cfg.log_keep,
+        })
+    }
+}
+
+/// The file being written, opened on the first line after each roll.
+#[derive(Debug)]
+pub struct LogFile {
+    policy: Policy,
+    file: Option<File>,
+    written: u64,
+    opened: Instant,
+}
+
+impl LogFile {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            file: None,
+            written: 0,
+            opened: Instant::now(),
+        }
+    }
+
+    /// Append `line` and a newline, rolling the file first when it is due.
+    /// After a failure the file is opened again for the next line.
+    pub fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let written = self.append(line);
+        if written.is_err() {
+            self.file = None;
+        }
+        written
+    }
+
+    fn append(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.file.is_some() && self.due(len) {
+            self.roll()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.policy.path)?;
+                self.written = file.metadata()?.len();
+                self.opened = Instant::now();
+                self.file.insert(file)
+            }
+        };
+        file.write_all(line)?;
+        file.write_all(b"\n")?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn due(&self, len: u64) -> bool {
+        let Policy {
+            rotate_bytes,
+            rotate_ms,
+            ..
+        } = self.policy;
+        let full = rotate_bytes > 0 && self.written > 0 && self.written + len > rotate_bytes;
+        let old = rotate_ms > 0 && self.opened.elapsed() >= Duration::from_millis(rotate_ms);
+        full || old
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.file = None;
+        let path = &self.policy.path;
+        let keep = self.policy.keep;
+        if keep == 0 {
+            return fs::remove_file(path);
+        }
+        // Missing older files are fine: there may not be `keep` of them yet.
+        let _ = fs::remove_file(format!("{path}.{keep}"));
+        for n in (1..keep).rev() {
+            let _ = fs::rename(format!("{path}.{n}"), format!("{path}.{}", n + 1));
+        }
+        fs::rename(path, format!("{path}.1"))
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_logfile.rs"]
+mod tests;
@@ -1,3 +1,5 @@
+// `mcp::tools_list` is a single `json!` literal that outgrows the default.
+#![recursion_limit = "512"]
 #![deny(
     clippy::unwrap_used,
     clippy::expect_used,
@@ -7,68 +9,101 @@
 )]
 
 mod cli;
-mod config;
-mod conn;
-mod db;
-mod handler;
 #[cfg(feature = "mcp")]
 mod mcp;
-mod types;
-mod writer;
 
 use agent_first_data::OutputFormat;
+use agent_first_psql::deadline::Deadline;
+use agent_first_psql::handler::{self, App};
+use agent_first_psql::types::*;
+use agent_first_psql::{config, writer, DbExecutor, ExecutorRegistry};
 use cli::Mode;
-use handler::App;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::io::AsyncBufReadExt;
 use tokio::sync::mpsc;
-use types::*;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 const OUTPUT_CHANNEL_CAPACITY: usize = 4096;
 
+/// Exit codes, by the class of the first error event (see [`exit_code`]).
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONNECT: i32 = 3;
+const EXIT_SQL: i32 = 4;
+const EXIT_POLICY: i32 = 5;
+const EXIT_TIMEOUT: i32 = 6;
+
+const RUNTIME_CANCEL_GRACE_MS: u64 = 1000;
+
 #[tokio::main]
 async fn main() {
     let mode = match cli::parse_args() {
         Ok(m) => m,
         Err(e) => {
             emit_cli_error(&e, OutputFormat::Json);
-            std::process::exit(2);
+            std::process::exit(EXIT_USAGE);
         }
     };
 
     match mode {
-        Mode::Cli(req) => run_cli(req).await,
+        Mode::Cli(req) => run_cli(*req).await,
         Mode::Pipe(init) => run_pipe(init).await,
         #[cfg(feature = "mcp")]
-        Mode::Mcp(init) => mcp::run_mcp(init.session, init.log).await,
+        Mode::Mcp(init) => mcp::run_mcp(init).await,
     }
 }
 
 async fn run_cli(req: cli::CliRequest) {
     let cli::CliRequest {
-        sql,
+        statements,
+        parallel,
+        on_error,
         params,
-        options,
+        mut options,
         session,
         output: output_format,
         log,
+        redact,
+        redact_salt_secret,
+        results_dir,
+        require_approval,
+        audit_log,
+        agent_name,
+        executor,
+        record,
+        replay,
+        mock,
+        chaos,
+        max_runtime_ms,
         startup_argv,
         startup_args,
         startup_env,
         startup_requested,
     } = req;
 
-    let config = RuntimeConfig::default();
+    let mut config = RuntimeConfig::default();
+    select_executor(&mut config, executor, record, replay, mock);
+    config.chaos = chaos;
+    let executor = build_executor(&config, output_format);
     let (tx, mut rx) = mpsc::channel::<Output>(OUTPUT_CHANNEL_CAPACITY);
-    let app = Arc::new(App::new(config, tx));
+    let app = Arc::new(App::with_executor(config, tx, executor));
 
     let mut cfg = app.config.write().await;
     cfg.sessions.insert("default".to_string(), session.clone());
     if !log.is_empty() {
         cfg.log = log.clone();
     }
+    cfg.redact = redact;
+    cfg.redact_salt_secret = redact_salt_secret;
+    cfg.results_dir = results_dir;
+    cfg.require_approval = require_approval;
+    cfg.audit_log = audit_log;
+    cfg.agent_name = agent_name;
     let startup_config = cfg.clone();
     drop(cfg);
 
@@ -83,28 +118,123 @@ async fn run_cli(req: cli::CliRequest) {
         emit_output(&event, output_format);
     }
 
-    app.requests_total.fetch_add(1, Ordering::Relaxed);
-    handler::execute_query(
-        &app,
-        None,
-        Some("default".to_string()),
-        sql,
-        params,
-        options,
-    )
-    .await;
+    if let Some(ms) = max_runtime_ms {
+        options.deadline = Some(runtime_deadline(options.deadline, ms));
+    }
 
-    drop(app);
+    // Print events as they come so many statements cannot fill the channel.
+    let printer = tokio::spawn(async move {
+        let mut code = 0;
+        while let Some(event) = rx.recv().await {
+            if code == 0 {
+                code = exit_code(&event);
+            }
+            emit_output(&event, output_format);
+        }
+        code
+    });
 
-    let mut had_error = false;
-    while let Some(event) = rx.recv().await {
-        if matches!(event, Output::Error { .. } | Output::SqlError { .. }) {
-            had_error = true;
+    let start = Instant::now();
+    let run = async {
+        match on_error {
+            Some(on_error) => {
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                handler::run_script(&app, statements, params, options, on_error).await;
+            }
+            None => run_statements(&app, statements, parallel, params, options).await,
         }
-        emit_output(&event, output_format);
+    };
+    let timed_out = match max_runtime_ms {
+        Some(ms) => tokio::time::timeout(std::time::Duration::from_millis(ms), run)
+            .await
+            .is_err(),
+        None => {
+            run.await;
+            false
+        }
+    };
+
+    drop(app);
+    let code = printer.await.unwrap_or(EXIT_ERROR);
+
+    if timed_out {
+        let timeout = Output::Error {
+            id: None,
+            error_code: "max_runtime_exceeded".to_string(),
+            error: format!(
+                "query did not finish within --max-runtime-ms {}",
+                max_runtime_ms.unwrap_or_default()
+            ),
+            retryable: true,
+            trace: Trace::only_duration(start.elapsed().as_millis() as u64),
+        };
+        emit_output(&timeout, output_format);
+        std::process::exit(EXIT_TIMEOUT);
+    }
+    std::process::exit(code);
+}
+
+/// Run the CLI statements on the default session, at most `parallel` at a
+/// time. With more than one, each gets its index as request `id`.
+async fn run_statements(
+    app: &Arc<App>,
+    statements: Vec<String>,
+    parallel: usize,
+    params: Vec<serde_json::Value>,
+    options: QueryOptions,
+) {
+    let indexed = statements.len() > 1;
+    let slots = Arc::new(tokio::sync::Semaphore::new(parallel));
+    // Dropping the set, e.g. on `--max-runtime-ms`, aborts what is running.
+    let mut running = tokio::task::JoinSet::new();
+    for (index, sql) in statements.into_iter().enumerate() {
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            break;
+        };
+        let app = app.clone();
+        let id = indexed.then(|| index.to_string());
+        let params = params.clone();
+        let options = options.clone();
+        app.requests_total.fetch_add(1, Ordering::Relaxed);
+        running.spawn(async move {
+            handler::execute_query(&app, id, Some("default".to_string()), sql, params, options)
+                .await;
+            drop(slot);
+        });
+    }
+    while running.join_next().await.is_some() {}
+}
+
+/// Exit code for one CLI event; `0` for events that are not failures.
+fn exit_code(event: &Output) -> i32 {
+    match event {
+        Output::SqlError { sqlstate, .. } if sqlstate == "57014" => EXIT_TIMEOUT,
+        Output::SqlError { .. } => EXIT_SQL,
+        Output::ApprovalRequired { .. } => EXIT_POLICY,
+        Output::Error { error_code, .. } => match error_code.as_str() {
+            "connect_failed" | "connect_timeout" | "auth_failed" => EXIT_CONNECT,
+            "result_too_large" | "approval_denied" | "backpressure" | "quota_exceeded"
+            | "budget_exceeded" => EXIT_POLICY,
+            "deadline_exceeded" | "max_runtime_exceeded" => EXIT_TIMEOUT,
+            _ => EXIT_ERROR,
+        },
+        _ => 0,
     }
+}
 
-    std::process::exit(if had_error { 1 } else { 0 });
+/// `--max-runtime-ms` also caps the statement timeout, a little past the
+/// runtime, so the server cancels the statement even if it never notices
+/// the connection closing when afpsql exits. An earlier `deadline` wins.
+fn runtime_deadline(deadline: Option<Deadline>, max_runtime_ms: u64) -> Deadline {
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let cap_ms = now_ms + max_runtime_ms + RUNTIME_CANCEL_GRACE_MS;
+    match deadline {
+        // An invalid deadline is kept so the query reports it.
+        Some(d) if d.epoch_ms().map_or(true, |ms| ms <= cap_ms) => d,
+        _ => Deadline::EpochMs(cap_ms),
+    }
 }
 
 async fn run_pipe(init: cli::PipeInit) {
@@ -112,6 +242,24 @@ async fn run_pipe(init: cli::PipeInit) {
         output,
         session,
         log,
+        log_file,
+        redact,
+        redact_salt_secret,
+        results_dir,
+        state_dir,
+        require_approval,
+        audit_log,
+        approver_sessions,
+        approver_secret,
+        allow_replication_role,
+        allow_backend_signals,
+        agent_name,
+        executor,
+        record,
+        replay,
+        mock,
+        chaos,
+        eager_connect,
         startup_argv,
         startup_args,
         startup_env,
@@ -127,6 +275,21 @@ async fn run_pipe(init: cli::PipeInit) {
     if !log.is_empty() {
         config.log = log.clone();
     }
+    config.log_file = log_file;
+    config.redact = redact;
+    config.redact_salt_secret = redact_salt_secret;
+    config.results_dir = results_dir;
+    config.state_dir = state_dir;
+    config.require_approval = require_approval;
+    config.audit_log = audit_log;
+    config.approver_sessions = approver_sessions;
+    config.approver_secret = approver_secret;
+    config.allow_replication_role = allow_replication_role;
+    config.allow_backend_signals = allow_backend_signals;
+    config.agent_name = agent_name;
+    select_executor(&mut config, executor, record, replay, mock);
+    config.chaos = chaos;
+    let executor = build_executor(&config, output);
     let startup_config = config.clone();
 
     if !log.is_empty() || startup_requested {
@@ -143,7 +306,10 @@ async fn run_pipe(init: cli::PipeInit) {
     let (tx, rx) = mpsc::channel::<Output>(OUTPUT_CHANNEL_CAPACITY);
     tokio::spawn(writer::writer_task(rx, output));
 
-    let app = Arc::new(App::new(config, tx));
+    let app = Arc::new(App::with_executor(config, tx, executor));
+    connect_eager_sessions(&app, eager_connect, output).await;
+    handler::spawn_pool_maintenance(&app);
+    handler::spawn_scheduler(&app);
 
     let stdin = tokio::io::stdin();
     let reader = tokio::io::BufReader::new(stdin);
@@ -180,6 +346,9 @@ async fn run_pipe(init: cli::PipeInit) {
                 params,
                 options,
             } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
                 let app2 = app.clone();
                 app.requests_total.fetch_add(1, Ordering::Relaxed);
                 let key = id.clone();
@@ -188,10 +357,91 @@ async fn run_pipe(init: cli::PipeInit) {
                 });
                 app.in_flight.lock().await.insert(key, handle);
             }
+            Input::Fanout {
+                id,
+                sessions,
+                sql,
+                params,
+                options,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::fanout(&app2, id, sessions, sql, params, options).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::Transfer(mut request) => {
+                let Some(id) = handler::claim_id(&app, request.id).await else {
+                    continue;
+                };
+                request.id = id;
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = request.id.clone();
+                let task = tokio::spawn(async move {
+                    handler::transfer(&app2, *request).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::Import(mut request) => {
+                let Some(id) = handler::claim_id(&app, request.id).await else {
+                    continue;
+                };
+                request.id = id;
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = request.id.clone();
+                let task = tokio::spawn(async move {
+                    handler::import(&app2, *request).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::Archive(mut request) => {
+                let Some(id) = handler::claim_id(&app, request.id).await else {
+                    continue;
+                };
+                request.id = id;
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = request.id.clone();
+                let task = tokio::spawn(async move {
+                    handler::archive(&app2, *request).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::Pause { id } => handler::pause(&app, id).await,
+            Input::OperationsList { id } => handler::operations_list(&app, id).await,
+            Input::DebugStats { id } => handler::debug_stats(&app, id).await,
+            Input::Resume { id } => {
+                if !handler::resume_running(&app, &id).await {
+                    // The checkpoint is keyed by id, so a busy id is refused
+                    // whatever `duplicate_id_policy` says.
+                    let reject = DuplicateIdPolicy::Reject;
+                    let Some(id) = handler::claim_id_as(&app, id, reject).await else {
+                        continue;
+                    };
+                    let app2 = app.clone();
+                    app.requests_total.fetch_add(1, Ordering::Relaxed);
+                    let key = id.clone();
+                    let task = tokio::spawn(async move {
+                        handler::resume_saved(&app2, id).await;
+                    });
+                    app.in_flight.lock().await.insert(key, task);
+                }
+            }
             Input::Config(patch) => {
-                let mut cfg = app.config.write().await;
-                cfg.apply_update(patch);
-                let _ = app.writer.send(Output::Config(cfg.clone())).await;
+                let eager = handler::eager_sessions(&patch);
+                let cfg = app.apply_config(*patch).await;
+                let _ = app.writer.send(Output::Config(Box::new(cfg))).await;
+                if !eager.is_empty() {
+                    let report = handler::connect_sessions(&app, eager).await;
+                    let _ = app.writer.send(report).await;
+                }
             }
             Input::Cancel { id } => {
                 if let Some(handle) = app.in_flight.lock().await.remove(&id) {
@@ -219,6 +469,188 @@ async fn run_pipe(init: cli::PipeInit) {
                         .await;
                 }
             }
+            Input::Ack { id, seq } => app.acks.ack(&id, seq),
+            Input::ResultGet {
+                id,
+                handle,
+                offset,
+                limit,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::result_get(&app2, id, handle, offset, limit).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::Approve {
+                id,
+                token,
+                session,
+                approver_secret,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::approve(&app2, id, token, session, approver_secret).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::GrantElevated {
+                id,
+                session,
+                role,
+                via_session,
+                duration_ms,
+                reason,
+            } => {
+                handler::grant_elevated(&app, id, session, role, via_session, duration_ms, reason)
+                    .await;
+            }
+            Input::HistoryList { id, limit } => {
+                handler::history_list(&app, id, limit).await;
+            }
+            Input::TranscriptExport {
+                id,
+                param_style,
+                session,
+            } => {
+                handler::transcript_export(&app, id, param_style, session).await;
+            }
+            Input::HistoryReplay { id, seq } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::history_replay(&app2, id, seq).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::SnapshotBegin { id, session } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::snapshot_begin(&app2, id, session).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::SnapshotQuery {
+                id,
+                session,
+                snapshot,
+                sql,
+                params,
+                options,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                app.requests_total.fetch_add(1, Ordering::Relaxed);
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::snapshot_query(&app2, id, session, snapshot, sql, params, options)
+                        .await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::SnapshotEnd {
+                id,
+                session,
+                snapshot,
+            } => {
+                handler::snapshot_end(&app, id, session, snapshot).await;
+            }
+            Input::WorkspaceOpen {
+                id,
+                workspace,
+                session,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::workspace_open(&app2, id, workspace, session).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::WorkspaceClose { id, workspace } => {
+                handler::workspace_close(&app, id, workspace).await;
+            }
+            Input::Describe {
+                id,
+                session,
+                sql,
+                options,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::describe_statement(&app2, id, session, sql, options).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::PreparedList {
+                id,
+                session,
+                older_than_ms,
+            } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::prepared_list(&app2, id, session, older_than_ms).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::CommitPrepared { id, session, gid } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::finish_prepared(&app2, id, session, gid, true).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::RollbackPrepared { id, session, gid } => {
+                let Some(id) = handler::claim_id(&app, id).await else {
+                    continue;
+                };
+                let app2 = app.clone();
+                let key = id.clone();
+                let task = tokio::spawn(async move {
+                    handler::finish_prepared(&app2, id, session, gid, false).await;
+                });
+                app.in_flight.lock().await.insert(key, task);
+            }
+            Input::InvalidateCache { id, session } => {
+                handler::invalidate_cache(&app, id, session).await;
+            }
+            Input::ResultDelete { id, handle } => {
+                handler::result_delete(&app, id, handle).await;
+            }
             Input::Ping => {
                 let _ = app
                     .writer
@@ -227,6 +659,14 @@ async fn run_pipe(init: cli::PipeInit) {
                             uptime_s: app.start_time.elapsed().as_secs(),
                             requests_total: app.requests_total.load(Ordering::Relaxed),
                             in_flight: app.in_flight.lock().await.len(),
+                            result_bytes_queued: app.memory.used(),
+                            writer_saturated_total: app.writer.saturated_total(),
+                            writer_dropped_total: app.writer.dropped_total(),
+                            budgets_remaining: app
+                                .budgets
+                                .lock()
+                                .await
+                                .remaining(std::time::Instant::now()),
                         },
                     })
                     .await;
@@ -262,6 +702,29 @@ async fn run_pipe(init: cli::PipeInit) {
     tokio::time::sleep(std::time::Duration::from_millis(50)).await;
 }
 
+/// Connect the sessions marked `eager` (all of them with `--eager-connect`)
+/// before any input is read; if one fails, print the report and exit.
+async fn connect_eager_sessions(app: &Arc<App>, all: bool, format: OutputFormat) {
+    let mut sessions: Vec<String> = app
+        .config
+        .read()
+        .await
+        .sessions
+        .iter()
+        .filter(|(_, s)| all || s.eager == Some(true))
+        .map(|(name, _)| name.clone())
+        .collect();
+    if sessions.is_empty() {
+        return;
+    }
+    sessions.sort();
+    let report = handler::connect_sessions(app, sessions).await;
+    if matches!(&report, Output::ConnectReport { failed, .. } if !failed.is_empty()) {
+        emit_output(&report, format);
+        std::process::exit(EXIT_CONNECT);
+    }
+}
+
 fn has_session_override(session: &SessionConfig) -> bool {
     session.dsn_secret.is_some()
         || session.conninfo_secret.is_some()
@@ -285,15 +748,72 @@ fn build_startup_log(
         session: session.map(std::string::ToString::to_string),
         error_code: None,
         command_tag: None,
+        warning: None,
         version: Some(config::VERSION.to_string()),
-        argv: Some(argv.to_vec()),
+        argv: Some(redact_secret_argv(argv)),
         config: Some(serde_json::to_value(config).unwrap_or(serde_json::Value::Null)),
         args: Some(args.clone()),
         env: Some(env.clone()),
+        statement: None,
         trace: Trace::only_duration(0),
     }
 }
 
+/// `argv` with the values of `--*-secret` flags masked, as `args` has them.
+fn redact_secret_argv(argv: &[String]) -> Vec<String> {
+    let mut masked = Vec::with_capacity(argv.len());
+    let mut secret_next = false;
+    for arg in argv {
+        if std::mem::take(&mut secret_next) {
+            masked.push("***".to_string());
+        } else if let Some((flag, _)) = arg.split_once('=').filter(|(f, _)| f.ends_with("-secret"))
+        {
+            masked.push(format!("{flag}=***"));
+        } else {
+            secret_next = arg.starts_with("--") && arg.ends_with("-secret");
+            masked.push(arg.clone());
+        }
+    }
+    masked
+}
+
+/// Apply `--executor`, `--record`, `--replay` and `--mock`, which clap keeps
+/// exclusive.
+fn select_executor(
+    config: &mut RuntimeConfig,
+    executor: Option<String>,
+    record: Option<String>,
+    replay: Option<String>,
+    mock: Option<String>,
+) {
+    if let Some(executor) = executor {
+        config.executor = executor;
+    }
+    if record.is_some() {
+        config.executor = "record".to_string();
+        config.record_path = record;
+    }
+    if replay.is_some() {
+        config.executor = "replay".to_string();
+        config.replay_path = replay;
+    }
+    if mock.is_some() {
+        config.executor = "mock".to_string();
+        config.mock_path = mock;
+    }
+}
+
+/// The backend named by `config.executor`; exits on an unknown name.
+fn build_executor(config: &RuntimeConfig, format: OutputFormat) -> Arc<dyn DbExecutor> {
+    match ExecutorRegistry::default().build(config) {
+        Ok(executor) => executor,
+        Err(e) => {
+            emit_cli_error(&e, format);
+            std::process::exit(EXIT_USAGE);
+        }
+    }
+}
+
 fn emit_cli_error(msg: &str, format: OutputFormat) {
     let value = agent_first_data::build_cli_error(msg);
     let rendered = agent_first_data::cli_output(&value, format);
@@ -301,9 +821,7 @@ fn emit_cli_error(msg: &str, format: OutputFormat) {
 }
 
 fn emit_output(out: &Output, format: OutputFormat) {
-    let value = serde_json::to_value(out).unwrap_or(serde_json::Value::Null);
-    let rendered = agent_first_data::cli_output(&value, format);
-    println!("{rendered}");
+    let _ = writer::write_output(&mut std::io::stdout().lock(), out, format);
 }
 
 #[cfg(test)]
@@ -1,28 +1,69 @@
-use crate::config::VERSION;
-use crate::handler::{self, App};
-use crate::types::{
-    CloseTrace, ConfigPatch, Output, PongTrace, QueryOptions, RuntimeConfig, SessionConfig,
+use crate::cli::PipeInit;
+use agent_first_psql::activity;
+use agent_first_psql::advisor;
+use agent_first_psql::anonymize;
+use agent_first_psql::checks;
+use agent_first_psql::config::VERSION;
+use agent_first_psql::db::ExecError;
+use agent_first_psql::er;
+use agent_first_psql::golden;
+use agent_first_psql::handler::{self, App};
+use agent_first_psql::plan;
+use agent_first_psql::replication;
+use agent_first_psql::schema_summary;
+use agent_first_psql::sqlgen;
+use agent_first_psql::subject;
+use agent_first_psql::transcript::ParamStyle;
+use agent_first_psql::types::{
+    ArchiveRequest, ChunkedWriteRequest, Chunking, CloseTrace, ConfigPatch, DeadLetter,
+    ImportRequest, Output, PongTrace, QueryOptions, RedactAction, RedactionRule, RuntimeConfig,
+    SessionConfig, TransferMethod, TransferRequest,
 };
 use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tokio::io::AsyncBufReadExt;
 use tokio::sync::mpsc;
 
 const OUTPUT_CHANNEL_CAPACITY: usize = 1024;
 
-pub async fn run_mcp(session: SessionConfig, log: Vec<String>) {
+pub async fn run_mcp(init: PipeInit) {
     let mut config = RuntimeConfig::default();
-    if has_session_override(&session) {
+    if has_session_override(&init.session) {
         config
             .sessions
-            .insert(config.default_session.clone(), session);
+            .insert(config.default_session.clone(), init.session);
     }
-    if !log.is_empty() {
-        config.log = log;
+    if !init.log.is_empty() {
+        config.log = init.log;
     }
+    config.log_file = init.log_file;
+    config.redact = init.redact;
+    config.redact_salt_secret = init.redact_salt_secret;
+    config.results_dir = init.results_dir;
+    config.state_dir = init.state_dir;
+    config.require_approval = init.require_approval;
+    config.audit_log = init.audit_log;
+    config.approver_sessions = init.approver_sessions;
+    config.approver_secret = init.approver_secret;
+    config.allow_replication_role = init.allow_replication_role;
+    config.allow_backend_signals = init.allow_backend_signals;
+    config.agent_name = init.agent_name;
+    crate::select_executor(
+        &mut config,
+        init.executor,
+        init.record,
+        init.replay,
+        init.mock,
+    );
+    config.chaos = init.chaos;
+    let executor = crate::build_executor(&config, init.output);
 
     let (tx, mut rx) = mpsc::channel::<Output>(OUTPUT_CHANNEL_CAPACITY);
-    let app = Arc::new(App::new(config, tx));
+    let app = Arc::new(App::with_executor(config, tx, executor));
+    crate::connect_eager_sessions(&app, init.eager_connect, init.output).await;
+    handler::spawn_pool_maintenance(&app);
+    handler::spawn_scheduler(&app);
 
     let stdin = tokio::io::stdin();
     let reader = tokio::io::BufReader::new(stdin);
@@ -51,10 +92,24 @@ pub async fn run_mcp(session: SessionConfig, log: Vec<String>) {
 
         match method {
             "initialize" => {
+                // `--agent-name` wins over what the client calls itself.
+                if let Some(name) = params
+                    .pointer("/clientInfo/name")
+                    .and_then(Value::as_str)
+                    .filter(|name| !name.is_empty())
+                {
+                    let mut cfg = app.config.write().await;
+                    if cfg.agent_name.is_none() {
+                        cfg.agent_name = Some(name.to_string());
+                    }
+                }
                 let result = json!({
                     "protocolVersion": "2024-11-05",
                     "serverInfo": {"name": "afpsql", "version": VERSION},
-                    "capabilities": {"tools": {"listChanged": false}}
+                    "capabilities": {
+                        "tools": {"listChanged": false},
+                        "resources": {"listChanged": false}
+                    }
                 });
                 if let Some(id) = id {
                     write_json(&jsonrpc_result(id, result));
@@ -68,6 +123,14 @@ pub async fn run_mcp(session: SessionConfig, log: Vec<String>) {
                             uptime_s: app.start_time.elapsed().as_secs(),
                             requests_total: app.requests_total.load(std::sync::atomic::Ordering::Relaxed),
                             in_flight: 0,
+                            result_bytes_queued: app.memory.used(),
+                            writer_saturated_total: app.writer.saturated_total(),
+                            writer_dropped_total: app.writer.dropped_total(),
+                            budgets_remaining: app
+                                .budgets
+                                .lock()
+                                .await
+                                .remaining(std::time::Instant::now()),
                         }
                     });
                     write_json(&jsonrpc_result(id, result));
@@ -84,6 +147,34 @@ pub async fn run_mcp(session: SessionConfig, log: Vec<String>) {
                     write_json(&jsonrpc_result(id, result));
                 }
             }
+            "resources/list" => {
+                if let Some(id) = id {
+                    write_json(&jsonrpc_result(id, resources_list(&app).await));
+                }
+            }
+            "resources/templates/list" => {
+                if let Some(id) = id {
+                    let result = json!({"resourceTemplates": [{
+                        "uriTemplate": format!("{ER_GRAPH_URI}{{session}}/{{schema}}"),
+                        "name": "ER graph",
+                        "description": "Tables, primary keys and foreign key edges of one schema",
+                        "mimeType": "application/json"
+                    }]});
+                    write_json(&jsonrpc_result(id, result));
+                }
+            }
+            "resources/read" => {
+                if let Some(id) = id {
+                    let uri = params
+                        .get("uri")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default();
+                    match read_resource(&app, uri).await {
+                        Ok(result) => write_json(&jsonrpc_result(id, result)),
+                        Err(e) => write_json(&jsonrpc_error(Some(id), -32002, e)),
+                    }
+                }
+            }
             "shutdown" => {
                 if let Some(id) = id {
                     write_json(&jsonrpc_result(id, json!({})));
@@ -134,47 +225,14 @@ async fn handle_tool_call(
                 return tool_error("missing required argument: sql");
             };
 
-            let query_id = arguments
-                .get("id")
-                .and_then(Value::as_str)
-                .unwrap_or("mcp")
-                .to_string();
-            let session = arguments
-                .get("session")
-                .and_then(Value::as_str)
-                .map(std::string::ToString::to_string);
+            let query_id = request_id(&arguments);
+            let session = request_session(&arguments);
             let params_vec = arguments
                 .get("params")
                 .and_then(Value::as_array)
                 .cloned()
                 .unwrap_or_default();
-            let options = QueryOptions {
-                stream_rows: arguments
-                    .get("stream_rows")
-                    .and_then(Value::as_bool)
-                    .unwrap_or(false),
-                batch_rows: arguments
-                    .get("batch_rows")
-                    .and_then(Value::as_u64)
-                    .map(|v| v as usize),
-                batch_bytes: arguments
-                    .get("batch_bytes")
-                    .and_then(Value::as_u64)
-                    .map(|v| v as usize),
-                statement_timeout_ms: arguments
-                    .get("statement_timeout_ms")
-                    .and_then(Value::as_u64),
-                lock_timeout_ms: arguments.get("lock_timeout_ms").and_then(Value::as_u64),
-                read_only: arguments.get("read_only").and_then(Value::as_bool),
-                inline_max_rows: arguments
-                    .get("inline_max_rows")
-                    .and_then(Value::as_u64)
-                    .map(|v| v as usize),
-                inline_max_bytes: arguments
-                    .get("inline_max_bytes")
-                    .and_then(Value::as_u64)
-                    .map(|v| v as usize),
-            };
+            let options = query_options_from_args(&arguments);
 
             handler::execute_query(
                 app,
@@ -189,79 +247,3348 @@ async fn handle_tool_call(
             let outputs = drain_outputs(rx);
             tool_ok(json!({"events": outputs}))
         }
+        "psql_fanout" => {
+            let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+                return tool_error("missing required argument: sql");
+            };
+            let sessions: Vec<String> = arguments
+                .get("sessions")
+                .and_then(Value::as_array)
+                .map(|a| {
+                    a.iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            let params_vec = arguments
+                .get("params")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            handler::fanout(
+                app,
+                request_id(&arguments),
+                sessions,
+                sql.to_string(),
+                params_vec,
+                query_options_from_args(&arguments),
+            )
+            .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_transfer" => tool_transfer(app, rx, &arguments).await,
+        "psql_import" => tool_import(app, rx, &arguments).await,
+        "psql_archive" => tool_archive(app, rx, &arguments).await,
+        "psql_resume" => tool_resume(app, rx, &arguments).await,
+        "psql_operations" => {
+            handler::operations_list(app, request_id(&arguments)).await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_debug_stats" => {
+            handler::debug_stats(app, request_id(&arguments)).await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_execute_block" => tool_execute_block(app, rx, &arguments).await,
+        "psql_insert" => tool_insert(app, rx, &arguments).await,
+        "psql_upsert" => tool_upsert(app, rx, &arguments).await,
+        "psql_sample" => tool_sample(app, rx, &arguments).await,
+        "psql_column_stats" => tool_column_stats(app, rx, &arguments).await,
+        "psql_aggregate" => tool_aggregate(app, rx, &arguments).await,
+        "psql_plan_check" => tool_plan_check(app, &arguments).await,
+        "psql_suggest_indexes" => tool_suggest_indexes(app, &arguments).await,
+        "psql_replication_status" => tool_replication_status(app, &arguments).await,
+        "psql_activity" => tool_activity(app, &arguments).await,
+        "psql_er_graph" => tool_er_graph(app, &arguments).await,
+        "psql_schema_summary" => tool_schema_summary(app, &arguments).await,
+        "psql_format_sql" => tool_format_sql(&arguments),
+        "psql_assert_snapshot" => tool_assert_snapshot(app, rx, &arguments).await,
+        "psql_run_checks" => tool_run_checks(app, &arguments).await,
+        "psql_export_sample" => tool_export_sample(app, &arguments).await,
+        "psql_find_subject_data" => tool_find_subject_data(app, &arguments).await,
+        "psql_maintenance" => tool_maintenance(app, rx, &arguments).await,
+        "psql_search" => tool_search(app, rx, &arguments).await,
+        #[cfg(feature = "pgvector")]
+        "psql_vector_search" => tool_vector_search(app, rx, &arguments).await,
+        #[cfg(feature = "pgvector")]
+        "psql_vector_upsert" => tool_vector_upsert(app, rx, &arguments).await,
+        "psql_update" | "psql_delete" => tool_update_delete(app, rx, name, &arguments).await,
+        "psql_dequeue" => tool_dequeue(app, rx, &arguments).await,
+        "psql_complete" => tool_complete(app, rx, &arguments).await,
+        "psql_approve" => {
+            let Some(token) = arguments.get("token").and_then(Value::as_str) else {
+                return tool_error("missing required argument: token");
+            };
+            handler::approve(
+                app,
+                request_id(&arguments),
+                token.to_string(),
+                request_session(&arguments),
+                arguments
+                    .get("approver_secret")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            )
+            .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_grant_elevated" => {
+            let Some(reason) = arguments.get("reason").and_then(Value::as_str) else {
+                return tool_error("missing required argument: reason");
+            };
+            let Some(duration_ms) = arguments.get("duration_ms").and_then(Value::as_u64) else {
+                return tool_error("missing required argument: duration_ms");
+            };
+            let text = |key: &str| {
+                arguments
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            };
+            handler::grant_elevated(
+                app,
+                request_id(&arguments),
+                request_session(&arguments),
+                text("role"),
+                text("via_session"),
+                duration_ms,
+                reason.to_string(),
+            )
+            .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_history" => {
+            let query_id = request_id(&arguments);
+            match arguments
+                .get("action")
+                .and_then(Value::as_str)
+                .unwrap_or("list")
+            {
+                "list" => {
+                    let limit = arguments
+                        .get("limit")
+                        .and_then(Value::as_u64)
+                        .map(|v| v as usize);
+                    handler::history_list(app, query_id, limit).await;
+                }
+                "replay" => {
+                    let Some(seq) = arguments.get("seq").and_then(Value::as_u64) else {
+                        return tool_error("replay requires argument: seq");
+                    };
+                    handler::history_replay(app, query_id, seq).await;
+                }
+                other => return tool_error(&format!("unknown history action: {other}")),
+            }
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_snapshot" => {
+            let query_id = request_id(&arguments);
+            let session = request_session(&arguments);
+            let snapshot = arguments
+                .get("snapshot")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            match arguments.get("action").and_then(Value::as_str) {
+                Some("begin") => handler::snapshot_begin(app, query_id, session).await,
+                Some("query") => {
+                    let Some(snapshot) = snapshot else {
+                        return tool_error("query requires argument: snapshot");
+                    };
+                    let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+                        return tool_error("query requires argument: sql");
+                    };
+                    let params_vec = arguments
+                        .get("params")
+                        .and_then(Value::as_array)
+                        .cloned()
+                        .unwrap_or_default();
+                    handler::snapshot_query(
+                        app,
+                        query_id,
+                        session,
+                        snapshot,
+                        sql.to_string(),
+                        params_vec,
+                        query_options_from_args(&arguments),
+                    )
+                    .await;
+                }
+                Some("end") => {
+                    let Some(snapshot) = snapshot else {
+                        return tool_error("end requires argument: snapshot");
+                    };
+                    handler::snapshot_end(app, query_id, session, snapshot).await;
+                }
+                Some(other) => return tool_error(&format!("unknown snapshot action: {other}")),
+                None => return tool_error("missing required argument: action"),
+            }
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_workspace" => {
+            let Some(workspace) = arguments.get("workspace").and_then(Value::as_str) else {
+                return tool_error("missing required argument: workspace");
+            };
+            let query_id = request_id(&arguments);
+            match arguments.get("action").and_then(Value::as_str) {
+                Some("open") => {
+                    let session = request_session(&arguments);
+                    handler::workspace_open(app, query_id, workspace.to_string(), session).await;
+                }
+                Some("close") => {
+                    handler::workspace_close(app, query_id, workspace.to_string()).await;
+                }
+                Some(other) => return tool_error(&format!("unknown workspace action: {other}")),
+                None => return tool_error("missing required argument: action"),
+            }
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_describe_statement" => {
+            let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+                return tool_error("missing required argument: sql");
+            };
+            handler::describe_statement(
+                app,
+                request_id(&arguments),
+                request_session(&arguments),
+                sql.to_string(),
+                query_options_from_args(&arguments),
+            )
+            .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_prepared" => {
+            let query_id = request_id(&arguments);
+            let session = request_session(&arguments);
+            let gid = arguments
+                .get("gid")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            let action = arguments.get("action").and_then(Value::as_str);
+            match (action, gid) {
+                (Some("list"), _) => {
+                    let older_than_ms = arguments.get("older_than_ms").and_then(Value::as_u64);
+                    handler::prepared_list(app, query_id, session, older_than_ms).await;
+                }
+                (Some(action @ ("commit" | "rollback")), Some(gid)) => {
+                    let commit = action == "commit";
+                    handler::finish_prepared(app, query_id, session, gid, commit).await;
+                }
+                (Some(action @ ("commit" | "rollback")), None) => {
+                    return tool_error(&format!("{action} requires argument: gid"));
+                }
+                (Some(other), _) => {
+                    return tool_error(&format!("unknown prepared action: {other}"))
+                }
+                (None, _) => return tool_error("missing required argument: action"),
+            }
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_transcript_export" => {
+            let param_style = match arguments.get("param_style") {
+                None | Some(Value::Null) => ParamStyle::default(),
+                Some(v) => match serde_json::from_value(v.clone()) {
+                    Ok(style) => style,
+                    Err(_) => return tool_error("param_style must be inline or psql_vars"),
+                },
+            };
+            handler::transcript_export(
+                app,
+                request_id(&arguments),
+                param_style,
+                request_session(&arguments),
+            )
+            .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_result_get" => {
+            let Some(handle) = arguments.get("handle").and_then(Value::as_str) else {
+                return tool_error("missing required argument: handle");
+            };
+            let offset = arguments.get("offset").and_then(Value::as_u64).unwrap_or(0) as usize;
+            let limit = arguments
+                .get("limit")
+                .and_then(Value::as_u64)
+                .map(|v| v as usize);
+            handler::result_get(
+                app,
+                request_id(&arguments),
+                handle.to_string(),
+                offset,
+                limit,
+            )
+            .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_invalidate_cache" => {
+            handler::invalidate_cache(app, request_id(&arguments), request_session(&arguments))
+                .await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
+        "psql_result_delete" => {
+            let Some(handle) = arguments.get("handle").and_then(Value::as_str) else {
+                return tool_error("missing required argument: handle");
+            };
+            handler::result_delete(app, request_id(&arguments), handle.to_string()).await;
+            tool_ok(json!({"events": drain_outputs(rx)}))
+        }
         "psql_config" => {
             if !arguments.is_object() {
                 return tool_error("arguments must be an object");
             }
-            let mut cfg = app.config.write().await;
             let patch: ConfigPatch = match serde_json::from_value(arguments.clone()) {
                 Ok(v) => v,
                 Err(e) => return tool_error(&format!("invalid config patch: {e}")),
             };
-            if arguments
+            let eager = handler::eager_sessions(&patch);
+            let cfg = if arguments
                 .as_object()
                 .map(|m| !m.is_empty())
                 .unwrap_or(false)
             {
-                cfg.apply_update(patch);
+                app.apply_config(patch).await
+            } else {
+                app.config.read().await.clone()
+            };
+            if eager.is_empty() {
+                return tool_ok(json!({"config": cfg}));
             }
-            tool_ok(json!({"config": cfg.clone()}))
+            let report = handler::connect_sessions(app, eager).await;
+            tool_ok(json!({"config": cfg, "connect_report": report}))
         }
         other => tool_error(&format!("unknown tool: {other}")),
     }
 }
 
-fn drain_outputs(rx: &mut mpsc::Receiver<Output>) -> Vec<Value> {
-    let mut outputs = vec![];
-    while let Ok(msg) = rx.try_recv() {
-        outputs.push(serde_json::to_value(msg).unwrap_or(Value::Null));
+async fn tool_transfer(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+        return tool_error("missing required argument: sql");
+    };
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let columns = arguments
+        .get("columns")
+        .and_then(Value::as_object)
+        .map(|m| {
+            m.iter()
+                .filter_map(|(from, to)| Some((from.clone(), to.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+    let method = match arguments.get("method").and_then(Value::as_str) {
+        None | Some("copy") => TransferMethod::Copy,
+        Some("insert") => TransferMethod::Insert,
+        Some(other) => return tool_error(&format!("unknown transfer method: {other}")),
+    };
+    let text = |key: &str| {
+        arguments
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let request = TransferRequest {
+        id: request_id(arguments),
+        source_session: text("source_session"),
+        sql: sql.to_string(),
+        params: arguments
+            .get("params")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default(),
+        target_session: text("target_session"),
+        table: table.to_string(),
+        columns,
+        method,
+        batch_rows: arguments
+            .get("batch_rows")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        options: query_options_from_args(arguments),
+    };
+    handler::transfer(app, request).await;
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_import(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(source) = arguments.get("source").and_then(Value::as_str) else {
+        return tool_error("missing required argument: source");
+    };
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let dead_letter = match dead_letter_arg(arguments) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let request = ImportRequest {
+        id: request_id(arguments),
+        session: arguments
+            .get("session")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        source: source.to_string(),
+        table: table.to_string(),
+        columns: arguments
+            .get("columns")
+            .and_then(Value::as_object)
+            .map(|m| {
+                m.iter()
+                    .filter_map(|(from, to)| Some((from.clone(), to.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        batch_rows: arguments
+            .get("batch_rows")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        max_errors: arguments
+            .get("max_errors")
+            .and_then(Value::as_u64)
+            .map_or(0, |v| v as usize),
+        dead_letter,
+        options: query_options_from_args(arguments),
+    };
+    handler::import(app, request).await;
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_archive(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(archive_table) = arguments.get("archive_table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: archive_table");
+    };
+    let Some(filter) = arguments.get("where") else {
+        return tool_error("missing required argument: where");
+    };
+    let request = ArchiveRequest {
+        id: request_id(arguments),
+        session: request_session(arguments),
+        table: table.to_string(),
+        archive_table: archive_table.to_string(),
+        filter: filter.clone(),
+        batch_rows: arguments
+            .get("batch_rows")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        options: query_options_from_args(arguments),
+    };
+    handler::archive(app, request).await;
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_resume(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(id) = arguments.get("id").and_then(Value::as_str) else {
+        return tool_error("missing required argument: id");
+    };
+    handler::resume_saved(app, id.to_string()).await;
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_execute_block(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let Some(body) = arguments.get("body").and_then(Value::as_str) else {
+        return tool_error("missing required argument: body");
+    };
+    let vars = match arguments.get("vars") {
+        None | Some(Value::Null) => vec![],
+        Some(Value::Object(map)) => map
+            .iter()
+            .map(|(k, v)| {
+                let text = match v {
+                    Value::String(s) => s.clone(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                (k.clone(), text)
+            })
+            .collect(),
+        Some(_) => return tool_error("vars must be an object"),
+    };
+
+    handler::execute_block(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        body.to_string(),
+        vars,
+        query_options_from_args(arguments),
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+/// Rows at or above this count are loaded with COPY when no conflict
+/// handling or RETURNING is requested.
+const INSERT_COPY_THRESHOLD_ROWS: usize = 1000;
+
+async fn tool_insert(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(rows) = arguments.get("rows").and_then(Value::as_array) else {
+        return tool_error("missing required argument: rows (array of objects)");
+    };
+    let on_conflict = match sqlgen::OnConflict::from_value(arguments.get("on_conflict")) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let returning = match sqlgen::string_list(arguments.get("returning"), "returning") {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => return tool_error(&e),
+    };
+    let copy_threshold = arguments
+        .get("copy_threshold_rows")
+        .and_then(Value::as_u64)
+        .map(|v| v as usize)
+        .unwrap_or(INSERT_COPY_THRESHOLD_ROWS);
+
+    let id = Some(request_id(arguments));
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+    // A COPY over the approval threshold could only fail; as an INSERT it
+    // is parked for `psql_approve` instead.
+    let needs_approval = app
+        .config
+        .read()
+        .await
+        .resolve_options(&options)
+        .approval_row_threshold
+        .is_some_and(|threshold| rows.len() > threshold);
+    let use_copy = on_conflict == sqlgen::OnConflict::Error
+        && returning.is_empty()
+        && !needs_approval
+        && rows.len() >= copy_threshold
+        && sqlgen::row_columns(rows)
+            .map(|cols| sqlgen::rows_are_dense(rows, &cols))
+            .unwrap_or(false);
+
+    let dead_letter = match dead_letter_arg(arguments) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    if let Some(dead_letter) = dead_letter {
+        if !returning.is_empty() {
+            return tool_error("returning cannot be combined with dead_letter");
+        }
+        let batch_rows = arguments
+            .get("batch_rows")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize);
+        handler::insert_rows(
+            app,
+            request_id(arguments),
+            session,
+            table.to_string(),
+            rows.clone(),
+            on_conflict,
+            batch_rows,
+            dead_letter,
+            options,
+        )
+        .await;
+    } else if use_copy {
+        let (sql, data) = match sqlgen::build_copy_in(table, rows) {
+            Ok(v) => v,
+            Err(e) => return tool_error(&e),
+        };
+        handler::execute_copy_in(app, id, session, sql, data, options).await;
+    } else {
+        let (sql, params) = match sqlgen::build_insert(table, rows, &on_conflict, &returning) {
+            Ok(v) => v,
+            Err(e) => return tool_error(&e),
+        };
+        handler::execute_query(app, id, session, sql, params, options).await;
     }
-    outputs
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
 }
 
-fn tools_list() -> Value {
-    json!({
-        "tools": [
-            {
-                "name": "psql_query",
-                "description": "Execute one SQL statement with positional bind parameters.",
-                "inputSchema": {
-                    "type": "object",
-                    "required": ["sql"],
-                    "properties": {
-                        "id": {"type":"string"},
-                        "session": {"type":"string"},
-                        "sql": {"type":"string"},
-                        "params": {"type":"array"},
-                        "stream_rows": {"type":"boolean"},
-                        "batch_rows": {"type":"integer"},
-                        "batch_bytes": {"type":"integer"},
-                        "statement_timeout_ms": {"type":"integer"},
-                        "lock_timeout_ms": {"type":"integer"},
-                        "read_only": {"type":"boolean"},
-                        "inline_max_rows": {"type":"integer"},
-                        "inline_max_bytes": {"type":"integer"}
-                    }
-                }
-            },
-            {
-                "name": "psql_config",
-                "description": "Read/update runtime config.",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "default_session": {"type":"string"},
-                        "sessions": {"type":"object"},
-                        "inline_max_rows": {"type":"integer"},
-                        "inline_max_bytes": {"type":"integer"},
-                        "statement_timeout_ms": {"type":"integer"},
-                        "lock_timeout_ms": {"type":"integer"},
-                        "log": {"type":"array"}
-                    }
+/// The `dead_letter` argument: `{"path": "..."}` or `{"table": "..."}`.
+fn dead_letter_arg(arguments: &Value) -> Result<Option<DeadLetter>, String> {
+    match arguments.get("dead_letter") {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => serde_json::from_value(v.clone())
+            .map(Some)
+            .map_err(|_| "dead_letter must be {\"path\": ...} or {\"table\": ...}".to_string()),
+    }
+}
+
+async fn tool_upsert(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(rows) = arguments.get("rows").and_then(Value::as_array) else {
+        return tool_error("missing required argument: rows (array of objects)");
+    };
+    let columns = match sqlgen::row_columns(rows) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let update_columns =
+        match sqlgen::string_list(arguments.get("update_columns"), "update_columns") {
+            Ok(v) => v,
+            Err(e) => return tool_error(&e),
+        };
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+
+    let key = match conflict_key(app, arguments, table, &columns, &session, &options).await {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    let (sql, params) = match sqlgen::build_upsert(table, rows, &key, update_columns) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        session,
+        sql,
+        params,
+        options,
+    )
+    .await;
+
+    tool_ok(json!({"key": key, "events": drain_outputs(rx)}))
+}
+
+/// Explicit `key` argument, or the first primary/unique key covered by `columns`.
+async fn conflict_key(
+    app: &Arc<App>,
+    arguments: &Value,
+    table: &str,
+    columns: &[String],
+    session: &Option<String>,
+    options: &QueryOptions,
+) -> Result<Vec<String>, Value> {
+    match sqlgen::string_list(arguments.get("key"), "key") {
+        Ok(Some(key)) => Ok(key),
+        Ok(None) => {
+            let relation = sqlgen::qualified_name(table).map_err(|e| tool_error(&e))?;
+            let keys = handler::fetch_catalog(
+                app,
+                session.as_deref(),
+                sqlgen::UNIQUE_KEYS_SQL,
+                &[Value::String(relation)],
+                options,
+            )
+            .await
+            .map_err(|e| tool_error(&format!("key inference failed: {e}")))?;
+            sqlgen::pick_conflict_key(&keys, columns).ok_or_else(|| {
+                tool_error(
+                    "no primary key or unique index is fully covered by the row columns; pass key explicitly",
+                )
+            })
+        }
+        Err(e) => Err(tool_error(&e)),
+    }
+}
+
+async fn tool_sample(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let limit = arguments.get("n").and_then(Value::as_u64).unwrap_or(10);
+    let random = match arguments.get("mode").and_then(Value::as_str) {
+        None | Some("first") => false,
+        Some("random") => true,
+        Some(other) => {
+            return tool_error(&format!(
+                "unsupported mode '{other}', expected first|random"
+            ))
+        }
+    };
+    let (sql, params) = match sqlgen::build_sample(table, limit, random) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let relation = match sqlgen::qualified_name(table) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+
+    let stats = match handler::fetch_catalog(
+        app,
+        session.as_deref(),
+        sqlgen::COLUMN_STATS_SQL,
+        &[Value::String(relation)],
+        &options,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => return tool_error(&format!("column stats failed: {e}")),
+    };
+    let estimated_rows = stats
+        .first()
+        .and_then(|r| r.get("estimated_rows"))
+        .cloned()
+        .unwrap_or(Value::Null);
+    let rules = app.config.read().await.redact.clone();
+    let columns: Vec<Value> = stats
+        .into_iter()
+        .map(|mut r| {
+            if let Some(obj) = r.as_object_mut() {
+                obj.remove("estimated_rows");
+                let column = obj.get("column").and_then(Value::as_str).unwrap_or("");
+                if sqlgen::is_redacted(&rules, Some(table), column) {
+                    obj.insert("min".to_string(), Value::Null);
+                    obj.insert("max".to_string(), Value::Null);
                 }
             }
-        ]
-    })
+            r
+        })
+        .collect();
+
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        session,
+        sql,
+        params,
+        options,
+    )
+    .await;
+
+    tool_ok(json!({
+        "table": table,
+        "estimated_rows": estimated_rows,
+        "columns": columns,
+        "events": drain_outputs(rx),
+    }))
+}
+
+async fn tool_column_stats(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let table = arguments.get("table").and_then(Value::as_str);
+    let sql = arguments.get("sql").and_then(Value::as_str);
+    let source = match (table, sql) {
+        (Some(table), None) => sqlgen::RowSource::Table(table),
+        (None, Some(sql)) => sqlgen::RowSource::Query(sql),
+        _ => return tool_error("pass exactly one of table or sql"),
+    };
+    let columns = match sqlgen::string_list(arguments.get("columns"), "columns") {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let params = arguments
+        .get("params")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let rules = app.config.read().await.redact.clone();
+    let (sql, params) = match sqlgen::build_column_profile(
+        source,
+        params,
+        columns.as_deref(),
+        &rules,
+        arguments.get("top_k").and_then(Value::as_u64).unwrap_or(5),
+        arguments
+            .get("buckets")
+            .and_then(Value::as_u64)
+            .unwrap_or(10),
+        arguments.get("max_rows").and_then(Value::as_u64),
+    ) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql,
+        params,
+        query_options_from_args(arguments),
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_aggregate(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let table = arguments.get("table").and_then(Value::as_str);
+    let sql = arguments.get("sql").and_then(Value::as_str);
+    let source = match (table, sql) {
+        (Some(table), None) => sqlgen::RowSource::Table(table),
+        (None, Some(sql)) => sqlgen::RowSource::Query(sql),
+        _ => return tool_error("pass exactly one of table or sql"),
+    };
+    let group_by = match sqlgen::string_list(arguments.get("group_by"), "group_by") {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => return tool_error(&e),
+    };
+    let Some(raw_aggregates) = arguments.get("aggregates").and_then(Value::as_array) else {
+        return tool_error("missing required argument: aggregates (array of {fn, column, as})");
+    };
+    let aggregates = match raw_aggregates
+        .iter()
+        .map(sqlgen::AggregateSpec::from_value)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let rollup = arguments
+        .get("rollup")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let rules = app.config.read().await.redact.clone();
+    let pivot = arguments.get("pivot").and_then(Value::as_str);
+    if let Some(pivot) = pivot {
+        if !group_by.iter().any(|c| c == pivot) {
+            return tool_error("pivot must be one of the group_by columns");
+        }
+        if sqlgen::is_redacted(&rules, table, pivot) {
+            return tool_error(&format!(
+                "column '{pivot}' is redacted; its values cannot become pivot columns"
+            ));
+        }
+        if aggregates.len() != 1 || rollup {
+            return tool_error("pivot needs exactly one aggregate and no rollup");
+        }
+    }
+    let params = arguments
+        .get("params")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let (sql, params) = match sqlgen::build_aggregate(
+        source,
+        params,
+        &group_by,
+        &aggregates,
+        rollup,
+        arguments.get("limit").and_then(Value::as_u64),
+        &rules,
+    ) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql.clone(),
+        params,
+        query_options_from_args(arguments),
+    )
+    .await;
+
+    // Row objects do not keep select order, so name the columns here.
+    let mut names = group_by;
+    if rollup {
+        names.push("grouping".to_string());
+    }
+    names.extend(aggregates.into_iter().map(|a| a.alias));
+    let mut events = drain_outputs(rx);
+    for event in &mut events {
+        if event.get("code").and_then(Value::as_str) == Some("result") {
+            compact_result(event, &names, pivot);
+        }
+    }
+    tool_ok(json!({"sql": sql, "events": events}))
+}
+
+async fn tool_plan_check(app: &Arc<App>, arguments: &Value) -> Value {
+    let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+        return tool_error("missing required argument: sql");
+    };
+    let params = arguments
+        .get("params")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let cost_threshold = arguments
+        .get("cost_threshold")
+        .and_then(Value::as_f64)
+        .unwrap_or(0.2);
+    let update_baseline = arguments
+        .get("update_baseline")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let session = request_session(arguments);
+    let rows = match handler::fetch_rows(
+        app,
+        session.as_deref(),
+        &format!("explain (format json) {}", sql.trim().trim_end_matches(';')),
+        &params,
+        &query_options_from_args(arguments),
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => return tool_error(&format!("explain failed: {e}")),
+    };
+    let Some(current) = rows.first().and_then(plan::snapshot) else {
+        return tool_error("explain returned no plan");
+    };
+    let session_name = match session {
+        Some(name) => name,
+        None => app.config.read().await.default_session.clone(),
+    };
+    let fingerprint = plan::sql_fingerprint(&session_name, sql);
+    let mut baselines = app.plan_baselines.lock().await;
+    let Some(baseline) = baselines.get(&fingerprint) else {
+        baselines.set(fingerprint.clone(), current.clone());
+        return tool_ok(json!({
+            "fingerprint": fingerprint,
+            "status": "baseline_recorded",
+            "current": current,
+        }));
+    };
+    let delta = plan::compare(&baseline, &current, cost_threshold);
+    let status = if delta.shape_changed || delta.cost_changed {
+        "changed"
+    } else {
+        "unchanged"
+    };
+    if update_baseline {
+        baselines.set(fingerprint.clone(), current.clone());
+    }
+    tool_ok(json!({
+        "fingerprint": fingerprint,
+        "status": status,
+        "shape_changed": delta.shape_changed,
+        "cost_change": delta.cost_change,
+        "cost_changed": delta.cost_changed,
+        "cost_threshold": cost_threshold,
+        "baseline": baseline,
+        "current": current,
+        "baseline_updated": update_baseline,
+    }))
+}
+
+async fn tool_replication_status(app: &Arc<App>, arguments: &Value) -> Value {
+    let sample_ms = arguments
+        .get("sample_ms")
+        .and_then(Value::as_u64)
+        .unwrap_or(replication::DEFAULT_SAMPLE_MS)
+        .min(replication::MAX_SAMPLE_MS);
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+    let fetch = |sql: String| {
+        let (session, options) = (session.clone(), options.clone());
+        async move { handler::fetch_rows(app, session.as_deref(), &sql, &[], &options).await }
+    };
+    let wal_bytes = |rows: &[Value]| rows.first().and_then(|r| r["wal_bytes"].as_u64());
+    let sampled: Result<_, ExecError> = async {
+        let before = fetch(replication::position_sql()).await?;
+        let started = std::time::Instant::now();
+        let mut after = before.clone();
+        if sample_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(sample_ms)).await;
+            after = fetch(replication::position_sql()).await?;
+        }
+        let rate = match (wal_bytes(&before), wal_bytes(&after), sample_ms > 0) {
+            (Some(b), Some(a), true) => replication::bytes_per_second(b, a, started.elapsed()),
+            _ => None,
+        };
+        let slots = fetch(replication::slots_sql()).await?;
+        let replicas = fetch(replication::replicas_sql()).await?;
+        Ok((after, rate, slots, replicas))
+    }
+    .await;
+    let (position, rate, slots, replicas) = match sampled {
+        Ok(v) => v,
+        Err(e) => return tool_error(&format!("replication status failed: {e}")),
+    };
+    let position = position.first().cloned().unwrap_or_default();
+    tool_ok(json!({
+        "in_recovery": position["in_recovery"],
+        "wal_bytes": position["wal_bytes"],
+        "wal_bytes_per_second": rate,
+        "sample_ms": sample_ms,
+        "replay_delay_ms": position["replay_delay_ms"],
+        "slots": slots,
+        "replicas": replicas,
+    }))
+}
+
+/// Prefix of ER graph resources; the full URI is `{ER_GRAPH_URI}{session}/{schema}`.
+const ER_GRAPH_URI: &str = "afpsql://er-graph/";
+
+/// Nodes and foreign key edges of `schema`, read through the schema cache.
+async fn er_graph(
+    app: &Arc<App>,
+    session: Option<&str>,
+    schema: &str,
+    options: &QueryOptions,
+) -> Result<(Vec<er::Node>, Vec<er::Edge>), String> {
+    let params = [Value::String(schema.to_string())];
+    let nodes = handler::fetch_catalog(app, session, er::NODES_SQL, &params, options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let edges = handler::fetch_catalog(app, session, er::EDGES_SQL, &params, options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let nodes = serde_json::from_value(Value::Array(nodes)).map_err(|e| e.to_string())?;
+    let edges: Vec<er::EdgeRow> =
+        serde_json::from_value(Value::Array(edges)).map_err(|e| e.to_string())?;
+    Ok((nodes, edges.into_iter().map(er::Edge::from).collect()))
+}
+
+async fn tool_er_graph(app: &Arc<App>, arguments: &Value) -> Value {
+    let schema = arguments
+        .get("schema")
+        .and_then(Value::as_str)
+        .unwrap_or("public");
+    let format = arguments.get("format").and_then(Value::as_str);
+    if !matches!(format, None | Some("json" | "dot" | "mermaid")) {
+        return tool_error("format must be json, dot or mermaid");
+    }
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+    let (nodes, edges) = match er_graph(app, session.as_deref(), schema, &options).await {
+        Ok(graph) => graph,
+        Err(e) => return tool_error(&format!("er graph failed: {e}")),
+    };
+    let text = match format {
+        Some("dot") => Some(er::to_dot(&nodes, &edges)),
+        Some("mermaid") => Some(er::to_mermaid(&nodes, &edges)),
+        _ => None,
+    };
+    let mut out = json!({"schema": schema, "nodes": nodes, "edges": edges});
+    if let Some(text) = text {
+        out["text"] = Value::String(text);
+    }
+    tool_ok(out)
+}
+
+async fn tool_schema_summary(app: &Arc<App>, arguments: &Value) -> Value {
+    let schema = arguments
+        .get("schema")
+        .and_then(Value::as_str)
+        .unwrap_or("public");
+    let max_tokens = arguments
+        .get("max_tokens")
+        .and_then(Value::as_u64)
+        .map_or(schema_summary::DEFAULT_MAX_TOKENS, |n| n as usize);
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+    let (nodes, edges) = match er_graph(app, session.as_deref(), schema, &options).await {
+        Ok(graph) => graph,
+        Err(e) => return tool_error(&format!("schema summary failed: {e}")),
+    };
+    let columns: Vec<schema_summary::Column> = match handler::fetch_catalog(
+        app,
+        session.as_deref(),
+        schema_summary::COLUMNS_SQL,
+        &[Value::String(schema.to_string())],
+        &options,
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|rows| serde_json::from_value(Value::Array(rows)).map_err(|e| e.to_string()))
+    {
+        Ok(columns) => columns,
+        Err(e) => return tool_error(&format!("schema summary failed: {e}")),
+    };
+    let summary = schema_summary::summarize(schema, &nodes, &edges, &columns, max_tokens);
+    tool_ok(json!({
+        "schema": schema,
+        "max_tokens": max_tokens,
+        "text": summary.text,
+        "estimated_tokens": summary.estimated_tokens,
+        "tables": summary.tables,
+        "omitted_tables": summary.omitted_tables,
+    }))
+}
+
+async fn tool_assert_snapshot(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+        return tool_error("missing required argument: sql");
+    };
+    let update = arguments
+        .get("update")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let path = match (
+        arguments.get("path").and_then(Value::as_str),
+        arguments.get("handle").and_then(Value::as_str),
+    ) {
+        (Some(path), None) => std::path::PathBuf::from(path),
+        (None, Some(_)) if update => return tool_error("update writes to a path, not a handle"),
+        (None, Some(handle)) => {
+            let Some(dir) = app.config.read().await.results_dir.clone() else {
+                return tool_error("results_dir is disabled; stored results have no handles");
+            };
+            match golden::handle_path(&dir, handle) {
+                Ok(path) => path,
+                Err(e) => return tool_error(&e),
+            }
+        }
+        _ => return tool_error("pass exactly one of path or handle"),
+    };
+    let key_columns = match sqlgen::string_list(arguments.get("key_columns"), "key_columns") {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => return tool_error(&e),
+    };
+    let ordered = arguments
+        .get("ordered")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if ordered && !key_columns.is_empty() {
+        return tool_error("pass key_columns or ordered, not both");
+    }
+    let ignore_columns =
+        match sqlgen::string_list(arguments.get("ignore_columns"), "ignore_columns") {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => return tool_error(&e),
+        };
+    let mut column_tolerances = HashMap::new();
+    if let Some(map) = arguments
+        .get("column_tolerances")
+        .and_then(Value::as_object)
+    {
+        for (column, tolerance) in map {
+            match tolerance.as_f64() {
+                Some(t) if t >= 0.0 => column_tolerances.insert(column.clone(), t),
+                _ => {
+                    return tool_error(&format!(
+                        "column_tolerances.{column} must be a non-negative number"
+                    ))
+                }
+            };
+        }
+    }
+    let tolerances = golden::Tolerances {
+        ignore_columns,
+        column_tolerances,
+        key_columns,
+        ordered,
+        max_row_changes: arguments
+            .get("max_row_changes")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as usize,
+    };
+
+    let params = arguments
+        .get("params")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql.to_string(),
+        params,
+        query_options_from_args(arguments),
+    )
+    .await;
+    let events = drain_outputs(rx);
+    let Some(result) = events.iter().find(|e| e["code"] == "result") else {
+        return tool_ok(json!({"passed": false, "events": events}));
+    };
+    let actual = golden::Page {
+        columns: serde_json::from_value(result["columns"].clone()).unwrap_or_default(),
+        rows: result["rows"].as_array().cloned().unwrap_or_default(),
+        total_rows: result["row_count"].as_u64().unwrap_or(0) as usize,
+    };
+
+    if update {
+        return match golden::save(&path, &actual.columns, &actual.rows) {
+            Ok(_) => tool_ok(json!({
+                "updated": true,
+                "path": path.display().to_string(),
+                "row_count": actual.rows.len(),
+            })),
+            Err(e) => tool_error(&format!("cannot write {}: {e}", path.display())),
+        };
+    }
+    let golden = match golden::load(&path) {
+        Ok(page) => page,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return tool_error(&format!(
+                "no golden result at {}; pass update: true to record one",
+                path.display()
+            ))
+        }
+        Err(e) => return tool_error(&format!("cannot read {}: {e}", path.display())),
+    };
+    tool_ok(json!(golden::compare(&golden, &actual, &tolerances)))
+}
+
+async fn tool_run_checks(app: &Arc<App>, arguments: &Value) -> Value {
+    let configured = app.config.read().await.checks.clone();
+    if configured.is_empty() {
+        return tool_error("no checks configured; add them under checks with psql_config");
+    }
+    let names = match sqlgen::string_list(arguments.get("names"), "names") {
+        Ok(Some(names)) => names,
+        Ok(None) => {
+            let mut names: Vec<String> = configured.keys().cloned().collect();
+            names.sort();
+            names
+        }
+        Err(e) => return tool_error(&e),
+    };
+    if let Some(unknown) = names.iter().find(|n| !configured.contains_key(*n)) {
+        let mut known: Vec<&str> = configured.keys().map(String::as_str).collect();
+        known.sort();
+        return tool_error(&format!(
+            "unknown check {unknown}; configured: {}",
+            known.join(", ")
+        ));
+    }
+    let session = request_session(arguments);
+    let sample_rows = arguments
+        .get("sample_rows")
+        .and_then(Value::as_u64)
+        .map_or(checks::DEFAULT_SAMPLE_ROWS, |n| n as usize);
+    let mut results = vec![];
+    for name in &names {
+        let check = &configured[name];
+        results.push(checks::run_check(app, name, check, session.as_deref(), sample_rows).await);
+    }
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    tool_ok(json!({
+        "passed": checks::passed(&results),
+        "pass": count(checks::CheckStatus::Pass),
+        "fail": count(checks::CheckStatus::Fail),
+        "error": count(checks::CheckStatus::Error),
+        "checks": results,
+    }))
+}
+
+async fn tool_export_sample(app: &Arc<App>, arguments: &Value) -> Value {
+    let tables = match sqlgen::string_list(arguments.get("tables"), "tables") {
+        Ok(Some(tables)) if !tables.is_empty() => tables,
+        Ok(_) => return tool_error("missing required argument: tables"),
+        Err(e) => return tool_error(&e),
+    };
+    let limit = arguments.get("n").and_then(Value::as_u64).unwrap_or(100);
+    let (mode, random) = match arguments.get("mode").and_then(Value::as_str) {
+        None | Some("first") => ("first", false),
+        Some("random") => ("random", true),
+        Some(other) => {
+            return tool_error(&format!(
+                "unsupported mode '{other}', expected first|random"
+            ))
+        }
+    };
+    let mut rules = vec![];
+    for (key, action) in [
+        ("pseudonymize", RedactAction::Pseudonymize),
+        ("mask", RedactAction::Mask),
+        ("drop", RedactAction::Null),
+    ] {
+        match sqlgen::string_list(arguments.get(key), key) {
+            Ok(columns) => rules.extend(
+                columns
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|column| RedactionRule { column, action }),
+            ),
+            Err(e) => return tool_error(&e),
+        }
+    }
+    let salt = arguments.get("salt_secret").and_then(Value::as_str);
+    let session = request_session(arguments);
+
+    let mut samples = vec![];
+    for table in &tables {
+        match anonymize::sample_table(app, session.as_deref(), table, limit, random, &rules, salt)
+            .await
+        {
+            Ok(sample) => samples.push(sample),
+            Err(e) => return tool_error(&format!("sampling {table} failed: {e}")),
+        }
+    }
+    let salted = salt.is_some() || app.config.read().await.redact_salt_secret.is_some();
+    let mut manifest = anonymize::Manifest {
+        mode: mode.to_string(),
+        rows_per_table: limit,
+        salted,
+        tables: samples.iter().map(|s| s.manifest.clone()).collect(),
+    };
+    let Some(dir) = arguments.get("path").and_then(Value::as_str) else {
+        let tables: Vec<Value> = samples
+            .into_iter()
+            .map(|s| json!({"table": s.manifest.table, "rows": s.rows}))
+            .collect();
+        return tool_ok(json!({"manifest": manifest, "tables": tables}));
+    };
+    let dir = std::path::PathBuf::from(dir);
+    match anonymize::write_export(&dir, &mut manifest, &samples) {
+        Ok(()) => tool_ok(json!({"path": dir.display().to_string(), "manifest": manifest})),
+        Err(e) => tool_error(&format!("cannot write {}: {e}", dir.display())),
+    }
+}
+
+async fn tool_find_subject_data(app: &Arc<App>, arguments: &Value) -> Value {
+    let value = match arguments.get("value") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => return tool_error("missing required argument: value"),
+    };
+    let patterns = match sqlgen::string_list(arguments.get("columns"), "columns") {
+        Ok(Some(patterns)) => patterns,
+        Ok(None) => app.config.read().await.subject_columns.clone(),
+        Err(e) => return tool_error(&e),
+    };
+    if patterns.is_empty() {
+        return tool_error("no subject columns; pass columns or configure subject_columns");
+    }
+    let schema = arguments
+        .get("schema")
+        .and_then(Value::as_str)
+        .unwrap_or("public");
+    let depth = arguments
+        .get("depth")
+        .and_then(Value::as_u64)
+        .map_or(subject::DEFAULT_DEPTH, |n| n as usize);
+    if depth > subject::MAX_DEPTH {
+        return tool_error(&format!("depth is at most {}", subject::MAX_DEPTH));
+    }
+    let session = request_session(arguments);
+    let mut options = query_options_from_args(arguments);
+    options.read_only = Some(true);
+    let (nodes, edges) = match er_graph(app, session.as_deref(), schema, &options).await {
+        Ok(graph) => graph,
+        Err(e) => return tool_error(&format!("subject search failed: {e}")),
+    };
+    let columns: Vec<schema_summary::Column> = match handler::fetch_catalog(
+        app,
+        session.as_deref(),
+        schema_summary::COLUMNS_SQL,
+        &[Value::String(schema.to_string())],
+        &options,
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|rows| serde_json::from_value(Value::Array(rows)).map_err(|e| e.to_string()))
+    {
+        Ok(columns) => columns,
+        Err(e) => return tool_error(&format!("subject search failed: {e}")),
+    };
+    let direct = subject::direct_steps(schema, &columns, &patterns);
+    if direct.is_empty() {
+        return tool_error(&format!(
+            "no column of schema {schema} matches {}",
+            patterns.join(", ")
+        ));
+    }
+    let searched: Vec<String> = direct.iter().map(|s| s.via[0].clone()).collect();
+
+    let params = [Value::String(value)];
+    let mut queue: std::collections::VecDeque<(subject::Step, usize)> =
+        direct.into_iter().map(|step| (step, 0)).collect();
+    let (mut locations, mut skipped, mut steps) = (vec![], vec![], 0);
+    while let Some((step, hops)) = queue.pop_front() {
+        if steps == subject::MAX_STEPS {
+            queue.push_front((step, hops));
+            break;
+        }
+        steps += 1;
+        let primary_key = nodes
+            .iter()
+            .find(|n| n.table == step.table)
+            .map(|n| n.primary_key.clone())
+            .unwrap_or_default();
+        let sql = subject::count_sql(schema, &step, &primary_key);
+        let row = match handler::fetch_rows(app, session.as_deref(), &sql, &params, &options).await
+        {
+            Ok(mut rows) if !rows.is_empty() => rows.swap_remove(0),
+            Ok(_) => continue,
+            // The value does not fit the column's type, such as an email
+            // address against an integer id.
+            Err(ExecError::Sql {
+                sqlstate, message, ..
+            }) if sqlstate.starts_with("22") && hops == 0 => {
+                skipped.push(json!({"column": step.via[0], "reason": message}));
+                continue;
+            }
+            Err(e) => return tool_error(&format!("subject search failed: {e}")),
+        };
+        let rows = row.get("rows").and_then(Value::as_i64).unwrap_or(0);
+        if rows == 0 {
+            continue;
+        }
+        if hops < depth {
+            for next in subject::referencing_steps(schema, &step, &nodes, &edges) {
+                queue.push_back((next, hops + 1));
+            }
+        }
+        locations.push(subject::Location {
+            table: step.table,
+            via: step.via,
+            depth: hops,
+            rows,
+            keys: row.get("keys").and_then(Value::as_array).cloned(),
+        });
+    }
+    tool_ok(json!({
+        "schema": schema,
+        "searched": searched,
+        "locations": locations,
+        "skipped": skipped,
+        "truncated": !queue.is_empty(),
+    }))
+}
+
+/// Longest indent `psql_format_sql` accepts, in spaces.
+const MAX_FORMAT_INDENT: u64 = 8;
+
+fn tool_format_sql(arguments: &Value) -> Value {
+    let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+        return tool_error("missing required argument: sql");
+    };
+    let indent = arguments.get("indent").and_then(Value::as_u64).unwrap_or(2);
+    if !(1..=MAX_FORMAT_INDENT).contains(&indent) {
+        return tool_error(&format!("indent must be 1 to {MAX_FORMAT_INDENT} spaces"));
+    }
+    let options = sqlformat::FormatOptions {
+        indent: sqlformat::Indent::Spaces(indent as u8),
+        uppercase: arguments
+            .get("uppercase")
+            .and_then(Value::as_bool)
+            .unwrap_or(true),
+        lines_between_queries: 1,
+    };
+    let (protected, bodies) = protect_dollar_quotes(sql);
+    let mut formatted = sqlformat::format(&protected, &sqlformat::QueryParams::None, options);
+    for (marker, body) in bodies {
+        formatted = formatted.replacen(&marker, &body, 1);
+    }
+    tool_ok(json!({"sql": formatted}))
+}
+
+/// `sql` with each dollar-quoted string swapped for a placeholder literal,
+/// and the placeholders with what they replaced. The formatter would
+/// otherwise reflow function bodies as if they were SQL of the statement.
+fn protect_dollar_quotes(sql: &str) -> (String, Vec<(String, String)>) {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(sql.len());
+    let mut bodies = vec![];
+    let mut rest = sql;
+    let mut prev: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        let skip = match c {
+            '\'' | '"' => rest[1..].find(c).map_or(rest.len(), |end| end + 2),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest.find("*/").map_or(rest.len(), |end| end + 2),
+            '$' if !prev.is_some_and(|p| is_ident(p) || p == '$') => {
+                let tag_len = rest[1..]
+                    .find(|t: char| !is_ident(t))
+                    .filter(|&n| rest[1 + n..].starts_with('$'))
+                    .filter(|&n| !rest[1..].starts_with(|t: char| t.is_ascii_digit()) || n == 0)
+                    .map(|n| n + 2);
+                let end = tag_len.and_then(|len| {
+                    let tag = &rest[..len];
+                    rest[len..].find(tag).map(|at| len + at + len)
+                });
+                match end {
+                    Some(end) => {
+                        let marker = format!("'\u{1}{}'", bodies.len());
+                        out.push_str(&marker);
+                        bodies.push((marker, rest[..end].to_string()));
+                        prev = Some('\'');
+                        rest = &rest[end..];
+                        continue;
+                    }
+                    None => c.len_utf8(),
+                }
+            }
+            _ => c.len_utf8(),
+        };
+        out.push_str(&rest[..skip]);
+        prev = rest[..skip].chars().last();
+        rest = &rest[skip..];
+    }
+    (out, bodies)
+}
+
+/// One ER graph resource for the `public` schema of each configured session.
+async fn resources_list(app: &Arc<App>) -> Value {
+    let mut sessions: Vec<String> = {
+        let cfg = app.config.read().await;
+        let mut names: Vec<String> = cfg.sessions.keys().cloned().collect();
+        if !names.contains(&cfg.default_session) {
+            names.push(cfg.default_session.clone());
+        }
+        names
+    };
+    sessions.sort();
+    let resources: Vec<Value> = sessions
+        .iter()
+        .map(|session| {
+            json!({
+                "uri": format!("{ER_GRAPH_URI}{session}/public"),
+                "name": format!("ER graph of {session}/public"),
+                "mimeType": "application/json"
+            })
+        })
+        .collect();
+    json!({"resources": resources})
+}
+
+async fn read_resource(app: &Arc<App>, uri: &str) -> Result<Value, String> {
+    let Some((session, schema)) = uri
+        .strip_prefix(ER_GRAPH_URI)
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(session, schema)| !session.is_empty() && !schema.is_empty())
+    else {
+        return Err(format!("unknown resource: {uri}"));
+    };
+    let options = query_options_from_args(&json!({}));
+    let (nodes, edges) = er_graph(app, Some(session), schema, &options).await?;
+    let text = json!({"schema": schema, "nodes": nodes, "edges": edges}).to_string();
+    Ok(json!({"contents": [{"uri": uri, "mimeType": "application/json", "text": text}]}))
+}
+
+async fn tool_activity(app: &Arc<App>, arguments: &Value) -> Value {
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+    let terminate = match arguments.get("action").and_then(Value::as_str) {
+        None | Some("list") => None,
+        Some("cancel_backend") => Some(false),
+        Some("terminate_backend") => Some(true),
+        Some(other) => return tool_error(&format!("unknown activity action: {other}")),
+    };
+    if let Some(terminate) = terminate {
+        let Some(pid) = arguments.get("pid").and_then(Value::as_i64) else {
+            return tool_error("cancel_backend and terminate_backend require argument: pid");
+        };
+        let id = arguments.get("id").and_then(Value::as_str);
+        return match handler::signal_backend(app, id, session.as_deref(), pid, terminate, &options)
+            .await
+        {
+            Ok(signalled) => tool_ok(json!({"pid": pid, "signalled": signalled})),
+            Err(e) => tool_error(&e),
+        };
+    }
+    let text = |key: &str| {
+        arguments
+            .get(key)
+            .and_then(Value::as_str)
+            .map_or(Value::Null, |v| json!(v))
+    };
+    let params = [
+        text("state"),
+        text("user"),
+        text("database"),
+        text("wait_event_type"),
+        arguments
+            .get("min_query_age_ms")
+            .and_then(Value::as_u64)
+            .map_or(Value::Null, |v| json!(v)),
+        json!(arguments
+            .get("include_background")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)),
+    ];
+    match handler::fetch_rows(
+        app,
+        session.as_deref(),
+        activity::LIST_SQL,
+        &params,
+        &options,
+    )
+    .await
+    {
+        Ok(backends) => tool_ok(json!({"backends": backends})),
+        Err(e) => tool_error(&format!("activity failed: {e}")),
+    }
+}
+
+async fn tool_suggest_indexes(app: &Arc<App>, arguments: &Value) -> Value {
+    let Some(sql) = arguments.get("sql").and_then(Value::as_str) else {
+        return tool_error("missing required argument: sql");
+    };
+    let params = arguments
+        .get("params")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let session = request_session(arguments);
+    let session = session.as_deref();
+    let options = query_options_from_args(arguments);
+    let explain_sql = format!(
+        "explain (format json, verbose) {}",
+        sql.trim().trim_end_matches(';')
+    );
+    let rows = match handler::fetch_rows(app, session, &explain_sql, &params, &options).await {
+        Ok(v) => v,
+        Err(e) => return tool_error(&format!("explain failed: {e}")),
+    };
+    let Some(root) = rows.first().and_then(plan::plan_root) else {
+        return tool_error("explain returned no plan");
+    };
+    let base_cost = root
+        .get("Total Cost")
+        .and_then(Value::as_f64)
+        .unwrap_or(0.0);
+    let hypopg = match handler::fetch_catalog(
+        app,
+        session,
+        "select 1 from pg_extension where extname = 'hypopg'",
+        &[],
+        &options,
+    )
+    .await
+    {
+        Ok(rows) => !rows.is_empty(),
+        Err(e) => return tool_error(&format!("extension lookup failed: {e}")),
+    };
+
+    let mut suggestions: Vec<serde_json::Map<String, Value>> = vec![];
+    let mut skipped: Vec<Value> = vec![];
+    for scan in advisor::filtered_scans(root) {
+        let table = scan.qualified();
+        let info = match handler::fetch_catalog(
+            app,
+            session,
+            sqlgen::TABLE_INDEXES_SQL,
+            &[Value::String(table.clone())],
+            &options,
+        )
+        .await
+        {
+            Ok(rows) => rows.into_iter().next().unwrap_or(Value::Null),
+            Err(e) => return tool_error(&format!("table lookup failed: {e}")),
+        };
+        let names = |key: &str| -> Vec<String> {
+            info.get(key)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        };
+        let mut skip = |reason: String| {
+            skipped.push(json!({"table": table, "filter": scan.filter, "reason": reason}));
+        };
+        let columns = advisor::filter_columns(&scan.filter, &names("columns"));
+        let Some(leading) = columns.first() else {
+            skip("the filter compares no plain columns".to_string());
+            continue;
+        };
+        if names("indexed_leading").contains(leading) {
+            skip(format!("an index already leads with {leading}"));
+            continue;
+        }
+        let create_index = advisor::create_index_sql(&scan, &columns);
+        if suggestions
+            .iter()
+            .any(|s| s.get("create_index") == Some(&json!(create_index)))
+        {
+            continue;
+        }
+        let table_rows = info
+            .get("estimated_rows")
+            .and_then(Value::as_f64)
+            .unwrap_or(-1.0);
+        let mut suggestion = serde_json::Map::new();
+        suggestion.insert("table".to_string(), json!(table));
+        suggestion.insert("columns".to_string(), json!(columns));
+        suggestion.insert("create_index".to_string(), json!(create_index));
+        suggestion.insert("filter".to_string(), json!(scan.filter));
+        suggestion.insert("scan_cost".to_string(), json!(scan.cost));
+        suggestion.insert("scan_rows".to_string(), json!(scan.rows));
+        if !hypopg {
+            if table_rows <= 0.0 {
+                skip("the table has no row estimate yet; run ANALYZE".to_string());
+                continue;
+            }
+            let Some(saving) = advisor::estimated_saving(&scan, table_rows) else {
+                skip("the filter keeps too much of the table for an index to pay off".to_string());
+                continue;
+            };
+            suggestion.insert("table_rows".to_string(), json!(table_rows));
+            suggestion.insert("estimated_saving".to_string(), json!(saving));
+        }
+        suggestions.push(suggestion);
+    }
+    if hypopg && !suggestions.is_empty() {
+        if let Err(e) = plan_hypothetical(
+            app,
+            session,
+            &explain_sql,
+            &params,
+            &options,
+            base_cost,
+            &mut suggestions,
+        )
+        .await
+        {
+            return tool_error(&format!("hypopg planning failed: {e}"));
+        }
+    }
+    let saving = |s: &serde_json::Map<String, Value>| {
+        s.get("estimated_saving")
+            .and_then(Value::as_f64)
+            .unwrap_or(f64::MIN)
+    };
+    suggestions.sort_by(|a, b| saving(b).total_cmp(&saving(a)));
+
+    tool_ok(json!({
+        "method": if hypopg { "hypopg" } else { "heuristic" },
+        "base_cost": base_cost,
+        "suggestions": suggestions,
+        "skipped": skipped,
+    }))
+}
+
+/// Plan the query again with each suggestion as a hypopg hypothetical index,
+/// all on one scratch connection since hypothetical indexes are per backend.
+async fn plan_hypothetical(
+    app: &Arc<App>,
+    session: Option<&str>,
+    explain_sql: &str,
+    params: &[Value],
+    options: &QueryOptions,
+    base_cost: f64,
+    suggestions: &mut [serde_json::Map<String, Value>],
+) -> Result<(), ExecError> {
+    let workspace = handler::open_scratch_workspace(app, session).await?;
+    let options = QueryOptions {
+        workspace: Some(workspace.clone()),
+        ..options.clone()
+    };
+    let mut result = Ok(());
+    for suggestion in suggestions.iter_mut() {
+        let create_index = suggestion.get("create_index").cloned().unwrap_or_default();
+        let planned = async {
+            let created = handler::fetch_rows(
+                app,
+                session,
+                "select indexrelid::text as indexrelid from hypopg_create_index($1)",
+                &[create_index],
+                &options,
+            )
+            .await?;
+            let plan = handler::fetch_rows(app, session, explain_sql, params, &options).await;
+            handler::fetch_rows(app, session, "select hypopg_reset()", &[], &options).await?;
+            Ok::<_, ExecError>((created, plan?))
+        }
+        .await;
+        let (created, plan) = match planned {
+            Ok(v) => v,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        let index_id = created
+            .first()
+            .and_then(|r| r.get("indexrelid"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let Some(snapshot) = plan.first().and_then(plan::snapshot) else {
+            continue;
+        };
+        let used = snapshot
+            .shape
+            .iter()
+            .any(|line| line.contains(&format!("<{index_id}>")));
+        let saving = ((base_cost - snapshot.total_cost) * 100.0).round() / 100.0;
+        suggestion.insert("estimated_cost".to_string(), json!(snapshot.total_cost));
+        suggestion.insert("estimated_saving".to_string(), json!(saving));
+        suggestion.insert("used_by_plan".to_string(), json!(used));
+    }
+    handler::close_scratch_workspace(app, &workspace).await;
+    result
+}
+
+async fn tool_maintenance(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(operation) = arguments.get("operation").and_then(Value::as_str) else {
+        return tool_error("missing required argument: operation");
+    };
+    let concurrently = arguments
+        .get("concurrently")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let built = sqlgen::MaintenanceOp::parse(operation)
+        .and_then(|op| sqlgen::build_maintenance(op, table, concurrently));
+    let (sql, blocks) = match built {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let session = request_session(arguments);
+    let mut options = query_options_from_args(arguments);
+    let (require_approval, has_profile) = {
+        let cfg = app.config.read().await;
+        (
+            cfg.require_approval,
+            cfg.timeout_profiles.contains_key("maintenance"),
+        )
+    };
+    if has_profile && options.timeout_profile.is_none() && options.statement_timeout_ms.is_none() {
+        options.timeout_profile = Some("maintenance".to_string());
+    }
+
+    if arguments
+        .get("dry_run")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+    {
+        let relation = match sqlgen::qualified_name(table) {
+            Ok(v) => v,
+            Err(e) => return tool_error(&e),
+        };
+        let stats = match handler::fetch_rows(
+            app,
+            session.as_deref(),
+            sqlgen::MAINTENANCE_STATS_SQL,
+            &[Value::String(relation)],
+            &options,
+        )
+        .await
+        {
+            Ok(rows) => rows.into_iter().next().unwrap_or(Value::Null),
+            Err(e) => return tool_error(&format!("table stats failed: {e}")),
+        };
+        return tool_ok(json!({
+            "dry_run": true,
+            "sql": sql,
+            "blocks": blocks,
+            "needs_approval": require_approval && blocks.is_some(),
+            "table_stats": stats,
+        }));
+    }
+    handler::run_maintenance(
+        app,
+        Some(request_id(arguments)),
+        session,
+        sql.clone(),
+        blocks,
+        options,
+    )
+    .await;
+
+    tool_ok(json!({"sql": sql, "events": drain_outputs(rx)}))
+}
+
+/// Reshape a `result` event in place: `columns` becomes `names` and each row
+/// an array in that order. With `pivot`, each value of that column becomes a
+/// column holding the (single, last) aggregate, one row per combination of
+/// the other group columns.
+fn compact_result(event: &mut Value, names: &[String], pivot: Option<&str>) {
+    let Some(event) = event.as_object_mut() else {
+        return;
+    };
+    let rows = match event.remove("rows") {
+        Some(Value::Array(rows)) => rows,
+        _ => vec![],
+    };
+    let cell = |row: &Value, name: &str| row.get(name).cloned().unwrap_or(Value::Null);
+    let (columns, rows) = match (pivot, names.last()) {
+        (Some(pivot), Some(value)) => {
+            let keys: Vec<&String> = names
+                .iter()
+                .filter(|n| *n != pivot && *n != value)
+                .collect();
+            let mut headers: Vec<Value> = vec![];
+            let mut out: Vec<Vec<Value>> = vec![];
+            let mut index: HashMap<String, usize> = HashMap::new();
+            for row in &rows {
+                let key: Vec<Value> = keys.iter().map(|k| cell(row, k)).collect();
+                let at = *index
+                    .entry(Value::Array(key.clone()).to_string())
+                    .or_insert_with(|| {
+                        out.push(key);
+                        out.len() - 1
+                    });
+                let header = cell(row, pivot);
+                let col = match headers.iter().position(|h| *h == header) {
+                    Some(col) => col,
+                    None => {
+                        headers.push(header);
+                        headers.len() - 1
+                    }
+                };
+                let target = &mut out[at];
+                target.resize(keys.len() + headers.len(), Value::Null);
+                target[keys.len() + col] = cell(row, value);
+            }
+            for row in &mut out {
+                row.resize(keys.len() + headers.len(), Value::Null);
+            }
+            let columns = keys
+                .iter()
+                .map(|k| k.to_string())
+                .chain(headers.into_iter().map(|h| match h {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                }))
+                .collect();
+            (columns, out)
+        }
+        _ => {
+            let rows = rows
+                .iter()
+                .map(|row| names.iter().map(|n| cell(row, n)).collect())
+                .collect();
+            (names.to_vec(), rows)
+        }
+    };
+    event.insert("row_count".to_string(), json!(rows.len()));
+    event.insert("columns".to_string(), json!(columns));
+    event.insert("rows".to_string(), json!(rows));
+}
+
+async fn tool_search(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let Some(term) = arguments.get("query").and_then(Value::as_str) else {
+        return tool_error("missing required argument: query");
+    };
+    let Some(raw_targets) = arguments.get("targets").and_then(Value::as_array) else {
+        return tool_error("missing required argument: targets (array of {table, columns})");
+    };
+    let mut targets = Vec::with_capacity(raw_targets.len());
+    for t in raw_targets {
+        let Some(table) = t.get("table").and_then(Value::as_str) else {
+            return tool_error("each target needs a table");
+        };
+        let columns = match sqlgen::string_list(t.get("columns"), "targets[].columns") {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => return tool_error(&e),
+        };
+        targets.push(sqlgen::SearchTarget {
+            table: table.to_string(),
+            columns,
+        });
+    }
+    let method = match sqlgen::SearchMethod::parse(arguments.get("method").and_then(Value::as_str))
+    {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let fts_config = arguments
+        .get("fts_config")
+        .and_then(Value::as_str)
+        .unwrap_or("simple");
+    let limit = arguments.get("limit").and_then(Value::as_u64).unwrap_or(20);
+
+    let rules = app.config.read().await.redact.clone();
+    let (sql, params) =
+        match sqlgen::build_search(&targets, term, method, fts_config, limit, &rules) {
+            Ok(v) => v,
+            Err(e) => return tool_error(&e),
+        };
+    let mut options = query_options_from_args(arguments);
+    options.nested_tables.insert(
+        "row".to_string(),
+        targets
+            .iter()
+            .filter_map(|t| sqlgen::qualified_name(&t.table).ok())
+            .collect(),
+    );
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql,
+        params,
+        options,
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+#[cfg(feature = "pgvector")]
+async fn tool_vector_search(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(column) = arguments.get("column").and_then(Value::as_str) else {
+        return tool_error("missing required argument: column");
+    };
+    let Some(embedding) = arguments.get("embedding").and_then(Value::as_array) else {
+        return tool_error("missing required argument: embedding (array of numbers)");
+    };
+    let metric = match sqlgen::VectorMetric::parse(arguments.get("metric").and_then(Value::as_str))
+    {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let k = arguments.get("k").and_then(Value::as_u64).unwrap_or(10);
+    let include_vector = arguments
+        .get("include_vector")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let (sql, params) = match sqlgen::build_vector_search(
+        table,
+        column,
+        embedding,
+        metric,
+        k,
+        arguments.get("where"),
+        include_vector,
+    ) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let mut options = query_options_from_args(arguments);
+    if let Ok(name) = sqlgen::qualified_name(table) {
+        options.nested_tables.insert("row".to_string(), vec![name]);
+    }
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql,
+        params,
+        options,
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+#[cfg(feature = "pgvector")]
+const VECTOR_UPSERT_BATCH_ROWS: usize = 500;
+
+#[cfg(feature = "pgvector")]
+async fn tool_vector_upsert(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(column) = arguments.get("column").and_then(Value::as_str) else {
+        return tool_error("missing required argument: column");
+    };
+    let Some(rows) = arguments.get("rows").and_then(Value::as_array) else {
+        return tool_error("missing required argument: rows (array of objects)");
+    };
+    let columns = match sqlgen::row_columns(rows) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let update_columns =
+        match sqlgen::string_list(arguments.get("update_columns"), "update_columns") {
+            Ok(v) => v,
+            Err(e) => return tool_error(&e),
+        };
+    let batch_rows = arguments
+        .get("batch_rows")
+        .and_then(Value::as_u64)
+        .and_then(|n| usize::try_from(n).ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(VECTOR_UPSERT_BATCH_ROWS);
+    let session = request_session(arguments);
+    let options = query_options_from_args(arguments);
+
+    let dimension = match arguments.get("dimension").and_then(Value::as_u64) {
+        Some(d) => usize::try_from(d).ok(),
+        None => {
+            let relation = match sqlgen::qualified_name(table) {
+                Ok(v) => v,
+                Err(e) => return tool_error(&e),
+            };
+            let info = match handler::fetch_catalog(
+                app,
+                session.as_deref(),
+                sqlgen::VECTOR_COLUMN_SQL,
+                &[Value::String(relation), Value::String(column.to_string())],
+                &options,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(e) => return tool_error(&format!("column lookup failed: {e}")),
+            };
+            let Some(info) = info.first() else {
+                return tool_error(&format!("column {column} not found on {table}"));
+            };
+            match info.get("type").and_then(Value::as_str) {
+                Some("vector") => {}
+                other => {
+                    return tool_error(&format!(
+                        "column {column} has type {}, expected vector",
+                        other.unwrap_or("unknown")
+                    ))
+                }
+            }
+            info.get("dimension")
+                .and_then(Value::as_u64)
+                .and_then(|d| usize::try_from(d).ok())
+        }
+    };
+    let key = match conflict_key(app, arguments, table, &columns, &session, &options).await {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    // Build every batch before running any, so bad input writes nothing.
+    let mut batches = Vec::new();
+    for chunk in rows.chunks(batch_rows) {
+        match sqlgen::build_vector_upsert(
+            table,
+            chunk,
+            &key,
+            column,
+            dimension,
+            update_columns.clone(),
+        ) {
+            Ok(v) => batches.push(v),
+            Err(e) => return tool_error(&e),
+        }
+    }
+    let id = request_id(arguments);
+    let mut events = Vec::new();
+    for (sql, params) in batches {
+        handler::execute_query(
+            app,
+            Some(id.clone()),
+            session.clone(),
+            sql,
+            params,
+            options.clone(),
+        )
+        .await;
+        let out = drain_outputs(rx);
+        let failed = out.iter().any(|e| {
+            matches!(
+                e.get("code").and_then(Value::as_str),
+                Some("error" | "sql_error")
+            )
+        });
+        events.extend(out);
+        if failed {
+            break;
+        }
+    }
+
+    tool_ok(json!({"key": key, "dimension": dimension, "events": events}))
+}
+
+async fn tool_update_delete(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    name: &str,
+    arguments: &Value,
+) -> Value {
+    let Some(table) = arguments.get("table").and_then(Value::as_str) else {
+        return tool_error("missing required argument: table");
+    };
+    let Some(filter) = arguments.get("where") else {
+        return tool_error("missing required argument: where");
+    };
+    let returning = match sqlgen::string_list(arguments.get("returning"), "returning") {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => return tool_error(&e),
+    };
+    if let Some(chunked) = arguments.get("chunked").filter(|v| !v.is_null()) {
+        let chunking = match serde_json::from_value::<Chunking>(chunked.clone()) {
+            Ok(v) => v,
+            Err(e) => return tool_error(&format!("invalid chunked: {e}")),
+        };
+        if !returning.is_empty() {
+            return tool_error("returning is not supported with chunked");
+        }
+        let set = if name == "psql_update" {
+            let Some(set) = arguments.get("set") else {
+                return tool_error("missing required argument: set");
+            };
+            Some(set.clone())
+        } else {
+            None
+        };
+        let request = ChunkedWriteRequest {
+            id: request_id(arguments),
+            session: request_session(arguments),
+            table: table.to_string(),
+            set,
+            filter: filter.clone(),
+            chunking,
+            options: query_options_from_args(arguments),
+        };
+        handler::chunked_write(app, request).await;
+        return tool_ok(json!({"events": drain_outputs(rx)}));
+    }
+    let built = if name == "psql_update" {
+        let Some(set) = arguments.get("set") else {
+            return tool_error("missing required argument: set");
+        };
+        sqlgen::build_update(table, set, filter, &returning)
+    } else {
+        sqlgen::build_delete(table, filter, &returning)
+    };
+    let (sql, params) = match built {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql,
+        params,
+        query_options_from_args(arguments),
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_dequeue(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>, arguments: &Value) -> Value {
+    let queue = match sqlgen::JobQueue::from_args(arguments) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let limit = arguments.get("limit").and_then(Value::as_u64).unwrap_or(1);
+    let (sql, params) = match sqlgen::build_dequeue(&queue, limit, arguments.get("where")) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql,
+        params,
+        query_options_from_args(arguments),
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+async fn tool_complete(
+    app: &Arc<App>,
+    rx: &mut mpsc::Receiver<Output>,
+    arguments: &Value,
+) -> Value {
+    let queue = match sqlgen::JobQueue::from_args(arguments) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+    let Some(ids) = arguments.get("ids").and_then(Value::as_array) else {
+        return tool_error("missing required argument: ids");
+    };
+    let failed = arguments
+        .get("failed")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let (sql, params) = match sqlgen::build_complete(&queue, ids, failed, arguments.get("set")) {
+        Ok(v) => v,
+        Err(e) => return tool_error(&e),
+    };
+
+    handler::execute_query(
+        app,
+        Some(request_id(arguments)),
+        request_session(arguments),
+        sql,
+        params,
+        query_options_from_args(arguments),
+    )
+    .await;
+
+    tool_ok(json!({"events": drain_outputs(rx)}))
+}
+
+fn queue_schema(required: &[&str], extra: Value) -> Value {
+    let mut properties = json!({
+        "id": {"type":"string"},
+        "session": {"type":"string"},
+        "table": {"type":"string"},
+        "id_column": {"type":"string", "description": "default id"},
+        "status_column": {"type":"string", "description": "default status"},
+        "ready_status": {"type":"string", "description": "default pending"},
+        "running_status": {"type":"string", "description": "default running"},
+        "done_status": {"type":"string", "description": "default done"},
+        "failed_status": {"type":"string", "description": "default failed"},
+        "statement_timeout_ms": {"type":"integer"},
+        "lock_timeout_ms": {"type":"integer"}
+    });
+    if let (Some(props), Value::Object(extra)) = (properties.as_object_mut(), extra) {
+        props.extend(extra);
+    }
+    json!({"type": "object", "required": required, "properties": properties})
+}
+
+fn where_schema() -> Value {
+    json!({
+        "type": "object",
+        "description": "column -> value (equality, null = IS NULL) or {\"op\": \"=|<>|<|<=|>|>=|like|ilike|in|not_in|is_null|is_not_null\", \"value\": ...}; must not be empty",
+        "minProperties": 1
+    })
+}
+
+fn chunked_schema() -> Value {
+    json!({
+        "type": "object",
+        "description": "run the write in batches that each commit on their own, with a progress event after each; an update walks the primary key",
+        "properties": {
+            "batch_rows": {"type":"integer", "description": "rows per batch (default 1000)"},
+            "sleep_ms": {"type":"integer", "description": "pause between batches"}
+        }
+    })
+}
+
+fn request_id(arguments: &Value) -> String {
+    arguments
+        .get("id")
+        .and_then(Value::as_str)
+        .unwrap_or("mcp")
+        .to_string()
+}
+
+fn request_session(arguments: &Value) -> Option<String> {
+    arguments
+        .get("session")
+        .and_then(Value::as_str)
+        .map(std::string::ToString::to_string)
+}
+
+fn query_options_from_args(arguments: &Value) -> QueryOptions {
+    QueryOptions {
+        stream_rows: arguments
+            .get("stream_rows")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        batch_rows: arguments
+            .get("batch_rows")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        batch_bytes: arguments
+            .get("batch_bytes")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        statement_timeout_ms: arguments
+            .get("statement_timeout_ms")
+            .and_then(Value::as_u64),
+        lock_timeout_ms: arguments.get("lock_timeout_ms").and_then(Value::as_u64),
+        timeout_profile: arguments
+            .get("timeout_profile")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        deadline: arguments
+            .get("deadline")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        read_only: arguments.get("read_only").and_then(Value::as_bool),
+        defer_constraints: arguments
+            .get("defer_constraints")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        replication_role: arguments
+            .get("replication_role")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        inline_max_rows: arguments
+            .get("inline_max_rows")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        default_limit: arguments
+            .get("default_limit")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        compress: arguments
+            .get("compress")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        inline_max_bytes: arguments
+            .get("inline_max_bytes")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        summarize: arguments
+            .get("summarize")
+            .and_then(Value::as_u64)
+            .map(|v| v as usize),
+        store_result: arguments
+            .get("store_result")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        export_uri: arguments
+            .get("export_uri")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        verify: arguments
+            .get("verify")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        publish_uri: arguments
+            .get("publish_uri")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        publish_key: arguments
+            .get("publish_key")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        publish_format: arguments
+            .get("publish_format")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        notify: arguments
+            .get("notify")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        server_timing: arguments
+            .get("server_timing")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        approved: false,
+        snapshot: None,
+        autocommit: false,
+        nested_tables: BTreeMap::new(),
+        workspace: arguments
+            .get("workspace")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        cache_ttl_ms: arguments.get("cache_ttl_ms").and_then(Value::as_u64),
+        materialize_to: arguments
+            .get("materialize_to")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        diff_output: arguments
+            .get("diff_output")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        key_columns: arguments
+            .get("key_columns")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        dedup: arguments
+            .get("dedup")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        resume_from: arguments.get("resume_from").and_then(Value::as_u64),
+        ack_window: None,
+        vars: arguments
+            .get("vars")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        idents: arguments
+            .get("idents")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        agent: None,
+        context: arguments
+            .get("context")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+        prepare_transaction: arguments
+            .get("prepare_transaction")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        expect: arguments
+            .get("expect")
+            .and_then(|v| serde_json::from_value(v.clone()).ok()),
+    }
+}
+
+fn drain_outputs(rx: &mut mpsc::Receiver<Output>) -> Vec<Value> {
+    let mut outputs = vec![];
+    while let Ok(msg) = rx.try_recv() {
+        outputs.push(serde_json::to_value(msg).unwrap_or(Value::Null));
+    }
+    outputs
+}
+
+fn tools_list() -> Value {
+    #[allow(unused_mut)]
+    let mut list = json!({
+        "tools": [
+            {
+                "name": "psql_query",
+                "description": "Execute one SQL statement with positional bind parameters.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array"},
+                        "stream_rows": {"type":"boolean"},
+                        "batch_rows": {"type":"integer"},
+                        "batch_bytes": {"type":"integer"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"},
+                        "deadline": {"type":["string", "integer"], "description": "absolute deadline, RFC 3339 or epoch ms; caps the statement timeout"},
+                        "lock_timeout_ms": {"type":"integer"},
+                        "read_only": {"type":"boolean"},
+                        "defer_constraints": {"type":"boolean", "description": "SET CONSTRAINTS ALL DEFERRED: deferrable constraints are checked at commit"},
+                        "replication_role": {"type":"string", "enum": ["origin", "replica", "local"], "description": "session_replication_role for the transaction; replica skips triggers and foreign key checks; needs --allow-replication-role at startup"},
+                        "context": {"type":"object", "additionalProperties": {"type":"string"}, "description": "custom settings such as app.tenant_id for row security policies, set for the transaction; cannot change keys the session fixes"},
+                        "prepare_transaction": {"type":"string", "description": "PREPARE TRANSACTION under this id instead of committing; finish with psql_prepared"},
+                        "expect": {
+                            "type":"object",
+                            "properties": {
+                                "columns": {"type":"array", "items": {"type":"object", "required": ["name"], "properties": {"name": {"type":"string"}, "type": {"type":"string"}}}},
+                                "exact_columns": {"type":"boolean"},
+                                "min_rows": {"type":"integer"},
+                                "max_rows": {"type":"integer"}
+                            },
+                            "description": "result contract: required columns (with types), optionally exactly those, and row bounds; a mismatch is a contract_violation error"
+                        },
+                        "inline_max_rows": {"type":"integer"},
+                        "inline_max_bytes": {"type":"integer"},
+                        "summarize": {"type":"integer", "description": "over the inline limits, return result_summary (row count, per-column min/max/distinct, this many first and last rows) instead of result_too_large"},
+                        "default_limit": {"type":"integer", "description": "cap on returned rows; 0 disables the configured default"},
+                        "compress": {"type":"string", "enum": ["gzip", "zstd"], "description": "compress streamed result_rows batches"},
+                        "store_result": {"type":"boolean", "description": "save rows under results_dir and return a result_stored handle"},
+                        "export_uri": {"type":"string", "description": "upload rows to s3://bucket/key or gs://bucket/key (.csv or .jsonl, optionally .gz) and return a result_exported summary"},
+                        "verify": {"type":"string", "enum": ["fail", "flag"], "description": "with export_uri: checksum the rows and the uploaded object; fail deletes a mismatched object, flag reports it"},
+                        "publish_uri": {"type":"string", "description": "publish rows to kafka://broker:9092/topic or nats://server:4222/subject and return a result_published summary"},
+                        "publish_key": {"type":"array", "items": {"type":"string"}, "description": "with publish_uri: columns keying each message (Kafka message key, extra NATS subject tokens)"},
+                        "publish_format": {"type":"string", "enum": ["row", "batch"], "description": "with publish_uri: one JSON object per row (default), or a JSON array of up to batch_rows rows sharing a key"},
+                        "notify": {"type":"object", "description": "announce the query's end when it ran for at least after_ms (default 10000): {\"url\": \"http(s)://...\"} posts a JSON record with a Slack style text, {\"command\": [\"prog\", \"arg\"]} runs a program with the record on stdin", "properties": {"url": {"type":"string"}, "command": {"type":"array", "items": {"type":"string"}}, "after_ms": {"type":"integer", "minimum": 0}}},
+                        "server_timing": {"type":"boolean", "description": "also run the statement under EXPLAIN ANALYZE, read-only in a rolled back savepoint, to add server_planning_ms and server_execution_ms to trace; writes report no server times"},
+                        "workspace": {"type":"string", "description": "run on the pinned connection of a psql_workspace"},
+                        "cache_ttl_ms": {"type":"integer", "description": "serve rows cached by an identical query up to this long ago"},
+                        "materialize_to": {
+                            "type":"object",
+                            "required": ["session", "table"],
+                            "properties": {"session": {"type":"string"}, "table": {"type":"string"}},
+                            "description": "write the rows into this table on a sqlite session (replacing it) and return the handle instead of rows"
+                        },
+                        "diff_output": {"type":"boolean", "description": "on a re-run, return only the rows added and removed since the previous run of the same query"},
+                        "key_columns": {"type":"array", "items": {"type":"string"}, "description": "columns identifying a row; streamed batches carry each row's key"},
+                        "dedup": {"type":"boolean", "description": "with key_columns: on a re-run, return only rows new or changed for their key"},
+                        "resume_from": {"type":"integer", "description": "with id: resend the retained stream of that id from this seq instead of re-running the query"},
+                        "vars": {"type":"object", "additionalProperties": {"type":"string"}, "description": "values for :name / {{name}} placeholders; :\"name\" and {{name|ident}} quote an identifier, :'name' and {{name|literal}} a literal"},
+                        "idents": {"type":"object", "additionalProperties": {"type":"string"}, "description": "table/column names for %{name} placeholders; each value is checked and quoted as one identifier"}
+                    }
+                }
+            },
+            {
+                "name": "psql_fanout",
+                "description": "Run one SQL statement on several sessions concurrently; returns one result or error per session.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sessions", "sql"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "sessions": {"type":"array", "items": {"type":"string"}},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array"},
+                        "vars": {"type":"object", "additionalProperties": {"type":"string"}},
+                        "idents": {"type":"object", "additionalProperties": {"type":"string"}},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string"},
+                        "read_only": {"type":"boolean"},
+                        "prepare_transaction": {"type":"string", "description": "PREPARE TRANSACTION under this id on every session instead of committing; finish with psql_prepared"},
+                        "inline_max_rows": {"type":"integer"},
+                        "inline_max_bytes": {"type":"integer"},
+                        "default_limit": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_transfer",
+                "description": "Copy the rows of a query on one session into a table on another session, in batches, without passing them through the agent.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql", "table"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "source_session": {"type":"string"},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array"},
+                        "target_session": {"type":"string"},
+                        "table": {"type":"string", "description": "table or schema.table on the target session"},
+                        "columns": {"type":"object", "description": "source column -> target column; only mapped columns are written"},
+                        "method": {"type":"string", "enum": ["copy", "insert"]},
+                        "batch_rows": {"type":"integer", "description": "rows per write (default 1000)"},
+                        "verify": {"type":"string", "enum": ["fail", "flag"], "description": "with method copy: checksum each batch on both ends; fail rolls a mismatched batch back and stops, flag reports it"},
+                        "defer_constraints": {"type":"boolean", "description": "SET CONSTRAINTS ALL DEFERRED: deferrable constraints are checked at commit"},
+                        "replication_role": {"type":"string", "enum": ["origin", "replica", "local"], "description": "session_replication_role for the transaction; replica skips triggers and foreign key checks; needs --allow-replication-role at startup"},
+                        "context": {"type":"object", "additionalProperties": {"type":"string"}, "description": "custom settings such as app.tenant_id for row security policies, set for the transaction; cannot change keys the session fixes"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "default_limit": {"type":"integer", "description": "cap on rows read; the configured default does not apply"}
+                    }
+                }
+            },
+            {
+                "name": "psql_archive",
+                "description": "Move the rows matching a structured where predicate into an archive table that has every column of the table. Each batch deletes and inserts in one statement and commits on its own; a progress event follows each batch.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table", "archive_table", "where"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string", "description": "table or schema.table the rows leave"},
+                        "archive_table": {"type":"string", "description": "table or schema.table the rows go to; extra columns get their defaults"},
+                        "where": where_schema(),
+                        "batch_rows": {"type":"integer", "description": "rows moved per batch (default 1000)"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "lock_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_resume",
+                "description": "Continue an archive, import or chunked update/delete from the checkpoint it left under state_dir when it was paused, failed or cut short. The request is repeated as saved, starting after the last batch that committed.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["id"],
+                    "properties": {
+                        "id": {"type":"string", "description": "id the operation ran under"}
+                    }
+                }
+            },
+            {
+                "name": "psql_operations",
+                "description": "List archives, imports and chunked writes running in this process, those saved under state_dir that nothing runs (left by a failure or restart, for psql_resume), and statements waiting for psql_approve.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"}
+                    }
+                }
+            },
+            {
+                "name": "psql_debug_stats",
+                "description": "Diagnose afpsql itself rather than the database: tokio worker and task counts, output channel depths, connection pool states, memory (RSS, and allocator stats in a build with the jemalloc feature) and time spent per module since startup.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"}
+                    }
+                }
+            },
+            {
+                "name": "psql_import",
+                "description": "Load a CSV or NDJSON file (local path, s3:// or gs:// URI; .gz is gunzipped) into a table with COPY, in batches. Rows the server rejects are reported by line instead of failing the import, up to max_errors.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["source", "table"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "source": {"type":"string", "description": "path or URI ending in .csv, .jsonl or .ndjson, optionally .gz; CSV needs a header row"},
+                        "table": {"type":"string", "description": "table or schema.table"},
+                        "columns": {"type":"object", "description": "source column -> table column; only mapped columns are written"},
+                        "batch_rows": {"type":"integer", "description": "rows per COPY (default 1000)"},
+                        "max_errors": {"type":"integer", "description": "rejected rows tolerated before the import stops (default 0)"},
+                        "dead_letter": {
+                            "type":"object",
+                            "properties": {"path": {"type":"string"}, "table": {"type":"string"}},
+                            "description": "write every rejected record with its error to this NDJSON file (path) or table (table)"
+                        },
+                        "verify": {"type":"string", "enum": ["fail", "flag"], "description": "checksum each batch as read and as stored; fail rolls a mismatched batch back and stops, flag reports it"},
+                        "defer_constraints": {"type":"boolean", "description": "SET CONSTRAINTS ALL DEFERRED: deferrable constraints are checked at commit"},
+                        "replication_role": {"type":"string", "enum": ["origin", "replica", "local"], "description": "session_replication_role for the transaction; replica skips triggers and foreign key checks; needs --allow-replication-role at startup"},
+                        "context": {"type":"object", "additionalProperties": {"type":"string"}, "description": "custom settings such as app.tenant_id for row security policies, set for the transaction; cannot change keys the session fixes"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_execute_block",
+                "description": "Run an anonymous plpgsql DO block. vars are readable in the body via current_setting('afpsql.<name>'); raised notices are returned as notice events.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["body"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "body": {"type":"string"},
+                        "vars": {"type":"object"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"},
+                        "lock_timeout_ms": {"type":"integer"},
+                        "read_only": {"type":"boolean"}
+                    }
+                }
+            },
+            {
+                "name": "psql_insert",
+                "description": "Insert rows (array of objects keyed by column) into a table with generated parameterized SQL; large conflict-free batches use COPY.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table", "rows"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string"},
+                        "rows": {"type":"array", "items": {"type":"object"}},
+                        "on_conflict": {
+                            "type":"object",
+                            "properties": {
+                                "action": {"type":"string", "enum": ["error", "nothing", "update"]},
+                                "target": {"type":"array", "items": {"type":"string"}},
+                                "update_columns": {"type":"array", "items": {"type":"string"}}
+                            }
+                        },
+                        "returning": {"type":"array", "items": {"type":"string"}},
+                        "copy_threshold_rows": {"type":"integer"},
+                        "dead_letter": {
+                            "type":"object",
+                            "properties": {"path": {"type":"string"}, "table": {"type":"string"}},
+                            "description": "insert in batches, retrying a refused batch row by row; rows still refused go to this NDJSON file (path) or table (table) with their errors, and the reply is insert_result"
+                        },
+                        "batch_rows": {"type":"integer", "description": "with dead_letter: rows per INSERT (default 1000)"},
+                        "defer_constraints": {"type":"boolean", "description": "SET CONSTRAINTS ALL DEFERRED: deferrable constraints are checked at commit"},
+                        "replication_role": {"type":"string", "enum": ["origin", "replica", "local"], "description": "session_replication_role for the transaction; replica skips triggers and foreign key checks; needs --allow-replication-role at startup"},
+                        "context": {"type":"object", "additionalProperties": {"type":"string"}, "description": "custom settings such as app.tenant_id for row security policies, set for the transaction; cannot change keys the session fixes"},
+                        "workspace": {"type":"string", "description": "insert on the pinned connection of a psql_workspace, e.g. into a temp table"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"},
+                        "lock_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_upsert",
+                "description": "Insert or update rows keyed by the table's primary key / unique index (inferred from the catalog unless key is given). Returns inserted vs updated counts.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table", "rows"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string"},
+                        "rows": {"type":"array", "items": {"type":"object"}},
+                        "key": {"type":"array", "items": {"type":"string"}},
+                        "update_columns": {"type":"array", "items": {"type":"string"}},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"},
+                        "lock_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_sample",
+                "description": "Return the first or random N rows of a table plus per-column estimates (null fraction, distinct count, numeric min/max) from planner statistics.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string"},
+                        "n": {"type":"integer"},
+                        "mode": {"type":"string", "enum": ["first", "random"]},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"}
+                    }
+                }
+            },
+            {
+                "name": "psql_column_stats",
+                "description": "Profile the columns of a table or query result with one aggregate query: null fraction, distinct count, top-k values, and min/max plus an equal-width histogram for numeric columns.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string", "description": "table or schema.table; give this or sql"},
+                        "sql": {"type":"string", "description": "row-returning query to profile; give this or table"},
+                        "params": {"type":"array", "description": "bind values for $1..$N in sql"},
+                        "columns": {"type":"array", "items": {"type":"string"}, "description": "profile only these columns"},
+                        "top_k": {"type":"integer", "description": "most common values per column (default 5)"},
+                        "buckets": {"type":"integer", "description": "histogram buckets for numeric columns (default 10)"},
+                        "max_rows": {"type":"integer", "description": "read at most this many rows"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"}
+                    }
+                }
+            },
+            {
+                "name": "psql_aggregate",
+                "description": "Group a table or query result and compute aggregates without writing SQL; returns compact rows, optionally with rollup subtotals or pivoted on one group column.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["aggregates"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string", "description": "table or schema.table; give this or sql"},
+                        "sql": {"type":"string", "description": "row-returning query to aggregate; give this or table"},
+                        "params": {"type":"array", "description": "bind values for $1..$N in sql"},
+                        "group_by": {"type":"array", "items": {"type":"string"}},
+                        "aggregates": {
+                            "type":"array",
+                            "items": {
+                                "type":"object",
+                                "required": ["fn"],
+                                "properties": {
+                                    "fn": {"type":"string", "enum": ["count", "count_distinct", "sum", "avg", "min", "max"]},
+                                    "column": {"type":"string", "description": "omit for count(*)"},
+                                    "as": {"type":"string", "description": "output name (default <fn>_<column>)"}
+                                }
+                            }
+                        },
+                        "rollup": {"type":"boolean", "description": "add subtotal and grand-total rows"},
+                        "pivot": {"type":"string", "description": "group_by column whose values become columns (one aggregate only)"},
+                        "limit": {"type":"integer"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"}
+                    }
+                }
+            },
+            {
+                "name": "psql_plan_check",
+                "description": "EXPLAIN a statement (without running it) and compare its plan shape and estimated cost with the baseline recorded for the same SQL; the first check records the baseline.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql"],
+                    "properties": {
+                        "session": {"type":"string"},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array", "description": "bind values for $1..$N"},
+                        "cost_threshold": {"type":"number", "description": "relative cost change reported as changed (default 0.2 = 20%)"},
+                        "update_baseline": {"type":"boolean", "description": "keep this plan as the new baseline"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_suggest_indexes",
+                "description": "Suggest CREATE INDEX statements for a query from its EXPLAIN plan (filtered sequential scans), with the expected cost saving; uses hypopg hypothetical indexes when installed, else selectivity heuristics. Nothing is created.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql"],
+                    "properties": {
+                        "session": {"type":"string"},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array", "description": "bind values for $1..$N"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_replication_status",
+                "description": "Report replication health: slots with the WAL they retain and their lag, connected replicas with lag in bytes and time, and the WAL generation rate over a short sample.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session": {"type":"string"},
+                        "sample_ms": {"type":"integer", "description": "how long to sample the WAL rate, at most 10000 (default 1000; 0 skips it)"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_er_graph",
+                "description": "Return the table relationship graph of a schema: tables with primary keys and row estimates, and foreign key edges with cardinality hints, optionally rendered as DOT or Mermaid text.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session": {"type":"string"},
+                        "schema": {"type":"string", "description": "schema to graph (default public)"},
+                        "format": {"type":"string", "enum": ["json", "dot", "mermaid"], "description": "dot or mermaid also returns the graph rendered as text"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_schema_summary",
+                "description": "Summarize a schema for an LLM context within a token budget: one line per table with its size as an order of magnitude, columns, primary key and foreign key targets, most connected tables first, plus the same in structured form.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session": {"type":"string"},
+                        "schema": {"type":"string", "description": "schema to summarize (default public)"},
+                        "max_tokens": {"type":"integer", "description": "approximate size limit of text, at four characters per token (default 2000)"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_describe_statement",
+                "description": "Prepare a statement without executing it and return the PostgreSQL type of each $n parameter and of each result column.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "sql": {"type":"string"},
+                        "workspace": {"type":"string", "description": "prepare on the pinned connection of a psql_workspace, where its temp tables are visible"},
+                        "vars": {"type":"object", "additionalProperties": {"type":"string"}},
+                        "idents": {"type":"object", "additionalProperties": {"type":"string"}}
+                    }
+                }
+            },
+            {
+                "name": "psql_assert_snapshot",
+                "description": "Run a query and compare its rows with a golden result, for data regression tests. Returns passed plus a structured diff: columns added or removed and rows added, removed or changed. With update, records the current result as the golden file instead.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array"},
+                        "path": {"type":"string", "description": "golden file, in the stored result format"},
+                        "handle": {"type":"string", "description": "stored result under results_dir to use as the golden result"},
+                        "update": {"type":"boolean", "description": "write the current result to path instead of comparing"},
+                        "key_columns": {"type":"array", "items": {"type":"string"}, "description": "match rows by these columns and report changed cells"},
+                        "ordered": {"type":"boolean", "description": "match rows by position; otherwise rows are compared as a multiset"},
+                        "ignore_columns": {"type":"array", "items": {"type":"string"}, "description": "columns left out of the comparison, such as timestamps"},
+                        "column_tolerances": {"type":"object", "additionalProperties": {"type":"number"}, "description": "largest absolute difference accepted per numeric column"},
+                        "max_row_changes": {"type":"integer", "description": "added, removed and changed rows allowed before the assertion fails (default 0)"},
+                        "workspace": {"type":"string"},
+                        "default_limit": {"type":"integer", "description": "cap on compared rows; 0 disables the configured default"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "vars": {"type":"object", "additionalProperties": {"type":"string"}},
+                        "idents": {"type":"object", "additionalProperties": {"type":"string"}}
+                    }
+                }
+            },
+            {
+                "name": "psql_run_checks",
+                "description": "Run the data quality checks configured under checks. Each check's SQL selects offending rows and passes when it returns none; failing checks come back with a count and sample rows. passed is false when an error-severity check fails or any check cannot run.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "names": {"type":"array", "items": {"type":"string"}, "description": "checks to run (default all, in name order)"},
+                        "session": {"type":"string", "description": "run every check on this session instead of its own"},
+                        "sample_rows": {"type":"integer", "description": "offending rows returned per failed check (default 5)"}
+                    }
+                }
+            },
+            {
+                "name": "psql_export_sample",
+                "description": "Sample tables into a shareable, anonymized dataset: the configured redaction rules apply, plus deterministic pseudonymization of the listed columns so keys still join. Returns or writes the rows with a manifest of the transformation applied to each column.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["tables"],
+                    "properties": {
+                        "session": {"type":"string"},
+                        "tables": {"type":"array", "items": {"type":"string"}, "description": "table or schema.table names"},
+                        "n": {"type":"integer", "description": "rows per table (default 100)"},
+                        "mode": {"type":"string", "enum": ["first", "random"]},
+                        "pseudonymize": {"type":"array", "items": {"type":"string"}, "description": "column patterns, as for redact, replaced by stable stand-ins of the same shape"},
+                        "mask": {"type":"array", "items": {"type":"string"}, "description": "column patterns replaced by \"[redacted]\""},
+                        "drop": {"type":"array", "items": {"type":"string"}, "description": "column patterns replaced by null"},
+                        "salt_secret": {"type":"string", "description": "salt for this export's hashes and pseudonyms instead of redact_salt_secret"},
+                        "path": {"type":"string", "description": "directory to write <schema.table>.jsonl files and manifest.json into; rows are returned inline when omitted"}
+                    }
+                }
+            },
+            {
+                "name": "psql_find_subject_data",
+                "description": "Find where a data subject's rows live, for access and erasure requests: searches the subject_columns (or columns) patterns for a value such as an email or id, then follows foreign keys that reference each hit. Returns each table with a row count, sample primary keys and the chain that reached it.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["value"],
+                    "properties": {
+                        "session": {"type":"string"},
+                        "value": {"type":["string", "number"], "description": "identifier to look for"},
+                        "columns": {"type":"array", "items": {"type":"string"}, "description": "column patterns (column, table.column, schema.table.column; * and ? globs) instead of the configured subject_columns"},
+                        "schema": {"type":"string", "description": "schema to search (default public)"},
+                        "depth": {"type":"integer", "description": "foreign key hops to follow from a direct hit, 0 to 4 (default 2)"},
+                        "statement_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_format_sql",
+                "description": "Format SQL with one clause per line, consistent indentation and keyword case, without running it; useful for diffing generated SQL, stable cache keys and human review.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["sql"],
+                    "properties": {
+                        "sql": {"type":"string"},
+                        "indent": {"type":"integer", "description": "spaces per level, 1 to 8 (default 2)"},
+                        "uppercase": {"type":"boolean", "description": "upper-case keywords (default true)"}
+                    }
+                }
+            },
+            {
+                "name": "psql_activity",
+                "description": "List server backends from pg_stat_activity with state, query age, wait events and blockers; cancel_backend and terminate_backend signal one by pid when started with --allow-backend-signals.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "action": {"type":"string", "enum": ["list", "cancel_backend", "terminate_backend"]},
+                        "pid": {"type":"integer", "description": "backend to signal; required for cancel_backend and terminate_backend"},
+                        "state": {"type":"string", "description": "list only backends in this state, e.g. active or idle in transaction"},
+                        "user": {"type":"string"},
+                        "database": {"type":"string"},
+                        "wait_event_type": {"type":"string", "description": "e.g. Lock, LWLock, IO, Client"},
+                        "min_query_age_ms": {"type":"integer", "description": "list only backends whose current or last query started at least this long ago"},
+                        "include_background": {"type":"boolean", "description": "also list autovacuum, WAL senders and other non-client backends"}
+                    }
+                }
+            },
+            {
+                "name": "psql_maintenance",
+                "description": "Run VACUUM, ANALYZE or REINDEX on one table, outside a transaction and through the approval and audit policy; dry_run shows the statement, its locking and the table's vacuum/analyze stats without running it.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table", "operation"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string", "description": "table or schema.table"},
+                        "operation": {"type":"string", "enum": ["vacuum", "vacuum_full", "vacuum_analyze", "analyze", "reindex"]},
+                        "concurrently": {"type":"boolean", "description": "REINDEX CONCURRENTLY (reindex only)"},
+                        "dry_run": {"type":"boolean"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "lock_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy; defaults to maintenance when configured"}
+                    }
+                }
+            },
+            {
+                "name": "psql_search",
+                "description": "Search text across table columns (ilike, pg_trgm word similarity, or full-text) and return ranked rows with matched snippets.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["query", "targets"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "query": {"type":"string"},
+                        "targets": {
+                            "type":"array",
+                            "items": {
+                                "type":"object",
+                                "required": ["table", "columns"],
+                                "properties": {
+                                    "table": {"type":"string"},
+                                    "columns": {"type":"array", "items": {"type":"string"}}
+                                }
+                            }
+                        },
+                        "method": {"type":"string", "enum": ["ilike", "trigram", "fts"]},
+                        "fts_config": {"type":"string"},
+                        "limit": {"type":"integer"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"}
+                    }
+                }
+            },
+            {
+                "name": "psql_update",
+                "description": "Update rows matching a structured, non-empty where predicate. Values are bound as parameters.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table", "set", "where"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string"},
+                        "set": {"type":"object", "minProperties": 1},
+                        "where": where_schema(),
+                        "returning": {"type":"array", "items": {"type":"string"}},
+                        "chunked": chunked_schema(),
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"},
+                        "lock_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_delete",
+                "description": "Delete rows matching a structured, non-empty where predicate. Values are bound as parameters.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["table", "where"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string"},
+                        "table": {"type":"string"},
+                        "where": where_schema(),
+                        "returning": {"type":"array", "items": {"type":"string"}},
+                        "chunked": chunked_schema(),
+                        "statement_timeout_ms": {"type":"integer"},
+                        "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"},
+                        "lock_timeout_ms": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_dequeue",
+                "description": "Claim jobs from a queue table with SELECT ... FOR UPDATE SKIP LOCKED: ready rows are marked running and returned, and concurrent workers never get the same job.",
+                "inputSchema": queue_schema(&["table"], json!({
+                    "limit": {"type":"integer", "description": "jobs to claim (default 1)"},
+                    "order_by": {"type":"array", "items": {"type":"string"}, "description": "claim order; default id_column"},
+                    "claimed_at_column": {"type":"string", "description": "set to now() when claimed"},
+                    "where": where_schema()
+                }))
+            },
+            {
+                "name": "psql_complete",
+                "description": "Mark claimed jobs done (or failed); only rows still running are updated and their ids returned.",
+                "inputSchema": queue_schema(&["table", "ids"], json!({
+                    "ids": {"type":"array", "description": "id_column values from psql_dequeue"},
+                    "failed": {"type":"boolean", "description": "use failed_status instead of done_status"},
+                    "set": {"type":"object", "description": "other columns to write, e.g. a result or error"}
+                }))
+            },
+            {
+                "name": "psql_config",
+                "description": "Read/update runtime config.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "default_session": {"type":"string"},
+                        "sessions": {"type":"object"},
+                        "inline_max_rows": {"type":"integer"},
+                        "inline_max_bytes": {"type":"integer"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "lock_timeout_ms": {"type":"integer"},
+                        "log": {"type":"array"},
+                        "log_file": {"type":"string", "description": "append log events to this file instead of returning them; empty string stops"},
+                        "log_rotate_bytes": {"type":"integer", "description": "roll log_file over before it grows past this; 0 never"},
+                        "log_rotate_ms": {"type":"integer", "description": "roll log_file over after writing it this long; 0 never"},
+                        "log_keep": {"type":"integer", "description": "rolled-over log files kept"},
+                        "log_system": {"type":"string", "enum": ["off", "syslog", "journald"], "description": "send log events to syslog or journald instead of returning them; needs the syslog feature"},
+                        "redact": {"type":"array", "items": {"type":"object"}},
+                        "default_limit": {"type":"integer"},
+                        "default_limit_action": {"type":"string", "enum": ["limit", "warn"]},
+                        "results_dir": {"type":"string", "description": "directory for stored results; empty string disables"},
+                        "state_dir": {"type":"string", "description": "directory for checkpoints of archive, import and chunked writes, for psql_resume; empty string disables"},
+                        "history_size": {"type":"integer", "description": "queries kept for psql_history; 0 disables"},
+                        "transcript_max_statements": {"type":"integer", "description": "statements kept for psql_transcript_export; 0 disables"},
+                        "require_approval": {"type":"boolean", "description": "park DDL and large writes for psql_approve; cannot be turned off"},
+                        "approval_row_threshold": {"type":"integer", "description": "writes over this many rows need approval; can only be lowered while approval is required"},
+                        "approval_ttl_ms": {"type":"integer"},
+                        "audit_log": {"type":"string", "description": "JSONL file for approval and elevation records; set once"},
+                        "elevation_max_ms": {"type":"integer", "description": "longest psql_grant_elevated duration; can only be lowered"},
+                        "timeout_profiles": {"type":"object", "description": "named timeouts: {name: {statement_timeout_ms, lock_timeout_ms, max_statement_timeout_ms}}; merged by name"},
+                        "injection_warnings": {"type":"boolean", "description": "log query.warning for SQL that looks built by string concatenation"},
+                        "slow_query_ms": {"type":"integer", "description": "log query.slow for queries that ran at least this long; 0 never"},
+                        "slow_query_params": {"type":"string", "enum": ["types", "values"], "description": "whether query.slow shows only param types or their values too"},
+                        "subject_columns": {"type":"array", "items": {"type":"string"}, "description": "column patterns holding data subject identifiers for psql_find_subject_data; replaces the current list"},
+                        "checks": {"type":"object", "description": "data quality checks for psql_run_checks by name: {\"sql\", \"severity\": \"warning\"|\"error\", \"description\", \"session\", \"statement_timeout_ms\"}; added or replaced by name"},
+                        "schedules": {"type":"object", "description": "queries run on a cron schedule (UTC) by name: {\"cron\": \"*/15 * * * *\", \"sql\", \"params\", \"session\", \"vars\", \"idents\", \"statement_timeout_ms\", \"sink\": {\"path\"}|{\"url\": \"http(s)://...\"}}; without a sink results arrive as schedule_result events with the next tool call; added or replaced by name, null removes"},
+                        "lint": {"type":"boolean", "description": "check each psql_query against the lint rules (select_star, missing_where, cross_join, non_sargable, implicit_cast) and add a lint event"},
+                        "lint_block": {"type":"array", "items": {"type":"string", "enum": ["warning", "error"]}, "description": "lint severities that stop the query with lint_blocked"},
+                        "annotate_queries": {"type":"boolean", "description": "prefix statements with /* afpsql id=... session=... agent=... */ for pg_stat_activity and server logs"},
+                        "agent_name": {"type":"string", "description": "agent in query annotations and audit records; defaults to the MCP clientInfo name; empty string removes it"},
+                        "agent_quotas": {"type":"object", "description": "per-agent limits: {agent or \"*\": {queries_per_minute, rows_per_minute, bytes_per_minute}}; merged by name, only ever tightened"},
+                        "agent_budgets": {"type":"object", "description": "per-agent token buckets: {agent or \"*\": {rows_per_minute, rows_per_hour, bytes_per_minute, bytes_per_hour}}; merged by name, only ever tightened"},
+                        "session_budgets": {"type":"object", "description": "per-session token buckets, same shape as agent_budgets"},
+                        "snapshot_ttl_ms": {"type":"integer", "description": "psql_snapshot snapshots not ended are released after this long"},
+                        "workspace_idle_ms": {"type":"integer", "description": "idle time after which a psql_workspace is closed"},
+                        "max_workspaces": {"type":"integer", "description": "workspaces open at once, each holding a connection"},
+                        "cache_max_entries": {"type":"integer", "description": "results kept for cache_ttl_ms queries; 0 disables"},
+                        "schema_cache_ttl_ms": {"type":"integer", "description": "how long tool catalog lookups are reused; 0 disables"},
+                        "diff_max_baselines": {"type":"integer", "description": "queries whose last rows are kept for diff_output and dedup; 0 disables both"},
+                        "resume_buffer_bytes": {"type":"integer", "description": "bytes of streamed batches kept for resume_from; 0 disables"},
+                        "writer_full_policy": {"type":"string", "enum": ["block", "drop_logs_first", "error"], "description": "what an output event does when the output channel is full"},
+                        "duplicate_id_policy": {"type":"string", "enum": ["reject", "suffix"], "description": "what a pipe request does when its id is still in flight"},
+                        "max_message_bytes": {"type":"integer", "description": "longest streamed result_rows line before it is split into parts; 0 never splits"},
+                        "pool_check_interval_ms": {"type":"integer", "description": "how often idle pooled connections are checked and broken ones closed; 0 disables"},
+                        "pool_idle_timeout_ms": {"type":"integer", "description": "close pooled connections unused this long; 0 keeps them"}
+                    }
+                }
+            },
+            {
+                "name": "psql_approve",
+                "description": "Execute a statement parked by require_approval, by its approval token. Hosts should have a human confirm this tool call.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["token"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "token": {"type":"string"},
+                        "session": {"type":"string", "description": "approver session; required when --approver-session is configured"},
+                        "approver_secret": {"type":"string", "description": "the --approver-secret; required when it is configured. Must come from the human confirming the call, never from the agent"}
+                    }
+                }
+            },
+            {
+                "name": "psql_grant_elevated",
+                "description": "Break-glass: run a session under a privileged role or another session's connection for a bounded time. The reason is written to audit_log; access reverts automatically.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["duration_ms", "reason"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string", "description": "session to elevate (default session when omitted)"},
+                        "role": {"type":"string", "description": "role assumed with SET LOCAL ROLE"},
+                        "via_session": {"type":"string", "description": "configured session whose connection is used"},
+                        "duration_ms": {"type":"integer", "description": "at most elevation_max_ms"},
+                        "reason": {"type":"string"}
+                    }
+                }
+            },
+            {
+                "name": "psql_history",
+                "description": "List recently executed queries (SQL, params fingerprint, session, outcome, timing) or replay one by seq.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"},
+                        "action": {"type":"string", "enum": ["list", "replay"]},
+                        "limit": {"type":"integer", "description": "newest entries to list"},
+                        "seq": {"type":"integer", "description": "entry to replay"}
+                    }
+                }
+            },
+            {
+                "name": "psql_snapshot",
+                "description": "Read several queries from one consistent view: begin exports a snapshot, query runs SQL in a REPEATABLE READ transaction importing it, end releases it.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["action"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "action": {"type":"string", "enum": ["begin", "query", "end"]},
+                        "session": {"type":"string"},
+                        "snapshot": {"type":"string", "description": "id from snapshot_started; required for query and end"},
+                        "sql": {"type":"string"},
+                        "params": {"type":"array"},
+                        "statement_timeout_ms": {"type":"integer"},
+                        "read_only": {"type":"boolean"},
+                        "inline_max_rows": {"type":"integer"},
+                        "default_limit": {"type":"integer"}
+                    }
+                }
+            },
+            {
+                "name": "psql_workspace",
+                "description": "Open or close a workspace: a pinned connection where temp tables persist across psql_query calls that pass the same workspace. Idle workspaces close after workspace_idle_ms.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["action", "workspace"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "action": {"type":"string", "enum": ["open", "close"]},
+                        "workspace": {"type":"string", "description": "caller-chosen workspace id"},
+                        "session": {"type":"string", "description": "session to pin a connection of (open only)"}
+                    }
+                }
+            },
+            {
+                "name": "psql_prepared",
+                "description": "Two-phase commit: list transactions left by prepare_transaction, or commit or roll one back by gid.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["action"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "action": {"type":"string", "enum": ["list", "commit", "rollback"]},
+                        "session": {"type":"string"},
+                        "gid": {"type":"string", "description": "prepare_transaction id; required for commit and rollback"},
+                        "older_than_ms": {"type":"integer", "description": "list only transactions prepared at least this long ago"}
+                    }
+                }
+            },
+            {
+                "name": "psql_transcript_export",
+                "description": "Export every statement executed in this run, in order, as a replayable .sql script (params as PREPARE/EXECUTE literals or psql variables).",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string", "description": "only statements run on this session"},
+                        "param_style": {"type":"string", "enum": ["inline", "psql_vars"]}
+                    }
+                }
+            },
+            {
+                "name": "psql_result_get",
+                "description": "Page through a result saved by store_result or an oversized query, by handle.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["handle"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "handle": {"type":"string"},
+                        "offset": {"type":"integer"},
+                        "limit": {"type":"integer", "description": "rows to return; defaults to inline_max_rows"}
+                    }
+                }
+            },
+            {
+                "name": "psql_result_delete",
+                "description": "Delete a stored result by handle.",
+                "inputSchema": {
+                    "type": "object",
+                    "required": ["handle"],
+                    "properties": {
+                        "id": {"type":"string"},
+                        "handle": {"type":"string"}
+                    }
+                }
+            },
+            {
+                "name": "psql_invalidate_cache",
+                "description": "Drop cached query results and catalog lookups, for one session or all, e.g. after a schema change made outside this process.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {"type":"string"},
+                        "session": {"type":"string", "description": "only this session; all when omitted"}
+                    }
+                }
+            }
+        ]
+    });
+    #[cfg(feature = "pgvector")]
+    if let Some(tools) = list["tools"].as_array_mut() {
+        tools.push(json!({
+            "name": "psql_vector_upsert",
+            "description": "Batch upsert rows with a pgvector embedding column given as number arrays; dimensions are checked against the column type (or `dimension`).",
+            "inputSchema": {
+                "type": "object",
+                "required": ["table", "column", "rows"],
+                "properties": {
+                    "id": {"type":"string"},
+                    "session": {"type":"string"},
+                    "table": {"type":"string"},
+                    "column": {"type":"string"},
+                    "rows": {"type":"array", "items": {"type":"object"}},
+                    "key": {"type":"array", "items": {"type":"string"}},
+                    "update_columns": {"type":"array", "items": {"type":"string"}},
+                    "dimension": {"type":"integer"},
+                    "batch_rows": {"type":"integer"},
+                    "statement_timeout_ms": {"type":"integer"},
+                    "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"}
+                }
+            }
+        }));
+        tools.push(json!({
+            "name": "psql_vector_search",
+            "description": "pgvector nearest-neighbour search: rows of table ordered by distance between column and the query embedding.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["table", "column", "embedding"],
+                "properties": {
+                    "id": {"type":"string"},
+                    "session": {"type":"string"},
+                    "table": {"type":"string"},
+                    "column": {"type":"string"},
+                    "embedding": {"type":"array", "items": {"type":"number"}},
+                    "metric": {"type":"string", "enum": ["cosine", "l2", "inner_product", "l1"]},
+                    "k": {"type":"integer"},
+                    "where": where_schema(),
+                    "include_vector": {"type":"boolean"},
+                    "statement_timeout_ms": {"type":"integer"},
+                    "timeout_profile": {"type":"string", "description": "named timeout policy from timeout_profiles"}
+                }
+            }
+        }));
+    }
+    list
 }
 
 fn tool_ok(value: Value) -> Value {
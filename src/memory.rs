@@ -0,0 +1,49 @@
+//! Accounting of result payload bytes held by the process.
+//!
+//! Rows fetched by a query are reserved as soon as the executor returns them
+//! and stay reserved until the query is done; every `result` /
+//! `result_rows` output also carries a [`MemoryReservation`] for its row
+//! bytes, released when the output is dropped, i.e. once the writer has
+//! rendered it. New queries are refused with a retryable `backpressure`
+//! error while usage is over the configured budget.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    used: AtomicUsize,
+}
+
+impl MemoryUsage {
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn reserve(self: &Arc<Self>, bytes: usize) -> MemoryReservation {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+        MemoryReservation {
+            usage: Some(Arc::clone(self)),
+            bytes,
+        }
+    }
+}
+
+/// Bytes charged to a [`MemoryUsage`] until dropped.
+#[derive(Debug, Default)]
+pub struct MemoryReservation {
+    usage: Option<Arc<MemoryUsage>>,
+    bytes: usize,
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        if let Some(usage) = &self.usage {
+            usage.used.fetch_sub(self.bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_memory.rs"]
+mod tests;
@@ -0,0 +1,726 @@
+//! In-memory `mock` executor for prototyping without PostgreSQL.
+//!
+//! Tables come from a JSON spec (`--mock SPEC`):
+//!
+//! ```json
+//! {"tables": {"users": [{"id": 1, "name": "ada"}], "orders": {"csv": "orders.csv"}}}
+//! ```
+//!
+//! A table is an array of row objects or `{"csv": PATH}`, PATH relative to
+//! the spec. Only one statement shape is understood:
+//!
+//! `SELECT * | count(*) | expr [AS name], ... [FROM table]
+//!  [WHERE col op value [AND ...]] [ORDER BY col [ASC|DESC], ...]
+//!  [LIMIT n] [OFFSET n]`
+//!
+//! where `expr` is a column, literal or `$n` parameter and `op` is one of
+//! `= <> != < <= > >=`, `IS NULL` or `IS NOT NULL`. Anything else fails with
+//! sqlstate `0A000`.
+
+use crate::db::{DbExecutor, ExecError, ExecOutcome};
+use crate::types::{ResolvedOptions, SessionConfig};
+use async_trait::async_trait;
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+type Row = Map<String, Value>;
+
+pub struct MockExecutor {
+    tables: HashMap<String, Vec<Row>>,
+}
+
+impl MockExecutor {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let spec: Value =
+            serde_json::from_str(&text).map_err(|e| format!("invalid mock spec {path}: {e}"))?;
+        let base = Path::new(path).parent().unwrap_or(Path::new("."));
+        Self::from_spec(&spec, base)
+    }
+
+    /// Build from a parsed spec; CSV paths are resolved against `base`.
+    pub fn from_spec(spec: &Value, base: &Path) -> Result<Self, String> {
+        let Some(tables_spec) = spec.get("tables").and_then(Value::as_object) else {
+            return Err("mock spec needs a \"tables\" object".to_string());
+        };
+        let mut tables = HashMap::new();
+        for (name, table) in tables_spec {
+            let rows = match table {
+                Value::Array(rows) => rows
+                    .iter()
+                    .map(|row| match row {
+                        Value::Object(row) => Ok(row.clone()),
+                        _ => Err(format!("mock table {name}: rows must be objects")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                Value::Object(obj) => match obj.get("csv").and_then(Value::as_str) {
+                    Some(csv) => {
+                        let csv_path = base.join(csv);
+                        let text = std::fs::read_to_string(&csv_path).map_err(|e| {
+                            format!("mock table {name}: cannot read {}: {e}", csv_path.display())
+                        })?;
+                        parse_csv(&text)
+                    }
+                    None => return Err(format!("mock table {name}: expected rows or {{\"csv\"}}")),
+                },
+                _ => return Err(format!("mock table {name}: expected rows or {{\"csv\"}}")),
+            };
+            tables.insert(name.to_lowercase(), rows);
+        }
+        Ok(Self { tables })
+    }
+
+    fn run(&self, sql: &str, params: &[Value]) -> Result<Vec<Value>, ExecError> {
+        let tokens = tokenize(sql)?;
+        let select = Parser {
+            tokens,
+            pos: 0,
+            params,
+        }
+        .parse_select()?;
+
+        let rows: Vec<Row> = match &select.from {
+            None => vec![Row::new()],
+            Some(table) => {
+                let Some(rows) = self.tables.get(table) else {
+                    return Err(sql_error(
+                        "42P01",
+                        format!("relation \"{table}\" does not exist"),
+                    ));
+                };
+                let known: BTreeSet<&String> = rows.iter().flat_map(|r| r.keys()).collect();
+                if !rows.is_empty() {
+                    for col in select.columns() {
+                        if !known.contains(col) {
+                            return Err(sql_error(
+                                "42703",
+                                format!("column \"{col}\" does not exist"),
+                            ));
+                        }
+                    }
+                }
+                rows.clone()
+            }
+        };
+        if select.from.is_none() {
+            if let Some(col) = select.columns().into_iter().next() {
+                return Err(sql_error(
+                    "42703",
+                    format!("column \"{col}\" does not exist"),
+                ));
+            }
+        }
+
+        let mut rows: Vec<Row> = rows
+            .into_iter()
+            .filter(|row| select.filters.iter().all(|f| f.matches(row)))
+            .collect();
+        for (col, desc) in select.order_by.iter().rev() {
+            rows.sort_by(|a, b| {
+                let ord = order_values(a.get(col), b.get(col));
+                if *desc {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            });
+        }
+
+        if let Projection::Count(name) = &select.projection {
+            let mut row = Row::new();
+            row.insert(name.clone(), Value::from(rows.len()));
+            return Ok(vec![Value::Object(row)]);
+        }
+
+        let rows = rows
+            .into_iter()
+            .skip(select.offset)
+            .take(select.limit.unwrap_or(usize::MAX));
+        Ok(rows
+            .map(|row| match &select.projection {
+                Projection::Items(items) => {
+                    let mut out = Row::new();
+                    for (expr, name) in items {
+                        let value = match expr {
+                            Expr::Column(col) => row.get(col).cloned().unwrap_or(Value::Null),
+                            Expr::Value(v) => v.clone(),
+                        };
+                        out.insert(name.clone(), value);
+                    }
+                    Value::Object(out)
+                }
+                _ => Value::Object(row),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl DbExecutor for MockExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        self.run(sql, params).map(ExecOutcome::Rows)
+    }
+}
+
+fn sql_error(sqlstate: &str, message: String) -> ExecError {
+    ExecError::Sql {
+        sqlstate: sqlstate.to_string(),
+        message,
+        detail: None,
+        hint: None,
+        position: None,
+    }
+}
+
+fn unsupported(what: &str) -> ExecError {
+    ExecError::Sql {
+        sqlstate: "0A000".to_string(),
+        message: format!("mock executor does not support {what}"),
+        detail: None,
+        hint: Some(
+            "the mock executor understands SELECT ... FROM ... WHERE ... ORDER BY ... LIMIT ... OFFSET"
+                .to_string(),
+        ),
+        position: None,
+    }
+}
+
+/// Header row, then one row per line; `""` quoting, numbers and
+/// `true`/`false` are typed, empty fields are null.
+fn parse_csv(text: &str) -> Vec<Row> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let header: Vec<String> = split_csv_line(header)
+        .into_iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    lines
+        .map(|line| {
+            header
+                .iter()
+                .cloned()
+                .zip(split_csv_line(line).into_iter().map(csv_value))
+                .collect()
+        })
+        .collect()
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn csv_value(field: String) -> Value {
+    match field.as_str() {
+        "" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => number_value(&field).unwrap_or(Value::String(field)),
+    }
+}
+
+fn number_value(text: &str) -> Option<Value> {
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(Value::from(n));
+    }
+    text.parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+}
+
+fn as_f64(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// `None` when either side is null or the types cannot be compared.
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Number(_), _) | (_, Value::Number(_)) => as_f64(a)?.partial_cmp(&as_f64(b)?),
+        _ => None,
+    }
+}
+
+/// Sort order with nulls last.
+fn order_values(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    let a = a.filter(|v| !v.is_null());
+    let b = b.filter(|v| !v.is_null());
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => compare_values(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keyword or bare identifier, lowercased.
+    Word(String),
+    /// `"quoted"` identifier, case kept.
+    Ident(String),
+    Str(String),
+    Num(Value),
+    Param(usize),
+    Sym(&'static str),
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, ExecError> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word.to_lowercase()));
+        } else if c.is_ascii_digit() {
+            let mut num = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                num.push(c);
+                chars.next();
+            }
+            let value = number_value(&num)
+                .ok_or_else(|| sql_error("42601", format!("invalid number {num}")))?;
+            tokens.push(Token::Num(value));
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some(q) if q == c && chars.peek() == Some(&c) => {
+                        text.push(c);
+                        chars.next();
+                    }
+                    Some(q) if q == c => break,
+                    Some(q) => text.push(q),
+                    None => {
+                        return Err(sql_error("42601", "unterminated quoted string".to_string()))
+                    }
+                }
+            }
+            tokens.push(if c == '\'' {
+                Token::Str(text)
+            } else {
+                Token::Ident(text)
+            });
+        } else if c == '$' {
+            chars.next();
+            let mut num = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                num.push(c);
+                chars.next();
+            }
+            let n = num
+                .parse()
+                .map_err(|_| sql_error("42601", "syntax error at or near \"$\"".to_string()))?;
+            tokens.push(Token::Param(n));
+        } else {
+            chars.next();
+            let two = chars.peek().map(|next| (c, *next));
+            let sym = match two {
+                Some(('<', '=')) => Some("<="),
+                Some(('>', '=')) => Some(">="),
+                Some(('<', '>')) => Some("<>"),
+                Some(('!', '=')) => Some("!="),
+                _ => None,
+            };
+            if let Some(sym) = sym {
+                chars.next();
+                tokens.push(Token::Sym(sym));
+                continue;
+            }
+            let sym = match c {
+                '*' => "*",
+                ',' => ",",
+                '(' => "(",
+                ')' => ")",
+                '=' => "=",
+                '<' => "<",
+                '>' => ">",
+                ';' => ";",
+                '-' => "-",
+                other => return Err(unsupported(&format!("\"{other}\""))),
+            };
+            tokens.push(Token::Sym(sym));
+        }
+    }
+    while tokens.last() == Some(&Token::Sym(";")) {
+        tokens.pop();
+    }
+    Ok(tokens)
+}
+
+enum Expr {
+    Column(String),
+    Value(Value),
+}
+
+enum Projection {
+    All,
+    Count(String),
+    Items(Vec<(Expr, String)>),
+}
+
+enum Filter {
+    Compare {
+        column: String,
+        op: &'static str,
+        value: Value,
+    },
+    IsNull {
+        column: String,
+        negated: bool,
+    },
+}
+
+impl Filter {
+    fn column(&self) -> &String {
+        match self {
+            Filter::Compare { column, .. } | Filter::IsNull { column, .. } => column,
+        }
+    }
+
+    fn matches(&self, row: &Row) -> bool {
+        match self {
+            Filter::IsNull { column, negated } => {
+                let is_null = row.get(column).is_none_or(Value::is_null);
+                is_null != *negated
+            }
+            Filter::Compare { column, op, value } => {
+                let Some(ord) = row
+                    .get(column)
+                    .and_then(|field| compare_values(field, value))
+                else {
+                    return false;
+                };
+                match *op {
+                    "=" => ord == Ordering::Equal,
+                    "<>" | "!=" => ord != Ordering::Equal,
+                    "<" => ord == Ordering::Less,
+                    "<=" => ord != Ordering::Greater,
+                    ">" => ord == Ordering::Greater,
+                    _ => ord != Ordering::Less,
+                }
+            }
+        }
+    }
+}
+
+struct Select {
+    projection: Projection,
+    from: Option<String>,
+    filters: Vec<Filter>,
+    order_by: Vec<(String, bool)>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl Select {
+    /// Every column the statement reads.
+    fn columns(&self) -> Vec<&String> {
+        let mut cols = Vec::new();
+        if let Projection::Items(items) = &self.projection {
+            for (expr, _) in items {
+                if let Expr::Column(col) = expr {
+                    cols.push(col);
+                }
+            }
+        }
+        cols.extend(self.filters.iter().map(Filter::column));
+        cols.extend(self.order_by.iter().map(|(col, _)| col));
+        cols
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    params: &'a [Value],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Word(w)) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_sym(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Sym(s)) if *s == sym) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ExecError> {
+        if self.eat_word(word) {
+            Ok(())
+        } else {
+            Err(self.syntax_error())
+        }
+    }
+
+    fn syntax_error(&self) -> ExecError {
+        let near = match self.peek() {
+            Some(Token::Word(w) | Token::Ident(w)) => w.clone(),
+            Some(Token::Str(s)) => format!("'{s}'"),
+            Some(Token::Num(n)) => n.to_string(),
+            Some(Token::Param(n)) => format!("${n}"),
+            Some(Token::Sym(s)) => s.to_string(),
+            None => return sql_error("42601", "syntax error at end of input".to_string()),
+        };
+        sql_error("42601", format!("syntax error at or near \"{near}\""))
+    }
+
+    fn identifier(&mut self) -> Result<String, ExecError> {
+        match self.next() {
+            Some(Token::Word(w) | Token::Ident(w)) => Ok(w),
+            _ => {
+                self.pos -= 1;
+                Err(self.syntax_error())
+            }
+        }
+    }
+
+    fn count(&mut self) -> Result<usize, ExecError> {
+        match self.next() {
+            Some(Token::Num(Value::Number(n))) if n.as_u64().is_some() => {
+                Ok(n.as_u64().unwrap_or(0) as usize)
+            }
+            Some(Token::Param(n)) => match self.param(n)? {
+                Value::Number(n) if n.as_u64().is_some() => Ok(n.as_u64().unwrap_or(0) as usize),
+                Value::String(s) => s
+                    .parse()
+                    .map_err(|_| sql_error("22P02", format!("invalid count {s}"))),
+                other => Err(sql_error("22P02", format!("invalid count {other}"))),
+            },
+            _ => {
+                self.pos -= 1;
+                Err(self.syntax_error())
+            }
+        }
+    }
+
+    fn param(&self, n: usize) -> Result<Value, ExecError> {
+        n.checked_sub(1)
+            .and_then(|i| self.params.get(i))
+            .cloned()
+            .ok_or_else(|| sql_error("42P02", format!("there is no parameter ${n}")))
+    }
+
+    /// A literal or parameter; `None` if the next token is neither.
+    fn value(&mut self) -> Result<Option<Value>, ExecError> {
+        let value = match self.peek().cloned() {
+            Some(Token::Str(s)) => Value::String(s),
+            Some(Token::Num(n)) => n,
+            Some(Token::Param(n)) => self.param(n)?,
+            Some(Token::Word(w)) if w == "null" => Value::Null,
+            Some(Token::Word(w)) if w == "true" => Value::Bool(true),
+            Some(Token::Word(w)) if w == "false" => Value::Bool(false),
+            Some(Token::Sym("-")) => {
+                self.pos += 1;
+                return match self.next() {
+                    Some(Token::Num(Value::Number(n))) => {
+                        let negated = match n.as_i64() {
+                            Some(i) => Some(Value::from(-i)),
+                            None => n.as_f64().and_then(|f| number_value(&(-f).to_string())),
+                        };
+                        Ok(negated)
+                    }
+                    _ => {
+                        self.pos -= 1;
+                        Err(self.syntax_error())
+                    }
+                };
+            }
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(value))
+    }
+
+    fn parse_select(mut self) -> Result<Select, ExecError> {
+        if !self.eat_word("select") {
+            return Err(unsupported("statements other than SELECT"));
+        }
+        let projection = self.projection()?;
+        let from = if self.eat_word("from") {
+            Some(self.identifier()?.to_lowercase())
+        } else {
+            None
+        };
+        let mut filters = Vec::new();
+        if self.eat_word("where") {
+            loop {
+                filters.push(self.filter()?);
+                if !self.eat_word("and") {
+                    break;
+                }
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.eat_word("order") {
+            self.expect_word("by")?;
+            loop {
+                let col = self.identifier()?;
+                let desc = self.eat_word("desc");
+                if !desc {
+                    self.eat_word("asc");
+                }
+                order_by.push((col, desc));
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+        }
+        let mut limit = None;
+        let mut offset = 0;
+        loop {
+            if self.eat_word("limit") {
+                limit = Some(self.count()?);
+            } else if self.eat_word("offset") {
+                offset = self.count()?;
+            } else {
+                break;
+            }
+        }
+        match self.peek() {
+            None => Ok(Select {
+                projection,
+                from,
+                filters,
+                order_by,
+                limit,
+                offset,
+            }),
+            Some(Token::Word(w))
+                if ["join", "group", "having", "union", "or"].contains(&w.as_str()) =>
+            {
+                Err(unsupported(&w.to_uppercase()))
+            }
+            Some(_) => Err(self.syntax_error()),
+        }
+    }
+
+    fn projection(&mut self) -> Result<Projection, ExecError> {
+        if self.eat_sym("*") {
+            return Ok(Projection::All);
+        }
+        if matches!(self.peek(), Some(Token::Word(w)) if w == "count")
+            && self.tokens.get(self.pos + 1) == Some(&Token::Sym("("))
+        {
+            self.pos += 2;
+            if !self.eat_sym("*") || !self.eat_sym(")") {
+                return Err(unsupported("count() over anything but *"));
+            }
+            let name = self.alias()?.unwrap_or_else(|| "count".to_string());
+            return Ok(Projection::Count(name));
+        }
+        let mut items = Vec::new();
+        loop {
+            let item = match self.value()? {
+                Some(value) => (Expr::Value(value), "?column?".to_string()),
+                None => {
+                    let col = self.identifier()?;
+                    if self.peek() == Some(&Token::Sym("(")) {
+                        return Err(unsupported(&format!("function {col}()")));
+                    }
+                    (Expr::Column(col.clone()), col)
+                }
+            };
+            let name = self.alias()?.unwrap_or(item.1);
+            items.push((item.0, name));
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+        Ok(Projection::Items(items))
+    }
+
+    fn alias(&mut self) -> Result<Option<String>, ExecError> {
+        if self.eat_word("as") {
+            Ok(Some(self.identifier()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn filter(&mut self) -> Result<Filter, ExecError> {
+        let column = self.identifier()?;
+        if self.eat_word("is") {
+            let negated = self.eat_word("not");
+            self.expect_word("null")?;
+            return Ok(Filter::IsNull { column, negated });
+        }
+        let op = match self.next() {
+            Some(Token::Sym(op)) if ["=", "<>", "!=", "<", "<=", ">", ">="].contains(&op) => op,
+            Some(Token::Word(w)) => return Err(unsupported(&w.to_uppercase())),
+            _ => {
+                self.pos -= 1;
+                return Err(self.syntax_error());
+            }
+        };
+        match self.value()? {
+            Some(value) => Ok(Filter::Compare { column, op, value }),
+            None => Err(unsupported(
+                "comparisons against anything but a literal or parameter",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_mock.rs"]
+mod tests;
@@ -0,0 +1,142 @@
+//! Notifications for queries with `notify`.
+//!
+//! A query that ran for at least `after_ms` announces that it finished, or
+//! failed, to a webhook or a command, so a person watching an agent hears
+//! about slow work without reading the output stream. The record carries a
+//! Slack style `text` line besides the details, so it can be posted to a
+//! Slack incoming webhook as is (`https://` needs the `https` feature). A command gets the record as JSON
+//! on stdin and the text in `AFPSQL_NOTIFY_TEXT`, which suits `notify-send`
+//! and `curl` alike.
+
+use crate::schedule::{self, Sink};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// Duration from which a query notifies when `after_ms` is not given.
+pub const DEFAULT_AFTER_MS: u64 = 10_000;
+
+/// Longest a notification command may run.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Characters of the statement quoted in a notification.
+const SQL_CHARS: usize = 200;
+
+/// Where a query's completion is announced, and from what duration.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Notify {
+    #[serde(flatten)]
+    pub target: NotifyTarget,
+    #[serde(default = "default_after_ms")]
+    pub after_ms: u64,
+}
+
+fn default_after_ms() -> u64 {
+    DEFAULT_AFTER_MS
+}
+
+/// `{"url": "http(s)://..."}` posts the record; `{"command": [...]}` runs a
+/// program without a shell.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyTarget {
+    Url(String),
+    Command(Vec<String>),
+}
+
+impl Notify {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.target {
+            NotifyTarget::Url(url) => schedule::split_url(url).map(|_| ()),
+            NotifyTarget::Command(argv) if argv.first().is_none_or(String::is_empty) => {
+                Err("notify.command needs a program".to_string())
+            }
+            NotifyTarget::Command(_) => Ok(()),
+        }
+    }
+}
+
+/// How a notified query ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finished<'a> {
+    pub id: Option<&'a str>,
+    pub session: &'a str,
+    pub sql: &'a str,
+    /// As in `history`: `result`, `sql_error` or `error`.
+    pub outcome: &'a str,
+    pub error_code: Option<&'a str>,
+    pub row_count: Option<usize>,
+    pub duration_ms: u64,
+}
+
+/// The JSON record sent for `finished`.
+pub fn record(finished: &Finished) -> Value {
+    let name = finished
+        .id
+        .map_or_else(|| "query".to_string(), |id| format!("query {id}"));
+    let seconds = finished.duration_ms as f64 / 1000.0;
+    let ending = match (finished.outcome, finished.error_code, finished.row_count) {
+        ("result", _, Some(1)) => "finished with 1 row".to_string(),
+        ("result", _, Some(rows)) => format!("finished with {rows} rows"),
+        ("result", _, None) => "finished".to_string(),
+        (outcome, Some(code), _) => format!("failed ({outcome} {code})"),
+        (outcome, None, _) => format!("failed ({outcome})"),
+    };
+    let mut sql: String = finished.sql.chars().take(SQL_CHARS).collect();
+    if sql.len() < finished.sql.len() {
+        sql.push_str("...");
+    }
+    json!({
+        "event": "query.finished",
+        "text": format!("afpsql: {name} on {} {ending} after {seconds:.1}s", finished.session),
+        "id": finished.id,
+        "session": finished.session,
+        "outcome": finished.outcome,
+        "error_code": finished.error_code,
+        "row_count": finished.row_count,
+        "duration_ms": finished.duration_ms,
+        "sql": sql,
+    })
+}
+
+/// Send `record` to `notify`'s target.
+pub async fn send(notify: &Notify, record: &Value) -> Result<(), String> {
+    match &notify.target {
+        NotifyTarget::Url(url) => schedule::deliver(&Sink::Url(url.clone()), record).await,
+        NotifyTarget::Command(argv) => run_command(argv, record)
+            .await
+            .map_err(|e| format!("notify command {}: {e}", argv[0])),
+    }
+}
+
+async fn run_command(argv: &[String], record: &Value) -> Result<(), String> {
+    let text = record["text"].as_str().unwrap_or_default();
+    let mut child = tokio::process::Command::new(&argv[0])
+        .args(&argv[1..])
+        .env("AFPSQL_NOTIFY_TEXT", text)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores its input may exit before reading it.
+        let _ = stdin.write_all(record.to_string().as_bytes()).await;
+    }
+    let status = tokio::time::timeout(COMMAND_TIMEOUT, child.wait())
+        .await
+        .map_err(|_| "did not exit within 10s".to_string())?
+        .map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("exited with {status}"))
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_notify.rs"]
+mod tests;
@@ -0,0 +1,280 @@
+//! Managed operations: batched writes that can be paused and resumed.
+//!
+//! `archive`, `import` and chunked `psql_update` / `psql_delete` check
+//! between batches whether they were asked to pause, and wait there until
+//! resumed. With `state_dir` set, each also saves its request and a cursor
+//! saying how far it got to `<state_dir>/operations/<id>.json` after every
+//! batch. The file is removed once the operation completes, so one left
+//! behind belongs to an operation that was paused, failed or cut short, and
+//! `resume` continues it from the cursor, in this process or a later one.
+
+use crate::deadletter::DeadRow;
+use crate::results::valid_handle;
+use crate::types::{ArchiveRequest, ChunkedWriteRequest, ImportRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    Archive(ArchiveRequest),
+    Chunked(ChunkedWriteRequest),
+    Import(ImportRequest),
+}
+
+impl Operation {
+    /// As in the `phase` of its `progress` events.
+    pub fn phase(&self) -> &'static str {
+        match self {
+            Self::Archive(_) => "archive",
+            Self::Chunked(req) if req.set.is_some() => "update",
+            Self::Chunked(_) => "delete",
+            Self::Import(_) => "import",
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        match self {
+            Self::Archive(req) => &req.table,
+            Self::Chunked(req) => &req.table,
+            Self::Import(req) => &req.table,
+        }
+    }
+}
+
+/// How far an operation got.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Rows moved or written; for an import, rows imported.
+    pub done: u64,
+    pub batches: usize,
+    /// Chunked update: the last primary key written, as text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Vec<Value>>,
+    /// Import: records read, rejected ones included.
+    #[serde(default)]
+    pub records_read: usize,
+    /// Import: records rejected so far, for the dead letter and
+    /// `max_errors`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<DeadRow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub operation: Operation,
+    pub cursor: Cursor,
+}
+
+/// A managed operation as listed by `operations_list`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OperationEntry {
+    pub id: String,
+    pub phase: String,
+    pub table: String,
+    /// `running` or `paused` in this process, or `saved` for a checkpoint
+    /// under `state_dir` that nothing here is running.
+    pub state: String,
+    pub done: u64,
+    pub batches: usize,
+}
+
+/// File holding the checkpoint of operation `id`. Ids name files, so they
+/// are held to the characters of a result handle.
+pub fn checkpoint_path(state_dir: &str, id: &str) -> Result<PathBuf, String> {
+    if !valid_handle(id) {
+        return Err(format!(
+            "operation id {id} must be 1-128 letters, digits, '_' or '-' to be saved"
+        ));
+    }
+    Ok(Path::new(state_dir)
+        .join("operations")
+        .join(format!("{id}.json")))
+}
+
+/// Write `operation` (already serialized) and `cursor` to `path`, replacing
+/// the previous checkpoint only once the new one is complete.
+pub fn save(path: &Path, operation: &Value, cursor: &Cursor) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let body = serde_json::to_vec(&serde_json::json!({
+        "operation": operation,
+        "cursor": cursor,
+    }))?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, body)?;
+    std::fs::rename(tmp, path)
+}
+
+pub fn load(path: &Path) -> std::io::Result<Checkpoint> {
+    let body = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+pub fn remove(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+impl Checkpoint {
+    /// How the checkpoint of operation `id` is listed when nothing here
+    /// runs it.
+    pub fn entry(&self, id: &str) -> OperationEntry {
+        let done = match self.operation {
+            Operation::Import(_) => self.cursor.records_read as u64,
+            _ => self.cursor.done,
+        };
+        OperationEntry {
+            id: id.to_string(),
+            phase: self.operation.phase().to_string(),
+            table: self.operation.table().to_string(),
+            state: "saved".to_string(),
+            done,
+            batches: self.cursor.batches,
+        }
+    }
+}
+
+/// Checkpoints under `state_dir`, by id; unreadable files are skipped.
+pub fn saved(state_dir: &str) -> Vec<(String, Checkpoint)> {
+    let dir = Path::new(state_dir).join("operations");
+    let mut saved: Vec<(String, Checkpoint)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                return None;
+            }
+            let id = path.file_stem()?.to_str()?.to_string();
+            Some((id, load(&path).ok()?))
+        })
+        .collect();
+    saved.sort_by(|a, b| a.0.cmp(&b.0));
+    saved
+}
+
+#[derive(Debug)]
+struct Running {
+    pause: watch::Sender<bool>,
+    phase: &'static str,
+    table: String,
+    done: u64,
+    batches: usize,
+}
+
+/// Pause switches of the operations running in this process, by id.
+#[derive(Debug, Default)]
+pub struct Controls {
+    running: Mutex<HashMap<String, Running>>,
+}
+
+impl Controls {
+    /// Register `operation` as `id`; `None` if one with that id is running.
+    pub fn register(self: &Arc<Self>, id: &str, operation: &Operation) -> Option<Control> {
+        let mut running = self.running.lock().ok()?;
+        if running.contains_key(id) {
+            return None;
+        }
+        let (tx, rx) = watch::channel(false);
+        running.insert(
+            id.to_string(),
+            Running {
+                pause: tx,
+                phase: operation.phase(),
+                table: operation.table().to_string(),
+                done: 0,
+                batches: 0,
+            },
+        );
+        Some(Control {
+            controls: self.clone(),
+            id: id.to_string(),
+            paused: rx,
+        })
+    }
+
+    /// Ask operation `id` to pause after its current batch, or to go on;
+    /// `false` if no such operation is running.
+    pub fn set_paused(&self, id: &str, paused: bool) -> bool {
+        let Ok(running) = self.running.lock() else {
+            return false;
+        };
+        running.get(id).is_some_and(|r| {
+            r.pause.send_replace(paused);
+            true
+        })
+    }
+
+    /// The operations running in this process, by id.
+    pub fn list(&self) -> Vec<OperationEntry> {
+        let Ok(running) = self.running.lock() else {
+            return vec![];
+        };
+        let mut entries: Vec<OperationEntry> = running
+            .iter()
+            .map(|(id, r)| OperationEntry {
+                id: id.clone(),
+                phase: r.phase.to_string(),
+                table: r.table.clone(),
+                state: if *r.pause.borrow() {
+                    "paused"
+                } else {
+                    "running"
+                }
+                .to_string(),
+                done: r.done,
+                batches: r.batches,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        entries
+    }
+}
+
+/// A running operation's end of its pause switch; unregisters the operation
+/// when dropped, including when its task is cancelled.
+#[derive(Debug)]
+pub struct Control {
+    controls: Arc<Controls>,
+    id: String,
+    paused: watch::Receiver<bool>,
+}
+
+impl Control {
+    pub fn paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Wait until the operation is resumed.
+    pub async fn resumed(&mut self) {
+        let _ = self.paused.wait_for(|paused| !paused).await;
+    }
+
+    /// Record how far the operation got, for `operations_list`.
+    pub fn progress(&self, done: u64, batches: usize) {
+        if let Ok(mut running) = self.controls.running.lock() {
+            if let Some(r) = running.get_mut(&self.id) {
+                r.done = done;
+                r.batches = batches;
+            }
+        }
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.controls.running.lock() {
+            running.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_operations.rs"]
+mod tests;
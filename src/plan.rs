@@ -0,0 +1,162 @@
+//! Baseline plans for `psql_plan_check`.
+//!
+//! A plan is reduced to its shape (node types with their relations and
+//! indexes, in tree order) and the planner's estimated total cost, and kept
+//! under a fingerprint of its session and whitespace-normalized SQL. Later
+//! checks of the same statement report whether the shape changed or the cost
+//! moved by more than a threshold.
+
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Baselines kept before the least recently checked are dropped.
+pub const MAX_BASELINES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanSnapshot {
+    /// One line per plan node, indented two spaces per level.
+    pub shape: Vec<String>,
+    pub total_cost: f64,
+    pub plan_rows: f64,
+}
+
+/// How a plan compares with its baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanDelta {
+    pub shape_changed: bool,
+    /// `(current - baseline) / baseline` total cost, rounded to four places;
+    /// `None` when the baseline cost is zero and the current is not.
+    pub cost_change: Option<f64>,
+    pub cost_changed: bool,
+}
+
+/// Stable key for `sql` on `session`; runs of whitespace count as one space.
+pub fn sql_fingerprint(session: &str, sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    for ch in sql.trim().trim_end_matches(';').trim_end().chars() {
+        if ch.is_whitespace() {
+            if !normalized.ends_with(' ') {
+                normalized.push(' ');
+            }
+        } else {
+            normalized.push(ch);
+        }
+    }
+    let digest = Sha256::digest(format!("{session}\n{normalized}").as_bytes());
+    digest.iter().take(8).map(|b| format!("{b:02x}")).collect()
+}
+
+/// Short digest of a plan's shape, equal for plans that differ only in
+/// their cost estimates.
+pub fn plan_hash(snapshot: &PlanSnapshot) -> String {
+    let digest = Sha256::digest(snapshot.shape.join("\n").as_bytes());
+    digest.iter().take(8).map(|b| format!("{b:02x}")).collect()
+}
+
+/// The root node of an `EXPLAIN (FORMAT JSON)` result: the
+/// `[{"Plan": ...}]` document, or a row holding it.
+pub fn plan_root(explain: &Value) -> Option<&Value> {
+    let document = match explain {
+        Value::Object(row) => row.values().next()?,
+        other => other,
+    };
+    document.get(0)?.get("Plan")
+}
+
+/// The snapshot of an `EXPLAIN (FORMAT JSON)` result (see [`plan_root`]).
+pub fn snapshot(explain: &Value) -> Option<PlanSnapshot> {
+    let plan = plan_root(explain)?;
+    let mut shape = vec![];
+    push_shape(plan, 0, &mut shape);
+    Some(PlanSnapshot {
+        shape,
+        total_cost: plan.get("Total Cost")?.as_f64()?,
+        plan_rows: plan.get("Plan Rows").and_then(Value::as_f64).unwrap_or(0.0),
+    })
+}
+
+fn push_shape(node: &Value, depth: usize, shape: &mut Vec<String>) {
+    let field = |name: &str| node.get(name).and_then(Value::as_str);
+    let mut line = format!(
+        "{}{}",
+        "  ".repeat(depth),
+        field("Node Type").unwrap_or("?")
+    );
+    if let Some(join) = field("Join Type") {
+        line.push_str(&format!(" ({join})"));
+    }
+    if let Some(index) = field("Index Name") {
+        line.push_str(&format!(" using {index}"));
+    }
+    if let Some(relation) = field("Relation Name") {
+        line.push_str(&format!(" on {relation}"));
+    }
+    shape.push(line);
+    for child in node
+        .get("Plans")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        push_shape(child, depth + 1, shape);
+    }
+}
+
+/// Compare `current` with `baseline`; a cost change counts once its
+/// magnitude exceeds `cost_threshold` (a fraction, `0.2` for 20%).
+pub fn compare(baseline: &PlanSnapshot, current: &PlanSnapshot, cost_threshold: f64) -> PlanDelta {
+    let cost_change = if baseline.total_cost == 0.0 {
+        (current.total_cost == 0.0).then_some(0.0)
+    } else {
+        let ratio = (current.total_cost - baseline.total_cost) / baseline.total_cost;
+        Some((ratio * 10_000.0).round() / 10_000.0)
+    };
+    PlanDelta {
+        shape_changed: baseline.shape != current.shape,
+        cost_change,
+        cost_changed: cost_change.is_none_or(|c| c.abs() > cost_threshold),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PlanBaselines {
+    /// Snapshot and the sequence number of its last check, by fingerprint.
+    entries: HashMap<String, (PlanSnapshot, u64)>,
+    next_seq: u64,
+}
+
+impl PlanBaselines {
+    /// The baseline for `fingerprint`, marked as just used.
+    pub fn get(&mut self, fingerprint: &str) -> Option<PlanSnapshot> {
+        self.next_seq += 1;
+        let seq = self.next_seq;
+        self.entries.get_mut(fingerprint).map(|(snapshot, used)| {
+            *used = seq;
+            snapshot.clone()
+        })
+    }
+
+    /// Store `snapshot` as the baseline for `fingerprint`, dropping the least
+    /// recently used beyond [`MAX_BASELINES`].
+    pub fn set(&mut self, fingerprint: String, snapshot: PlanSnapshot) {
+        self.next_seq += 1;
+        self.entries.insert(fingerprint, (snapshot, self.next_seq));
+        while self.entries.len() > MAX_BASELINES {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, seq))| *seq)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_plan.rs"]
+mod tests;
@@ -0,0 +1,176 @@
+//! The process's own counters, for `debug_stats`.
+//!
+//! `debug_stats` diagnoses afpsql rather than the database: tokio's view of
+//! its tasks, how full the output channels are, the connection pools, memory
+//! and the time spent in a few hot modules. Those modules hold a `timer`
+//! while they work; dropping it adds to a table kept for the whole process,
+//! so the writer task, which has no `App`, counts as well.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static TIMINGS: Mutex<BTreeMap<&'static str, ModuleTiming>> = Mutex::new(BTreeMap::new());
+
+/// Time spent in one module since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ModuleTiming {
+    pub calls: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+impl ModuleTiming {
+    fn add(&mut self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        self.calls += 1;
+        self.total_us += us;
+        self.max_us = self.max_us.max(us);
+    }
+}
+
+/// Counts the time until it is dropped against `module`.
+pub struct Timer {
+    module: &'static str,
+    start: Instant,
+}
+
+pub fn timer(module: &'static str) -> Timer {
+    Timer {
+        module,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        record(self.module, self.start.elapsed());
+    }
+}
+
+pub fn record(module: &'static str, elapsed: Duration) {
+    if let Ok(mut timings) = TIMINGS.lock() {
+        timings.entry(module).or_default().add(elapsed);
+    }
+}
+
+pub fn timings() -> BTreeMap<String, ModuleTiming> {
+    TIMINGS
+        .lock()
+        .map(|timings| {
+            timings
+                .iter()
+                .map(|(module, timing)| (module.to_string(), *timing))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// What tokio reports about the runtime the caller runs on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RuntimeStats {
+    pub workers: usize,
+    /// Tasks spawned and not yet finished, the request tasks among them.
+    pub alive_tasks: usize,
+    /// Tasks waiting in the shared queue for a worker.
+    pub global_queue_depth: usize,
+    /// Requests in flight, as `pong` counts them.
+    pub in_flight: usize,
+}
+
+pub fn runtime(in_flight: usize) -> RuntimeStats {
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return RuntimeStats {
+            in_flight,
+            ..Default::default()
+        };
+    };
+    let metrics = handle.metrics();
+    RuntimeStats {
+        workers: metrics.num_workers(),
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+        in_flight,
+    }
+}
+
+/// Events waiting in the output channels.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ChannelStats {
+    pub output_queued: usize,
+    pub output_capacity: usize,
+    /// Waiting for `log_file` or `log_system`, when either is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_queued: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryStats {
+    /// Result data held against `memory_budget_bytes`.
+    pub result_bytes_queued: usize,
+    /// Resident set size and its peak, where `/proc` has them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rss_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+    /// Only in a build with the `jemalloc` feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocator: Option<AllocatorStats>,
+}
+
+/// jemalloc's own accounting, in bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AllocatorStats {
+    /// Handed out to the program.
+    pub allocated: u64,
+    /// In pages holding allocations.
+    pub active: u64,
+    /// Physically resident, including allocator metadata.
+    pub resident: u64,
+    /// Mapped from the system.
+    pub mapped: u64,
+}
+
+pub fn memory(result_bytes_queued: usize) -> MemoryStats {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    MemoryStats {
+        result_bytes_queued,
+        rss_bytes: status_kb(&status, "VmRSS:").map(|kb| kb * 1024),
+        peak_rss_bytes: status_kb(&status, "VmHWM:").map(|kb| kb * 1024),
+        allocator: allocator(),
+    }
+}
+
+/// A `/proc/self/status` line such as `VmRSS:     1234 kB`.
+fn status_kb(status: &str, key: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with(key))?;
+    line[key.len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(feature = "jemalloc")]
+fn allocator() -> Option<AllocatorStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+    // The statistics are a snapshot taken when the epoch advances.
+    epoch::advance().ok()?;
+    Some(AllocatorStats {
+        allocated: stats::allocated::read().ok()? as u64,
+        active: stats::active::read().ok()? as u64,
+        resident: stats::resident::read().ok()? as u64,
+        mapped: stats::mapped::read().ok()? as u64,
+    })
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn allocator() -> Option<AllocatorStats> {
+    None
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_profile.rs"]
+mod tests;
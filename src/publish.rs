@@ -0,0 +1,365 @@
+//! Publishing query results to Kafka or NATS, for queries with
+//! `publish_uri`.
+//!
+//! `kafka://broker:9092[,broker2:9092]/topic` and
+//! `nats://server:4222[,server2:4222]/subject` name the destination. Each
+//! message carries one row as a JSON object, or with `publish_format:
+//! "batch"` a JSON array of up to `batch_rows` rows. `publish_key` columns
+//! key the messages: the Kafka message key, or further subject tokens on
+//! NATS, so `orders` keyed by `customer_id` publishes to `orders.42`.
+//!
+//! Kafka client properties, such as `security.protocol=SASL_SSL`, go in the
+//! URI's query string; the SASL credentials come from `KAFKA_SASL_USERNAME`
+//! and `KAFKA_SASL_PASSWORD`. NATS credentials come from `NATS_USER` and
+//! `NATS_PASSWORD`, `NATS_TOKEN` or `NATS_CREDS`, as for the `nats` tool.
+
+// Without the features only the option checks and encoding are used.
+#![cfg_attr(not(all(feature = "kafka", feature = "nats")), allow(dead_code))]
+
+use crate::types::PublishFormat;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Whether this build includes the `kafka` feature.
+pub const KAFKA_AVAILABLE: bool = cfg!(feature = "kafka");
+
+/// Whether this build includes the `nats` feature.
+pub const NATS_AVAILABLE: bool = cfg!(feature = "nats");
+
+/// Longest a broker may take to accept the connection or a message.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Kafka properties holding secrets, which must not appear in a URI that
+/// is echoed and logged.
+const SECRET_PROPERTIES: [&str; 3] = ["sasl.password", "ssl.key.password", "ssl.keystore.password"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Broker {
+    Kafka,
+    Nats,
+}
+
+impl Broker {
+    pub fn available(self) -> bool {
+        match self {
+            Broker::Kafka => KAFKA_AVAILABLE,
+            Broker::Nats => NATS_AVAILABLE,
+        }
+    }
+
+    pub fn feature(self) -> &'static str {
+        match self {
+            Broker::Kafka => "kafka",
+            Broker::Nats => "nats",
+        }
+    }
+}
+
+/// A `kafka://` topic or `nats://` subject.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishUri {
+    pub broker: Broker,
+    /// `host:port` of each broker or server.
+    pub servers: Vec<String>,
+    /// Kafka topic or NATS subject.
+    pub topic: String,
+    /// Kafka client properties from the query string.
+    pub properties: Vec<(String, String)>,
+}
+
+impl PublishUri {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let (broker, rest) = if let Some(rest) = uri.strip_prefix("kafka://") {
+            (Broker::Kafka, rest)
+        } else if let Some(rest) = uri.strip_prefix("nats://") {
+            (Broker::Nats, rest)
+        } else {
+            return Err(format!("{uri:?} must start with kafka:// or nats://"));
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let Some((authority, topic)) = rest
+            .split_once('/')
+            .filter(|(a, t)| !a.is_empty() && !t.is_empty())
+        else {
+            return Err(format!("{uri:?} needs a server and a topic"));
+        };
+        if authority.contains('@') {
+            return Err(format!(
+                "{uri:?} must not hold credentials; set them in the environment"
+            ));
+        }
+        let servers: Vec<String> = authority.split(',').map(str::to_string).collect();
+        if servers.iter().any(String::is_empty) {
+            return Err(format!("{uri:?} has an empty server in its list"));
+        }
+        if topic.contains('/') || topic.contains(char::is_whitespace) {
+            return Err(format!(
+                "{uri:?}: topic {topic:?} cannot hold '/' or spaces"
+            ));
+        }
+        if broker == Broker::Nats
+            && (topic.contains(['*', '>']) || topic.split('.').any(str::is_empty))
+        {
+            return Err(format!(
+                "{uri:?}: subject {topic:?} must be dot separated tokens without wildcards"
+            ));
+        }
+        let mut properties = vec![];
+        if let Some(query) = query.filter(|q| !q.is_empty()) {
+            if broker == Broker::Nats {
+                return Err(format!("{uri:?}: nats:// takes no query string"));
+            }
+            for pair in query.split('&') {
+                let Some((key, value)) = pair.split_once('=').filter(|(k, _)| !k.is_empty()) else {
+                    return Err(format!("{uri:?}: {pair:?} is not a key=value property"));
+                };
+                if SECRET_PROPERTIES.contains(&key) {
+                    return Err(format!(
+                        "{uri:?}: {key} must not be in the URI; set KAFKA_SASL_PASSWORD instead"
+                    ));
+                }
+                properties.push((key.to_string(), value.to_string()));
+            }
+        }
+        Ok(Self {
+            broker,
+            servers,
+            topic: topic.to_string(),
+            properties,
+        })
+    }
+}
+
+/// One message to publish.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Kafka topic or NATS subject, with the key's tokens on NATS.
+    pub topic: String,
+    /// Kafka message key.
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+/// The messages carrying `rows` to `target`. With `batch`, consecutive
+/// rows with the same key share a message of up to `batch_rows` rows.
+pub fn messages(
+    target: &PublishUri,
+    rows: &[Value],
+    key_columns: &[String],
+    format: PublishFormat,
+    batch_rows: usize,
+) -> Result<Vec<Message>, String> {
+    let mut keyed: Vec<(Option<Vec<String>>, &Value)> = Vec::with_capacity(rows.len());
+    for row in rows {
+        keyed.push((row_key(row, key_columns)?, row));
+    }
+    let mut out = vec![];
+    let mut rest = keyed.as_slice();
+    while let Some((key, _)) = rest.first() {
+        let take = match format {
+            PublishFormat::Row => 1,
+            PublishFormat::Batch => rest
+                .iter()
+                .take(batch_rows.max(1))
+                .take_while(|(k, _)| k == key)
+                .count(),
+        };
+        let (group, tail) = rest.split_at(take);
+        let payload = match format {
+            PublishFormat::Row => group[0].1.to_string(),
+            PublishFormat::Batch => {
+                Value::Array(group.iter().map(|(_, row)| (*row).clone()).collect()).to_string()
+            }
+        };
+        out.push(message(target, key.as_deref(), payload.into_bytes()));
+        rest = tail;
+    }
+    Ok(out)
+}
+
+fn message(target: &PublishUri, key: Option<&[String]>, payload: Vec<u8>) -> Message {
+    match (target.broker, key) {
+        (Broker::Nats, Some(parts)) => {
+            let mut topic = target.topic.clone();
+            for part in parts {
+                topic.push('.');
+                topic.push_str(&subject_token(part));
+            }
+            Message {
+                topic,
+                key: None,
+                payload,
+            }
+        }
+        (Broker::Kafka, Some([single])) => Message {
+            topic: target.topic.clone(),
+            key: Some(single.clone()),
+            payload,
+        },
+        (Broker::Kafka, Some(parts)) => Message {
+            topic: target.topic.clone(),
+            key: Some(Value::from(parts.to_vec()).to_string()),
+            payload,
+        },
+        (_, None) => Message {
+            topic: target.topic.clone(),
+            key: None,
+            payload,
+        },
+    }
+}
+
+/// The `key_columns` values of `row` as text; `None` without key columns.
+fn row_key(row: &Value, key_columns: &[String]) -> Result<Option<Vec<String>>, String> {
+    if key_columns.is_empty() {
+        return Ok(None);
+    }
+    key_columns
+        .iter()
+        .map(|column| match row.get(column) {
+            None => Err(format!("publish_key column {column} is not in the result")),
+            Some(Value::String(s)) => Ok(s.clone()),
+            Some(other) => Ok(other.to_string()),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// `value` as one NATS subject token: separators, wildcards and whitespace
+/// become `_`.
+fn subject_token(value: &str) -> String {
+    if value.is_empty() {
+        return "_".to_string();
+    }
+    value
+        .chars()
+        .map(|c| {
+            if c == '.' || c == '*' || c == '>' || c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Publish `messages` to `target` and wait until the broker has them all;
+/// returns the payload bytes sent.
+pub async fn publish(target: &PublishUri, messages: &[Message]) -> Result<u64, String> {
+    match target.broker {
+        Broker::Kafka => publish_kafka(target, messages).await?,
+        Broker::Nats => publish_nats(target, messages).await?,
+    }
+    Ok(messages.iter().map(|m| m.payload.len() as u64).sum())
+}
+
+#[cfg(feature = "kafka")]
+async fn publish_kafka(target: &PublishUri, messages: &[Message]) -> Result<(), String> {
+    use rdkafka::config::ClientConfig;
+    use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+    use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord};
+    use std::collections::VecDeque;
+
+    async fn delivered(delivery: DeliveryFuture) -> Result<(), String> {
+        match delivery.await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err((e, _))) => Err(e.to_string()),
+            Err(_) => Err("the producer shut down before delivery".to_string()),
+        }
+    }
+
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", target.servers.join(","))
+        .set("message.timeout.ms", TIMEOUT.as_millis().to_string());
+    for (key, value) in &target.properties {
+        config.set(key, value);
+    }
+    for (var, key) in [
+        ("KAFKA_SASL_USERNAME", "sasl.username"),
+        ("KAFKA_SASL_PASSWORD", "sasl.password"),
+    ] {
+        if let Ok(value) = std::env::var(var) {
+            config.set(key, value);
+        }
+    }
+    let producer: FutureProducer = config.create().map_err(|e| e.to_string())?;
+    // Enqueue without waiting for each delivery; when librdkafka's queue is
+    // full, wait for the oldest delivery to make room.
+    let mut pending = VecDeque::new();
+    for message in messages {
+        let mut record = FutureRecord::to(&message.topic).payload(&message.payload);
+        if let Some(key) = &message.key {
+            record = record.key(key);
+        }
+        loop {
+            match producer.send_result(record) {
+                Ok(delivery) => {
+                    pending.push_back(delivery);
+                    break;
+                }
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), back)) => {
+                    record = back;
+                    match pending.pop_front() {
+                        Some(delivery) => delivered(delivery).await?,
+                        None => tokio::time::sleep(Duration::from_millis(10)).await,
+                    }
+                }
+                Err((e, _)) => return Err(e.to_string()),
+            }
+        }
+    }
+    for delivery in pending {
+        delivered(delivery).await?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "kafka"))]
+async fn publish_kafka(_: &PublishUri, _: &[Message]) -> Result<(), String> {
+    Err("kafka:// requires a build with the kafka feature".to_string())
+}
+
+#[cfg(feature = "nats")]
+async fn publish_nats(target: &PublishUri, messages: &[Message]) -> Result<(), String> {
+    let mut options = async_nats::ConnectOptions::new().connection_timeout(TIMEOUT);
+    if let (Ok(user), Ok(password)) = (std::env::var("NATS_USER"), std::env::var("NATS_PASSWORD")) {
+        options = options.user_and_password(user, password);
+    } else if let Ok(token) = std::env::var("NATS_TOKEN") {
+        options = options.token(token);
+    } else if let Ok(path) = std::env::var("NATS_CREDS") {
+        options = options
+            .credentials_file(&path)
+            .await
+            .map_err(|e| format!("cannot read NATS_CREDS {path}: {e}"))?;
+    }
+    let client = options
+        .connect(target.servers.as_slice())
+        .await
+        .map_err(|e| e.to_string())?;
+    for message in messages {
+        client
+            .publish(
+                message.topic.clone(),
+                bytes::Bytes::copy_from_slice(&message.payload),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tokio::time::timeout(TIMEOUT, client.flush())
+        .await
+        .map_err(|_| "the server did not confirm within 30s".to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "nats"))]
+async fn publish_nats(_: &PublishUri, _: &[Message]) -> Result<(), String> {
+    Err("nats:// requires a build with the nats feature".to_string())
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_publish.rs"]
+mod tests;
@@ -0,0 +1,150 @@
+//! Per-agent usage limits from `agent_quotas`.
+//!
+//! Usage is counted in fixed one-minute windows per agent: queries when they
+//! are admitted, result rows and bytes once they are sent. A query is refused
+//! while any count has reached its limit, so one large result can overshoot a
+//! row or byte limit, but then holds the agent off until the window ends.
+//! Only agents that a quota applies to are tracked. Agents counted under the
+//! `"*"` entry share one window: agent names are self-declared, so a window
+//! per name would let a new name start afresh.
+
+use crate::types::AgentQuota;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Name requests without an agent are counted under.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// Entry for agents (or sessions) that have none of their own.
+pub const ANY_AGENT: &str = "*";
+
+/// The entry for `name` in `agent_quotas` or a budget map, with the key
+/// usage is counted under: its own, else the shared `"*"` entry.
+pub fn quota_for<'a, T>(limits: &'a HashMap<String, T>, name: &str) -> Option<(&'a str, &'a T)> {
+    limits
+        .get_key_value(name)
+        .or_else(|| limits.get_key_value(ANY_AGENT))
+        .map(|(key, limit)| (key.as_str(), limit))
+}
+
+/// Merge `patch` into `limits` without loosening them: each patched entry
+/// is `tighten`ed against the one its name falls under now, so neither a
+/// new name nor a changed entry allows more than before.
+pub fn tighten_limits<T>(
+    limits: &mut HashMap<String, T>,
+    patch: HashMap<String, T>,
+    tighten: fn(&T, T) -> T,
+) {
+    for (name, limit) in patch {
+        let limit = match quota_for(limits, &name) {
+            Some((_, current)) => tighten(current, limit),
+            None => limit,
+        };
+        limits.insert(name, limit);
+    }
+}
+
+/// The stricter of two limits, `None` being unlimited.
+pub fn tighter(current: Option<u64>, patch: Option<u64>) -> Option<u64> {
+    match (current, patch) {
+        (Some(current), Some(patch)) => Some(current.min(patch)),
+        (current, patch) => current.or(patch),
+    }
+}
+
+/// `patch` limited to what `current` allows.
+pub fn tighten_quota(current: &AgentQuota, patch: AgentQuota) -> AgentQuota {
+    AgentQuota {
+        queries_per_minute: tighter(current.queries_per_minute, patch.queries_per_minute),
+        rows_per_minute: tighter(current.rows_per_minute, patch.rows_per_minute),
+        bytes_per_minute: tighter(current.bytes_per_minute, patch.bytes_per_minute),
+    }
+}
+
+#[derive(Debug)]
+struct Usage {
+    started: Instant,
+    queries: u64,
+    rows: u64,
+    bytes: u64,
+}
+
+impl Usage {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            queries: 0,
+            rows: 0,
+            bytes: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Quotas {
+    usage: HashMap<String, Usage>,
+}
+
+impl Quotas {
+    /// Count one query for `agent` (the key from [`quota_for`]), or say
+    /// which limit it has reached and when to retry.
+    pub fn admit(&mut self, agent: &str, quota: &AgentQuota, now: Instant) -> Result<(), String> {
+        let usage = self.window(agent, now);
+        let reached = [
+            (
+                "queries_per_minute",
+                quota.queries_per_minute,
+                usage.queries,
+            ),
+            ("rows_per_minute", quota.rows_per_minute, usage.rows),
+            ("bytes_per_minute", quota.bytes_per_minute, usage.bytes),
+        ]
+        .into_iter()
+        .find(|(_, limit, used)| limit.is_some_and(|limit| *used >= limit));
+        if let Some((name, limit, used)) = reached {
+            let retry_s = WINDOW
+                .saturating_sub(now.duration_since(usage.started))
+                .as_secs()
+                .max(1);
+            let who = if agent == ANY_AGENT {
+                "agents without a quota of their own".to_string()
+            } else {
+                format!("agent {agent}")
+            };
+            return Err(format!(
+                "{who} used {used} of {name} {}; retry in {retry_s}s",
+                limit.unwrap_or_default()
+            ));
+        }
+        usage.queries += 1;
+        Ok(())
+    }
+
+    /// Add a sent result to `agent`'s window, if it is tracked.
+    pub fn charge(&mut self, agent: &str, rows: u64, bytes: u64, now: Instant) {
+        if !self.usage.contains_key(agent) {
+            return;
+        }
+        let usage = self.window(agent, now);
+        usage.rows += rows;
+        usage.bytes += bytes;
+    }
+
+    /// `agent`'s current window, started afresh once a minute has passed.
+    fn window(&mut self, agent: &str, now: Instant) -> &mut Usage {
+        let usage = self
+            .usage
+            .entry(agent.to_string())
+            .or_insert_with(|| Usage::new(now));
+        if now.duration_since(usage.started) >= WINDOW {
+            *usage = Usage::new(now);
+        }
+        usage
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_quota.rs"]
+mod tests;
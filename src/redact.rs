@@ -0,0 +1,228 @@
+//! Column redaction applied to result rows before they leave the executor.
+//!
+//! Rules without a `.` match column names anywhere in a row, including keys of
+//! nested JSON objects (e.g. `to_jsonb(t)` built by the structured tools).
+//! Table-qualified rules only match columns whose source table is known, and
+//! keys nested in them: from the prepared statement for plain columns, or
+//! from `nested_tables` for the JSON rows `psql_search` builds. Other
+//! computed columns have no source.
+
+use crate::profile;
+use crate::types::{RedactAction, RedactionRule};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+pub const MASK: &str = "[redacted]";
+
+/// `$1` is the list of table OIDs; rows are `(oid, schema, table)`.
+pub const TABLE_NAMES_SQL: &str = "select c.oid, n.nspname::text, c.relname::text \
+     from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+     where c.oid = any($1::oid[])";
+
+/// `$1` is a list of table names as `regclass` text; rows are
+/// `(schema, table)` for those that exist.
+pub const NAMED_TABLES_SQL: &str = "select n.nspname::text, c.relname::text \
+     from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+     where c.oid in (select to_regclass(t) from unnest($1::text[]) t)";
+
+/// `(schema, table)` sources of each result column, keyed by column name
+/// (a name can repeat across joined tables).
+pub type ColumnOrigins = HashMap<String, Vec<(String, String)>>;
+
+/// Whether any rule is table-qualified and so needs [`ColumnOrigins`].
+pub fn needs_origins(rules: &[RedactionRule]) -> bool {
+    rules.iter().any(|r| r.column.contains('.'))
+}
+
+pub fn redact_rows(
+    rows: &mut [Value],
+    origins: &ColumnOrigins,
+    rules: &[RedactionRule],
+    salt: Option<&str>,
+) {
+    if rules.is_empty() {
+        return;
+    }
+    let _timer = profile::timer("redact");
+    for row in rows {
+        let Value::Object(map) = row else {
+            continue;
+        };
+        for (column, value) in map.iter_mut() {
+            let sources = origins.get(column).map(Vec::as_slice).unwrap_or_default();
+            match action_for(rules, column, sources) {
+                Some(action) => apply(action, value, salt),
+                None => redact_nested(value, rules, sources, salt),
+            }
+        }
+    }
+}
+
+/// Redact keys nested in a column read from `sources`, which
+/// table-qualified rules are matched against.
+fn redact_nested(
+    value: &mut Value,
+    rules: &[RedactionRule],
+    sources: &[(String, String)],
+    salt: Option<&str>,
+) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match action_for(rules, key, sources) {
+                    Some(action) => apply(action, v, salt),
+                    None => redact_nested(v, rules, sources, salt),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for v in items {
+                redact_nested(v, rules, sources, salt);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Column globs of the rules that can match columns read from `table`
+/// (`table` or `schema.table`). A table or schema that is not known matches,
+/// so with no `table` every rule's column glob is returned.
+pub fn column_globs<'a>(rules: &'a [RedactionRule], table: Option<&str>) -> Vec<&'a str> {
+    let (schema, table) = match table.map(|t| t.split_once('.').unwrap_or(("", t))) {
+        Some(("", table)) => (None, Some(table)),
+        Some((schema, table)) => (Some(schema), Some(table)),
+        None => (None, None),
+    };
+    let known = |pattern: &str, name: Option<&str>| name.is_none_or(|n| glob_match(pattern, n));
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let parts: Vec<&str> = rule.column.split('.').collect();
+            match parts.as_slice() {
+                [c] => Some(*c),
+                [t, c] => known(t, table).then_some(*c),
+                [s, t, c] => (known(s, schema) && known(t, table)).then_some(*c),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn action_for(
+    rules: &[RedactionRule],
+    column: &str,
+    sources: &[(String, String)],
+) -> Option<RedactAction> {
+    rule_for(rules, column, sources).map(|rule| rule.action)
+}
+
+/// The first rule matching `column` read from one of `sources`.
+pub fn rule_for<'a>(
+    rules: &'a [RedactionRule],
+    column: &str,
+    sources: &[(String, String)],
+) -> Option<&'a RedactionRule> {
+    rules.iter().find(|rule| {
+        let parts: Vec<&str> = rule.column.split('.').collect();
+        match parts.as_slice() {
+            [c] => glob_match(c, column),
+            [t, c] => {
+                glob_match(c, column) && sources.iter().any(|(_, table)| glob_match(t, table))
+            }
+            [s, t, c] => {
+                glob_match(c, column)
+                    && sources
+                        .iter()
+                        .any(|(schema, table)| glob_match(s, schema) && glob_match(t, table))
+            }
+            _ => false,
+        }
+    })
+}
+
+fn apply(action: RedactAction, value: &mut Value, salt: Option<&str>) {
+    if value.is_null() {
+        return;
+    }
+    *value = match action {
+        RedactAction::Mask => Value::String(MASK.to_string()),
+        RedactAction::Null => Value::Null,
+        RedactAction::Hash => {
+            let hex: String = digest(value, salt)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            Value::String(format!("sha256:{hex}"))
+        }
+        RedactAction::Pseudonymize => pseudonym(value, salt),
+    };
+}
+
+/// Salted SHA-256 of a value's text.
+fn digest(value: &Value, salt: Option<&str>) -> [u8; 32] {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(salt.unwrap_or_default().as_bytes());
+    hasher.update(text.as_bytes());
+    hasher.finalize().into()
+}
+
+/// A stand-in derived from the value's hash, so equal values get equal
+/// stand-ins and joins on pseudonymized keys still match. Numbers stay
+/// numbers (below 2^53, safe in JSON), email addresses stay addresses at
+/// `example.invalid`, and anything else becomes `anon_<hex>`.
+pub fn pseudonym(value: &Value, salt: Option<&str>) -> Value {
+    let digest = digest(value, salt);
+    let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    match value {
+        Value::Number(_) => {
+            let mut head = [0u8; 8];
+            head.copy_from_slice(&digest[..8]);
+            Value::from(u64::from_be_bytes(head) >> 11)
+        }
+        Value::String(s)
+            if s.split_once('@')
+                .is_some_and(|(l, d)| !l.is_empty() && !d.is_empty()) =>
+        {
+            Value::String(format!("{hex}@example.invalid"))
+        }
+        _ => Value::String(format!("anon_{hex}")),
+    }
+}
+
+/// Case-insensitive glob: `*` matches any run of characters, `?` exactly one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.to_lowercase().chars().collect();
+    let t: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+            }
+            Some(c) if *c == '?' || *c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    pi = star + 1;
+                    ti = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_redact.rs"]
+mod tests;
@@ -0,0 +1,112 @@
+//! Named executor backends, so the runtime can run statements somewhere
+//! other than PostgreSQL (`RuntimeConfig.executor`, `--executor`).
+
+use crate::chaos::ChaosExecutor;
+use crate::db::{DbExecutor, ExecError, ExecOutcome, PostgresExecutor};
+use crate::mock::MockExecutor;
+use crate::replay::{RecordingExecutor, ReplayExecutor};
+use crate::types::{ResolvedOptions, RuntimeConfig, SessionConfig};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Builds an executor for a runtime config; errors are reported at startup.
+pub type ExecutorFactory = fn(&RuntimeConfig) -> Result<Arc<dyn DbExecutor>, String>;
+
+pub struct ExecutorRegistry {
+    factories: BTreeMap<String, ExecutorFactory>,
+}
+
+impl Default for ExecutorRegistry {
+    /// `postgres`, `echo`, `record`, `replay` and `mock`.
+    fn default() -> Self {
+        let mut registry = Self {
+            factories: BTreeMap::new(),
+        };
+        registry.register("postgres", |_| Ok(Arc::new(PostgresExecutor::new())));
+        registry.register("echo", |_| Ok(Arc::new(EchoExecutor)));
+        registry.register("record", |config| {
+            let path = config
+                .record_path
+                .as_deref()
+                .ok_or("the record executor needs record_path")?;
+            let inner = Arc::new(PostgresExecutor::new());
+            Ok(Arc::new(RecordingExecutor::create(path, inner)?))
+        });
+        registry.register("replay", |config| {
+            let path = config
+                .replay_path
+                .as_deref()
+                .ok_or("the replay executor needs replay_path")?;
+            Ok(Arc::new(ReplayExecutor::load(path)?))
+        });
+        registry.register("mock", |config| {
+            let path = config
+                .mock_path
+                .as_deref()
+                .ok_or("the mock executor needs mock_path")?;
+            Ok(Arc::new(MockExecutor::load(path)?))
+        });
+        registry
+    }
+}
+
+impl ExecutorRegistry {
+    /// Add or replace the backend called `name`.
+    pub fn register(&mut self, name: &str, factory: ExecutorFactory) {
+        self.factories.insert(name.to_string(), factory);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// The executor named by `config.executor`, behind `config.chaos` when
+    /// that is set.
+    pub fn build(&self, config: &RuntimeConfig) -> Result<Arc<dyn DbExecutor>, String> {
+        let executor = match self.factories.get(&config.executor) {
+            Some(factory) => factory(config)?,
+            None => {
+                return Err(format!(
+                    "unknown executor: {}; available: {}",
+                    config.executor,
+                    self.names().join(", ")
+                ))
+            }
+        };
+        match &config.chaos {
+            Some(chaos) => {
+                chaos.validate()?;
+                Ok(Arc::new(ChaosExecutor::new(executor, chaos.clone())))
+            }
+            None => Ok(executor),
+        }
+    }
+}
+
+/// Answers every statement with one row describing it, without connecting
+/// anywhere; for exercising a pipeline without a database.
+pub struct EchoExecutor;
+
+#[async_trait]
+impl DbExecutor for EchoExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Rows(vec![json!({
+            "session": session_name,
+            "sql": sql,
+            "params": params,
+        })]))
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_registry.rs"]
+mod tests;
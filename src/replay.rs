@@ -0,0 +1,395 @@
+//! Record/replay executors for hermetic tests.
+//!
+//! `--record PATH` runs statements on PostgreSQL and appends each one with
+//! its outcome to a JSONL fixture; `--replay PATH` answers statements from
+//! such a fixture without connecting. Replay matches on session, SQL text and
+//! params; a statement recorded several times gets its outcomes in recorded
+//! order, the last one repeating once they run out.
+
+use crate::db::{
+    BlockRun, CopyReadback, DbExecutor, ExecError, ExecOutcome, PoolReport, PoolState, ScriptRun,
+};
+use crate::types::{ColumnInfo, ResolvedOptions, SessionConfig, Timing};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// One line of a fixture file.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FixtureEntry {
+    pub session: String,
+    pub sql: String,
+    #[serde(default)]
+    pub params: Vec<Value>,
+    #[serde(flatten)]
+    pub outcome: RecordedOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RecordedOutcome {
+    Rows {
+        rows: Vec<Value>,
+    },
+    Command {
+        affected: usize,
+    },
+    SqlError {
+        sqlstate: String,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hint: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        position: Option<String>,
+    },
+    Error {
+        error_code: String,
+        message: String,
+    },
+}
+
+impl RecordedOutcome {
+    fn record(result: &Result<ExecOutcome, ExecError>) -> Self {
+        match result {
+            Ok(ExecOutcome::Rows(rows)) => Self::Rows { rows: rows.clone() },
+            Ok(ExecOutcome::Command { affected }) => Self::Command {
+                affected: *affected,
+            },
+            Err(ExecError::Sql {
+                sqlstate,
+                message,
+                detail,
+                hint,
+                position,
+            }) => Self::SqlError {
+                sqlstate: sqlstate.clone(),
+                message: message.clone(),
+                detail: detail.clone(),
+                hint: hint.clone(),
+                position: position.clone(),
+            },
+            Err(e) => {
+                let (error_code, message) = match e {
+                    ExecError::Connect(m) => ("connect_failed", m),
+                    ExecError::InvalidParams(m) => ("invalid_params", m),
+                    ExecError::ApprovalRequired(m) => ("approval_required", m),
+                    ExecError::Internal(m) | ExecError::Sql { message: m, .. } => ("internal", m),
+                };
+                Self::Error {
+                    error_code: error_code.to_string(),
+                    message: message.clone(),
+                }
+            }
+        }
+    }
+
+    fn replay(&self) -> Result<ExecOutcome, ExecError> {
+        match self.clone() {
+            Self::Rows { rows } => Ok(ExecOutcome::Rows(rows)),
+            Self::Command { affected } => Ok(ExecOutcome::Command { affected }),
+            Self::SqlError {
+                sqlstate,
+                message,
+                detail,
+                hint,
+                position,
+            } => Err(ExecError::Sql {
+                sqlstate,
+                message,
+                detail,
+                hint,
+                position,
+            }),
+            Self::Error {
+                error_code,
+                message,
+            } => Err(match error_code.as_str() {
+                "connect_failed" => ExecError::Connect(message),
+                "invalid_params" => ExecError::InvalidParams(message),
+                "approval_required" => ExecError::ApprovalRequired(message),
+                _ => ExecError::Internal(message),
+            }),
+        }
+    }
+}
+
+/// Runs statements on `inner` and appends each to the fixture file, which
+/// is truncated when the executor is created.
+pub struct RecordingExecutor {
+    inner: Arc<dyn DbExecutor>,
+    file: Mutex<File>,
+}
+
+impl RecordingExecutor {
+    pub fn create(path: &str, inner: Arc<dyn DbExecutor>) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("cannot create {path}: {e}"))?;
+        Ok(Self {
+            inner,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn append(&self, entry: &FixtureEntry) -> Result<(), String> {
+        let mut line = serde_json::to_vec(entry).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| "fixture file lock poisoned".to_string())?;
+        file.write_all(&line)
+            .and_then(|_| file.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl DbExecutor for RecordingExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        self.execute_timed(session_name, session_cfg, sql, params, opts)
+            .await
+            .0
+    }
+
+    async fn execute_timed(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> (Result<ExecOutcome, ExecError>, Timing) {
+        let (result, timing) = self
+            .inner
+            .execute_timed(session_name, session_cfg, sql, params, opts)
+            .await;
+        let entry = FixtureEntry {
+            session: session_name.to_string(),
+            sql: sql.to_string(),
+            params: params.to_vec(),
+            outcome: RecordedOutcome::record(&result),
+        };
+        if let Err(e) = self.append(&entry) {
+            let error = ExecError::Internal(format!("recording failed: {e}"));
+            return (Err(error), timing);
+        }
+        (result, timing)
+    }
+
+    async fn execute_block(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        body: &str,
+        vars: &[(String, String)],
+        opts: &ResolvedOptions,
+    ) -> BlockRun {
+        self.inner
+            .execute_block(session_name, session_cfg, body, vars, opts)
+            .await
+    }
+
+    async fn export_snapshot(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<String, ExecError> {
+        self.inner.export_snapshot(session_name, session_cfg).await
+    }
+
+    async fn release_snapshot(&self, session_name: &str, snapshot: &str) -> bool {
+        self.inner.release_snapshot(session_name, snapshot).await
+    }
+
+    async fn open_workspace(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        workspace: &str,
+    ) -> Result<(), ExecError> {
+        self.inner
+            .open_workspace(session_name, session_cfg, workspace)
+            .await
+    }
+
+    async fn close_workspace(&self, workspace: &str) -> bool {
+        self.inner.close_workspace(workspace).await
+    }
+
+    async fn reconnect_workspace(&self, workspace: &str) -> Result<bool, ExecError> {
+        self.inner.reconnect_workspace(workspace).await
+    }
+
+    fn schema_generation(&self) -> u64 {
+        self.inner.schema_generation()
+    }
+
+    async fn execute_script(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        statements: &[String],
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> ScriptRun {
+        self.inner
+            .execute_script(session_name, session_cfg, statements, params, opts)
+            .await
+    }
+
+    async fn describe(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        opts: &ResolvedOptions,
+    ) -> Result<(Vec<String>, Vec<ColumnInfo>), ExecError> {
+        self.inner
+            .describe(session_name, session_cfg, sql, opts)
+            .await
+    }
+
+    async fn copy_in(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        self.inner
+            .copy_in(session_name, session_cfg, sql, data, opts)
+            .await
+    }
+
+    async fn copy_in_verified(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        readback: &CopyReadback<'_>,
+        opts: &ResolvedOptions,
+    ) -> Result<(u64, Vec<Value>, bool), ExecError> {
+        self.inner
+            .copy_in_verified(session_name, session_cfg, sql, data, readback, opts)
+            .await
+    }
+
+    async fn connect(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<(), ExecError> {
+        self.inner.connect(session_name, session_cfg).await
+    }
+
+    async fn maintain_pools(&self, idle_timeout: Option<Duration>) -> Vec<PoolReport> {
+        self.inner.maintain_pools(idle_timeout).await
+    }
+
+    async fn pool_states(&self) -> Vec<PoolState> {
+        self.inner.pool_states().await
+    }
+}
+
+type FixtureKey = (String, String, String);
+
+struct Responses {
+    outcomes: Vec<RecordedOutcome>,
+    served: usize,
+}
+
+/// Answers statements from a fixture file; a statement that was never
+/// recorded fails with `invalid_request`.
+pub struct ReplayExecutor {
+    responses: Mutex<HashMap<FixtureKey, Responses>>,
+}
+
+fn fixture_key(session: &str, sql: &str, params: &[Value]) -> FixtureKey {
+    let params = serde_json::to_string(params).unwrap_or_default();
+    (session.to_string(), sql.to_string(), params)
+}
+
+impl ReplayExecutor {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+        let mut entries = Vec::new();
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("cannot read {path}: {e}"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: FixtureEntry = serde_json::from_str(&line)
+                .map_err(|e| format!("{path}:{}: invalid fixture entry: {e}", n + 1))?;
+            entries.push(entry);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    pub fn from_entries(entries: Vec<FixtureEntry>) -> Self {
+        let mut responses: HashMap<FixtureKey, Responses> = HashMap::new();
+        for entry in entries {
+            responses
+                .entry(fixture_key(&entry.session, &entry.sql, &entry.params))
+                .or_insert_with(|| Responses {
+                    outcomes: Vec::new(),
+                    served: 0,
+                })
+                .outcomes
+                .push(entry.outcome);
+        }
+        Self {
+            responses: Mutex::new(responses),
+        }
+    }
+}
+
+#[async_trait]
+impl DbExecutor for ReplayExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        let mut responses = self
+            .responses
+            .lock()
+            .map_err(|_| ExecError::Internal("replay fixture lock poisoned".to_string()))?;
+        let Some(recorded) = responses.get_mut(&fixture_key(session_name, sql, params)) else {
+            return Err(ExecError::Internal(format!(
+                "replay fixture has no response for session {session_name}: {sql}"
+            )));
+        };
+        let idx = recorded
+            .served
+            .min(recorded.outcomes.len().saturating_sub(1));
+        recorded.served += 1;
+        match recorded.outcomes.get(idx) {
+            Some(outcome) => outcome.replay(),
+            None => Err(ExecError::Internal(format!(
+                "replay fixture has no response for session {session_name}: {sql}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_replay.rs"]
+mod tests;
@@ -0,0 +1,71 @@
+//! Queries behind `psql_replication_status`.
+//!
+//! Lag is measured from the server's own WAL position: `pg_current_wal_lsn()`
+//! on a primary and the last replayed position on a standby, so the same
+//! report reads sensibly on both. The WAL rate is how far that position moves
+//! over a short sample.
+
+use std::time::Duration;
+
+/// Sample taken for the WAL rate when the caller does not pick one.
+pub const DEFAULT_SAMPLE_MS: u64 = 1000;
+
+/// Longest sample the tool waits for.
+pub const MAX_SAMPLE_MS: u64 = 10_000;
+
+const CURRENT_LSN: &str = "case when pg_is_in_recovery() then pg_last_wal_replay_lsn() \
+                           else pg_current_wal_lsn() end";
+
+/// Whether the server is a standby, its WAL position in bytes, and on a
+/// standby how long ago the last replayed transaction committed.
+pub fn position_sql() -> String {
+    format!(
+        "select pg_is_in_recovery() as in_recovery, \
+         pg_wal_lsn_diff({CURRENT_LSN}, '0/0')::bigint as wal_bytes, \
+         case when pg_is_in_recovery() then \
+         (extract(epoch from now() - pg_last_xact_replay_timestamp()) * 1000)::bigint \
+         end as replay_delay_ms"
+    )
+}
+
+/// One row per replication slot, with the WAL it holds back and how far its
+/// consumer is behind.
+pub fn slots_sql() -> String {
+    format!(
+        "select slot_name, slot_type, plugin, database::text as database, active, active_pid, \
+         wal_status, \
+         pg_wal_lsn_diff({CURRENT_LSN}, restart_lsn)::bigint as retained_bytes, \
+         pg_wal_lsn_diff({CURRENT_LSN}, coalesce(confirmed_flush_lsn, restart_lsn))::bigint \
+         as lag_bytes \
+         from pg_replication_slots order by slot_name"
+    )
+}
+
+/// One row per connected standby or subscriber, with lag in bytes at each
+/// stage and PostgreSQL's own lag times.
+pub fn replicas_sql() -> String {
+    format!(
+        "select pid, application_name, client_addr::text as client_addr, state, sync_state, \
+         pg_wal_lsn_diff({CURRENT_LSN}, sent_lsn)::bigint as send_lag_bytes, \
+         pg_wal_lsn_diff({CURRENT_LSN}, flush_lsn)::bigint as flush_lag_bytes, \
+         pg_wal_lsn_diff({CURRENT_LSN}, replay_lsn)::bigint as replay_lag_bytes, \
+         (extract(epoch from write_lag) * 1000)::bigint as write_lag_ms, \
+         (extract(epoch from flush_lag) * 1000)::bigint as flush_lag_ms, \
+         (extract(epoch from replay_lag) * 1000)::bigint as replay_lag_ms \
+         from pg_stat_replication order by application_name, pid"
+    )
+}
+
+/// WAL bytes written (or replayed) per second between two positions taken
+/// `elapsed` apart; `None` for an empty interval.
+pub fn bytes_per_second(before: u64, after: u64, elapsed: Duration) -> Option<u64> {
+    let ms = elapsed.as_millis();
+    if ms == 0 {
+        return None;
+    }
+    Some((u128::from(after.saturating_sub(before)) * 1000 / ms) as u64)
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_replication.rs"]
+mod tests;
@@ -0,0 +1,138 @@
+//! On-disk store for query results that agents revisit by handle.
+//!
+//! Each stored result is `<results_dir>/<handle>.jsonl`: a header line with
+//! `columns` and `row_count`, then one row per line. Files are written under a
+//! temporary name and renamed, so a handle never names a partial result.
+//! Handles are checked by [`valid_handle`] before any path is built from them.
+
+use crate::types::ColumnInfo;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    columns: Vec<ColumnInfo>,
+    row_count: usize,
+}
+
+/// One slice of a stored result.
+#[derive(Debug)]
+pub struct Page {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<Value>,
+    pub total_rows: usize,
+}
+
+/// `res_<unix millis>_<pid>_<seq>`: unique across processes sharing a directory.
+pub fn new_handle() -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    format!("res_{millis}_{}_{seq}", std::process::id())
+}
+
+/// Handles are plain `[A-Za-z0-9_-]` names so they cannot escape the store.
+pub fn valid_handle(handle: &str) -> bool {
+    !handle.is_empty()
+        && handle.len() <= 128
+        && handle
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+pub fn result_path(dir: &Path, handle: &str) -> PathBuf {
+    dir.join(format!("{handle}.jsonl"))
+}
+
+/// Write `rows` under `handle`; returns the row payload bytes written.
+pub fn save(dir: &Path, handle: &str, columns: &[ColumnInfo], rows: &[Value]) -> io::Result<usize> {
+    std::fs::create_dir_all(dir)?;
+    save_file(&result_path(dir, handle), columns, rows)
+}
+
+/// Write `rows` to `path` in the same format, such as a golden file kept
+/// outside `results_dir`.
+pub fn save_file(path: &Path, columns: &[ColumnInfo], rows: &[Value]) -> io::Result<usize> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let written = write_file(&tmp, columns, rows);
+    match written {
+        Ok(bytes) => {
+            std::fs::rename(&tmp, path)?;
+            Ok(bytes)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+fn write_file(path: &Path, columns: &[ColumnInfo], rows: &[Value]) -> io::Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let header = Header {
+        columns: columns.to_vec(),
+        row_count: rows.len(),
+    };
+    serde_json::to_writer(&mut out, &header)?;
+    out.write_all(b"\n")?;
+    let mut bytes = 0usize;
+    for row in rows {
+        let line = serde_json::to_vec(row)?;
+        bytes += line.len();
+        out.write_all(&line)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(bytes)
+}
+
+/// Read up to `limit` rows starting at row `offset`.
+pub fn read_page(dir: &Path, handle: &str, offset: usize, limit: usize) -> io::Result<Page> {
+    read_rows(&result_path(dir, handle), offset, limit)
+}
+
+/// Read every row of the result file at `path`.
+pub fn read_file(path: &Path) -> io::Result<Page> {
+    read_rows(path, 0, usize::MAX)
+}
+
+fn read_rows(path: &Path, offset: usize, limit: usize) -> io::Result<Page> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty result file",
+            ))
+        }
+    };
+    let rows = lines
+        .skip(offset)
+        .take(limit)
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect::<io::Result<Vec<Value>>>()?;
+    Ok(Page {
+        columns: header.columns,
+        rows,
+        total_rows: header.row_count,
+    })
+}
+
+pub fn delete(dir: &Path, handle: &str) -> io::Result<()> {
+    std::fs::remove_file(result_path(dir, handle))
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_results.rs"]
+mod tests;
@@ -0,0 +1,102 @@
+//! Recently streamed results, kept so a consumer that lost part of a stream
+//! can ask for it again with `resume_from` instead of re-running the query.
+//!
+//! Streams are kept by request id, batch by batch as they are sent, within a
+//! total of `resume_buffer_bytes`; the oldest streams are dropped first, and
+//! a stream larger than the whole budget is not kept at all.
+
+use crate::types::{ColumnInfo, ResolvedOptions, Trace};
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub struct StreamEnd {
+    pub command_tag: String,
+    pub limited: Option<bool>,
+    pub warning: Option<String>,
+    pub trace: Trace,
+}
+
+#[derive(Debug)]
+pub struct RetainedStream {
+    pub session: Option<String>,
+    pub columns: Vec<ColumnInfo>,
+    /// Options the batches were sent with (`key_columns`, `compress`).
+    pub opts: ResolvedOptions,
+    /// Rows and payload bytes of each batch; batch `i` has seq `i + 1`.
+    pub batches: Vec<(Vec<Value>, usize)>,
+    /// Set once `result_end` was sent; its seq follows the last batch.
+    pub end: Option<StreamEnd>,
+    bytes: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct StreamLog {
+    /// Oldest first.
+    streams: Vec<(String, RetainedStream)>,
+    bytes: usize,
+}
+
+impl StreamLog {
+    /// Start keeping the stream `id`, replacing an older one with that id.
+    pub fn begin(
+        &mut self,
+        id: &str,
+        session: Option<String>,
+        columns: Vec<ColumnInfo>,
+        opts: &ResolvedOptions,
+    ) {
+        self.remove(id);
+        let stream = RetainedStream {
+            session,
+            columns,
+            opts: opts.clone(),
+            batches: Vec::new(),
+            end: None,
+            bytes: 0,
+        };
+        self.streams.push((id.to_string(), stream));
+    }
+
+    /// Keep one more batch of `id`, dropping streams to stay within
+    /// `budget` bytes.
+    pub fn append(&mut self, id: &str, rows: Vec<Value>, bytes: usize, budget: usize) {
+        let Some((_, stream)) = self.streams.iter_mut().find(|(k, _)| k == id) else {
+            return;
+        };
+        stream.batches.push((rows, bytes));
+        stream.bytes += bytes;
+        self.bytes += bytes;
+        if stream.bytes > budget {
+            self.remove(id);
+            return;
+        }
+        while self.bytes > budget {
+            let Some(oldest) = self.streams.iter().position(|(k, _)| k != id) else {
+                break;
+            };
+            let (_, dropped) = self.streams.remove(oldest);
+            self.bytes -= dropped.bytes;
+        }
+    }
+
+    pub fn finish(&mut self, id: &str, end: StreamEnd) {
+        if let Some((_, stream)) = self.streams.iter_mut().find(|(k, _)| k == id) {
+            stream.end = Some(end);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&RetainedStream> {
+        self.streams.iter().find(|(k, _)| k == id).map(|(_, s)| s)
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(pos) = self.streams.iter().position(|(k, _)| k == id) {
+            let (_, stream) = self.streams.remove(pos);
+            self.bytes -= stream.bytes;
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_resume.rs"]
+mod tests;
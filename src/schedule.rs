@@ -0,0 +1,359 @@
+//! Cron schedules for the `schedules` config.
+//!
+//! A schedule runs its query whenever its cron expression matches the
+//! current minute, in UTC, while afpsql runs in pipe or MCP mode. The five
+//! fields are minute, hour, day of month, month and day of week, each `*`,
+//! a number, a range `a-b`, a step `*/n` or `a-b/n`, or a comma separated
+//! list of those; months and days of the week may be named (`jan`, `mon`).
+//! As in cron, when both day fields are restricted a day matching either
+//! one counts. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`
+//! are shorthands.
+//!
+//! Results go to the output stream, are appended to a file, or are posted
+//! to a webhook as JSON; `https://` webhooks need the `https` feature.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Longest a webhook may take to accept a delivery.
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Days searched for the next match; every valid expression matches within
+/// eight years, as February 29 does.
+const SEARCH_DAYS: i64 = 366 * 8;
+
+/// A parsed cron expression; it serializes back to the text it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month and day of week fields were `*`.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, String> {
+        Cron::parse(&source)
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.source
+    }
+}
+
+impl Cron {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let expanded = match source.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded
+            .split(char::is_whitespace)
+            .filter(|f| !f.is_empty())
+            .collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(format!(
+                "cron expression {source:?} needs 5 fields: minute hour day-of-month month day-of-week"
+            ));
+        };
+        let invalid = |e: String| format!("cron expression {source:?}: {e}");
+        let mut weekdays = field(weekday, 0, 7, &WEEKDAYS).map_err(invalid)?;
+        // 7 is Sunday too.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        let cron = Self {
+            source: source.to_string(),
+            minutes: field(minute, 0, 59, &[]).map_err(invalid)?,
+            hours: field(hour, 0, 23, &[]).map_err(invalid)?,
+            days: field(day, 1, 31, &[]).map_err(invalid)?,
+            months: field(month, 1, 12, &MONTHS).map_err(invalid)?,
+            weekdays,
+            any_day: *day == "*",
+            any_weekday: *weekday == "*",
+        };
+        if cron.next_after(0).is_none() {
+            return Err(format!("cron expression {source:?} never matches"));
+        }
+        Ok(cron)
+    }
+
+    /// Whether the expression matches the minute starting at `epoch_ms`.
+    pub fn matches(&self, epoch_ms: u64) -> bool {
+        let minute = epoch_ms / 60_000;
+        let (days, in_day) = ((minute / 1440) as i64, minute % 1440);
+        self.day_matches(days)
+            && self.hours & (1 << (in_day / 60)) != 0
+            && self.minutes & (1 << (in_day % 60)) != 0
+    }
+
+    /// Start of the first matching minute after `epoch_ms`.
+    pub fn next_after(&self, epoch_ms: u64) -> Option<u64> {
+        let first = epoch_ms / 60_000 + 1;
+        let first_day = (first / 1440) as i64;
+        for days in first_day..first_day + SEARCH_DAYS {
+            if !self.day_matches(days) {
+                continue;
+            }
+            let from = if days == first_day { first % 1440 } else { 0 };
+            let found = (from..1440)
+                .find(|m| self.hours & (1 << (m / 60)) != 0 && self.minutes & (1 << (m % 60)) != 0);
+            if let Some(m) = found {
+                return Some((days as u64 * 1440 + m) * 60_000);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        // 1970-01-01 was a Thursday.
+        let weekday = (days + 4).rem_euclid(7);
+        let by_day = self.days & (1 << day) != 0;
+        let by_weekday = self.weekdays & (1 << weekday) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => by_day || by_weekday,
+            _ => by_day && by_weekday,
+        }
+    }
+}
+
+/// The values one field allows, as bits.
+fn field(text: &str, min: u64, max: u64, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u64, String> {
+        let lower = s.to_ascii_lowercase();
+        let n = match names.iter().position(|n| *n == lower) {
+            // Named months start at 1, named weekdays at 0.
+            Some(i) => i as u64 + min,
+            None => s
+                .parse::<u64>()
+                .map_err(|_| format!("{s:?} is not a number"))?,
+        };
+        if n < min || n > max {
+            return Err(format!("{n} is outside {min}-{max}"));
+        }
+        Ok(n)
+    };
+    let mut bits = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("step {step:?} must be a positive number"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((a, b)) => (value(a)?, value(b)?),
+            // `n/step` runs from n to the end of the field.
+            None if step > 1 => (value(range)?, max),
+            None => {
+                let n = value(range)?;
+                (n, n)
+            }
+        };
+        if from > to {
+            return Err(format!("range {range:?} runs backwards"));
+        }
+        for n in (from..=to).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+/// Year, month and day of the date `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u64;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u64;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Where a schedule's results go besides the output stream:
+/// `{"path": "..."}` appends one JSON line per run, `{"url": "..."}` posts
+/// each run as JSON to an `http://` or `https://` webhook.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sink {
+    Path(String),
+    Url(String),
+}
+
+/// Deliver one run's `record` to `sink`.
+pub async fn deliver(sink: &Sink, record: &Value) -> Result<(), String> {
+    match sink {
+        Sink::Path(path) => append(path, record).map_err(|e| format!("cannot write {path}: {e}")),
+        Sink::Url(url) => tokio::time::timeout(WEBHOOK_TIMEOUT, post_json(url, record))
+            .await
+            .unwrap_or_else(|_| Err(format!("{url} did not answer within 10s")))
+            .map_err(|e| format!("webhook {url}: {e}")),
+    }
+}
+
+fn append(path: &str, record: &Value) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+/// POST `body` over HTTP/1.1, or HTTPS in a build with `https`, and
+/// require a 2xx reply.
+async fn post_json(url: &str, body: &Value) -> Result<(), String> {
+    let (host, port, path) = split_url(url)?;
+    let body = serde_json::to_vec(body).map_err(|e| e.to_string())?;
+    let stream = tokio::net::TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let head = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\nUser-Agent: afpsql\r\n\r\n",
+        body.len()
+    );
+    #[cfg(feature = "https")]
+    if url.starts_with("https://") {
+        let stream = tls_connect(&host, stream).await?;
+        return exchange(stream, &head, &body).await;
+    }
+    exchange(stream, &head, &body).await
+}
+
+async fn exchange<S>(mut stream: S, head: &str, body: &[u8]) -> Result<(), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream
+        .write_all(head.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    stream.write_all(body).await.map_err(|e| e.to_string())?;
+    let mut status = [0u8; 12];
+    stream
+        .read_exact(&mut status)
+        .await
+        .map_err(|e| format!("no HTTP reply: {e}"))?;
+    let status = String::from_utf8_lossy(&status[9..12]).to_string();
+    if status.starts_with('2') {
+        Ok(())
+    } else {
+        Err(format!("replied with HTTP {status}"))
+    }
+}
+
+/// TLS over `stream`, verifying `host` against the Mozilla root
+/// certificates bundled at build time.
+#[cfg(feature = "https")]
+async fn tls_connect(
+    host: &str,
+    stream: tokio::net::TcpStream,
+) -> Result<tokio_rustls::client::TlsStream<tokio::net::TcpStream>, String> {
+    use std::sync::{Arc, OnceLock};
+    use tokio_rustls::rustls::{self, pki_types::ServerName, ClientConfig, RootCertStore};
+
+    static CONFIG: OnceLock<Result<Arc<ClientConfig>, String>> = OnceLock::new();
+    let config = CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .map(|builder| {
+                    Arc::new(builder.with_root_certificates(roots).with_no_client_auth())
+                })
+                .map_err(|e| e.to_string())
+        })
+        .clone()?;
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|_| format!("{host} is not a valid TLS server name"))?;
+    tokio_rustls::TlsConnector::from(config)
+        .connect(name, stream)
+        .await
+        .map_err(|e| format!("TLS: {e}"))
+}
+
+/// Host, port and path of an `http://` or `https://` URL.
+pub fn split_url(url: &str) -> Result<(String, u16, String), String> {
+    let (rest, default_port) = if let Some(rest) = url.strip_prefix("http://") {
+        (rest, 80)
+    } else if let Some(rest) = url.strip_prefix("https://") {
+        if !cfg!(feature = "https") {
+            return Err(format!(
+                "webhook {url} is https://; build afpsql with --features https to post to it"
+            ));
+        }
+        (rest, 443)
+    } else {
+        return Err(format!("webhook {url} must be an http:// or https:// URL"));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    // `[::1]:8080` for IPv6.
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, rest)) => (host, rest.strip_prefix(':')),
+            None => return Err(format!("webhook {url} has an unclosed '['")),
+        },
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("webhook {url} has an invalid port"))?,
+        None => default_port,
+    };
+    if host.is_empty() {
+        return Err(format!("webhook {url} has no host"));
+    }
+    Ok((host.to_string(), port, path))
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_schedule.rs"]
+mod tests;
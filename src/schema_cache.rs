@@ -0,0 +1,81 @@
+//! Catalog lookups made on behalf of tools (key inference, column stats,
+//! vector column types), cached per session for `schema_cache_ttl_ms`.
+//!
+//! An entry is dropped once it outlives the TTL, when `invalidate_cache`
+//! clears it, or when the executor has seen DDL since it was stored.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct CachedLookup {
+    rows: Vec<Value>,
+    stored_at: Instant,
+    /// Executor schema generation when the lookup ran.
+    generation: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    /// By `(session, lookup)`, where lookup is the SQL and its params.
+    entries: HashMap<(String, String), CachedLookup>,
+}
+
+fn lookup_key(sql: &str, params: &[Value]) -> String {
+    format!("{sql}\n{}", Value::from(params.to_vec()))
+}
+
+impl SchemaCache {
+    /// Rows of a lookup stored less than `ttl` ago at `generation`.
+    pub fn get(
+        &mut self,
+        session: &str,
+        sql: &str,
+        params: &[Value],
+        ttl: Duration,
+        generation: u64,
+    ) -> Option<Vec<Value>> {
+        let key = (session.to_string(), lookup_key(sql, params));
+        let entry = self.entries.get(&key)?;
+        if entry.generation != generation || entry.stored_at.elapsed() >= ttl {
+            self.entries.remove(&key);
+            return None;
+        }
+        Some(entry.rows.clone())
+    }
+
+    /// Store a lookup's rows, first dropping entries that are stale under
+    /// `ttl` and `generation`.
+    pub fn insert(
+        &mut self,
+        session: &str,
+        sql: &str,
+        params: &[Value],
+        rows: Vec<Value>,
+        ttl: Duration,
+        generation: u64,
+    ) {
+        self.entries
+            .retain(|_, e| e.generation == generation && e.stored_at.elapsed() < ttl);
+        let entry = CachedLookup {
+            rows,
+            stored_at: Instant::now(),
+            generation,
+        };
+        self.entries
+            .insert((session.to_string(), lookup_key(sql, params)), entry);
+    }
+
+    /// Drop the entries of `session`, or all of them; returns how many.
+    pub fn invalidate(&mut self, session: Option<&str>) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|(s, _), _| session.is_some_and(|only| only != s));
+        before - self.entries.len()
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_schema_cache.rs"]
+mod tests;
@@ -0,0 +1,202 @@
+//! Compact schema summaries for `psql_schema_summary`.
+//!
+//! A summary opens with one prose line about the schema, then gives one line
+//! per table: its size as an order of magnitude and its columns, marking the
+//! primary key and where each foreign key column points. Tables with the
+//! most relationships come first. When the budget runs short a table keeps
+//! only its key columns, and once even that does not fit the remaining
+//! tables are named as omitted. Tokens are estimated as four characters
+//! each, close enough for common tokenizers on identifier-heavy text.
+
+use crate::er::{Cardinality, Edge, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Budget used when the caller does not pick one.
+pub const DEFAULT_MAX_TOKENS: usize = 2000;
+
+/// Columns of the tables of schema `$1`, in table order.
+pub const COLUMNS_SQL: &str = "select c.relname::text as table, a.attname::text as name, \
+     format_type(a.atttypid, a.atttypmod) as type \
+     from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+     join pg_attribute a on a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped \
+     where n.nspname = $1 and c.relkind in ('r', 'p') and not c.relispartition \
+     order by c.relname, a.attnum";
+
+/// A row of [`COLUMNS_SQL`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct Column {
+    pub table: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub data_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableSummary {
+    pub table: String,
+    pub rows: String,
+    pub primary_key: Vec<String>,
+    /// Each foreign key as `columns -> table(columns)`.
+    pub references: Vec<String>,
+    /// Whether the line lists every column or only the keys.
+    pub all_columns: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub text: String,
+    pub estimated_tokens: usize,
+    pub tables: Vec<TableSummary>,
+    pub omitted_tables: Vec<String>,
+}
+
+/// Rough token count of `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Row count as an order of magnitude: `~100 rows`, `~10K rows`, `empty`.
+pub fn magnitude(rows: Option<i64>) -> String {
+    let rows = match rows {
+        None => return "unanalyzed".to_string(),
+        Some(rows) if rows <= 0 => return "empty".to_string(),
+        Some(rows) if rows < 10 => return "<10 rows".to_string(),
+        Some(rows) => rows,
+    };
+    let digits = rows.ilog10();
+    let (unit, scale) = match digits {
+        0..=2 => ("", 0),
+        3..=5 => ("K", 3),
+        6..=8 => ("M", 6),
+        _ => ("B", 9),
+    };
+    format!("~{}{unit} rows", 10_i64.pow(digits - scale))
+}
+
+/// Summary of `schema` in at most about `max_tokens` tokens.
+pub fn summarize(
+    schema: &str,
+    nodes: &[Node],
+    edges: &[Edge],
+    columns: &[Column],
+    max_tokens: usize,
+) -> Summary {
+    let mut degree: HashMap<&str, usize> = HashMap::new();
+    for edge in edges {
+        *degree.entry(edge.from_table.as_str()).or_default() += 1;
+        *degree.entry(edge.to_table.as_str()).or_default() += 1;
+    }
+    let mut ordered: Vec<&Node> = nodes.iter().collect();
+    ordered.sort_by(|a, b| {
+        let degree = |n: &Node| degree.get(n.table.as_str()).copied().unwrap_or(0);
+        degree(b)
+            .cmp(&degree(a))
+            .then(b.estimated_rows.cmp(&a.estimated_rows))
+            .then(a.table.cmp(&b.table))
+    });
+
+    let mut text = format!(
+        "Schema {schema}: {} tables, {} foreign keys. Columns are `name type`; PK marks the \
+         primary key and -> the column a foreign key references.\n",
+        nodes.len(),
+        edges.len()
+    );
+    let mut tables = vec![];
+    let mut omitted_tables = vec![];
+    for node in ordered {
+        if !omitted_tables.is_empty() {
+            omitted_tables.push(node.table.clone());
+            continue;
+        }
+        let outgoing: Vec<&Edge> = edges
+            .iter()
+            .filter(|e| e.from_table == node.table)
+            .collect();
+        let table_columns: Vec<&Column> =
+            columns.iter().filter(|c| c.table == node.table).collect();
+        let full = table_line(node, &outgoing, &table_columns, false);
+        let keys = table_line(node, &outgoing, &table_columns, true);
+        let all_columns = if estimate_tokens(&text) + estimate_tokens(&full) <= max_tokens {
+            text.push_str(&full);
+            true
+        } else if estimate_tokens(&text) + estimate_tokens(&keys) <= max_tokens {
+            text.push_str(&keys);
+            false
+        } else {
+            omitted_tables.push(node.table.clone());
+            continue;
+        };
+        tables.push(TableSummary {
+            table: node.table.clone(),
+            rows: magnitude(node.estimated_rows),
+            primary_key: node.primary_key.clone(),
+            references: outgoing.iter().map(|e| reference(e)).collect(),
+            all_columns,
+        });
+    }
+    if !omitted_tables.is_empty() {
+        let note = format!("Omitted {} more tables.\n", omitted_tables.len());
+        if estimate_tokens(&text) + estimate_tokens(&note) <= max_tokens {
+            text.push_str(&note);
+        }
+    }
+    Summary {
+        estimated_tokens: estimate_tokens(&text),
+        text,
+        tables,
+        omitted_tables,
+    }
+}
+
+fn reference(edge: &Edge) -> String {
+    format!(
+        "{} -> {}({})",
+        edge.from_columns.join(", "),
+        edge.to_table,
+        edge.to_columns.join(", ")
+    )
+}
+
+/// `- table (~1K rows): col type PK, col type -> other.id, …`; with
+/// `keys_only` the other columns are counted instead of listed.
+fn table_line(node: &Node, outgoing: &[&Edge], columns: &[&Column], keys_only: bool) -> String {
+    let mut parts = vec![];
+    let mut skipped = 0;
+    for column in columns {
+        let mut part = format!("{} {}", column.name, column.data_type);
+        let is_key = node.primary_key.contains(&column.name);
+        if is_key {
+            part.push_str(" PK");
+        }
+        let mut is_foreign = false;
+        for edge in outgoing {
+            if let Some(i) = edge.from_columns.iter().position(|c| *c == column.name) {
+                is_foreign = true;
+                let target = edge.to_columns.get(i).map_or("", String::as_str);
+                part.push_str(&format!(" -> {}.{target}", edge.to_table));
+                if edge.cardinality == Cardinality::OneToOne {
+                    part.push_str(" (1:1)");
+                }
+            }
+        }
+        if keys_only && !is_key && !is_foreign {
+            skipped += 1;
+        } else {
+            parts.push(part);
+        }
+    }
+    if skipped > 0 {
+        parts.push(format!("+{skipped} more columns"));
+    }
+    format!(
+        "- {} ({}): {}\n",
+        node.table,
+        magnitude(node.estimated_rows),
+        parts.join(", ")
+    )
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_schema_summary.rs"]
+mod tests;
@@ -0,0 +1,153 @@
+//! Splitting SQL scripts into statements.
+//!
+//! Queries run as one prepared statement each, so a script piped in the way
+//! `psql` accepts it is cut at top-level `;` first. Semicolons inside quotes,
+//! comments, and dollar-quoted bodies do not end a statement. The scanners
+//! for those are shared with [`crate::template`].
+
+/// Statements of `script`, trimmed and without the trailing `;`. Pieces that
+/// hold only whitespace and comments are dropped.
+pub fn split_statements(script: &str) -> Vec<String> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut statements = vec![];
+    let mut begin = 0;
+    let mut has_code = false;
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(end) = comment_end(&chars, i) {
+            i = end;
+            continue;
+        }
+        if chars[i] == ';' {
+            if has_code {
+                statements.push(
+                    chars[begin..i]
+                        .iter()
+                        .collect::<String>()
+                        .trim()
+                        .to_string(),
+                );
+            }
+            i += 1;
+            begin = i;
+            has_code = false;
+            continue;
+        }
+        if !chars[i].is_whitespace() {
+            has_code = true;
+        }
+        i = quoted_end(&chars, i).unwrap_or(i + 1);
+    }
+    if has_code {
+        statements.push(chars[begin..].iter().collect::<String>().trim().to_string());
+    }
+    statements
+}
+
+/// Index after the `--` or `/* */` comment starting at `i`, if one does.
+pub(crate) fn comment_end(chars: &[char], i: usize) -> Option<usize> {
+    match (chars[i], chars.get(i + 1)) {
+        ('-', Some('-')) => Some(
+            chars[i..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map_or(chars.len(), |p| i + p),
+        ),
+        ('/', Some('*')) => Some(skip_block_comment(chars, i)),
+        _ => None,
+    }
+}
+
+/// Index after the string literal, quoted identifier or dollar-quoted body
+/// starting at `i`, if one does.
+pub(crate) fn quoted_end(chars: &[char], i: usize) -> Option<usize> {
+    match chars[i] {
+        '\'' => {
+            let escapes =
+                i > 0 && matches!(chars[i - 1], 'e' | 'E') && !continues_word(chars, i - 1);
+            Some(skip_string(chars, i, escapes))
+        }
+        '"' => Some(skip_quoted_ident(chars, i)),
+        '$' if !continues_word(chars, i) => {
+            Some(skip_dollar_quote(chars, i)).filter(|&end| end > i + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the char at `i` is part of a word started before it.
+fn continues_word(chars: &[char], i: usize) -> bool {
+    i > 0 && (chars[i - 1].is_alphanumeric() || chars[i - 1] == '_')
+}
+
+/// Index after the `/* ... */` comment at `i`; these nest in PostgreSQL.
+fn skip_block_comment(chars: &[char], mut i: usize) -> usize {
+    let mut depth = 0usize;
+    while i < chars.len() {
+        if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+            depth += 1;
+            i += 2;
+        } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                break;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    i
+}
+
+/// Index after the `'...'` literal at `i`; `''` is an escaped quote, and so
+/// is `\'` in an `E'...'` literal.
+fn skip_string(chars: &[char], mut i: usize, escapes: bool) -> usize {
+    i += 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if escapes => i += 2,
+            '\'' if chars.get(i + 1) == Some(&'\'') => i += 2,
+            '\'' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// Index after the `"..."` identifier at `i`; `""` inside is an escaped quote
+/// and simply reopens it.
+fn skip_quoted_ident(chars: &[char], mut i: usize) -> usize {
+    i += 1;
+    while i < chars.len() && chars[i] != '"' {
+        i += 1;
+    }
+    i + 1
+}
+
+/// Index after the `$tag$ ... $tag$` body at `i`, or after the `$` when it
+/// does not open one (e.g. a `$1` placeholder).
+fn skip_dollar_quote(chars: &[char], i: usize) -> usize {
+    let tag_end = chars[i + 1..]
+        .iter()
+        .position(|&t| t == '$')
+        .map(|p| i + 1 + p)
+        .filter(|&end| {
+            let tag = &chars[i + 1..end];
+            tag.first().is_none_or(|t| !t.is_ascii_digit())
+                && tag.iter().all(|t| t.is_alphanumeric() || *t == '_')
+        });
+    let Some(tag_end) = tag_end else {
+        return i + 1;
+    };
+    let tag = &chars[i..=tag_end];
+    let mut j = tag_end + 1;
+    while j < chars.len() && !chars[j..].starts_with(tag) {
+        j += 1;
+    }
+    j + tag.len()
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_script.rs"]
+mod tests;
@@ -0,0 +1,1522 @@
+//! SQL generation for structured tool inputs.
+//!
+//! Builders here never splice values into SQL text: identifiers are quoted and
+//! values are returned as positional `params` for `$N` placeholders.
+
+use crate::redact;
+use crate::types::RedactionRule;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// PostgreSQL limit on bind parameters per statement.
+pub const MAX_BIND_PARAMS: usize = 65_535;
+
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote a `table` or `schema.table` name.
+pub fn qualified_name(name: &str) -> Result<String, String> {
+    let parts: Vec<&str> = name.split('.').collect();
+    if parts.len() > 2 || parts.iter().any(|p| p.is_empty()) {
+        return Err(format!(
+            "invalid table name '{name}', expected table or schema.table"
+        ));
+    }
+    Ok(parts
+        .iter()
+        .map(|p| quote_ident(p))
+        .collect::<Vec<_>>()
+        .join("."))
+}
+
+fn quote_ident_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|n| quote_ident(n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnConflict {
+    Error,
+    Nothing {
+        target: Vec<String>,
+    },
+    Update {
+        target: Vec<String>,
+        columns: Option<Vec<String>>,
+    },
+}
+
+impl OnConflict {
+    /// Parse the `on_conflict` tool argument:
+    /// `{"action": "nothing"|"update", "target": [...], "update_columns": [...]}`.
+    pub fn from_value(v: Option<&Value>) -> Result<Self, String> {
+        let Some(v) = v.filter(|v| !v.is_null()) else {
+            return Ok(Self::Error);
+        };
+        let action = v
+            .get("action")
+            .and_then(Value::as_str)
+            .ok_or("on_conflict.action is required")?;
+        let target = string_list(v.get("target"), "on_conflict.target")?.unwrap_or_default();
+        match action {
+            "error" => Ok(Self::Error),
+            "nothing" => Ok(Self::Nothing { target }),
+            "update" => {
+                if target.is_empty() {
+                    return Err("on_conflict.target is required for action=update".to_string());
+                }
+                Ok(Self::Update {
+                    target,
+                    columns: string_list(v.get("update_columns"), "on_conflict.update_columns")?,
+                })
+            }
+            other => Err(format!(
+                "unsupported on_conflict.action '{other}', expected error|nothing|update"
+            )),
+        }
+    }
+}
+
+pub fn string_list(v: Option<&Value>, field: &str) -> Result<Option<Vec<String>>, String> {
+    match v {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|i| {
+                i.as_str()
+                    .map(std::string::ToString::to_string)
+                    .ok_or_else(|| format!("{field} must be an array of strings"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some),
+        Some(_) => Err(format!("{field} must be an array of strings")),
+    }
+}
+
+/// Column names in order of first appearance across all row objects.
+pub fn row_columns(rows: &[Value]) -> Result<Vec<String>, String> {
+    if rows.is_empty() {
+        return Err("rows must not be empty".to_string());
+    }
+    let mut columns: Vec<String> = vec![];
+    for (idx, row) in rows.iter().enumerate() {
+        let Value::Object(map) = row else {
+            return Err(format!("rows[{idx}] must be an object"));
+        };
+        for key in map.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    if columns.is_empty() {
+        return Err("rows must contain at least one column".to_string());
+    }
+    Ok(columns)
+}
+
+/// `CREATE TABLE` with a column per key of `rows`, typed with the SQLite
+/// affinity of its values: `integer`, `real` or `text` (objects and arrays
+/// are stored as JSON text). Mixed or all-null columns get no type.
+pub fn build_create_table(table: &str, rows: &[Value]) -> Result<String, String> {
+    let table = qualified_name(table)?;
+    let columns = row_columns(rows)?;
+    let defs: Vec<String> = columns
+        .iter()
+        .map(|c| match column_affinity(rows, c) {
+            Some(affinity) => format!("{} {affinity}", quote_ident(c)),
+            None => quote_ident(c),
+        })
+        .collect();
+    Ok(format!("create table {table} ({})", defs.join(", ")))
+}
+
+fn column_affinity(rows: &[Value], column: &str) -> Option<&'static str> {
+    let mut affinity = None;
+    for value in rows.iter().filter_map(|r| r.get(column)) {
+        let this = match value {
+            Value::Null => continue,
+            Value::Bool(_) => "integer",
+            Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+            Value::Number(_) => "real",
+            _ => "text",
+        };
+        affinity = match (affinity, this) {
+            (None, this) => Some(this),
+            (Some(seen), this) if seen == this => Some(seen),
+            (Some("integer" | "real"), "integer" | "real") => Some("real"),
+            _ => return None,
+        };
+    }
+    affinity
+}
+
+/// Whether every row object carries every column (required for COPY, which
+/// cannot express `DEFAULT` for missing keys).
+pub fn rows_are_dense(rows: &[Value], columns: &[String]) -> bool {
+    rows.iter().all(|r| {
+        r.as_object()
+            .map(|m| columns.iter().all(|c| m.contains_key(c)))
+            .unwrap_or(false)
+    })
+}
+
+/// Multi-row `INSERT ... VALUES` with one placeholder per present value and
+/// `DEFAULT` for keys a row omits.
+pub fn build_insert(
+    table: &str,
+    rows: &[Value],
+    on_conflict: &OnConflict,
+    returning: &[String],
+) -> Result<(String, Vec<Value>), String> {
+    insert_with_casts(table, rows, on_conflict, returning, &[])
+}
+
+/// [`build_insert`] with `$N::<cast>` placeholders for the listed columns,
+/// for types the driver cannot bind directly (e.g. `text::vector`).
+fn insert_with_casts(
+    table: &str,
+    rows: &[Value],
+    on_conflict: &OnConflict,
+    returning: &[String],
+    casts: &[(&str, &str)],
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let columns = row_columns(rows)?;
+    let mut params: Vec<Value> = vec![];
+    let mut tuples: Vec<String> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let map = row.as_object().ok_or("rows must be objects")?;
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|c| match map.get(c) {
+                Some(v) => {
+                    params.push(v.clone());
+                    match casts.iter().find(|(col, _)| col == c) {
+                        Some((_, cast)) => format!("${}::{cast}", params.len()),
+                        None => format!("${}", params.len()),
+                    }
+                }
+                None => "default".to_string(),
+            })
+            .collect();
+        tuples.push(format!("({})", cells.join(", ")));
+    }
+    if params.len() > MAX_BIND_PARAMS {
+        return Err(format!(
+            "insert needs {} bind params (max {MAX_BIND_PARAMS}); send fewer rows per call",
+            params.len()
+        ));
+    }
+
+    let mut sql = format!(
+        "insert into {table} ({}) values {}",
+        quote_ident_list(&columns),
+        tuples.join(", ")
+    );
+    match on_conflict {
+        OnConflict::Error => {}
+        OnConflict::Nothing { target } if target.is_empty() => {
+            sql.push_str(" on conflict do nothing");
+        }
+        OnConflict::Nothing { target } => {
+            sql.push_str(&format!(
+                " on conflict ({}) do nothing",
+                quote_ident_list(target)
+            ));
+        }
+        OnConflict::Update {
+            target,
+            columns: update,
+        } => {
+            let update: Vec<String> = update.clone().unwrap_or_else(|| {
+                columns
+                    .iter()
+                    .filter(|c| !target.contains(c))
+                    .cloned()
+                    .collect()
+            });
+            if update.is_empty() {
+                sql.push_str(&format!(
+                    " on conflict ({}) do nothing",
+                    quote_ident_list(target)
+                ));
+            } else {
+                let sets: Vec<String> = update
+                    .iter()
+                    .map(|c| format!("{0} = excluded.{0}", quote_ident(c)))
+                    .collect();
+                sql.push_str(&format!(
+                    " on conflict ({}) do update set {}",
+                    quote_ident_list(target),
+                    sets.join(", ")
+                ));
+            }
+        }
+    }
+    push_returning(&mut sql, returning);
+    Ok((sql, params))
+}
+
+/// Unique, non-partial, column-only indexes of a table (primary key first).
+/// `$1` is the table's [`qualified_name`], so mixed-case names resolve as
+/// quoted; rows are `{"is_primary", "columns"}`.
+pub const UNIQUE_KEYS_SQL: &str = "select i.indisprimary as is_primary, \
+     array_agg(a.attname::text order by k.ord) as columns \
+     from pg_index i \
+     cross join lateral unnest(i.indkey::int2[]) with ordinality as k(attnum, ord) \
+     join pg_attribute a on a.attrelid = i.indrelid and a.attnum = k.attnum \
+     where i.indrelid = $1::text::regclass and i.indisunique \
+     and i.indpred is null and i.indexprs is null \
+     group by i.indexrelid, i.indisprimary \
+     order by i.indisprimary desc, i.indexrelid";
+
+/// Pick the first unique key (rows from [`UNIQUE_KEYS_SQL`]) whose columns
+/// are all present in `columns`.
+pub fn pick_conflict_key(keys: &[Value], columns: &[String]) -> Option<Vec<String>> {
+    keys.iter()
+        .filter_map(|k| string_list(k.get("columns"), "columns").ok().flatten())
+        .find(|key| !key.is_empty() && key.iter().all(|c| columns.contains(c)))
+}
+
+/// `INSERT ... ON CONFLICT (key) DO UPDATE` wrapped to report
+/// `{"inserted": n, "updated": m}` (via the `xmax = 0` insert marker).
+pub fn build_upsert(
+    table: &str,
+    rows: &[Value],
+    key: &[String],
+    update_columns: Option<Vec<String>>,
+) -> Result<(String, Vec<Value>), String> {
+    upsert_with_casts(table, rows, key, update_columns, &[])
+}
+
+fn upsert_with_casts(
+    table: &str,
+    rows: &[Value],
+    key: &[String],
+    update_columns: Option<Vec<String>>,
+    casts: &[(&str, &str)],
+) -> Result<(String, Vec<Value>), String> {
+    if key.is_empty() {
+        return Err("upsert key must not be empty".to_string());
+    }
+    let conflict = OnConflict::Update {
+        target: key.to_vec(),
+        columns: update_columns,
+    };
+    let (insert_sql, params) = insert_with_casts(table, rows, &conflict, &[], casts)?;
+    let sql = format!(
+        "with upserted as ({insert_sql} returning (xmax = 0) as inserted) \
+         select count(*) filter (where inserted) as inserted, \
+         count(*) filter (where not inserted) as updated from upserted"
+    );
+    Ok((sql, params))
+}
+
+/// `SELECT *` sample of a table: first `$1` rows, or a random `$1` rows.
+pub fn build_sample(table: &str, limit: u64, random: bool) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let order = if random { " order by random()" } else { "" };
+    Ok((
+        format!("select * from {table}{order} limit $1"),
+        vec![Value::from(limit)],
+    ))
+}
+
+/// Per-column planner statistics for a table (`$1` = its [`qualified_name`]).
+/// Values come from `pg_stats`, so they are estimates and `null` until the
+/// table has been analyzed; `min`/`max` are histogram bounds for numerics.
+pub const COLUMN_STATS_SQL: &str = "select a.attname::text as column, \
+     format_type(a.atttypid, a.atttypmod) as type, \
+     c.reltuples::bigint as estimated_rows, \
+     s.null_frac, \
+     case when s.n_distinct is null then null \
+          when s.n_distinct >= 0 then s.n_distinct::bigint \
+          else round(-s.n_distinct * greatest(c.reltuples, 0))::bigint end as distinct_estimate, \
+     case when a.atttypid in ('int2'::regtype, 'int4'::regtype, 'int8'::regtype, \
+                              'float4'::regtype, 'float8'::regtype, 'numeric'::regtype) \
+          then (s.histogram_bounds::text::text[])[1]::numeric end as min, \
+     case when a.atttypid in ('int2'::regtype, 'int4'::regtype, 'int8'::regtype, \
+                              'float4'::regtype, 'float8'::regtype, 'numeric'::regtype) \
+          then (s.histogram_bounds::text::text[])[cardinality(s.histogram_bounds::text::text[])]::numeric end as max \
+     from pg_class c \
+     join pg_namespace n on n.oid = c.relnamespace \
+     join pg_attribute a on a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped \
+     left join pg_stats s on s.schemaname = n.nspname and s.tablename = c.relname and s.attname = a.attname \
+     where c.oid = $1::text::regclass \
+     order by a.attnum";
+
+/// Row estimate, columns and the leading column of each existing index of
+/// a table (`$1` = table name as text), for index suggestions.
+pub const TABLE_INDEXES_SQL: &str = "select c.reltuples::float8 as estimated_rows, \
+     (select coalesce(jsonb_agg(a.attname::text order by a.attnum), '[]'::jsonb) \
+        from pg_attribute a where a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped) as columns, \
+     (select coalesce(jsonb_agg(distinct a.attname::text), '[]'::jsonb) \
+        from pg_index i join pg_attribute a on a.attrelid = i.indrelid and a.attnum = i.indkey[0] \
+        where i.indrelid = c.oid) as indexed_leading \
+     from pg_class c where c.oid = $1::text::regclass";
+
+/// Rows read by `psql_column_stats` and `psql_aggregate`.
+pub enum RowSource<'a> {
+    Table(&'a str),
+    /// A row-returning statement; its own `$N` params come first.
+    Query(&'a str),
+}
+
+impl RowSource<'_> {
+    /// The table read, when known.
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            Self::Table(table) => Some(table),
+            Self::Query(_) => None,
+        }
+    }
+
+    /// `redact` column globs that can match this source's columns.
+    fn redacted_globs<'r>(&self, rules: &'r [RedactionRule]) -> Vec<&'r str> {
+        redact::column_globs(rules, self.table())
+    }
+
+    /// The source as a `FROM` item: a quoted name or a parenthesized query.
+    fn relation(&self) -> Result<String, String> {
+        match self {
+            Self::Table(table) => qualified_name(table),
+            Self::Query(sql) => {
+                let sql = sql.trim().trim_end_matches(';').trim_end();
+                if sql.is_empty() {
+                    return Err("sql must not be empty".to_string());
+                }
+                Ok(format!("({sql})"))
+            }
+        }
+    }
+}
+
+/// One aggregate query profiling each column of `source` (or only
+/// `columns`): a row per column with `rows`, `null_fraction`,
+/// `distinct_count`, the `top_k` most common `top_values`, and for columns
+/// holding only numbers `min`, `max` and a `histogram` of `buckets`
+/// equal-width buckets. Values pass through `to_jsonb`, so they are counted
+/// and compared in their JSON form. Columns a `redact` rule matches report
+/// `redacted: true` and no `top_values`, `min`, `max` or `histogram`; with
+/// a query source, table-qualified rules match by column name alone. `max_rows` caps the rows read. The returned params are
+/// `params` followed by the profile's own.
+pub fn build_column_profile(
+    source: RowSource,
+    mut params: Vec<Value>,
+    columns: Option<&[String]>,
+    redact: &[RedactionRule],
+    top_k: u64,
+    buckets: u64,
+    max_rows: Option<u64>,
+) -> Result<(String, Vec<Value>), String> {
+    if buckets == 0 {
+        return Err("buckets must be at least 1".to_string());
+    }
+    let from = source.relation()?;
+    let select = match columns {
+        Some([]) => return Err("columns must not be empty".to_string()),
+        Some(columns) => quote_ident_list(columns),
+        None => "*".to_string(),
+    };
+    let top_p = format!("${}::bigint", params.len() + 1);
+    let buckets_p = format!("${}::int", params.len() + 2);
+    let redacted_p = format!(
+        "array(select jsonb_array_elements_text(${}::jsonb))",
+        params.len() + 3
+    );
+    params.push(Value::from(top_k));
+    params.push(Value::from(buckets));
+    params.push(Value::from(
+        source
+            .redacted_globs(redact)
+            .into_iter()
+            .map(glob_to_like)
+            .collect::<Vec<_>>(),
+    ));
+    let limit = match max_rows {
+        Some(n) => {
+            params.push(Value::from(n));
+            format!(" limit ${}::bigint", params.len())
+        }
+        None => String::new(),
+    };
+    let number = "(c.v #>> '{}')::numeric";
+    let sql = format!(
+        "with afpsql_src as (select {select} from {from} s{limit}), \
+         cells as (select e.key as col, e.value as v \
+           from afpsql_src cross join lateral jsonb_each(to_jsonb(afpsql_src)) e), \
+         total as (select count(*) as n from afpsql_src), \
+         per_col as (select col, col ilike any({redacted_p}) as redacted, \
+           count(*) filter (where v = 'null'::jsonb) as nulls, \
+           count(distinct v) filter (where v <> 'null'::jsonb) as distinct_count, \
+           bool_and(jsonb_typeof(v) = 'number') filter (where v <> 'null'::jsonb) as is_numeric, \
+           min((v #>> '{{}}')::numeric) filter (where jsonb_typeof(v) = 'number') as lo, \
+           max((v #>> '{{}}')::numeric) filter (where jsonb_typeof(v) = 'number') as hi \
+           from cells group by col), \
+         top as (select col, jsonb_agg(jsonb_build_object('value', v, 'count', c) order by c desc, v) as top_values \
+           from (select col, v, count(*) as c, \
+             row_number() over (partition by col order by count(*) desc, v) as rank \
+             from cells where v <> 'null'::jsonb and not col ilike any({redacted_p}) \
+             group by col, v) ranked \
+           where rank <= {top_p} group by col), \
+         bucketed as (select c.col, \
+           case when p.hi = p.lo then 1 \
+                else least(width_bucket({number}, p.lo, p.hi, {buckets_p}), {buckets_p}) end as bucket, \
+           count(*) as c \
+           from cells c join per_col p using (col) \
+           where p.is_numeric and not p.redacted and c.v <> 'null'::jsonb group by 1, 2), \
+         hist as (select p.col, jsonb_agg(jsonb_build_object( \
+             'lower', (p.lo + (g.bucket - 1) * (p.hi - p.lo) / {buckets_p})::float8, \
+             'upper', (p.lo + g.bucket * (p.hi - p.lo) / {buckets_p})::float8, \
+             'count', coalesce(b.c, 0)) order by g.bucket) as histogram \
+           from per_col p cross join generate_series(1, {buckets_p}) g(bucket) \
+           left join bucketed b on b.col = p.col and b.bucket = g.bucket \
+           where p.is_numeric and not p.redacted group by p.col) \
+         select p.col as column, t.n as rows, \
+           round(p.nulls::numeric / nullif(t.n, 0), 4)::float8 as null_fraction, \
+           p.distinct_count, p.redacted, \
+           case when not p.redacted then coalesce(top.top_values, '[]'::jsonb) end as top_values, \
+           case when p.is_numeric and not p.redacted then p.lo end as min, \
+           case when p.is_numeric and not p.redacted then p.hi end as max, hist.histogram \
+         from per_col p cross join total t \
+         left join top using (col) left join hist using (col) \
+         order by p.col"
+    );
+    Ok((sql, params))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFn {
+    Count,
+    CountDistinct,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFn {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "count" => Ok(Self::Count),
+            "count_distinct" => Ok(Self::CountDistinct),
+            "sum" => Ok(Self::Sum),
+            "avg" => Ok(Self::Avg),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            other => Err(format!(
+                "unsupported aggregate '{other}', expected count|count_distinct|sum|avg|min|max"
+            )),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::CountDistinct => "count_distinct",
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateSpec {
+    pub func: AggregateFn,
+    /// `None` only for `count`, which then counts rows.
+    pub column: Option<String>,
+    pub alias: String,
+}
+
+impl AggregateSpec {
+    /// Parse one `aggregates` item: `{"fn": "sum", "column": "amount",
+    /// "as": "total"}`. The alias defaults to `<fn>_<column>`, or `count`.
+    pub fn from_value(v: &Value) -> Result<Self, String> {
+        let func = AggregateFn::parse(
+            v.get("fn")
+                .and_then(Value::as_str)
+                .ok_or("aggregates[].fn is required")?,
+        )?;
+        let column = v.get("column").and_then(Value::as_str).map(str::to_string);
+        if column.is_none() && func != AggregateFn::Count {
+            return Err(format!("aggregate {} needs a column", func.name()));
+        }
+        let alias = match (v.get("as").and_then(Value::as_str), &column) {
+            (Some(alias), _) => alias.to_string(),
+            (None, Some(column)) => format!("{}_{column}", func.name()),
+            (None, None) => "count".to_string(),
+        };
+        Ok(Self {
+            func,
+            column,
+            alias,
+        })
+    }
+
+    fn sql(&self) -> String {
+        let column = self.column.as_deref().map(quote_ident);
+        let expr = match (self.func, column) {
+            (AggregateFn::Count, None) => "count(*)".to_string(),
+            (AggregateFn::CountDistinct, Some(c)) => format!("count(distinct {c})"),
+            (func, Some(c)) => format!("{}({c})", func.name()),
+            (func, None) => format!("{}(*)", func.name()),
+        };
+        format!("{expr} as {}", quote_ident(&self.alias))
+    }
+}
+
+/// `GROUP BY` of `source` over `group_by` computing `aggregates`, ordered by
+/// the group columns. With `rollup`, subtotal rows for each prefix of
+/// `group_by` and a grand total follow their groups, with the rolled-up
+/// columns null and a `grouping` bitmask telling them apart from null
+/// values. Columns a `redact` rule matches can only be counted: their
+/// values would otherwise come back under the aggregate's alias, which the
+/// rules do not match. The returned params are `params` followed by the
+/// `limit`.
+pub fn build_aggregate(
+    source: RowSource,
+    mut params: Vec<Value>,
+    group_by: &[String],
+    aggregates: &[AggregateSpec],
+    rollup: bool,
+    limit: Option<u64>,
+    redact: &[RedactionRule],
+) -> Result<(String, Vec<Value>), String> {
+    if aggregates.is_empty() {
+        return Err("aggregates must not be empty".to_string());
+    }
+    for spec in aggregates {
+        let Some(column) = &spec.column else {
+            continue;
+        };
+        let counted = matches!(spec.func, AggregateFn::Count | AggregateFn::CountDistinct);
+        if !counted && is_redacted(redact, source.table(), column) {
+            return Err(format!(
+                "column '{column}' is redacted; only count and count_distinct can aggregate it, not {}",
+                spec.func.name()
+            ));
+        }
+    }
+    if rollup && group_by.is_empty() {
+        return Err("rollup needs group_by columns".to_string());
+    }
+    let mut names: Vec<&str> = group_by.iter().map(String::as_str).collect();
+    if rollup {
+        names.push("grouping");
+    }
+    for spec in aggregates {
+        if names.contains(&spec.alias.as_str()) {
+            return Err(format!("duplicate output column '{}'", spec.alias));
+        }
+        names.push(&spec.alias);
+    }
+    let from = source.relation()?;
+    let groups = quote_ident_list(group_by);
+    let mut select: Vec<String> = group_by.iter().map(|c| quote_ident(c)).collect();
+    if rollup {
+        select.push(format!("grouping({groups}) as \"grouping\""));
+    }
+    select.extend(aggregates.iter().map(AggregateSpec::sql));
+    let mut sql = format!("select {} from {from} s", select.join(", "));
+    if !group_by.is_empty() {
+        let order: Vec<String> = (1..=group_by.len()).map(|i| i.to_string()).collect();
+        if rollup {
+            sql.push_str(&format!(" group by rollup ({groups})"));
+        } else {
+            sql.push_str(&format!(" group by {groups}"));
+        }
+        sql.push_str(&format!(" order by {}", order.join(", ")));
+    }
+    if let Some(n) = limit {
+        params.push(Value::from(n));
+        sql.push_str(&format!(" limit ${}::bigint", params.len()));
+    }
+    Ok((sql, params))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaintenanceOp {
+    Vacuum,
+    VacuumFull,
+    VacuumAnalyze,
+    Analyze,
+    Reindex,
+}
+
+impl MaintenanceOp {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "vacuum" => Ok(Self::Vacuum),
+            "vacuum_full" => Ok(Self::VacuumFull),
+            "vacuum_analyze" => Ok(Self::VacuumAnalyze),
+            "analyze" => Ok(Self::Analyze),
+            "reindex" => Ok(Self::Reindex),
+            other => Err(format!(
+                "unsupported operation '{other}', expected vacuum|vacuum_full|vacuum_analyze|analyze|reindex"
+            )),
+        }
+    }
+}
+
+/// The statement running `op` on `table`, and why it blocks other sessions'
+/// use of the table while it runs, if it does. Only `reindex` can run
+/// `concurrently`.
+pub fn build_maintenance(
+    op: MaintenanceOp,
+    table: &str,
+    concurrently: bool,
+) -> Result<(String, Option<String>), String> {
+    let name = qualified_name(table)?;
+    if concurrently && op != MaintenanceOp::Reindex {
+        return Err("concurrently applies to reindex only".to_string());
+    }
+    Ok(match op {
+        MaintenanceOp::Vacuum => (format!("vacuum {name}"), None),
+        MaintenanceOp::VacuumAnalyze => (format!("vacuum (analyze) {name}"), None),
+        MaintenanceOp::Analyze => (format!("analyze {name}"), None),
+        MaintenanceOp::VacuumFull => (
+            format!("vacuum (full) {name}"),
+            Some(format!(
+                "vacuum full rewrites {table} under an ACCESS EXCLUSIVE lock, blocking reads and writes"
+            )),
+        ),
+        MaintenanceOp::Reindex if concurrently => {
+            (format!("reindex table concurrently {name}"), None)
+        }
+        MaintenanceOp::Reindex => (
+            format!("reindex table {name}"),
+            Some(format!(
+                "reindex blocks writes to {table} and reads through its indexes; pass concurrently to avoid it"
+            )),
+        ),
+    })
+}
+
+/// Size, live and dead tuples and the last (auto)vacuum and analyze of a
+/// table (`$1` = its [`qualified_name`]), for maintenance dry runs.
+pub const MAINTENANCE_STATS_SQL: &str = "select pg_total_relation_size(c.oid) as total_bytes, \
+     s.n_live_tup, s.n_dead_tup, s.n_mod_since_analyze, \
+     s.last_vacuum, s.last_autovacuum, s.last_analyze, s.last_autoanalyze \
+     from pg_class c left join pg_stat_all_tables s on s.relid = c.oid \
+     where c.oid = $1::text::regclass";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchMethod {
+    Ilike,
+    Trigram,
+    Fts,
+}
+
+impl SearchMethod {
+    pub fn parse(v: Option<&str>) -> Result<Self, String> {
+        match v.unwrap_or("ilike") {
+            "ilike" => Ok(Self::Ilike),
+            "trigram" => Ok(Self::Trigram),
+            "fts" => Ok(Self::Fts),
+            other => Err(format!(
+                "unsupported search method '{other}', expected ilike|trigram|fts"
+            )),
+        }
+    }
+}
+
+pub struct SearchTarget {
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
+/// Whether a `redact` rule matches `column` read from `table` (`None` when
+/// the source is not known, matching table-qualified rules by column alone).
+pub fn is_redacted(rules: &[RedactionRule], table: Option<&str>, column: &str) -> bool {
+    redact::column_globs(rules, table)
+        .into_iter()
+        .any(|glob| redact::glob_match(glob, column))
+}
+
+/// A redaction glob (`*`, `?`) as an `ilike` pattern.
+pub fn glob_to_like(glob: &str) -> String {
+    escape_like(glob).replace('*', "%").replace('?', "_")
+}
+
+/// Escape `%`, `_` and `\` so `term` matches literally inside LIKE patterns.
+pub fn escape_like(term: &str) -> String {
+    let mut out = String::with_capacity(term.len());
+    for ch in term.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// One ranked `UNION ALL` search over all targets. Each output row is
+/// `{"table", "row", "rank", "snippet"}`, ordered by `rank` descending. A
+/// snippet taken from a column a `redact` rule matches is `[redacted]`;
+/// `row` is left to the executor's redaction.
+pub fn build_search(
+    targets: &[SearchTarget],
+    term: &str,
+    method: SearchMethod,
+    fts_config: &str,
+    limit: u64,
+    redact: &[RedactionRule],
+) -> Result<(String, Vec<Value>), String> {
+    if targets.is_empty() {
+        return Err("targets must not be empty".to_string());
+    }
+    if term.is_empty() {
+        return Err("query must not be empty".to_string());
+    }
+    let mut params: Vec<Value> = vec![Value::String(term.to_string())];
+    let term_p = "$1";
+    let aux_p = match method {
+        SearchMethod::Ilike => {
+            params.push(Value::String(format!("%{}%", escape_like(term))));
+            "$2"
+        }
+        SearchMethod::Fts => {
+            params.push(Value::String(fts_config.to_string()));
+            "$2::text::regconfig"
+        }
+        SearchMethod::Trigram => "",
+    };
+
+    let mut branches = Vec::with_capacity(targets.len());
+    for target in targets {
+        let table_sql = qualified_name(&target.table)?;
+        if target.columns.is_empty() {
+            return Err(format!(
+                "targets[{}].columns must not be empty",
+                target.table
+            ));
+        }
+        let cols: Vec<String> = target
+            .columns
+            .iter()
+            .map(|c| format!("t.{}::text", quote_ident(c)))
+            .collect();
+        let redacted: Vec<bool> = target
+            .columns
+            .iter()
+            .map(|c| is_redacted(redact, Some(&target.table), c))
+            .collect();
+        let mask = format!("'{}'", redact::MASK);
+        params.push(Value::String(target.table.clone()));
+        let table_p = format!("${}::text", params.len());
+
+        let (filter, rank, snippet) = match method {
+            SearchMethod::Ilike => {
+                let hits: Vec<String> = cols.iter().map(|c| format!("{c} ilike {aux_p}")).collect();
+                let first_hit: Vec<String> = cols
+                    .iter()
+                    .zip(&redacted)
+                    .map(|(c, redacted)| {
+                        let excerpt = if *redacted {
+                            mask.clone()
+                        } else {
+                            format!(
+                                "substring({c} from greatest(strpos(lower({c}), lower({term_p})) - 40, 1) for length({term_p}) + 80)"
+                            )
+                        };
+                        format!("case when {c} ilike {aux_p} then {excerpt} end")
+                    })
+                    .collect();
+                (
+                    hits.join(" or "),
+                    format!(
+                        "({})::float8",
+                        hits.iter()
+                            .map(|h| format!("({h})::int"))
+                            .collect::<Vec<_>>()
+                            .join(" + ")
+                    ),
+                    format!("coalesce({})", first_hit.join(", ")),
+                )
+            }
+            SearchMethod::Trigram => (
+                cols.iter()
+                    .map(|c| format!("{term_p} <% {c}"))
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                format!(
+                    "greatest({})::float8",
+                    cols.iter()
+                        .map(|c| format!("word_similarity({term_p}, {c})"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                format!(
+                    "(select case when r then {mask} else v end \
+                     from unnest(array[{}], array[{}]::bool[]) u(v, r) \
+                     order by word_similarity({term_p}, v) desc nulls last limit 1)",
+                    cols.join(", "),
+                    redacted
+                        .iter()
+                        .map(bool::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ),
+            SearchMethod::Fts => {
+                let doc = format!("concat_ws(' ', {})", cols.join(", "));
+                let query = format!("websearch_to_tsquery({aux_p}, {term_p})");
+                let shown: Vec<&str> = cols
+                    .iter()
+                    .zip(&redacted)
+                    .filter(|(_, redacted)| !**redacted)
+                    .map(|(c, _)| c.as_str())
+                    .collect();
+                let headline = if shown.is_empty() {
+                    mask.clone()
+                } else {
+                    format!(
+                        "ts_headline({aux_p}, concat_ws(' ', {}), {query}, 'StartSel=**, StopSel=**, MaxFragments=2')",
+                        shown.join(", ")
+                    )
+                };
+                (
+                    format!("to_tsvector({aux_p}, {doc}) @@ {query}"),
+                    format!("ts_rank(to_tsvector({aux_p}, {doc}), {query})::float8"),
+                    headline,
+                )
+            }
+        };
+        branches.push(format!(
+            "select {table_p} as \"table\", to_jsonb(t) as row, {rank} as rank, {snippet} as snippet \
+             from {table_sql} t where {filter}"
+        ));
+    }
+    params.push(Value::from(limit));
+    let sql = format!(
+        "{} order by rank desc limit ${}",
+        branches.join(" union all "),
+        params.len()
+    );
+    Ok((sql, params))
+}
+
+#[cfg(feature = "pgvector")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorMetric {
+    L2,
+    Cosine,
+    InnerProduct,
+    L1,
+}
+
+#[cfg(feature = "pgvector")]
+impl VectorMetric {
+    pub fn parse(v: Option<&str>) -> Result<Self, String> {
+        match v.unwrap_or("cosine") {
+            "l2" => Ok(Self::L2),
+            "cosine" => Ok(Self::Cosine),
+            "inner_product" => Ok(Self::InnerProduct),
+            "l1" => Ok(Self::L1),
+            other => Err(format!(
+                "unsupported metric '{other}', expected l2|cosine|inner_product|l1"
+            )),
+        }
+    }
+
+    /// pgvector distance operator; `<#>` yields the negated inner product so
+    /// ascending order is nearest-first for every metric.
+    pub fn operator(self) -> &'static str {
+        match self {
+            Self::L2 => "<->",
+            Self::Cosine => "<=>",
+            Self::InnerProduct => "<#>",
+            Self::L1 => "<+>",
+        }
+    }
+}
+
+/// Render an embedding as pgvector text input (`[1,2,3]`), rejecting
+/// non-numeric or empty arrays.
+#[cfg(feature = "pgvector")]
+pub fn vector_literal(embedding: &[Value]) -> Result<String, String> {
+    if embedding.is_empty() {
+        return Err("embedding must not be empty".to_string());
+    }
+    let parts = embedding
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            v.as_f64()
+                .filter(|f| f.is_finite())
+                .map(|f| f.to_string())
+                .ok_or_else(|| format!("embedding[{i}] must be a finite number"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("[{}]", parts.join(",")))
+}
+
+/// k-nearest-neighbour query ordered by the metric's distance operator.
+/// Rows are `{"row", "distance"}`; the vector column is dropped from `row`
+/// unless `include_vector` is set.
+#[cfg(feature = "pgvector")]
+pub fn build_vector_search(
+    table: &str,
+    column: &str,
+    embedding: &[Value],
+    metric: VectorMetric,
+    k: u64,
+    filter: Option<&Value>,
+    include_vector: bool,
+) -> Result<(String, Vec<Value>), String> {
+    let table_sql = qualified_name(table)?;
+    let col = format!("t.{}", quote_ident(column));
+    let mut params = vec![Value::String(vector_literal(embedding)?)];
+    let distance = format!("{col} {} $1::text::vector", metric.operator());
+    let predicate = match filter.filter(|f| !f.is_null()) {
+        Some(f) => format!(" where {}", build_where(f, &mut params)?),
+        None => String::new(),
+    };
+    let row = if include_vector {
+        "to_jsonb(t)".to_string()
+    } else {
+        params.push(Value::String(column.to_string()));
+        format!("to_jsonb(t) - ${}::text", params.len())
+    };
+    params.push(Value::from(k));
+    Ok((
+        format!(
+            "select {row} as row, {distance} as distance from {table_sql} t{predicate} \
+             order by {distance} limit ${}",
+            params.len()
+        ),
+        params,
+    ))
+}
+
+/// Column type and declared dimension of a `vector` column.
+/// `$1` is the table's [`qualified_name`], `$2` the column name.
+#[cfg(feature = "pgvector")]
+pub const VECTOR_COLUMN_SQL: &str = "select t.typname::text as type, \
+     nullif(a.atttypmod, -1) as dimension \
+     from pg_attribute a join pg_type t on t.oid = a.atttypid \
+     where a.attrelid = $1::text::regclass and a.attname = $2 \
+     and a.attnum > 0 and not a.attisdropped";
+
+/// [`build_upsert`] where `vector_column` holds JSON number arrays: each is
+/// checked against `dimension` and bound as text cast to `vector`.
+#[cfg(feature = "pgvector")]
+pub fn build_vector_upsert(
+    table: &str,
+    rows: &[Value],
+    key: &[String],
+    vector_column: &str,
+    dimension: Option<usize>,
+    update_columns: Option<Vec<String>>,
+) -> Result<(String, Vec<Value>), String> {
+    let rows = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut map = row.as_object().ok_or("rows must be objects")?.clone();
+            let literal = match map.get(vector_column) {
+                None | Some(Value::Null) => return Ok(Value::Object(map)),
+                Some(Value::Array(embedding)) => {
+                    if let Some(d) = dimension.filter(|d| *d != embedding.len()) {
+                        return Err(format!(
+                            "rows[{i}].{vector_column} has {} dimensions, expected {d}",
+                            embedding.len()
+                        ));
+                    }
+                    vector_literal(embedding).map_err(|e| format!("rows[{i}]: {e}"))?
+                }
+                Some(_) => {
+                    return Err(format!(
+                        "rows[{i}].{vector_column} must be an array of numbers"
+                    ))
+                }
+            };
+            map.insert(vector_column.to_string(), Value::String(literal));
+            Ok(Value::Object(map))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    upsert_with_casts(
+        table,
+        &rows,
+        key,
+        update_columns,
+        &[(vector_column, "text::vector")],
+    )
+}
+
+/// `UPDATE table SET ... WHERE ...`; refuses empty `set` or `where`.
+pub fn build_update(
+    table: &str,
+    set: &Value,
+    filter: &Value,
+    returning: &[String],
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let Some(set) = set.as_object().filter(|m| !m.is_empty()) else {
+        return Err("set must be a non-empty object of column values".to_string());
+    };
+    let mut params: Vec<Value> = vec![];
+    let assignments = assignments(set, &mut params);
+    let predicate = build_where(filter, &mut params)?;
+    let mut sql = format!(
+        "update {table} set {} where {predicate}",
+        assignments.join(", ")
+    );
+    push_returning(&mut sql, returning);
+    Ok((sql, params))
+}
+
+/// `"column" = $N` for each entry of `set`, its value appended to `params`.
+fn assignments(set: &serde_json::Map<String, Value>, params: &mut Vec<Value>) -> Vec<String> {
+    set.iter()
+        .map(|(col, v)| {
+            params.push(v.clone());
+            format!("{} = ${}", quote_ident(col), params.len())
+        })
+        .collect()
+}
+
+/// `DELETE FROM table WHERE ...`; refuses an empty `where`.
+pub fn build_delete(
+    table: &str,
+    filter: &Value,
+    returning: &[String],
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let mut params: Vec<Value> = vec![];
+    let predicate = build_where(filter, &mut params)?;
+    let mut sql = format!("delete from {table} where {predicate}");
+    push_returning(&mut sql, returning);
+    Ok((sql, params))
+}
+
+/// `SELECT count(*) FROM table WHERE ...`; refuses an empty `where`.
+pub fn build_count(table: &str, filter: &Value) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let mut params: Vec<Value> = vec![];
+    let predicate = build_where(filter, &mut params)?;
+    Ok((
+        format!("select count(*) as rows from {table} where {predicate}"),
+        params,
+    ))
+}
+
+/// `SELECT count(*)` over the rows `sql` returns.
+pub fn build_count_of(sql: &str) -> String {
+    let sql = sql.trim().trim_end_matches(';');
+    format!("select count(*) as rows from ({sql}\n) as afpsql_counted")
+}
+
+/// Move up to `limit` rows matching `filter` from `table` into `archive` in
+/// one statement, so the delete and the insert commit or roll back
+/// together. `columns` are the source table's; the archive table must have
+/// them all and may have more, left to their defaults.
+pub fn build_archive_batch(
+    table: &str,
+    archive: &str,
+    columns: &[String],
+    filter: &Value,
+    limit: usize,
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let archive = qualified_name(archive)?;
+    if table == archive {
+        return Err("archive_table must differ from table".to_string());
+    }
+    if columns.is_empty() {
+        return Err(format!("{table} has no columns to archive"));
+    }
+    let mut params: Vec<Value> = vec![];
+    let predicate = build_where(filter, &mut params)?;
+    params.push(Value::from(limit));
+    let columns = quote_ident_list(columns);
+    let sql = format!(
+        "with moved as (delete from {table} where {} returning {columns}) \
+         insert into {archive} ({columns}) select {columns} from moved",
+        row_batch(&table, &predicate, params.len())
+    );
+    Ok((sql, params))
+}
+
+/// One batch of a chunked `DELETE`: up to `limit` rows matching `filter`.
+/// Deleted rows no longer match, so each batch picks up where the last one
+/// stopped.
+pub fn build_delete_chunk(
+    table: &str,
+    filter: &Value,
+    limit: usize,
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let mut params: Vec<Value> = vec![];
+    let predicate = build_where(filter, &mut params)?;
+    params.push(Value::from(limit));
+    let sql = format!(
+        "delete from {table} where {}",
+        row_batch(&table, &predicate, params.len())
+    );
+    Ok((sql, params))
+}
+
+/// Primary key columns of a table in key order, with their types. `$1` is
+/// the table's [`qualified_name`]; rows are `{"column", "type"}`.
+pub const PRIMARY_KEY_SQL: &str = "select a.attname::text as column, \
+     format_type(a.atttypid, a.atttypmod) as type \
+     from pg_index i \
+     cross join lateral unnest(i.indkey::int2[]) with ordinality as k(attnum, ord) \
+     join pg_attribute a on a.attrelid = i.indrelid and a.attnum = k.attnum \
+     where i.indrelid = $1::text::regclass and i.indisprimary \
+     order by k.ord";
+
+/// One batch of a chunked `UPDATE`: the next `limit` rows matching `filter`
+/// in primary key order, after `after` (the last key of the previous batch,
+/// as text), locked and updated in one statement. `key` holds the primary
+/// key columns with their types. Updated rows may still match, so batches
+/// walk the key instead of picking whatever matches. The statement returns
+/// one row: `picked`, the rows in the batch, `updated`, and `last`, the
+/// batch's last key as an array of text, or null when it was empty.
+pub fn build_update_chunk(
+    table: &str,
+    set: &Value,
+    filter: &Value,
+    key: &[(String, String)],
+    after: Option<&[Value]>,
+    limit: usize,
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(table)?;
+    let Some(set) = set.as_object().filter(|m| !m.is_empty()) else {
+        return Err("set must be a non-empty object of column values".to_string());
+    };
+    if key.is_empty() {
+        return Err(format!("chunked update of {table} needs a primary key"));
+    }
+    if let Some((column, _)) = key.iter().find(|(column, _)| set.contains_key(column)) {
+        return Err(format!(
+            "chunked update cannot set primary key column {column}"
+        ));
+    }
+    let mut params: Vec<Value> = vec![];
+    let assignments = assignments(set, &mut params);
+    let mut predicate = build_where(filter, &mut params)?;
+    let columns: Vec<String> = key.iter().map(|(column, _)| column.clone()).collect();
+    let key_list = quote_ident_list(&columns);
+    if let Some(after) = after {
+        if after.len() != key.len() {
+            return Err(format!("expected {} key values after", key.len()));
+        }
+        let bounds: Vec<String> = key
+            .iter()
+            .zip(after)
+            .map(|((_, type_name), v)| {
+                params.push(v.clone());
+                format!("${}::text::{type_name}", params.len())
+            })
+            .collect();
+        predicate = format!("{predicate} and ({key_list}) > ({})", bounds.join(", "));
+    }
+    params.push(Value::from(limit));
+    let join: Vec<String> = columns
+        .iter()
+        .map(|c| format!("target.{0} = batch.{0}", quote_ident(c)))
+        .collect();
+    let last: Vec<String> = columns
+        .iter()
+        .map(|c| format!("{}::text", quote_ident(c)))
+        .collect();
+    let descending: Vec<String> = columns
+        .iter()
+        .map(|c| format!("{} desc", quote_ident(c)))
+        .collect();
+    let sql = format!(
+        "with batch as (select {key_list} from {table} where {predicate} \
+         order by {key_list} limit ${} for update), \
+         updated as (update {table} as target set {} from batch where {} returning 1) \
+         select (select count(*) from batch) as picked, \
+         (select count(*) from updated) as updated, \
+         (select json_build_array({}) from batch order by {} limit 1) as last",
+        params.len(),
+        assignments.join(", "),
+        join.join(" and "),
+        last.join(", "),
+        descending.join(", ")
+    );
+    Ok((sql, params))
+}
+
+/// `(tableoid, ctid) in (...)`: up to `$limit_param` rows of `table`
+/// matching `predicate`, picked by their physical address, which also
+/// tells apart the partitions of a partitioned table.
+fn row_batch(table: &str, predicate: &str, limit_param: usize) -> String {
+    format!(
+        "(tableoid, ctid) in \
+         (select tableoid, ctid from {table} where {predicate} limit ${limit_param})"
+    )
+}
+
+fn push_returning(sql: &mut String, returning: &[String]) {
+    if !returning.is_empty() {
+        sql.push_str(&format!(" returning {}", quote_ident_list(returning)));
+    }
+}
+
+/// Column layout of a job-queue table, from `psql_dequeue` / `psql_complete`
+/// arguments. A job moves from `ready_status` to `running_status` when
+/// claimed, then to `done_status` or `failed_status`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobQueue {
+    pub table: String,
+    pub id_column: String,
+    pub status_column: String,
+    pub ready_status: String,
+    pub running_status: String,
+    pub done_status: String,
+    pub failed_status: String,
+    /// Claim order; defaults to `id_column`.
+    pub order_by: Vec<String>,
+    /// Set to `now()` when a job is claimed.
+    pub claimed_at_column: Option<String>,
+}
+
+impl JobQueue {
+    pub fn from_args(args: &Value) -> Result<Self, String> {
+        let text = |key: &str, default: &str| match args.get(key) {
+            None | Some(Value::Null) => Ok(default.to_string()),
+            Some(Value::String(s)) if !s.is_empty() => Ok(s.clone()),
+            Some(_) => Err(format!("{key} must be a non-empty string")),
+        };
+        let table = args
+            .get("table")
+            .and_then(Value::as_str)
+            .ok_or("missing required argument: table")?
+            .to_string();
+        let id_column = text("id_column", "id")?;
+        let order_by = string_list(args.get("order_by"), "order_by")?
+            .filter(|cols| !cols.is_empty())
+            .unwrap_or_else(|| vec![id_column.clone()]);
+        Ok(Self {
+            table,
+            status_column: text("status_column", "status")?,
+            ready_status: text("ready_status", "pending")?,
+            running_status: text("running_status", "running")?,
+            done_status: text("done_status", "done")?,
+            failed_status: text("failed_status", "failed")?,
+            order_by,
+            claimed_at_column: match args.get("claimed_at_column") {
+                None | Some(Value::Null) => None,
+                Some(_) => Some(text("claimed_at_column", "")?),
+            },
+            id_column,
+        })
+    }
+}
+
+/// Claim up to `limit` ready jobs in one statement: `SELECT ... FOR UPDATE
+/// SKIP LOCKED` picks rows no other worker holds, and the `UPDATE` marks them
+/// running and returns them. `filter` narrows the candidates (same shape as
+/// [`build_where`]).
+pub fn build_dequeue(
+    queue: &JobQueue,
+    limit: u64,
+    filter: Option<&Value>,
+) -> Result<(String, Vec<Value>), String> {
+    let table = qualified_name(&queue.table)?;
+    let id = quote_ident(&queue.id_column);
+    let status = quote_ident(&queue.status_column);
+    let mut params = vec![Value::String(queue.ready_status.clone())];
+    let mut predicate = format!("{status} = $1");
+    if let Some(filter) = filter.filter(|f| !f.is_null()) {
+        predicate.push_str(&format!(" and {}", build_where(filter, &mut params)?));
+    }
+    params.push(Value::from(limit.max(1)));
+    let limit_param = params.len();
+    params.push(Value::String(queue.running_status.clone()));
+    let mut assignments = format!("{status} = ${}", params.len());
+    if let Some(col) = &queue.claimed_at_column {
+        assignments.push_str(&format!(", {} = now()", quote_ident(col)));
+    }
+    let sql = format!(
+        "with next as (select {id} from {table} where {predicate} order by {} \
+         limit ${limit_param} for update skip locked) \
+         update {table} as job set {assignments} from next \
+         where job.{id} = next.{id} returning job.*",
+        quote_ident_list(&queue.order_by)
+    );
+    Ok((sql, params))
+}
+
+/// Move claimed jobs to `done_status`, or `failed_status` when `failed`.
+/// Only rows still in `running_status` are touched; `set` adds other
+/// columns to write (e.g. a result or error message).
+pub fn build_complete(
+    queue: &JobQueue,
+    ids: &[Value],
+    failed: bool,
+    set: Option<&Value>,
+) -> Result<(String, Vec<Value>), String> {
+    if ids.is_empty() {
+        return Err("ids must be a non-empty array".to_string());
+    }
+    let mut assignments = match set {
+        None | Some(Value::Null) => serde_json::Map::new(),
+        Some(Value::Object(map)) => map.clone(),
+        Some(_) => return Err("set must be an object of column values".to_string()),
+    };
+    let status = if failed {
+        &queue.failed_status
+    } else {
+        &queue.done_status
+    };
+    assignments.insert(queue.status_column.clone(), Value::String(status.clone()));
+    let mut filter = serde_json::Map::new();
+    filter.insert(
+        queue.id_column.clone(),
+        serde_json::json!({"op": "in", "value": ids}),
+    );
+    filter.insert(
+        queue.status_column.clone(),
+        Value::String(queue.running_status.clone()),
+    );
+    build_update(
+        &queue.table,
+        &Value::Object(assignments),
+        &Value::Object(filter),
+        std::slice::from_ref(&queue.id_column),
+    )
+}
+
+/// Structured predicate: an object of `column -> condition`, AND-ed together.
+///
+/// A condition is either a scalar (equality; `null` means `IS NULL`) or
+/// `{"op": "...", "value": ...}` with `op` one of `=`, `<>`, `<`, `<=`, `>`,
+/// `>=`, `like`, `ilike`, `in`, `not_in`, `is_null`, `is_not_null`.
+/// Values are appended to `params` and referenced as `$N`.
+pub fn build_where(filter: &Value, params: &mut Vec<Value>) -> Result<String, String> {
+    let Some(map) = filter.as_object().filter(|m| !m.is_empty()) else {
+        return Err("where must be a non-empty object; unfiltered writes are refused".to_string());
+    };
+    let mut terms = Vec::with_capacity(map.len());
+    for (col, cond) in map {
+        let col_sql = quote_ident(col);
+        let (op, value) = match cond {
+            Value::Object(spec) => (
+                spec.get("op")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| format!("where.{col}.op is required"))?,
+                spec.get("value").cloned().unwrap_or(Value::Null),
+            ),
+            Value::Null => ("is_null", Value::Null),
+            other => ("=", other.clone()),
+        };
+        let term = match op {
+            "=" | "<>" | "!=" | "<" | "<=" | ">" | ">=" | "like" | "ilike" => {
+                if value.is_null() {
+                    return Err(format!(
+                        "where.{col}: null value needs op is_null/is_not_null"
+                    ));
+                }
+                params.push(value);
+                format!("{col_sql} {op} ${}", params.len())
+            }
+            "in" | "not_in" => {
+                let items = value
+                    .as_array()
+                    .filter(|a| !a.is_empty())
+                    .ok_or_else(|| format!("where.{col}: {op} needs a non-empty array value"))?;
+                let placeholders: Vec<String> = items
+                    .iter()
+                    .map(|v| {
+                        params.push(v.clone());
+                        format!("${}", params.len())
+                    })
+                    .collect();
+                let keyword = if op == "in" { "in" } else { "not in" };
+                format!("{col_sql} {keyword} ({})", placeholders.join(", "))
+            }
+            "is_null" => format!("{col_sql} is null"),
+            "is_not_null" => format!("{col_sql} is not null"),
+            other => return Err(format!("where.{col}: unsupported op '{other}'")),
+        };
+        terms.push(term);
+    }
+    Ok(terms.join(" and "))
+}
+
+/// Rename row keys per `mapping` (source -> target), dropping unmapped keys.
+/// An empty mapping keeps rows as they are.
+pub fn rename_columns(rows: Vec<Value>, mapping: &HashMap<String, String>) -> Vec<Value> {
+    if mapping.is_empty() {
+        return rows;
+    }
+    rows.into_iter()
+        .map(|row| match row {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .filter_map(|(k, v)| mapping.get(&k).map(|to| (to.clone(), v)))
+                    .collect(),
+            ),
+            other => other,
+        })
+        .collect()
+}
+
+/// `COPY table (cols) FROM STDIN` statement plus its text-format payload.
+pub fn build_copy_in(table: &str, rows: &[Value]) -> Result<(String, Vec<u8>), String> {
+    let table_sql = qualified_name(table)?;
+    let columns = row_columns(rows)?;
+    let sql = format!(
+        "copy {table_sql} ({}) from stdin",
+        quote_ident_list(&columns)
+    );
+    let mut data: Vec<u8> = vec![];
+    for row in rows {
+        let map = row.as_object().ok_or("rows must be objects")?;
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|c| copy_text_field(map.get(c).unwrap_or(&Value::Null)))
+            .collect();
+        data.extend_from_slice(fields.join("\t").as_bytes());
+        data.push(b'\n');
+    }
+    Ok((sql, data))
+}
+
+/// Read back, as `jsonb`, the columns `build_copy_in` writes for `rows`
+/// from the rows the current transaction inserted into `table`
+/// (PostgreSQL 13 or later).
+pub fn build_copy_readback(table: &str, rows: &[Value]) -> Result<String, String> {
+    let table_sql = qualified_name(table)?;
+    let columns = quote_ident_list(&row_columns(rows)?);
+    Ok(format!(
+        "select to_jsonb(r) from (select {columns} from {table_sql} \
+         where xmin = pg_current_xact_id()::xid) r"
+    ))
+}
+
+fn copy_text_field(v: &Value) -> String {
+    let raw = match v {
+        Value::Null => return "\\N".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let mut out = String::with_capacity(raw.len());
+    for ch in raw.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_sqlgen.rs"]
+mod tests;
@@ -0,0 +1,472 @@
+//! SQLite side sessions (`sqlite` feature).
+//!
+//! A session with `sqlite_path` runs its statements on that local file
+//! instead of PostgreSQL, through the same request handling, limits and
+//! output formats. [`SqliteSessions`] wraps the configured executor and
+//! routes by session; every other session passes straight through.
+//!
+//! Each statement runs in its own transaction. `read_only` rejects
+//! statements SQLite does not report as read-only, `statement_timeout_ms`
+//! interrupts the statement and `lock_timeout_ms` is the busy timeout. With
+//! `require_approval`, schema changes and writes over the threshold are
+//! rolled back like on PostgreSQL. `defer_constraints` defers foreign key
+//! checks to commit. Snapshots, workspaces, DO blocks, `COPY`,
+//! `replication_role`, `context`, prepared transactions and `describe`
+//! are PostgreSQL-only.
+
+use crate::db::{
+    send_pages, BlockRun, CopyReadback, DbExecutor, ExecError, ExecOutcome, PoolReport, PoolState,
+    ScriptRun,
+};
+use crate::redact::{self, ColumnOrigins};
+use crate::types::{ColumnInfo, ResolvedOptions, SessionConfig};
+use async_trait::async_trait;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{Connection, ErrorCode, InterruptHandle};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+struct LocalDb {
+    conn: Mutex<Connection>,
+    interrupt: InterruptHandle,
+}
+
+pub struct SqliteSessions {
+    inner: Arc<dyn DbExecutor>,
+    /// Open databases by path.
+    dbs: tokio::sync::Mutex<HashMap<String, Arc<LocalDb>>>,
+}
+
+impl SqliteSessions {
+    pub fn new(inner: Arc<dyn DbExecutor>) -> Self {
+        Self {
+            inner,
+            dbs: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn open(&self, path: &str) -> Result<Arc<LocalDb>, ExecError> {
+        let mut dbs = self.dbs.lock().await;
+        if let Some(db) = dbs.get(path) {
+            return Ok(db.clone());
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| ExecError::Connect(format!("cannot open sqlite database {path}: {e}")))?;
+        let db = Arc::new(LocalDb {
+            interrupt: conn.get_interrupt_handle(),
+            conn: Mutex::new(conn),
+        });
+        dbs.insert(path.to_string(), db.clone());
+        Ok(db)
+    }
+
+    async fn run(
+        &self,
+        path: &str,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        let db = self.open(path).await?;
+        let task = {
+            let db = db.clone();
+            let sql = sql.to_string();
+            let params = params.to_vec();
+            let opts = opts.clone();
+            tokio::task::spawn_blocking(move || {
+                let conn = db.conn.lock().map_err(|_| {
+                    ExecError::Internal("sqlite connection lock poisoned".to_string())
+                })?;
+                run_statement(&conn, &sql, &params, &opts)
+            })
+        };
+        let joined = if opts.statement_timeout_ms == 0 {
+            task.await
+        } else {
+            match tokio::time::timeout(Duration::from_millis(opts.statement_timeout_ms), task).await
+            {
+                Ok(joined) => joined,
+                Err(_) => {
+                    db.interrupt.interrupt();
+                    return Err(sql_error(
+                        "57014",
+                        "canceling statement due to statement timeout".to_string(),
+                    ));
+                }
+            }
+        };
+        joined.map_err(|e| ExecError::Internal(format!("sqlite task failed: {e}")))?
+    }
+}
+
+fn postgres_only(what: &str) -> ExecError {
+    ExecError::Internal(format!("{what} are not supported on sqlite sessions"))
+}
+
+#[async_trait]
+impl DbExecutor for SqliteSessions {
+    async fn execute(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        match &session_cfg.sqlite_path {
+            Some(path) => self.run(path, sql, params, opts).await,
+            None => {
+                self.inner
+                    .execute(session_name, session_cfg, sql, params, opts)
+                    .await
+            }
+        }
+    }
+
+    async fn execute_pages(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+        page_rows: usize,
+        pages: mpsc::Sender<Vec<Value>>,
+    ) -> Result<(), ExecError> {
+        match &session_cfg.sqlite_path {
+            Some(path) => {
+                send_pages(self.run(path, sql, params, opts).await?, page_rows, &pages).await
+            }
+            None => {
+                self.inner
+                    .execute_pages(
+                        session_name,
+                        session_cfg,
+                        sql,
+                        params,
+                        opts,
+                        page_rows,
+                        pages,
+                    )
+                    .await
+            }
+        }
+    }
+
+    async fn execute_block(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        body: &str,
+        vars: &[(String, String)],
+        opts: &ResolvedOptions,
+    ) -> BlockRun {
+        if session_cfg.sqlite_path.is_some() {
+            return BlockRun::failed(postgres_only("DO blocks"));
+        }
+        self.inner
+            .execute_block(session_name, session_cfg, body, vars, opts)
+            .await
+    }
+
+    async fn export_snapshot(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<String, ExecError> {
+        if session_cfg.sqlite_path.is_some() {
+            return Err(postgres_only("snapshots"));
+        }
+        self.inner.export_snapshot(session_name, session_cfg).await
+    }
+
+    async fn release_snapshot(&self, session_name: &str, snapshot: &str) -> bool {
+        self.inner.release_snapshot(session_name, snapshot).await
+    }
+
+    async fn open_workspace(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        workspace: &str,
+    ) -> Result<(), ExecError> {
+        if session_cfg.sqlite_path.is_some() {
+            return Err(postgres_only("workspaces"));
+        }
+        self.inner
+            .open_workspace(session_name, session_cfg, workspace)
+            .await
+    }
+
+    async fn close_workspace(&self, workspace: &str) -> bool {
+        self.inner.close_workspace(workspace).await
+    }
+
+    async fn reconnect_workspace(&self, workspace: &str) -> Result<bool, ExecError> {
+        self.inner.reconnect_workspace(workspace).await
+    }
+
+    fn schema_generation(&self) -> u64 {
+        self.inner.schema_generation()
+    }
+
+    async fn execute_script(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        statements: &[String],
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> ScriptRun {
+        if session_cfg.sqlite_path.is_some() {
+            return ScriptRun::failed(postgres_only("scripts in one transaction"));
+        }
+        self.inner
+            .execute_script(session_name, session_cfg, statements, params, opts)
+            .await
+    }
+
+    async fn describe(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        opts: &ResolvedOptions,
+    ) -> Result<(Vec<String>, Vec<ColumnInfo>), ExecError> {
+        if session_cfg.sqlite_path.is_some() {
+            return Err(postgres_only("statement descriptions"));
+        }
+        self.inner
+            .describe(session_name, session_cfg, sql, opts)
+            .await
+    }
+
+    async fn copy_in(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        if session_cfg.sqlite_path.is_some() {
+            return Err(postgres_only("COPY statements"));
+        }
+        self.inner
+            .copy_in(session_name, session_cfg, sql, data, opts)
+            .await
+    }
+
+    async fn copy_in_verified(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        readback: &CopyReadback<'_>,
+        opts: &ResolvedOptions,
+    ) -> Result<(u64, Vec<Value>, bool), ExecError> {
+        if session_cfg.sqlite_path.is_some() {
+            return Err(postgres_only("COPY statements"));
+        }
+        self.inner
+            .copy_in_verified(session_name, session_cfg, sql, data, readback, opts)
+            .await
+    }
+
+    async fn connect(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+    ) -> Result<(), ExecError> {
+        match &session_cfg.sqlite_path {
+            Some(path) => self.open(path).await.map(|_| ()),
+            None => self.inner.connect(session_name, session_cfg).await,
+        }
+    }
+
+    async fn maintain_pools(&self, idle_timeout: Option<Duration>) -> Vec<PoolReport> {
+        self.inner.maintain_pools(idle_timeout).await
+    }
+
+    async fn pool_states(&self) -> Vec<PoolState> {
+        self.inner.pool_states().await
+    }
+}
+
+fn run_statement(
+    conn: &Connection,
+    sql: &str,
+    params: &[Value],
+    opts: &ResolvedOptions,
+) -> Result<ExecOutcome, ExecError> {
+    conn.busy_timeout(Duration::from_millis(opts.lock_timeout_ms))
+        .map_err(map_sqlite_error)?;
+    let schema_before = schema_version(conn)?;
+    if opts.replication_role.is_some() {
+        return Err(postgres_only("replication roles"));
+    }
+    if !opts.context.is_empty() {
+        return Err(postgres_only("row security contexts"));
+    }
+    if opts.prepare_transaction.is_some() {
+        return Err(postgres_only("prepared transactions"));
+    }
+    let tx = conn.unchecked_transaction().map_err(map_sqlite_error)?;
+    if opts.defer_constraints {
+        // Reset by SQLite when the transaction ends.
+        conn.pragma_update(None, "defer_foreign_keys", true)
+            .map_err(map_sqlite_error)?;
+    }
+    let mut stmt = conn.prepare(sql).map_err(map_sqlite_error)?;
+    if stmt.parameter_count() != params.len() {
+        return Err(ExecError::InvalidParams(format!(
+            "statement expects {} params, got {}",
+            stmt.parameter_count(),
+            params.len()
+        )));
+    }
+    let writes = !stmt.readonly();
+    if opts.read_only && writes {
+        return Err(sql_error(
+            "25006",
+            "cannot execute a write statement in a read-only query".to_string(),
+        ));
+    }
+    let bind: Vec<SqlValue> = params.iter().map(bind_value).collect();
+    let params = rusqlite::params_from_iter(bind.iter());
+
+    let outcome = if stmt.column_count() > 0 {
+        let names: Vec<String> = stmt
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let mut rows = stmt.query(params).map_err(map_sqlite_error)?;
+        let mut json_rows = Vec::new();
+        while let Some(row) = rows.next().map_err(map_sqlite_error)? {
+            let mut obj = Map::new();
+            for (i, name) in names.iter().enumerate() {
+                let value = row.get_ref(i).map_err(map_sqlite_error)?;
+                obj.insert(name.clone(), column_value(value));
+            }
+            json_rows.push(Value::Object(obj));
+        }
+        ExecOutcome::Rows(json_rows)
+    } else {
+        let affected = stmt.execute(params).map_err(map_sqlite_error)?;
+        ExecOutcome::Command { affected }
+    };
+    drop(stmt);
+
+    if let Some(threshold) = opts.approval_row_threshold.filter(|_| writes) {
+        if schema_version(conn)? != schema_before {
+            return Err(ExecError::ApprovalRequired(
+                "statement changes the sqlite schema".to_string(),
+            ));
+        }
+        let affected = match &outcome {
+            ExecOutcome::Rows(rows) => rows.len(),
+            ExecOutcome::Command { affected } => *affected,
+        };
+        if affected > threshold {
+            return Err(ExecError::ApprovalRequired(format!(
+                "statement affects {affected} rows, over approval_row_threshold {threshold}"
+            )));
+        }
+    }
+    tx.commit().map_err(map_sqlite_error)?;
+
+    Ok(match outcome {
+        ExecOutcome::Rows(mut rows) => {
+            redact::redact_rows(
+                &mut rows,
+                &ColumnOrigins::new(),
+                &opts.redact,
+                opts.redact_salt_secret.as_deref(),
+            );
+            ExecOutcome::Rows(rows)
+        }
+        command => command,
+    })
+}
+
+fn schema_version(conn: &Connection) -> Result<i64, ExecError> {
+    conn.query_row("PRAGMA schema_version", [], |row| row.get(0))
+        .map_err(map_sqlite_error)
+}
+
+fn bind_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+/// Blobs are rendered like PostgreSQL `bytea` (`\x` + hex).
+fn column_value(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => {
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            Value::String(format!("\\x{hex}"))
+        }
+    }
+}
+
+fn sql_error(sqlstate: &str, message: String) -> ExecError {
+    ExecError::Sql {
+        sqlstate: sqlstate.to_string(),
+        message,
+        detail: None,
+        hint: None,
+        position: None,
+    }
+}
+
+/// SQLite has no SQLSTATE; result codes map onto the nearest class.
+fn map_sqlite_error(err: rusqlite::Error) -> ExecError {
+    let (failure, message, position) = match err {
+        rusqlite::Error::SqliteFailure(failure, message) => {
+            let message = message.unwrap_or_else(|| failure.to_string());
+            (failure, message, None)
+        }
+        rusqlite::Error::SqlInputError {
+            error, msg, offset, ..
+        } => {
+            let position = (offset >= 0).then(|| (offset + 1).to_string());
+            (error, msg, position)
+        }
+        other => return ExecError::Internal(other.to_string()),
+    };
+    let sqlstate = match failure.code {
+        ErrorCode::ConstraintViolation => "23000",
+        ErrorCode::ReadOnly => "25006",
+        ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => "55P03",
+        ErrorCode::OperationInterrupted => "57014",
+        _ => "42000",
+    };
+    ExecError::Sql {
+        sqlstate: sqlstate.to_string(),
+        message,
+        detail: Some(format!("sqlite result code {}", failure.extended_code)),
+        hint: None,
+        position,
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_sqlite.rs"]
+mod tests;
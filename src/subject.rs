@@ -0,0 +1,159 @@
+//! Data subject lookups for `psql_find_subject_data`.
+//!
+//! The search starts from the columns matching `subject_columns` patterns
+//! (such as `users.email` or `*.customer_email`), looking for rows whose
+//! value equals the one given. From each table with a hit it follows the
+//! foreign keys that reference it, so an order pointing at a matched user
+//! is found too, to a bounded depth. Each table is visited once per chain,
+//! which keeps self-references and cycles finite.
+
+use crate::er::{Edge, Node};
+use crate::redact::glob_match;
+use crate::schema_summary::Column;
+use crate::sqlgen::quote_ident;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Foreign key hops followed from a direct hit by default.
+pub const DEFAULT_DEPTH: usize = 2;
+
+/// Deepest chain a caller can ask for.
+pub const MAX_DEPTH: usize = 4;
+
+/// Primary keys listed per location.
+pub const MAX_KEYS: usize = 10;
+
+/// Row sets searched at most per lookup, direct hits and chains together.
+pub const MAX_STEPS: usize = 200;
+
+/// Rows of one table tied to the subject: directly through a subject
+/// column, or through a chain of foreign keys from such a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Bare table name within the searched schema.
+    pub table: String,
+    /// Condition on the table's own columns selecting the rows; `$1` is the
+    /// value searched for.
+    pub condition: String,
+    /// How the rows were reached, starting from the subject column.
+    pub via: Vec<String>,
+    /// Tables on the chain so far, ending with `table`.
+    pub chain: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Location {
+    pub table: String,
+    pub via: Vec<String>,
+    pub depth: usize,
+    pub rows: i64,
+    /// Primary keys of up to [`MAX_KEYS`] of the rows, each as an array;
+    /// absent for tables without a primary key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<Value>>,
+}
+
+/// Whether `pattern` (`column`, `table.column` or `schema.table.column`,
+/// globbed as for redaction) names `column` of `schema.table`.
+pub fn pattern_matches(pattern: &str, schema: &str, table: &str, column: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('.').collect();
+    match parts.as_slice() {
+        [c] => glob_match(c, column),
+        [t, c] => glob_match(t, table) && glob_match(c, column),
+        [s, t, c] => glob_match(s, schema) && glob_match(t, table) && glob_match(c, column),
+        _ => false,
+    }
+}
+
+/// Direct hits: one step per column of `schema` matching a pattern. The
+/// value is cast to the column's type so an index on it can be used.
+pub fn direct_steps(schema: &str, columns: &[Column], patterns: &[String]) -> Vec<Step> {
+    columns
+        .iter()
+        .filter(|c| {
+            patterns
+                .iter()
+                .any(|p| pattern_matches(p, schema, &c.table, &c.name))
+        })
+        .map(|c| Step {
+            table: c.table.clone(),
+            condition: format!("{} = $1::text::{}", quote_ident(&c.name), c.data_type),
+            via: vec![format!("{}.{}", c.table, c.name)],
+            chain: vec![c.table.clone()],
+        })
+        .collect()
+}
+
+/// Steps one foreign key further from `step`: rows of each table in the
+/// schema that reference a row `step` selects, unless that table is
+/// already on the chain.
+pub fn referencing_steps(schema: &str, step: &Step, nodes: &[Node], edges: &[Edge]) -> Vec<Step> {
+    edges
+        .iter()
+        .filter(|e| e.to_table == step.table && !step.chain.contains(&e.from_table))
+        .filter(|e| nodes.iter().any(|n| n.table == e.from_table))
+        .map(|e| {
+            let list = |columns: &[String]| {
+                columns
+                    .iter()
+                    .map(|c| quote_ident(c))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let mut via = step.via.clone();
+            via.push(format!(
+                "{}.{} -> {}.{}",
+                e.from_table,
+                e.from_columns.join(","),
+                e.to_table,
+                e.to_columns.join(",")
+            ));
+            let mut chain = step.chain.clone();
+            chain.push(e.from_table.clone());
+            Step {
+                table: e.from_table.clone(),
+                chain,
+                condition: format!(
+                    "({}) in (select {} from {} where {})",
+                    list(&e.from_columns),
+                    list(&e.to_columns),
+                    table_ref(schema, &step.table),
+                    step.condition
+                ),
+                via,
+            }
+        })
+        .collect()
+}
+
+/// Row count and up to [`MAX_KEYS`] primary keys of the rows `step`
+/// selects, as one row with `rows` and `keys`.
+pub fn count_sql(schema: &str, step: &Step, primary_key: &[String]) -> String {
+    let table = table_ref(schema, &step.table);
+    let keys = if primary_key.is_empty() {
+        "null::json".to_string()
+    } else {
+        let columns = primary_key
+            .iter()
+            .map(|c| quote_ident(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "(select coalesce(json_agg(json_build_array({columns})), '[]'::json) \
+             from (select {columns} from {table} where {} limit {MAX_KEYS}) k)",
+            step.condition
+        )
+    };
+    format!(
+        "select (select count(*) from {table} where {}) as rows, {keys} as keys",
+        step.condition
+    )
+}
+
+fn table_ref(schema: &str, table: &str) -> String {
+    format!("{}.{}", quote_ident(schema), quote_ident(table))
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_subject.rs"]
+mod tests;
@@ -0,0 +1,87 @@
+//! Summaries of results too large to return inline.
+//!
+//! A query with `summarize` that would fail with `result_too_large` instead
+//! gets the row count, per-column statistics and its first and last rows,
+//! which is usually enough for an agent to decide what to ask for next.
+
+use crate::types::{ColumnInfo, ColumnStats};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+
+/// Distinct values remembered per column before counting stops.
+const DISTINCT_CAP: usize = 10_000;
+
+/// The first `n` rows and the last `n` after them.
+pub fn head_tail(rows: &[Value], n: usize) -> (Vec<Value>, Vec<Value>) {
+    let head = rows[..n.min(rows.len())].to_vec();
+    let tail = rows[rows.len().saturating_sub(n).max(head.len())..].to_vec();
+    (head, tail)
+}
+
+/// Statistics of each column over all `rows`.
+pub fn column_stats(rows: &[Value], columns: &[ColumnInfo]) -> BTreeMap<String, ColumnStats> {
+    columns
+        .iter()
+        .map(|column| {
+            let values = rows.iter().map(|row| row.get(&column.name));
+            (column.name.clone(), stats(values))
+        })
+        .collect()
+}
+
+fn stats<'a>(values: impl Iterator<Item = Option<&'a Value>>) -> ColumnStats {
+    let mut nulls = 0;
+    let mut seen = HashSet::new();
+    let mut capped = false;
+    // `None` once two values could not be compared.
+    let mut range: Option<Option<(&Value, &Value)>> = Some(None);
+    for value in values {
+        let Some(value) = value.filter(|v| !v.is_null()) else {
+            nulls += 1;
+            continue;
+        };
+        if seen.len() < DISTINCT_CAP {
+            seen.insert(value.to_string());
+        } else if !capped {
+            capped = !seen.contains(&value.to_string());
+        }
+        range = match range {
+            Some(None) => compare(value, value).map(|_| Some((value, value))),
+            Some(Some((min, max))) => match (compare(value, min), compare(value, max)) {
+                (Some(lo), Some(hi)) => Some(Some((
+                    if lo == Ordering::Less { value } else { min },
+                    if hi == Ordering::Greater { value } else { max },
+                ))),
+                _ => None,
+            },
+            None => None,
+        };
+    }
+    let (min, max) = match range.flatten() {
+        Some((min, max)) => (Some(min.clone()), Some(max.clone())),
+        None => (None, None),
+    };
+    ColumnStats {
+        min,
+        max,
+        nulls,
+        distinct: seen.len(),
+        distinct_capped: capped,
+    }
+}
+
+/// Order numbers, strings (timestamps and dates sort as text) and booleans
+/// among their own kind; anything else does not compare.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_summary.rs"]
+mod tests;
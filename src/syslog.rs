@@ -0,0 +1,148 @@
+//! Shipping `log` events to syslog or journald, for `log_system`.
+//!
+//! Both are local datagram sockets, so nothing but the running daemon is
+//! needed. syslog gets one `<PRI>afpsql[pid]: @cee:{...}` line per event,
+//! the JSON behind the CEE cookie that rsyslog and syslog-ng parse into
+//! fields. journald gets its native protocol: the event's fields as
+//! `AFPSQL_*` journal fields besides `MESSAGE`, `PRIORITY` and the full
+//! event in `AFPSQL_JSON`, so `journalctl AFPSQL_EVENT=query.slow` works.
+
+// Without the feature only the formatting is compiled, for the tests.
+#![cfg_attr(not(all(feature = "syslog", unix)), allow(dead_code))]
+
+use crate::types::LogSystem;
+use serde_json::Value;
+
+/// Whether this build includes the `syslog` feature.
+pub const AVAILABLE: bool = cfg!(all(feature = "syslog", unix));
+
+pub const SYSLOG_SOCKET: &str = "/dev/log";
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+const IDENTIFIER: &str = "afpsql";
+
+/// syslog facility `user`.
+const FACILITY: u8 = 1;
+
+/// syslog severity of `event`: `err` for failures, `warning` for warnings
+/// and slow or lagging work, `info` otherwise.
+pub fn severity(event: &str) -> u8 {
+    if event.ends_with("error") || event.ends_with("failed") {
+        3
+    } else if ["warning", "slow", "lagging", "skipped"]
+        .iter()
+        .any(|suffix| event.ends_with(suffix))
+    {
+        4
+    } else {
+        6
+    }
+}
+
+fn field<'a>(record: &'a Value, name: &str) -> Option<&'a str> {
+    record.get(name).and_then(Value::as_str)
+}
+
+/// One line for the syslog socket.
+pub fn syslog_message(record: &Value) -> Vec<u8> {
+    let event = field(record, "event").unwrap_or_default();
+    let pri = FACILITY * 8 + severity(event);
+    format!("<{pri}>{IDENTIFIER}[{}]: @cee:{record}", std::process::id()).into_bytes()
+}
+
+/// One journald native-protocol datagram.
+pub fn journald_message(record: &Value) -> Vec<u8> {
+    let event = field(record, "event").unwrap_or_default();
+    let mut message = event.to_string();
+    if let Some(id) = field(record, "request_id") {
+        message.push_str(&format!(" {id}"));
+    }
+    if let Some(warning) = field(record, "warning") {
+        message.push_str(&format!(": {warning}"));
+    }
+    let mut out = vec![];
+    push_field(&mut out, "MESSAGE", &message);
+    push_field(&mut out, "PRIORITY", &severity(event).to_string());
+    push_field(&mut out, "SYSLOG_IDENTIFIER", IDENTIFIER);
+    push_field(&mut out, "SYSLOG_PID", &std::process::id().to_string());
+    for (key, name) in [
+        ("AFPSQL_EVENT", "event"),
+        ("AFPSQL_REQUEST_ID", "request_id"),
+        ("AFPSQL_SESSION", "session"),
+        ("AFPSQL_ERROR_CODE", "error_code"),
+        ("AFPSQL_COMMAND_TAG", "command_tag"),
+        ("AFPSQL_WARNING", "warning"),
+    ] {
+        if let Some(value) = field(record, name) {
+            push_field(&mut out, key, value);
+        }
+    }
+    if let Some(ms) = record.pointer("/trace/duration_ms").and_then(Value::as_u64) {
+        push_field(&mut out, "AFPSQL_DURATION_MS", &ms.to_string());
+    }
+    push_field(&mut out, "AFPSQL_JSON", &record.to_string());
+    out
+}
+
+/// `KEY=value\n`, or for a value with a newline `KEY\n`, its length as
+/// 64-bit little endian, the value and `\n`.
+fn push_field(out: &mut Vec<u8>, key: &str, value: &str) {
+    out.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        out.push(b'\n');
+        out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        out.push(b'=');
+    }
+    out.extend_from_slice(value.as_bytes());
+    out.push(b'\n');
+}
+
+/// A connected syslog or journald socket.
+#[cfg(all(feature = "syslog", unix))]
+pub struct SystemLogger {
+    socket: std::os::unix::net::UnixDatagram,
+    target: LogSystem,
+}
+
+#[cfg(all(feature = "syslog", unix))]
+impl SystemLogger {
+    pub fn connect(target: LogSystem) -> std::io::Result<Self> {
+        let path = match target {
+            LogSystem::Journald => JOURNALD_SOCKET,
+            _ => SYSLOG_SOCKET,
+        };
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { socket, target })
+    }
+
+    pub fn send(&self, record: &Value) -> std::io::Result<()> {
+        let message = match self.target {
+            LogSystem::Journald => journald_message(record),
+            _ => syslog_message(record),
+        };
+        self.socket.send(&message).map(|_| ())
+    }
+}
+
+#[cfg(not(all(feature = "syslog", unix)))]
+pub struct SystemLogger;
+
+#[cfg(not(all(feature = "syslog", unix)))]
+impl SystemLogger {
+    pub fn connect(_target: LogSystem) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "log_system needs a build with --features syslog",
+        ))
+    }
+
+    pub fn send(&self, _record: &Value) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_syslog.rs"]
+mod tests;
@@ -0,0 +1,209 @@
+//! Variable substitution in SQL text, for the parts of a statement that
+//! cannot be bind parameters (table names, sort columns, `LIMIT` in DDL ...).
+//!
+//! `psql` forms `:name`, `:'name'` and `:"name"` and template forms
+//! `{{name}}`, `{{name|literal}}` and `{{name|ident}}` insert the value as
+//! text, as a quoted literal, or as a quoted identifier. Nothing inside
+//! strings, quoted identifiers, dollar-quoted bodies or comments is touched.
+//!
+//! `%{name}` placeholders take only identifiers, from a separate `idents`
+//! map whose values are checked before anything is substituted.
+
+use crate::script::{comment_end, quoted_end};
+use crate::sqlgen::quote_ident;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy)]
+enum Quoting {
+    Raw,
+    Literal,
+    Ident,
+}
+
+/// `sql` with `vars` substituted. An undefined `{{name}}` is an error; an
+/// undefined `:name` is left as written, as `psql` does.
+pub fn expand(sql: &str, vars: &BTreeMap<String, String>) -> Result<String, String> {
+    rewrite(sql, |chars, i, out| match (chars[i], chars.get(i + 1)) {
+        (':', Some(':')) => {
+            out.push_str("::");
+            Ok(Some(i + 2))
+        }
+        (':', _) => match colon_var(chars, i) {
+            Some((name, quoting, end)) if vars.contains_key(&name) => {
+                out.push_str(&render(&name, &vars[&name], quoting)?);
+                Ok(Some(end))
+            }
+            _ => Ok(None),
+        },
+        ('{', Some('{')) => {
+            let (name, quoting, end) = brace_var(chars, i)?;
+            let value = vars
+                .get(&name)
+                .ok_or_else(|| format!("undefined variable {{{{{name}}}}}"))?;
+            out.push_str(&render(&name, value, quoting)?);
+            Ok(Some(end))
+        }
+        _ => Ok(None),
+    })
+}
+
+/// `sql` with each `%{name}` replaced by `idents[name]` as a quoted
+/// identifier. Every value must pass [`check_ident`]; an undefined name is
+/// an error.
+pub fn expand_idents(sql: &str, idents: &BTreeMap<String, String>) -> Result<String, String> {
+    for (name, value) in idents {
+        check_ident(value).map_err(|e| format!("idents.{name}: {e}"))?;
+    }
+    rewrite(sql, |chars, i, out| {
+        if chars[i] != '%' || chars.get(i + 1) != Some(&'{') {
+            return Ok(None);
+        }
+        let close = chars[i + 2..]
+            .iter()
+            .position(|&c| c == '}')
+            .map(|p| i + 2 + p)
+            .ok_or("unclosed %{ in sql")?;
+        let name: String = chars[i + 2..close].iter().collect();
+        let value = idents
+            .get(name.trim())
+            .ok_or_else(|| format!("undefined identifier %{{{name}}}"))?;
+        out.push_str(&quote_ident(value));
+        Ok(Some(close + 1))
+    })
+}
+
+/// `NAMEDATALEN - 1` in a default PostgreSQL build.
+const MAX_IDENT_BYTES: usize = 63;
+
+/// An identifier value is one name: not empty, no NUL, and at most 63 bytes
+/// so PostgreSQL does not silently truncate it to another name.
+pub fn check_ident(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        Err("identifier is empty".to_string())
+    } else if value.contains('\0') {
+        Err("identifier contains NUL".to_string())
+    } else if value.len() > MAX_IDENT_BYTES {
+        Err(format!(
+            "identifier is {} bytes; the limit is {MAX_IDENT_BYTES}",
+            value.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Copy `sql`, letting `at` replace text at each index outside strings,
+/// quoted identifiers, dollar-quoted bodies and comments. `at` returns the
+/// index after what it wrote, or `None` to copy the char as is.
+fn rewrite(
+    sql: &str,
+    mut at: impl FnMut(&[char], usize, &mut String) -> Result<Option<usize>, String>,
+) -> Result<String, String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut out = String::with_capacity(sql.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(end) = comment_end(&chars, i).or_else(|| quoted_end(&chars, i)) {
+            out.extend(&chars[i..end.min(chars.len())]);
+            i = end;
+            continue;
+        }
+        match at(&chars, i, &mut out)? {
+            Some(end) => i = end,
+            None => {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Variable names follow `psql`: a letter or `_`, then letters, digits, `_`.
+pub fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The `:name`, `:'name'` or `:"name"` at `i`, with the index after it.
+fn colon_var(chars: &[char], i: usize) -> Option<(String, Quoting, usize)> {
+    let (quoting, begin) = match chars.get(i + 1) {
+        Some('\'') => (Quoting::Literal, i + 2),
+        Some('"') => (Quoting::Ident, i + 2),
+        _ => (Quoting::Raw, i + 1),
+    };
+    let len = chars[begin..]
+        .iter()
+        .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+        .unwrap_or(chars.len() - begin);
+    let name: String = chars[begin..begin + len].iter().collect();
+    if !valid_name(&name) {
+        return None;
+    }
+    let mut end = begin + len;
+    if let Quoting::Literal | Quoting::Ident = quoting {
+        if chars.get(end) != chars.get(i + 1) {
+            return None;
+        }
+        end += 1;
+    }
+    Some((name, quoting, end))
+}
+
+/// The `{{name}}` or `{{name|filter}}` at `i`, with the index after it.
+fn brace_var(chars: &[char], i: usize) -> Result<(String, Quoting, usize), String> {
+    let close = chars[i + 2..]
+        .windows(2)
+        .position(|w| w == ['}', '}'])
+        .map(|p| i + 2 + p)
+        .ok_or("unclosed {{ in sql")?;
+    let inner: String = chars[i + 2..close].iter().collect();
+    let (name, filter) = match inner.split_once('|') {
+        Some((name, filter)) => (name.trim(), Some(filter.trim())),
+        None => (inner.trim(), None),
+    };
+    let quoting = match filter {
+        None => Quoting::Raw,
+        Some("literal") => Quoting::Literal,
+        Some("ident") => Quoting::Ident,
+        Some(other) => {
+            return Err(format!(
+                "unknown filter '{other}' in {{{{{inner}}}}}; expected literal or ident"
+            ))
+        }
+    };
+    if !valid_name(name) {
+        return Err(format!("invalid variable name in {{{{{inner}}}}}"));
+    }
+    Ok((name.to_string(), quoting, close + 2))
+}
+
+fn render(name: &str, value: &str, quoting: Quoting) -> Result<String, String> {
+    Ok(match quoting {
+        Quoting::Raw => value.to_string(),
+        Quoting::Literal => quote_literal(value),
+        Quoting::Ident => {
+            check_ident(value).map_err(|e| format!("vars.{name}: {e}"))?;
+            quote_ident(value)
+        }
+    })
+}
+
+/// Quote as a string literal the way libpq's `PQescapeLiteral` does: with
+/// backslashes, an `E''` literal that reads the same whatever
+/// `standard_conforming_strings` says.
+fn quote_literal(value: &str) -> String {
+    let quoted = value.replace('\'', "''");
+    if value.contains('\\') {
+        format!(" E'{}'", quoted.replace('\\', "\\\\"))
+    } else {
+        format!("'{quoted}'")
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_template.rs"]
+mod tests;
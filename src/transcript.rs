@@ -0,0 +1,159 @@
+//! Ordered log of statements sent to the database during this run, exported
+//! by `transcript_export` as a `.sql` script.
+//!
+//! The SQL text is never rewritten: statements with params are replayed as
+//! `PREPARE` / `EXECUTE` so PostgreSQL resolves `$n` exactly as it did for
+//! the original request.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub session: String,
+    pub sql: String,
+    pub params: Vec<Value>,
+    /// SQLSTATE when the statement failed; failed statements are exported
+    /// commented out.
+    pub sqlstate: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Transcript {
+    statements: VecDeque<Statement>,
+    omitted: usize,
+}
+
+/// How `transcript_export` renders param values.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamStyle {
+    /// Literals in the `EXECUTE` argument list.
+    #[default]
+    Inline,
+    /// psql `\set` variables referenced as `:'p<statement>_<n>'`.
+    PsqlVars,
+}
+
+impl Transcript {
+    /// Append, dropping the oldest statements beyond `capacity`.
+    pub fn record(&mut self, statement: Statement, capacity: usize) {
+        self.statements.push_back(statement);
+        while self.statements.len() > capacity {
+            self.statements.pop_front();
+            self.omitted += 1;
+        }
+    }
+
+    pub fn omitted(&self) -> usize {
+        self.omitted
+    }
+
+    /// The script for every kept statement, or only those run on `session`.
+    pub fn render(&self, style: ParamStyle, session: Option<&str>) -> (String, usize) {
+        let mut out = String::new();
+        let _ = writeln!(out, "-- afpsql transcript");
+        if self.omitted > 0 {
+            let _ = writeln!(out, "-- {} earlier statements omitted", self.omitted);
+        }
+        let mut count = 0usize;
+        for (n, stmt) in self.statements.iter().enumerate() {
+            if session.is_some_and(|s| s != stmt.session) {
+                continue;
+            }
+            count += 1;
+            let seq = self.omitted + n + 1;
+            out.push('\n');
+            let body = render_statement(stmt, seq, style);
+            match &stmt.sqlstate {
+                None => {
+                    let _ = writeln!(out, "-- [{seq}] session={}", stmt.session);
+                    out.push_str(&body);
+                }
+                Some(state) => {
+                    let _ = writeln!(
+                        out,
+                        "-- [{seq}] session={} failed sqlstate={state}",
+                        stmt.session
+                    );
+                    for line in body.lines() {
+                        let _ = writeln!(out, "-- {line}");
+                    }
+                }
+            }
+        }
+        (out, count)
+    }
+}
+
+fn render_statement(stmt: &Statement, n: usize, style: ParamStyle) -> String {
+    let sql = stmt.sql.trim().trim_end_matches(';').trim_end();
+    // Keep the terminator out of a trailing `--` comment.
+    let sql = if sql.lines().last().is_some_and(|l| l.contains("--")) {
+        format!("{sql}\n")
+    } else {
+        sql.to_string()
+    };
+    if stmt.params.is_empty() {
+        return format!("{sql};\n");
+    }
+    let name = format!("afpsql_{n}");
+    let mut out = String::new();
+    let args: Vec<String> = match style {
+        ParamStyle::Inline => stmt.params.iter().map(sql_literal).collect(),
+        ParamStyle::PsqlVars => stmt
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, v)| match param_text(v) {
+                None => "NULL".to_string(),
+                Some(text) => {
+                    let var = format!("p{n}_{}", i + 1);
+                    let _ = writeln!(out, "\\set {var} '{}'", psql_escape(&text));
+                    format!(":'{var}'")
+                }
+            })
+            .collect(),
+    };
+    let _ = writeln!(out, "PREPARE {name} AS {sql};");
+    let _ = writeln!(out, "EXECUTE {name}({});", args.join(", "));
+    let _ = writeln!(out, "DEALLOCATE {name};");
+    out
+}
+
+/// Text form the executor binds for a value; `None` for SQL NULL.
+fn param_text(v: &Value) -> Option<String> {
+    match v {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+pub fn sql_literal(v: &Value) -> String {
+    match param_text(v) {
+        None => "NULL".to_string(),
+        Some(text) => format!("'{}'", text.replace('\'', "''")),
+    }
+}
+
+fn psql_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_transcript.rs"]
+mod tests;
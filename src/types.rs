@@ -1,6 +1,17 @@
+use crate::approval::PendingEntry;
+use crate::chaos::Chaos;
+use crate::db::PoolState;
+use crate::deadline::Deadline;
+use crate::history::HistoryEntry;
+use crate::memory::MemoryReservation;
+use crate::notify::Notify;
+use crate::operations::OperationEntry;
+use crate::profile::{ChannelStats, MemoryStats, ModuleTiming, RuntimeStats};
+use crate::schedule::{Cron, Sink};
+use crate::transcript::ParamStyle;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "code")]
@@ -16,17 +27,176 @@ pub enum Input {
         #[serde(default)]
         options: QueryOptions,
     },
+    #[serde(rename = "fanout")]
+    Fanout {
+        id: String,
+        sessions: Vec<String>,
+        sql: String,
+        #[serde(default)]
+        params: Vec<Value>,
+        #[serde(default)]
+        options: QueryOptions,
+    },
+    #[serde(rename = "transfer")]
+    Transfer(Box<TransferRequest>),
+    #[serde(rename = "import")]
+    Import(Box<ImportRequest>),
+    #[serde(rename = "archive")]
+    Archive(Box<ArchiveRequest>),
+    /// Pause a running `archive`, `import` or chunked write after its
+    /// current batch.
+    #[serde(rename = "pause")]
+    Pause { id: String },
+    /// Go on with a paused operation, or continue a saved one.
+    #[serde(rename = "resume")]
+    Resume { id: String },
+    /// Managed operations running or saved, and statements pending approval.
+    #[serde(rename = "operations_list")]
+    OperationsList { id: String },
     #[serde(rename = "config")]
-    Config(ConfigPatch),
+    Config(Box<ConfigPatch>),
     #[serde(rename = "cancel")]
     Cancel { id: String },
+    /// The consumer has processed the events of stream `id` up to `seq`.
+    #[serde(rename = "ack")]
+    Ack { id: String, seq: u64 },
+    #[serde(rename = "result_get")]
+    ResultGet {
+        id: String,
+        handle: String,
+        #[serde(default)]
+        offset: usize,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    #[serde(rename = "result_delete")]
+    ResultDelete { id: String, handle: String },
+    #[serde(rename = "approve")]
+    Approve {
+        id: String,
+        token: String,
+        /// Approver session; required when `approver_sessions` is configured.
+        #[serde(default)]
+        session: Option<String>,
+        /// Must match `--approver-secret` when one is configured.
+        #[serde(default)]
+        approver_secret: Option<String>,
+    },
+    #[serde(rename = "grant_elevated")]
+    GrantElevated {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        /// Role assumed with `SET LOCAL ROLE` for each statement.
+        #[serde(default)]
+        role: Option<String>,
+        /// Configured session whose connection is used instead.
+        #[serde(default)]
+        via_session: Option<String>,
+        duration_ms: u64,
+        reason: String,
+    },
+    #[serde(rename = "history_list")]
+    HistoryList {
+        id: String,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    #[serde(rename = "history_replay")]
+    HistoryReplay { id: String, seq: u64 },
+    #[serde(rename = "transcript_export")]
+    TranscriptExport {
+        id: String,
+        #[serde(default)]
+        param_style: ParamStyle,
+        #[serde(default)]
+        session: Option<String>,
+    },
+    #[serde(rename = "snapshot_begin")]
+    SnapshotBegin {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+    },
+    #[serde(rename = "snapshot_query")]
+    SnapshotQuery {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        snapshot: String,
+        sql: String,
+        #[serde(default)]
+        params: Vec<Value>,
+        #[serde(default)]
+        options: QueryOptions,
+    },
+    #[serde(rename = "snapshot_end")]
+    SnapshotEnd {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        snapshot: String,
+    },
+    #[serde(rename = "workspace_open")]
+    WorkspaceOpen {
+        id: String,
+        workspace: String,
+        #[serde(default)]
+        session: Option<String>,
+    },
+    #[serde(rename = "workspace_close")]
+    WorkspaceClose { id: String, workspace: String },
+    /// Parameter and result column types of `sql`, without running it.
+    #[serde(rename = "describe")]
+    Describe {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        sql: String,
+        #[serde(default)]
+        options: QueryOptions,
+    },
+    /// List transactions left by `prepare_transaction`.
+    #[serde(rename = "prepared_list")]
+    PreparedList {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        /// Only those prepared at least this long ago.
+        #[serde(default)]
+        older_than_ms: Option<u64>,
+    },
+    #[serde(rename = "commit_prepared")]
+    CommitPrepared {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        gid: String,
+    },
+    #[serde(rename = "rollback_prepared")]
+    RollbackPrepared {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+        gid: String,
+    },
+    /// Drop cached results and catalog lookups, of one session or all.
+    #[serde(rename = "invalidate_cache")]
+    InvalidateCache {
+        id: String,
+        #[serde(default)]
+        session: Option<String>,
+    },
+    /// The process's own tasks, channels, pools, memory and timings.
+    #[serde(rename = "debug_stats")]
+    DebugStats { id: String },
     #[serde(rename = "ping")]
     Ping,
     #[serde(rename = "close")]
     Close,
 }
 
-#[derive(Debug, Deserialize, Default, Clone)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 #[allow(dead_code)]
 pub struct QueryOptions {
     #[serde(default)]
@@ -35,9 +205,163 @@ pub struct QueryOptions {
     pub batch_bytes: Option<usize>,
     pub statement_timeout_ms: Option<u64>,
     pub lock_timeout_ms: Option<u64>,
+    /// Name from `timeout_profiles`; `statement_timeout_ms` may then only
+    /// lower the profile's timeout, up to its ceiling.
+    pub timeout_profile: Option<String>,
+    /// Absolute deadline (epoch ms or RFC 3339); caps `statement_timeout_ms`
+    /// at the time left.
+    pub deadline: Option<Deadline>,
     pub read_only: Option<bool>,
+    /// Run with `SET CONSTRAINTS ALL DEFERRED`, so deferrable constraints
+    /// are checked at commit.
+    #[serde(default)]
+    pub defer_constraints: bool,
+    /// `session_replication_role` for the statement's transaction; other
+    /// than `origin` it needs `allow_replication_role`.
+    pub replication_role: Option<ReplicationRole>,
     pub inline_max_rows: Option<usize>,
     pub inline_max_bytes: Option<usize>,
+    /// Reply to a result over the inline limits with `result_summary`, with
+    /// this many first and last rows, instead of `result_too_large`.
+    pub summarize: Option<usize>,
+    /// Overrides the configured `default_limit`; `0` disables it.
+    pub default_limit: Option<usize>,
+    /// Compress streamed `result_rows` batches (needs the `compression` feature).
+    pub compress: Option<Compression>,
+    /// Save the rows to `results_dir` and return a handle instead of rows.
+    #[serde(default)]
+    pub store_result: bool,
+    /// Upload the rows to this `s3://` or `gs://` object instead of returning
+    /// them (needs the `object-store` feature).
+    pub export_uri: Option<String>,
+    /// With `export_uri`, and in `transfer` and `import`: checksum the rows
+    /// on both ends.
+    pub verify: Option<VerifyMode>,
+    /// Publish the rows to this `kafka://` topic or `nats://` subject
+    /// instead of returning them (needs the `kafka` or `nats` feature).
+    pub publish_uri: Option<String>,
+    /// With `publish_uri`: columns keying each message.
+    pub publish_key: Option<Vec<String>>,
+    /// With `publish_uri`: one message per row (default) or per batch.
+    pub publish_format: Option<PublishFormat>,
+    /// Announce the query's end to a webhook or command when it ran for at
+    /// least `after_ms`.
+    pub notify: Option<Notify>,
+    /// Also run the statement under `EXPLAIN ANALYZE`, read-only and rolled
+    /// back, for the server's planning and execution times in `trace`.
+    #[serde(default)]
+    pub server_timing: bool,
+    /// Set only when running a statement released by `approve`.
+    #[serde(skip)]
+    pub approved: bool,
+    /// Exported snapshot to import; set only by `snapshot_query`.
+    #[serde(skip)]
+    pub snapshot: Option<String>,
+    /// Run outside a transaction block; set only by `psql_maintenance`.
+    #[serde(skip)]
+    pub autocommit: bool,
+    /// Tables whose rows a JSON result column holds, by column, so
+    /// table-qualified `redact` rules apply inside it; set only by
+    /// `psql_search`.
+    #[serde(skip)]
+    pub nested_tables: BTreeMap<String, Vec<String>>,
+    /// Run on the pinned connection of this open workspace.
+    pub workspace: Option<String>,
+    /// Serve rows cached by an identical query up to this long ago, and
+    /// cache this query's rows for as long.
+    pub cache_ttl_ms: Option<u64>,
+    /// Write the rows into a table on a SQLite session instead of
+    /// returning them.
+    pub materialize_to: Option<MaterializeTarget>,
+    /// On a re-run, reply with the rows added and removed since the last
+    /// run of the same query instead of all rows.
+    #[serde(default)]
+    pub diff_output: bool,
+    /// Columns identifying a row; streamed batches then carry each row's key.
+    pub key_columns: Option<Vec<String>>,
+    /// On a re-run, emit only rows that are new or changed for their
+    /// `key_columns` key.
+    #[serde(default)]
+    pub dedup: bool,
+    /// Resend the retained stream of this request id from this `seq` on
+    /// instead of running the statement.
+    pub resume_from: Option<u64>,
+    /// Stream at most this many events past the last `ack` of this request.
+    pub ack_window: Option<u64>,
+    /// Values for `:name` / `{{name}}` in the SQL text (see [`crate::template`]).
+    pub vars: Option<BTreeMap<String, String>>,
+    /// Identifiers for `%{name}` in the SQL text, quoted as identifiers.
+    pub idents: Option<BTreeMap<String, String>>,
+    /// Agent making the request, for annotations, audit records and
+    /// `agent_quotas`; defaults to the configured `agent_name`.
+    pub agent: Option<String>,
+    /// Custom settings such as `app.tenant_id` for row security policies,
+    /// set with `set_config` in the statement's transaction.
+    pub context: Option<BTreeMap<String, String>>,
+    /// End the statement's transaction with `PREPARE TRANSACTION` under
+    /// this id instead of committing it.
+    pub prepare_transaction: Option<String>,
+    /// Columns and row counts the result must have; a result that differs
+    /// is a `contract_violation`.
+    pub expect: Option<ResultContract>,
+}
+
+/// `expect` of a query.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ResultContract {
+    /// Columns the result must have, in any order unless `exact_columns`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<ExpectedColumn>>,
+    /// The result has exactly `columns`, in that order.
+    #[serde(default)]
+    pub exact_columns: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_rows: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExpectedColumn {
+    pub name: String,
+    /// Type as written in SQL (`integer`, `text[]`) or PostgreSQL's own
+    /// name (`int4`); any type when left out.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+}
+
+/// Scratch table a query's rows are written into; replaced if it exists.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaterializeTarget {
+    pub session: String,
+    pub table: String,
+}
+
+/// What one message published to `publish_uri` carries.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishFormat {
+    /// One row, as a JSON object.
+    #[default]
+    Row,
+    /// Up to `batch_rows` rows sharing a key, as a JSON array.
+    Batch,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+/// Rows of one batch as a compressed JSON array.
+#[derive(Debug, Serialize)]
+pub struct CompressedRows {
+    pub codec: Compression,
+    pub encoding: String,
+    pub raw_bytes: usize,
+    pub data: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -53,6 +377,215 @@ pub enum Output {
         columns: Vec<ColumnInfo>,
         rows: Vec<Value>,
         row_count: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        /// `true` when the rows came from the result cache.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cached: Option<bool>,
+        trace: Trace,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    /// Reply to a `summarize` query whose rows are over the inline limits.
+    #[serde(rename = "result_summary")]
+    ResultSummary {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        columns: Vec<ColumnInfo>,
+        row_count: usize,
+        column_stats: BTreeMap<String, ColumnStats>,
+        head: Vec<Value>,
+        tail: Vec<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        trace: Trace,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    /// Reply to a `diff_output` query that has a baseline.
+    #[serde(rename = "result_diff")]
+    ResultDiff {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        added: Vec<Value>,
+        removed: Vec<Value>,
+        /// Rows in the full result.
+        row_count: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        trace: Trace,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    #[serde(rename = "fanout_result")]
+    FanoutResult {
+        id: String,
+        /// One entry per requested session.
+        results: BTreeMap<String, FanoutEntry>,
+        ok_count: usize,
+        error_count: usize,
+        trace: Trace,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    /// Last event of a CLI script run with `--on-error`.
+    #[serde(rename = "script_result")]
+    ScriptResult {
+        on_error: OnError,
+        /// One entry per statement, in script order.
+        statements: Vec<ScriptStatement>,
+        ok_count: usize,
+        error_count: usize,
+        skipped_count: usize,
+        /// With `rollback_statement`: whether the script's transaction
+        /// committed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        committed: Option<bool>,
+        trace: Trace,
+    },
+    #[serde(rename = "transfer_result")]
+    TransferResult {
+        id: String,
+        source_session: String,
+        target_session: String,
+        table: String,
+        rows_read: usize,
+        rows_written: u64,
+        batches: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        verification: Option<Verification>,
+        trace: Trace,
+    },
+    #[serde(rename = "import_result")]
+    ImportResult {
+        id: String,
+        session: String,
+        table: String,
+        source: String,
+        /// Records read from the source, rejected ones included.
+        rows_read: usize,
+        rows_imported: u64,
+        rows_rejected: usize,
+        /// The first rejected rows, in source order.
+        rejected: Vec<RejectedRow>,
+        batches: usize,
+        /// Whether records were left unread after more than `max_errors`
+        /// rejections.
+        stopped: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        verification: Option<Verification>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        dead_letter: Option<DeadLetterSummary>,
+        trace: Trace,
+    },
+    #[serde(rename = "archive_result")]
+    ArchiveResult {
+        id: String,
+        session: String,
+        table: String,
+        archive_table: String,
+        rows_moved: u64,
+        batches: usize,
+        trace: Trace,
+    },
+    /// Reply to a `psql_update` or `psql_delete` with `chunked`.
+    #[serde(rename = "chunked_result")]
+    ChunkedResult {
+        id: String,
+        session: String,
+        table: String,
+        rows_affected: u64,
+        batches: usize,
+        trace: Trace,
+    },
+    /// A managed operation stopped between batches for `pause`.
+    #[serde(rename = "paused")]
+    Paused {
+        id: String,
+        session: String,
+        phase: String,
+        done: u64,
+    },
+    /// One run of a configured schedule; with a `sink`, this is the record
+    /// delivered there instead.
+    #[serde(rename = "schedule_result")]
+    ScheduleResult {
+        /// The schedule's name.
+        id: String,
+        session: String,
+        /// Start of the minute the run was due, Unix epoch milliseconds.
+        scheduled_at_ms: u64,
+        rows: Vec<Value>,
+        /// Rows returned, or affected by a statement returning none.
+        row_count: usize,
+        trace: Trace,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    #[serde(rename = "resumed")]
+    Resumed {
+        id: String,
+        /// Whether it was continued from its saved checkpoint rather than
+        /// woken up.
+        restored: bool,
+    },
+    #[serde(rename = "operations")]
+    Operations {
+        id: String,
+        operations: Vec<OperationEntry>,
+        approvals: Vec<PendingEntry>,
+    },
+    /// Sent after each batch of a long operation.
+    #[serde(rename = "progress")]
+    Progress {
+        id: String,
+        session: String,
+        /// What is being done: `archive`, `update`, `delete`, `transfer`,
+        /// `import` or `insert`.
+        phase: String,
+        /// Rows done so far; for `import`, records read.
+        done: u64,
+        /// Estimated total, when known up front.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<u64>,
+        elapsed_ms: u64,
+        /// Estimated time left, at the rate so far; needs `total`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_ms: Option<u64>,
+    },
+    /// Reply to a `psql_insert` with `dead_letter`, which writes in batches.
+    #[serde(rename = "insert_result")]
+    InsertResult {
+        id: String,
+        session: String,
+        table: String,
+        rows_inserted: u64,
+        rows_rejected: usize,
+        batches: usize,
+        dead_letter: DeadLetterSummary,
+        trace: Trace,
+    },
+    #[serde(rename = "materialized")]
+    Materialized {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        /// Session the query ran on.
+        session: String,
+        /// Session and table to query the rows from.
+        target_session: String,
+        table: String,
+        columns: Vec<String>,
+        row_count: usize,
         trace: Trace,
     },
     #[serde(rename = "result_start")]
@@ -61,21 +594,244 @@ pub enum Output {
         #[serde(skip_serializing_if = "Option::is_none")]
         session: Option<String>,
         columns: Vec<ColumnInfo>,
+        /// Position in the stream: `0` here, then one per batch and the end.
+        seq: u64,
     },
     #[serde(rename = "result_rows")]
     ResultRows {
         id: String,
+        seq: u64,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
         rows: Vec<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rows_compressed: Option<CompressedRows>,
+        /// `key_columns` values of each row, in row order.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        keys: Vec<Value>,
         rows_batch_count: usize,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    /// One piece of a `result_rows` event longer than `max_message_bytes`;
+    /// the `chunk`s of one `id` and `seq`, in `part` order, make up its line.
+    #[serde(rename = "result_rows")]
+    ResultRowsPart {
+        id: String,
+        seq: u64,
+        part: usize,
+        #[serde(rename = "final")]
+        last: bool,
+        chunk: String,
+        #[serde(skip)]
+        memory: MemoryReservation,
     },
     #[serde(rename = "result_end")]
     ResultEnd {
         id: String,
+        seq: u64,
         #[serde(skip_serializing_if = "Option::is_none")]
         session: Option<String>,
         command_tag: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
         trace: Trace,
     },
+    #[serde(rename = "result_stored")]
+    ResultStored {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        handle: String,
+        columns: Vec<ColumnInfo>,
+        row_count: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        trace: Trace,
+    },
+    #[serde(rename = "result_exported")]
+    ResultExported {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        uri: String,
+        format: String,
+        gzip: bool,
+        columns: Vec<ColumnInfo>,
+        row_count: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        verification: Option<Verification>,
+        trace: Trace,
+    },
+    #[serde(rename = "result_published")]
+    ResultPublished {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        uri: String,
+        messages: usize,
+        row_count: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        trace: Trace,
+    },
+    #[serde(rename = "result_page")]
+    ResultPage {
+        id: String,
+        handle: String,
+        columns: Vec<ColumnInfo>,
+        rows: Vec<Value>,
+        offset: usize,
+        row_count: usize,
+        total_rows: usize,
+        trace: Trace,
+        #[serde(skip)]
+        memory: MemoryReservation,
+    },
+    #[serde(rename = "result_deleted")]
+    ResultDeleted {
+        id: String,
+        handle: String,
+        trace: Trace,
+    },
+    #[serde(rename = "approval_required")]
+    ApprovalRequired {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        session: String,
+        token: String,
+        reason: String,
+        expires_in_ms: u64,
+        trace: Trace,
+    },
+    #[serde(rename = "elevated")]
+    Elevated {
+        id: String,
+        session: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        role: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        via_session: Option<String>,
+        expires_in_ms: u64,
+        trace: Trace,
+    },
+    #[serde(rename = "snapshot_started")]
+    SnapshotStarted {
+        id: String,
+        session: String,
+        snapshot: String,
+        expires_in_ms: u64,
+        trace: Trace,
+    },
+    #[serde(rename = "snapshot_ended")]
+    SnapshotEnded {
+        id: String,
+        session: String,
+        snapshot: String,
+        trace: Trace,
+    },
+    /// What the lint rules found in query `id`, sent before its result or,
+    /// when `blocked`, before the `lint_blocked` error.
+    #[serde(rename = "lint")]
+    Lint {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        session: String,
+        findings: Vec<LintFinding>,
+        blocked: bool,
+    },
+    #[serde(rename = "described")]
+    Described {
+        id: String,
+        session: String,
+        /// Type of each `$n`, in order.
+        params: Vec<String>,
+        /// Empty for a statement that returns no rows.
+        columns: Vec<ColumnInfo>,
+        trace: Trace,
+    },
+    #[serde(rename = "prepared_transactions")]
+    PreparedTransactions {
+        id: String,
+        session: String,
+        transactions: Vec<PreparedTransaction>,
+        trace: Trace,
+    },
+    #[serde(rename = "prepared_finished")]
+    PreparedFinished {
+        id: String,
+        session: String,
+        gid: String,
+        committed: bool,
+        trace: Trace,
+    },
+    #[serde(rename = "workspace_opened")]
+    WorkspaceOpened {
+        id: String,
+        workspace: String,
+        session: String,
+        idle_timeout_ms: u64,
+        trace: Trace,
+    },
+    /// A workspace's connection was found closed and replaced before the
+    /// query `id` ran on it; its temp tables and `SET` state are gone.
+    #[serde(rename = "session_reconnected")]
+    SessionReconnected {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        session: String,
+        workspace: String,
+        trace: Trace,
+    },
+    /// Outcome of connecting sessions ahead of their first query
+    /// (`--eager-connect` or `eager` sessions).
+    #[serde(rename = "connect_report")]
+    ConnectReport {
+        connected: Vec<String>,
+        /// Connect error by session.
+        failed: BTreeMap<String, String>,
+        trace: Trace,
+    },
+    #[serde(rename = "workspace_closed")]
+    WorkspaceClosed {
+        id: String,
+        workspace: String,
+        trace: Trace,
+    },
+    #[serde(rename = "cache_invalidated")]
+    CacheInvalidated {
+        id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        result_entries: usize,
+        schema_entries: usize,
+        trace: Trace,
+    },
+    #[serde(rename = "history")]
+    History {
+        id: String,
+        entries: Vec<HistoryEntry>,
+    },
+    #[serde(rename = "transcript")]
+    Transcript {
+        id: String,
+        format: String,
+        statement_count: usize,
+        omitted: usize,
+        script: String,
+    },
     #[serde(rename = "sql_error")]
     SqlError {
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -101,10 +857,34 @@ pub enum Output {
         retryable: bool,
         trace: Trace,
     },
+    #[serde(rename = "notice")]
+    Notice {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        severity: String,
+        sqlstate: String,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        hint: Option<String>,
+    },
     #[serde(rename = "config")]
-    Config(RuntimeConfig),
+    Config(Box<RuntimeConfig>),
     #[serde(rename = "pong")]
     Pong { trace: PongTrace },
+    #[serde(rename = "debug_stats")]
+    DebugStats {
+        id: String,
+        runtime: RuntimeStats,
+        channels: ChannelStats,
+        pools: Vec<PoolState>,
+        memory: MemoryStats,
+        /// By module: `db.execute`, `lint`, `redact`, `writer`, `log_sinks`.
+        timings: BTreeMap<String, ModuleTiming>,
+    },
     #[serde(rename = "close")]
     Close { message: String, trace: CloseTrace },
     #[serde(rename = "log")]
@@ -119,6 +899,8 @@ pub enum Output {
         #[serde(skip_serializing_if = "Option::is_none")]
         command_tag: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         version: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         argv: Option<Vec<String>>,
@@ -128,17 +910,140 @@ pub enum Output {
         args: Option<Value>,
         #[serde(skip_serializing_if = "Option::is_none")]
         env: Option<Value>,
+        /// With `query.slow`: the statement.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        statement: Option<Box<SlowQuery>>,
         trace: Trace,
     },
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// The statement a `query.slow` log is about.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SlowQuery {
+    pub sql: String,
+    /// As `psql_plan_check` keys its baselines.
+    pub fingerprint: String,
+    pub param_types: Vec<&'static str>,
+    /// With `slow_query_params: "values"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<Value>>,
+    /// Digest of the plan's shape now; `None` when it cannot be explained.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan_hash: Option<String>,
+}
+
+/// What a multi-statement script does when a statement fails.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Stop; later statements are skipped.
+    Stop,
+    /// Run the rest; each statement commits on its own.
+    Continue,
+    /// Run the whole script in one transaction, rolling a failed statement
+    /// back to a savepoint taken before it, and commit the rest at the end.
+    RollbackStatement,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementStatus {
+    Ok,
+    Error,
+    /// Never ran: after a failure with `stop`, or after the transaction of
+    /// a `rollback_statement` script was lost.
+    Skipped,
+    /// Ran, but its transaction did not commit.
+    RolledBack,
+}
+
+/// Outcome of one statement of a script.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ScriptStatement {
+    pub index: usize,
+    pub status: StatementStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sqlstate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    Warning,
+    Error,
+}
+
+/// One rule a query broke.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LintFinding {
+    pub rule: String,
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+/// A transaction left by `PREPARE TRANSACTION`, from `pg_prepared_xacts`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PreparedTransaction {
+    pub gid: String,
+    pub owner: String,
+    /// Database it belongs to; it can only be finished from there.
+    pub database: String,
+    pub prepared: String,
+    pub age_ms: u64,
+}
+
+/// Outcome of a `fanout` query on one session.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FanoutEntry {
+    Ok {
+        command_tag: String,
+        columns: Vec<ColumnInfo>,
+        rows: Vec<Value>,
+        row_count: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limited: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warning: Option<String>,
+        duration_ms: u64,
+    },
+    Error {
+        error_code: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sqlstate: Option<String>,
+        error: String,
+        duration_ms: u64,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ColumnInfo {
     pub name: String,
     #[serde(rename = "type")]
     pub type_name: String,
 }
 
+/// One column of a `result_summary`, over every row of the result.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ColumnStats {
+    /// Smallest and largest value; left out when the column mixes kinds of
+    /// values or holds objects or arrays.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Value>,
+    pub nulls: usize,
+    /// Distinct non-null values, counted up to 10000.
+    pub distinct: usize,
+    /// More distinct values exist than `distinct` says.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub distinct_capped: bool,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct Trace {
     pub duration_ms: u64,
@@ -146,6 +1051,11 @@ pub struct Trace {
     pub row_count: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payload_bytes: Option<usize>,
+    /// Age of the cached rows served instead of running the statement.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_age_ms: Option<u64>,
+    #[serde(flatten)]
+    pub timing: Timing,
 }
 
 impl Trace {
@@ -154,15 +1064,52 @@ impl Trace {
             duration_ms,
             row_count: None,
             payload_bytes: None,
+            cache_age_ms: None,
+            timing: Timing::default(),
         }
     }
 }
 
+/// Where a statement's time went, as far as the executor measured it.
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct Timing {
+    /// Waiting for a pooled connection, or for the pinned one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_wait_ms: Option<u64>,
+    /// Opening a new connection, when the query needed one; not part of
+    /// `pool_wait_ms`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<u64>,
+    /// Connections the statement was started on; more than 1 when a pooled
+    /// connection turned out to be closed before `BEGIN`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+    /// Running the statement on the connection, from `BEGIN` through
+    /// fetching its rows to `COMMIT`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execute_ms: Option<u64>,
+    /// With `server_timing`: the server's own planning and execution times
+    /// from `EXPLAIN ANALYZE`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_planning_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_execution_ms: Option<f64>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PongTrace {
     pub uptime_s: u64,
     pub requests_total: u64,
     pub in_flight: usize,
+    pub result_bytes_queued: usize,
+    /// Events that found the output channel full.
+    pub writer_saturated_total: u64,
+    /// Events dropped by `writer_full_policy`.
+    pub writer_dropped_total: u64,
+    /// Rows or bytes left in each budget in use, by `agent:<name>` or
+    /// `session:<name>`; `agent:*` and `session:*` are shared.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub budgets_remaining: BTreeMap<String, BTreeMap<String, u64>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -187,6 +1134,30 @@ pub struct SessionConfig {
     pub dbname: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password_secret: Option<String>,
+    /// Run every query on one dedicated connection, so `SET` state carries
+    /// over between queries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+    /// Local SQLite database file this session runs on instead of
+    /// PostgreSQL; needs the `sqlite` feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sqlite_path: Option<String>,
+    /// Idle time before TCP keepalive probes start on this session's
+    /// connections (PostgreSQL `keepalives_idle`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_idle_ms: Option<u64>,
+    /// Server settings (`search_path`, `role`, `work_mem`, ...) every
+    /// connection of the session starts with, including reconnects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<BTreeMap<String, String>>,
+    /// Row security context every query on the session runs with; a
+    /// query's `context` cannot change these keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<BTreeMap<String, String>>,
+    /// Connect as soon as the session is configured instead of on its
+    /// first query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eager: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -200,6 +1171,631 @@ pub struct RuntimeConfig {
     pub lock_timeout_ms: u64,
     #[serde(default)]
     pub log: Vec<String>,
+    /// Append `log` events to this file instead of the output stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<String>,
+    /// Roll `log_file` over before it grows past this; `0` never.
+    #[serde(default = "default_log_rotate_bytes")]
+    pub log_rotate_bytes: u64,
+    /// Roll `log_file` over after writing it this long; `0` never.
+    #[serde(default)]
+    pub log_rotate_ms: u64,
+    /// Rolled-over log files kept.
+    #[serde(default = "default_log_keep")]
+    pub log_keep: usize,
+    /// Send `log` events to syslog or journald instead of the output stream.
+    #[serde(default)]
+    pub log_system: LogSystem,
+    #[serde(default)]
+    pub redact: Vec<RedactionRule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redact_salt_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_limit: Option<usize>,
+    #[serde(default)]
+    pub default_limit_action: DefaultLimitAction,
+    /// Result bytes held (fetched or queued for output) above which new
+    /// queries get `backpressure`; `0` disables the check.
+    #[serde(default = "default_memory_budget_bytes")]
+    pub memory_budget_bytes: usize,
+    /// Directory for stored results; unset disables `store_result`,
+    /// `result_get` and `result_delete`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results_dir: Option<String>,
+    /// Directory where managed operations save their checkpoints; unset,
+    /// they cannot be resumed once stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<String>,
+    /// Executed queries kept for `history_list` / `history_replay`; `0` disables.
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    /// Statements kept for `transcript_export`; `0` disables recording.
+    #[serde(default = "default_transcript_max_statements")]
+    pub transcript_max_statements: usize,
+    /// Park DDL and large writes until `approve`d. Once on, it cannot be
+    /// turned off at runtime.
+    #[serde(default)]
+    pub require_approval: bool,
+    #[serde(default = "default_approval_row_threshold")]
+    pub approval_row_threshold: usize,
+    #[serde(default = "default_approval_ttl_ms")]
+    pub approval_ttl_ms: u64,
+    /// Sessions allowed to `approve`, set at startup only. When set, the
+    /// approver session must differ from the requester and connect as a
+    /// different database user, and `approver_secret` is required.
+    #[serde(default)]
+    pub approver_sessions: Vec<String>,
+    /// Secret every `approve` must present, set at startup only. Never
+    /// serialized, so it cannot be read back through `config`.
+    #[serde(skip)]
+    pub approver_secret: Option<String>,
+    /// JSONL file receiving approval records. Once set it cannot be changed
+    /// at runtime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<String>,
+    /// Longest `grant_elevated` duration; can only be lowered at runtime.
+    #[serde(default = "default_elevation_max_ms")]
+    pub elevation_max_ms: u64,
+    /// Log `query.warning` for SQL that looks built by concatenating values.
+    #[serde(default)]
+    pub injection_warnings: bool,
+    /// Log `query.slow` for queries that ran at least this long; `0` never.
+    #[serde(default)]
+    pub slow_query_ms: u64,
+    #[serde(default)]
+    pub slow_query_params: SlowQueryParams,
+    /// Check each query against the lint rules and send a `lint` event with
+    /// what they find.
+    #[serde(default)]
+    pub lint: bool,
+    /// Severities that stop a query instead of only being reported.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lint_block: Vec<LintSeverity>,
+    /// Let queries set `replication_role`; set only by
+    /// `--allow-replication-role` at startup.
+    #[serde(default)]
+    pub allow_replication_role: bool,
+    /// Let `psql_activity` cancel and terminate backends; set only by
+    /// `--allow-backend-signals` at startup.
+    #[serde(default)]
+    pub allow_backend_signals: bool,
+    /// Prefix PostgreSQL statements with a `/* afpsql id=... */` comment so
+    /// they can be traced from `pg_stat_activity` and server logs.
+    #[serde(default = "default_annotate_queries")]
+    pub annotate_queries: bool,
+    /// Agent for requests that do not name one: `agent=` in the annotation
+    /// comment and `agent` in audit records. MCP mode takes the client's
+    /// `clientInfo.name` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_name: Option<String>,
+    /// Per-agent limits by agent name; `"*"` applies to agents without an
+    /// entry of their own, which share its count.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub agent_quotas: HashMap<String, AgentQuota>,
+    /// Row and byte budgets by agent name, with `"*"` as in `agent_quotas`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub agent_budgets: HashMap<String, CostBudget>,
+    /// Row and byte budgets by session, with `"*"` for other sessions.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub session_budgets: HashMap<String, CostBudget>,
+    /// Named timeout policies selected per query with `timeout_profile`.
+    #[serde(default = "default_timeout_profiles")]
+    pub timeout_profiles: HashMap<String, TimeoutProfile>,
+    /// Column patterns holding data subject identifiers, such as
+    /// `users.email`, searched by `psql_find_subject_data`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subject_columns: Vec<String>,
+    /// Data quality assertions run by `psql_run_checks`, by name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub checks: HashMap<String, Check>,
+    /// Queries run on a cron schedule in pipe and MCP mode, by name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub schedules: HashMap<String, Schedule>,
+    /// Snapshots not ended with `snapshot_end` are released after this long.
+    #[serde(default = "default_snapshot_ttl_ms")]
+    pub snapshot_ttl_ms: u64,
+    /// Workspaces with no query for this long are closed.
+    #[serde(default = "default_workspace_idle_ms")]
+    pub workspace_idle_ms: u64,
+    /// Open workspaces allowed at once; each holds a connection.
+    #[serde(default = "default_max_workspaces")]
+    pub max_workspaces: usize,
+    /// Results kept for queries with `cache_ttl_ms`; `0` disables caching.
+    #[serde(default = "default_cache_max_entries")]
+    pub cache_max_entries: usize,
+    /// How long tool catalog lookups are reused; `0` disables the schema cache.
+    #[serde(default = "default_schema_cache_ttl_ms")]
+    pub schema_cache_ttl_ms: u64,
+    /// Queries whose last rows are kept for `diff_output` and `dedup`; `0`
+    /// disables both.
+    #[serde(default = "default_diff_max_baselines")]
+    pub diff_max_baselines: usize,
+    /// Bytes of recently streamed batches kept for `resume_from`; `0`
+    /// disables resuming.
+    #[serde(default = "default_resume_buffer_bytes")]
+    pub resume_buffer_bytes: usize,
+    #[serde(default)]
+    pub writer_full_policy: WriterFullPolicy,
+    #[serde(default)]
+    pub duplicate_id_policy: DuplicateIdPolicy,
+    /// Longest `result_rows` line before it is split into parts; `0` never
+    /// splits.
+    #[serde(default)]
+    pub max_message_bytes: usize,
+    /// How often pooled connections are checked; `0` turns pool maintenance
+    /// off.
+    #[serde(default = "default_pool_check_interval_ms")]
+    pub pool_check_interval_ms: u64,
+    /// Pooled connections unused for this long are closed; `0` keeps them.
+    #[serde(default = "default_pool_idle_timeout_ms")]
+    pub pool_idle_timeout_ms: u64,
+    /// Backend from the executor registry that runs statements; fixed at
+    /// startup.
+    #[serde(default = "default_executor")]
+    pub executor: String,
+    /// Fixture file written by the `record` executor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_path: Option<String>,
+    /// Fixture file served by the `replay` executor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_path: Option<String>,
+    /// Table spec served by the `mock` executor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mock_path: Option<String>,
+    /// Faults injected into statements for resilience tests; fixed at
+    /// startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chaos: Option<Chaos>,
+}
+
+/// Usage one agent may have per minute; unset limits are unlimited.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AgentQuota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queries_per_minute: Option<u64>,
+    /// Result rows sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows_per_minute: Option<u64>,
+    /// Result payload bytes sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_minute: Option<u64>,
+}
+
+/// Result rows and bytes an agent or session may use, refilled
+/// continuously over each period; unset limits are unlimited.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CostBudget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows_per_minute: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows_per_hour: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_minute: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_hour: Option<u64>,
+}
+
+/// Timeouts applied to queries that name this profile.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TimeoutProfile {
+    pub statement_timeout_ms: u64,
+    /// Falls back to the global `lock_timeout_ms`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_timeout_ms: Option<u64>,
+    /// Highest `statement_timeout_ms` a query can ask for under this
+    /// profile; defaults to the profile's own timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_statement_timeout_ms: Option<u64>,
+}
+
+/// A named data quality assertion. `sql` selects the rows that break it,
+/// so the check passes when it returns none.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Check {
+    pub sql: String,
+    #[serde(default)]
+    pub severity: CheckSeverity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Falls back to the default session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement_timeout_ms: Option<u64>,
+}
+
+/// A query run whenever `cron` matches, with its result sent to `sink`, or
+/// to the output stream without one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Schedule {
+    pub cron: Cron,
+    pub sql: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Value>,
+    /// Falls back to the default session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Template values, as in `options.vars` and `options.idents`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vars: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idents: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement_timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sink: Option<Sink>,
+}
+
+/// Whether a failing check fails the whole run or is only reported.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckSeverity {
+    Warning,
+    #[default]
+    Error,
+}
+
+fn default_timeout_profiles() -> HashMap<String, TimeoutProfile> {
+    [
+        ("interactive", 5_000),
+        ("batch", 300_000),
+        ("maintenance", 3_600_000),
+    ]
+    .into_iter()
+    .map(|(name, ms)| {
+        let profile = TimeoutProfile {
+            statement_timeout_ms: ms,
+            lock_timeout_ms: None,
+            max_statement_timeout_ms: None,
+        };
+        (name.to_string(), profile)
+    })
+    .collect()
+}
+
+fn default_annotate_queries() -> bool {
+    true
+}
+
+fn default_snapshot_ttl_ms() -> u64 {
+    600_000
+}
+
+fn default_workspace_idle_ms() -> u64 {
+    600_000
+}
+
+fn default_max_workspaces() -> usize {
+    8
+}
+
+fn default_cache_max_entries() -> usize {
+    256
+}
+
+fn default_schema_cache_ttl_ms() -> u64 {
+    60_000
+}
+
+fn default_diff_max_baselines() -> usize {
+    64
+}
+
+fn default_pool_check_interval_ms() -> u64 {
+    30_000
+}
+
+fn default_pool_idle_timeout_ms() -> u64 {
+    600_000
+}
+
+fn default_log_rotate_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_log_keep() -> usize {
+    5
+}
+
+fn default_resume_buffer_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_executor() -> String {
+    "postgres".to_string()
+}
+
+fn default_elevation_max_ms() -> u64 {
+    3_600_000
+}
+
+fn default_approval_row_threshold() -> usize {
+    1000
+}
+
+fn default_approval_ttl_ms() -> u64 {
+    900_000
+}
+
+fn default_transcript_max_statements() -> usize {
+    10_000
+}
+
+fn default_history_size() -> usize {
+    100
+}
+
+fn default_memory_budget_bytes() -> usize {
+    536_870_912
+}
+
+/// What an output event does when the writer channel is full.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WriterFullPolicy {
+    /// Wait for room.
+    #[default]
+    Block,
+    /// Drop `log` events; wait for room for everything else.
+    DropLogsFirst,
+    /// Drop the event; a stream that loses a batch stops.
+    Error,
+}
+
+/// The system logger `log` events go to; needs the `syslog` feature.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSystem {
+    #[default]
+    Off,
+    Syslog,
+    Journald,
+}
+
+/// How much of a slow query's params its `query.slow` log shows.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowQueryParams {
+    /// Only the JSON type of each.
+    #[default]
+    Types,
+    /// The values too.
+    Values,
+}
+
+/// What a pipe request does when its id is still in flight.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateIdPolicy {
+    /// Fail the new request with `duplicate_id`.
+    #[default]
+    Reject,
+    /// Run the new request as `id#2`, `id#3`, ...
+    Suffix,
+}
+
+/// What to do when a row-returning statement yields more than `default_limit` rows.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultLimitAction {
+    /// Stop fetching after `default_limit` rows and report `limited: true`.
+    #[default]
+    Limit,
+    /// Return every row with a `warning`.
+    Warn,
+}
+
+/// A `transfer` input: copy the rows of a query on one session into a table
+/// on another.
+#[derive(Debug, Deserialize)]
+pub struct TransferRequest {
+    pub id: String,
+    /// Session the query reads from; default session if omitted.
+    #[serde(default)]
+    pub source_session: Option<String>,
+    pub sql: String,
+    #[serde(default)]
+    pub params: Vec<Value>,
+    /// Session written to; default session if omitted.
+    #[serde(default)]
+    pub target_session: Option<String>,
+    /// `table` or `schema.table` on the target session.
+    pub table: String,
+    /// Source column -> target column. When set, only mapped columns are
+    /// written; otherwise every column keeps its name.
+    #[serde(default)]
+    pub columns: HashMap<String, String>,
+    #[serde(default)]
+    pub method: TransferMethod,
+    #[serde(default)]
+    pub batch_rows: Option<usize>,
+    #[serde(default)]
+    pub options: QueryOptions,
+}
+
+/// An `import` input: load the rows of a CSV or NDJSON file into a table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRequest {
+    pub id: String,
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Local path, or `s3://` / `gs://` URI.
+    pub source: String,
+    /// `table` or `schema.table`.
+    pub table: String,
+    /// Source column -> table column. When set, only mapped columns are
+    /// written.
+    #[serde(default)]
+    pub columns: HashMap<String, String>,
+    #[serde(default)]
+    pub batch_rows: Option<usize>,
+    /// Rejected rows tolerated before the import stops.
+    #[serde(default)]
+    pub max_errors: usize,
+    /// Where every rejected record is written, with its error.
+    #[serde(default)]
+    pub dead_letter: Option<DeadLetter>,
+    #[serde(default)]
+    pub options: QueryOptions,
+}
+
+/// An `archive` input: move the rows matching a filter from a table into
+/// an archive table, in batches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveRequest {
+    pub id: String,
+    #[serde(default)]
+    pub session: Option<String>,
+    /// `table` or `schema.table` the rows are moved out of.
+    pub table: String,
+    /// `table` or `schema.table` the rows are moved into; it has every
+    /// column of `table`.
+    pub archive_table: String,
+    /// Structured predicate, as for `psql_delete`.
+    #[serde(rename = "where")]
+    pub filter: Value,
+    #[serde(default)]
+    pub batch_rows: Option<usize>,
+    #[serde(default)]
+    pub options: QueryOptions,
+}
+
+/// `chunked` on `psql_update` / `psql_delete`: how to split the write.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Chunking {
+    /// Rows written per batch.
+    #[serde(default)]
+    pub batch_rows: Option<usize>,
+    /// Pause between batches, letting replicas and other writers catch up.
+    #[serde(default)]
+    pub sleep_ms: u64,
+}
+
+/// A `psql_update` (with `set`) or `psql_delete` run in batches, each
+/// committing on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkedWriteRequest {
+    pub id: String,
+    pub session: Option<String>,
+    pub table: String,
+    pub set: Option<Value>,
+    /// Structured predicate, as for `psql_delete`.
+    #[serde(rename = "where")]
+    pub filter: Value,
+    pub chunking: Chunking,
+    pub options: QueryOptions,
+}
+
+/// Where rejected rows are captured: `{"path": "..."}` for an NDJSON file,
+/// replaced on each run, or `{"table": "..."}` for a table on the session
+/// written to, created if missing and appended to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetter {
+    Path(String),
+    Table(String),
+}
+
+/// The rows written to a dead letter, or why they could not be.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DeadLetterSummary {
+    #[serde(flatten)]
+    pub to: DeadLetter,
+    pub rows: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A source record `import` could not load.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct RejectedRow {
+    /// Line of the source the record starts on.
+    pub line: usize,
+    pub error: String,
+}
+
+/// Value of PostgreSQL's `session_replication_role`. `replica` and `local`
+/// skip ordinary triggers, and with them foreign key checks.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicationRole {
+    Origin,
+    Replica,
+    Local,
+}
+
+impl ReplicationRole {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReplicationRole::Origin => "origin",
+            ReplicationRole::Replica => "replica",
+            ReplicationRole::Local => "local",
+        }
+    }
+}
+
+/// What `verify` does when the rows read back differ from those sent.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyMode {
+    /// Roll the batch back (delete the exported object) and fail with
+    /// `checksum_mismatch`.
+    Fail,
+    /// Keep the rows and report `matched: false`.
+    Flag,
+}
+
+/// Row count and content checksum of one end of a verified operation.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Checksum {
+    pub rows: u64,
+    /// 16 hex digits; see `checksum.rs` for how rows are hashed.
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Verification {
+    /// The rows sent.
+    pub source: Checksum,
+    /// The rows read back from the table or object written.
+    pub target: Checksum,
+    pub matched: bool,
+}
+
+/// How `transfer` writes rows into the target table.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferMethod {
+    /// `COPY ... FROM STDIN`, one per batch.
+    #[default]
+    Copy,
+    /// Multi-row `INSERT`, one per batch; columns a row lacks get `DEFAULT`.
+    Insert,
+}
+
+/// Masks matching columns in emitted rows. `column` is a glob on the column
+/// name (`password`, `*_ssn`) or `[schema.]table.column`, each part a glob.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RedactionRule {
+    pub column: String,
+    #[serde(default)]
+    pub action: RedactAction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactAction {
+    /// Replace the value with `"[redacted]"`.
+    #[default]
+    Mask,
+    /// Replace the value with `"sha256:<hex>"` of its text (salted if configured).
+    Hash,
+    /// Replace the value with `null`.
+    Null,
+    /// Replace the value with a stand-in derived from its hash (salted if
+    /// configured) that keeps its shape: equal values map to equal
+    /// stand-ins, so pseudonymized keys still join.
+    Pseudonymize,
 }
 
 impl Default for RuntimeConfig {
@@ -214,6 +1810,60 @@ impl Default for RuntimeConfig {
             statement_timeout_ms: 30_000,
             lock_timeout_ms: 5_000,
             log: vec![],
+            log_file: None,
+            log_rotate_bytes: default_log_rotate_bytes(),
+            log_rotate_ms: 0,
+            log_keep: default_log_keep(),
+            log_system: LogSystem::default(),
+            redact: vec![],
+            redact_salt_secret: None,
+            default_limit: None,
+            default_limit_action: DefaultLimitAction::Limit,
+            memory_budget_bytes: default_memory_budget_bytes(),
+            results_dir: None,
+            state_dir: None,
+            history_size: default_history_size(),
+            transcript_max_statements: default_transcript_max_statements(),
+            require_approval: false,
+            approval_row_threshold: default_approval_row_threshold(),
+            approval_ttl_ms: default_approval_ttl_ms(),
+            approver_sessions: vec![],
+            approver_secret: None,
+            audit_log: None,
+            elevation_max_ms: default_elevation_max_ms(),
+            injection_warnings: false,
+            slow_query_ms: 0,
+            slow_query_params: SlowQueryParams::default(),
+            lint: false,
+            lint_block: vec![],
+            allow_replication_role: false,
+            allow_backend_signals: false,
+            annotate_queries: default_annotate_queries(),
+            agent_name: None,
+            agent_quotas: HashMap::new(),
+            agent_budgets: HashMap::new(),
+            session_budgets: HashMap::new(),
+            timeout_profiles: default_timeout_profiles(),
+            checks: HashMap::new(),
+            schedules: HashMap::new(),
+            subject_columns: vec![],
+            snapshot_ttl_ms: default_snapshot_ttl_ms(),
+            workspace_idle_ms: default_workspace_idle_ms(),
+            max_workspaces: default_max_workspaces(),
+            cache_max_entries: default_cache_max_entries(),
+            schema_cache_ttl_ms: default_schema_cache_ttl_ms(),
+            diff_max_baselines: default_diff_max_baselines(),
+            resume_buffer_bytes: default_resume_buffer_bytes(),
+            writer_full_policy: WriterFullPolicy::default(),
+            duplicate_id_policy: DuplicateIdPolicy::default(),
+            max_message_bytes: 0,
+            pool_check_interval_ms: default_pool_check_interval_ms(),
+            pool_idle_timeout_ms: default_pool_idle_timeout_ms(),
+            executor: default_executor(),
+            record_path: None,
+            replay_path: None,
+            mock_path: None,
+            chaos: None,
         }
     }
 }
@@ -227,6 +1877,67 @@ pub struct ConfigPatch {
     pub statement_timeout_ms: Option<u64>,
     pub lock_timeout_ms: Option<u64>,
     pub log: Option<Vec<String>>,
+    /// `""` sends `log` events back to the output stream.
+    pub log_file: Option<String>,
+    pub log_rotate_bytes: Option<u64>,
+    pub log_rotate_ms: Option<u64>,
+    pub log_keep: Option<usize>,
+    pub log_system: Option<LogSystem>,
+    /// Appended to the active rules; rules are never removed at runtime.
+    pub redact: Option<Vec<RedactionRule>>,
+    pub redact_salt_secret: Option<String>,
+    /// `0` turns the safeguard off.
+    pub default_limit: Option<usize>,
+    pub default_limit_action: Option<DefaultLimitAction>,
+    pub memory_budget_bytes: Option<usize>,
+    /// An empty string turns the result store off.
+    pub results_dir: Option<String>,
+    /// An empty string turns checkpoints off.
+    pub state_dir: Option<String>,
+    pub history_size: Option<usize>,
+    pub transcript_max_statements: Option<usize>,
+    /// `false` is ignored once approval is required.
+    pub require_approval: Option<bool>,
+    /// Can only be lowered while approval is required.
+    pub approval_row_threshold: Option<usize>,
+    pub approval_ttl_ms: Option<u64>,
+    pub audit_log: Option<String>,
+    /// Can only be lowered.
+    pub elevation_max_ms: Option<u64>,
+    pub injection_warnings: Option<bool>,
+    pub slow_query_ms: Option<u64>,
+    pub slow_query_params: Option<SlowQueryParams>,
+    pub lint: Option<bool>,
+    pub lint_block: Option<Vec<LintSeverity>>,
+    pub annotate_queries: Option<bool>,
+    /// An empty string removes the name.
+    pub agent_name: Option<String>,
+    /// Merged by agent name; limits can only be tightened.
+    pub agent_quotas: Option<HashMap<String, AgentQuota>>,
+    /// Merged by agent name; limits can only be tightened.
+    pub agent_budgets: Option<HashMap<String, CostBudget>>,
+    /// Merged by session name; limits can only be tightened.
+    pub session_budgets: Option<HashMap<String, CostBudget>>,
+    /// Added or replaced by name; profiles are never removed at runtime.
+    pub timeout_profiles: Option<HashMap<String, TimeoutProfile>>,
+    /// Added or replaced by name; checks are never removed at runtime.
+    pub checks: Option<HashMap<String, Check>>,
+    /// Added or replaced by name; `null` removes a schedule.
+    pub schedules: Option<HashMap<String, Option<Schedule>>>,
+    /// Replaces the configured patterns.
+    pub subject_columns: Option<Vec<String>>,
+    pub snapshot_ttl_ms: Option<u64>,
+    pub workspace_idle_ms: Option<u64>,
+    pub max_workspaces: Option<usize>,
+    pub cache_max_entries: Option<usize>,
+    pub schema_cache_ttl_ms: Option<u64>,
+    pub diff_max_baselines: Option<usize>,
+    pub resume_buffer_bytes: Option<usize>,
+    pub writer_full_policy: Option<WriterFullPolicy>,
+    pub duplicate_id_policy: Option<DuplicateIdPolicy>,
+    pub max_message_bytes: Option<usize>,
+    pub pool_check_interval_ms: Option<u64>,
+    pub pool_idle_timeout_ms: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -238,6 +1949,13 @@ pub struct SessionConfigPatch {
     pub user: Option<String>,
     pub dbname: Option<String>,
     pub password_secret: Option<String>,
+    pub pinned: Option<bool>,
+    pub sqlite_path: Option<String>,
+    pub tcp_keepalive_idle_ms: Option<u64>,
+    pub settings: Option<BTreeMap<String, String>>,
+    /// Merged by name.
+    pub context: Option<BTreeMap<String, String>>,
+    pub eager: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
@@ -249,8 +1967,52 @@ pub struct ResolvedOptions {
     pub statement_timeout_ms: u64,
     pub lock_timeout_ms: u64,
     pub read_only: bool,
+    pub defer_constraints: bool,
+    pub replication_role: Option<ReplicationRole>,
     pub inline_max_rows: usize,
     pub inline_max_bytes: usize,
+    pub redact: Vec<RedactionRule>,
+    pub redact_salt_secret: Option<String>,
+    pub default_limit: Option<usize>,
+    pub default_limit_action: DefaultLimitAction,
+    pub compress: Option<Compression>,
+    pub store_result: bool,
+    pub export_uri: Option<String>,
+    pub verify: Option<VerifyMode>,
+    pub publish_uri: Option<String>,
+    pub publish_key: Vec<String>,
+    pub publish_format: PublishFormat,
+    pub server_timing: bool,
+    pub results_dir: Option<String>,
+    /// Writes over this many rows, and DDL, need approval; `None` when
+    /// approval is off or already granted.
+    pub approval_row_threshold: Option<usize>,
+    /// Role from a live `grant_elevated`, set per statement.
+    pub role: Option<String>,
+    /// Run in a `REPEATABLE READ` transaction importing this snapshot.
+    pub snapshot: Option<String>,
+    /// Run outside a transaction block on a connection of its own, for
+    /// `VACUUM` and `REINDEX ... CONCURRENTLY`.
+    pub autocommit: bool,
+    /// Quoted names of the tables a JSON column's objects were read from.
+    pub nested_tables: BTreeMap<String, Vec<String>>,
+    pub workspace: Option<String>,
+    pub cache_ttl_ms: Option<u64>,
+    pub key_columns: Vec<String>,
+    /// Baseline the rows are deduplicated against; set per statement when
+    /// the query asks for `dedup`.
+    pub dedup_baseline: Option<String>,
+    pub ack_window: Option<u64>,
+    pub max_message_bytes: Option<usize>,
+    pub summarize: Option<usize>,
+    /// Comment prefixed to the statement sent to PostgreSQL, ending in a
+    /// space; set per request when `annotate_queries` is on.
+    pub annotation: Option<String>,
+    /// The session's and the query's `context`, set per request.
+    pub context: BTreeMap<String, String>,
+    /// Set only for `query` and `fanout`, whose statement is one
+    /// transaction.
+    pub prepare_transaction: Option<String>,
 }
 
 #[cfg(test)]
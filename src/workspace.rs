@@ -0,0 +1,65 @@
+//! Workspaces opened by `workspace_open`.
+//!
+//! Each workspace pins one connection of its session, so temp tables and
+//! other connection state survive across the queries that name it. A
+//! workspace is closed by `workspace_close` or once it has been idle for
+//! `workspace_idle_ms`; closing the connection drops its temp tables.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub session: String,
+    /// When the last query naming this workspace started.
+    pub last_used: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    open: HashMap<String, Workspace>,
+}
+
+impl Workspaces {
+    pub fn len(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.open.contains_key(id)
+    }
+
+    pub fn open(&mut self, id: &str, session: &str) {
+        let workspace = Workspace {
+            session: session.to_string(),
+            last_used: Instant::now(),
+        };
+        self.open.insert(id.to_string(), workspace);
+    }
+
+    /// Mark `id` used now and return its session; `None` when not open.
+    pub fn touch(&mut self, id: &str) -> Option<String> {
+        let workspace = self.open.get_mut(id)?;
+        workspace.last_used = Instant::now();
+        Some(workspace.session.clone())
+    }
+
+    pub fn close(&mut self, id: &str) -> Option<Workspace> {
+        self.open.remove(id)
+    }
+
+    /// Time left before `id` has been idle for `idle`. Once it has, the
+    /// workspace is removed and zero is returned; `None` when not open.
+    pub fn expire_idle(&mut self, id: &str, idle: Duration) -> Option<Duration> {
+        let used = self.open.get(id)?.last_used.elapsed();
+        if used >= idle {
+            self.open.remove(id);
+            return Some(Duration::ZERO);
+        }
+        Some(idle - used)
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_workspace.rs"]
+mod tests;
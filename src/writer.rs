@@ -1,19 +1,350 @@
-use crate::types::Output;
+use crate::logfile::{self, LogFile};
+use crate::profile::{self, ChannelStats};
+use crate::syslog::{self, SystemLogger};
+use crate::types::{FanoutEntry, LogSystem, Output, RuntimeConfig, Trace, WriterFullPolicy};
 use agent_first_data::OutputFormat;
+use serde_json::Value;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+
+/// Log events queued for `log_file` and `log_system` before senders wait.
+const LOG_CHANNEL_CAPACITY: usize = 1024;
+
+/// Where `log` events go instead of the output stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LogSinks {
+    file: Option<logfile::Policy>,
+    system: Option<LogSystem>,
+}
+
+impl LogSinks {
+    /// `None` when events stay on the stream. `log_system` counts only in a
+    /// build with the `syslog` feature.
+    fn from_config(cfg: &RuntimeConfig) -> Option<Self> {
+        let file = logfile::Policy::from_config(cfg);
+        let system =
+            Some(cfg.log_system).filter(|system| syslog::AVAILABLE && *system != LogSystem::Off);
+        (file.is_some() || system.is_some()).then_some(Self { file, system })
+    }
+}
+
+#[derive(Debug, Default)]
+struct SenderState {
+    /// `writer_full_policy`, and whether `writer_lagging` is logged.
+    policy: Mutex<(WriterFullPolicy, bool)>,
+    saturated_total: AtomicU64,
+    dropped_total: AtomicU64,
+    /// Set when the channel filled up, cleared once it is half empty again.
+    lagging: AtomicBool,
+    /// With `log_file` or `log_system`: the task writing there, which gets
+    /// the `log` events.
+    log_sinks: Mutex<Option<(LogSinks, mpsc::Sender<Output>)>>,
+}
+
+/// The output channel as query tasks see it: sends apply
+/// `writer_full_policy` when the consumer falls behind and count how often
+/// that happens.
+#[derive(Debug, Clone)]
+pub struct OutputSender {
+    tx: mpsc::Sender<Output>,
+    state: Arc<SenderState>,
+}
+
+impl From<mpsc::Sender<Output>> for OutputSender {
+    fn from(tx: mpsc::Sender<Output>) -> Self {
+        Self {
+            tx,
+            state: Arc::default(),
+        }
+    }
+}
+
+impl OutputSender {
+    /// Pick up `writer_full_policy`, the `log` filters, `log_file` and
+    /// `log_system` from `cfg`. Changed log sinks start a new writing task;
+    /// the old one finishes what it was sent and stops.
+    pub fn configure(&self, cfg: &RuntimeConfig) {
+        let log = crate::handler::log_enabled(&cfg.log, "writer_lagging");
+        if let Ok(mut policy) = self.state.policy.lock() {
+            *policy = (cfg.writer_full_policy, log);
+        }
+        let wanted = LogSinks::from_config(cfg);
+        let Ok(mut current) = self.state.log_sinks.lock() else {
+            return;
+        };
+        if current.as_ref().map(|(sinks, _)| sinks) == wanted.as_ref() {
+            return;
+        }
+        *current = wanted.and_then(|sinks| {
+            let runtime = tokio::runtime::Handle::try_current().ok()?;
+            let (tx, rx) = mpsc::channel(LOG_CHANNEL_CAPACITY);
+            let task_sinks = sinks.clone();
+            runtime.spawn_blocking(move || write_logs(task_sinks, rx));
+            Some((sinks, tx))
+        });
+    }
+
+    /// The log sinks task's channel, when `log` events go there.
+    fn log_sinks(&self) -> Option<mpsc::Sender<Output>> {
+        let current = self.state.log_sinks.lock().ok()?;
+        current.as_ref().map(|(_, tx)| tx.clone())
+    }
+
+    /// Depth of the output channel and of the log sinks' channel.
+    pub fn channel_stats(&self) -> ChannelStats {
+        let log_queued = self.log_sinks().map(|tx| tx.max_capacity() - tx.capacity());
+        ChannelStats {
+            output_queued: self.tx.max_capacity() - self.tx.capacity(),
+            output_capacity: self.tx.max_capacity(),
+            log_queued,
+        }
+    }
+
+    pub fn saturated_total(&self) -> u64 {
+        self.state.saturated_total.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped_total(&self) -> u64 {
+        self.state.dropped_total.load(Ordering::Relaxed)
+    }
+
+    /// Queue `output`; fails when the channel is closed, or full under the
+    /// `error` policy.
+    pub async fn send(&self, output: Output) -> Result<(), SendError<Output>> {
+        if matches!(output, Output::Log { .. }) {
+            if let Some(log_sinks) = self.log_sinks() {
+                return log_sinks.send(output).await;
+            }
+        }
+        let output = match self.tx.try_send(output) {
+            Ok(()) => {
+                if self.tx.capacity() * 2 >= self.tx.max_capacity() {
+                    self.state.lagging.store(false, Ordering::Relaxed);
+                }
+                return Ok(());
+            }
+            Err(TrySendError::Closed(output)) => return Err(SendError(output)),
+            Err(TrySendError::Full(output)) => output,
+        };
+        self.state.saturated_total.fetch_add(1, Ordering::Relaxed);
+        let (policy, log) = self.state.policy.lock().map(|p| *p).unwrap_or_default();
+        match policy {
+            WriterFullPolicy::DropLogsFirst if matches!(output, Output::Log { .. }) => {
+                self.state.dropped_total.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            WriterFullPolicy::Error => {
+                self.state.dropped_total.fetch_add(1, Ordering::Relaxed);
+                return Err(SendError(output));
+            }
+            _ => {}
+        }
+        let start = Instant::now();
+        self.tx.send(output).await?;
+        if log && !self.state.lagging.swap(true, Ordering::Relaxed) {
+            let waited_ms = start.elapsed().as_millis() as u64;
+            let warning = format!(
+                "output channel full ({} events); waited {waited_ms} ms for the consumer",
+                self.tx.max_capacity()
+            );
+            let lagging = Output::Log {
+                event: "writer_lagging".to_string(),
+                request_id: None,
+                session: None,
+                error_code: None,
+                command_tag: None,
+                warning: Some(warning),
+                version: None,
+                argv: None,
+                config: None,
+                args: None,
+                env: None,
+                statement: None,
+                trace: Trace::only_duration(waited_ms),
+            };
+            let _ = match self.log_sinks() {
+                Some(log_sinks) => log_sinks.send(lagging).await,
+                None => self.tx.send(lagging).await,
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Write each event from `rx` to the log file and the system logger until
+/// every sender is gone. Blocks, so it runs on a thread of its own. An event
+/// a sink cannot take is dropped there; the file is opened, or the logger
+/// connected, again for the next.
+fn write_logs(sinks: LogSinks, mut rx: mpsc::Receiver<Output>) {
+    let mut file = sinks.file.map(LogFile::new);
+    let mut logger: Option<SystemLogger> = None;
+    while let Some(output) = rx.blocking_recv() {
+        let _timer = profile::timer("log_sinks");
+        let mut line = vec![];
+        if write_output(&mut line, &output, OutputFormat::Json).is_err() {
+            continue;
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if let Some(file) = &mut file {
+            let _ = file.write_line(&line);
+        }
+        let Some(system) = sinks.system else {
+            continue;
+        };
+        let Ok(record) = serde_json::from_slice::<Value>(&line) else {
+            continue;
+        };
+        if logger.is_none() {
+            logger = SystemLogger::connect(system).ok();
+        }
+        if logger.as_ref().is_some_and(|l| l.send(&record).is_err()) {
+            logger = None;
+        }
+    }
+}
 
 pub async fn writer_task(mut rx: mpsc::Receiver<Output>, format: OutputFormat) {
+    let mut out = std::io::BufWriter::new(std::io::stdout());
     while let Some(output) = rx.recv().await {
-        let value = serde_json::to_value(output).unwrap_or(serde_json::Value::Null);
-        let rendered = agent_first_data::cli_output(&value, format);
-
-        let stdout = std::io::stdout();
-        let mut out = stdout.lock();
-        let _ = out.write_all(rendered.as_bytes());
-        if !rendered.ends_with('\n') {
-            let _ = out.write_all(b"\n");
+        let _timer = profile::timer("writer");
+        let _ = write_output(&mut out, &output, format);
+        // Drain whatever is already queued before paying for a flush.
+        while let Ok(output) = rx.try_recv() {
+            let _ = write_output(&mut out, &output, format);
         }
         let _ = out.flush();
     }
 }
+
+/// Render one event as a line. JSON events without `_secret` keys are
+/// serialized straight into `out`; everything else goes through
+/// `agent_first_data::cli_output`, which owns formatting and redaction.
+pub fn write_output(
+    out: &mut impl Write,
+    output: &Output,
+    format: OutputFormat,
+) -> std::io::Result<()> {
+    if format == OutputFormat::Json && !needs_redaction(output) {
+        serde_json::to_writer(&mut *out, output)?;
+        return out.write_all(b"\n");
+    }
+    let value = serde_json::to_value(output).unwrap_or(Value::Null);
+    let rendered = agent_first_data::cli_output(&value, format);
+    out.write_all(rendered.as_bytes())?;
+    if !rendered.ends_with('\n') {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// The JSON line of `output` in pieces of at most `chunk_max` bytes once
+/// escaped as JSON strings, or `None` when the line is at most `line_max`
+/// bytes. Concatenated, the pieces are the line.
+pub fn split_line(output: &Output, line_max: usize, chunk_max: usize) -> Option<Vec<String>> {
+    let line = if needs_redaction(output) {
+        let value = serde_json::to_value(output).unwrap_or(Value::Null);
+        agent_first_data::cli_output(&value, OutputFormat::Json)
+            .trim_end()
+            .to_string()
+    } else {
+        serde_json::to_string(output).unwrap_or_default()
+    };
+    if line.len() <= line_max {
+        return None;
+    }
+    let chunk_max = chunk_max.max(6);
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    let mut escaped = 0;
+    for c in line.chars() {
+        let len = match c {
+            '"' | '\\' | '\u{08}' | '\u{0c}' | '\n' | '\r' | '\t' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        };
+        if escaped + len > chunk_max {
+            chunks.push(std::mem::take(&mut chunk));
+            escaped = 0;
+        }
+        chunk.push(c);
+        escaped += len;
+    }
+    chunks.push(chunk);
+    Some(chunks)
+}
+
+/// Whether `output` may carry a `_secret` key. Only variants known to hold
+/// no row data or user-supplied values skip the check; anything else,
+/// including variants added later, goes through redaction.
+fn needs_redaction(output: &Output) -> bool {
+    match output {
+        Output::Result { rows, .. }
+        | Output::ResultRows { rows, .. }
+        | Output::ResultPage { rows, .. }
+        | Output::ScheduleResult { rows, .. } => rows.iter().any(has_secret_key),
+        Output::ResultDiff { added, removed, .. } => {
+            added.iter().chain(removed).any(has_secret_key)
+        }
+        Output::ResultSummary {
+            column_stats,
+            head,
+            tail,
+            ..
+        } => {
+            column_stats.keys().any(|k| k.ends_with("_secret"))
+                || head.iter().chain(tail).any(has_secret_key)
+        }
+        Output::FanoutResult { results, .. } => results.values().any(|entry| match entry {
+            FanoutEntry::Ok { rows, .. } => rows.iter().any(has_secret_key),
+            FanoutEntry::Error { .. } => false,
+        }),
+        // Parts are cut from a line that was already redacted.
+        Output::ResultRowsPart { .. }
+        | Output::ResultStart { .. }
+        | Output::ResultEnd { .. }
+        | Output::ResultStored { .. }
+        | Output::ResultExported { .. }
+        | Output::ResultPublished { .. }
+        | Output::ResultDeleted { .. }
+        | Output::TransferResult { .. }
+        | Output::ArchiveResult { .. }
+        | Output::ChunkedResult { .. }
+        | Output::Materialized { .. }
+        | Output::Paused { .. }
+        | Output::Resumed { .. }
+        | Output::Progress { .. }
+        | Output::Elevated { .. }
+        | Output::SnapshotStarted { .. }
+        | Output::SnapshotEnded { .. }
+        | Output::Described { .. }
+        | Output::PreparedFinished { .. }
+        | Output::WorkspaceOpened { .. }
+        | Output::SessionReconnected { .. }
+        | Output::WorkspaceClosed { .. }
+        | Output::CacheInvalidated { .. }
+        | Output::Pong { .. }
+        | Output::DebugStats { .. }
+        | Output::Close { .. } => false,
+        _ => true,
+    }
+}
+
+fn has_secret_key(v: &Value) -> bool {
+    match v {
+        Value::Object(map) => map
+            .iter()
+            .any(|(k, v)| k.ends_with("_secret") || has_secret_key(v)),
+        Value::Array(items) => items.iter().any(has_secret_key),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/support/unit_writer.rs"]
+mod tests;
@@ -42,6 +42,102 @@ fn afd_cli_param_binding_query() {
     assert_eq!(v["rows"][0]["n"], 42);
 }
 
+#[test]
+fn afd_cli_substitutes_defined_vars() {
+    let out = Command::new(bin())
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .arg("--sql")
+        .arg("select :'greeting' as g, {{n}}::int as n from {{tbl|ident}} limit 1")
+        .arg("--define")
+        .arg("greeting=it's")
+        .arg("--define")
+        .arg("n=3")
+        .arg("--define")
+        .arg("tbl=pg_class")
+        .output()
+        .expect("run afpsql");
+
+    assert_eq!(out.status.code(), Some(0));
+    let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
+    assert_eq!(v["rows"][0]["g"], "it's");
+    assert_eq!(v["rows"][0]["n"], 3);
+}
+
+#[test]
+fn afd_cli_quotes_idents() {
+    let out = Command::new(bin())
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .arg("--sql")
+        .arg("select count(*) > 0 as found from %{schema}.%{table}")
+        .arg("--ident")
+        .arg("schema=pg_catalog")
+        .arg("--ident")
+        .arg("table=pg_class")
+        .output()
+        .expect("run afpsql");
+    assert_eq!(out.status.code(), Some(0));
+    let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
+    assert_eq!(v["rows"][0]["found"], true);
+
+    // A name is one quoted identifier, never SQL.
+    let out = Command::new(bin())
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .arg("--sql")
+        .arg("select 1 from %{table}")
+        .arg("--ident")
+        .arg("table=pg_class; drop table x")
+        .output()
+        .expect("run afpsql");
+    assert_eq!(out.status.code(), Some(4));
+    let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
+    assert_eq!(v["sqlstate"], "42P01");
+}
+
+#[test]
+fn afd_cli_runs_repeated_sql_in_parallel() {
+    let out = Command::new(bin())
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .arg("--sql")
+        .arg("select pg_sleep(0.3), 1 as n")
+        .arg("--sql")
+        .arg("select pg_sleep(0.3), 2 as n")
+        .arg("--sql")
+        .arg("select 3 as n")
+        .arg("--parallel")
+        .arg("3")
+        .output()
+        .expect("run afpsql");
+
+    assert!(
+        out.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let text = String::from_utf8(out.stdout).expect("utf8");
+    let mut by_id: Vec<(String, i64)> = text
+        .lines()
+        .map(|l| serde_json::from_str::<Value>(l).expect("json line"))
+        .map(|v| {
+            assert_eq!(v["code"], "result");
+            let id = v["id"].as_str().expect("id").to_string();
+            (id, v["rows"][0]["n"].as_i64().expect("n"))
+        })
+        .collect();
+    by_id.sort();
+    assert_eq!(
+        by_id,
+        [
+            ("0".to_string(), 1),
+            ("1".to_string(), 2),
+            ("2".to_string(), 3)
+        ]
+    );
+}
+
 #[test]
 fn psql_mode_translates_v_params() {
     let out = Command::new(bin())
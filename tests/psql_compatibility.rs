@@ -1,6 +1,7 @@
 use serde_json::Value;
+use std::io::Write;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 fn test_dsn() -> String {
     std::env::var("AFPSQL_TEST_DSN_SECRET")
@@ -46,6 +47,41 @@ fn psql_mode_only_translates_supported_cli_flags() {
     assert_eq!(v["error_code"], "invalid_request");
 }
 
+#[test]
+fn psql_mode_runs_a_script_from_stdin_on_one_connection() {
+    let mut child = Command::new(bin())
+        .arg("--mode")
+        .arg("psql")
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn afpsql");
+    child
+        .stdin
+        .take()
+        .expect("stdin")
+        .write_all(
+            b"create temp table script_t (n int);\n\
+              insert into script_t values (7); -- seed; one row\n\
+              select n from script_t;\n",
+        )
+        .expect("write script");
+    let out = child.wait_with_output().expect("wait afpsql");
+    assert_eq!(out.status.code(), Some(0));
+
+    let events: Vec<Value> = String::from_utf8(out.stdout)
+        .expect("utf8")
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json line"))
+        .collect();
+    let results: Vec<&Value> = events.iter().filter(|v| v["code"] == "result").collect();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[2]["id"], "2");
+    assert_eq!(results[2]["rows"][0]["n"], 7);
+}
+
 #[test]
 fn afd_mode_rejects_psql_short_flags() {
     let out = Command::new(bin())
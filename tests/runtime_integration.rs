@@ -28,7 +28,7 @@ fn cli_invalid_param_count_returns_error() {
         .output()
         .expect("run afpsql");
 
-    assert!(!out.status.success());
+    assert_eq!(out.status.code(), Some(1));
     let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
     assert_eq!(v["code"], "error");
     assert_eq!(v["error_code"], "invalid_params");
@@ -46,7 +46,7 @@ fn cli_result_too_large_without_streaming() {
         .output()
         .expect("run afpsql");
 
-    assert!(!out.status.success());
+    assert_eq!(out.status.code(), Some(5));
     let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
     assert_eq!(v["code"], "error");
     assert_eq!(v["error_code"], "result_too_large");
@@ -63,11 +63,31 @@ fn cli_read_only_rejects_write() {
         .output()
         .expect("run afpsql");
 
-    assert!(!out.status.success());
+    assert_eq!(out.status.code(), Some(4));
     let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
     assert_eq!(v["code"], "sql_error");
 }
 
+#[test]
+fn cli_max_runtime_exits_with_timeout_error() {
+    let started = std::time::Instant::now();
+    let out = Command::new(bin())
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .arg("--sql")
+        .arg("select pg_sleep(10)")
+        .arg("--max-runtime-ms")
+        .arg("300")
+        .output()
+        .expect("run afpsql");
+
+    assert_eq!(out.status.code(), Some(6));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
+    assert_eq!(v["code"], "error");
+    assert_eq!(v["error_code"], "max_runtime_exceeded");
+}
+
 #[test]
 fn cli_statement_timeout_triggers_sql_error() {
     let out = Command::new(bin())
@@ -80,7 +100,7 @@ fn cli_statement_timeout_triggers_sql_error() {
         .output()
         .expect("run afpsql");
 
-    assert!(!out.status.success());
+    assert_eq!(out.status.code(), Some(6));
     let v: Value = serde_json::from_slice(&out.stdout).expect("json output");
     assert_eq!(v["code"], "sql_error");
 }
@@ -422,3 +442,330 @@ fn pipe_cancel_race_and_long_query() {
     assert!(text.contains("\"code\":\"close\""));
     assert!(text.contains("\"error_code\":\"cancelled\"") || text.contains("\"code\":\"result\""));
 }
+
+#[test]
+fn pipe_eager_connect_fails_fast_with_report() {
+    let out = Command::new(bin())
+        .arg("--mode")
+        .arg("pipe")
+        .arg("--eager-connect")
+        .arg("--dsn-secret")
+        .arg("postgresql://127.0.0.1:1/postgres")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .expect("run afpsql");
+    assert_eq!(out.status.code(), Some(3));
+    let text = String::from_utf8(out.stdout).expect("utf8");
+    let report: Value = serde_json::from_str(text.trim()).expect("report json");
+    assert_eq!(report["code"], "connect_report");
+    assert_eq!(report["connected"], serde_json::json!([]));
+    assert!(report["failed"]["default"]
+        .as_str()
+        .is_some_and(|e| e.starts_with("connect_failed")));
+}
+
+#[test]
+fn mcp_insert_over_the_approval_threshold_is_parked_not_copied() {
+    let table = format!("afpsql_insert_approval_{}", std::process::id());
+    let sql = |sql: String| {
+        Command::new(bin())
+            .arg("--dsn-secret")
+            .arg(test_dsn())
+            .arg("--sql")
+            .arg(sql)
+            .output()
+            .expect("run afpsql")
+    };
+    assert!(sql(format!("create table {table} (id int)"))
+        .status
+        .success());
+
+    let call = |id: u64, name: &str, arguments: Value| {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": {"name": name, "arguments": arguments}
+        })
+        .to_string()
+            + "\n"
+    };
+    let rows: Vec<Value> = (1..=4).map(|n| serde_json::json!({"id": n})).collect();
+    let payload = call(
+        1,
+        "psql_config",
+        serde_json::json!({"approval_row_threshold": 2}),
+    ) + &call(
+        2,
+        "psql_insert",
+        serde_json::json!({"table": table, "rows": rows, "copy_threshold_rows": 1}),
+    );
+    let mut child = Command::new(bin())
+        .arg("--mode")
+        .arg("mcp")
+        .arg("--require-approval")
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn afpsql mode mcp");
+    child
+        .stdin
+        .take()
+        .expect("stdin")
+        .write_all(payload.as_bytes())
+        .expect("write stdin");
+    let out = child.wait_with_output().expect("wait output");
+    let count = sql(format!("select count(*) as n from {table}"));
+    let _ = sql(format!("drop table {table}"));
+
+    let text = String::from_utf8(out.stdout).expect("utf8");
+    assert!(text.contains("approval_required"), "{text}");
+    assert!(text.contains("apr_"), "{text}");
+    let count: Value = serde_json::from_slice(&count.stdout).expect("count json");
+    assert_eq!(count["rows"][0]["n"], 0, "{count}");
+}
+
+/// Run `calls` as MCP `tools/call` requests against a server started with
+/// `args`, returning its output.
+fn mcp_calls(args: &[&str], calls: &[(&str, Value)]) -> String {
+    let payload: String = calls
+        .iter()
+        .enumerate()
+        .map(|(id, (name, arguments))| {
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id + 1,
+                "method": "tools/call",
+                "params": {"name": name, "arguments": arguments}
+            })
+            .to_string()
+                + "\n"
+        })
+        .collect();
+    let mut child = Command::new(bin())
+        .arg("--mode")
+        .arg("mcp")
+        .args(args)
+        .arg("--dsn-secret")
+        .arg(test_dsn())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn afpsql mode mcp");
+    child
+        .stdin
+        .take()
+        .expect("stdin")
+        .write_all(payload.as_bytes())
+        .expect("write stdin");
+    let out = child.wait_with_output().expect("wait output");
+    String::from_utf8(out.stdout).expect("utf8")
+}
+
+#[test]
+fn mcp_column_stats_hide_values_of_redacted_columns() {
+    let text = mcp_calls(
+        &["--redact", "ssn"],
+        &[(
+            "psql_column_stats",
+            serde_json::json!({
+                "sql": "select * from (values ('123-45-6789', 7), ('987-65-4321', 9)) v(ssn, n)"
+            }),
+        )],
+    );
+    assert!(!text.contains("123-45-6789"), "{text}");
+    assert!(!text.contains("987-65-4321"), "{text}");
+    assert!(text.contains(r#""column":"ssn""#), "{text}");
+    assert!(text.contains(r#""redacted":true"#), "{text}");
+    assert!(text.contains(r#""min":7"#), "{text}");
+}
+
+#[test]
+fn mcp_search_redacts_snippets_and_nested_rows() {
+    let table = format!("afpsql_search_redact_{}", std::process::id());
+    let sql = |sql: String| {
+        Command::new(bin())
+            .arg("--dsn-secret")
+            .arg(test_dsn())
+            .arg("--sql")
+            .arg(sql)
+            .output()
+            .expect("run afpsql")
+    };
+    assert!(sql(format!(
+        "create table {table} as select 'ann' as name, '123-45-6789' as ssn"
+    ))
+    .status
+    .success());
+    let rule = format!("public.{table}.ssn");
+    let text = mcp_calls(
+        &["--redact", &rule],
+        &[(
+            "psql_search",
+            serde_json::json!({
+                "query": "45-67",
+                "targets": [{"table": table, "columns": ["name", "ssn"]}]
+            }),
+        )],
+    );
+    let _ = sql(format!("drop table {table}"));
+    assert!(!text.contains("123-45-6789"), "{text}");
+    assert!(text.contains(r#""snippet":"[redacted]""#), "{text}");
+    assert!(text.contains(r#""ssn":"[redacted]""#), "{text}");
+    assert!(text.contains(r#""name":"ann""#), "{text}");
+}
+
+#[test]
+fn mcp_aggregate_refuses_min_of_a_redacted_column() {
+    let text = mcp_calls(
+        &["--redact", "ssn"],
+        &[
+            (
+                "psql_aggregate",
+                serde_json::json!({
+                    "sql": "select concat('123-45-', '6789') as ssn",
+                    "aggregates": [{"fn": "min", "column": "ssn"}]
+                }),
+            ),
+            (
+                "psql_aggregate",
+                serde_json::json!({
+                    "sql": "select concat('123-45-', '6789') as ssn",
+                    "aggregates": [{"fn": "count_distinct", "column": "ssn"}]
+                }),
+            ),
+        ],
+    );
+    assert!(!text.contains("123-45-6789"), "{text}");
+    assert!(text.contains("'ssn' is redacted"), "{text}");
+    assert!(text.contains("count_distinct_ssn"), "{text}");
+}
+
+#[test]
+fn mcp_upsert_infers_the_key_of_a_mixed_case_table() {
+    let table = format!("Afpsql_Upsert_{}", std::process::id());
+    let sql = |sql: String| {
+        Command::new(bin())
+            .arg("--dsn-secret")
+            .arg(test_dsn())
+            .arg("--sql")
+            .arg(sql)
+            .output()
+            .expect("run afpsql")
+    };
+    assert!(sql(format!(
+        r#"create table "{table}" (id int primary key, v text)"#
+    ))
+    .status
+    .success());
+    let upsert = |v: &str| {
+        (
+            "psql_upsert",
+            serde_json::json!({"table": table, "rows": [{"id": 1, "v": v}]}),
+        )
+    };
+    let text = mcp_calls(&[], &[upsert("a"), upsert("b")]);
+    let _ = sql(format!(r#"drop table "{table}""#));
+    assert!(!text.contains("key inference failed"), "{text}");
+    assert!(text.contains(r#"\"key\":[\"id\"]"#), "{text}");
+    assert!(text.contains(r#"\"updated\":1"#), "{text}");
+}
+
+#[test]
+fn mcp_sample_reads_statistics_of_a_mixed_case_table() {
+    let table = format!("Afpsql_Sample_{}", std::process::id());
+    let sql = |sql: String| {
+        Command::new(bin())
+            .arg("--dsn-secret")
+            .arg(test_dsn())
+            .arg("--sql")
+            .arg(sql)
+            .output()
+            .expect("run afpsql")
+    };
+    assert!(sql(format!(r#"create table "{table}" as select 1 as id"#))
+        .status
+        .success());
+    let text = mcp_calls(&[], &[("psql_sample", serde_json::json!({"table": table}))]);
+    let _ = sql(format!(r#"drop table "{table}""#));
+    assert!(!text.contains("does not exist"), "{text}");
+    assert!(text.contains(r#"\"column\":\"id\""#), "{text}");
+}
+
+#[test]
+fn mcp_maintenance_dry_run_finds_a_mixed_case_table() {
+    let table = format!("Afpsql_Maint_{}", std::process::id());
+    let sql = |sql: String| {
+        Command::new(bin())
+            .arg("--dsn-secret")
+            .arg(test_dsn())
+            .arg("--sql")
+            .arg(sql)
+            .output()
+            .expect("run afpsql")
+    };
+    assert!(sql(format!(r#"create table "{table}" as select 1 as id"#))
+        .status
+        .success());
+    let text = mcp_calls(
+        &[],
+        &[(
+            "psql_maintenance",
+            serde_json::json!({"table": table, "operation": "analyze", "dry_run": true}),
+        )],
+    );
+    let _ = sql(format!(r#"drop table "{table}""#));
+    assert!(!text.contains("table stats failed"), "{text}");
+    assert!(text.contains(r#"\"total_bytes\":"#), "{text}");
+}
+
+#[test]
+fn mcp_chunked_update_finds_the_key_of_a_mixed_case_table() {
+    let table = format!("Afpsql_Chunked_{}", std::process::id());
+    let sql = |sql: String| {
+        Command::new(bin())
+            .arg("--dsn-secret")
+            .arg(test_dsn())
+            .arg("--sql")
+            .arg(sql)
+            .output()
+            .expect("run afpsql")
+    };
+    assert!(sql(format!(
+        r#"create table "{table}" as select g as id, 'a' as v from generate_series(1, 5) g"#
+    ))
+    .status
+    .success());
+    assert!(
+        sql(format!(r#"alter table "{table}" add primary key (id)"#))
+            .status
+            .success()
+    );
+    let text = mcp_calls(
+        &[],
+        &[(
+            "psql_update",
+            serde_json::json!({
+                "table": table,
+                "set": {"v": "b"},
+                "where": {"v": "a"},
+                "chunked": {"batch_rows": 2}
+            }),
+        )],
+    );
+    let left = sql(format!(
+        r#"select count(*) as n from "{table}" where v = 'a'"#
+    ));
+    let _ = sql(format!(r#"drop table "{table}""#));
+    assert!(!text.contains("does not exist"), "{text}");
+    assert!(
+        String::from_utf8_lossy(&left.stdout).contains(r#""n":0"#),
+        "{text}"
+    );
+}
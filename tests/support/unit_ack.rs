@@ -0,0 +1,30 @@
+use super::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn wait_for_returns_once_acked_or_finished() {
+    let acks = Arc::new(Acks::default());
+    acks.begin("s");
+    acks.ack("s", 2);
+    acks.wait_for("s", 2).await;
+
+    let waiter = {
+        let acks = acks.clone();
+        tokio::spawn(async move { acks.wait_for("s", 5).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiter.is_finished());
+    acks.ack("s", 4);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiter.is_finished());
+    acks.ack("s", 5);
+    waiter.await.unwrap();
+
+    // Untracked streams never wait, and acks for them are dropped.
+    acks.finish("s");
+    acks.ack("s", 9);
+    acks.wait_for("s", 100).await;
+    acks.ack("other", 1);
+    acks.wait_for("other", 100).await;
+}
@@ -0,0 +1,57 @@
+use super::*;
+use serde_json::json;
+
+fn columns() -> Vec<String> {
+    ["id", "status", "n", "Kind"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+#[test]
+fn filtered_scans_finds_seq_scans_with_filters() {
+    let plan = json!({
+        "Node Type": "Nested Loop",
+        "Total Cost": 900.0,
+        "Plans": [
+            {"Node Type": "Seq Scan", "Relation Name": "orders", "Schema": "app",
+             "Filter": "(orders.status = 'open'::text)", "Total Cost": 400.0, "Plan Rows": 20},
+            {"Node Type": "Seq Scan", "Relation Name": "users", "Schema": "app",
+             "Total Cost": 100.0, "Plan Rows": 1000}
+        ]
+    });
+    let scans = filtered_scans(&plan);
+    assert_eq!(scans.len(), 1);
+    assert_eq!(scans[0].qualified(), "\"app\".\"orders\"");
+    assert_eq!((scans[0].cost, scans[0].rows), (400.0, 20.0));
+}
+
+#[test]
+fn filter_columns_puts_equality_first_and_skips_literals() {
+    let filter =
+        "((t.n > 5) AND (t.status = 'id = 1'::text) AND (t.\"Kind\" = ANY ('{a,b}'::text[])))";
+    assert_eq!(
+        filter_columns(filter, &columns()),
+        vec!["status", "Kind", "n"]
+    );
+    assert!(filter_columns("(lower(t.email) = 'x'::text)", &columns()).is_empty());
+    let many = "((id = 1) AND (status = 'x'::text) AND (n = 2) AND (\"Kind\" = 'k'::text))";
+    assert_eq!(filter_columns(many, &columns()).len(), MAX_INDEX_COLUMNS);
+}
+
+#[test]
+fn create_index_and_saving() {
+    let scan = FilteredScan {
+        schema: "public".to_string(),
+        relation: "orders".to_string(),
+        filter: String::new(),
+        cost: 400.0,
+        rows: 20.0,
+    };
+    assert_eq!(
+        create_index_sql(&scan, &["status".to_string(), "n".to_string()]),
+        "create index on \"public\".\"orders\" (\"status\", \"n\")"
+    );
+    assert_eq!(estimated_saving(&scan, 20_000.0), Some(399.6));
+    assert_eq!(estimated_saving(&scan, 40.0), None);
+}
@@ -0,0 +1,67 @@
+use super::*;
+use serde_json::json;
+
+fn rule(column: &str, action: RedactAction) -> RedactionRule {
+    RedactionRule {
+        column: column.to_string(),
+        action,
+    }
+}
+
+fn users() -> TableInfo {
+    TableInfo {
+        schema: "public".to_string(),
+        table: "users".to_string(),
+        columns: vec!["id".to_string(), "email".to_string(), "plan".to_string()],
+    }
+}
+
+#[test]
+fn configured_rules_win_over_request_rules() {
+    let config = [rule("users.email", RedactAction::Mask)];
+    let request = [
+        rule("email", RedactAction::Pseudonymize),
+        rule("id", RedactAction::Pseudonymize),
+    ];
+    let columns = column_transforms(&users(), &config, &request);
+    assert_eq!(
+        serde_json::to_value(&columns).unwrap(),
+        json!([
+            {"column": "id", "transform": "pseudonymize", "rule": "id", "source": "request"},
+            {"column": "email", "transform": "mask", "rule": "users.email", "source": "config"},
+            {"column": "plan", "transform": "none"}
+        ])
+    );
+}
+
+#[test]
+fn export_writes_a_file_per_table_and_the_manifest() {
+    let dir = std::env::temp_dir().join(format!("afpsql_anonymize_{}", std::process::id()));
+    let sample = TableSample {
+        manifest: TableManifest {
+            table: "public.we/ird".to_string(),
+            rows: 1,
+            file: None,
+            columns: column_transforms(&users(), &[], &[]),
+        },
+        rows: vec![json!({"id": 1, "email": "a@b.c", "plan": "pro"})],
+    };
+    let mut manifest = Manifest {
+        mode: "first".to_string(),
+        rows_per_table: 10,
+        salted: false,
+        tables: vec![sample.manifest.clone()],
+    };
+    write_export(&dir, &mut manifest, &[sample]).unwrap();
+    assert_eq!(
+        manifest.tables[0].file.as_deref(),
+        Some("public.we_ird.jsonl")
+    );
+    let page = results::read_file(&dir.join("public.we_ird.jsonl")).unwrap();
+    assert_eq!(page.rows.len(), 1);
+    assert_eq!(page.columns.len(), 3);
+    let written: Value =
+        serde_json::from_slice(&std::fs::read(dir.join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(written["tables"][0]["file"], "public.we_ird.jsonl");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,67 @@
+use super::*;
+
+fn pending(ttl_ms: u64) -> PendingApproval {
+    PendingApproval {
+        session: "default".to_string(),
+        sql: "drop table t".to_string(),
+        params: vec![],
+        options: QueryOptions::default(),
+        reason: "statement takes exclusive locks on t".to_string(),
+        expires_at: expires_at(ttl_ms),
+    }
+}
+
+#[test]
+fn park_and_take_once() {
+    let mut a = Approvals::default();
+    let token = a.park(pending(60_000));
+    assert!(token.starts_with("apr_"));
+    assert_eq!(a.take(&token).unwrap().sql, "drop table t");
+    assert!(a.take(&token).unwrap_err().contains("unknown"));
+}
+
+#[test]
+fn expired_token_is_refused() {
+    let mut a = Approvals::default();
+    let token = a.park(pending(0));
+    assert!(a.take(&token).unwrap_err().contains("expired"));
+}
+
+#[test]
+fn tokens_are_distinct() {
+    let mut a = Approvals::default();
+    let t1 = a.park(pending(60_000));
+    let t2 = a.park(pending(60_000));
+    assert_ne!(t1, t2);
+}
+
+#[test]
+fn get_leaves_token_in_place() {
+    let mut a = Approvals::default();
+    let token = a.park(pending(60_000));
+    assert!(a.get(&token).is_ok());
+    assert!(a.take(&token).is_ok());
+    assert!(a.get(&token).is_err());
+}
+
+#[test]
+fn pending_approvals_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("afpsql_approvals_{}", std::process::id()));
+    let state_dir = dir.to_string_lossy().into_owned();
+    let mut a = Approvals::load(Some(&state_dir));
+    let kept = a.park(pending(60_000));
+    let taken = a.park(pending(60_000));
+    let expired = a.park(pending(0));
+    assert!(a.take(&taken).is_ok());
+
+    let restarted = Approvals::load(Some(&state_dir));
+    let listed: Vec<String> = restarted.list().into_iter().map(|p| p.token).collect();
+    assert_eq!(listed, vec![kept.clone()]);
+    assert_eq!(restarted.get(&kept).unwrap().sql, "drop table t");
+    assert!(restarted.get(&expired).unwrap_err().contains("unknown"));
+    assert!(!dir
+        .join("approvals")
+        .join(format!("{expired}.json"))
+        .exists());
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,26 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn append_writes_one_record_per_line() {
+    let path = std::env::temp_dir().join(format!("afpsql_audit_{}.jsonl", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let _ = std::fs::remove_file(&path);
+    append(&path, "approval.granted", json!({"token": "apr_1"})).unwrap();
+    append(&path, "approval.denied", json!({"token": "apr_2"})).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<Value> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["event"], "approval.granted");
+    assert_eq!(lines[0]["token"], "apr_1");
+    assert!(lines[1]["at_ms"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn append_reports_unwritable_path() {
+    assert!(append("/nonexistent-dir/afpsql/audit.jsonl", "x", json!({})).is_err());
+}
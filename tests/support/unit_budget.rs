@@ -0,0 +1,75 @@
+use super::*;
+
+fn budget(rows_per_minute: Option<u64>, bytes_per_hour: Option<u64>) -> CostBudget {
+    CostBudget {
+        rows_per_minute,
+        rows_per_hour: None,
+        bytes_per_minute: None,
+        bytes_per_hour,
+    }
+}
+
+#[test]
+fn spent_bucket_refuses_until_refilled() {
+    let mut b = Budgets::default();
+    let rows = budget(Some(60), None);
+    let now = Instant::now();
+    assert!(b.admit("agent:bot", &rows, now).is_ok());
+    b.charge("agent:bot", 90, 0, now);
+    let err = b.admit("agent:bot", &rows, now).unwrap_err();
+    assert!(
+        err.contains("agent:bot spent its rows_per_minute budget of 60"),
+        "{err}"
+    );
+    assert!(err.contains("retry in 31s"), "{err}");
+    // One row per second comes back.
+    assert!(b
+        .admit("agent:bot", &rows, now + Duration::from_secs(30))
+        .is_err());
+    assert!(b
+        .admit("agent:bot", &rows, now + Duration::from_secs(31))
+        .is_ok());
+}
+
+#[test]
+fn rows_and_bytes_drain_their_own_buckets() {
+    let mut b = Budgets::default();
+    let both = budget(Some(100), Some(1_000));
+    let now = Instant::now();
+    assert!(b.admit("session:s", &both, now).is_ok());
+    b.charge("session:s", 10, 400, now);
+    let left = &b.remaining(now)["session:s"];
+    assert_eq!(left["rows_per_minute"], 90);
+    assert_eq!(left["bytes_per_hour"], 600);
+}
+
+#[test]
+fn untracked_scopes_are_not_charged() {
+    let mut b = Budgets::default();
+    b.charge("agent:bot", 10, 10, Instant::now());
+    assert!(b.remaining(Instant::now()).is_empty());
+}
+
+#[test]
+fn reconfigured_budget_keeps_what_is_spent() {
+    let mut b = Budgets::default();
+    let now = Instant::now();
+    assert!(b.admit("agent:bot", &budget(Some(100), None), now).is_ok());
+    b.charge("agent:bot", 80, 0, now);
+    assert!(b.admit("agent:bot", &budget(Some(50), None), now).is_ok());
+    assert_eq!(b.remaining(now)["agent:bot"]["rows_per_minute"], 20);
+    assert!(b.admit("agent:bot", &budget(Some(10), None), now).is_ok());
+    assert_eq!(b.remaining(now)["agent:bot"]["rows_per_minute"], 10);
+}
+
+#[test]
+fn tightened_budgets_keep_the_lower_limit() {
+    assert_eq!(
+        tighten_budget(&budget(Some(60), None), budget(Some(600), Some(1_000))),
+        budget(Some(60), Some(1_000))
+    );
+    assert_eq!(
+        tighten_budget(&budget(Some(60), Some(1_000)), budget(None, None)),
+        budget(Some(60), Some(1_000))
+    );
+}
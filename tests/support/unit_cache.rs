@@ -0,0 +1,70 @@
+use super::*;
+use crate::types::{QueryOptions, RuntimeConfig};
+
+fn opts() -> ResolvedOptions {
+    RuntimeConfig::default().resolve_options(&QueryOptions::default())
+}
+
+#[test]
+fn cache_key_covers_sql_params_session_and_row_options() {
+    let o = opts();
+    let key = cache_key("default", "select $1", &[json!(1)], &o);
+    assert_eq!(key, cache_key("default", "select $1", &[json!(1)], &o));
+    assert_ne!(key, cache_key("default", "select  $1", &[json!(1)], &o));
+    assert_ne!(key, cache_key("default", "select $1", &[json!(2)], &o));
+    assert_ne!(key, cache_key("replica", "select $1", &[json!(1)], &o));
+
+    let mut limited = opts();
+    limited.default_limit = Some(1);
+    assert_ne!(
+        key,
+        cache_key("default", "select $1", &[json!(1)], &limited)
+    );
+
+    let mut tenant = opts();
+    tenant
+        .context
+        .insert("app.tenant_id".to_string(), "42".to_string());
+    assert_ne!(key, cache_key("default", "select $1", &[json!(1)], &tenant));
+}
+
+#[test]
+fn get_honours_max_age_and_ttl() {
+    let mut cache = ResultCache::default();
+    let minute = Duration::from_secs(60);
+    cache.insert("k".to_string(), "default", vec![json!({"n": 1})], minute, 8);
+    let (rows, age) = cache.get("k", minute).unwrap();
+    assert_eq!(rows, vec![json!({"n": 1})]);
+    assert!(age < minute);
+    assert!(cache.get("k", Duration::ZERO).is_none());
+    assert!(cache.get("other", minute).is_none());
+
+    cache.insert("gone".to_string(), "default", vec![], Duration::ZERO, 8);
+    assert!(cache.get("gone", minute).is_none());
+}
+
+#[test]
+fn insert_evicts_oldest_at_capacity() {
+    let mut cache = ResultCache::default();
+    let minute = Duration::from_secs(60);
+    cache.insert("a".to_string(), "default", vec![], minute, 2);
+    cache.insert("b".to_string(), "default", vec![], minute, 2);
+    cache.insert("c".to_string(), "default", vec![], minute, 2);
+    assert!(cache.get("a", minute).is_none());
+    assert!(cache.get("b", minute).is_some());
+    assert!(cache.get("c", minute).is_some());
+
+    cache.insert("d".to_string(), "default", vec![], minute, 0);
+    assert!(cache.get("d", minute).is_none());
+}
+
+#[test]
+fn invalidate_drops_results_of_one_session_or_all() {
+    let mut cache = ResultCache::default();
+    let minute = Duration::from_secs(60);
+    cache.insert("a".to_string(), "default", vec![], minute, 8);
+    cache.insert("b".to_string(), "replica", vec![], minute, 8);
+    assert_eq!(cache.invalidate(Some("replica")), 1);
+    assert!(cache.get("a", minute).is_some());
+    assert_eq!(cache.invalidate(None), 1);
+}
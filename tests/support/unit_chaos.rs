@@ -0,0 +1,89 @@
+use super::*;
+use crate::registry::EchoExecutor;
+use crate::types::{QueryOptions, RuntimeConfig};
+
+fn chaos(json: serde_json::Value) -> Chaos {
+    serde_json::from_value(json).unwrap()
+}
+
+#[test]
+fn chaos_validates_probabilities_and_sqlstates() {
+    let quiet = chaos(serde_json::json!({}));
+    assert!(quiet.validate().is_ok());
+    assert_eq!(quiet.sqlstates, DEFAULT_SQLSTATES);
+    for bad in [
+        serde_json::json!({"drop_probability": 1.5}),
+        serde_json::json!({"error_probability": 0.5, "sqlstates": []}),
+        serde_json::json!({"sqlstates": ["4000"]}),
+    ] {
+        assert!(chaos(bad.clone()).validate().is_err(), "{bad}");
+    }
+    assert!(serde_json::from_value::<Chaos>(serde_json::json!({"drop": 1})).is_err());
+}
+
+#[test]
+fn fault_is_the_same_for_the_same_seed_and_position() {
+    let c = chaos(serde_json::json!({
+        "seed": 7,
+        "latency_probability": 0.5,
+        "drop_probability": 0.2,
+        "error_probability": 0.3,
+    }));
+    let run = |c: &Chaos| -> Vec<String> {
+        (0..200)
+            .map(|n| {
+                let (delay, error) = c.fault(n);
+                format!(
+                    "{delay} {}",
+                    error.map(|e| e.to_string()).unwrap_or_default()
+                )
+            })
+            .collect()
+    };
+    let first = run(&c);
+    assert_eq!(first, run(&c));
+    let other = Chaos {
+        seed: 8,
+        ..c.clone()
+    };
+    assert_ne!(first, run(&other));
+
+    let delayed = first.iter().filter(|f| f.starts_with("true")).count();
+    let dropped = first
+        .iter()
+        .filter(|f| f.contains("connect_failed"))
+        .count();
+    let failed = first.iter().filter(|f| f.contains("sql_error")).count();
+    assert!((60..140).contains(&delayed), "{delayed}");
+    assert!((15..70).contains(&dropped), "{dropped}");
+    assert!((20..90).contains(&failed), "{failed}");
+
+    let always = chaos(serde_json::json!({"error_probability": 1.0, "sqlstates": ["40001"]}));
+    assert!(matches!(
+        always.fault(3).1,
+        Some(ExecError::Sql { sqlstate, .. }) if sqlstate == "40001"
+    ));
+}
+
+#[tokio::test]
+async fn chaos_executor_fails_statements_before_they_reach_the_inner_executor() {
+    let inner: Arc<dyn DbExecutor> = Arc::new(EchoExecutor);
+    let executor = ChaosExecutor::new(
+        inner,
+        chaos(
+            serde_json::json!({"drop_probability": 1.0, "latency_probability": 1.0, "latency_ms": 20}),
+        ),
+    );
+    let started = std::time::Instant::now();
+    let result = executor
+        .execute(
+            "default",
+            &SessionConfig::default(),
+            "select 1",
+            &[],
+            &RuntimeConfig::default().resolve_options(&QueryOptions::default()),
+        )
+        .await;
+    assert!(matches!(result, Err(ExecError::Connect(m)) if m.contains("chaos")));
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
@@ -0,0 +1,77 @@
+use super::*;
+use serde_json::json;
+
+fn check(severity: CheckSeverity) -> Check {
+    Check {
+        sql: "select id from orders where customer_id is null".to_string(),
+        severity,
+        description: Some("orders have a customer".to_string()),
+        session: None,
+        statement_timeout_ms: None,
+    }
+}
+
+#[test]
+fn rows_fail_a_check_with_a_sample() {
+    let rows: Vec<Value> = (0..=MAX_COUNTED_ROWS).map(|id| json!({"id": id})).collect();
+    let result = check_result(
+        "orphans",
+        &check(CheckSeverity::Error),
+        Ok(ExecOutcome::Rows(rows)),
+        2,
+    );
+    assert_eq!(result.status, CheckStatus::Fail);
+    assert_eq!(result.failing_rows, MAX_COUNTED_ROWS);
+    assert!(result.more_rows);
+    assert_eq!(result.sample, vec![json!({"id": 0}), json!({"id": 1})]);
+
+    let clean = check_result(
+        "orphans",
+        &check(CheckSeverity::Error),
+        Ok(ExecOutcome::Rows(vec![])),
+        2,
+    );
+    assert_eq!(clean.status, CheckStatus::Pass);
+    assert_eq!(
+        serde_json::to_value(&clean).unwrap(),
+        json!({
+            "name": "orphans",
+            "severity": "error",
+            "status": "pass",
+            "description": "orders have a customer",
+            "failing_rows": 0,
+            "duration_ms": 0
+        })
+    );
+}
+
+#[test]
+fn warnings_fail_only_themselves_and_errors_fail_the_run() {
+    let failing = |severity| {
+        check_result(
+            "c",
+            &check(severity),
+            Ok(ExecOutcome::Rows(vec![json!({"id": 1})])),
+            DEFAULT_SAMPLE_ROWS,
+        )
+    };
+    assert!(passed(&[failing(CheckSeverity::Warning)]));
+    assert!(!passed(&[failing(CheckSeverity::Error)]));
+
+    let broken = check_result(
+        "c",
+        &check(CheckSeverity::Warning),
+        Err(ExecError::Internal("relation does not exist".to_string())),
+        DEFAULT_SAMPLE_ROWS,
+    );
+    assert_eq!(broken.status, CheckStatus::Error);
+    assert!(!passed(&[broken]));
+
+    let write = check_result(
+        "c",
+        &check(CheckSeverity::Warning),
+        Ok(ExecOutcome::Command { affected: 0 }),
+        DEFAULT_SAMPLE_ROWS,
+    );
+    assert_eq!(write.error.as_deref(), Some("check sql must return rows"));
+}
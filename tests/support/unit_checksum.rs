@@ -0,0 +1,63 @@
+use super::*;
+use serde_json::json;
+
+fn columns() -> Vec<String> {
+    vec!["id".to_string(), "note".to_string()]
+}
+
+#[test]
+fn checksum_ignores_row_order_and_reads_numeric_text() {
+    let rows = vec![
+        json!({"id": 1, "note": "a"}),
+        json!({"id": 2, "note": null}),
+    ];
+    let reversed: Vec<Value> = rows.iter().rev().cloned().collect();
+    assert_eq!(
+        RowHasher::of(&rows, &columns()),
+        RowHasher::of(&reversed, &columns())
+    );
+
+    let from_csv = vec![json!({"id": "1", "note": "a"}), json!({"id": "2"})];
+    assert_eq!(
+        RowHasher::of(&from_csv, &columns()),
+        RowHasher::of(&rows, &columns())
+    );
+    let padded = vec![json!({"id": "001", "note": "a"}), json!({"id": 2})];
+    assert_ne!(
+        RowHasher::of(&padded, &columns()),
+        RowHasher::of(&rows, &columns())
+    );
+
+    let mut merged = RowHasher::of(&rows[..1], &columns());
+    merged.merge(RowHasher::of(&rows[1..], &columns()));
+    assert_eq!(merged, RowHasher::of(&rows, &columns()));
+    assert_eq!(merged.checksum().rows, 2);
+    assert_eq!(merged.checksum().checksum.len(), 16);
+}
+
+#[test]
+fn verifier_rejects_mismatches_only_in_fail_mode() {
+    let sent = RowHasher::of(&[json!({"id": 1, "note": "a"})], &columns());
+    let stored = RowHasher::of(&[json!({"id": 1, "note": "b"})], &columns());
+
+    let mut fail = Verifier::new(VerifyMode::Fail);
+    assert!(fail.accepts(&sent, &sent));
+    assert!(!fail.accepts(&sent, &stored));
+    fail.record(sent, sent, true);
+    assert!(fail.mismatch().is_none());
+    fail.record(sent, stored, false);
+    assert!(fail
+        .mismatch()
+        .unwrap()
+        .starts_with("checksum mismatch: sent 1 rows"));
+    // The rolled-back batch is not part of the totals.
+    let report = fail.report();
+    assert!(report.matched);
+    assert_eq!(report.source.rows, 1);
+
+    let mut flag = Verifier::new(VerifyMode::Flag);
+    assert!(flag.accepts(&sent, &stored));
+    flag.record(sent, stored, true);
+    assert!(flag.mismatch().is_none());
+    assert!(!flag.report().matched);
+}
@@ -83,11 +83,56 @@ fn startup_requested_detects_raw_log_entries() {
     ]));
 }
 
+fn no_stdin() -> Result<String, String> {
+    Err("stdin is a terminal".to_string())
+}
+
 #[test]
-fn load_sql_validation() {
-    assert!(load_sql(Some("select 1".to_string()), None).is_ok());
-    assert!(load_sql(Some("x".to_string()), Some("y".to_string())).is_err());
-    assert!(load_sql(None, None).is_err());
+fn load_psql_statements_validation() {
+    assert_eq!(
+        load_psql_statements(Some("select 1; select 2".to_string()), None, no_stdin).unwrap(),
+        ["select 1; select 2"]
+    );
+    assert!(load_psql_statements(Some("x".to_string()), Some("y".to_string()), no_stdin).is_err());
+    assert!(load_psql_statements(None, None, no_stdin).is_err());
+}
+
+#[test]
+fn load_psql_statements_reads_scripts_from_stdin() {
+    let script =
+        || Ok("create temp table t (n int);\n-- seed\ninsert into t values (1);\n".to_string());
+    let expected = [
+        "create temp table t (n int)",
+        "-- seed\ninsert into t values (1)",
+    ];
+    assert_eq!(load_psql_statements(None, None, script).unwrap(), expected);
+    assert_eq!(
+        load_psql_statements(None, Some("-".to_string()), script).unwrap(),
+        expected
+    );
+    let blank = || Ok("  -- nothing here\n".to_string());
+    assert!(load_psql_statements(None, None, blank).is_err());
+}
+
+#[test]
+fn load_statements_keeps_sql_then_files() {
+    let path = std::env::temp_dir().join(format!("afpsql_stmts_{}.sql", std::process::id()));
+    std::fs::write(&path, "select 3").unwrap();
+    let statements = load_statements(
+        vec!["select 1".to_string(), "select 2".to_string()],
+        vec![path.to_string_lossy().to_string()],
+        no_stdin,
+    )
+    .unwrap();
+    assert_eq!(statements, ["select 1", "select 2", "select 3"]);
+    assert!(load_statements(vec![], vec![], no_stdin).is_err());
+    assert!(load_statements(
+        vec![],
+        vec!["/nonexistent/afpsql.sql".to_string()],
+        no_stdin
+    )
+    .is_err());
+    let _ = std::fs::remove_file(path);
 }
 
 #[test]
@@ -113,14 +158,18 @@ fn parse_psql_mode_all_flags_and_sql_file() {
         "host=localhost user=roger dbname=postgres".to_string(),
         "-v".to_string(),
         "1=7".to_string(),
+        "-v".to_string(),
+        "tbl=users".to_string(),
         "--output".to_string(),
         "plain".to_string(),
     ];
     let mode = parse_psql_mode(&raw).unwrap();
     match mode {
         Mode::Cli(req) => {
-            assert_eq!(req.sql.trim(), "select $1::int");
+            assert_eq!(req.statements, ["select $1::int"]);
             assert_eq!(req.params.len(), 1);
+            let vars = req.options.vars.as_ref().unwrap();
+            assert_eq!(vars["tbl"], "users");
             assert!(matches!(req.output, OutputFormat::Plain));
             assert_eq!(req.session.host.as_deref(), Some("localhost"));
             assert_eq!(req.session.user.as_deref(), Some("roger"));
@@ -190,3 +239,70 @@ fn parse_psql_mode_port_and_v_errors() {
     let err = parse_psql_mode(&bad_v).err().unwrap_or_default();
     assert!(err.contains("expected N=value") || err.contains("invalid"));
 }
+
+#[test]
+fn parse_redact_rules_actions() {
+    let rules = parse_redact_rules(&["*_ssn".to_string(), "users.email=hash".to_string()]).unwrap();
+    assert_eq!(rules[0].action, RedactAction::Mask);
+    assert_eq!(rules[1].column, "users.email");
+    assert_eq!(rules[1].action, RedactAction::Hash);
+    assert!(parse_redact_rules(&["x=drop".to_string()]).is_err());
+    assert!(parse_redact_rules(&["=null".to_string()]).is_err());
+}
+
+#[test]
+fn load_statements_expands_sql_dash_from_stdin() {
+    let statements = load_statements(
+        vec!["select 0".to_string(), "-".to_string()],
+        vec![],
+        || Ok("select 1; select 2;".to_string()),
+    )
+    .unwrap();
+    assert_eq!(statements, ["select 0", "select 1", "select 2"]);
+
+    let err = load_statements(vec!["-".to_string(), "-".to_string()], vec![], || {
+        Ok("select 1".to_string())
+    })
+    .unwrap_err();
+    assert!(err.contains("only be given once"));
+    assert!(load_statements(vec!["-".to_string()], vec![], no_stdin).is_err());
+}
+
+#[test]
+fn parse_defines_validates_names() {
+    assert_eq!(parse_defines(&[]).unwrap(), None);
+    let vars = parse_defines(&["tbl=public.users".to_string(), "eq=a=b".to_string()])
+        .unwrap()
+        .unwrap();
+    assert_eq!(vars["tbl"], "public.users");
+    assert_eq!(vars["eq"], "a=b");
+    assert!(parse_defines(&["novalue".to_string()]).is_err());
+    assert!(parse_defines(&["1st=x".to_string()]).is_err());
+}
+
+#[test]
+fn parse_idents_checks_values() {
+    let idents = parse_idents(&["table=Orders".to_string()])
+        .unwrap()
+        .unwrap();
+    assert_eq!(idents["table"], "Orders");
+    assert!(parse_idents(&["table=".to_string()]).is_err());
+    assert_eq!(parse_idents(&[]).unwrap(), None);
+}
+
+#[test]
+fn parse_psql_vars_maps_error_handling_variables() {
+    let entries = |list: &[&str]| list.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    let (params, vars, on_error) =
+        parse_psql_vars(&entries(&["1=7", "ON_ERROR_STOP=1", "table=orders"])).unwrap();
+    assert_eq!(params, vec![json!(7)]);
+    assert_eq!(on_error, Some(OnError::Stop));
+    assert!(vars.is_some_and(|v| v.len() == 1 && v["table"] == "orders"));
+
+    let (_, vars, on_error) =
+        parse_psql_vars(&entries(&["ON_ERROR_ROLLBACK=interactive"])).unwrap();
+    assert_eq!((vars, on_error), (None, Some(OnError::RollbackStatement)));
+    let (_, _, on_error) = parse_psql_vars(&entries(&["ON_ERROR_STOP=off"])).unwrap();
+    assert_eq!(on_error, None);
+    assert!(parse_psql_vars(&entries(&["ON_ERROR_STOP=on", "ON_ERROR_ROLLBACK=on"])).is_err());
+}
@@ -0,0 +1,67 @@
+use super::*;
+use crate::db::{ExecError, ExecOutcome};
+use crate::types::SessionConfig;
+use async_trait::async_trait;
+use serde_json::json;
+
+struct EchoExecutor;
+
+#[async_trait]
+impl DbExecutor for EchoExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        _params: &[Value],
+        _opts: &crate::types::ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Rows(vec![
+            json!({"session": session_name, "sql": sql}),
+        ]))
+    }
+}
+
+#[tokio::test]
+async fn query_returns_the_events_of_the_request() {
+    let mut client = Client::with_executor(RuntimeConfig::default(), Arc::new(EchoExecutor));
+    let patch: ConfigPatch =
+        serde_json::from_value(json!({"sessions": {"replica": {"host": "replica"}}})).unwrap();
+    let cfg = client.configure(patch).await;
+    assert!(cfg.sessions.contains_key("replica"));
+
+    let events = client
+        .query(Some("replica"), "select 1", vec![], QueryOptions::default())
+        .await;
+    match events.as_slice() {
+        [Output::Result { rows, .. }] => {
+            assert_eq!(rows[0], json!({"session": "replica", "sql": "select 1"}));
+        }
+        other => panic!("expected one result, got {other:?}"),
+    }
+
+    let app = client.app().clone();
+    let events = client
+        .collect(handler::invalidate_cache(&app, "i".to_string(), None))
+        .await;
+    assert!(matches!(
+        events.as_slice(),
+        [Output::CacheInvalidated { .. }]
+    ));
+}
+
+#[tokio::test]
+async fn from_registry_uses_the_configured_executor() {
+    let config = RuntimeConfig {
+        executor: "echo".to_string(),
+        ..RuntimeConfig::default()
+    };
+    let mut client = Client::from_registry(config, &ExecutorRegistry::default()).unwrap();
+    let events = client
+        .query(None, "select 2", vec![], QueryOptions::default())
+        .await;
+    match events.as_slice() {
+        [Output::Result { rows, .. }] => assert_eq!(rows[0]["sql"], "select 2"),
+        other => panic!("expected one result, got {other:?}"),
+    }
+}
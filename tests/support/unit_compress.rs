@@ -0,0 +1,34 @@
+use super::*;
+use base64::Engine;
+use serde_json::json;
+use std::io::Read;
+
+fn decode(c: &CompressedRows) -> Vec<u8> {
+    let packed = base64::engine::general_purpose::STANDARD
+        .decode(&c.data)
+        .unwrap();
+    match c.codec {
+        Compression::Gzip => {
+            let mut out = vec![];
+            flate2::read::GzDecoder::new(packed.as_slice())
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        }
+        Compression::Zstd => zstd::decode_all(packed.as_slice()).unwrap(),
+    }
+}
+
+#[test]
+fn compress_rows_round_trips() {
+    let rows: Vec<Value> = (0..50).map(|i| json!({"id": i, "name": "same"})).collect();
+    for codec in [Compression::Gzip, Compression::Zstd] {
+        let c = compress_rows(&rows, codec).unwrap();
+        assert_eq!(c.encoding, "base64");
+        let raw = decode(&c);
+        assert_eq!(raw.len(), c.raw_bytes);
+        assert!(c.data.len() < c.raw_bytes);
+        let back: Vec<Value> = serde_json::from_slice(&raw).unwrap();
+        assert_eq!(back, rows);
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[test]
 fn apply_update_adds_default_session_if_missing() {
@@ -25,6 +25,12 @@ fn apply_update_merges_session_fields() {
             user: Some("roger".to_string()),
             dbname: Some("postgres".to_string()),
             password_secret: Some("pw".to_string()),
+            pinned: Some(true),
+            sqlite_path: Some("local.db".to_string()),
+            tcp_keepalive_idle_ms: Some(60_000),
+            settings: Some([("search_path".to_string(), "app".to_string())].into()),
+            context: Some([("app.tenant_id".to_string(), "42".to_string())].into()),
+            eager: Some(true),
         },
     );
     cfg.apply_update(ConfigPatch {
@@ -47,6 +53,18 @@ fn apply_update_merges_session_fields() {
     assert_eq!(s1.user.as_deref(), Some("roger"));
     assert_eq!(s1.dbname.as_deref(), Some("postgres"));
     assert_eq!(s1.password_secret.as_deref(), Some("pw"));
+    assert_eq!(s1.pinned, Some(true));
+    assert_eq!(s1.sqlite_path.as_deref(), Some("local.db"));
+    assert_eq!(s1.tcp_keepalive_idle_ms, Some(60_000));
+    assert_eq!(s1.eager, Some(true));
+    assert_eq!(
+        s1.settings.as_ref().and_then(|s| s.get("search_path")),
+        Some(&"app".to_string())
+    );
+    assert_eq!(
+        s1.context.as_ref().and_then(|c| c.get("app.tenant_id")),
+        Some(&"42".to_string())
+    );
     assert_eq!(cfg.inline_max_rows, 10);
     assert_eq!(cfg.inline_max_bytes, 20);
     assert_eq!(cfg.statement_timeout_ms, 30);
@@ -78,16 +96,205 @@ fn resolve_options_applies_defaults_and_overrides() {
         batch_bytes: Some(1),
         statement_timeout_ms: Some(1),
         lock_timeout_ms: Some(2),
+        timeout_profile: None,
+        deadline: None,
         read_only: Some(true),
+        defer_constraints: true,
+        replication_role: Some(ReplicationRole::Replica),
         inline_max_rows: Some(3),
         inline_max_bytes: Some(4),
+        summarize: None,
+        default_limit: Some(5),
+        compress: None,
+        store_result: false,
+        export_uri: None,
+        verify: None,
+        publish_uri: None,
+        publish_key: None,
+        publish_format: None,
+        notify: None,
+        server_timing: false,
+        approved: false,
+        snapshot: None,
+        autocommit: false,
+        nested_tables: BTreeMap::new(),
+        workspace: None,
+        cache_ttl_ms: Some(0),
+        materialize_to: None,
+        diff_output: false,
+        key_columns: None,
+        dedup: false,
+        resume_from: None,
+        ack_window: None,
+        vars: None,
+        idents: None,
+        agent: None,
+        context: None,
+        prepare_transaction: None,
+        expect: None,
     });
     assert!(resolved.stream_rows);
+    assert_eq!(resolved.cache_ttl_ms, None);
     assert_eq!(resolved.batch_rows, 1);
     assert_eq!(resolved.batch_bytes, 1024);
     assert_eq!(resolved.statement_timeout_ms, 1);
     assert_eq!(resolved.lock_timeout_ms, 2);
     assert!(resolved.read_only);
+    assert!(resolved.defer_constraints);
+    assert_eq!(resolved.replication_role, Some(ReplicationRole::Replica));
     assert_eq!(resolved.inline_max_rows, 3);
     assert_eq!(resolved.inline_max_bytes, 4);
+    assert_eq!(resolved.default_limit, Some(5));
+    assert_eq!(resolved.fetch_cap(), Some(6));
+}
+
+#[test]
+fn apply_update_only_appends_redaction_rules() {
+    let mut cfg = RuntimeConfig::default();
+    let rule = |c: &str| RedactionRule {
+        column: c.to_string(),
+        action: RedactAction::Mask,
+    };
+    cfg.redact = vec![rule("password")];
+    cfg.apply_update(ConfigPatch {
+        redact: Some(vec![rule("*_ssn"), rule("password")]),
+        ..Default::default()
+    });
+    cfg.apply_update(ConfigPatch {
+        redact: Some(vec![]),
+        ..Default::default()
+    });
+    assert_eq!(cfg.redact, vec![rule("password"), rule("*_ssn")]);
+    assert_eq!(
+        cfg.resolve_options(&QueryOptions::default()).redact.len(),
+        2
+    );
+}
+
+#[test]
+fn allow_backend_signals_is_startup_only() {
+    let mut cfg = RuntimeConfig::default();
+    let patch: ConfigPatch =
+        serde_json::from_value(serde_json::json!({"allow_backend_signals": true})).unwrap();
+    cfg.apply_update(patch);
+    assert!(!cfg.allow_backend_signals);
+}
+
+#[test]
+fn allow_replication_role_is_startup_only() {
+    let mut cfg = RuntimeConfig::default();
+    let patch: ConfigPatch =
+        serde_json::from_value(serde_json::json!({"allow_replication_role": true})).unwrap();
+    cfg.apply_update(patch);
+    assert!(!cfg.allow_replication_role);
+}
+
+#[test]
+fn default_limit_zero_disables() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.apply_update(ConfigPatch {
+        default_limit: Some(100),
+        ..Default::default()
+    });
+    assert_eq!(
+        cfg.resolve_options(&QueryOptions::default()).fetch_cap(),
+        Some(101)
+    );
+    let off = cfg.resolve_options(&QueryOptions {
+        default_limit: Some(0),
+        ..Default::default()
+    });
+    assert_eq!(off.default_limit, None);
+    cfg.apply_update(ConfigPatch {
+        default_limit: Some(0),
+        ..Default::default()
+    });
+    assert_eq!(cfg.default_limit, None);
+}
+
+#[test]
+fn timeout_profile_sets_timeouts_within_its_ceiling() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.apply_update(ConfigPatch {
+        timeout_profiles: Some(HashMap::from([(
+            "report".to_string(),
+            TimeoutProfile {
+                statement_timeout_ms: 60_000,
+                lock_timeout_ms: Some(100),
+                max_statement_timeout_ms: Some(120_000),
+            },
+        )])),
+        ..Default::default()
+    });
+    assert!(cfg.timeout_profiles.contains_key("interactive"));
+    let profile = |name: &str, ms: Option<u64>| {
+        cfg.resolve_options(&QueryOptions {
+            timeout_profile: Some(name.to_string()),
+            statement_timeout_ms: ms,
+            ..Default::default()
+        })
+    };
+    let report = profile("report", None);
+    assert_eq!(report.statement_timeout_ms, 60_000);
+    assert_eq!(report.lock_timeout_ms, 100);
+    assert_eq!(profile("report", Some(90_000)).statement_timeout_ms, 90_000);
+    assert_eq!(
+        profile("report", Some(900_000)).statement_timeout_ms,
+        120_000
+    );
+    assert_eq!(
+        profile("interactive", Some(60_000)).statement_timeout_ms,
+        5_000
+    );
+    assert_eq!(profile("interactive", Some(10)).statement_timeout_ms, 10);
+    assert_eq!(
+        profile("interactive", None).lock_timeout_ms,
+        cfg.lock_timeout_ms
+    );
+}
+
+#[test]
+fn approvers_are_startup_only() {
+    let mut cfg = RuntimeConfig {
+        approver_sessions: vec!["human".to_string()],
+        approver_secret: Some("s3cret".to_string()),
+        ..RuntimeConfig::default()
+    };
+    let patch: ConfigPatch =
+        serde_json::from_value(serde_json::json!({"approver_sessions": ["agent"]})).unwrap();
+    cfg.apply_update(patch);
+    assert_eq!(cfg.approver_sessions, vec!["human".to_string()]);
+    let shown = serde_json::to_value(&cfg).unwrap();
+    assert!(shown.get("approver_secret").is_none());
+}
+
+#[test]
+fn patches_cannot_raise_a_budget() {
+    let budget = |rows_per_hour| CostBudget {
+        rows_per_minute: None,
+        rows_per_hour,
+        bytes_per_minute: None,
+        bytes_per_hour: None,
+    };
+    let mut cfg = RuntimeConfig {
+        agent_budgets: HashMap::from([("bot".to_string(), budget(Some(100)))]),
+        session_budgets: HashMap::from([("*".to_string(), budget(Some(10)))]),
+        ..RuntimeConfig::default()
+    };
+    let patch: ConfigPatch = serde_json::from_value(serde_json::json!({
+        "agent_budgets": {"bot": {"rows_per_hour": 1000000}},
+        "session_budgets": {"*": {}, "mine": {"rows_per_hour": 500}},
+    }))
+    .unwrap();
+    cfg.apply_update(patch);
+    assert_eq!(cfg.agent_budgets["bot"], budget(Some(100)));
+    assert_eq!(cfg.session_budgets["*"], budget(Some(10)));
+    assert_eq!(cfg.session_budgets["mine"], budget(Some(10)));
+
+    let patch: ConfigPatch = serde_json::from_value(serde_json::json!({
+        "agent_budgets": {"bot": {"rows_per_hour": 5}},
+    }))
+    .unwrap();
+    cfg.apply_update(patch);
+    assert_eq!(cfg.agent_budgets["bot"], budget(Some(5)));
 }
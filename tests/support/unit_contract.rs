@@ -0,0 +1,71 @@
+use super::*;
+use crate::types::ExpectedColumn;
+
+fn column(name: &str, type_name: &str) -> ColumnInfo {
+    ColumnInfo {
+        name: name.to_string(),
+        type_name: type_name.to_string(),
+    }
+}
+
+fn expect(columns: &[(&str, Option<&str>)]) -> ResultContract {
+    ResultContract {
+        columns: Some(
+            columns
+                .iter()
+                .map(|(name, t)| ExpectedColumn {
+                    name: name.to_string(),
+                    type_name: t.map(str::to_string),
+                })
+                .collect(),
+        ),
+        ..ResultContract::default()
+    }
+}
+
+#[test]
+fn sql_type_names_match_postgres_names() {
+    assert_eq!(type_name("integer"), "int4");
+    assert_eq!(type_name("INT"), "int4");
+    assert_eq!(type_name("int4"), "int4");
+    assert_eq!(type_name("timestamp with time zone"), "timestamptz");
+    assert_eq!(type_name("bigint[]"), "_int8");
+    assert_eq!(type_name("jsonb"), "jsonb");
+}
+
+#[test]
+fn columns_are_checked_by_name_and_type() {
+    let actual = [column("id", "int8"), column("email", "text")];
+    assert!(
+        column_violations(&expect(&[("email", None), ("id", Some("bigint"))]), &actual).is_empty()
+    );
+    assert_eq!(
+        column_violations(&expect(&[("id", Some("integer")), ("name", None)]), &actual),
+        ["column id is int8, expected integer", "missing column name"]
+    );
+    let mut exact = expect(&[("email", None), ("id", None)]);
+    exact.exact_columns = true;
+    assert_eq!(
+        column_violations(&exact, &actual),
+        ["columns are [id, email], expected exactly [email, id]"]
+    );
+}
+
+#[test]
+fn row_bounds() {
+    let contract = ResultContract {
+        min_rows: Some(1),
+        max_rows: Some(3),
+        ..ResultContract::default()
+    };
+    assert_eq!(
+        row_violation(&contract, 0).as_deref(),
+        Some("0 rows, expected at least 1")
+    );
+    assert_eq!(row_violation(&contract, 3), None);
+    assert_eq!(
+        row_violation(&contract, 4).as_deref(),
+        Some("4 rows, expected at most 3")
+    );
+    assert_eq!(row_violation(&ResultContract::default(), 10), None);
+}
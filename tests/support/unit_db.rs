@@ -1,5 +1,5 @@
 use super::*;
-use crate::types::{QueryOptions, RuntimeConfig};
+use crate::types::{QueryOptions, RedactAction, RedactionRule, ReplicationRole, RuntimeConfig};
 
 #[test]
 fn parse_helpers_error_paths() {
@@ -26,6 +26,29 @@ fn parse_helpers_error_paths() {
     assert_eq!(parse_text(&Value::Null), "");
 }
 
+#[test]
+fn session_settings_become_startup_options() {
+    let mut cfg = SessionConfig {
+        dsn_secret: Some("postgresql://localhost/postgres?options=-c%20jit%3Doff".to_string()),
+        settings: Some(
+            [
+                ("search_path".to_string(), "app, public".to_string()),
+                ("work_mem".to_string(), "64MB".to_string()),
+            ]
+            .into(),
+        ),
+        ..SessionConfig::default()
+    };
+    let pg_cfg = pg_config(&cfg).unwrap();
+    assert_eq!(
+        pg_cfg.get_options(),
+        Some("-c jit=off -c search_path=app,\\ public -c work_mem=64MB")
+    );
+
+    cfg.settings = Some([("bad name".to_string(), "x".to_string())].into());
+    assert!(matches!(pg_config(&cfg), Err(ExecError::Connect(_))));
+}
+
 #[test]
 fn build_params_types() {
     let values = vec![
@@ -50,7 +73,7 @@ fn build_params_types() {
         Type::JSONB,
         Type::VARCHAR,
     ];
-    let params = build_params(&values, &tys).expect("build params");
+    let params = build_params(&values, &tys, &ExtTypeMap::new()).expect("build params");
     let refs = build_param_refs(&params);
     assert_eq!(refs.len(), 9);
 }
@@ -125,3 +148,1062 @@ async fn postgres_executor_success_and_sql_error() {
         .await;
     assert!(matches!(err, Err(ExecError::Sql { .. })));
 }
+
+#[tokio::test]
+async fn postgres_executor_sends_the_annotation_and_keeps_error_positions() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let mut opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    opts.annotation = Some("/* afpsql id=q1 session=default */ ".to_string());
+
+    let out = exec
+        .execute(
+            "default",
+            &cfg,
+            "select query from pg_stat_activity where pid = pg_backend_pid()",
+            &[],
+            &opts,
+        )
+        .await
+        .expect("ok");
+    let ExecOutcome::Rows(rows) = out else {
+        panic!("expected rows");
+    };
+    let query = rows[0]["query"].as_str().expect("query text");
+    assert!(
+        query.starts_with("/* afpsql id=q1 session=default */ "),
+        "{query}"
+    );
+
+    let err = exec
+        .execute(
+            "default",
+            &cfg,
+            "select 1 from no_such_table_afpsql",
+            &[],
+            &opts,
+        )
+        .await;
+    match err {
+        Err(ExecError::Sql { position, .. }) => assert_eq!(position.as_deref(), Some("15")),
+        other => panic!("expected sql error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_runs_the_column_profile() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let (sql, params) = crate::sqlgen::build_column_profile(
+        crate::sqlgen::RowSource::Query(
+            "select * from (values (1, 'a'), (2, 'a'), (10, 'b'), ($1::int, null)) v(n, s)",
+        ),
+        vec![serde_json::json!(10)],
+        None,
+        &[RedactionRule {
+            column: "s".to_string(),
+            action: RedactAction::Mask,
+        }],
+        1,
+        3,
+        None,
+    )
+    .expect("sql");
+    let out = exec
+        .execute("default", &cfg, &sql, &params, &opts)
+        .await
+        .expect("ok");
+    let ExecOutcome::Rows(rows) = out else {
+        panic!("expected rows");
+    };
+    assert_eq!(rows.len(), 2);
+    let (n, s) = (&rows[0], &rows[1]);
+    assert_eq!(n["column"], "n");
+    assert_eq!(n["rows"], 4);
+    assert_eq!(n["distinct_count"], 3);
+    assert_eq!(
+        n["top_values"],
+        serde_json::json!([{"value": 10, "count": 2}])
+    );
+    let counts: Vec<&Value> = n["histogram"]
+        .as_array()
+        .expect("histogram")
+        .iter()
+        .map(|b| &b["count"])
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            &serde_json::json!(2),
+            &serde_json::json!(0),
+            &serde_json::json!(2)
+        ]
+    );
+    assert_eq!(n["redacted"], false);
+    assert_eq!(s["null_fraction"], 0.25);
+    assert_eq!(s["distinct_count"], 2);
+    assert_eq!(s["redacted"], true);
+    assert_eq!(s["top_values"], Value::Null);
+    assert_eq!(s["histogram"], Value::Null);
+}
+
+#[test]
+fn do_block_helpers_validate_input() {
+    assert!(build_do_block("begin null; end").is_ok());
+    assert!(matches!(
+        build_do_block("begin perform $afpsql_block$; end"),
+        Err(ExecError::InvalidParams(_))
+    ));
+    assert!(validate_block_var_name("tenant_id").is_ok());
+    assert!(validate_block_var_name("1bad").is_err());
+    assert!(validate_block_var_name("bad-name").is_err());
+}
+
+#[tokio::test]
+async fn postgres_executor_block_captures_notices() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let run = exec
+        .execute_block(
+            "default",
+            &cfg,
+            "begin raise notice 'n=%', current_setting('afpsql.n'); end",
+            &[("n".to_string(), "7".to_string())],
+            &opts,
+        )
+        .await;
+    run.result.expect("block ok");
+    assert_eq!(run.notices.len(), 1);
+    assert_eq!(run.notices[0].message, "n=7");
+
+    let run = exec
+        .execute_block(
+            "default",
+            &cfg,
+            "begin raise notice 'step 1'; raise exception 'step 2 failed'; end",
+            &[],
+            &opts,
+        )
+        .await;
+    assert!(matches!(run.result, Err(ExecError::Sql { .. })));
+    let messages: Vec<_> = run.notices.iter().map(|n| n.message.as_str()).collect();
+    assert_eq!(messages, ["step 1"]);
+}
+
+#[tokio::test]
+async fn postgres_executor_copy_in_loads_rows() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let table = format!("afpsql_copy_{}", std::process::id());
+    exec.execute(
+        "default",
+        &cfg,
+        &format!("create table {table} (id int, note text)"),
+        &[],
+        &opts,
+    )
+    .await
+    .expect("create");
+    let copied = exec
+        .copy_in(
+            "default",
+            &cfg,
+            &format!("copy {table} (id, note) from stdin"),
+            b"1\ta\n2\t\\N\n".to_vec(),
+            &opts,
+        )
+        .await;
+    let mut rt = RuntimeConfig::default();
+    rt.require_approval = true;
+    rt.approval_row_threshold = 2;
+    let guarded = rt.resolve_options(&QueryOptions::default());
+    let over = exec
+        .copy_in(
+            "default",
+            &cfg,
+            &format!("copy {table} (id, note) from stdin"),
+            b"3\tc\n4\td\n5\te\n".to_vec(),
+            &guarded,
+        )
+        .await;
+    let count = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("select count(*) as n from {table}"),
+            &[],
+            &opts,
+        )
+        .await;
+    let _ = exec
+        .execute("default", &cfg, &format!("drop table {table}"), &[], &opts)
+        .await;
+    assert_eq!(copied.expect("copy ok"), 2);
+    match over {
+        Err(ExecError::ApprovalRequired(reason)) => assert!(reason.contains("3 rows")),
+        other => panic!("unexpected {other:?}"),
+    }
+    match count.expect("count") {
+        ExecOutcome::Rows(rows) => assert_eq!(rows[0]["n"], 2),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_copy_in_verified_rolls_back_rejected_batches() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let table = format!("afpsql_verified_{}", std::process::id());
+    exec.execute(
+        "default",
+        &cfg,
+        &format!("create table {table} (id int, note text)"),
+        &[],
+        &opts,
+    )
+    .await
+    .expect("create");
+    let copy = format!("copy {table} (id, note) from stdin");
+    let readback = format!(
+        "select to_jsonb(r) from (select id, note from {table} where xmin = pg_current_xact_id()::xid) r"
+    );
+    let reject = |_: &[Value]| false;
+    let rejected = exec
+        .copy_in_verified(
+            "default",
+            &cfg,
+            &copy,
+            b"1\ta\n".to_vec(),
+            &CopyReadback {
+                sql: &readback,
+                keep: &reject,
+            },
+            &opts,
+        )
+        .await;
+    let accept = |_: &[Value]| true;
+    let kept = exec
+        .copy_in_verified(
+            "default",
+            &cfg,
+            &copy,
+            b"2\tb\n".to_vec(),
+            &CopyReadback {
+                sql: &readback,
+                keep: &accept,
+            },
+            &opts,
+        )
+        .await;
+    let all = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("select id from {table}"),
+            &[],
+            &opts,
+        )
+        .await;
+    let _ = exec
+        .execute("default", &cfg, &format!("drop table {table}"), &[], &opts)
+        .await;
+    let (copied, rows, keep) = rejected.expect("rejected copy");
+    assert_eq!((copied, keep), (1, false));
+    assert_eq!(rows, vec![serde_json::json!({"id": 1, "note": "a"})]);
+    let (_, rows, keep) = kept.expect("kept copy");
+    assert!(keep);
+    assert_eq!(rows, vec![serde_json::json!({"id": 2, "note": "b"})]);
+    let ExecOutcome::Rows(all) = all.expect("select") else {
+        panic!("expected rows");
+    };
+    assert_eq!(all.len(), 1);
+}
+
+#[tokio::test]
+async fn postgres_executor_script_rolls_back_only_the_failed_statement() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let table = format!("afpsql_script_{}", std::process::id());
+    let statements = vec![
+        format!("create table {table} (id int primary key)"),
+        format!("insert into {table} values (1)"),
+        format!("insert into {table} values (1)"),
+        format!("insert into {table} values (2)"),
+    ];
+    let run = exec
+        .execute_script("default", &cfg, &statements, &[], &opts)
+        .await;
+    let ending = vec![
+        format!("insert into {table} values (3)"),
+        "commit".to_string(),
+        format!("insert into {table} values (4)"),
+    ];
+    let ended = exec
+        .execute_script("default", &cfg, &ending, &[], &opts)
+        .await;
+    let all = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("select id from {table} order by id"),
+            &[],
+            &opts,
+        )
+        .await;
+    let _ = exec
+        .execute("default", &cfg, &format!("drop table {table}"), &[], &opts)
+        .await;
+    assert!(run.committed.is_ok());
+    assert_eq!(run.outcomes.len(), 4);
+    assert!(matches!(
+        &run.outcomes[2],
+        Err(ExecError::Sql { sqlstate, .. }) if sqlstate == "23505"
+    ));
+    assert!(run.outcomes[3].is_ok());
+    assert!(ended.ended_early && ended.committed.is_err());
+    assert_eq!(ended.outcomes.len(), 2);
+    let ExecOutcome::Rows(all) = all.expect("select") else {
+        panic!("expected rows");
+    };
+    assert_eq!(
+        all,
+        vec![
+            serde_json::json!({"id": 1}),
+            serde_json::json!({"id": 2}),
+            serde_json::json!({"id": 3})
+        ]
+    );
+}
+
+#[tokio::test]
+async fn postgres_executor_defers_constraints_and_skips_triggers_as_replica() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let (parent, child) = (
+        format!("afpsql_parent_{}", std::process::id()),
+        format!("afpsql_child_{}", std::process::id()),
+    );
+    let setup = [
+        format!("create table {parent} (id int primary key)"),
+        format!("create table {child} (parent_id int references {parent} deferrable)"),
+    ];
+    for sql in &setup {
+        exec.execute("default", &cfg, sql, &[], &opts)
+            .await
+            .expect("create");
+    }
+    // The child row comes before its parent.
+    let statements = vec![
+        format!("insert into {child} values ($1)"),
+        format!("insert into {parent} values ($1)"),
+    ];
+    let script = |id: i64, opts: ResolvedOptions| {
+        let (exec, cfg, statements) = (&exec, &cfg, &statements);
+        async move {
+            let params = [serde_json::json!(id)];
+            exec.execute_script("default", cfg, statements, &params, &opts)
+                .await
+        }
+    };
+    let immediate = script(1, opts.clone()).await;
+    let deferred = script(
+        2,
+        ResolvedOptions {
+            defer_constraints: true,
+            ..opts.clone()
+        },
+    )
+    .await;
+    let orphan = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("insert into {child} values (99)"),
+            &[],
+            &ResolvedOptions {
+                replication_role: Some(ReplicationRole::Replica),
+                ..opts.clone()
+            },
+        )
+        .await;
+    let _ = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("drop table {child}, {parent}"),
+            &[],
+            &opts,
+        )
+        .await;
+    assert!(matches!(
+        &immediate.outcomes[0],
+        Err(ExecError::Sql { sqlstate, .. }) if sqlstate == "23503"
+    ));
+    assert!(deferred.committed.is_ok());
+    assert!(deferred.outcomes.iter().all(Result::is_ok));
+    assert!(orphan.is_ok());
+}
+
+#[tokio::test]
+async fn postgres_executor_sets_context_for_the_transaction_only() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let mut tenant = opts.clone();
+    tenant
+        .context
+        .insert("app.tenant_id".to_string(), "42".to_string());
+    let sql = "select current_setting('app.tenant_id', true) as tenant";
+    let rows = |outcome| match outcome {
+        Ok(ExecOutcome::Rows(rows)) => rows,
+        other => panic!("expected rows, got {other:?}"),
+    };
+    let scoped = rows(exec.execute("default", &cfg, sql, &[], &tenant).await);
+    assert_eq!(scoped[0]["tenant"], "42");
+    // A later query on the same pool does not inherit it.
+    let after = rows(exec.execute("default", &cfg, sql, &[], &opts).await);
+    assert!(after[0]["tenant"].as_str().unwrap_or_default().is_empty());
+}
+
+#[tokio::test]
+async fn postgres_executor_describes_without_running() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let (params, columns) = exec
+        .describe(
+            "default",
+            &cfg,
+            "select $1::int + 1 as n, $2::text as label",
+            &opts,
+        )
+        .await
+        .expect("describe");
+    assert_eq!(params, ["int4", "text"]);
+    let columns: Vec<(&str, &str)> = columns
+        .iter()
+        .map(|c| (c.name.as_str(), c.type_name.as_str()))
+        .collect();
+    assert_eq!(columns, [("n", "int4"), ("label", "text")]);
+    // A write is prepared, not executed.
+    let (params, columns) = exec
+        .describe("default", &cfg, "create table afpsql_never (id int)", &opts)
+        .await
+        .expect("describe ddl");
+    assert!(params.is_empty() && columns.is_empty());
+    let exists = exec
+        .execute(
+            "default",
+            &cfg,
+            "select to_regclass('afpsql_never') is not null as found",
+            &[],
+            &opts,
+        )
+        .await;
+    assert!(matches!(exists, Ok(ExecOutcome::Rows(rows)) if rows[0]["found"] == false));
+}
+
+#[tokio::test]
+async fn postgres_executor_prepares_the_statement_transaction() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let run = |sql: String, opts: ResolvedOptions| {
+        let (exec, cfg) = (&exec, &cfg);
+        async move { exec.execute("default", cfg, &sql, &[], &opts).await }
+    };
+    let table = format!("afpsql_2pc_{}", std::process::id());
+    let gid = format!("afpsql_test_{}", std::process::id());
+    run(format!("create table {table} (id int)"), opts.clone())
+        .await
+        .expect("create");
+    let prepared = run(
+        format!("insert into {table} values (1)"),
+        ResolvedOptions {
+            prepare_transaction: Some(gid.clone()),
+            ..opts.clone()
+        },
+    )
+    .await;
+    let count = format!("select count(*) as n from {table}");
+    let seen = run(count.clone(), opts.clone()).await;
+    let finish = ResolvedOptions {
+        autocommit: true,
+        ..opts.clone()
+    };
+    let committed = run(format!("commit prepared '{gid}'"), finish).await;
+    let after = run(count, opts.clone()).await;
+    let _ = run(format!("drop table {table}"), opts.clone()).await;
+
+    match prepared {
+        // Servers run with max_prepared_transactions = 0 by default.
+        Err(ExecError::Sql { sqlstate, .. }) if sqlstate == "55000" => return,
+        other => assert!(matches!(other, Ok(ExecOutcome::Command { affected: 1 }))),
+    }
+    let n = |outcome: Result<ExecOutcome, ExecError>| match outcome {
+        Ok(ExecOutcome::Rows(rows)) => rows[0]["n"].clone(),
+        other => panic!("expected rows, got {other:?}"),
+    };
+    assert_eq!(n(seen), 0);
+    assert!(committed.is_ok());
+    assert_eq!(n(after), 1);
+}
+
+#[tokio::test]
+async fn postgres_executor_queries_see_the_exported_snapshot() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let mut opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let table = format!("afpsql_snapshot_{}", std::process::id());
+    let run = |sql: String, opts: ResolvedOptions| {
+        let (exec, cfg) = (&exec, &cfg);
+        async move { exec.execute("default", cfg, &sql, &[], &opts).await }
+    };
+    run(format!("create table {table} (id int)"), opts.clone())
+        .await
+        .expect("create");
+    run(format!("insert into {table} values (1)"), opts.clone())
+        .await
+        .expect("insert");
+    let snapshot = exec.export_snapshot("default", &cfg).await.expect("export");
+    run(format!("insert into {table} values (2)"), opts.clone())
+        .await
+        .expect("insert");
+
+    opts.snapshot = Some(snapshot.clone());
+    let seen = run(
+        format!("select count(*)::int as n from {table}"),
+        opts.clone(),
+    )
+    .await;
+    assert!(exec.release_snapshot("default", &snapshot).await);
+    assert!(!exec.release_snapshot("default", &snapshot).await);
+    opts.snapshot = None;
+    let _ = run(format!("drop table {table}"), opts).await;
+    match seen.expect("select") {
+        ExecOutcome::Rows(rows) => assert_eq!(rows[0]["n"], 1),
+        ExecOutcome::Command { .. } => panic!("expected rows"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_workspace_keeps_temp_tables() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let mut opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    opts.workspace = Some("ws".to_string());
+    exec.open_workspace("default", &cfg, "ws")
+        .await
+        .expect("open");
+    exec.execute(
+        "default",
+        &cfg,
+        "create temp table scratch (n int)",
+        &[],
+        &opts,
+    )
+    .await
+    .expect("create temp");
+    exec.copy_in(
+        "default",
+        &cfg,
+        "copy scratch from stdin",
+        b"1\n2\n".to_vec(),
+        &opts,
+    )
+    .await
+    .expect("copy");
+    let out = exec
+        .execute(
+            "default",
+            &cfg,
+            "select count(*)::int as n from scratch",
+            &[],
+            &opts,
+        )
+        .await
+        .expect("select");
+    match out {
+        ExecOutcome::Rows(rows) => assert_eq!(rows[0]["n"], 2),
+        ExecOutcome::Command { .. } => panic!("expected rows"),
+    }
+
+    assert!(exec.close_workspace("ws").await);
+    assert!(!exec.close_workspace("ws").await);
+    let err = exec.execute("default", &cfg, "select 1", &[], &opts).await;
+    assert!(matches!(err, Err(ExecError::Internal(_))));
+}
+
+#[tokio::test]
+async fn postgres_executor_reconnects_a_dropped_workspace() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        settings: Some([("search_path".to_string(), "app, public".to_string())].into()),
+        ..Default::default()
+    };
+    let pooled = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let mut opts = pooled.clone();
+    opts.workspace = Some("ws".to_string());
+    exec.open_workspace("default", &cfg, "ws")
+        .await
+        .expect("open");
+    assert!(!exec.reconnect_workspace("ws").await.expect("check"));
+
+    let pid = match exec
+        .execute(
+            "default",
+            &cfg,
+            "select pg_backend_pid() as pid",
+            &[],
+            &opts,
+        )
+        .await
+        .expect("pid")
+    {
+        ExecOutcome::Rows(rows) => rows[0]["pid"].clone(),
+        ExecOutcome::Command { .. } => panic!("expected rows"),
+    };
+    exec.execute(
+        "default",
+        &cfg,
+        "select pg_terminate_backend($1::int)",
+        &[pid],
+        &pooled,
+    )
+    .await
+    .expect("terminate");
+
+    let mut reconnected = false;
+    for _ in 0..50 {
+        if exec.reconnect_workspace("ws").await.expect("reconnect") {
+            reconnected = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(reconnected);
+    let out = exec
+        .execute("default", &cfg, "show search_path", &[], &opts)
+        .await
+        .expect("show");
+    match out {
+        ExecOutcome::Rows(rows) => assert_eq!(rows[0]["search_path"], "app, public"),
+        ExecOutcome::Command { .. } => panic!("expected rows"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_binds_extension_types() {
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    // Extension types are learned when the pool is created, so install first.
+    PostgresExecutor::new()
+        .execute(
+            "default",
+            &cfg,
+            "create extension if not exists hstore",
+            &[],
+            &opts,
+        )
+        .await
+        .expect("create extension");
+
+    let exec = PostgresExecutor::new();
+    let out = exec
+        .execute(
+            "default",
+            &cfg,
+            "select $1::hstore -> 'a' as a, $1::hstore ? 'z' as has_z",
+            &[serde_json::json!({"a": "x", "z": null})],
+            &opts,
+        )
+        .await
+        .expect("hstore param");
+    match out {
+        ExecOutcome::Rows(rows) => {
+            assert_eq!(rows[0]["a"], "x");
+            assert_eq!(rows[0]["has_z"], true);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_redacts_by_source_table() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let table = format!("afpsql_redact_{}", std::process::id());
+    let mut rt = RuntimeConfig::default();
+    rt.redact = vec![crate::types::RedactionRule {
+        column: format!("{table}.secret"),
+        action: crate::types::RedactAction::Mask,
+    }];
+    let opts = rt.resolve_options(&QueryOptions::default());
+    exec.execute(
+        "default",
+        &cfg,
+        &format!("create table {table} (id int, secret text)"),
+        &[],
+        &opts,
+    )
+    .await
+    .expect("create");
+    let out = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("insert into {table} values (1, 's') returning id, secret, 'x' as secret2"),
+            &[],
+            &opts,
+        )
+        .await;
+    let plain = exec
+        .execute("default", &cfg, "select 's' as secret", &[], &opts)
+        .await;
+    let _ = exec
+        .execute("default", &cfg, &format!("drop table {table}"), &[], &opts)
+        .await;
+    match (out.expect("insert"), plain.expect("select")) {
+        (ExecOutcome::Rows(rows), ExecOutcome::Rows(plain)) => {
+            assert_eq!(rows[0]["secret"], crate::redact::MASK);
+            assert_eq!(rows[0]["id"], 1);
+            assert_eq!(plain[0]["secret"], "s");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_parks_ddl_and_large_writes() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let table = format!("afpsql_approval_{}", std::process::id());
+    let open = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let mut rt = RuntimeConfig::default();
+    rt.require_approval = true;
+    rt.approval_row_threshold = 2;
+    let guarded = rt.resolve_options(&QueryOptions::default());
+
+    let create = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("create table {table} (id int)"),
+            &[],
+            &guarded,
+        )
+        .await;
+    assert!(matches!(create, Err(ExecError::ApprovalRequired(_))));
+
+    exec.execute(
+        "default",
+        &cfg,
+        &format!("create table {table} (id int)"),
+        &[],
+        &open,
+    )
+    .await
+    .expect("create");
+    let small = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("insert into {table} select generate_series(1, 2)"),
+            &[],
+            &guarded,
+        )
+        .await;
+    let large = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("insert into {table} select generate_series(1, 3) returning id"),
+            &[],
+            &guarded,
+        )
+        .await;
+    let read = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("select * from {table}, generate_series(1, 5)"),
+            &[],
+            &guarded,
+        )
+        .await;
+    let truncate = exec
+        .execute("default", &cfg, &format!("truncate {table}"), &[], &guarded)
+        .await;
+    let drop = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("drop table {table}"),
+            &[],
+            &guarded,
+        )
+        .await;
+    let count = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("select count(*) as n from {table}"),
+            &[],
+            &open,
+        )
+        .await;
+    let _ = exec
+        .execute("default", &cfg, &format!("drop table {table}"), &[], &open)
+        .await;
+
+    assert!(matches!(small, Ok(ExecOutcome::Command { affected: 2 })));
+    match large {
+        Err(ExecError::ApprovalRequired(reason)) => assert!(reason.contains("3 rows")),
+        other => panic!("unexpected {other:?}"),
+    }
+    assert!(matches!(read, Ok(ExecOutcome::Rows(ref rows)) if rows.len() == 10));
+    match truncate {
+        Err(ExecError::ApprovalRequired(reason)) => assert!(reason.contains(&table)),
+        other => panic!("unexpected {other:?}"),
+    }
+    // Named though the trial dropped it.
+    match drop {
+        Err(ExecError::ApprovalRequired(reason)) => {
+            assert!(reason.contains(&format!("public.{table}")), "{reason}");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    match count.expect("count") {
+        ExecOutcome::Rows(rows) => assert_eq!(rows[0]["n"], 2),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_asks_approval_for_blocks() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let table = format!("afpsql_block_approval_{}", std::process::id());
+    let open = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let mut rt = RuntimeConfig::default();
+    rt.require_approval = true;
+    rt.approval_row_threshold = 2;
+    let guarded = rt.resolve_options(&QueryOptions::default());
+    exec.execute(
+        "default",
+        &cfg,
+        &format!("create table {table} (id int)"),
+        &[],
+        &open,
+    )
+    .await
+    .expect("create");
+
+    let block = |body: String| {
+        let (exec, cfg, guarded) = (&exec, &cfg, &guarded);
+        async move {
+            exec.execute_block("default", cfg, &body, &[], guarded)
+                .await
+                .result
+        }
+    };
+    let drop = block(format!("begin drop table {table}; end")).await;
+    let large = block(format!(
+        "begin insert into {table} select generate_series(1, 3); end"
+    ))
+    .await;
+    let small = block(format!("begin insert into {table} values (1); end")).await;
+    let count = exec
+        .execute(
+            "default",
+            &cfg,
+            &format!("select count(*) as n from {table}"),
+            &[],
+            &open,
+        )
+        .await;
+    let _ = exec
+        .execute("default", &cfg, &format!("drop table {table}"), &[], &open)
+        .await;
+
+    assert!(
+        matches!(drop, Err(ExecError::ApprovalRequired(_))),
+        "{drop:?}"
+    );
+    match large {
+        Err(ExecError::ApprovalRequired(reason)) => assert!(reason.contains("3 rows")),
+        other => panic!("unexpected {other:?}"),
+    }
+    small.expect("small block");
+    match count.expect("count") {
+        ExecOutcome::Rows(rows) => assert_eq!(rows[0]["n"], 1),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn postgres_executor_bumps_schema_generation_on_ddl() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    exec.execute("default", &cfg, "set local work_mem = '8MB'", &[], &opts)
+        .await
+        .expect("set");
+    assert_eq!(exec.schema_generation(), 0);
+    exec.execute(
+        "default",
+        &cfg,
+        "create temp table ddl_probe (n int) on commit drop",
+        &[],
+        &opts,
+    )
+    .await
+    .expect("create");
+    assert_eq!(exec.schema_generation(), 1);
+}
+
+#[tokio::test]
+async fn postgres_executor_explains_a_parameterized_statement() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let out = exec
+        .execute(
+            "default",
+            &cfg,
+            "explain (format json) select relname from pg_class where relpages > $1::int",
+            &[serde_json::json!(10)],
+            &opts,
+        )
+        .await
+        .expect("ok");
+    let ExecOutcome::Rows(rows) = out else {
+        panic!("expected rows");
+    };
+    let snap = crate::plan::snapshot(&rows[0]).expect("plan");
+    assert!(snap.shape[0].contains("pg_class"), "{:?}", snap.shape);
+    assert!(snap.total_cost > 0.0);
+}
+
+#[tokio::test]
+async fn postgres_executor_runs_vacuum_outside_a_transaction() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let mut opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let in_tx = exec
+        .execute("default", &cfg, "vacuum pg_catalog.pg_am", &[], &opts)
+        .await;
+    assert!(matches!(in_tx, Err(ExecError::Sql { .. })), "{in_tx:?}");
+    opts.autocommit = true;
+    let out = exec
+        .execute("default", &cfg, "vacuum pg_catalog.pg_am", &[], &opts)
+        .await
+        .expect("vacuum");
+    assert!(matches!(out, ExecOutcome::Command { .. }));
+}
+
+#[tokio::test]
+async fn postgres_executor_reads_pages_through_a_portal() {
+    let exec = PostgresExecutor::new();
+    let cfg = SessionConfig {
+        dsn_secret: Some(test_dsn()),
+        ..Default::default()
+    };
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let (tx, mut rx) = mpsc::channel(8);
+    exec.execute_pages(
+        "default",
+        &cfg,
+        "select n from generate_series(1, $1::int) as n",
+        &[serde_json::json!(5)],
+        &opts,
+        2,
+        tx,
+    )
+    .await
+    .expect("pages");
+    let mut sizes = vec![];
+    while let Some(page) = rx.recv().await {
+        sizes.push(page.len());
+    }
+    assert_eq!(sizes, [2, 2, 1]);
+
+    let (tx, _rx) = mpsc::channel(8);
+    let err = exec
+        .execute_pages(
+            "default",
+            &cfg,
+            "set local work_mem = '4MB'",
+            &[],
+            &opts,
+            2,
+            tx,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("must return rows"), "{err}");
+}
@@ -0,0 +1,39 @@
+use super::*;
+
+#[test]
+fn encode_writes_one_object_per_row() {
+    let rows = vec![
+        DeadRow {
+            position: 3,
+            error: "duplicate key".to_string(),
+            row: Some(json!({"id": 1})),
+            raw: None,
+        },
+        DeadRow {
+            position: 7,
+            error: "expected 2 fields, found 3".to_string(),
+            row: None,
+            raw: Some("1,a,b".to_string()),
+        },
+    ];
+    let text = String::from_utf8(encode(&rows)).unwrap();
+    let lines: Vec<Value> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            json!({"position": 3, "error": "duplicate key", "row": {"id": 1}, "raw": null}),
+            json!({"position": 7, "error": "expected 2 fields, found 3", "row": null, "raw": "1,a,b"}),
+        ]
+    );
+    assert!(encode(&[]).is_empty());
+}
+
+#[test]
+fn create_table_sql_checks_the_name() {
+    let sql = create_table_sql("etl.failed_orders").unwrap();
+    assert!(sql.starts_with("create table if not exists \"etl\".\"failed_orders\" ("));
+    assert!(create_table_sql("a.b.c").is_err());
+}
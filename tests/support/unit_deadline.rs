@@ -0,0 +1,41 @@
+use super::*;
+
+#[test]
+fn parses_rfc3339_variants() {
+    let ms = |s: &str| Deadline::Rfc3339(s.to_string()).epoch_ms();
+    assert_eq!(ms("1970-01-01T00:00:00Z"), Ok(0));
+    assert_eq!(ms("2026-10-16T12:00:00Z"), Ok(1_792_152_000_000));
+    assert_eq!(ms("2026-10-16T12:00:00.25Z"), Ok(1_792_152_000_250));
+    assert_eq!(ms("2026-10-16T14:00:00+02:00"), Ok(1_792_152_000_000));
+    assert_eq!(
+        ms("2026-10-16 07:00:00.123456-05:00"),
+        Ok(1_792_152_000_123)
+    );
+    assert_eq!(ms("2000-02-29T00:00:00z"), Ok(951_782_400_000));
+}
+
+#[test]
+fn rejects_malformed_deadlines() {
+    for bad in [
+        "2026-10-16",
+        "2026-10-16T12:00:00",
+        "2026-13-01T00:00:00Z",
+        "2026-10-16T12:00:00.Z",
+        "2026-10-16T12:00:00+0200",
+        "tomorrow morning please",
+    ] {
+        let err = Deadline::Rfc3339(bad.to_string()).epoch_ms().unwrap_err();
+        assert!(err.contains("invalid deadline"), "{bad}: {err}");
+    }
+}
+
+#[test]
+fn remaining_saturates_and_cli_values_pick_a_form() {
+    assert_eq!(Deadline::EpochMs(1_500).remaining_ms(1_000), Ok(500));
+    assert_eq!(Deadline::EpochMs(1_000).remaining_ms(2_000), Ok(0));
+    assert_eq!(Deadline::from_arg("1500"), Deadline::EpochMs(1_500));
+    assert_eq!(
+        Deadline::from_arg("2026-10-16T12:00:00Z"),
+        Deadline::Rfc3339("2026-10-16T12:00:00Z".to_string())
+    );
+}
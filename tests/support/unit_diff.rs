@@ -0,0 +1,50 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn row_diff_compares_rows_as_a_multiset() {
+    let old = vec![json!({"n": 1}), json!({"n": 1}), json!({"n": 2})];
+    let new = vec![json!({"n": 3}), json!({"n": 1}), json!({"n": 2})];
+    let (added, removed) = row_diff(&old, &new);
+    assert_eq!(added, vec![json!({"n": 3})]);
+    assert_eq!(removed, vec![json!({"n": 1})]);
+
+    let (added, removed) = row_diff(&new, &new);
+    assert!(added.is_empty() && removed.is_empty());
+}
+
+#[test]
+fn swap_returns_the_previous_rows_and_evicts_the_least_recently_used() {
+    let mut baselines = Baselines::default();
+    assert_eq!(baselines.swap("a".to_string(), vec![json!(1)], 2), None);
+    assert_eq!(baselines.swap("b".to_string(), vec![json!(2)], 2), None);
+    assert_eq!(
+        baselines.swap("a".to_string(), vec![json!(3)], 2),
+        Some(vec![json!(1)])
+    );
+    // "b" is now the oldest and makes room for "c".
+    assert_eq!(baselines.swap("c".to_string(), vec![json!(4)], 2), None);
+    assert_eq!(baselines.swap("b".to_string(), vec![json!(5)], 2), None);
+    assert_eq!(
+        baselines.swap("c".to_string(), vec![json!(6)], 2),
+        Some(vec![json!(4)])
+    );
+}
+
+#[test]
+fn changed_rows_keeps_rows_new_or_changed_for_their_key() {
+    let key = vec!["id".to_string()];
+    assert_eq!(row_key(&json!({"id": 7, "v": 1}), &key), json!([7]));
+    assert_eq!(row_key(&json!({"v": 1}), &key), json!([null]));
+
+    let old = vec![json!({"id": 1, "v": "a"}), json!({"id": 2, "v": "b"})];
+    let new = vec![
+        json!({"id": 1, "v": "a"}),
+        json!({"id": 2, "v": "c"}),
+        json!({"id": 3, "v": "d"}),
+    ];
+    assert_eq!(
+        changed_rows(&old, new, &key),
+        vec![json!({"id": 2, "v": "c"}), json!({"id": 3, "v": "d"})]
+    );
+}
@@ -0,0 +1,38 @@
+use super::*;
+use std::time::Duration;
+
+fn elevation(ttl_ms: u64) -> Elevation {
+    Elevation {
+        grant: 0,
+        role: Some("dba".to_string()),
+        via_session: None,
+        reason: "incident 42".to_string(),
+        expires_at: Instant::now() + Duration::from_millis(ttl_ms),
+    }
+}
+
+#[test]
+fn grant_is_active_until_reverted() {
+    let mut e = Elevations::default();
+    let grant = e.grant("default", elevation(60_000));
+    assert_eq!(e.active("default").unwrap().role.as_deref(), Some("dba"));
+    assert!(e.active("other").is_none());
+    assert!(e.revert("default", grant).is_some());
+    assert!(e.active("default").is_none());
+}
+
+#[test]
+fn expired_grant_is_not_active() {
+    let mut e = Elevations::default();
+    e.grant("default", elevation(0));
+    assert!(e.active("default").is_none());
+}
+
+#[test]
+fn stale_revert_keeps_newer_grant() {
+    let mut e = Elevations::default();
+    let first = e.grant("default", elevation(60_000));
+    let second = e.grant("default", elevation(60_000));
+    assert!(e.revert("default", first).is_none());
+    assert_eq!(e.active("default").unwrap().grant, second);
+}
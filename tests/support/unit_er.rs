@@ -0,0 +1,67 @@
+use super::*;
+
+fn graph() -> (Vec<Node>, Vec<Edge>) {
+    let nodes = vec![
+        Node {
+            table: "customers".to_string(),
+            primary_key: vec!["id".to_string()],
+            estimated_rows: Some(10),
+        },
+        Node {
+            table: "order \"items\"".to_string(),
+            primary_key: vec![],
+            estimated_rows: None,
+        },
+    ];
+    let edges = vec![Edge::from(EdgeRow {
+        name: "items_customer_fk".to_string(),
+        from_table: "order \"items\"".to_string(),
+        from_columns: vec!["customer_id".to_string()],
+        to_table: "customers".to_string(),
+        to_columns: vec!["id".to_string()],
+        unique_from: false,
+        optional: true,
+    })];
+    (nodes, edges)
+}
+
+#[test]
+fn edge_cardinality_follows_a_unique_key_on_the_referencing_columns() {
+    let (_, edges) = graph();
+    assert_eq!(edges[0].cardinality, Cardinality::ManyToOne);
+    let one = Edge::from(EdgeRow {
+        name: "profile_fk".to_string(),
+        from_table: "profiles".to_string(),
+        from_columns: vec!["user_id".to_string()],
+        to_table: "users".to_string(),
+        to_columns: vec!["id".to_string()],
+        unique_from: true,
+        optional: false,
+    });
+    assert_eq!(one.cardinality, Cardinality::OneToOne);
+    assert_eq!(
+        serde_json::to_value(one.cardinality).ok(),
+        Some(serde_json::json!("one_to_one"))
+    );
+}
+
+#[test]
+fn dot_quotes_table_names() {
+    let (nodes, edges) = graph();
+    let dot = to_dot(&nodes, &edges);
+    assert!(dot.starts_with("digraph er {"));
+    assert!(dot.contains("  \"order \\\"items\\\"\";\n"));
+    assert!(
+        dot.contains("  \"order \\\"items\\\"\" -> \"customers\" [label=\"customer_id -> id\"];\n")
+    );
+}
+
+#[test]
+fn mermaid_sanitizes_entities_and_marks_optional_parents() {
+    let (nodes, edges) = graph();
+    let text = to_mermaid(&nodes, &edges);
+    assert!(text.starts_with("erDiagram\n"));
+    assert!(text.contains("  customers {\n    key id PK\n  }\n"));
+    assert!(text.contains("  order__items_\n"));
+    assert!(text.contains("  customers |o--o{ order__items_ : \"items_customer_fk\"\n"));
+}
@@ -0,0 +1,78 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn parse_reads_provider_format_and_compression() {
+    let target = ObjectUri::parse("s3://exports/daily/orders.csv.gz").unwrap();
+    assert_eq!(target.provider, Provider::S3);
+    assert_eq!(target.bucket, "exports");
+    assert_eq!(target.key, "daily/orders.csv.gz");
+    assert_eq!(target.format, Format::Csv);
+    assert!(target.gzip);
+
+    let target = ObjectUri::parse("gs://exports/orders.ndjson").unwrap();
+    assert_eq!(target.provider, Provider::Gcs);
+    assert_eq!(target.format, Format::Ndjson);
+    assert!(!target.gzip);
+
+    for bad in [
+        "file:///tmp/orders.csv",
+        "s3://exports",
+        "s3:///orders.csv",
+        "s3://exports/orders.parquet",
+        "s3://exports/orders.gz",
+    ] {
+        assert!(ObjectUri::parse(bad).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn encode_rows_writes_csv_and_ndjson() {
+    let rows = vec![
+        json!({"id": 1, "note": "plain", "tags": ["a", "b"]}),
+        json!({"id": 2, "note": "say \"hi\", then\nleave", "tags": null}),
+    ];
+    let columns = vec!["id".to_string(), "note".to_string(), "tags".to_string()];
+    let csv = String::from_utf8(encode_rows(&rows, &columns, Format::Csv, true)).unwrap();
+    assert_eq!(
+        csv,
+        "id,note,tags\n1,plain,\"[\"\"a\"\",\"\"b\"\"]\"\n2,\"say \"\"hi\"\", then\nleave\",\n"
+    );
+    let body = encode_rows(&rows[..1], &columns, Format::Csv, false);
+    assert_eq!(body, b"1,plain,\"[\"\"a\"\",\"\"b\"\"]\"\n");
+
+    let ndjson = String::from_utf8(encode_rows(&rows, &columns, Format::Ndjson, true)).unwrap();
+    let lines: Vec<Value> = ndjson
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines, rows);
+}
+
+#[cfg(feature = "object-store")]
+#[tokio::test]
+async fn write_object_uploads_gzipped_chunks() {
+    use object_store::memory::InMemory;
+    use object_store::ObjectStore;
+    use std::io::Read;
+
+    let store = InMemory::new();
+    let target = ObjectUri::parse("s3://exports/big.csv.gz").unwrap();
+    let rows: Vec<Value> = (0..CHUNK_ROWS + 5).map(|i| json!({"n": i})).collect();
+    let columns = vec!["n".to_string()];
+    let written = write_object(&store, &target, &columns, &rows)
+        .await
+        .unwrap();
+
+    let path = object_store::path::Path::from("big.csv.gz");
+    let packed = store.get(&path).await.unwrap().bytes().await.unwrap();
+    assert_eq!(packed.len() as u64, written);
+    let mut csv = String::new();
+    flate2::read::GzDecoder::new(packed.as_ref())
+        .read_to_string(&mut csv)
+        .unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), CHUNK_ROWS + 6);
+    assert_eq!(lines[0], "n");
+    assert_eq!(lines[CHUNK_ROWS + 5], (CHUNK_ROWS + 4).to_string());
+}
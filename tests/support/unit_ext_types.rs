@@ -0,0 +1,56 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn vector_binary_encoding() {
+    let v = PgVector::from_json(&json!([1.0, -2.5])).unwrap();
+    let mut out = BytesMut::new();
+    let ty = Type::new(
+        "vector".to_string(),
+        90001,
+        tokio_postgres::types::Kind::Simple,
+        "public".to_string(),
+    );
+    v.to_sql(&ty, &mut out).unwrap();
+    let mut expected = vec![0u8, 2, 0, 0];
+    expected.extend_from_slice(&1.0f32.to_be_bytes());
+    expected.extend_from_slice(&(-2.5f32).to_be_bytes());
+    assert_eq!(out.to_vec(), expected);
+    assert!(<PgVector as ToSql>::accepts(&ty));
+    assert!(!<PgVector as ToSql>::accepts(&Type::TEXT));
+
+    assert_eq!(
+        PgVector::from_json(&json!("[3,4]")).unwrap(),
+        PgVector(vec![3.0, 4.0])
+    );
+    assert!(PgVector::from_json(&json!("x")).is_err());
+    assert!(PgVector::from_json(&json!([1, "a"])).is_err());
+}
+
+#[test]
+fn bind_by_extension_type() {
+    assert_eq!(ext_type_by_name("hstore"), Some(ExtType::Hstore));
+    assert_eq!(ext_type_by_name("text"), None);
+
+    match ExtType::Hstore
+        .bind(&json!({"a": "x", "n": 1, "z": null}), 1)
+        .unwrap()
+    {
+        ExtParam::Hstore(m) => {
+            assert_eq!(m["a"].as_deref(), Some("x"));
+            assert_eq!(m["n"].as_deref(), Some("1"));
+            assert_eq!(m["z"], None);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    let err = ExtType::Hstore.bind(&json!([1]), 2).unwrap_err();
+    assert!(err.contains("$2"));
+    assert!(matches!(
+        ExtType::Citext.bind(&json!("Ab"), 1).unwrap(),
+        ExtParam::Text(s) if s == "Ab"
+    ));
+    assert!(ExtType::Vector
+        .bind(&json!({}), 3)
+        .unwrap_err()
+        .contains("$3"));
+}
@@ -0,0 +1,116 @@
+use super::*;
+
+fn page(rows: Vec<Value>) -> Page {
+    let columns = match rows.first() {
+        Some(Value::Object(first)) => first
+            .keys()
+            .map(|name| ColumnInfo {
+                name: name.clone(),
+                type_name: "json".to_string(),
+            })
+            .collect(),
+        _ => vec![],
+    };
+    Page {
+        columns,
+        total_rows: rows.len(),
+        rows,
+    }
+}
+
+#[test]
+fn identical_results_pass_in_any_order() {
+    let golden = page(vec![json!({"id": 1}), json!({"id": 2})]);
+    let actual = page(vec![json!({"id": 2}), json!({"id": 1})]);
+    let diff = compare(&golden, &actual, &Tolerances::default());
+    assert!(diff.passed);
+    assert_eq!((diff.rows_added, diff.rows_removed), (0, 0));
+
+    let ordered = Tolerances {
+        ordered: true,
+        ..Tolerances::default()
+    };
+    let diff = compare(&golden, &actual, &ordered);
+    assert!(!diff.passed);
+    assert_eq!(diff.rows_changed, 2);
+    assert_eq!(diff.changed[0].index, Some(0));
+    assert_eq!(
+        diff.changed[0].cells["id"],
+        json!({"golden": 1, "actual": 2})
+    );
+}
+
+#[test]
+fn keyed_rows_report_changed_cells_within_tolerances() {
+    let golden = page(vec![
+        json!({"id": 1, "total": "10.00", "seen_at": "2026-01-01"}),
+        json!({"id": 2, "total": "5.00", "seen_at": "2026-01-01"}),
+        json!({"id": 3, "total": "1.00", "seen_at": "2026-01-01"}),
+    ]);
+    let actual = page(vec![
+        json!({"id": 1, "total": "10.004", "seen_at": "2026-02-01"}),
+        json!({"id": 2, "total": "7.00", "seen_at": "2026-02-01"}),
+        json!({"id": 4, "total": "1.00", "seen_at": "2026-02-01"}),
+    ]);
+    let tolerances = Tolerances {
+        key_columns: vec!["id".to_string()],
+        ignore_columns: vec!["seen_at".to_string()],
+        column_tolerances: HashMap::from([("total".to_string(), 0.01)]),
+        ..Tolerances::default()
+    };
+    let diff = compare(&golden, &actual, &tolerances);
+    assert!(!diff.passed);
+    assert_eq!(diff.added, vec![json!({"id": 4, "total": "1.00"})]);
+    assert_eq!(diff.removed, vec![json!({"id": 3, "total": "1.00"})]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].key, Some(json!([2])));
+    assert_eq!(
+        Value::Object(diff.changed[0].cells.clone()),
+        json!({"total": {"golden": "5.00", "actual": "7.00"}})
+    );
+
+    let lenient = Tolerances {
+        max_row_changes: 3,
+        ..tolerances
+    };
+    assert!(compare(&golden, &actual, &lenient).passed);
+}
+
+#[test]
+fn multiset_matches_rows_within_tolerance_and_flags_columns() {
+    let golden = page(vec![json!({"n": 1.0}), json!({"n": 2.0})]);
+    let actual = page(vec![json!({"n": 2.0}), json!({"n": 1.05})]);
+    let tolerances = Tolerances {
+        column_tolerances: HashMap::from([("n".to_string(), 0.1)]),
+        ..Tolerances::default()
+    };
+    assert!(compare(&golden, &actual, &tolerances).passed);
+    let edge = page(vec![json!({"n": 2.0}), json!({"n": 1.1})]);
+    assert!(compare(&golden, &edge, &tolerances).passed);
+
+    let renamed = page(vec![json!({"m": 1.0}), json!({"m": 2.0})]);
+    let diff = compare(&golden, &renamed, &Tolerances::default());
+    assert_eq!(diff.columns_added, ["m"]);
+    assert_eq!(diff.columns_removed, ["n"]);
+    assert!(!diff.passed);
+
+    // An empty result names no columns, so only the rows differ.
+    let diff = compare(&golden, &page(vec![]), &Tolerances::default());
+    assert!(diff.columns_removed.is_empty());
+    assert_eq!(diff.rows_removed, 2);
+}
+
+#[test]
+fn golden_files_round_trip() {
+    let path = std::env::temp_dir().join(format!("afpsql_golden_{}.jsonl", std::process::id()));
+    let rows = vec![json!({"id": 1}), json!({"id": 2})];
+    let golden = page(rows.clone());
+    save(&path, &golden.columns, &rows).unwrap();
+    let loaded = load(&path).unwrap();
+    assert_eq!(loaded.rows, rows);
+    assert_eq!(loaded.total_rows, 2);
+    assert!(compare(&loaded, &golden, &Tolerances::default()).passed);
+    let _ = std::fs::remove_file(&path);
+
+    assert!(handle_path("/tmp/results", "../etc/passwd").is_err());
+}
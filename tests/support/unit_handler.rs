@@ -1,7 +1,9 @@
 use super::*;
 use crate::db::{DbExecutor, ExecError, ExecOutcome};
+use crate::deadline::Deadline;
+use crate::types::{LintSeverity, ResultContract};
 use async_trait::async_trait;
-use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex, RwLock};
 
@@ -34,8 +36,38 @@ async fn emit_rows_result_paths() {
         statement_timeout_ms: 100,
         lock_timeout_ms: 100,
         read_only: false,
+        defer_constraints: false,
+        replication_role: None,
         inline_max_rows: 100,
         inline_max_bytes: 100000,
+        redact: vec![],
+        redact_salt_secret: None,
+        default_limit: None,
+        default_limit_action: DefaultLimitAction::Limit,
+        compress: None,
+        store_result: false,
+        export_uri: None,
+        verify: None,
+        publish_uri: None,
+        publish_key: vec![],
+        publish_format: PublishFormat::Row,
+        server_timing: false,
+        results_dir: None,
+        approval_row_threshold: None,
+        role: None,
+        snapshot: None,
+        autocommit: false,
+        nested_tables: BTreeMap::new(),
+        workspace: None,
+        cache_ttl_ms: None,
+        key_columns: vec![],
+        dedup_baseline: None,
+        ack_window: None,
+        max_message_bytes: None,
+        annotation: None,
+        summarize: None,
+        context: BTreeMap::new(),
+        prepare_transaction: None,
     };
     let status = emit_rows_result(
         &app,
@@ -48,6 +80,8 @@ async fn emit_rows_result_paths() {
         ],
         std::time::Instant::now(),
         &stream_opts,
+        None,
+        Timing::default(),
     )
     .await;
     assert!(matches!(status, RowEmitStatus::Sent { .. }));
@@ -60,8 +94,38 @@ async fn emit_rows_result_paths() {
         statement_timeout_ms: 100,
         lock_timeout_ms: 100,
         read_only: false,
+        defer_constraints: false,
+        replication_role: None,
         inline_max_rows: 1,
         inline_max_bytes: 10000,
+        redact: vec![],
+        redact_salt_secret: None,
+        default_limit: None,
+        default_limit_action: DefaultLimitAction::Limit,
+        compress: None,
+        store_result: false,
+        export_uri: None,
+        verify: None,
+        publish_uri: None,
+        publish_key: vec![],
+        publish_format: PublishFormat::Row,
+        server_timing: false,
+        results_dir: None,
+        approval_row_threshold: None,
+        role: None,
+        snapshot: None,
+        autocommit: false,
+        nested_tables: BTreeMap::new(),
+        workspace: None,
+        cache_ttl_ms: None,
+        key_columns: vec![],
+        dedup_baseline: None,
+        ack_window: None,
+        max_message_bytes: None,
+        annotation: None,
+        summarize: None,
+        context: BTreeMap::new(),
+        prepare_transaction: None,
     };
     let status = emit_rows_result(
         &app,
@@ -70,9 +134,256 @@ async fn emit_rows_result_paths() {
         vec![serde_json::json!({"n":1}), serde_json::json!({"n":2})],
         std::time::Instant::now(),
         &inline_opts,
+        None,
+        Timing::default(),
     )
     .await;
-    assert!(matches!(status, RowEmitStatus::TooLarge { .. }));
+    assert!(matches!(
+        status,
+        RowEmitStatus::Failed {
+            error_code: "result_too_large",
+            ..
+        }
+    ));
+    while rx.try_recv().is_ok() {}
+
+    // Asked for 3 rows at each end, but both ends fit in inline_max_rows.
+    let summary_opts = ResolvedOptions {
+        inline_max_rows: 2,
+        summarize: Some(3),
+        ..inline_opts
+    };
+    let status = emit_rows_result(
+        &app,
+        Some("q3".to_string()),
+        Some("default".to_string()),
+        (1..=5).map(|n| serde_json::json!({"n": n})).collect(),
+        std::time::Instant::now(),
+        &summary_opts,
+        None,
+        Timing::default(),
+    )
+    .await;
+    assert!(matches!(status, RowEmitStatus::Sent { .. }));
+    match rx.recv().await {
+        Some(Output::ResultSummary {
+            row_count,
+            column_stats,
+            head,
+            tail,
+            ..
+        }) => {
+            assert_eq!(row_count, 5);
+            assert_eq!(head, vec![serde_json::json!({"n": 1})]);
+            assert_eq!(tail, vec![serde_json::json!({"n": 5})]);
+            assert_eq!(column_stats["n"].max, Some(serde_json::json!(5)));
+            assert_eq!(column_stats["n"].distinct, 5);
+        }
+        other => panic!("expected result_summary, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn oversized_result_is_stored_and_paged_by_handle() {
+    let dir = std::env::temp_dir().join(format!("afpsql_handler_{}", results::new_handle()));
+    let cfg = RuntimeConfig {
+        inline_max_rows: 2,
+        results_dir: Some(dir.to_string_lossy().into_owned()),
+        ..RuntimeConfig::default()
+    };
+    let rows: Vec<Value> = (0..5).map(|n| serde_json::json!({"n": n})).collect();
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(rows)));
+
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "select n from t".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    let Some(Output::ResultStored {
+        handle, row_count, ..
+    }) = rx.recv().await
+    else {
+        panic!("expected result_stored");
+    };
+    assert_eq!(row_count, 5);
+
+    result_get(&app, "g".to_string(), handle.clone(), 3, Some(10)).await;
+    match rx.recv().await {
+        Some(Output::ResultPage {
+            rows,
+            offset,
+            total_rows,
+            ..
+        }) => {
+            assert_eq!(offset, 3);
+            assert_eq!(total_rows, 5);
+            assert_eq!(
+                rows,
+                vec![serde_json::json!({"n": 3}), serde_json::json!({"n": 4})]
+            );
+        }
+        other => panic!("expected result_page, got {other:?}"),
+    }
+
+    result_delete(&app, "d".to_string(), handle.clone()).await;
+    assert!(matches!(
+        rx.recv().await,
+        Some(Output::ResultDeleted { .. })
+    ));
+    result_get(&app, "g2".to_string(), handle, 0, None).await;
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "invalid_request");
+            assert!(error.contains("unknown result handle"));
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn result_get_rejects_path_handles() {
+    let cfg = RuntimeConfig {
+        results_dir: Some(std::env::temp_dir().to_string_lossy().into_owned()),
+        ..RuntimeConfig::default()
+    };
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 0 }));
+    result_get(&app, "g".to_string(), "../secrets".to_string(), 0, None).await;
+    match rx.recv().await {
+        Some(Output::Error { error, .. }) => assert!(error.contains("invalid result handle")),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn store_result_requires_results_dir() {
+    let (app, mut rx) =
+        test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+    let options = QueryOptions {
+        store_result: true,
+        ..QueryOptions::default()
+    };
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "select 1".to_string(),
+        vec![],
+        options,
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn replication_role_needs_allow_replication_role() {
+    for allowed in [false, true] {
+        let cfg = RuntimeConfig {
+            allow_replication_role: allowed,
+            ..RuntimeConfig::default()
+        };
+        let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 1 }));
+        let options = QueryOptions {
+            replication_role: Some(ReplicationRole::Replica),
+            ..QueryOptions::default()
+        };
+        execute_query(
+            &app,
+            Some("load".to_string()),
+            None,
+            "insert into t values (1)".to_string(),
+            vec![],
+            options,
+        )
+        .await;
+        match rx.recv().await {
+            Some(Output::Error { error_code, .. }) if !allowed => {
+                assert_eq!(error_code, "invalid_request")
+            }
+            Some(Output::Result { command_tag, .. }) if allowed => {
+                assert_eq!(command_tag, "EXECUTE 1")
+            }
+            other => panic!("unexpected {other:?} with allowed={allowed}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn signal_backend_needs_allow_backend_signals() {
+    let (app, _rx) = test_app_with_executor(
+        RuntimeConfig::default(),
+        Ok(ExecOutcome::Rows(vec![json!({"signalled": true})])),
+    );
+    let options = QueryOptions::default();
+    let refused = signal_backend(&app, None, None, 42, true, &options).await;
+    assert!(refused.is_err_and(|e| e.contains("--allow-backend-signals")));
+
+    app.config.write().await.allow_backend_signals = true;
+    let sent = signal_backend(&app, None, None, 42, true, &options).await;
+    assert_eq!(sent, Ok(true));
+}
+
+#[tokio::test]
+async fn prepare_transaction_is_never_served_from_cache() {
+    let (app, mut rx) = test_app_with_executor(
+        RuntimeConfig::default(),
+        Ok(ExecOutcome::Command { affected: 1 }),
+    );
+    let options = QueryOptions {
+        prepare_transaction: Some("g1".to_string()),
+        cache_ttl_ms: Some(60_000),
+        ..QueryOptions::default()
+    };
+    execute_query(
+        &app,
+        Some("p".to_string()),
+        None,
+        "update t set n = 1".to_string(),
+        vec![],
+        options,
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[test]
+fn query_context_keeps_session_keys_fixed() {
+    let map = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+    let session = map(&[("app.tenant_id", "42")]);
+    assert_eq!(query_context(Some(&session), None).unwrap(), session);
+    assert_eq!(
+        query_context(
+            Some(&session),
+            Some(&map(&[("app.tenant_id", "42"), ("app.user_id", "7")]))
+        )
+        .unwrap(),
+        map(&[("app.tenant_id", "42"), ("app.user_id", "7")])
+    );
+    assert!(query_context(Some(&session), Some(&map(&[("app.tenant_id", "43")]))).is_err());
+    for bad in ["tenant_id", "app.", "app.tenant id", "1app.x", "app.x;y"] {
+        assert!(
+            query_context(None, Some(&map(&[(bad, "1")]))).is_err(),
+            "{bad}"
+        );
+    }
+    assert!(query_context(None, Some(&map(&[("my_app.v2.user$id", "1")]))).is_ok());
 }
 
 struct MockExecutor {
@@ -100,17 +411,41 @@ impl DbExecutor for MockExecutor {
 fn test_app_with_executor(
     cfg: RuntimeConfig,
     result: Result<ExecOutcome, ExecError>,
+) -> (Arc<App>, mpsc::Receiver<Output>) {
+    let executor = MockExecutor {
+        result: Mutex::new(Some(result)),
+    };
+    test_app(cfg, Arc::new(executor))
+}
+
+fn test_app(
+    cfg: RuntimeConfig,
+    executor: Arc<dyn DbExecutor>,
 ) -> (Arc<App>, mpsc::Receiver<Output>) {
     let (tx, rx) = mpsc::channel(64);
     let app = Arc::new(App {
         config: RwLock::new(cfg),
-        executor: Arc::new(MockExecutor {
-            result: Mutex::new(Some(result)),
-        }),
-        writer: tx,
+        executor,
+        writer: tx.into(),
         in_flight: Mutex::new(std::collections::HashMap::new()),
         requests_total: AtomicU64::new(0),
         start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
     });
     (app, rx)
 }
@@ -169,3 +504,2718 @@ async fn execute_query_maps_executor_outcomes() {
         let _ = rx.recv().await.unwrap();
     }
 }
+
+struct BlockExecutor;
+
+#[async_trait]
+impl DbExecutor for BlockExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Command { affected: 0 })
+    }
+
+    async fn execute_block(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _body: &str,
+        vars: &[(String, String)],
+        _opts: &ResolvedOptions,
+    ) -> crate::db::BlockRun {
+        let notices = vars
+            .iter()
+            .map(|(k, v)| crate::db::Notice {
+                severity: "NOTICE".to_string(),
+                sqlstate: "00000".to_string(),
+                message: format!("{k}={v}"),
+                detail: None,
+                hint: None,
+            })
+            .collect();
+        // A `fail` var makes the block raise after its notices.
+        let result = if vars.iter().any(|(k, _)| k == "fail") {
+            Err(ExecError::Sql {
+                sqlstate: "P0001".to_string(),
+                message: "failed".to_string(),
+                detail: None,
+                hint: None,
+                position: None,
+            })
+        } else {
+            Ok(())
+        };
+        crate::db::BlockRun { notices, result }
+    }
+}
+
+#[tokio::test]
+async fn execute_block_emits_notices_then_result() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let app = Arc::new(App {
+        config: RwLock::new(RuntimeConfig::default()),
+        executor: Arc::new(BlockExecutor),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    execute_block(
+        &app,
+        Some("b1".to_string()),
+        None,
+        "begin null; end".to_string(),
+        vec![("n".to_string(), "1".to_string())],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await.unwrap() {
+        Output::Notice { message, .. } => assert_eq!(message, "n=1"),
+        _ => panic!("expected notice"),
+    }
+    match rx.recv().await.unwrap() {
+        Output::Result { command_tag, .. } => assert_eq!(command_tag, "DO"),
+        _ => panic!("expected result"),
+    }
+
+    execute_block(
+        &app,
+        Some("b2".to_string()),
+        None,
+        "begin raise exception 'failed'; end".to_string(),
+        vec![("fail".to_string(), "1".to_string())],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await.unwrap() {
+        Output::Notice { id, message, .. } => {
+            assert_eq!((id.as_deref(), message.as_str()), (Some("b2"), "fail=1"));
+        }
+        _ => panic!("expected notice"),
+    }
+    match rx.recv().await.unwrap() {
+        Output::SqlError { id, sqlstate, .. } => {
+            assert_eq!((id.as_deref(), sqlstate.as_str()), (Some("b2"), "P0001"));
+        }
+        _ => panic!("expected sql_error"),
+    }
+}
+
+#[test]
+fn apply_default_limit_truncates_or_warns() {
+    let mut opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    let rows = || {
+        (0..3)
+            .map(|n| serde_json::json!({"n": n}))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(apply_default_limit(rows(), &opts).0.len(), 3);
+
+    opts.default_limit = Some(2);
+    let (kept, limited, warning) = apply_default_limit(rows(), &opts);
+    assert_eq!((kept.len(), limited, warning), (2, Some(true), None));
+
+    opts.default_limit_action = DefaultLimitAction::Warn;
+    let (kept, limited, warning) = apply_default_limit(rows(), &opts);
+    assert_eq!((kept.len(), limited), (3, None));
+    assert!(warning.unwrap().contains("default_limit 2"));
+}
+
+#[test]
+fn batched_writes_need_approval_as_a_whole() {
+    let mut opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    assert!(batches_need_approval(&opts, "archive", Some(1_000_000)).is_none());
+
+    opts.approval_row_threshold = Some(100);
+    assert!(batches_need_approval(&opts, "archive", Some(100)).is_none());
+    let err = batches_need_approval(&opts, "archive", Some(101)).unwrap();
+    assert!(matches!(err, ExecError::ApprovalRequired(m) if m.contains("matches 101 rows")));
+    assert!(batches_need_approval(&opts, "archive", None).is_some());
+}
+
+#[test]
+fn eta_extrapolates_the_rate_so_far() {
+    assert_eq!(eta_ms(1000, 250, Some(1000)), Some(3000));
+    assert_eq!(eta_ms(1000, 1000, Some(1000)), Some(0));
+    assert_eq!(eta_ms(1000, 0, Some(1000)), None);
+    assert_eq!(eta_ms(1000, 250, None), None);
+}
+
+#[tokio::test]
+async fn execute_query_refuses_when_over_memory_budget() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cfg = RuntimeConfig::default();
+    cfg.memory_budget_bytes = 8;
+    let app = Arc::new(App::new(cfg, tx));
+    let held = app.memory.reserve(8);
+
+    execute_query(
+        &app,
+        Some("q1".to_string()),
+        None,
+        "select 1".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await.unwrap() {
+        Output::Error {
+            error_code,
+            retryable,
+            ..
+        } => {
+            assert_eq!(error_code, "backpressure");
+            assert!(retryable);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    drop(held);
+    assert_eq!(app.memory.used(), 0);
+}
+
+#[tokio::test]
+async fn import_checks_its_source_before_writing() {
+    let (app, mut rx) =
+        test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+    let request = |source: &str| ImportRequest {
+        id: "i".to_string(),
+        session: None,
+        source: source.to_string(),
+        table: "orders".to_string(),
+        columns: Default::default(),
+        batch_rows: None,
+        max_errors: 0,
+        dead_letter: None,
+        options: QueryOptions::default(),
+    };
+    for (source, expected) in [
+        ("orders.txt", "invalid_request"),
+        ("/nonexistent/orders.csv", "import_failed"),
+    ] {
+        import(&app, request(source)).await;
+        match rx.recv().await {
+            Some(Output::Error { error_code, .. }) => assert_eq!(error_code, expected),
+            other => panic!("expected error, got {other:?}"),
+        }
+    }
+}
+
+/// Refuses any `INSERT` binding a negative number, as a check constraint
+/// would.
+struct CheckedInsertExecutor;
+
+#[async_trait]
+impl DbExecutor for CheckedInsertExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        if params.iter().any(|p| p.as_i64().is_some_and(|n| n < 0)) {
+            return Err(ExecError::Sql {
+                sqlstate: "23514".to_string(),
+                message: "violates check constraint".to_string(),
+                detail: None,
+                hint: None,
+                position: None,
+            });
+        }
+        Ok(ExecOutcome::Command {
+            affected: params.len(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn insert_rows_captures_refused_rows_in_the_dead_letter() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let app = Arc::new(App::with_executor(
+        RuntimeConfig::default(),
+        tx,
+        Arc::new(CheckedInsertExecutor),
+    ));
+    let path = std::env::temp_dir().join(format!("afpsql_dead_{}.jsonl", std::process::id()));
+    let rows = vec![json!({"n": 1}), json!({"n": -2}), json!({"n": 3})];
+    insert_rows(
+        &app,
+        "i".to_string(),
+        None,
+        "counts".to_string(),
+        rows,
+        sqlgen::OnConflict::Error,
+        Some(2),
+        DeadLetter::Path(path.to_string_lossy().into_owned()),
+        QueryOptions::default(),
+    )
+    .await;
+    let written = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    for expected in [2, 3] {
+        match rx.recv().await {
+            Some(Output::Progress {
+                phase, done, total, ..
+            }) => assert_eq!((phase.as_str(), done, total), ("insert", expected, Some(3))),
+            other => panic!("expected progress, got {other:?}"),
+        }
+    }
+    match rx.recv().await {
+        Some(Output::InsertResult {
+            rows_inserted,
+            rows_rejected,
+            batches,
+            dead_letter,
+            ..
+        }) => {
+            assert_eq!((rows_inserted, rows_rejected, batches), (2, 1, 2));
+            assert_eq!((dead_letter.rows, dead_letter.error), (1, None));
+        }
+        other => panic!("expected insert_result, got {other:?}"),
+    }
+    let dead: Value = serde_json::from_str(written.trim()).unwrap();
+    assert_eq!(dead["position"], 2);
+    assert_eq!(dead["row"], json!({"n": -2}));
+    assert_eq!(dead["error"], "violates check constraint");
+}
+
+#[tokio::test]
+async fn insert_rows_over_the_approval_threshold_writes_nothing() {
+    let (app, mut rx) = test_app(approval_config(2), Arc::new(CountOnlyExecutor));
+    insert_rows(
+        &app,
+        "i".to_string(),
+        None,
+        "counts".to_string(),
+        vec![json!({"n": 1}), json!({"n": 2}), json!({"n": 3})],
+        sqlgen::OnConflict::Error,
+        Some(1),
+        DeadLetter::Path("unused.jsonl".to_string()),
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "approval_required");
+            assert!(error.contains("insert matches 3 rows"), "{error}");
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn compress_requires_feature() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let app = Arc::new(App::new(RuntimeConfig::default(), tx));
+    let options = QueryOptions {
+        stream_rows: true,
+        compress: Some(Compression::Gzip),
+        ..Default::default()
+    };
+    let target = resolve_target(&app, Some("q1"), None, &options, std::time::Instant::now()).await;
+    if crate::compress::AVAILABLE {
+        assert!(target.is_some());
+    } else {
+        assert!(target.is_none());
+        assert!(matches!(rx.recv().await, Some(Output::Error { .. })));
+    }
+}
+
+#[tokio::test]
+async fn history_records_and_replays_queries() {
+    let (app, mut rx) = test_app_with_executor(
+        RuntimeConfig::default(),
+        Err(ExecError::Sql {
+            sqlstate: "42P01".to_string(),
+            message: "missing".to_string(),
+            detail: None,
+            hint: None,
+            position: None,
+        }),
+    );
+    execute_query(
+        &app,
+        Some("q1".to_string()),
+        None,
+        "select * from t where id = $1".to_string(),
+        vec![serde_json::json!(7)],
+        QueryOptions::default(),
+    )
+    .await;
+    while rx.try_recv().is_ok() {}
+
+    history_list(&app, "h".to_string(), None).await;
+    let Some(Output::History { entries, .. }) = rx.recv().await else {
+        panic!("expected history");
+    };
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].seq, 1);
+    assert_eq!(entries[0].outcome, "sql_error");
+    assert_eq!(entries[0].error_code.as_deref(), Some("42P01"));
+    assert_eq!(entries[0].params_count, 1);
+
+    history_replay(&app, "r".to_string(), 1).await;
+    match rx.recv().await {
+        Some(Output::Result { id, .. }) => assert_eq!(id.as_deref(), Some("r")),
+        other => panic!("expected result, got {other:?}"),
+    }
+    let entries = app.history.lock().await.recent(10);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].sql, entries[0].sql);
+    assert_eq!(entries[1].params, vec![serde_json::json!(7)]);
+
+    history_replay(&app, "r2".to_string(), 99).await;
+    assert!(matches!(rx.recv().await, Some(Output::Error { .. })));
+}
+
+#[tokio::test]
+async fn approval_parks_then_approve_runs_once() {
+    let (app, mut rx) = test_app_with_executor(
+        RuntimeConfig::default(),
+        Err(ExecError::ApprovalRequired(
+            "statement takes exclusive locks on t".to_string(),
+        )),
+    );
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "drop table t".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    let Some(Output::ApprovalRequired { token, reason, .. }) = rx.recv().await else {
+        panic!("expected approval_required");
+    };
+    assert!(reason.contains("exclusive locks"));
+    assert!(
+        app.transcript
+            .lock()
+            .await
+            .render(Default::default(), None)
+            .1
+            == 0
+    );
+
+    approve(&app, "a".to_string(), token.clone(), None, None).await;
+    match rx.recv().await {
+        Some(Output::Result { id, .. }) => assert_eq!(id.as_deref(), Some("a")),
+        other => panic!("expected result, got {other:?}"),
+    }
+    approve(&app, "a2".to_string(), token, None, None).await;
+    match rx.recv().await {
+        Some(Output::Error { error, .. }) => assert!(error.contains("unknown approval token")),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+/// Parks the first statement, answers `current_user` lookups per session.
+struct PrincipalExecutor {
+    parked: Mutex<bool>,
+}
+
+#[async_trait]
+impl DbExecutor for PrincipalExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        if sql.contains("current_user") {
+            let principal = if session_name == "human" {
+                "alice"
+            } else {
+                "agent"
+            };
+            return Ok(ExecOutcome::Rows(vec![
+                serde_json::json!({"principal": principal}),
+            ]));
+        }
+        let mut parked = self.parked.lock().await;
+        if !*parked {
+            *parked = true;
+            return Err(ExecError::ApprovalRequired(
+                "exclusive locks on t".to_string(),
+            ));
+        }
+        Ok(ExecOutcome::Command { affected: 1 })
+    }
+}
+
+#[tokio::test]
+async fn dual_control_requires_distinct_approver_principal() {
+    let audit = std::env::temp_dir().join(format!("afpsql_dual_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&audit);
+    let mut cfg = RuntimeConfig::default();
+    for name in ["human", "agent2"] {
+        cfg.sessions
+            .insert(name.to_string(), SessionConfig::default());
+    }
+    cfg.approver_sessions = vec![
+        "human".to_string(),
+        "agent2".to_string(),
+        "default".to_string(),
+    ];
+    cfg.approver_secret = Some("s3cret".to_string());
+    cfg.audit_log = Some(audit.to_string_lossy().into_owned());
+    let (tx, mut rx) = mpsc::channel(64);
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: Arc::new(PrincipalExecutor {
+            parked: Mutex::new(false),
+        }),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "drop table t".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    let Some(Output::ApprovalRequired { token, .. }) = rx.recv().await else {
+        panic!("expected approval_required");
+    };
+
+    let secret = Some("s3cret");
+    for (approver, given, expected) in [
+        (Some("human"), None, "requires approver_secret"),
+        (Some("human"), Some("guess"), "does not match"),
+        (None, secret, "requires session"),
+        (Some("default"), secret, "must differ"),
+        (Some("agent2"), secret, "same database user"),
+        (Some("nobody"), secret, "not in approver_sessions"),
+    ] {
+        approve(
+            &app,
+            "a".to_string(),
+            token.clone(),
+            approver.map(str::to_string),
+            given.map(str::to_string),
+        )
+        .await;
+        match rx.recv().await {
+            Some(Output::Error {
+                error_code, error, ..
+            }) => {
+                assert_eq!(error_code, "approval_denied");
+                assert!(error.contains(expected), "{error}");
+            }
+            other => panic!("expected error, got {other:?}"),
+        }
+    }
+
+    approve(
+        &app,
+        "a".to_string(),
+        token,
+        Some("human".to_string()),
+        secret.map(str::to_string),
+    )
+    .await;
+    assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+
+    let text = std::fs::read_to_string(&audit).unwrap();
+    let _ = std::fs::remove_file(&audit);
+    let records: Vec<Value> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(records[0]["event"], "approval.required");
+    assert_eq!(records[1]["event"], "approval.denied");
+    let granted = records.last().unwrap();
+    assert_eq!(granted["event"], "approval.granted");
+    assert_eq!(granted["requester_principal"], "agent");
+    assert_eq!(granted["approver_session"], "human");
+    assert_eq!(granted["approver_principal"], "alice");
+}
+
+#[test]
+fn approver_sessions_need_the_approver_secret() {
+    let err = check_approver_secret(None, Some("anything"), true).unwrap_err();
+    assert!(err.contains("--approver-secret"), "{err}");
+    assert!(check_approver_secret(None, None, false).is_ok());
+    assert!(check_approver_secret(Some("s3cret"), Some("s3cret"), false).is_ok());
+    assert!(check_approver_secret(Some("s3cret"), Some("s3cre"), false).is_err());
+    assert!(check_approver_secret(Some("s3cret"), None, false).is_err());
+}
+
+/// Echoes which connection and role a statement ran with.
+struct ConnEchoExecutor;
+
+#[async_trait]
+impl DbExecutor for ConnEchoExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Rows(vec![
+            serde_json::json!({"conn": session_name, "role": opts.role}),
+        ]))
+    }
+}
+
+async fn echo_row(app: &Arc<App>, rx: &mut mpsc::Receiver<Output>) -> Value {
+    execute_query(
+        app,
+        Some("q".to_string()),
+        None,
+        "select 1".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Result { rows, .. }) => rows[0].clone(),
+        other => panic!("expected result, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn grant_elevated_is_audited_and_reverts() {
+    let audit = std::env::temp_dir().join(format!("afpsql_elev_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&audit);
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("admin".to_string(), SessionConfig::default());
+    let (tx, mut rx) = mpsc::channel(64);
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: Arc::new(ConnEchoExecutor),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    let grant = |reason: &str| {
+        grant_elevated(
+            &app,
+            "g".to_string(),
+            None,
+            Some("dba".to_string()),
+            Some("admin".to_string()),
+            200,
+            reason.to_string(),
+        )
+    };
+
+    grant("incident 42").await;
+    match rx.recv().await {
+        Some(Output::Error { error, .. }) => assert!(error.contains("audit_log"), "{error}"),
+        other => panic!("expected error, got {other:?}"),
+    }
+    app.config.write().await.audit_log = Some(audit.to_string_lossy().into_owned());
+    grant("  ").await;
+    match rx.recv().await {
+        Some(Output::Error { error, .. }) => assert!(error.contains("reason"), "{error}"),
+        other => panic!("expected error, got {other:?}"),
+    }
+
+    grant("incident 42").await;
+    assert!(matches!(
+        rx.recv().await,
+        Some(Output::Elevated {
+            expires_in_ms: 200,
+            ..
+        })
+    ));
+    let row = echo_row(&app, &mut rx).await;
+    assert_eq!(row["conn"], "admin");
+    assert_eq!(row["role"], "dba");
+
+    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+    let row = echo_row(&app, &mut rx).await;
+    assert_eq!(row["conn"], "default");
+    assert!(row["role"].is_null());
+
+    let text = std::fs::read_to_string(&audit).unwrap();
+    let _ = std::fs::remove_file(&audit);
+    let events: Vec<String> = text
+        .lines()
+        .map(|l| serde_json::from_str::<Value>(l).unwrap()["event"].to_string())
+        .collect();
+    assert_eq!(events, ["\"elevation.granted\"", "\"elevation.expired\""]);
+    assert!(text.contains("incident 42"));
+}
+
+#[tokio::test]
+async fn undefined_template_vars_are_rejected_before_running() {
+    let (app, mut rx) = test_app_with_executor(
+        RuntimeConfig::default(),
+        Ok(ExecOutcome::Command { affected: 0 }),
+    );
+    let options = QueryOptions {
+        vars: Some([("n".to_string(), "1".to_string())].into()),
+        ..QueryOptions::default()
+    };
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "select * from {{tbl|ident}} limit :n".to_string(),
+        vec![],
+        options,
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "invalid_request");
+            assert!(error.contains("{{tbl}}"), "{error}");
+        }
+        other => panic!("expected invalid_request, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn agent_quotas_refuse_queries_over_the_limit() {
+    let quota = AgentQuota {
+        queries_per_minute: Some(1),
+        rows_per_minute: None,
+        bytes_per_minute: None,
+    };
+    let cfg = RuntimeConfig {
+        agent_name: Some("bot".to_string()),
+        agent_quotas: [("bot".to_string(), quota)].into(),
+        ..RuntimeConfig::default()
+    };
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 0 }));
+    let as_agent = |agent: Option<&str>| QueryOptions {
+        agent: agent.map(str::to_string),
+        ..QueryOptions::default()
+    };
+    let mut codes = vec![];
+    for agent in [None, Some("bot"), Some("other")] {
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            "update t set n = 1".to_string(),
+            vec![],
+            as_agent(agent),
+        )
+        .await;
+        codes.push(match rx.recv().await {
+            Some(Output::Error { error_code, .. }) => error_code,
+            Some(_) => "ok".to_string(),
+            None => panic!("no event"),
+        });
+    }
+    assert_eq!(codes, ["ok", "quota_exceeded", "ok"]);
+}
+
+#[tokio::test]
+async fn agents_under_the_star_quota_share_it() {
+    let quota = AgentQuota {
+        queries_per_minute: Some(1),
+        rows_per_minute: None,
+        bytes_per_minute: None,
+    };
+    let cfg = RuntimeConfig {
+        agent_quotas: [("*".to_string(), quota)].into(),
+        ..RuntimeConfig::default()
+    };
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 0 }));
+    let mut codes = vec![];
+    for agent in ["a", "b"] {
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            "update t set n = 1".to_string(),
+            vec![],
+            QueryOptions {
+                agent: Some(agent.to_string()),
+                ..QueryOptions::default()
+            },
+        )
+        .await;
+        codes.push(match rx.recv().await {
+            Some(Output::Error { error_code, .. }) => error_code,
+            Some(_) => "ok".to_string(),
+            None => panic!("no event"),
+        });
+    }
+    assert_eq!(codes, ["ok", "quota_exceeded"]);
+}
+
+#[tokio::test]
+async fn session_budgets_refuse_once_rows_are_spent() {
+    let budget = CostBudget {
+        rows_per_minute: None,
+        rows_per_hour: Some(2),
+        bytes_per_minute: None,
+        bytes_per_hour: None,
+    };
+    let cfg = RuntimeConfig {
+        session_budgets: [("*".to_string(), budget)].into(),
+        ..RuntimeConfig::default()
+    };
+    let rows = vec![json!({"n": 1}), json!({"n": 2}), json!({"n": 3})];
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(rows)));
+    let mut codes = vec![];
+    // A second session under "*" draws on the same bucket.
+    for session in ["default", "other"] {
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            Some(session.to_string()),
+            "select n from t".to_string(),
+            vec![],
+            QueryOptions::default(),
+        )
+        .await;
+        codes.push(match rx.recv().await {
+            Some(Output::Error { error_code, .. }) => error_code,
+            Some(_) => "ok".to_string(),
+            None => panic!("no event"),
+        });
+    }
+    assert_eq!(codes, ["ok", "budget_exceeded"]);
+    let remaining = app.budgets.lock().await.remaining(Instant::now());
+    assert_eq!(remaining["session:*"]["rows_per_hour"], 0);
+}
+
+#[tokio::test]
+async fn injection_warnings_are_logged_when_enabled() {
+    let sql = "select * from users where name = '' or 'a'='a'";
+    for enabled in [false, true] {
+        let cfg = RuntimeConfig {
+            injection_warnings: enabled,
+            log: vec!["query.warning".to_string()],
+            ..RuntimeConfig::default()
+        };
+        let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 0 }));
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            sql.to_string(),
+            vec![],
+            QueryOptions::default(),
+        )
+        .await;
+        match rx.recv().await {
+            Some(Output::Log {
+                event,
+                error_code,
+                warning,
+                ..
+            }) => {
+                assert!(enabled);
+                assert_eq!(event, "query.warning");
+                assert_eq!(error_code.as_deref(), Some("tautology"));
+                assert!(warning.is_some());
+                assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+            }
+            Some(Output::Result { .. }) => assert!(!enabled),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn lint_reports_findings_and_blocks_configured_severities() {
+    for block in [vec![], vec![LintSeverity::Error]] {
+        let cfg = RuntimeConfig {
+            lint: true,
+            lint_block: block.clone(),
+            ..RuntimeConfig::default()
+        };
+        let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 3 }));
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            "delete from audit".to_string(),
+            vec![],
+            QueryOptions::default(),
+        )
+        .await;
+        match rx.recv().await {
+            Some(Output::Lint {
+                findings, blocked, ..
+            }) => {
+                assert_eq!(findings[0].rule, "missing_where");
+                assert_eq!(blocked, !block.is_empty());
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        match rx.recv().await {
+            Some(Output::Error { error_code, .. }) => {
+                assert!(!block.is_empty());
+                assert_eq!(error_code, "lint_blocked");
+            }
+            Some(Output::Result { .. }) => assert!(block.is_empty()),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn expect_row_bounds_turn_results_into_contract_violations() {
+    let rows = vec![json!({"id": 1}), json!({"id": 2})];
+    for (max_rows, violated) in [(Some(2), false), (Some(1), true)] {
+        let (app, mut rx) = test_app_with_executor(
+            RuntimeConfig::default(),
+            Ok(ExecOutcome::Rows(rows.clone())),
+        );
+        let options = QueryOptions {
+            expect: Some(ResultContract {
+                max_rows,
+                ..ResultContract::default()
+            }),
+            ..QueryOptions::default()
+        };
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            "select id from t".to_string(),
+            vec![],
+            options,
+        )
+        .await;
+        match rx.recv().await {
+            Some(Output::Error {
+                error_code, error, ..
+            }) => {
+                assert!(violated);
+                assert_eq!(error_code, "contract_violation");
+                assert_eq!(error, "2 rows, expected at most 1");
+            }
+            Some(Output::Result { row_count, .. }) => {
+                assert!(!violated);
+                assert_eq!(row_count, 2);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn unknown_timeout_profile_is_rejected() {
+    let (app, mut rx) =
+        test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+    let options = QueryOptions {
+        timeout_profile: Some("overnight".to_string()),
+        ..QueryOptions::default()
+    };
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "select 1".to_string(),
+        vec![],
+        options,
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "invalid_request");
+            assert!(error.contains("batch, interactive, maintenance"), "{error}");
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn deadline_is_checked_before_running() {
+    for (deadline, expected) in [
+        (Deadline::EpochMs(1), Some("deadline_exceeded")),
+        (
+            Deadline::Rfc3339("soon".to_string()),
+            Some("invalid_request"),
+        ),
+        (Deadline::EpochMs(unix_millis() + 60_000), None),
+    ] {
+        let (app, mut rx) =
+            test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+        let options = QueryOptions {
+            deadline: Some(deadline),
+            ..QueryOptions::default()
+        };
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            "select 1".to_string(),
+            vec![],
+            options,
+        )
+        .await;
+        match (rx.recv().await, expected) {
+            (Some(Output::Error { error_code, .. }), Some(code)) => assert_eq!(error_code, code),
+            (Some(Output::Result { .. }), None) => {}
+            (other, _) => panic!("expected {expected:?}, got {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn workspace_queries_need_an_open_workspace_on_their_session() {
+    let (app, mut rx) =
+        test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+    app.workspaces.lock().await.open("ws", "default");
+    for (workspace, session, expected) in [
+        ("missing", None, Some("invalid_request")),
+        ("ws", Some("other"), Some("invalid_request")),
+        ("ws", None, None),
+    ] {
+        let options = QueryOptions {
+            workspace: Some(workspace.to_string()),
+            ..QueryOptions::default()
+        };
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            session.map(str::to_string),
+            "select 1".to_string(),
+            vec![],
+            options,
+        )
+        .await;
+        match (rx.recv().await, expected) {
+            (Some(Output::Error { error_code, .. }), Some(code)) => assert_eq!(error_code, code),
+            (Some(Output::Result { session, .. }), None) => {
+                assert_eq!(session.as_deref(), Some("default"))
+            }
+            (other, _) => panic!("expected {expected:?}, got {other:?}"),
+        }
+    }
+
+    workspace_close(&app, "c".to_string(), "ws".to_string()).await;
+    assert!(matches!(
+        rx.recv().await,
+        Some(Output::WorkspaceClosed { .. })
+    ));
+    assert!(!app.workspaces.lock().await.contains("ws"));
+}
+
+#[derive(Default)]
+struct SnapshotExecutor {
+    imported: Mutex<Vec<Option<String>>>,
+    released: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl DbExecutor for SnapshotExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        self.imported.lock().await.push(opts.snapshot.clone());
+        Ok(ExecOutcome::Command { affected: 0 })
+    }
+
+    async fn export_snapshot(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+    ) -> Result<String, ExecError> {
+        Ok("00000003-0000001B-1".to_string())
+    }
+
+    async fn release_snapshot(&self, _session_name: &str, snapshot: &str) -> bool {
+        self.released.lock().await.push(snapshot.to_string());
+        true
+    }
+}
+
+#[tokio::test]
+async fn snapshot_queries_import_the_exported_snapshot() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let executor = Arc::new(SnapshotExecutor::default());
+    let app = Arc::new(App {
+        config: RwLock::new(RuntimeConfig::default()),
+        executor: executor.clone(),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+
+    snapshot_begin(&app, "s1".to_string(), None).await;
+    let snapshot = match rx.recv().await.unwrap() {
+        Output::SnapshotStarted {
+            snapshot,
+            expires_in_ms,
+            ..
+        } => {
+            assert_eq!(expires_in_ms, 600_000);
+            snapshot
+        }
+        other => panic!("expected snapshot_started, got {other:?}"),
+    };
+
+    let query = |id: &str, snapshot: &str| {
+        snapshot_query(
+            &app,
+            id.to_string(),
+            None,
+            snapshot.to_string(),
+            "select 1".to_string(),
+            vec![],
+            QueryOptions::default(),
+        )
+    };
+    query("q1", &snapshot).await;
+    assert!(matches!(rx.recv().await.unwrap(), Output::Result { .. }));
+    query("q2", "unknown").await;
+    match rx.recv().await.unwrap() {
+        Output::Error { error_code, .. } => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+    assert_eq!(
+        *executor.imported.lock().await,
+        vec![Some(snapshot.clone())]
+    );
+
+    snapshot_end(&app, "e1".to_string(), None, snapshot.clone()).await;
+    assert!(matches!(
+        rx.recv().await.unwrap(),
+        Output::SnapshotEnded { .. }
+    ));
+    assert_eq!(*executor.released.lock().await, vec![snapshot.clone()]);
+    query("q3", &snapshot).await;
+    match rx.recv().await.unwrap() {
+        Output::Error { error_code, .. } => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[derive(Default)]
+struct PinExecutor {
+    opened: Mutex<Vec<String>>,
+    ran_on: Mutex<Vec<Option<String>>>,
+    /// The pinned connection was closed by the server.
+    dropped: AtomicBool,
+}
+
+#[async_trait]
+impl DbExecutor for PinExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        self.ran_on.lock().await.push(opts.workspace.clone());
+        Ok(ExecOutcome::Command { affected: 0 })
+    }
+
+    async fn open_workspace(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        workspace: &str,
+    ) -> Result<(), ExecError> {
+        self.opened.lock().await.push(workspace.to_string());
+        Ok(())
+    }
+
+    async fn reconnect_workspace(&self, _workspace: &str) -> Result<bool, ExecError> {
+        Ok(self.dropped.swap(false, Ordering::Relaxed))
+    }
+}
+
+#[tokio::test]
+async fn pinned_session_queries_share_one_connection() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions.insert(
+        "default".to_string(),
+        SessionConfig {
+            pinned: Some(true),
+            ..SessionConfig::default()
+        },
+    );
+    let executor = Arc::new(PinExecutor::default());
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: executor.clone(),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    for sql in ["set search_path = app", "select 1"] {
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            sql.to_string(),
+            vec![],
+            QueryOptions::default(),
+        )
+        .await;
+        assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+    }
+    let pinned = Some("session:default".to_string());
+    assert_eq!(*executor.opened.lock().await, vec!["session:default"]);
+    assert_eq!(
+        *executor.ran_on.lock().await,
+        vec![pinned.clone(), pinned.clone()]
+    );
+
+    executor.dropped.store(true, Ordering::Relaxed);
+    execute_query(
+        &app,
+        Some("q2".to_string()),
+        None,
+        "select 2".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::SessionReconnected {
+            id,
+            session,
+            workspace,
+            ..
+        }) => {
+            assert_eq!(id.as_deref(), Some("q2"));
+            assert_eq!(session, "default");
+            assert_eq!(Some(workspace), pinned);
+        }
+        other => panic!("expected session_reconnected, got {other:?}"),
+    }
+    assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+
+    workspace_open(&app, "w".to_string(), "session:x".to_string(), None).await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+/// Fails every query on the `replica` session.
+struct FanoutExecutor;
+
+#[async_trait]
+impl DbExecutor for FanoutExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        if session_name == "replica" {
+            return Err(ExecError::Connect("connection refused".to_string()));
+        }
+        Ok(ExecOutcome::Rows(vec![json!({"session": session_name})]))
+    }
+}
+
+#[tokio::test]
+async fn fanout_isolates_per_session_errors() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cfg = RuntimeConfig::default();
+    for name in ["primary", "replica"] {
+        cfg.sessions
+            .insert(name.to_string(), SessionConfig::default());
+    }
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: Arc::new(FanoutExecutor),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    let sessions = ["primary", "replica", "missing", "primary"].map(str::to_string);
+    fanout(
+        &app,
+        "f".to_string(),
+        sessions.to_vec(),
+        "select 1".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    let Some(Output::FanoutResult {
+        results,
+        ok_count,
+        error_count,
+        ..
+    }) = rx.recv().await
+    else {
+        panic!("expected fanout_result");
+    };
+    assert_eq!((ok_count, error_count), (1, 2));
+    match &results["primary"] {
+        FanoutEntry::Ok { rows, .. } => assert_eq!(rows[0]["session"], "primary"),
+        other => panic!("expected rows, got {other:?}"),
+    }
+    for name in ["replica", "missing"] {
+        match &results[name] {
+            FanoutEntry::Error { error_code, .. } => assert_eq!(error_code, "connect_failed"),
+            other => panic!("expected error, got {other:?}"),
+        }
+    }
+
+    fanout(
+        &app,
+        "f".to_string(),
+        vec![],
+        "select 1".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+/// Serves two rows on `source` and records what is copied into `target`.
+#[derive(Default)]
+struct TransferExecutor {
+    source_read_only: Mutex<Option<bool>>,
+    copies: Mutex<Vec<(String, String)>>,
+}
+
+#[async_trait]
+impl DbExecutor for TransferExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        *self.source_read_only.lock().await = Some(opts.read_only);
+        Ok(ExecOutcome::Rows(vec![
+            json!({"id": 1, "name": "a"}),
+            json!({"id": 2, "name": "b"}),
+        ]))
+    }
+
+    async fn copy_in(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        data: Vec<u8>,
+        _opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        let mut copies = self.copies.lock().await;
+        if copies.len() == 1 {
+            return Err(ExecError::Connect("connection reset".to_string()));
+        }
+        assert_eq!(session_name, "target");
+        copies.push((sql.to_string(), String::from_utf8_lossy(&data).to_string()));
+        Ok(1)
+    }
+}
+
+#[tokio::test]
+async fn transfer_copies_mapped_columns_in_batches() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cfg = RuntimeConfig::default();
+    for name in ["source", "target"] {
+        cfg.sessions
+            .insert(name.to_string(), SessionConfig::default());
+    }
+    let executor = Arc::new(TransferExecutor::default());
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: executor.clone(),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    let request = |batch_rows| TransferRequest {
+        id: "t".to_string(),
+        source_session: Some("source".to_string()),
+        sql: "select id, name from users".to_string(),
+        params: vec![],
+        target_session: Some("target".to_string()),
+        table: "archive.users".to_string(),
+        columns: std::collections::HashMap::from([("id".to_string(), "user_id".to_string())]),
+        method: TransferMethod::Copy,
+        batch_rows,
+        options: QueryOptions::default(),
+    };
+
+    // The second batch fails: the error reports the rows already written.
+    transfer(&app, request(Some(1))).await;
+    match rx.recv().await {
+        Some(Output::Progress { done, total, .. }) => assert_eq!((done, total), (1, None)),
+        other => panic!("expected progress, got {other:?}"),
+    }
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "connect_failed");
+            assert!(error.ends_with("(after 1 rows written)"), "{error}");
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+    assert_eq!(*executor.source_read_only.lock().await, Some(true));
+    let (sql, data) = executor.copies.lock().await[0].clone();
+    assert_eq!(sql, "copy \"archive\".\"users\" (\"user_id\") from stdin");
+    assert_eq!(data, "1\n");
+
+    executor.copies.lock().await.clear();
+    transfer(&app, request(None)).await;
+    assert!(matches!(rx.recv().await, Some(Output::Progress { .. })));
+    match rx.recv().await {
+        Some(Output::TransferResult {
+            rows_read, batches, ..
+        }) => assert_eq!((rows_read, batches), (2, 1)),
+        other => panic!("expected transfer_result, got {other:?}"),
+    }
+}
+
+/// Serves `source` only in pages of two rows and records the pages it sent
+/// and the batches copied into `target`.
+#[derive(Default)]
+struct PagedExecutor {
+    pages_sent: Mutex<Vec<usize>>,
+    copies: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl DbExecutor for PagedExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        panic!("transfer must read its source in pages");
+    }
+
+    async fn execute_pages(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+        page_rows: usize,
+        pages: mpsc::Sender<Vec<Value>>,
+    ) -> Result<(), ExecError> {
+        assert_eq!(page_rows, 2);
+        for n in 0..5 {
+            let page = vec![json!({"id": 2 * n}), json!({"id": 2 * n + 1})];
+            if pages.send(page).await.is_err() {
+                break;
+            }
+            self.pages_sent.lock().await.push(n);
+        }
+        Ok(())
+    }
+
+    async fn copy_in(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        data: Vec<u8>,
+        _opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        let data = String::from_utf8_lossy(&data).to_string();
+        let rows = data.lines().count() as u64;
+        self.copies.lock().await.push(data);
+        Ok(rows)
+    }
+}
+
+#[tokio::test]
+async fn transfer_writes_each_page_as_it_is_read() {
+    let mut cfg = RuntimeConfig::default();
+    for name in ["source", "target"] {
+        cfg.sessions
+            .insert(name.to_string(), SessionConfig::default());
+    }
+    let executor = Arc::new(PagedExecutor::default());
+    let (app, mut rx) = test_app(cfg, executor.clone());
+    let request = TransferRequest {
+        id: "t".to_string(),
+        source_session: Some("source".to_string()),
+        sql: "select id from users".to_string(),
+        params: vec![],
+        target_session: Some("target".to_string()),
+        table: "users_copy".to_string(),
+        columns: std::collections::HashMap::new(),
+        method: TransferMethod::Copy,
+        batch_rows: Some(2),
+        options: QueryOptions {
+            default_limit: Some(5),
+            ..QueryOptions::default()
+        },
+    };
+    transfer(&app, request).await;
+    for done in [2, 4, 5] {
+        match rx.recv().await {
+            Some(Output::Progress { done: d, total, .. }) => assert_eq!((d, total), (done, None)),
+            other => panic!("expected progress, got {other:?}"),
+        }
+    }
+    match rx.recv().await {
+        Some(Output::TransferResult {
+            rows_read,
+            rows_written,
+            batches,
+            ..
+        }) => assert_eq!((rows_read, rows_written, batches), (5, 5, 3)),
+        other => panic!("expected transfer_result, got {other:?}"),
+    }
+    assert_eq!(*executor.copies.lock().await, ["0\n1\n", "2\n3\n", "4\n"]);
+    // Reading stopped at the limit instead of running through the source.
+    assert!(executor.pages_sent.lock().await.len() < 5);
+    assert_eq!(app.memory.used(), 0);
+}
+
+/// Counts five rows for any query and refuses to write.
+struct CountOnlyExecutor;
+
+#[async_trait]
+impl DbExecutor for CountOnlyExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        assert!(sql.starts_with("select count(*)"), "{sql}");
+        Ok(ExecOutcome::Rows(vec![json!({"rows": 5})]))
+    }
+
+    async fn copy_in(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _data: Vec<u8>,
+        _opts: &ResolvedOptions,
+    ) -> Result<u64, ExecError> {
+        panic!("nothing may be written before approval");
+    }
+}
+
+fn approval_config(threshold: usize) -> RuntimeConfig {
+    let mut cfg = RuntimeConfig::default();
+    cfg.require_approval = true;
+    cfg.approval_row_threshold = threshold;
+    cfg
+}
+
+#[tokio::test]
+async fn transfer_over_the_approval_threshold_writes_nothing() {
+    let (app, mut rx) = test_app(approval_config(2), Arc::new(CountOnlyExecutor));
+    let request = TransferRequest {
+        id: "t".to_string(),
+        source_session: None,
+        sql: "select id from users;".to_string(),
+        params: vec![],
+        target_session: None,
+        table: "users_copy".to_string(),
+        columns: std::collections::HashMap::new(),
+        method: TransferMethod::Copy,
+        batch_rows: Some(1),
+        options: QueryOptions::default(),
+    };
+    transfer(&app, request).await;
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "approval_required");
+            assert!(error.contains("transfer matches 5 rows"), "{error}");
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn import_over_the_approval_threshold_writes_nothing() {
+    let (app, mut rx) = test_app(approval_config(2), Arc::new(CountOnlyExecutor));
+    let path = std::env::temp_dir().join(format!("afpsql_approval_{}.csv", std::process::id()));
+    std::fs::write(&path, "id\n1\n2\n3\n").unwrap();
+    let request = ImportRequest {
+        id: "i".to_string(),
+        session: None,
+        source: path.to_string_lossy().to_string(),
+        table: "orders".to_string(),
+        columns: Default::default(),
+        batch_rows: Some(1),
+        max_errors: 0,
+        dead_letter: None,
+        options: QueryOptions::default(),
+    };
+    import(&app, request).await;
+    let _ = std::fs::remove_file(&path);
+    match rx.recv().await {
+        Some(Output::Error {
+            error_code, error, ..
+        }) => {
+            assert_eq!(error_code, "approval_required");
+            assert!(error.contains("import matches 3 rows"), "{error}");
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+/// Serves two rows on `source` and records the statements run elsewhere.
+#[derive(Default)]
+struct MaterializeExecutor {
+    statements: Mutex<Vec<(String, String, Vec<Value>)>>,
+}
+
+#[async_trait]
+impl DbExecutor for MaterializeExecutor {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        if session_name == "source" {
+            return Ok(ExecOutcome::Rows(vec![
+                json!({"id": 1, "score": 0.5, "tags": ["x"]}),
+                json!({"id": 2, "score": 3, "tags": null}),
+            ]));
+        }
+        let statement = (session_name.to_string(), sql.to_string(), params.to_vec());
+        self.statements.lock().await.push(statement);
+        Ok(ExecOutcome::Command { affected: 2 })
+    }
+}
+
+#[tokio::test]
+async fn materialize_to_replaces_a_sqlite_table_with_the_rows() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("source".to_string(), SessionConfig::default());
+    let scratch = SessionConfig {
+        sqlite_path: Some("scratch.db".to_string()),
+        ..SessionConfig::default()
+    };
+    cfg.sessions.insert("scratch".to_string(), scratch);
+    let executor = Arc::new(MaterializeExecutor::default());
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: executor.clone(),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    let options = |session: &str| QueryOptions {
+        materialize_to: Some(MaterializeTarget {
+            session: session.to_string(),
+            table: "recent".to_string(),
+        }),
+        ..QueryOptions::default()
+    };
+    let sql = "select * from events".to_string();
+
+    execute_query(
+        &app,
+        Some("m".to_string()),
+        Some("source".to_string()),
+        sql.clone(),
+        vec![],
+        options("scratch"),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Materialized {
+            target_session,
+            table,
+            columns,
+            row_count,
+            ..
+        }) => {
+            assert_eq!(
+                (target_session.as_str(), table.as_str()),
+                ("scratch", "recent")
+            );
+            assert_eq!(columns, vec!["id", "score", "tags"]);
+            assert_eq!(row_count, 2);
+        }
+        other => panic!("expected materialized, got {other:?}"),
+    }
+    let statements = executor.statements.lock().await.clone();
+    let sqls: Vec<&str> = statements.iter().map(|(_, sql, _)| sql.as_str()).collect();
+    assert_eq!(
+        sqls,
+        vec![
+            "drop table if exists \"recent\"",
+            "create table \"recent\" (\"id\" integer, \"score\" real, \"tags\" text)",
+            "insert into \"recent\" (\"id\", \"score\", \"tags\") values ($1, $2, $3), ($4, $5, $6)",
+        ]
+    );
+    assert!(statements
+        .iter()
+        .all(|(session, _, _)| session == "scratch"));
+
+    execute_query(
+        &app,
+        None,
+        Some("source".to_string()),
+        sql,
+        vec![],
+        options("source"),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error { error, .. }) => {
+            assert!(error.contains("not a sqlite session"), "{error}")
+        }
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn cache_ttl_serves_repeated_reads_from_the_cache() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(vec![json!({"n": 1})])));
+    let options = QueryOptions {
+        cache_ttl_ms: Some(60_000),
+        ..QueryOptions::default()
+    };
+    // The mock answers once; the second query must come from the cache.
+    for expect_cached in [None, Some(true)] {
+        execute_query(
+            &app,
+            Some("q".to_string()),
+            None,
+            "select 1 as n".to_string(),
+            vec![],
+            options.clone(),
+        )
+        .await;
+        match rx.recv().await {
+            Some(Output::Result {
+                rows,
+                cached,
+                trace,
+                ..
+            }) => {
+                assert_eq!(rows, vec![json!({"n": 1})]);
+                assert_eq!(cached, expect_cached);
+                assert_eq!(trace.cache_age_ms.is_some(), expect_cached.is_some());
+            }
+            other => panic!("expected result, got {other:?}"),
+        }
+    }
+
+    execute_query(
+        &app,
+        Some("q".to_string()),
+        None,
+        "select 1 as n".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Result { command_tag, .. }) => assert_eq!(command_tag, "EXECUTE 0"),
+        other => panic!("expected result, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn catalog_lookups_are_cached_until_invalidated() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(vec![json!({"k": 1})])));
+    let options = QueryOptions::default();
+    let lookup = || fetch_catalog(&app, None, "keys", &[], &options);
+    // The mock answers once; the second lookup must come from the cache.
+    assert_eq!(lookup().await.unwrap(), vec![json!({"k": 1})]);
+    assert_eq!(lookup().await.unwrap(), vec![json!({"k": 1})]);
+
+    invalidate_cache(&app, "i".to_string(), Some("default".to_string())).await;
+    match rx.recv().await {
+        Some(Output::CacheInvalidated { schema_entries, .. }) => assert_eq!(schema_entries, 1),
+        other => panic!("expected cache_invalidated, got {other:?}"),
+    }
+    assert!(lookup().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn diff_output_replies_with_rows_changed_since_the_previous_run() {
+    use crate::replay::{FixtureEntry, RecordedOutcome, ReplayExecutor};
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let sql = "select id, state from jobs";
+    let run = |rows: Vec<Value>| FixtureEntry {
+        session: "default".to_string(),
+        sql: sql.to_string(),
+        params: vec![],
+        outcome: RecordedOutcome::Rows { rows },
+    };
+    let executor = ReplayExecutor::from_entries(vec![
+        run(vec![
+            json!({"id": 1, "state": "queued"}),
+            json!({"id": 2, "state": "queued"}),
+        ]),
+        run(vec![
+            json!({"id": 1, "state": "done"}),
+            json!({"id": 2, "state": "queued"}),
+            json!({"id": 3, "state": "queued"}),
+        ]),
+    ]);
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: Arc::new(executor),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    let options = QueryOptions {
+        diff_output: true,
+        ..QueryOptions::default()
+    };
+    let query = || execute_query(&app, None, None, sql.to_string(), vec![], options.clone());
+
+    query().await;
+    match rx.recv().await {
+        Some(Output::Result { row_count, .. }) => assert_eq!(row_count, 2),
+        other => panic!("expected result, got {other:?}"),
+    }
+    query().await;
+    match rx.recv().await {
+        Some(Output::ResultDiff {
+            added,
+            removed,
+            row_count,
+            ..
+        }) => {
+            assert_eq!(
+                added,
+                vec![
+                    json!({"id": 1, "state": "done"}),
+                    json!({"id": 3, "state": "queued"}),
+                ]
+            );
+            assert_eq!(removed, vec![json!({"id": 1, "state": "queued"})]);
+            assert_eq!(row_count, 3);
+        }
+        other => panic!("expected result_diff, got {other:?}"),
+    }
+    query().await;
+    match rx.recv().await {
+        Some(Output::ResultDiff { added, removed, .. }) => {
+            assert!(added.is_empty() && removed.is_empty())
+        }
+        other => panic!("expected result_diff, got {other:?}"),
+    }
+
+    let streamed = QueryOptions {
+        stream_rows: true,
+        ..options.clone()
+    };
+    execute_query(&app, None, None, sql.to_string(), vec![], streamed).await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn dedup_streams_only_rows_changed_for_their_key() {
+    use crate::replay::{FixtureEntry, RecordedOutcome, ReplayExecutor};
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let sql = "select id, state from jobs";
+    let run = |rows: Vec<Value>| FixtureEntry {
+        session: "default".to_string(),
+        sql: sql.to_string(),
+        params: vec![],
+        outcome: RecordedOutcome::Rows { rows },
+    };
+    let executor = ReplayExecutor::from_entries(vec![
+        run(vec![
+            json!({"id": 1, "state": "queued"}),
+            json!({"id": 2, "state": "queued"}),
+        ]),
+        run(vec![
+            json!({"id": 1, "state": "done"}),
+            json!({"id": 2, "state": "queued"}),
+        ]),
+    ]);
+    let (tx, mut rx) = mpsc::channel(64);
+    let app = Arc::new(App::with_executor(cfg, tx, Arc::new(executor)));
+    let options = QueryOptions {
+        stream_rows: true,
+        key_columns: Some(vec!["id".to_string()]),
+        dedup: true,
+        ..QueryOptions::default()
+    };
+    let mut streamed = vec![];
+    for _ in 0..2 {
+        execute_query(
+            &app,
+            Some("w".to_string()),
+            None,
+            sql.to_string(),
+            vec![],
+            options.clone(),
+        )
+        .await;
+        let mut batch = (vec![], vec![]);
+        loop {
+            match rx.recv().await {
+                Some(Output::ResultStart { .. }) => {}
+                Some(Output::ResultRows { rows, keys, .. }) => {
+                    batch.0.extend(rows);
+                    batch.1.extend(keys);
+                }
+                Some(Output::ResultEnd { .. }) => break,
+                other => panic!("expected streamed rows, got {other:?}"),
+            }
+        }
+        streamed.push(batch);
+    }
+    assert_eq!(streamed[0].1, vec![json!([1]), json!([2])]);
+    assert_eq!(
+        streamed[1],
+        (vec![json!({"id": 1, "state": "done"})], vec![json!([1])])
+    );
+
+    let no_key = QueryOptions {
+        key_columns: None,
+        ..options
+    };
+    execute_query(&app, None, None, sql.to_string(), vec![], no_key).await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn claim_id_refuses_or_suffixes_an_id_in_flight() {
+    let (app, mut rx) =
+        test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+    let running = tokio::spawn(std::future::pending::<()>());
+    app.in_flight.lock().await.insert("q1".into(), running);
+    assert_eq!(claim_id(&app, "q2".into()).await.as_deref(), Some("q2"));
+
+    assert_eq!(claim_id(&app, "q1".into()).await, None);
+    match rx.recv().await {
+        Some(Output::Error { id, error_code, .. }) => {
+            assert_eq!(id.as_deref(), Some("q1"));
+            assert_eq!(error_code, "duplicate_id");
+        }
+        other => panic!("expected duplicate_id, got {other:?}"),
+    }
+
+    app.config.write().await.duplicate_id_policy = DuplicateIdPolicy::Suffix;
+    assert_eq!(claim_id(&app, "q1".into()).await.as_deref(), Some("q1#2"));
+    let reject = DuplicateIdPolicy::Reject;
+    assert_eq!(claim_id_as(&app, "q1".into(), reject).await, None);
+    assert!(matches!(rx.recv().await, Some(Output::Error { .. })));
+    let second = tokio::spawn(std::future::pending::<()>());
+    app.in_flight.lock().await.insert("q1#2".into(), second);
+    assert_eq!(claim_id(&app, "q1".into()).await.as_deref(), Some("q1#3"));
+
+    for (_, handle) in app.in_flight.lock().await.drain() {
+        handle.abort();
+    }
+    tokio::task::yield_now().await;
+    assert_eq!(claim_id(&app, "q1".into()).await.as_deref(), Some("q1"));
+}
+
+#[tokio::test]
+async fn resume_from_resends_a_retained_stream_without_running_it() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let rows = (1..=5).map(|n| json!({"n": n})).collect();
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(rows)));
+    let query = |options: QueryOptions| {
+        execute_query(
+            &app,
+            Some("s".to_string()),
+            None,
+            "select n from t".to_string(),
+            vec![],
+            options,
+        )
+    };
+    let streamed = QueryOptions {
+        stream_rows: true,
+        batch_rows: Some(2),
+        ..QueryOptions::default()
+    };
+    query(streamed.clone()).await;
+    let mut first = vec![];
+    while let Ok(out) = rx.try_recv() {
+        first.push(serde_json::to_value(out).unwrap());
+    }
+    let seqs: Vec<_> = first.iter().map(|o| o["seq"].as_u64().unwrap()).collect();
+    assert_eq!(seqs, vec![0, 1, 2, 3, 4]);
+
+    // The executor answers once; a resumed stream must come from the log.
+    query(QueryOptions {
+        resume_from: Some(2),
+        ..streamed
+    })
+    .await;
+    let mut resumed = vec![];
+    while let Ok(out) = rx.try_recv() {
+        resumed.push(serde_json::to_value(out).unwrap());
+    }
+    assert_eq!(resumed, first[2..].to_vec());
+
+    execute_query(
+        &app,
+        Some("other".to_string()),
+        None,
+        String::new(),
+        vec![],
+        QueryOptions {
+            resume_from: Some(0),
+            ..QueryOptions::default()
+        },
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "invalid_request"),
+        other => panic!("expected error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn ack_window_holds_batches_until_acked() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let rows = (1..=4).map(|n| json!({"n": n})).collect();
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(rows)));
+    let options = QueryOptions {
+        stream_rows: true,
+        batch_rows: Some(1),
+        ack_window: Some(2),
+        ..QueryOptions::default()
+    };
+    let task = {
+        let app = app.clone();
+        tokio::spawn(async move {
+            execute_query(
+                &app,
+                Some("a".to_string()),
+                None,
+                "select n from t".to_string(),
+                vec![],
+                options,
+            )
+            .await
+        })
+    };
+    let mut seen = vec![];
+    for _ in 0..3 {
+        seen.push(serde_json::to_value(rx.recv().await.unwrap()).unwrap()["seq"].clone());
+    }
+    assert_eq!(seen, vec![json!(0), json!(1), json!(2)]);
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    assert!(rx.try_recv().is_err());
+
+    app.acks.ack("a", 2);
+    let mut rest = vec![];
+    while rest.len() < 3 {
+        rest.push(serde_json::to_value(rx.recv().await.unwrap()).unwrap()["seq"].clone());
+    }
+    assert_eq!(rest, vec![json!(3), json!(4), json!(5)]);
+    task.await.unwrap();
+}
+
+#[tokio::test]
+async fn oversized_result_rows_are_split_into_parts() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    cfg.max_message_bytes = 300;
+    let row = json!({"doc": "x".repeat(1000)});
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(vec![row.clone()])));
+    execute_query(
+        &app,
+        Some("big".to_string()),
+        None,
+        "select doc from t".to_string(),
+        vec![],
+        QueryOptions {
+            stream_rows: true,
+            ..QueryOptions::default()
+        },
+    )
+    .await;
+    let mut chunks = String::new();
+    let mut finals = vec![];
+    while let Ok(out) = rx.try_recv() {
+        let line = serde_json::to_string(&out).unwrap();
+        assert!(line.len() <= 300, "{line}");
+        if let Output::ResultRowsPart {
+            seq,
+            part,
+            last,
+            chunk,
+            ..
+        } = out
+        {
+            assert_eq!((seq, part), (1, finals.len()));
+            finals.push(last);
+            chunks.push_str(&chunk);
+        }
+    }
+    assert!(finals.len() > 3);
+    assert_eq!(finals.iter().filter(|last| **last).count(), 1);
+    assert_eq!(finals.last(), Some(&true));
+    let event: Value = serde_json::from_str(&chunks).unwrap();
+    assert_eq!(event["code"], "result_rows");
+    assert_eq!(event["seq"], 1);
+    assert_eq!(event["rows"], json!([row]));
+}
+
+struct PruningExecutor;
+
+#[async_trait]
+impl DbExecutor for PruningExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Command { affected: 0 })
+    }
+
+    async fn maintain_pools(
+        &self,
+        idle_timeout: Option<std::time::Duration>,
+    ) -> Vec<crate::db::PoolReport> {
+        assert_eq!(idle_timeout, Some(std::time::Duration::from_millis(500)));
+        vec![crate::db::PoolReport {
+            session: "default".to_string(),
+            idle: 2,
+            broken: 1,
+        }]
+    }
+}
+
+#[tokio::test]
+async fn pool_maintenance_logs_pruned_connections() {
+    let (tx, mut rx) = mpsc::channel(64);
+    let cfg = RuntimeConfig {
+        pool_check_interval_ms: 10,
+        pool_idle_timeout_ms: 500,
+        log: vec!["pool".to_string()],
+        ..RuntimeConfig::default()
+    };
+    let app = Arc::new(App {
+        config: RwLock::new(cfg),
+        executor: Arc::new(PruningExecutor),
+        writer: tx.into(),
+        in_flight: Mutex::new(std::collections::HashMap::new()),
+        requests_total: AtomicU64::new(0),
+        start_time: std::time::Instant::now(),
+        memory: Default::default(),
+        history: Default::default(),
+        transcript: Default::default(),
+        approvals: Default::default(),
+        elevations: Default::default(),
+        snapshots: Default::default(),
+        workspaces: Default::default(),
+        cache: Default::default(),
+        schema_cache: Default::default(),
+        diff_baselines: Default::default(),
+        streams: Default::default(),
+        acks: Default::default(),
+        quotas: Default::default(),
+        budgets: Default::default(),
+        plan_baselines: Default::default(),
+        operations: Default::default(),
+    });
+    spawn_pool_maintenance(&app);
+    match rx.recv().await {
+        Some(Output::Log {
+            event,
+            session,
+            warning,
+            ..
+        }) => {
+            assert_eq!(event, "pool.pruned");
+            assert_eq!(session.as_deref(), Some("default"));
+            assert_eq!(
+                warning.as_deref(),
+                Some("closed 2 idle and 1 broken connections")
+            );
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+/// Connects every session but `down`.
+struct ConnectExecutor;
+
+#[async_trait]
+impl DbExecutor for ConnectExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Command { affected: 0 })
+    }
+
+    async fn connect(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+    ) -> Result<(), ExecError> {
+        match session_name {
+            "down" => Err(ExecError::Connect("connection refused".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn eager_sessions_are_connected_and_reported() {
+    let patch: ConfigPatch = serde_json::from_value(json!({"sessions": {
+        "up": {"host": "a", "eager": true},
+        "down": {"host": "b", "eager": true},
+        "lazy": {"host": "c"},
+    }}))
+    .unwrap();
+    let eager = eager_sessions(&patch);
+    assert_eq!(eager, ["down", "up"]);
+
+    let (tx, _rx) = mpsc::channel(64);
+    let app = Arc::new(App::with_executor(
+        RuntimeConfig::default(),
+        tx,
+        Arc::new(ConnectExecutor),
+    ));
+    app.apply_config(patch).await;
+    let mut sessions = eager;
+    sessions.push("missing".to_string());
+    match connect_sessions(&app, sessions).await {
+        Output::ConnectReport {
+            connected, failed, ..
+        } => {
+            assert_eq!(connected, ["up"]);
+            assert_eq!(
+                failed.get("down").map(String::as_str),
+                Some("connect_failed: connection refused")
+            );
+            assert_eq!(
+                failed.get("missing").map(String::as_str),
+                Some("unknown session: missing")
+            );
+        }
+        other => panic!("expected connect_report, got {other:?}"),
+    }
+}
+
+#[test]
+fn query_annotation_cannot_close_the_comment() {
+    assert_eq!(
+        query_annotation(Some("q1"), "default", Some("etl-bot")),
+        "/* afpsql id=q1 session=default agent=etl-bot */ "
+    );
+    assert_eq!(
+        query_annotation(Some("x*/ drop table t; /*"), "s", None),
+        "/* afpsql id=x___drop_table_t____ session=s */ "
+    );
+}
+
+#[tokio::test]
+async fn maintenance_parks_blocking_statements_for_approval() {
+    let cfg = RuntimeConfig {
+        require_approval: true,
+        ..RuntimeConfig::default()
+    };
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Command { affected: 0 }));
+    let mut codes = vec![];
+    for (blocks, read_only) in [
+        (Some("blocks t".to_string()), None),
+        (None, None),
+        (None, Some(true)),
+    ] {
+        run_maintenance(
+            &app,
+            Some("m".to_string()),
+            None,
+            "vacuum t".to_string(),
+            blocks,
+            QueryOptions {
+                read_only,
+                ..QueryOptions::default()
+            },
+        )
+        .await;
+        codes.push(match rx.recv().await {
+            Some(Output::ApprovalRequired { .. }) => "approval_required".to_string(),
+            Some(Output::Error { error_code, .. }) => error_code,
+            Some(_) => "ok".to_string(),
+            None => panic!("no event"),
+        });
+        while rx.try_recv().is_ok() {}
+    }
+    assert_eq!(codes, ["approval_required", "ok", "invalid_request"]);
+}
+
+/// Reports fixed timings, and server times only when asked for them.
+struct TimedExecutor;
+
+#[async_trait]
+impl DbExecutor for TimedExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Ok(ExecOutcome::Rows(vec![json!({"n": 1})]))
+    }
+
+    async fn execute_timed(
+        &self,
+        session_name: &str,
+        session_cfg: &SessionConfig,
+        sql: &str,
+        params: &[Value],
+        opts: &ResolvedOptions,
+    ) -> (Result<ExecOutcome, ExecError>, Timing) {
+        let result = self
+            .execute(session_name, session_cfg, sql, params, opts)
+            .await;
+        let timing = Timing {
+            pool_wait_ms: Some(3),
+            connect_ms: None,
+            attempts: Some(2),
+            execute_ms: Some(7),
+            server_planning_ms: opts.server_timing.then_some(0.25),
+            server_execution_ms: opts.server_timing.then_some(1.5),
+        };
+        (result, timing)
+    }
+}
+
+#[tokio::test]
+async fn trace_splits_pool_wait_execution_and_server_time() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    let (app, mut rx) = test_app(cfg, Arc::new(TimedExecutor));
+    for server_timing in [false, true] {
+        let options = QueryOptions {
+            server_timing,
+            ..QueryOptions::default()
+        };
+        execute_query(&app, None, None, "select 1 as n".into(), vec![], options).await;
+        let Some(Output::Result { trace, .. }) = rx.recv().await else {
+            panic!("expected result");
+        };
+        let trace = serde_json::to_value(&trace).unwrap();
+        assert_eq!(trace["pool_wait_ms"], 3);
+        assert_eq!(trace["execute_ms"], 7);
+        assert_eq!(trace["attempts"], 2);
+        assert!(trace.get("connect_ms").is_none());
+        assert_eq!(trace.get("server_planning_ms").is_some(), server_timing);
+        assert_eq!(trace.get("server_execution_ms").is_some(), server_timing);
+    }
+}
+
+/// Takes 20ms per statement and explains any as a sequential scan.
+struct SlowExecutor;
+
+#[async_trait]
+impl DbExecutor for SlowExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        if sql.starts_with("explain") {
+            let plan = json!([{"Plan": {"Node Type": "Seq Scan", "Relation Name": "orders", "Total Cost": 10.0}}]);
+            return Ok(ExecOutcome::Rows(vec![json!({"QUERY PLAN": plan})]));
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        Ok(ExecOutcome::Rows(vec![json!({"id": 1})]))
+    }
+}
+
+#[tokio::test]
+async fn slow_query_logs_fingerprint_param_types_and_plan_hash() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    cfg.log = vec!["query.slow".to_string()];
+    cfg.slow_query_ms = 10;
+    let (app, mut rx) = test_app(cfg, Arc::new(SlowExecutor));
+    let sql = "select * from orders where id = $1";
+    for values in [false, true] {
+        if values {
+            app.config.write().await.slow_query_params = SlowQueryParams::Values;
+        }
+        let params = vec![json!(7)];
+        execute_query(
+            &app,
+            None,
+            None,
+            sql.into(),
+            params,
+            QueryOptions::default(),
+        )
+        .await;
+        assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+        let Some(Output::Log {
+            event, statement, ..
+        }) = rx.recv().await
+        else {
+            panic!("expected query.slow");
+        };
+        assert_eq!(event, "query.slow");
+        let statement = statement.unwrap();
+        assert_eq!(statement.fingerprint, plan::sql_fingerprint("default", sql));
+        assert_eq!(statement.param_types, vec!["number"]);
+        assert_eq!(statement.params, values.then(|| vec![json!(7)]));
+        assert!(statement.plan_hash.is_some());
+    }
+
+    app.config.write().await.slow_query_ms = 60_000;
+    execute_query(
+        &app,
+        None,
+        None,
+        sql.into(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn debug_stats_reports_the_process_not_the_database() {
+    let (app, mut rx) =
+        test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(vec![])));
+    debug_stats(&app, "d1".into()).await;
+    let Some(Output::DebugStats {
+        id,
+        runtime,
+        channels,
+        pools,
+        memory,
+        ..
+    }) = rx.recv().await
+    else {
+        panic!("expected debug_stats");
+    };
+    assert_eq!(id, "d1");
+    assert_eq!(runtime.workers, 1);
+    assert_eq!(runtime.in_flight, 0);
+    assert_eq!(channels.output_queued, 0);
+    assert_eq!(channels.output_capacity, 64);
+    assert_eq!(channels.log_queued, None);
+    assert!(pools.is_empty());
+    assert_eq!(memory.result_bytes_queued, 0);
+}
+
+#[tokio::test]
+async fn schedule_runs_mask_secrets_and_keep_inline_limits() {
+    let path =
+        std::env::temp_dir().join(format!("afpsql_schedule_{}.jsonl", results::new_handle()));
+    let schedule: Schedule = serde_json::from_value(serde_json::json!({
+        "cron": "@hourly",
+        "sql": "select token_secret from api_keys",
+        "sink": {"path": path.to_string_lossy()},
+    }))
+    .unwrap();
+    let rows = vec![serde_json::json!({"token_secret": "hunter2"})];
+    let (app, _rx) = test_app_with_executor(RuntimeConfig::default(), Ok(ExecOutcome::Rows(rows)));
+    run_schedule(&app, "keys".to_string(), schedule.clone(), 0).await;
+    let written = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(!written.contains("hunter2"), "{written}");
+
+    let cfg = RuntimeConfig {
+        inline_max_rows: 1,
+        ..RuntimeConfig::default()
+    };
+    let rows: Vec<Value> = (0..3).map(|n| serde_json::json!({"n": n})).collect();
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(rows)));
+    run_schedule(&app, "keys".to_string(), schedule, 0).await;
+    match rx.recv().await {
+        Some(Output::Error { error_code, .. }) => assert_eq!(error_code, "result_too_large"),
+        other => panic!("expected result_too_large, got {other:?}"),
+    }
+    assert!(!path.exists());
+    assert_eq!(app.memory.used(), 0);
+}
+
+#[tokio::test]
+async fn rows_in_flight_count_against_the_memory_budget() {
+    let mut cfg = RuntimeConfig::default();
+    cfg.sessions
+        .insert("default".to_string(), SessionConfig::default());
+    cfg.memory_budget_bytes = 2000;
+    let rows = (0..4)
+        .map(|n| json!({"n": n, "doc": "x".repeat(600)}))
+        .collect();
+    let (app, mut rx) = test_app_with_executor(cfg, Ok(ExecOutcome::Rows(rows)));
+    let options = QueryOptions {
+        stream_rows: true,
+        batch_rows: Some(1),
+        ack_window: Some(1),
+        ..QueryOptions::default()
+    };
+    let big = {
+        let app = app.clone();
+        tokio::spawn(async move {
+            execute_query(
+                &app,
+                Some("big".to_string()),
+                None,
+                "select n, doc from t".to_string(),
+                vec![],
+                options,
+            )
+            .await
+        })
+    };
+    // Take what the stream sent before it waits for an ack; nothing is queued
+    // any more, but its fetched rows are still held.
+    for _ in 0..2 {
+        drop(rx.recv().await);
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    assert!(rx.try_recv().is_err());
+    assert!(app.memory.used() >= 2000, "{}", app.memory.used());
+
+    execute_query(
+        &app,
+        Some("second".to_string()),
+        None,
+        "select 1".to_string(),
+        vec![],
+        QueryOptions::default(),
+    )
+    .await;
+    match rx.recv().await {
+        Some(Output::Error { id, error_code, .. }) => {
+            assert_eq!(id.as_deref(), Some("second"));
+            assert_eq!(error_code, "backpressure");
+        }
+        other => panic!("expected backpressure, got {other:?}"),
+    }
+
+    big.abort();
+    let _ = big.await;
+    assert_eq!(app.memory.used(), 0);
+}
@@ -0,0 +1,74 @@
+use super::*;
+use serde_json::json;
+
+fn entry(sql: &str) -> HistoryEntry {
+    HistoryEntry {
+        seq: 0,
+        id: None,
+        session: "default".to_string(),
+        sql: sql.to_string(),
+        params_count: 0,
+        params_fingerprint: None,
+        outcome: "result".to_string(),
+        error_code: None,
+        row_count: Some(1),
+        duration_ms: 1,
+        started_at_ms: 0,
+        params: vec![],
+        options: QueryOptions::default(),
+    }
+}
+
+#[test]
+fn record_assigns_seq_and_evicts_oldest() {
+    let mut h = History::default();
+    for n in 0..5 {
+        h.record(entry(&format!("select {n}")), 3);
+    }
+    let recent = h.recent(10);
+    assert_eq!(
+        recent.iter().map(|e| e.seq).collect::<Vec<_>>(),
+        vec![3, 4, 5]
+    );
+    assert_eq!(h.recent(1)[0].sql, "select 4");
+    assert!(h.get(1).is_none());
+    assert_eq!(h.get(4).map(|e| e.sql.as_str()), Some("select 3"));
+}
+
+#[test]
+fn zero_capacity_keeps_nothing() {
+    let mut h = History::default();
+    h.record(entry("select 1"), 0);
+    assert!(h.recent(10).is_empty());
+}
+
+#[test]
+fn fingerprint_is_stable_and_hides_values() {
+    assert_eq!(params_fingerprint(&[]), None);
+    let a = params_fingerprint(&[json!("hunter2"), json!(1)]).unwrap();
+    assert_eq!(a.len(), 16);
+    assert_eq!(
+        Some(a.clone()),
+        params_fingerprint(&[json!("hunter2"), json!(1)])
+    );
+    assert_ne!(
+        Some(a.clone()),
+        params_fingerprint(&[json!("hunter3"), json!(1)])
+    );
+    let serialized = serde_json::to_string(&HistoryEntry {
+        params: vec![json!("hunter2")],
+        params_fingerprint: Some(a),
+        ..entry("select $1")
+    })
+    .unwrap();
+    assert!(!serialized.contains("hunter2"));
+}
+
+#[test]
+fn param_types_name_json_types_only() {
+    let params = vec![json!(null), json!(1), json!("secret"), json!({"a": [true]})];
+    assert_eq!(
+        param_types(&params),
+        vec!["null", "number", "string", "object"]
+    );
+}
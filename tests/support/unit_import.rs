@@ -0,0 +1,70 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn parse_takes_local_paths_and_object_uris() {
+    let source = ImportSource::parse("/tmp/orders.csv").unwrap();
+    assert_eq!(
+        source.location,
+        Location::Local(PathBuf::from("/tmp/orders.csv"))
+    );
+    assert_eq!((source.format, source.gzip), (Format::Csv, false));
+
+    let source = ImportSource::parse("gs://exports/orders.jsonl.gz").unwrap();
+    assert!(matches!(source.location, Location::Object(ref uri) if uri.key == "orders.jsonl.gz"));
+    assert_eq!((source.format, source.gzip), (Format::Ndjson, true));
+
+    assert!(ImportSource::parse("orders.txt").is_err());
+    assert!(ImportSource::parse("ftp://host/orders.csv").is_err());
+}
+
+#[test]
+fn csv_records_handle_quotes_nulls_and_bad_lines() {
+    let text =
+        "id,note\r\n1,plain\n\n2,\"multi\nline, \"\"quoted\"\"\"\n3,\n4,\"\"\n5,a,b\n6,x\"y\n";
+    let got: Vec<Record> = records(text, Format::Csv).unwrap().collect();
+    let row = |line, id: &str, note: Value| Record::Row {
+        line,
+        row: json!({"id": id, "note": note}),
+    };
+    assert_eq!(got[0], row(2, "1", json!("plain")));
+    assert_eq!(got[1], row(4, "2", json!("multi\nline, \"quoted\"")));
+    assert_eq!(got[2], row(6, "3", Value::Null));
+    assert_eq!(got[3], row(7, "4", json!("")));
+    assert!(
+        matches!(&got[4], Record::Rejected { line: 8, error, raw } if error.contains("expected 2 fields") && raw == "5,a,b")
+    );
+    assert!(matches!(got[5], Record::Rejected { line: 9, .. }));
+    assert_eq!(got.len(), 6);
+
+    assert!(records("", Format::Csv).is_err());
+    assert!(records("id,id\n1,2\n", Format::Csv).is_err());
+    let unterminated: Vec<Record> = records("id\n\"open\n", Format::Csv).unwrap().collect();
+    assert!(matches!(
+        &unterminated[..],
+        [Record::Rejected { line: 2, .. }]
+    ));
+}
+
+#[test]
+fn ndjson_records_reject_lines_that_are_not_objects() {
+    let text = "{\"id\": 1}\n[1]\n\n{broken\n{\"id\": 2, \"tags\": [\"a\"]}";
+    let got: Vec<Record> = records(text, Format::Ndjson).unwrap().collect();
+    assert_eq!(
+        got[0],
+        Record::Row {
+            line: 1,
+            row: json!({"id": 1})
+        }
+    );
+    assert!(matches!(&got[1], Record::Rejected { line: 2, raw, .. } if raw == "[1]"));
+    assert!(matches!(got[2], Record::Rejected { line: 4, .. }));
+    assert_eq!(
+        got[3],
+        Record::Row {
+            line: 5,
+            row: json!({"id": 2, "tags": ["a"]})
+        }
+    );
+    assert_eq!(got.len(), 4);
+}
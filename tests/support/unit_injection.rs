@@ -0,0 +1,56 @@
+use super::*;
+
+fn codes(sql: &str) -> Vec<&'static str> {
+    analyze(sql).into_iter().map(|f| f.code).collect()
+}
+
+#[test]
+fn parameterized_sql_is_clean() {
+    assert!(codes("select * from users where name = $1 and id = $2").is_empty());
+    assert!(codes("select 'it''s' as s, e'a\\'b' as t where x = 'y'").is_empty());
+    assert!(codes("select a from t union select a from u").is_empty());
+    assert!(codes("create function f() returns text as $$ select '$1' $$ language sql").is_empty());
+}
+
+#[test]
+fn quoted_placeholders_and_templates() {
+    assert_eq!(
+        codes("select * from t where id = '$1'"),
+        ["quoted_placeholder"]
+    );
+    assert_eq!(
+        codes("select * from t where name = '${name}'"),
+        ["quoted_placeholder"]
+    );
+    assert_eq!(codes("select '{{user}}'"), ["quoted_placeholder"]);
+    assert!(codes("select '$100 off' as promo").is_empty());
+}
+
+#[test]
+fn classic_injection_shapes() {
+    assert_eq!(
+        codes("select * from t where name = 'x' or 'a'='a'"),
+        ["tautology"]
+    );
+    assert_eq!(codes("select * from t where id = 1 OR 1=1"), ["tautology"]);
+    assert_eq!(
+        codes("select * from t where name = 'admin'--' and pw = 'p'"),
+        ["comment_after_literal"]
+    );
+    assert_eq!(
+        codes("select a from t where b = '' union all select null, null"),
+        ["union_after_literal", "union_null_padding"]
+    );
+}
+
+#[test]
+fn long_literals_are_shortened_in_messages() {
+    let sql = format!("select '{}${{x}}'", "a".repeat(100));
+    let findings = analyze(&sql);
+    assert_eq!(findings.len(), 1);
+    assert!(
+        findings[0].message.contains("..."),
+        "{}",
+        findings[0].message
+    );
+}
@@ -0,0 +1,87 @@
+use super::*;
+
+fn rules(sql: &str) -> Vec<String> {
+    lint(sql).into_iter().map(|f| f.rule).collect()
+}
+
+#[test]
+fn clean_queries_have_no_findings() {
+    assert!(rules("select id, name from users where id = $1").is_empty());
+    assert!(rules("select count(*) from orders o join users u on u.id = o.user_id").is_empty());
+    assert!(rules("select a, b from t, u where t.id = u.id").is_empty());
+    assert!(rules("select t.id, x from t, unnest(t.tags) as x").is_empty());
+    assert!(rules("select 1 where exists (select * from t where t.id = 2)").is_empty());
+    assert!(rules("update t set a = 1 where id = $1 returning *").is_empty());
+    assert!(rules("select extract(epoch from now())").is_empty());
+}
+
+#[test]
+fn select_star_in_select_lists() {
+    assert_eq!(rules("select * from users"), ["select_star"]);
+    assert_eq!(rules("select distinct * from users"), ["select_star"]);
+    assert_eq!(
+        rules("select u.*, o.id from users u join o on true"),
+        ["select_star"]
+    );
+    assert!(rules("select 2 * 3").is_empty());
+}
+
+#[test]
+fn writes_without_where_are_errors() {
+    let findings = lint("delete from audit");
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].rule, "missing_where");
+    assert_eq!(findings[0].severity, LintSeverity::Error);
+    assert_eq!(
+        rules("update t set a = (select max(b) from u where u.id = 1)"),
+        ["missing_where"]
+    );
+    assert_eq!(
+        rules("with d as (delete from t returning id) select id from d"),
+        ["missing_where"]
+    );
+    assert_eq!(
+        rules("with x as (select 1) update t set a = 1"),
+        ["missing_where"]
+    );
+    assert!(rules("delete from t using u where t.id = u.id").is_empty());
+    // Not statements of their own.
+    assert!(rules("alter table t add foreign key (a) references u on delete cascade").is_empty());
+    assert!(rules("insert into t values (1) on conflict (id) do update set a = 1").is_empty());
+}
+
+#[test]
+fn cross_joins() {
+    assert_eq!(rules("select a.id from a cross join b"), ["cross_join"]);
+    assert_eq!(rules("select a.id from a, b"), ["cross_join"]);
+    assert_eq!(
+        rules("select n from (select a.n from a, b) s where n > 1"),
+        ["cross_join"]
+    );
+}
+
+#[test]
+fn non_sargable_predicates_and_implicit_casts() {
+    assert_eq!(
+        rules("select id from users where lower(email) = $1"),
+        ["non_sargable"]
+    );
+    assert_eq!(
+        rules("select id from users where active and u.created_at::date = $1"),
+        ["non_sargable"]
+    );
+    assert_eq!(
+        rules("select id from users where name like '%son'"),
+        ["non_sargable"]
+    );
+    assert!(rules("select id from users where name like 'jo%'").is_empty());
+    assert!(
+        rules("select id from users where not exists (select 1 from b where b.u = users.id)")
+            .is_empty()
+    );
+    assert_eq!(
+        rules("select id from items where qty = 1.5 or 2.5 < qty"),
+        ["implicit_cast", "implicit_cast"]
+    );
+    assert!(rules("select id from items where qty = 2").is_empty());
+}
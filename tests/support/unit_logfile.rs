@@ -0,0 +1,50 @@
+use super::*;
+
+fn policy(dir: &std::path::Path, rotate_bytes: u64, keep: usize) -> Policy {
+    Policy {
+        path: dir.join("afpsql.log").display().to_string(),
+        rotate_bytes,
+        rotate_ms: 0,
+        keep,
+    }
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("afpsql-logfile-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn rolls_over_by_size_and_keeps_the_newest_files() {
+    let dir = temp_dir("size");
+    let policy = policy(&dir, 12, 2);
+    let mut file = LogFile::new(policy.clone());
+    for line in ["one", "two", "three", "four", "five"] {
+        // Lines take their length plus a newline; 12 bytes hold two.
+        file.write_line(line.as_bytes()).unwrap();
+    }
+    let read = |suffix: &str| fs::read_to_string(format!("{}{suffix}", policy.path)).unwrap();
+    assert_eq!(read(""), "five\n");
+    assert_eq!(read(".1"), "three\nfour\n");
+    assert_eq!(read(".2"), "one\ntwo\n");
+    assert!(fs::metadata(format!("{}.3", policy.path)).is_err());
+
+    file.write_line(b"sixty").unwrap();
+    file.write_line(b"seven").unwrap();
+    assert_eq!(read(".2"), "three\nfour\n");
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn keep_zero_starts_over_and_a_long_line_is_still_written() {
+    let dir = temp_dir("keep0");
+    let policy = policy(&dir, 8, 0);
+    let mut file = LogFile::new(policy.clone());
+    file.write_line(b"a line longer than the limit").unwrap();
+    file.write_line(b"next").unwrap();
+    assert_eq!(fs::read_to_string(&policy.path).unwrap(), "next\n");
+    assert!(fs::metadata(format!("{}.1", policy.path)).is_err());
+    let _ = fs::remove_dir_all(dir);
+}
@@ -47,3 +47,71 @@ fn build_startup_log_has_afdata_fields() {
         _ => panic!("expected startup log"),
     }
 }
+
+#[test]
+fn runtime_deadline_keeps_the_earlier_deadline() {
+    let soon = Deadline::EpochMs(1);
+    assert_eq!(runtime_deadline(Some(soon.clone()), 60_000), soon);
+
+    let bad = Deadline::Rfc3339("tomorrow".to_string());
+    assert_eq!(runtime_deadline(Some(bad.clone()), 60_000), bad);
+
+    match runtime_deadline(Some(Deadline::EpochMs(u64::MAX)), 60_000) {
+        Deadline::EpochMs(ms) => assert!(ms < u64::MAX),
+        other => panic!("expected epoch deadline, got {other:?}"),
+    }
+}
+
+#[test]
+fn exit_code_follows_the_error_class() {
+    let error = |code: &str| Output::Error {
+        id: None,
+        error_code: code.to_string(),
+        error: String::new(),
+        retryable: false,
+        trace: Trace::only_duration(0),
+    };
+    let sql_error = |sqlstate: &str| Output::SqlError {
+        id: None,
+        session: None,
+        sqlstate: sqlstate.to_string(),
+        message: String::new(),
+        detail: None,
+        hint: None,
+        position: None,
+        trace: Trace::only_duration(0),
+    };
+    assert_eq!(exit_code(&Output::Config(Box::default())), 0);
+    assert_eq!(exit_code(&error("invalid_params")), EXIT_ERROR);
+    assert_eq!(exit_code(&error("connect_failed")), EXIT_CONNECT);
+    assert_eq!(exit_code(&sql_error("42P01")), EXIT_SQL);
+    assert_eq!(exit_code(&error("result_too_large")), EXIT_POLICY);
+    assert_eq!(exit_code(&sql_error("57014")), EXIT_TIMEOUT);
+    assert_eq!(exit_code(&error("max_runtime_exceeded")), EXIT_TIMEOUT);
+}
+
+#[test]
+fn startup_argv_masks_secret_flags() {
+    let argv: Vec<String> = [
+        "afpsql",
+        "--approver-secret",
+        "hunter2",
+        "--dsn-secret=postgresql://u:pw@h/db",
+        "--mode",
+        "pipe",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    assert_eq!(
+        redact_secret_argv(&argv),
+        vec![
+            "afpsql",
+            "--approver-secret",
+            "***",
+            "--dsn-secret=***",
+            "--mode",
+            "pipe"
+        ]
+    );
+}
@@ -6,6 +6,7 @@ fn tools_list_contains_expected_tools() {
     let text = list.to_string();
     assert!(text.contains("psql_query"));
     assert!(text.contains("psql_config"));
+    assert!(text.contains("psql_execute_block"));
 }
 
 #[test]
@@ -33,3 +34,65 @@ fn has_session_override_detects_values() {
         ..Default::default()
     }));
 }
+
+fn aggregate_event() -> Value {
+    serde_json::json!({
+        "code": "result",
+        "columns": [{"name": "count", "type": "int8"}, {"name": "day", "type": "int4"}, {"name": "region", "type": "text"}],
+        "rows": [
+            {"region": "eu", "day": 1, "count": 3},
+            {"region": "eu", "day": 2, "count": 4},
+            {"region": "us", "day": 2, "count": 5}
+        ],
+        "row_count": 3
+    })
+}
+
+fn names() -> Vec<String> {
+    vec!["region".to_string(), "day".to_string(), "count".to_string()]
+}
+
+#[test]
+fn compact_result_turns_rows_into_arrays() {
+    let mut event = aggregate_event();
+    compact_result(&mut event, &names(), None);
+    assert_eq!(
+        event["columns"],
+        serde_json::json!(["region", "day", "count"])
+    );
+    assert_eq!(event["rows"][2], serde_json::json!(["us", 2, 5]));
+    assert_eq!(event["row_count"], 3);
+}
+
+#[test]
+fn compact_result_pivots_one_group_column() {
+    let mut event = aggregate_event();
+    compact_result(&mut event, &names(), Some("day"));
+    assert_eq!(event["columns"], serde_json::json!(["region", "1", "2"]));
+    assert_eq!(
+        event["rows"],
+        serde_json::json!([["eu", 3, 4], ["us", null, 5]])
+    );
+    assert_eq!(event["row_count"], 2);
+}
+
+#[test]
+fn format_sql_keeps_placeholders_casts_and_literals() {
+    let out = tool_format_sql(&json!({
+        "sql": "select id, name::text from users where id = $1 and note = 'a  b' order by id"
+    }));
+    assert_eq!(out["isError"], false);
+    let sql = out["structuredContent"]["sql"].as_str().unwrap_or_default();
+    assert_eq!(
+        sql,
+        "SELECT\n  id,\n  name :: text\nFROM\n  users\nWHERE\n  id = $1\n  AND note = 'a  b'\nORDER BY\n  id"
+    );
+    let body = tool_format_sql(&json!({"sql": "do $$ begin  perform 1; end $$"}));
+    assert!(body["structuredContent"]["sql"]
+        .as_str()
+        .is_some_and(|sql| sql.contains("$$ begin  perform 1; end $$")));
+    assert_eq!(
+        tool_format_sql(&json!({"sql": "select 1", "indent": 0}))["isError"],
+        true
+    );
+}
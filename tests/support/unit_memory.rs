@@ -0,0 +1,15 @@
+use super::*;
+
+#[test]
+fn reservations_release_on_drop() {
+    let usage = Arc::new(MemoryUsage::default());
+    let a = usage.reserve(10);
+    let b = usage.reserve(5);
+    assert_eq!(usage.used(), 15);
+    drop(a);
+    assert_eq!(usage.used(), 5);
+    drop(b);
+    assert_eq!(usage.used(), 0);
+    drop(MemoryReservation::default());
+    assert_eq!(usage.used(), 0);
+}
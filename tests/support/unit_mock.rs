@@ -0,0 +1,81 @@
+use super::*;
+use serde_json::json;
+
+fn mock() -> MockExecutor {
+    let spec = json!({"tables": {
+        "users": [
+            {"id": 1, "name": "ada", "team": "core"},
+            {"id": 2, "name": "grace", "team": null},
+            {"id": 3, "name": "linus", "team": "core"},
+        ],
+    }});
+    MockExecutor::from_spec(&spec, Path::new(".")).unwrap()
+}
+
+fn sqlstate(result: Result<Vec<Value>, ExecError>) -> String {
+    match result {
+        Err(ExecError::Sql { sqlstate, .. }) => sqlstate,
+        other => panic!("expected sql error, got {other:?}"),
+    }
+}
+
+#[test]
+fn select_filters_orders_and_pages() {
+    let mock = mock();
+    let rows = mock
+        .run(
+            "SELECT id, name AS who FROM users WHERE team = $1 AND id >= 1 ORDER BY id DESC LIMIT 1;",
+            &[json!("core")],
+        )
+        .unwrap();
+    assert_eq!(rows, vec![json!({"id": 3, "who": "linus"})]);
+
+    let rows = mock
+        .run("select name from users order by name limit 2 offset 1", &[])
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![json!({"name": "grace"}), json!({"name": "linus"})]
+    );
+
+    let rows = mock
+        .run("select count(*) from users where team is null", &[])
+        .unwrap();
+    assert_eq!(rows, vec![json!({"count": 1})]);
+
+    let rows = mock.run("select * from users where id = '2'", &[]).unwrap();
+    assert_eq!(rows[0]["name"], "grace");
+
+    let rows = mock.run("select 1 as n, 'x' as s", &[]).unwrap();
+    assert_eq!(rows, vec![json!({"n": 1, "s": "x"})]);
+}
+
+#[test]
+fn unsupported_sql_fails_like_postgres() {
+    let mock = mock();
+    assert_eq!(sqlstate(mock.run("select * from nope", &[])), "42P01");
+    assert_eq!(sqlstate(mock.run("select age from users", &[])), "42703");
+    assert_eq!(sqlstate(mock.run("delete from users", &[])), "0A000");
+    assert_eq!(
+        sqlstate(mock.run("select * from users where id = 1 or id = 2", &[])),
+        "0A000"
+    );
+    assert_eq!(
+        sqlstate(mock.run("select * from users where", &[])),
+        "42601"
+    );
+    assert_eq!(sqlstate(mock.run("select $2", &[json!(1)])), "42P02");
+}
+
+#[test]
+fn csv_tables_are_typed() {
+    let rows =
+        parse_csv("id,name,score,active\n1,\"Smith, J\",2.5,true\n2,\"say \"\"hi\"\"\",,false\n");
+    assert_eq!(
+        rows.into_iter().map(Value::Object).collect::<Vec<_>>(),
+        vec![
+            json!({"id": 1, "name": "Smith, J", "score": 2.5, "active": true}),
+            json!({"id": 2, "name": "say \"hi\"", "score": null, "active": false}),
+        ]
+    );
+}
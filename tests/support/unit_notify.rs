@@ -0,0 +1,94 @@
+use super::*;
+
+fn finished<'a>(outcome: &'a str, error_code: Option<&'a str>) -> Finished<'a> {
+    Finished {
+        id: Some("q1"),
+        session: "default",
+        sql: "select * from orders",
+        outcome,
+        error_code,
+        row_count: Some(3),
+        duration_ms: 12_345,
+    }
+}
+
+#[test]
+fn notify_parses_a_url_or_a_command() {
+    let notify: Notify = serde_json::from_value(json!({"url": "http://relay:8080/slack"})).unwrap();
+    assert_eq!(
+        notify.target,
+        NotifyTarget::Url("http://relay:8080/slack".into())
+    );
+    assert_eq!(notify.after_ms, DEFAULT_AFTER_MS);
+    assert!(notify.validate().is_ok());
+
+    let notify: Notify =
+        serde_json::from_value(json!({"command": ["notify-send", "afpsql"], "after_ms": 0}))
+            .unwrap();
+    assert_eq!(notify.after_ms, 0);
+    assert!(notify.validate().is_ok());
+
+    for bad in [
+        json!({"url": "ftp://relay/x"}),
+        json!({"command": []}),
+        json!({"command": [""]}),
+    ] {
+        let notify: Notify = serde_json::from_value(bad.clone()).unwrap();
+        assert!(notify.validate().is_err(), "{bad}");
+    }
+    assert!(serde_json::from_value::<Notify>(json!({"after_ms": 5})).is_err());
+}
+
+#[test]
+fn record_says_how_the_query_ended() {
+    let ok = record(&finished("result", None));
+    assert_eq!(
+        ok["text"],
+        "afpsql: query q1 on default finished with 3 rows after 12.3s"
+    );
+    assert_eq!(ok["event"], "query.finished");
+    assert_eq!(ok["duration_ms"], 12_345);
+
+    let failed = record(&finished("sql_error", Some("57014")));
+    assert_eq!(
+        failed["text"],
+        "afpsql: query q1 on default failed (sql_error 57014) after 12.3s"
+    );
+
+    let long = "x".repeat(500);
+    let mut f = finished("result", None);
+    f.sql = &long;
+    let sql = record(&f)["sql"].as_str().unwrap().to_string();
+    assert_eq!(sql.len(), SQL_CHARS + 3);
+}
+
+#[tokio::test]
+async fn command_gets_the_record_on_stdin() {
+    let dir = std::env::temp_dir().join(format!("afpsql-notify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("record.json");
+    let notify = Notify {
+        target: NotifyTarget::Command(vec![
+            "sh".into(),
+            "-c".into(),
+            format!(
+                "cat > {0}; echo >> {0}; echo \"$AFPSQL_NOTIFY_TEXT\" >> {0}",
+                out.display()
+            ),
+        ]),
+        after_ms: 0,
+    };
+    let sent = record(&finished("result", None));
+    send(&notify, &sent).await.unwrap();
+    let written = std::fs::read_to_string(&out).unwrap();
+    let (body, text) = written.split_once('\n').unwrap();
+    assert_eq!(serde_json::from_str::<Value>(body).unwrap(), sent);
+    assert_eq!(text.trim_end(), sent["text"].as_str().unwrap());
+
+    let failing = Notify {
+        target: NotifyTarget::Command(vec!["false".into()]),
+        after_ms: 0,
+    };
+    assert!(send(&failing, &sent).await.is_err());
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,86 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn checkpoints_round_trip_through_the_state_dir() {
+    let dir = std::env::temp_dir().join(format!("afpsql_operations_{}", std::process::id()));
+    let path = checkpoint_path(&dir.to_string_lossy(), "archive-1").unwrap();
+    assert!(path.ends_with("operations/archive-1.json"));
+
+    let request: ArchiveRequest = serde_json::from_value(json!({
+        "id": "archive-1",
+        "table": "events",
+        "archive_table": "events_archive",
+        "where": {"id": {"lt": 100}},
+        "batch_rows": 10
+    }))
+    .unwrap();
+    let operation = serde_json::to_value(Operation::Archive(request)).unwrap();
+    assert_eq!(operation["kind"], "archive");
+    let cursor = Cursor {
+        done: 30,
+        batches: 3,
+        ..Cursor::default()
+    };
+    save(&path, &operation, &cursor).unwrap();
+
+    let loaded = load(&path).unwrap();
+    assert_eq!(loaded.cursor, cursor);
+    match loaded.operation {
+        Operation::Archive(request) => {
+            assert_eq!(request.archive_table, "events_archive");
+            assert_eq!(request.filter, json!({"id": {"lt": 100}}));
+        }
+        other => panic!("unexpected operation {other:?}"),
+    }
+
+    remove(&path);
+    assert!(load(&path).is_err());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn checkpoint_ids_stay_inside_the_state_dir() {
+    assert!(checkpoint_path("/tmp", "../escape").is_err());
+    assert!(checkpoint_path("/tmp", "").is_err());
+}
+
+#[test]
+fn controls_track_running_operations() {
+    let request: ChunkedWriteRequest = serde_json::from_value(json!({
+        "id": "op",
+        "table": "events",
+        "where": {"id": {"op": ">", "value": 0}},
+        "chunking": {"batch_rows": 10},
+        "options": {}
+    }))
+    .unwrap();
+    let operation = Operation::Chunked(request);
+    assert_eq!(operation.phase(), "delete");
+
+    let controls = Arc::new(Controls::default());
+    let control = controls.register("op", &operation).unwrap();
+    assert!(controls.register("op", &operation).is_none());
+    assert!(!control.paused());
+
+    control.progress(40, 4);
+    assert!(controls.set_paused("op", true));
+    assert!(control.paused());
+    assert!(!controls.set_paused("other", true));
+    assert_eq!(
+        controls.list(),
+        vec![OperationEntry {
+            id: "op".to_string(),
+            phase: "delete".to_string(),
+            table: "events".to_string(),
+            state: "paused".to_string(),
+            done: 40,
+            batches: 4,
+        }]
+    );
+
+    drop(control);
+    assert!(!controls.set_paused("op", false));
+    assert!(controls.list().is_empty());
+    assert!(controls.register("op", &operation).is_some());
+}
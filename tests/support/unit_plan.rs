@@ -0,0 +1,100 @@
+use super::*;
+use serde_json::json;
+
+fn explain(scan: &str, cost: f64) -> Value {
+    json!({"QUERY PLAN": [{"Plan": {
+        "Node Type": "Hash Join",
+        "Join Type": "Inner",
+        "Total Cost": cost,
+        "Plan Rows": 10,
+        "Plans": [
+            {"Node Type": scan, "Relation Name": "orders", "Index Name": "orders_pkey"},
+            {"Node Type": "Hash", "Plans": [{"Node Type": "Seq Scan", "Relation Name": "users"}]}
+        ]
+    }}]})
+}
+
+#[test]
+fn snapshot_lists_nodes_in_tree_order() {
+    let snap = snapshot(&explain("Index Scan", 42.5)).unwrap();
+    assert_eq!(
+        snap.shape,
+        vec![
+            "Hash Join (Inner)",
+            "  Index Scan using orders_pkey on orders",
+            "  Hash",
+            "    Seq Scan on users",
+        ]
+    );
+    assert_eq!((snap.total_cost, snap.plan_rows), (42.5, 10.0));
+    assert!(snapshot(&json!({"QUERY PLAN": []})).is_none());
+}
+
+#[test]
+fn compare_flags_shape_and_cost_changes() {
+    let base = snapshot(&explain("Index Scan", 100.0)).unwrap();
+    let same = compare(
+        &base,
+        &snapshot(&explain("Index Scan", 110.0)).unwrap(),
+        0.2,
+    );
+    assert!(!same.shape_changed && !same.cost_changed);
+    assert_eq!(same.cost_change, Some(0.1));
+
+    let slower = compare(
+        &base,
+        &snapshot(&explain("Bitmap Heap Scan", 150.0)).unwrap(),
+        0.2,
+    );
+    assert!(slower.shape_changed && slower.cost_changed);
+
+    let zero = snapshot(&explain("Index Scan", 0.0)).unwrap();
+    let from_zero = compare(&zero, &base, 0.2);
+    assert_eq!(from_zero.cost_change, None);
+    assert!(from_zero.cost_changed);
+}
+
+#[test]
+fn plan_hash_follows_shape_not_cost() {
+    let base = plan_hash(&snapshot(&explain("Index Scan", 100.0)).unwrap());
+    assert_eq!(base.len(), 16);
+    assert_eq!(
+        plan_hash(&snapshot(&explain("Index Scan", 900.0)).unwrap()),
+        base
+    );
+    assert_ne!(
+        plan_hash(&snapshot(&explain("Seq Scan", 100.0)).unwrap()),
+        base
+    );
+}
+
+#[test]
+fn fingerprint_ignores_whitespace_but_not_session() {
+    let a = sql_fingerprint("default", "select *\n  from t where id = $1;");
+    assert_eq!(
+        a,
+        sql_fingerprint("default", " select * from t where id = $1")
+    );
+    assert_ne!(
+        a,
+        sql_fingerprint("replica", "select * from t where id = $1")
+    );
+    assert_ne!(
+        a,
+        sql_fingerprint("default", "select * from t where id = $2")
+    );
+}
+
+#[test]
+fn baselines_drop_the_least_recently_checked() {
+    let snap = snapshot(&explain("Index Scan", 1.0)).unwrap();
+    let mut baselines = PlanBaselines::default();
+    for i in 0..MAX_BASELINES {
+        baselines.set(format!("k{i}"), snap.clone());
+    }
+    assert!(baselines.get("k0").is_some());
+    baselines.set("new".to_string(), snap);
+    assert!(baselines.get("k0").is_some());
+    assert!(baselines.get("k1").is_none());
+    assert!(baselines.get("new").is_some());
+}
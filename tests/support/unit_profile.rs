@@ -0,0 +1,28 @@
+use super::*;
+
+#[test]
+fn timer_adds_to_its_module() {
+    let before = timings().get("test.profile").copied().unwrap_or_default();
+    {
+        let _timer = timer("test.profile");
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    record("test.profile", Duration::from_micros(10));
+    let after = timings()["test.profile"];
+    assert_eq!(after.calls, before.calls + 2);
+    assert!(after.total_us >= before.total_us + 2_010);
+    assert!(after.max_us >= 2_000);
+}
+
+#[test]
+fn status_kb_reads_proc_status_lines() {
+    let status = "Name:\tafpsql\nVmHWM:\t   20480 kB\nVmRSS:\t   10240 kB\n";
+    assert_eq!(status_kb(status, "VmRSS:"), Some(10240));
+    assert_eq!(status_kb(status, "VmHWM:"), Some(20480));
+    assert_eq!(status_kb(status, "VmSwap:"), None);
+    let memory = memory(7);
+    assert_eq!(memory.result_bytes_queued, 7);
+    if cfg!(target_os = "linux") {
+        assert!(memory.rss_bytes.is_some_and(|rss| rss > 0));
+    }
+}
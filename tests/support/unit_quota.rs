@@ -0,0 +1,94 @@
+use super::*;
+
+fn quota(queries: Option<u64>, rows: Option<u64>, bytes: Option<u64>) -> AgentQuota {
+    AgentQuota {
+        queries_per_minute: queries,
+        rows_per_minute: rows,
+        bytes_per_minute: bytes,
+    }
+}
+
+#[test]
+fn queries_are_limited_per_window() {
+    let mut q = Quotas::default();
+    let limit = quota(Some(2), None, None);
+    let now = Instant::now();
+    assert!(q.admit("bot", &limit, now).is_ok());
+    assert!(q.admit("bot", &limit, now).is_ok());
+    let err = q.admit("bot", &limit, now).unwrap_err();
+    assert!(err.contains("queries_per_minute 2"), "{err}");
+    assert!(q.admit("other", &limit, now).is_ok());
+    assert!(q.admit("bot", &limit, now + WINDOW).is_ok());
+}
+
+#[test]
+fn rows_and_bytes_count_once_sent() {
+    let mut q = Quotas::default();
+    let limit = quota(None, Some(100), Some(1_000));
+    let now = Instant::now();
+    assert!(q.admit("bot", &limit, now).is_ok());
+    q.charge("bot", 150, 10, now);
+    let err = q.admit("bot", &limit, now).unwrap_err();
+    assert!(err.contains("used 150 of rows_per_minute 100"), "{err}");
+
+    let mut q = Quotas::default();
+    assert!(q.admit("bot", &limit, now).is_ok());
+    q.charge("bot", 1, 1_000, now);
+    assert!(q.admit("bot", &limit, now).is_err());
+}
+
+#[test]
+fn untracked_agents_are_not_charged() {
+    let mut q = Quotas::default();
+    q.charge("bot", 10, 10, Instant::now());
+    assert!(q.usage.is_empty());
+}
+
+#[test]
+fn star_entry_covers_agents_without_their_own() {
+    let quotas = HashMap::from([
+        ("bot".to_string(), quota(Some(1), None, None)),
+        (ANY_AGENT.to_string(), quota(Some(5), None, None)),
+    ]);
+    let (key, limit) = quota_for(&quotas, "bot").unwrap();
+    assert_eq!((key, limit.queries_per_minute), ("bot", Some(1)));
+    let (key, limit) = quota_for(&quotas, "x").unwrap();
+    assert_eq!((key, limit.queries_per_minute), (ANY_AGENT, Some(5)));
+    assert!(quota_for::<AgentQuota>(&HashMap::new(), "x").is_none());
+}
+
+#[test]
+fn star_agents_share_one_window() {
+    let mut q = Quotas::default();
+    let limit = quota(Some(1), None, None);
+    let now = Instant::now();
+    assert!(q.admit(ANY_AGENT, &limit, now).is_ok());
+    let err = q.admit(ANY_AGENT, &limit, now).unwrap_err();
+    assert!(
+        err.starts_with("agents without a quota of their own"),
+        "{err}"
+    );
+}
+
+#[test]
+fn patches_only_tighten_quotas() {
+    let mut quotas = HashMap::from([
+        ("bot".to_string(), quota(Some(10), None, Some(100))),
+        (ANY_AGENT.to_string(), quota(Some(5), Some(50), None)),
+    ]);
+    let patch = HashMap::from([
+        ("bot".to_string(), quota(Some(99), Some(20), None)),
+        ("new".to_string(), quota(Some(1_000), None, None)),
+    ]);
+    tighten_limits(&mut quotas, patch, tighten_quota);
+    assert_eq!(quotas["bot"], quota(Some(10), Some(20), Some(100)));
+    assert_eq!(quotas["new"], quota(Some(5), Some(50), None));
+
+    let mut open = HashMap::new();
+    tighten_limits(
+        &mut open,
+        HashMap::from([("bot".to_string(), quota(Some(3), None, None))]),
+        tighten_quota,
+    );
+    assert_eq!(open["bot"], quota(Some(3), None, None));
+}
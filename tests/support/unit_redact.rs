@@ -0,0 +1,130 @@
+use super::*;
+use serde_json::json;
+
+fn rule(column: &str, action: RedactAction) -> RedactionRule {
+    RedactionRule {
+        column: column.to_string(),
+        action,
+    }
+}
+
+#[test]
+fn glob_match_cases() {
+    assert!(glob_match("*_ssn", "customer_SSN"));
+    assert!(glob_match("password", "Password"));
+    assert!(glob_match("a?c*", "abcdef"));
+    assert!(glob_match("*", ""));
+    assert!(!glob_match("*_ssn", "ssn_hint"));
+    assert!(!glob_match("pass", "password"));
+}
+
+#[test]
+fn redact_rows_by_name_and_nested() {
+    let rules = vec![
+        rule("password", RedactAction::Mask),
+        rule("*_ssn", RedactAction::Null),
+        rule("email", RedactAction::Hash),
+    ];
+    let mut rows = vec![json!({
+        "id": 1,
+        "password": "hunter2",
+        "tax_ssn": "123",
+        "email": "a@b.c",
+        "row": {"password": "x", "tags": [{"user_ssn": "9"}]},
+        "note": null
+    })];
+    redact_rows(&mut rows, &ColumnOrigins::new(), &rules, None);
+    let row = &rows[0];
+    assert_eq!(row["id"], 1);
+    assert_eq!(row["password"], MASK);
+    assert_eq!(row["tax_ssn"], Value::Null);
+    assert!(row["email"].as_str().unwrap().starts_with("sha256:"));
+    assert_eq!(row["row"]["password"], MASK);
+    assert_eq!(row["row"]["tags"][0]["user_ssn"], Value::Null);
+
+    let mut salted = vec![json!({"email": "a@b.c"})];
+    redact_rows(&mut salted, &ColumnOrigins::new(), &rules, Some("pepper"));
+    assert_ne!(salted[0]["email"], row["email"]);
+}
+
+#[test]
+fn qualified_rules_need_origin() {
+    let rules = vec![rule("public.users.name", RedactAction::Mask)];
+    assert!(needs_origins(&rules));
+    let mut origins = ColumnOrigins::new();
+    origins.insert(
+        "name".to_string(),
+        vec![("public".to_string(), "users".to_string())],
+    );
+    let mut rows = vec![json!({"name": "ann"})];
+    redact_rows(&mut rows, &origins, &rules, None);
+    assert_eq!(rows[0]["name"], MASK);
+
+    let mut rows = vec![json!({"name": "ann"})];
+    redact_rows(&mut rows, &ColumnOrigins::new(), &rules, None);
+    assert_eq!(rows[0]["name"], "ann");
+}
+
+#[test]
+fn pseudonyms_keep_shape_and_equality() {
+    let rules = vec![
+        rule("*id", RedactAction::Pseudonymize),
+        rule("email", RedactAction::Pseudonymize),
+    ];
+    let mut rows = vec![
+        json!({"id": 7, "email": "ann@corp.example", "name_id": "x1"}),
+        json!({"id": 8, "email": "ann@corp.example", "name_id": null}),
+    ];
+    redact_rows(&mut rows, &ColumnOrigins::new(), &rules, Some("pepper"));
+    let id = rows[0]["id"].as_u64().unwrap();
+    assert!(id < 1 << 53);
+    assert_ne!(rows[0]["id"], rows[1]["id"]);
+    assert_eq!(rows[0]["email"], rows[1]["email"]);
+    assert!(rows[0]["email"]
+        .as_str()
+        .unwrap()
+        .ends_with("@example.invalid"));
+    assert!(rows[0]["name_id"].as_str().unwrap().starts_with("anon_"));
+    assert_eq!(rows[1]["name_id"], Value::Null);
+    // The same value and salt always map to the same stand-in.
+    assert_eq!(pseudonym(&json!(7), Some("pepper")), rows[0]["id"]);
+    assert_ne!(pseudonym(&json!(7), None), rows[0]["id"]);
+}
+
+#[test]
+fn column_globs_skip_rules_for_other_tables() {
+    let rules = vec![
+        rule("ssn", RedactAction::Mask),
+        rule("users.email", RedactAction::Mask),
+        rule("hr.*.salary", RedactAction::Mask),
+    ];
+    assert_eq!(column_globs(&rules, Some("public.orders")), vec!["ssn"]);
+    // Without a schema, a schema-qualified rule may still apply.
+    assert_eq!(column_globs(&rules, Some("orders")), vec!["ssn", "salary"]);
+    assert_eq!(
+        column_globs(&rules, Some("public.users")),
+        vec!["ssn", "email"]
+    );
+    assert_eq!(
+        column_globs(&rules, Some("hr.staff")),
+        vec!["ssn", "salary"]
+    );
+    assert_eq!(column_globs(&rules, None), vec!["ssn", "email", "salary"]);
+}
+
+#[test]
+fn qualified_rules_reach_keys_nested_in_a_sourced_column() {
+    let rules = vec![rule("users.ssn", RedactAction::Mask)];
+    let mut rows = vec![json!({"row": {"ssn": "123-45-6789", "name": "ann"}})];
+    redact_rows(&mut rows, &ColumnOrigins::new(), &rules, None);
+    assert_eq!(rows[0]["row"]["ssn"], "123-45-6789");
+    let origins: ColumnOrigins = [(
+        "row".to_string(),
+        vec![("public".to_string(), "users".to_string())],
+    )]
+    .into_iter()
+    .collect();
+    redact_rows(&mut rows, &origins, &rules, None);
+    assert_eq!(rows[0]["row"]["ssn"], MASK);
+    assert_eq!(rows[0]["row"]["name"], "ann");
+}
@@ -0,0 +1,95 @@
+use super::*;
+
+#[test]
+fn default_registry_builds_the_configured_executor() {
+    let registry = ExecutorRegistry::default();
+    assert_eq!(
+        registry.names(),
+        vec!["echo", "mock", "postgres", "record", "replay"]
+    );
+
+    let config = RuntimeConfig {
+        executor: "echo".to_string(),
+        ..RuntimeConfig::default()
+    };
+    assert!(registry.build(&config).is_ok());
+
+    let config = RuntimeConfig {
+        executor: "replay".to_string(),
+        ..RuntimeConfig::default()
+    };
+    let err = registry.build(&config).err().unwrap();
+    assert_eq!(err, "the replay executor needs replay_path");
+
+    let config = RuntimeConfig {
+        executor: "oracle".to_string(),
+        ..RuntimeConfig::default()
+    };
+    let err = registry.build(&config).err().unwrap();
+    assert_eq!(
+        err,
+        "unknown executor: oracle; available: echo, mock, postgres, record, replay"
+    );
+}
+
+#[test]
+fn chaos_wraps_the_configured_executor_once_valid() {
+    let registry = ExecutorRegistry::default();
+    let mut chaos: crate::chaos::Chaos = serde_json::from_value(json!({"seed": 1})).unwrap();
+    let config = RuntimeConfig {
+        executor: "echo".to_string(),
+        chaos: Some(chaos.clone()),
+        ..RuntimeConfig::default()
+    };
+    assert!(registry.build(&config).is_ok());
+
+    chaos.latency_probability = 2.0;
+    let config = RuntimeConfig {
+        chaos: Some(chaos),
+        ..config
+    };
+    let err = registry.build(&config).err().unwrap();
+    assert_eq!(err, "chaos.latency_probability must be between 0 and 1");
+}
+
+#[test]
+fn registered_factories_see_the_config() {
+    let mut registry = ExecutorRegistry::default();
+    registry.register("picky", |config| {
+        if config.results_dir.is_none() {
+            Err("picky needs results_dir".to_string())
+        } else {
+            Ok(Arc::new(EchoExecutor))
+        }
+    });
+    let config = RuntimeConfig {
+        executor: "picky".to_string(),
+        ..RuntimeConfig::default()
+    };
+    assert_eq!(
+        registry.build(&config).err().unwrap(),
+        "picky needs results_dir"
+    );
+}
+
+#[tokio::test]
+async fn echo_executor_describes_the_statement() {
+    let opts = RuntimeConfig::default().resolve_options(&Default::default());
+    let outcome = EchoExecutor
+        .execute(
+            "default",
+            &SessionConfig::default(),
+            "select $1",
+            &[json!(7)],
+            &opts,
+        )
+        .await
+        .unwrap();
+    match outcome {
+        ExecOutcome::Rows(rows) => assert_eq!(
+            rows,
+            vec![json!({"session": "default", "sql": "select $1", "params": [7]})]
+        ),
+        other => panic!("expected rows, got {other:?}"),
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+use crate::types::RuntimeConfig;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts calls so repeated statements get different rows.
+#[derive(Default)]
+struct CountingExecutor {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl DbExecutor for CountingExecutor {
+    async fn execute(
+        &self,
+        _session_name: &str,
+        _session_cfg: &SessionConfig,
+        sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        let n = self.calls.fetch_add(1, Ordering::SeqCst);
+        match sql {
+            "select n" => Ok(ExecOutcome::Rows(vec![json!({"n": n})])),
+            "delete" => Ok(ExecOutcome::Command { affected: 3 }),
+            _ => Err(ExecError::Sql {
+                sqlstate: "42601".to_string(),
+                message: "syntax error".to_string(),
+                detail: None,
+                hint: None,
+                position: Some("1".to_string()),
+            }),
+        }
+    }
+}
+
+fn rows(result: Result<ExecOutcome, ExecError>) -> Vec<Value> {
+    match result {
+        Ok(ExecOutcome::Rows(rows)) => rows,
+        other => panic!("expected rows, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn replay_serves_what_was_recorded() {
+    let path = std::env::temp_dir().join(format!("afpsql_fixture_{}.jsonl", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let cfg = SessionConfig::default();
+    let opts = RuntimeConfig::default().resolve_options(&Default::default());
+
+    let recorder = RecordingExecutor::create(&path, Arc::new(CountingExecutor::default())).unwrap();
+    for sql in ["select n", "select n", "delete", "selec"] {
+        let _ = recorder
+            .execute("default", &cfg, sql, &[json!(1)], &opts)
+            .await;
+    }
+    drop(recorder);
+
+    let replay = ReplayExecutor::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let params = [json!(1)];
+    let run = |sql: &'static str| replay.execute("default", &cfg, sql, &params, &opts);
+    assert_eq!(rows(run("select n").await), vec![json!({"n": 0})]);
+    assert_eq!(rows(run("select n").await), vec![json!({"n": 1})]);
+    assert_eq!(rows(run("select n").await), vec![json!({"n": 1})]);
+    assert!(matches!(
+        run("delete").await,
+        Ok(ExecOutcome::Command { affected: 3 })
+    ));
+    match run("selec").await {
+        Err(ExecError::Sql {
+            sqlstate, position, ..
+        }) => {
+            assert_eq!(sqlstate, "42601");
+            assert_eq!(position.as_deref(), Some("1"));
+        }
+        other => panic!("expected sql error, got {other:?}"),
+    }
+
+    let other_params = replay
+        .execute("default", &cfg, "select n", &[json!(2)], &opts)
+        .await;
+    match other_params {
+        Err(ExecError::Internal(message)) => {
+            assert!(message.contains("no response"), "{message}")
+        }
+        other => panic!("expected a miss, got {other:?}"),
+    }
+}
+
+#[test]
+fn fixture_entries_are_flat_json_lines() {
+    let entry = FixtureEntry {
+        session: "default".to_string(),
+        sql: "select 1".to_string(),
+        params: vec![],
+        outcome: RecordedOutcome::Error {
+            error_code: "connect_failed".to_string(),
+            message: "refused".to_string(),
+        },
+    };
+    let value = serde_json::to_value(&entry).unwrap();
+    assert_eq!(
+        value,
+        json!({"session": "default", "sql": "select 1", "params": [], "outcome": "error",
+               "error_code": "connect_failed", "message": "refused"})
+    );
+    assert!(matches!(
+        RecordedOutcome::Error {
+            error_code: "connect_failed".to_string(),
+            message: "refused".to_string(),
+        }
+        .replay(),
+        Err(ExecError::Connect(_))
+    ));
+}
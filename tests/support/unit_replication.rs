@@ -0,0 +1,14 @@
+use super::*;
+
+#[test]
+fn bytes_per_second_scales_to_the_sample() {
+    let half = Duration::from_millis(500);
+    assert_eq!(bytes_per_second(1_000, 9_000, half), Some(16_000));
+    assert_eq!(
+        bytes_per_second(0, 3_000, Duration::from_secs(3)),
+        Some(1_000)
+    );
+    // A standby that restarted replay never reads as negative.
+    assert_eq!(bytes_per_second(9_000, 1_000, half), Some(0));
+    assert_eq!(bytes_per_second(0, 1, Duration::ZERO), None);
+}
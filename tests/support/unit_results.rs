@@ -0,0 +1,65 @@
+use super::*;
+use serde_json::json;
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("afpsql_results_{}", new_handle()))
+}
+
+fn columns() -> Vec<ColumnInfo> {
+    vec![ColumnInfo {
+        name: "n".to_string(),
+        type_name: "json".to_string(),
+    }]
+}
+
+#[test]
+fn save_and_page_through_result() {
+    let dir = scratch_dir();
+    let rows: Vec<Value> = (0..10).map(|n| json!({"n": n})).collect();
+    let handle = new_handle();
+    let bytes = save(&dir, &handle, &columns(), &rows).unwrap();
+    assert_eq!(
+        bytes,
+        rows.iter().map(|r| r.to_string().len()).sum::<usize>()
+    );
+
+    let page = read_page(&dir, &handle, 3, 4).unwrap();
+    assert_eq!(page.total_rows, 10);
+    assert_eq!(page.columns[0].name, "n");
+    assert_eq!(
+        page.rows,
+        vec![
+            json!({"n": 3}),
+            json!({"n": 4}),
+            json!({"n": 5}),
+            json!({"n": 6})
+        ]
+    );
+
+    let tail = read_page(&dir, &handle, 8, 100).unwrap();
+    assert_eq!(tail.rows.len(), 2);
+    assert!(read_page(&dir, &handle, 20, 5).unwrap().rows.is_empty());
+
+    delete(&dir, &handle).unwrap();
+    let err = read_page(&dir, &handle, 0, 1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn handles_are_unique_and_valid() {
+    let a = new_handle();
+    let b = new_handle();
+    assert_ne!(a, b);
+    assert!(valid_handle(&a));
+}
+
+#[test]
+fn valid_handle_rejects_paths() {
+    assert!(!valid_handle(""));
+    assert!(!valid_handle("../etc/passwd"));
+    assert!(!valid_handle("a/b"));
+    assert!(!valid_handle("res.jsonl"));
+    assert!(!valid_handle(&"x".repeat(129)));
+    assert!(valid_handle("res_1_2_3"));
+}
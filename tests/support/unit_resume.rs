@@ -0,0 +1,29 @@
+use super::*;
+use crate::types::{QueryOptions, RuntimeConfig};
+use serde_json::json;
+
+fn begin(log: &mut StreamLog, id: &str) {
+    let opts = RuntimeConfig::default().resolve_options(&QueryOptions::default());
+    log.begin(id, None, vec![], &opts);
+}
+
+#[test]
+fn oldest_streams_are_dropped_to_stay_within_the_budget() {
+    let mut log = StreamLog::default();
+    begin(&mut log, "a");
+    log.append("a", vec![json!(1)], 60, 100);
+    begin(&mut log, "b");
+    log.append("b", vec![json!(2)], 30, 100);
+    assert!(log.get("a").is_some());
+
+    log.append("b", vec![json!(3)], 30, 100);
+    assert!(log.get("a").is_none());
+    assert_eq!(log.get("b").unwrap().batches.len(), 2);
+
+    // A stream that outgrows the whole budget is not kept.
+    log.append("b", vec![json!(4)], 50, 100);
+    assert!(log.get("b").is_none());
+    begin(&mut log, "c");
+    log.append("c", vec![json!(5)], 100, 100);
+    assert!(log.get("c").is_some());
+}
@@ -0,0 +1,98 @@
+use super::*;
+
+// Friday 2026-10-16 09:30 UTC.
+const FRIDAY_0930: u64 = 1_792_143_000_000;
+const MINUTE: u64 = 60_000;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+#[test]
+fn steps_ranges_and_names() {
+    let cron = Cron::parse("*/15 9-17 * * mon-fri").unwrap();
+    assert!(cron.matches(FRIDAY_0930));
+    assert!(!cron.matches(FRIDAY_0930 + MINUTE));
+    assert_eq!(
+        cron.next_after(FRIDAY_0930),
+        Some(FRIDAY_0930 + 15 * MINUTE)
+    );
+    // From Friday 17:45 to Monday 09:00.
+    let friday_1745 = FRIDAY_0930 + 8 * HOUR + 15 * MINUTE;
+    assert_eq!(
+        cron.next_after(friday_1745),
+        Some(FRIDAY_0930 + 3 * DAY - 30 * MINUTE)
+    );
+
+    let sunday = Cron::parse("0 0 * * 7").unwrap();
+    let saturday_midnight = FRIDAY_0930 - 9 * HOUR - 30 * MINUTE + DAY;
+    assert_eq!(
+        sunday.next_after(FRIDAY_0930),
+        Some(saturday_midnight + DAY)
+    );
+}
+
+#[test]
+fn restricted_day_fields_match_either() {
+    let cron = Cron::parse("0 0 1 * mon").unwrap();
+    let monday_midnight = FRIDAY_0930 - 9 * HOUR - 30 * MINUTE + 3 * DAY;
+    assert_eq!(cron.next_after(FRIDAY_0930), Some(monday_midnight));
+
+    let leap_day = Cron::parse("0 0 29 feb *").unwrap();
+    assert_eq!(leap_day.next_after(FRIDAY_0930), Some(1_835_395_200_000));
+}
+
+#[test]
+fn shorthands_keep_their_text() {
+    let cron: Cron = serde_json::from_value(serde_json::json!("@daily")).unwrap();
+    assert_eq!(
+        cron.next_after(FRIDAY_0930),
+        Some(FRIDAY_0930 + 14 * HOUR + 30 * MINUTE)
+    );
+    assert_eq!(serde_json::to_value(&cron).unwrap(), "@daily");
+}
+
+#[test]
+fn invalid_expressions_are_refused() {
+    for (source, error) in [
+        ("* * *", "needs 5 fields"),
+        ("61 * * * *", "outside 0-59"),
+        ("*/0 * * * *", "positive number"),
+        ("5-1 * * * *", "backwards"),
+        ("0 0 * foo *", "not a number"),
+        ("0 0 30 2 *", "never matches"),
+    ] {
+        let e = Cron::parse(source).unwrap_err();
+        assert!(e.contains(error), "{source}: {e}");
+    }
+}
+
+#[test]
+fn webhooks_are_http_or_https() {
+    assert_eq!(
+        split_url("http://localhost:8080/hooks/report").unwrap(),
+        ("localhost".to_string(), 8080, "/hooks/report".to_string())
+    );
+    assert_eq!(
+        split_url("http://[::1]/").unwrap(),
+        ("::1".to_string(), 80, "/".to_string())
+    );
+    assert_eq!(
+        split_url("http://relay").unwrap(),
+        ("relay".to_string(), 80, "/".to_string())
+    );
+    if cfg!(feature = "https") {
+        assert_eq!(
+            split_url("https://hooks.slack.com/services/x").unwrap(),
+            (
+                "hooks.slack.com".to_string(),
+                443,
+                "/services/x".to_string()
+            )
+        );
+    } else {
+        assert!(split_url("https://hooks.slack.com/services/x")
+            .unwrap_err()
+            .contains("--features https"));
+    }
+    assert!(split_url("ftp://relay/").unwrap_err().contains("https://"));
+    assert!(split_url("http://relay:x/").is_err());
+}
@@ -0,0 +1,38 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn lookups_expire_with_ttl_and_schema_generation() {
+    let mut cache = SchemaCache::default();
+    let minute = Duration::from_secs(60);
+    let params = [json!("users")];
+    cache.insert("default", "keys", &params, vec![json!({"k": 1})], minute, 3);
+    assert_eq!(
+        cache.get("default", "keys", &params, minute, 3),
+        Some(vec![json!({"k": 1})])
+    );
+    assert!(cache
+        .get("default", "keys", &[json!("orders")], minute, 3)
+        .is_none());
+    assert!(cache.get("replica", "keys", &params, minute, 3).is_none());
+    assert!(cache
+        .get("default", "keys", &params, Duration::ZERO, 3)
+        .is_none());
+
+    cache.insert("default", "keys", &params, vec![], minute, 3);
+    assert!(cache.get("default", "keys", &params, minute, 4).is_none());
+}
+
+#[test]
+fn invalidate_drops_one_session_or_all() {
+    let mut cache = SchemaCache::default();
+    let minute = Duration::from_secs(60);
+    for session in ["a", "b"] {
+        cache.insert(session, "keys", &[], vec![], minute, 0);
+        cache.insert(session, "stats", &[], vec![], minute, 0);
+    }
+    assert_eq!(cache.invalidate(Some("a")), 2);
+    assert!(cache.get("a", "keys", &[], minute, 0).is_none());
+    assert!(cache.get("b", "keys", &[], minute, 0).is_some());
+    assert_eq!(cache.invalidate(None), 2);
+}
@@ -0,0 +1,87 @@
+use super::*;
+use crate::er::EdgeRow;
+
+fn node(table: &str, primary_key: &[&str], estimated_rows: Option<i64>) -> Node {
+    Node {
+        table: table.to_string(),
+        primary_key: primary_key.iter().map(|c| c.to_string()).collect(),
+        estimated_rows,
+    }
+}
+
+fn column(table: &str, name: &str, data_type: &str) -> Column {
+    Column {
+        table: table.to_string(),
+        name: name.to_string(),
+        data_type: data_type.to_string(),
+    }
+}
+
+fn shop() -> (Vec<Node>, Vec<Edge>, Vec<Column>) {
+    let nodes = vec![
+        node("audit", &[], None),
+        node("orders", &["id"], Some(52_000)),
+        node("users", &["id"], Some(1_200)),
+    ];
+    let edges = vec![Edge::from(EdgeRow {
+        name: "orders_user_id_fkey".to_string(),
+        from_table: "orders".to_string(),
+        from_columns: vec!["user_id".to_string()],
+        to_table: "users".to_string(),
+        to_columns: vec!["id".to_string()],
+        unique_from: false,
+        optional: false,
+    })];
+    let columns = vec![
+        column("audit", "note", "text"),
+        column("orders", "id", "bigint"),
+        column("orders", "user_id", "integer"),
+        column("orders", "total", "numeric"),
+        column("orders", "placed_at", "timestamp with time zone"),
+        column("users", "id", "integer"),
+        column("users", "email", "text"),
+    ];
+    (nodes, edges, columns)
+}
+
+#[test]
+fn magnitude_rounds_down_to_a_power_of_ten() {
+    assert_eq!(magnitude(None), "unanalyzed");
+    assert_eq!(magnitude(Some(0)), "empty");
+    assert_eq!(magnitude(Some(7)), "<10 rows");
+    assert_eq!(magnitude(Some(420)), "~100 rows");
+    assert_eq!(magnitude(Some(52_000)), "~10K rows");
+    assert_eq!(magnitude(Some(3_000_000)), "~1M rows");
+    assert_eq!(magnitude(Some(12_000_000_000)), "~10B rows");
+}
+
+#[test]
+fn related_tables_come_first_with_their_keys_marked() {
+    let (nodes, edges, columns) = shop();
+    let summary = summarize("public", &nodes, &edges, &columns, DEFAULT_MAX_TOKENS);
+    let lines: Vec<&str> = summary.text.lines().collect();
+    assert!(lines[0].starts_with("Schema public: 3 tables, 1 foreign keys."));
+    assert_eq!(
+        lines[1],
+        "- orders (~10K rows): id bigint PK, user_id integer -> users.id, total numeric, \
+         placed_at timestamp with time zone"
+    );
+    assert_eq!(lines[2], "- users (~1K rows): id integer PK, email text");
+    assert_eq!(lines[3], "- audit (unanalyzed): note text");
+    assert!(summary.omitted_tables.is_empty());
+    assert_eq!(summary.tables[0].references, ["user_id -> users(id)"]);
+    assert_eq!(summary.estimated_tokens, estimate_tokens(&summary.text));
+}
+
+#[test]
+fn a_tight_budget_drops_columns_then_tables() {
+    let (nodes, edges, columns) = shop();
+    let header = summarize("public", &[], &[], &[], DEFAULT_MAX_TOKENS).estimated_tokens;
+    let summary = summarize("public", &nodes, &edges, &columns, header + 22);
+    assert!(summary.estimated_tokens <= header + 22);
+    assert!(summary.text.contains(
+        "- orders (~10K rows): id bigint PK, user_id integer -> users.id, +2 more columns\n"
+    ));
+    assert!(!summary.tables[0].all_columns);
+    assert_eq!(summary.omitted_tables, ["users", "audit"]);
+}
@@ -0,0 +1,36 @@
+use super::*;
+
+#[test]
+fn splits_at_top_level_semicolons() {
+    assert_eq!(
+        split_statements("select 1;\nselect 2 ;  \n\nselect 3"),
+        ["select 1", "select 2", "select 3"]
+    );
+    assert!(split_statements("  ;; -- only a comment\n/* and another */ ;").is_empty());
+}
+
+#[test]
+fn semicolons_inside_quotes_and_comments_do_not_split() {
+    let script = "select 'a;b', E'it\\'s;', \"odd;name\" from t; -- trailing; comment\n\
+                  /* outer /* nested; */ still; */ select 2";
+    assert_eq!(
+        split_statements(script),
+        [
+            "select 'a;b', E'it\\'s;', \"odd;name\" from t",
+            "-- trailing; comment\n/* outer /* nested; */ still; */ select 2",
+        ]
+    );
+}
+
+#[test]
+fn dollar_quoted_bodies_stay_whole() {
+    let script = "create function f() returns int language plpgsql as $body$\n\
+                  begin return 1; end;\n$body$;\n\
+                  do $$ begin perform 1; end $$;\n\
+                  select $1::int, a$b$ from t";
+    let statements = split_statements(script);
+    assert_eq!(statements.len(), 3);
+    assert!(statements[0].ends_with("end;\n$body$"));
+    assert_eq!(statements[1], "do $$ begin perform 1; end $$");
+    assert_eq!(statements[2], "select $1::int, a$b$ from t");
+}
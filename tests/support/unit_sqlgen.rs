@@ -0,0 +1,610 @@
+use super::*;
+use crate::types::RedactAction;
+use serde_json::json;
+
+#[test]
+fn quote_ident_escapes_quotes() {
+    assert_eq!(quote_ident("users"), "\"users\"");
+    assert_eq!(quote_ident("we\"ird"), "\"we\"\"ird\"");
+}
+
+#[test]
+fn qualified_name_shapes() {
+    assert_eq!(qualified_name("users").unwrap(), "\"users\"");
+    assert_eq!(qualified_name("app.users").unwrap(), "\"app\".\"users\"");
+    assert!(qualified_name("a.b.c").is_err());
+    assert!(qualified_name("app.").is_err());
+}
+
+#[test]
+fn build_insert_uses_params_and_defaults() {
+    let rows = vec![json!({"id":1,"name":"a"}), json!({"id":2})];
+    let (sql, params) = build_insert("users", &rows, &OnConflict::Error, &[]).unwrap();
+    assert_eq!(
+        sql,
+        "insert into \"users\" (\"id\", \"name\") values ($1, $2), ($3, default)"
+    );
+    assert_eq!(params, vec![json!(1), json!("a"), json!(2)]);
+}
+
+#[test]
+fn build_insert_on_conflict_and_returning() {
+    let rows = vec![json!({"id":1,"name":"a"})];
+    let conflict =
+        OnConflict::from_value(Some(&json!({"action":"update","target":["id"]}))).unwrap();
+    let (sql, _) = build_insert("users", &rows, &conflict, &["id".to_string()]).unwrap();
+    assert!(sql.ends_with(
+        "on conflict (\"id\") do update set \"name\" = excluded.\"name\" returning \"id\""
+    ));
+
+    let nothing = OnConflict::from_value(Some(&json!({"action":"nothing"}))).unwrap();
+    let (sql, _) = build_insert("users", &rows, &nothing, &[]).unwrap();
+    assert!(sql.ends_with("on conflict do nothing"));
+
+    assert!(OnConflict::from_value(Some(&json!({"action":"update"}))).is_err());
+    assert!(OnConflict::from_value(Some(&json!({"action":"merge"}))).is_err());
+}
+
+#[test]
+fn build_insert_rejects_bad_rows() {
+    assert!(build_insert("t", &[], &OnConflict::Error, &[]).is_err());
+    assert!(build_insert("t", &[json!(1)], &OnConflict::Error, &[]).is_err());
+    assert!(build_insert("t", &[json!({})], &OnConflict::Error, &[]).is_err());
+}
+
+#[test]
+fn build_copy_in_encodes_text_format() {
+    let rows = vec![
+        json!({"id":1,"note":"a\tb\\c","meta":{"k":true}}),
+        json!({"id":2,"note":null,"meta":null}),
+    ];
+    let cols = row_columns(&rows).unwrap();
+    assert!(rows_are_dense(&rows, &cols));
+    let (sql, data) = build_copy_in("t", &rows).unwrap();
+    assert_eq!(sql, "copy \"t\" (\"id\", \"meta\", \"note\") from stdin");
+    assert_eq!(
+        String::from_utf8(data).unwrap(),
+        "1\t{\"k\":true}\ta\\tb\\\\c\n2\t\\N\t\\N\n"
+    );
+}
+
+#[test]
+fn build_where_conditions() {
+    let mut params = vec![];
+    let sql = build_where(
+        &json!({
+            "a": 1,
+            "b": null,
+            "c": {"op": ">=", "value": 5},
+            "d": {"op": "in", "value": ["x", "y"]},
+            "e": {"op": "is_not_null"}
+        }),
+        &mut params,
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "\"a\" = $1 and \"b\" is null and \"c\" >= $2 and \"d\" in ($3, $4) and \"e\" is not null"
+    );
+    assert_eq!(params, vec![json!(1), json!(5), json!("x"), json!("y")]);
+}
+
+#[test]
+fn build_where_refuses_empty_and_bad_ops() {
+    let mut params = vec![];
+    assert!(build_where(&json!({}), &mut params).is_err());
+    assert!(build_where(&json!(null), &mut params).is_err());
+    assert!(build_where(&json!({"a": {"op": "between"}}), &mut params).is_err());
+    assert!(build_where(&json!({"a": {"op": "in", "value": []}}), &mut params).is_err());
+    assert!(build_where(&json!({"a": {"op": "=", "value": null}}), &mut params).is_err());
+}
+
+#[test]
+fn build_update_and_delete() {
+    let (sql, params) = build_update(
+        "app.users",
+        &json!({"status": "off"}),
+        &json!({"id": 7}),
+        &["id".to_string()],
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "update \"app\".\"users\" set \"status\" = $1 where \"id\" = $2 returning \"id\""
+    );
+    assert_eq!(params, vec![json!("off"), json!(7)]);
+    assert!(build_update("users", &json!({}), &json!({"id": 7}), &[]).is_err());
+    assert!(build_update("users", &json!({"a": 1}), &json!({}), &[]).is_err());
+
+    let (sql, _) = build_delete("users", &json!({"id": 7}), &[]).unwrap();
+    assert_eq!(sql, "delete from \"users\" where \"id\" = $1");
+    assert!(build_delete("users", &json!({}), &[]).is_err());
+}
+
+#[test]
+fn build_archive_batch_moves_rows_in_one_statement() {
+    let columns = vec!["id".to_string(), "created_at".to_string()];
+    let (sql, params) = build_archive_batch(
+        "app.events",
+        "archive.events",
+        &columns,
+        &json!({"created_at": {"op": "<", "value": "2024-01-01"}}),
+        500,
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "with moved as (delete from \"app\".\"events\" where (tableoid, ctid) in \
+         (select tableoid, ctid from \"app\".\"events\" where \"created_at\" < $1 limit $2) \
+         returning \"id\", \"created_at\") \
+         insert into \"archive\".\"events\" (\"id\", \"created_at\") \
+         select \"id\", \"created_at\" from moved"
+    );
+    assert_eq!(params, vec![json!("2024-01-01"), json!(500)]);
+    assert!(build_archive_batch("events", "events", &columns, &json!({"id": 1}), 10).is_err());
+    assert!(build_archive_batch("events", "old", &columns, &json!({}), 10).is_err());
+    assert!(build_archive_batch("events", "old", &[], &json!({"id": 1}), 10).is_err());
+
+    let (sql, params) = build_count("events", &json!({"id": 1})).unwrap();
+    assert_eq!(
+        sql,
+        "select count(*) as rows from \"events\" where \"id\" = $1"
+    );
+    assert_eq!(params, vec![json!(1)]);
+    assert_eq!(
+        build_count_of("select id from users where n > $1; "),
+        "select count(*) as rows from (select id from users where n > $1\n) as afpsql_counted"
+    );
+}
+
+#[test]
+fn chunked_writes_batch_by_rows_or_key() {
+    let (sql, params) = build_delete_chunk("events", &json!({"kind": "debug"}), 100).unwrap();
+    assert_eq!(
+        sql,
+        "delete from \"events\" where (tableoid, ctid) in \
+         (select tableoid, ctid from \"events\" where \"kind\" = $1 limit $2)"
+    );
+    assert_eq!(params, vec![json!("debug"), json!(100)]);
+
+    let key = vec![
+        ("tenant".to_string(), "integer".to_string()),
+        ("id".to_string(), "uuid".to_string()),
+    ];
+    let (sql, params) = build_update_chunk(
+        "app.users",
+        &json!({"status": "off"}),
+        &json!({"plan": "free"}),
+        &key,
+        Some(&[json!("3"), json!("0c4e")]),
+        50,
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "with batch as (select \"tenant\", \"id\" from \"app\".\"users\" \
+         where \"plan\" = $2 and (\"tenant\", \"id\") > ($3::text::integer, $4::text::uuid) \
+         order by \"tenant\", \"id\" limit $5 for update), \
+         updated as (update \"app\".\"users\" as target set \"status\" = $1 from batch \
+         where target.\"tenant\" = batch.\"tenant\" and target.\"id\" = batch.\"id\" returning 1) \
+         select (select count(*) from batch) as picked, \
+         (select count(*) from updated) as updated, \
+         (select json_build_array(\"tenant\"::text, \"id\"::text) from batch \
+         order by \"tenant\" desc, \"id\" desc limit 1) as last"
+    );
+    assert_eq!(
+        params,
+        vec![
+            json!("off"),
+            json!("free"),
+            json!("3"),
+            json!("0c4e"),
+            json!(50)
+        ]
+    );
+
+    let (sql, _) =
+        build_update_chunk("users", &json!({"a": 1}), &json!({"b": 2}), &key, None, 50).unwrap();
+    assert!(sql.contains("where \"b\" = $2 order by"));
+    assert!(build_update_chunk("users", &json!({"a": 1}), &json!({"b": 2}), &[], None, 5).is_err());
+    assert!(
+        build_update_chunk("users", &json!({"id": 1}), &json!({"b": 2}), &key, None, 5).is_err()
+    );
+}
+
+#[test]
+fn build_dequeue_claims_with_skip_locked() {
+    let queue = JobQueue::from_args(&json!({"table": "app.jobs"})).unwrap();
+    let (sql, params) = build_dequeue(&queue, 5, None).unwrap();
+    assert_eq!(
+        sql,
+        "with next as (select \"id\" from \"app\".\"jobs\" where \"status\" = $1 \
+         order by \"id\" limit $2 for update skip locked) \
+         update \"app\".\"jobs\" as job set \"status\" = $3 from next \
+         where job.\"id\" = next.\"id\" returning job.*"
+    );
+    assert_eq!(params, vec![json!("pending"), json!(5), json!("running")]);
+
+    let queue = JobQueue::from_args(&json!({
+        "table": "jobs",
+        "id_column": "job_id",
+        "ready_status": "new",
+        "order_by": ["priority", "job_id"],
+        "claimed_at_column": "claimed_at"
+    }))
+    .unwrap();
+    let (sql, params) = build_dequeue(&queue, 0, Some(&json!({"queue": "mail"}))).unwrap();
+    assert!(sql.contains(
+        "where \"status\" = $1 and \"queue\" = $2 order by \"priority\", \"job_id\" limit $3"
+    ));
+    assert!(sql.contains("set \"status\" = $4, \"claimed_at\" = now()"));
+    assert_eq!(
+        params,
+        vec![json!("new"), json!("mail"), json!(1), json!("running")]
+    );
+
+    assert!(JobQueue::from_args(&json!({})).is_err());
+    assert!(JobQueue::from_args(&json!({"table": "jobs", "status_column": ""})).is_err());
+}
+
+#[test]
+fn build_complete_updates_running_jobs_only() {
+    let queue = JobQueue::from_args(&json!({"table": "jobs"})).unwrap();
+    let (sql, params) = build_complete(&queue, &[json!(1), json!(2)], false, None).unwrap();
+    assert_eq!(
+        sql,
+        "update \"jobs\" set \"status\" = $1 where \"id\" in ($2, $3) and \"status\" = $4 returning \"id\""
+    );
+    assert_eq!(
+        params,
+        vec![json!("done"), json!(1), json!(2), json!("running")]
+    );
+
+    let set = json!({"error": "timeout", "status": "ignored"});
+    let (sql, params) = build_complete(&queue, &[json!(1)], true, Some(&set)).unwrap();
+    assert!(sql.starts_with("update \"jobs\" set \"error\" = $1, \"status\" = $2 where"));
+    assert_eq!(params[..2], [json!("timeout"), json!("failed")]);
+
+    assert!(build_complete(&queue, &[], false, None).is_err());
+    assert!(build_complete(&queue, &[json!(1)], false, Some(&json!([1]))).is_err());
+}
+
+#[test]
+fn pick_conflict_key_prefers_first_covered_key() {
+    let keys = vec![
+        json!({"is_primary": true, "columns": ["id"]}),
+        json!({"is_primary": false, "columns": ["email"]}),
+    ];
+    let cols = vec!["email".to_string(), "name".to_string()];
+    assert_eq!(
+        pick_conflict_key(&keys, &cols),
+        Some(vec!["email".to_string()])
+    );
+    assert_eq!(pick_conflict_key(&keys, &["name".to_string()]), None);
+}
+
+#[test]
+fn build_upsert_wraps_counts() {
+    let rows = vec![json!({"id":1,"name":"a"})];
+    let (sql, params) = build_upsert("users", &rows, &["id".to_string()], None).unwrap();
+    assert!(sql.starts_with("with upserted as (insert into \"users\""));
+    assert!(
+        sql.contains("do update set \"name\" = excluded.\"name\" returning (xmax = 0) as inserted")
+    );
+    assert!(sql.ends_with("as updated from upserted"));
+    assert_eq!(params.len(), 2);
+    assert!(build_upsert("users", &rows, &[], None).is_err());
+}
+
+#[test]
+fn build_sample_modes() {
+    let (sql, params) = build_sample("app.t", 5, false).unwrap();
+    assert_eq!(sql, "select * from \"app\".\"t\" limit $1");
+    assert_eq!(params, vec![json!(5)]);
+    let (sql, _) = build_sample("t", 5, true).unwrap();
+    assert_eq!(sql, "select * from \"t\" order by random() limit $1");
+}
+
+#[test]
+fn build_column_profile_numbers_its_params_after_the_query() {
+    let (sql, params) = build_column_profile(
+        RowSource::Query("select * from t where k = $1;"),
+        vec![json!("a")],
+        Some(&["n".to_string()]),
+        &[RedactionRule {
+            column: "*ssn*".to_string(),
+            action: RedactAction::Mask,
+        }],
+        3,
+        4,
+        Some(100),
+    )
+    .unwrap();
+    assert!(
+        sql.starts_with(
+            "with afpsql_src as (select \"n\" from (select * from t where k = $1) s limit $5::bigint)"
+        ),
+        "{sql}"
+    );
+    assert!(sql.contains("rank <= $2::bigint"));
+    assert!(sql.contains("generate_series(1, $3::int)"));
+    assert!(sql.contains("col ilike any(array(select jsonb_array_elements_text($4::jsonb)))"));
+    assert_eq!(
+        params,
+        vec![json!("a"), json!(3), json!(4), json!(["%ssn%"]), json!(100)]
+    );
+
+    let (sql, params) =
+        build_column_profile(RowSource::Table("app.t"), vec![], None, &[], 5, 10, None).unwrap();
+    assert!(sql.starts_with("with afpsql_src as (select * from \"app\".\"t\" s)"));
+    assert_eq!(params, vec![json!(5), json!(10), json!([])]);
+
+    assert!(build_column_profile(RowSource::Table("t"), vec![], None, &[], 5, 0, None).is_err());
+    assert!(build_column_profile(RowSource::Query(" ; "), vec![], None, &[], 5, 1, None).is_err());
+    assert!(
+        build_column_profile(RowSource::Table("t"), vec![], Some(&[]), &[], 5, 1, None).is_err()
+    );
+    assert_eq!(glob_to_like("a_*?"), "a\\_%_");
+}
+
+#[test]
+fn aggregate_spec_defaults_its_alias() {
+    let spec = AggregateSpec::from_value(&json!({"fn": "sum", "column": "amount"})).unwrap();
+    assert_eq!(spec.alias, "sum_amount");
+    let spec = AggregateSpec::from_value(&json!({"fn": "count"})).unwrap();
+    assert_eq!((spec.column, spec.alias.as_str()), (None, "count"));
+    assert!(AggregateSpec::from_value(&json!({"fn": "avg"})).is_err());
+    assert!(AggregateSpec::from_value(&json!({"fn": "median", "column": "x"})).is_err());
+}
+
+#[test]
+fn build_aggregate_groups_and_rolls_up() {
+    let specs = vec![
+        AggregateSpec::from_value(&json!({"fn": "count"})).unwrap(),
+        AggregateSpec::from_value(
+            &json!({"fn": "count_distinct", "column": "user", "as": "users"}),
+        )
+        .unwrap(),
+    ];
+    let group_by = vec!["region".to_string(), "day".to_string()];
+    let (sql, params) = build_aggregate(
+        RowSource::Query("select * from t where k = $1;"),
+        vec![json!("a")],
+        &group_by,
+        &specs,
+        false,
+        Some(50),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "select \"region\", \"day\", count(*) as \"count\", count(distinct \"user\") as \"users\" \
+         from (select * from t where k = $1) s group by \"region\", \"day\" order by 1, 2 limit $2::bigint"
+    );
+    assert_eq!(params, vec![json!("a"), json!(50)]);
+
+    let (sql, params) = build_aggregate(
+        RowSource::Table("t"),
+        vec![],
+        &group_by,
+        &specs[..1],
+        true,
+        None,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "select \"region\", \"day\", grouping(\"region\", \"day\") as \"grouping\", count(*) as \"count\" \
+         from \"t\" s group by rollup (\"region\", \"day\") order by 1, 2"
+    );
+    assert!(params.is_empty());
+
+    let (sql, _) = build_aggregate(
+        RowSource::Table("t"),
+        vec![],
+        &[],
+        &specs[..1],
+        false,
+        None,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(sql, "select count(*) as \"count\" from \"t\" s");
+
+    assert!(build_aggregate(RowSource::Table("t"), vec![], &[], &specs, true, None, &[]).is_err());
+    assert!(build_aggregate(
+        RowSource::Table("t"),
+        vec![],
+        &group_by,
+        &[],
+        false,
+        None,
+        &[]
+    )
+    .is_err());
+    let clash = vec![AggregateSpec::from_value(&json!({"fn": "count", "as": "day"})).unwrap()];
+    assert!(build_aggregate(
+        RowSource::Table("t"),
+        vec![],
+        &group_by,
+        &clash,
+        false,
+        None,
+        &[]
+    )
+    .is_err());
+
+    let rules = [RedactionRule {
+        column: "users.ssn".to_string(),
+        action: RedactAction::Mask,
+    }];
+    let aggregate = |func: &str, source: RowSource| {
+        let spec = AggregateSpec::from_value(&json!({"fn": func, "column": "ssn"})).unwrap();
+        build_aggregate(source, vec![], &[], &[spec], false, None, &rules)
+    };
+    for func in ["min", "max", "sum", "avg"] {
+        let err = aggregate(func, RowSource::Table("users")).unwrap_err();
+        assert!(err.contains("'ssn' is redacted"), "{err}");
+        assert!(aggregate(func, RowSource::Query("select ssn from users")).is_err());
+        assert!(aggregate(func, RowSource::Table("orders")).is_ok());
+    }
+    assert!(aggregate("count_distinct", RowSource::Table("users")).is_ok());
+}
+
+#[test]
+fn build_maintenance_reports_blocking_operations() {
+    let (sql, blocks) = build_maintenance(MaintenanceOp::Vacuum, "app.t", false).unwrap();
+    assert_eq!((sql.as_str(), blocks), ("vacuum \"app\".\"t\"", None));
+    let (sql, blocks) = build_maintenance(MaintenanceOp::VacuumFull, "t", false).unwrap();
+    assert_eq!(sql, "vacuum (full) \"t\"");
+    assert!(blocks.unwrap().contains("ACCESS EXCLUSIVE"));
+    let (sql, blocks) = build_maintenance(MaintenanceOp::Reindex, "t", true).unwrap();
+    assert_eq!(
+        (sql.as_str(), blocks),
+        ("reindex table concurrently \"t\"", None)
+    );
+    assert!(build_maintenance(MaintenanceOp::Reindex, "t", false)
+        .unwrap()
+        .1
+        .is_some());
+    assert!(build_maintenance(MaintenanceOp::Analyze, "t", true).is_err());
+    assert!(MaintenanceOp::parse("cluster").is_err());
+}
+
+#[test]
+fn escape_like_escapes_wildcards() {
+    assert_eq!(escape_like("50%_a\\b"), "50\\%\\_a\\\\b");
+}
+
+#[test]
+fn build_search_methods() {
+    let targets = vec![
+        SearchTarget {
+            table: "docs".to_string(),
+            columns: vec!["title".to_string(), "body".to_string()],
+        },
+        SearchTarget {
+            table: "notes".to_string(),
+            columns: vec!["text".to_string()],
+        },
+    ];
+    let (sql, params) =
+        build_search(&targets, "a_b", SearchMethod::Ilike, "simple", 5, &[]).unwrap();
+    assert!(sql.contains("t.\"title\"::text ilike $2 or t.\"body\"::text ilike $2"));
+    assert!(sql.contains(" union all "));
+    assert!(sql.ends_with("order by rank desc limit $5"));
+    assert_eq!(
+        params,
+        vec![
+            json!("a_b"),
+            json!("%a\\_b%"),
+            json!("docs"),
+            json!("notes"),
+            json!(5)
+        ]
+    );
+
+    let (sql, params) = build_search(&targets, "x", SearchMethod::Fts, "english", 5, &[]).unwrap();
+    assert!(sql.contains("websearch_to_tsquery($2::text::regconfig, $1)"));
+    assert_eq!(params[1], json!("english"));
+
+    let (sql, params) =
+        build_search(&targets, "x", SearchMethod::Trigram, "simple", 5, &[]).unwrap();
+    assert!(sql.contains("$1 <% t.\"text\"::text"));
+    assert_eq!(params.len(), 4);
+
+    let rules = [RedactionRule {
+        column: "docs.body".to_string(),
+        action: RedactAction::Hash,
+    }];
+    let (sql, _) = build_search(&targets, "x", SearchMethod::Ilike, "simple", 5, &rules).unwrap();
+    assert!(sql.contains("case when t.\"body\"::text ilike $2 then '[redacted]' end"));
+    assert!(sql.contains("case when t.\"title\"::text ilike $2 then substring(t.\"title\"::text"));
+    assert!(sql.contains("case when t.\"text\"::text ilike $2 then substring("));
+    let (sql, _) = build_search(&targets, "x", SearchMethod::Trigram, "simple", 5, &rules).unwrap();
+    assert!(sql.contains("array[false, true]::bool[]"));
+    let (sql, _) = build_search(&targets, "x", SearchMethod::Fts, "simple", 5, &rules).unwrap();
+    assert!(sql.contains("ts_headline($2::text::regconfig, concat_ws(' ', t.\"title\"::text),"));
+
+    assert!(build_search(&[], "x", SearchMethod::Ilike, "simple", 5, &[]).is_err());
+    assert!(SearchMethod::parse(Some("regex")).is_err());
+}
+
+#[cfg(feature = "pgvector")]
+#[test]
+fn build_vector_search_operators_and_filter() {
+    let emb = vec![json!(0.5), json!(1)];
+    let (sql, params) = build_vector_search(
+        "items",
+        "embedding",
+        &emb,
+        VectorMetric::Cosine,
+        3,
+        Some(&json!({"kind": "doc"})),
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        sql,
+        "select to_jsonb(t) - $3::text as row, t.\"embedding\" <=> $1::text::vector as distance \
+         from \"items\" t where \"kind\" = $2 order by t.\"embedding\" <=> $1::text::vector limit $4"
+    );
+    assert_eq!(
+        params,
+        vec![json!("[0.5,1]"), json!("doc"), json!("embedding"), json!(3)]
+    );
+    assert_eq!(VectorMetric::parse(Some("l2")).unwrap().operator(), "<->");
+    assert!(VectorMetric::parse(Some("dot")).is_err());
+    assert!(vector_literal(&[]).is_err());
+    assert!(vector_literal(&[json!("x")]).is_err());
+}
+
+#[cfg(feature = "pgvector")]
+#[test]
+fn build_vector_upsert_casts_and_checks_dimension() {
+    let rows = vec![
+        json!({"id": 1, "emb": [0.5, 1]}),
+        json!({"id": 2, "emb": null}),
+    ];
+    let key = vec!["id".to_string()];
+    let (sql, params) = build_vector_upsert("items", &rows, &key, "emb", Some(2), None).unwrap();
+    assert!(sql.contains("values ($1::text::vector, $2), ($3::text::vector, $4)"));
+    assert!(sql.contains("on conflict (\"id\") do update set \"emb\" = excluded.\"emb\""));
+    assert_eq!(params[0], json!("[0.5,1]"));
+    assert_eq!(params[2], Value::Null);
+
+    let err = build_vector_upsert("items", &rows, &key, "emb", Some(3), None).unwrap_err();
+    assert!(err.contains("expected 3"));
+    let bad = vec![json!({"id": 1, "emb": "x"})];
+    assert!(build_vector_upsert("items", &bad, &key, "emb", None, None).is_err());
+}
+
+#[test]
+fn rename_columns_maps_and_drops_unmapped_keys() {
+    let rows = vec![json!({"id": 1, "name": "a", "secret": "x"})];
+    assert_eq!(rename_columns(rows.clone(), &HashMap::new()), rows);
+
+    let mapping = HashMap::from([
+        ("id".to_string(), "user_id".to_string()),
+        ("name".to_string(), "label".to_string()),
+    ]);
+    assert_eq!(
+        rename_columns(rows, &mapping),
+        vec![json!({"user_id": 1, "label": "a"})]
+    );
+}
+
+#[test]
+fn create_table_infers_sqlite_affinities() {
+    let rows = vec![
+        json!({"id": 1, "score": 1, "name": "a", "meta": {"k": 1}, "mixed": 1, "empty": null}),
+        json!({"id": 2, "score": 2.5, "name": null, "meta": [1], "mixed": "x"}),
+    ];
+    assert_eq!(
+        build_create_table("scratch.t", &rows).unwrap(),
+        "create table \"scratch\".\"t\" (\"empty\", \"id\" integer, \"meta\" text, \"mixed\", \"name\" text, \"score\" real)"
+    );
+    assert!(build_create_table("t", &[]).is_err());
+}
@@ -0,0 +1,123 @@
+use super::*;
+use crate::types::RuntimeConfig;
+use serde_json::json;
+
+struct Unreachable;
+
+#[async_trait]
+impl DbExecutor for Unreachable {
+    async fn execute(
+        &self,
+        session_name: &str,
+        _session_cfg: &SessionConfig,
+        _sql: &str,
+        _params: &[Value],
+        _opts: &ResolvedOptions,
+    ) -> Result<ExecOutcome, ExecError> {
+        Err(ExecError::Connect(format!(
+            "{session_name} went to postgres"
+        )))
+    }
+}
+
+fn temp_db(name: &str) -> SessionConfig {
+    let path = std::env::temp_dir().join(format!("afpsql_{name}_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    SessionConfig {
+        sqlite_path: Some(path.to_string_lossy().into_owned()),
+        ..SessionConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn sqlite_sessions_run_locally_and_others_pass_through() {
+    let sessions = SqliteSessions::new(Arc::new(Unreachable));
+    let local = temp_db("local");
+    let opts = RuntimeConfig::default().resolve_options(&Default::default());
+    let run = |sql: &'static str, params: Vec<Value>| {
+        let local = local.clone();
+        let opts = opts.clone();
+        let sessions = &sessions;
+        async move { sessions.execute("local", &local, sql, &params, &opts).await }
+    };
+
+    run(
+        "create table t (id integer, name text, score real, raw blob)",
+        vec![],
+    )
+    .await
+    .unwrap();
+    match run(
+        "insert into t values (?1, ?2, null, x'ff00'), (2, null, 1.5, null)",
+        vec![json!(1), json!("ada")],
+    )
+    .await
+    {
+        Ok(ExecOutcome::Command { affected }) => assert_eq!(affected, 2),
+        other => panic!("expected command, got {other:?}"),
+    }
+    match run("select * from t where id = $1", vec![json!(1)]).await {
+        Ok(ExecOutcome::Rows(rows)) => assert_eq!(
+            rows,
+            vec![json!({"id": 1, "name": "ada", "score": null, "raw": "\\xff00"})]
+        ),
+        other => panic!("expected rows, got {other:?}"),
+    }
+    match run("select nope from t", vec![]).await {
+        Err(ExecError::Sql {
+            sqlstate, position, ..
+        }) => {
+            assert_eq!(sqlstate, "42000");
+            assert_eq!(position.as_deref(), Some("8"));
+        }
+        other => panic!("expected sql error, got {other:?}"),
+    }
+
+    let remote = SessionConfig::default();
+    assert!(matches!(
+        sessions.execute("main", &remote, "select 1", &[], &opts).await,
+        Err(ExecError::Connect(m)) if m == "main went to postgres"
+    ));
+    let _ = std::fs::remove_file(local.sqlite_path.unwrap_or_default());
+}
+
+#[tokio::test]
+async fn sqlite_sessions_honour_read_only_and_approval() {
+    let sessions = SqliteSessions::new(Arc::new(Unreachable));
+    let local = temp_db("guarded");
+    let mut config = RuntimeConfig::default();
+    let opts = config.resolve_options(&Default::default());
+    sessions
+        .execute("local", &local, "create table t (id integer)", &[], &opts)
+        .await
+        .unwrap();
+
+    let read_only = config.resolve_options(&crate::types::QueryOptions {
+        read_only: Some(true),
+        ..Default::default()
+    });
+    match sessions
+        .execute("local", &local, "insert into t values (1)", &[], &read_only)
+        .await
+    {
+        Err(ExecError::Sql { sqlstate, .. }) => assert_eq!(sqlstate, "25006"),
+        other => panic!("expected read-only error, got {other:?}"),
+    }
+
+    config.require_approval = true;
+    let guarded = config.resolve_options(&Default::default());
+    assert!(matches!(
+        sessions
+            .execute("local", &local, "drop table t", &[], &guarded)
+            .await,
+        Err(ExecError::ApprovalRequired(_))
+    ));
+    match sessions
+        .execute("local", &local, "select count(*) as n from t", &[], &opts)
+        .await
+    {
+        Ok(ExecOutcome::Rows(rows)) => assert_eq!(rows, vec![json!({"n": 0})]),
+        other => panic!("table should survive the rollback, got {other:?}"),
+    }
+    let _ = std::fs::remove_file(local.sqlite_path.unwrap_or_default());
+}
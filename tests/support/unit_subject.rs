@@ -0,0 +1,96 @@
+use super::*;
+use crate::er::Cardinality;
+
+fn column(table: &str, name: &str, data_type: &str) -> Column {
+    Column {
+        table: table.to_string(),
+        name: name.to_string(),
+        data_type: data_type.to_string(),
+    }
+}
+
+fn node(table: &str) -> Node {
+    Node {
+        table: table.to_string(),
+        primary_key: vec!["id".to_string()],
+        estimated_rows: None,
+    }
+}
+
+fn edge(from: &str, from_column: &str, to: &str) -> Edge {
+    Edge {
+        name: format!("{from}_{from_column}_fkey"),
+        from_table: from.to_string(),
+        from_columns: vec![from_column.to_string()],
+        to_table: to.to_string(),
+        to_columns: vec!["id".to_string()],
+        cardinality: Cardinality::ManyToOne,
+        optional: false,
+    }
+}
+
+#[test]
+fn patterns_match_like_redaction_rules() {
+    assert!(pattern_matches("email", "public", "users", "Email"));
+    assert!(pattern_matches(
+        "*.customer_*",
+        "public",
+        "orders",
+        "customer_email"
+    ));
+    assert!(pattern_matches("public.users.id", "public", "users", "id"));
+    assert!(!pattern_matches("users.id", "public", "orders", "id"));
+    assert!(!pattern_matches("crm.users.id", "public", "users", "id"));
+}
+
+#[test]
+fn direct_hits_cast_the_value_to_the_column_type() {
+    let columns = [
+        column("users", "id", "bigint"),
+        column("users", "email", "character varying(255)"),
+        column("orders", "id", "bigint"),
+    ];
+    let steps = direct_steps("public", &columns, &["users.*".to_string()]);
+    assert_eq!(steps.len(), 2);
+    assert_eq!(
+        steps[1].condition,
+        "\"email\" = $1::text::character varying(255)"
+    );
+    assert_eq!(steps[1].via, ["users.email"]);
+}
+
+#[test]
+fn chains_follow_referencing_keys_once_per_table() {
+    let nodes = [node("users"), node("orders"), node("order_items")];
+    let edges = [
+        edge("orders", "user_id", "users"),
+        edge("order_items", "order_id", "orders"),
+        edge("users", "referred_by", "users"),
+    ];
+    let start = direct_steps(
+        "public",
+        &[column("users", "email", "text")],
+        &["email".to_string()],
+    );
+    let orders = referencing_steps("public", &start[0], &nodes, &edges);
+    // The self-reference is not followed back into users.
+    assert_eq!(orders.len(), 1);
+    assert_eq!(
+        orders[0].condition,
+        "(\"user_id\") in (select \"id\" from \"public\".\"users\" where \"email\" = $1::text::text)"
+    );
+    let items = referencing_steps("public", &orders[0], &nodes, &edges);
+    assert_eq!(items[0].table, "order_items");
+    assert_eq!(
+        items[0].via,
+        [
+            "users.email",
+            "orders.user_id -> users.id",
+            "order_items.order_id -> orders.id"
+        ]
+    );
+    assert!(referencing_steps("public", &items[0], &nodes, &edges).is_empty());
+
+    let sql = count_sql("public", &items[0], &[]);
+    assert!(sql.ends_with("null::json as keys"));
+}
@@ -0,0 +1,60 @@
+use super::*;
+use serde_json::json;
+
+fn column(name: &str) -> ColumnInfo {
+    ColumnInfo {
+        name: name.to_string(),
+        type_name: "json".to_string(),
+    }
+}
+
+#[test]
+fn head_and_tail_do_not_overlap() {
+    let rows: Vec<Value> = (1..=5).map(|n| json!(n)).collect();
+    assert_eq!(
+        head_tail(&rows, 2),
+        (vec![json!(1), json!(2)], vec![json!(4), json!(5)])
+    );
+    assert_eq!(
+        head_tail(&rows, 3),
+        (vec![json!(1), json!(2), json!(3)], vec![json!(4), json!(5)])
+    );
+    assert_eq!(head_tail(&rows, 9).1, Vec::<Value>::new());
+    assert_eq!(head_tail(&rows, 0), (vec![], vec![]));
+}
+
+#[test]
+fn stats_cover_min_max_nulls_and_distinct() {
+    let rows = vec![
+        json!({"n": 3, "day": "2024-02-01", "ok": true}),
+        json!({"n": 1.5, "day": null, "ok": false}),
+        json!({"n": 3, "day": "2023-12-31"}),
+    ];
+    let stats = column_stats(&rows, &[column("n"), column("day"), column("ok")]);
+    assert_eq!(stats["n"].min, Some(json!(1.5)));
+    assert_eq!(stats["n"].max, Some(json!(3)));
+    assert_eq!(stats["n"].distinct, 2);
+    assert_eq!(stats["day"].min, Some(json!("2023-12-31")));
+    assert_eq!(stats["day"].nulls, 1);
+    assert_eq!(stats["ok"].nulls, 1);
+    assert_eq!(stats["ok"].max, Some(json!(true)));
+}
+
+#[test]
+fn mixed_or_nested_values_have_no_range() {
+    let rows = vec![json!({"v": 1}), json!({"v": "a"}), json!({"j": {"a": 1}})];
+    let stats = column_stats(&rows, &[column("v"), column("j")]);
+    assert_eq!(stats["v"].min, None);
+    assert_eq!(stats["v"].distinct, 2);
+    assert_eq!(stats["j"].max, None);
+    assert_eq!(stats["j"].distinct, 1);
+}
+
+#[test]
+fn distinct_count_stops_at_the_cap() {
+    let rows: Vec<Value> = (0..DISTINCT_CAP + 5).map(|n| json!({"n": n})).collect();
+    let stats = column_stats(&rows, &[column("n")]);
+    assert_eq!(stats["n"].distinct, DISTINCT_CAP);
+    assert!(stats["n"].distinct_capped);
+    assert_eq!(stats["n"].max, Some(json!(DISTINCT_CAP + 4)));
+}
@@ -0,0 +1,55 @@
+use super::*;
+use serde_json::json;
+
+fn slow_query() -> Value {
+    json!({
+        "code": "log",
+        "event": "query.slow",
+        "request_id": "q1",
+        "session": "default",
+        "warning": "took 1500 ms",
+        "trace": {"duration_ms": 1500},
+    })
+}
+
+#[test]
+fn severity_follows_the_event_name() {
+    assert_eq!(severity("query.error"), 3);
+    assert_eq!(severity("export.failed"), 3);
+    assert_eq!(severity("query.slow"), 4);
+    assert_eq!(severity("writer_lagging"), 4);
+    assert_eq!(severity("startup"), 6);
+}
+
+#[test]
+fn syslog_message_carries_the_event_behind_the_cee_cookie() {
+    let message = String::from_utf8(syslog_message(&slow_query())).unwrap();
+    let prefix = format!("<12>afpsql[{}]: @cee:", std::process::id());
+    let body = message.strip_prefix(&prefix).unwrap();
+    assert_eq!(serde_json::from_str::<Value>(body).unwrap(), slow_query());
+}
+
+#[test]
+fn journald_message_has_a_field_per_event_detail() {
+    let message = journald_message(&slow_query());
+    let text = String::from_utf8_lossy(&message);
+    for line in [
+        "MESSAGE=query.slow q1: took 1500 ms\n",
+        "PRIORITY=4\n",
+        "SYSLOG_IDENTIFIER=afpsql\n",
+        "AFPSQL_EVENT=query.slow\n",
+        "AFPSQL_REQUEST_ID=q1\n",
+        "AFPSQL_SESSION=default\n",
+        "AFPSQL_DURATION_MS=1500\n",
+    ] {
+        assert!(text.contains(line), "{line:?} in {text}");
+    }
+    assert!(!text.contains("AFPSQL_ERROR_CODE"));
+
+    let mut out = vec![];
+    push_field(&mut out, "MESSAGE", "two\nlines");
+    let mut expected = b"MESSAGE\n".to_vec();
+    expected.extend_from_slice(&9u64.to_le_bytes());
+    expected.extend_from_slice(b"two\nlines\n");
+    assert_eq!(out, expected);
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn psql_forms_insert_raw_literal_and_ident() {
+    let vars = vars(&[("tbl", "Order Items"), ("who", "o'brien"), ("n", "10")]);
+    assert_eq!(
+        expand("select * from :\"tbl\" where name = :'who' limit :n", &vars).unwrap(),
+        "select * from \"Order Items\" where name = 'o''brien' limit 10"
+    );
+}
+
+#[test]
+fn template_forms_insert_raw_literal_and_ident() {
+    let vars = vars(&[("tbl", "a\"b"), ("who", "x"), ("n", "5")]);
+    assert_eq!(
+        expand(
+            "select * from {{tbl|ident}} where k = {{ who | literal }} limit {{n}}",
+            &vars
+        )
+        .unwrap(),
+        "select * from \"a\"\"b\" where k = 'x' limit 5"
+    );
+}
+
+#[test]
+fn backslashes_use_an_escape_literal() {
+    let vars = vars(&[("p", "C:\\tmp\\'x")]);
+    assert_eq!(
+        expand("select :'p'", &vars).unwrap(),
+        "select  E'C:\\\\tmp\\\\''x'"
+    );
+}
+
+#[test]
+fn quoted_text_comments_and_casts_are_untouched() {
+    let vars = vars(&[("n", "1")]);
+    let sql = "select ':n', \":n\", $$ :n {{n}} $$, 2::int -- :n {{missing}}\n/* {{n}} */ , :n";
+    assert_eq!(
+        expand(sql, &vars).unwrap(),
+        "select ':n', \":n\", $$ :n {{n}} $$, 2::int -- :n {{missing}}\n/* {{n}} */ , 1"
+    );
+}
+
+#[test]
+fn undefined_and_malformed_variables() {
+    let vars = vars(&[("n", "1")]);
+    assert_eq!(
+        expand("select :other, $1", &vars).unwrap(),
+        "select :other, $1"
+    );
+    assert!(expand("select {{other}}", &vars)
+        .unwrap_err()
+        .contains("undefined"));
+    assert!(expand("select {{n|upper}}", &vars)
+        .unwrap_err()
+        .contains("filter"));
+    assert!(expand("select {{n", &vars)
+        .unwrap_err()
+        .contains("unclosed"));
+    assert!(expand("select {{1x}}", &vars).is_err());
+    assert!(valid_name("_t1") && !valid_name("1t") && !valid_name("a-b"));
+}
+
+#[test]
+fn idents_are_checked_and_quoted() {
+    let idents = vars(&[("schema", "public"), ("table", "Order\"Items")]);
+    assert_eq!(
+        expand_idents(
+            "select '%{table}', * from %{schema}.%{ table } -- %{x}",
+            &idents
+        )
+        .unwrap(),
+        "select '%{table}', * from \"public\".\"Order\"\"Items\" -- %{x}"
+    );
+    assert_eq!(
+        expand_idents("select 7 % 3", &idents).unwrap(),
+        "select 7 % 3"
+    );
+    assert!(expand_idents("select * from %{other}", &idents)
+        .unwrap_err()
+        .contains("undefined"));
+    assert!(expand_idents("select * from %{table", &idents).is_err());
+
+    let long = "x".repeat(64);
+    for bad in ["", "a\0b", long.as_str()] {
+        let err = expand_idents("select 1", &vars(&[("t", bad)])).unwrap_err();
+        assert!(err.starts_with("idents.t: "), "{err}");
+    }
+    assert!(expand("select {{t|ident}}", &vars(&[("t", "")])).is_err());
+}
@@ -0,0 +1,74 @@
+use super::*;
+use serde_json::json;
+
+fn stmt(sql: &str, params: Vec<Value>, sqlstate: Option<&str>) -> Statement {
+    Statement {
+        session: "default".to_string(),
+        sql: sql.to_string(),
+        params,
+        sqlstate: sqlstate.map(str::to_string),
+    }
+}
+
+#[test]
+fn render_inline_uses_prepare_execute() {
+    let mut t = Transcript::default();
+    t.record(
+        stmt("create table t (id int, name text);", vec![], None),
+        10,
+    );
+    t.record(
+        stmt(
+            "insert into t values ($1, $2)",
+            vec![json!(1), json!("O'Brien")],
+            None,
+        ),
+        10,
+    );
+    t.record(stmt("select * from nope", vec![], Some("42P01")), 10);
+    let (script, count) = t.render(ParamStyle::Inline, None);
+    assert_eq!(count, 3);
+    assert!(script.contains("create table t (id int, name text);\n"));
+    assert!(script.contains("PREPARE afpsql_2 AS insert into t values ($1, $2);\n"));
+    assert!(script.contains("EXECUTE afpsql_2('1', 'O''Brien');\n"));
+    assert!(script.contains("DEALLOCATE afpsql_2;\n"));
+    assert!(script.contains("-- [3] session=default failed sqlstate=42P01\n-- select * from nope;"));
+}
+
+#[test]
+fn render_psql_vars_escapes_values() {
+    let mut t = Transcript::default();
+    t.record(
+        stmt("select $1, $2", vec![json!("a'b\\c\nd"), Value::Null], None),
+        10,
+    );
+    let (script, _) = t.render(ParamStyle::PsqlVars, None);
+    assert!(script.contains("\\set p1_1 'a\\'b\\\\c\\nd'\n"));
+    assert!(script.contains("EXECUTE afpsql_1(:'p1_1', NULL);"));
+}
+
+#[test]
+fn trailing_comment_keeps_terminator_live() {
+    let mut t = Transcript::default();
+    t.record(stmt("select 1 -- note", vec![], None), 10);
+    let (script, _) = t.render(ParamStyle::Inline, None);
+    assert!(script.contains("select 1 -- note\n;\n"));
+}
+
+#[test]
+fn capacity_and_session_filter() {
+    let mut t = Transcript::default();
+    for n in 0..4 {
+        let mut s = stmt(&format!("select {n}"), vec![], None);
+        if n % 2 == 1 {
+            s.session = "other".to_string();
+        }
+        t.record(s, 3);
+    }
+    assert_eq!(t.omitted(), 1);
+    let (script, count) = t.render(ParamStyle::Inline, Some("other"));
+    assert_eq!(count, 2);
+    assert!(script.contains("-- 1 earlier statements omitted"));
+    assert!(script.contains("-- [2] session=other\nselect 1;"));
+    assert!(!script.contains("select 2;"));
+}
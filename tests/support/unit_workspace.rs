@@ -0,0 +1,25 @@
+use super::*;
+
+#[test]
+fn touch_returns_session_of_open_workspace() {
+    let mut w = Workspaces::default();
+    w.open("ws1", "default");
+    assert_eq!(w.touch("ws1").as_deref(), Some("default"));
+    assert!(w.touch("ws2").is_none());
+    assert_eq!(w.len(), 1);
+    assert!(w.close("ws1").is_some());
+    assert!(w.touch("ws1").is_none());
+}
+
+#[test]
+fn expire_idle_removes_only_idle_workspaces() {
+    let mut w = Workspaces::default();
+    w.open("ws1", "default");
+    let left = w.expire_idle("ws1", Duration::from_secs(60)).unwrap();
+    assert!(left > Duration::ZERO);
+    assert!(w.contains("ws1"));
+
+    assert_eq!(w.expire_idle("ws1", Duration::ZERO), Some(Duration::ZERO));
+    assert!(!w.contains("ws1"));
+    assert_eq!(w.expire_idle("ws1", Duration::ZERO), None);
+}
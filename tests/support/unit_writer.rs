@@ -0,0 +1,219 @@
+use super::*;
+use crate::memory::MemoryReservation;
+use crate::types::Trace;
+use serde_json::json;
+
+fn result_with(rows: Vec<Value>) -> Output {
+    Output::Result {
+        id: Some("q1".to_string()),
+        session: Some("default".to_string()),
+        command_tag: format!("ROWS {}", rows.len()),
+        columns: vec![],
+        row_count: rows.len(),
+        rows,
+        limited: None,
+        warning: None,
+        cached: None,
+        trace: Trace::only_duration(1),
+        memory: MemoryReservation::default(),
+    }
+}
+
+fn slow_path(output: &Output, format: OutputFormat) -> String {
+    let value = serde_json::to_value(output).unwrap();
+    agent_first_data::cli_output(&value, format)
+}
+
+#[test]
+fn json_fast_path_matches_value_path() {
+    let out = result_with(vec![json!({"n": 1, "s": "a\"b"}), json!({"n": null})]);
+    assert!(!needs_redaction(&out));
+    let mut buf = vec![];
+    write_output(&mut buf, &out, OutputFormat::Json).unwrap();
+    assert_eq!(buf.last(), Some(&b'\n'));
+    let fast: Value = serde_json::from_slice(&buf).unwrap();
+    let slow: Value = serde_json::from_str(&slow_path(&out, OutputFormat::Json)).unwrap();
+    assert_eq!(fast, slow);
+}
+
+#[test]
+fn secret_keys_and_config_take_redacting_path() {
+    assert!(needs_redaction(&result_with(vec![
+        json!({"row": {"api_secret": "x"}})
+    ])));
+    assert!(needs_redaction(&Output::Config(Box::default())));
+    assert!(needs_redaction(&Output::ScheduleResult {
+        id: "hourly".to_string(),
+        session: "default".to_string(),
+        scheduled_at_ms: 0,
+        rows: vec![json!({"token_secret": "x"})],
+        row_count: 1,
+        trace: Trace::only_duration(1),
+        memory: MemoryReservation::default(),
+    }));
+    // Variants not known to be free of secrets take the redacting path.
+    assert!(needs_redaction(&Output::History {
+        id: "h".to_string(),
+        entries: vec![],
+    }));
+    assert!(!needs_redaction(&Output::Resumed {
+        id: "a".to_string(),
+        restored: false,
+    }));
+    let out = result_with(vec![json!({"n": 1})]);
+    let mut buf = vec![];
+    write_output(&mut buf, &out, OutputFormat::Yaml).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!("{}\n", slow_path(&out, OutputFormat::Yaml))
+    );
+}
+
+/// `cargo test --release -- --ignored --nocapture bench_writer` prints
+/// the per-event cost of both paths for a 1000-row batch.
+#[test]
+#[ignore]
+fn bench_writer_json_paths() {
+    let rows: Vec<Value> = (0..1000)
+        .map(|i| json!({"id": i, "name": format!("user {i}"), "score": i as f64 / 3.0, "tags": ["a", "b"]}))
+        .collect();
+    let out = result_with(rows);
+    let iterations = 200;
+
+    let mut sink = std::io::sink();
+    let start = std::time::Instant::now();
+    for _ in 0..iterations {
+        let rendered = slow_path(&out, OutputFormat::Json);
+        sink.write_all(rendered.as_bytes()).unwrap();
+    }
+    let value_path = start.elapsed();
+
+    let mut buf = Vec::with_capacity(1 << 20);
+    let start = std::time::Instant::now();
+    for _ in 0..iterations {
+        buf.clear();
+        write_output(&mut buf, &out, OutputFormat::Json).unwrap();
+    }
+    let direct = start.elapsed();
+
+    println!(
+        "to_value+cli_output: {:?}/event, direct: {:?}/event",
+        value_path / iterations,
+        direct / iterations
+    );
+    assert!(direct < value_path);
+}
+
+fn log_event(event: &str) -> Output {
+    Output::Log {
+        event: event.to_string(),
+        request_id: None,
+        session: None,
+        error_code: None,
+        command_tag: None,
+        warning: None,
+        version: None,
+        argv: None,
+        config: None,
+        args: None,
+        env: None,
+        statement: None,
+        trace: Trace::only_duration(0),
+    }
+}
+
+fn sender_of_one(policy: WriterFullPolicy, log: &[&str]) -> (OutputSender, mpsc::Receiver<Output>) {
+    let (tx, rx) = mpsc::channel(1);
+    let sender = OutputSender::from(tx);
+    let cfg = RuntimeConfig {
+        writer_full_policy: policy,
+        log: log.iter().map(|s| s.to_string()).collect(),
+        ..RuntimeConfig::default()
+    };
+    sender.configure(&cfg);
+    (sender, rx)
+}
+
+#[tokio::test]
+async fn full_channel_follows_writer_full_policy() {
+    let (sender, mut rx) = sender_of_one(WriterFullPolicy::DropLogsFirst, &[]);
+    sender.send(result_with(vec![])).await.unwrap();
+    sender.send(log_event("query.result")).await.unwrap();
+    assert_eq!((sender.saturated_total(), sender.dropped_total()), (1, 1));
+    assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+    assert!(rx.try_recv().is_err());
+
+    let (sender, _rx) = sender_of_one(WriterFullPolicy::Error, &[]);
+    sender.send(result_with(vec![])).await.unwrap();
+    assert!(sender.send(result_with(vec![])).await.is_err());
+    assert_eq!(sender.dropped_total(), 1);
+}
+
+#[tokio::test]
+async fn blocked_sends_log_writer_lagging_once() {
+    let (sender, mut rx) = sender_of_one(WriterFullPolicy::Block, &["writer_lagging"]);
+    sender.send(result_with(vec![])).await.unwrap();
+    let blocked = {
+        let sender = sender.clone();
+        tokio::spawn(async move { sender.send(result_with(vec![])).await })
+    };
+    tokio::task::yield_now().await;
+    let mut events = vec![];
+    for _ in 0..3 {
+        events.push(serde_json::to_value(rx.recv().await.unwrap()).unwrap());
+    }
+    blocked.await.unwrap().unwrap();
+    assert_eq!(events[2]["event"], "writer_lagging");
+    assert_eq!(sender.saturated_total(), 1);
+    assert_eq!(sender.dropped_total(), 0);
+}
+
+#[tokio::test]
+async fn log_file_takes_log_events_off_the_output_stream() {
+    let dir = std::env::temp_dir().join(format!("afpsql-writer-log-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("afpsql.log");
+    let (tx, mut rx) = mpsc::channel(8);
+    let sender = OutputSender::from(tx);
+    let mut cfg = RuntimeConfig {
+        log_file: Some(path.display().to_string()),
+        ..RuntimeConfig::default()
+    };
+    sender.configure(&cfg);
+    sender.send(log_event("query.result")).await.unwrap();
+    sender.send(result_with(vec![])).await.unwrap();
+    assert!(matches!(rx.recv().await, Some(Output::Result { .. })));
+    assert!(rx.try_recv().is_err());
+
+    // Dropping the log file's channel lets its task finish writing.
+    cfg.log_file = None;
+    sender.configure(&cfg);
+    let mut written = String::new();
+    for _ in 0..100 {
+        written = std::fs::read_to_string(&path).unwrap_or_default();
+        if !written.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let line: Value = serde_json::from_str(written.trim_end()).unwrap();
+    assert_eq!(line["event"], "query.result");
+    sender.send(log_event("query.error")).await.unwrap();
+    assert!(matches!(rx.recv().await, Some(Output::Log { .. })));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn split_line_pieces_fit_once_escaped_and_rejoin() {
+    let out = result_with(vec![json!({"text": "quote \" and \\ and é".repeat(20)})]);
+    let line = serde_json::to_string(&out).unwrap();
+    assert!(split_line(&out, line.len(), 64).is_none());
+
+    let chunks = split_line(&out, 100, 64).unwrap();
+    assert!(chunks.len() > 1);
+    for chunk in &chunks {
+        let escaped = serde_json::to_string(chunk).unwrap();
+        assert!(escaped.len() - 2 <= 64, "{escaped}");
+    }
+    assert_eq!(chunks.concat(), line);
+}